@@ -284,16 +284,33 @@ char*           dc_get_blobdir               (const dc_context_t* context);
  * - `imap_certificate_checks` = how to check IMAP certificates, one of the @ref DC_CERTCK flags, defaults to #DC_CERTCK_AUTO (0)
  * - `smtp_certificate_checks` = how to check SMTP certificates, one of the @ref DC_CERTCK flags, defaults to #DC_CERTCK_AUTO (0)
  * - `displayname`  = Own name to use when sending messages.  MUAs are allowed to spread this way eg. using CC, defaults to empty
- * - `selfstatus`   = Own status to display eg. in email footers, defaults to a standard text
+ * - `selfstatus`   = Own status to display eg. in email footers, defaults to a standard text.
+ *                    The status is appended to outgoing messages after a `-- ` line
+ *                    unless `message_signature` is set.
+ * - `message_signature` = Signature appended to outgoing messages after a `-- ` line,
+ *                    but not to system messages.
+ *                    If unset, `selfstatus` is used. An empty string disables the footer.
  * - `selfavatar`   = File containing avatar. Will immediately be copied to the 
  *                    `blobdir`; the original image will not be needed anymore.
  *                    NULL to remove the avatar.
  *                    As for `displayname` and `selfstatus`, also the avatar is sent to the recipients.
  *                    To save traffic, however, the avatar is attached only as needed
  *                    and also recoded to a reasonable size.
+ * - `mailer_user_agent` = Name of the app sent in the `X-Mailer` header,
+ *                    which is encrypted if the message is,
+ *                    defaults to the core version.
+ *                    An empty string omits the header.
+ * - `message_id_domain` = Domain used in the Message-IDs of outgoing messages,
+ *                    eg. to not reveal the provider's hostname;
+ *                    if unset, the domain of the configured address is used (default).
  * - `e2ee_enabled` = 0=no end-to-end-encryption, 1=prefer end-to-end-encryption (default)
  * - `mdns_enabled` = 0=do not send or request read receipts,
- *                    1=send and request read receipts (default)
+ *                    1=send and request read receipts (default),
+ *                    can be overridden per chat by dc_set_chat_mdns_enabled()
+ * - `seen_markers_enabled` = 1=tell the members of a group which messages were seen,
+ *                    this is lighter than read receipts and shown to all group members,
+ *                    nothing is sent if `mdns_enabled` is 0,
+ *                    0=do not send seen markers (default)
  * - `bcc_self`     = 0=do not send a copy of outgoing messages to self (default),
  *                    1=send a copy of outgoing messages to self.
  *                    Sending messages to self is needed for a proper multi-account setup,
@@ -310,18 +327,62 @@ char*           dc_get_blobdir               (const dc_context_t* context);
  * - `mvbox_move`   = 1=heuristically detect chat-messages
  *                    and move them to the `DeltaChat`-folder,
  *                    0=do not move chat-messages
+ * - `only_fetch_inbox` = 1=watch only the `INBOX`-folder, ignoring `sentbox_watch`,
+ *                    `mvbox_watch` and `mvbox_move`; the `DeltaChat`-folder is not created,
+ *                    0=use the options above (default),
+ *                    changes require restarting IO by calling dc_stop_io() and then dc_start_io().
+ * - `mvbox_folder` = name of the folder to use as `DeltaChat`-folder,
+ *                    unset=detect or create the folder (default),
+ *                    changes require restarting IO by calling dc_stop_io() and then dc_start_io().
+ * - `sentbox_folder` = name of the folder to use as `Sent`-folder,
+ *                    unset=detect the folder (default),
+ *                    changes require restarting IO by calling dc_stop_io() and then dc_start_io().
  * - `show_emails`  = DC_SHOW_EMAILS_OFF (0)=
  *                    show direct replies to chats only (default),
  *                    DC_SHOW_EMAILS_ACCEPTED_CONTACTS (1)=
  *                    also show all mails of confirmed contacts,
  *                    DC_SHOW_EMAILS_ALL (2)=
  *                    also show mails of unconfirmed contacts in the deaddrop.
+ * - `spam_handling` = DC_SPAM_HANDLING_OFF (0)=
+ *                    handle messages flagged as spam as any other message (default),
+ *                    DC_SPAM_HANDLING_SEPARATE (1)=
+ *                    move messages of unknown senders flagged as spam by the
+ *                    `X-Spam-Flag` or `X-Spam-Status` header to DC_CHAT_ID_SPAM,
+ *                    see dc_get_chat_msgs().
+ *                    Only headers added by the receiving server,
+ *                    above its `Received` header, are taken into account.
+ * - `spam_trusted_hops` = number of topmost `Received` headers added by the
+ *                    receiving server, default 1. `X-Spam-*` headers above the
+ *                    last of them are used for `spam_handling`.
+ *                    Set this to 2 or more if the server's spam filter
+ *                    reinjects messages, as amavis does.
  * - `key_gen_type` = DC_KEY_GEN_DEFAULT (0)=
  *                    generate recommended key type (default),
  *                    DC_KEY_GEN_RSA2048 (1)=
  *                    generate RSA 2048 keypair
  *                    DC_KEY_GEN_ED25519 (2)=
  *                    generate Ed25519 keypair
+ * - `debug_trace_protocol` = 1=log every IMAP and SMTP protocol line
+ *                    with passwords and tokens masked,
+ *                    this is meant for debugging provider issues and very verbose,
+ *                    changes require restarting IO by calling dc_stop_io() and then dc_start_io(),
+ *                    0=do not log protocol lines (default)
+ * - `allow_insecure_autoconfig` = 1=if autoconfig over HTTPS fails,
+ *                    request `/.well-known/autoconfig/` of the email domain over plain HTTP,
+ *                    only meant for legacy servers in trusted local networks
+ *                    as the answer is not authenticated,
+ *                    0=use HTTPS only (default)
+ * - `oauth2_sasl_mechanism` = SASL mechanism used for OAuth2 logins,
+ *                    `XOAUTH2` or `OAUTHBEARER`,
+ *                    only needed for servers advertising mechanisms they do not support,
+ *                    if unset, IMAP uses `OAUTHBEARER` if advertised by the server
+ *                    and `XOAUTH2` otherwise (default),
+ *                    SMTP only supports `XOAUTH2` and fails to connect if `OAUTHBEARER` is set
+ * - `housekeeping_interval` = seconds between two automatic housekeeping runs,
+ *                    deleting unreferenced files, expired messages and unused keys,
+ *                    0=disable automatic housekeeping, default is 86400 (one day)
+ * - `keep_superseded_keys` = number of superseded keys of every contact
+ *                    kept by housekeeping, the most recent ones are kept, defaults to 0.
  * - `save_mime_headers` = 1=save mime headers
  *                    and make dc_get_mime_headers() work for subsequent calls,
  *                    0=do not save mime headers (default)
@@ -336,6 +397,17 @@ char*           dc_get_blobdir               (const dc_context_t* context);
  *                    "Saved messages" are deleted from the server as well as
  *                    emails matching the `show_emails` settings above, the UI should clearly point that out.
  *                    See also dc_estimate_deletion_cnt().
+ * - `auto_show_remote_images` = 1=keep remote images in the html returned by dc_get_msg_html(),
+ *                    0=remove remote images from the html to avoid tracking (default)
+ * - `fetch_recent_msgs` = number of recent messages fetched from the inbox after dc_configure() (default 100),
+ *                    0=fetch only messages arriving after dc_configure().
+ * - `smtp_max_idle` = seconds after which an unused SMTP connection is closed (default 240),
+ *                    0=keep idle connections open.
+ * - `imap_fetch_batch_size` = maximum number of messages downloaded
+ *                    with a single IMAP command, larger values need less round trips
+ *                    but more memory, values are limited to 1..1000, defaults to 100.
+ * - `message_group_window` = maximum number of seconds between two messages of a sender
+ *                    to be grouped by dc_get_message_groups(), defaults to 300.
  * - `media_quality` = DC_MEDIA_QUALITY_BALANCED (0) =
  *                    good outgoing images/videos/voice quality at reasonable sizes (default)
  *                    DC_MEDIA_QUALITY_WORSE (1)
@@ -344,6 +416,11 @@ char*           dc_get_blobdir               (const dc_context_t* context);
  *                    The library uses the `media_quality` setting to use different defaults
  *                    for recoding images sent with type DC_MSG_IMAGE.
  *                    If needed, recoding other file types is up to the UI.
+ * - `display_name_preference` = DC_DISPLAY_NAME_PREFERENCE_USER_SET (0) =
+ *                    dc_contact_get_display_name() returns the name set by the user
+ *                    or, if unset, the name set by the contact (default)
+ *                    DC_DISPLAY_NAME_PREFERENCE_AUTH_NAME (1) =
+ *                    prefer the name set by the contact over the name set by the user.
  * - `webrtc_instance` = webrtc instance to use for videochats in the form
  *                    `[basicwebrtc:|jitsi:]https://example.com/subdir#roomname=$ROOM`
  *                    if the url is prefixed by `basicwebrtc`, the server is assumed to be of the type
@@ -390,6 +467,9 @@ char*           dc_get_config                (dc_context_t* context, const char*
 /**
  * Set stock string translation.
  *
+ * The translation must not contain other placeholders than the default string,
+ * eg. `%1$s` and `%2$s`, the order may differ.
+ * Missing placeholders are accepted with a warning.
  * The function will emit warnings if it returns an error state.
  *
  * @memberof dc_context_t
@@ -401,6 +481,91 @@ char*           dc_get_config                (dc_context_t* context, const char*
 int             dc_set_stock_translation(dc_context_t* context, uint32_t stock_id, const char* stock_msg);
 
 
+/**
+ * Set a plural form of a stock string.
+ *
+ * Stock strings containing a count, eg. DC_STR_NEW_MESSAGES,
+ * may need different translations depending on the count.
+ * The core uses the DC_PLURAL_ZERO form for a count of 0 and the DC_PLURAL_ONE form
+ * for a count of 1, if set; the DC_PLURAL_OTHER form is used otherwise.
+ * If no plural forms are set for a stock string, the English rules apply.
+ *
+ * The placeholders are checked as for dc_set_stock_translation(),
+ * the DC_PLURAL_ZERO and DC_PLURAL_ONE forms may omit them without a warning,
+ * eg. "No new messages".
+ *
+ * @memberof dc_context_t
+ * @param context The context object
+ * @param stock_id the integer id of the stock message (DC_STR_*)
+ * @param plural_form one of DC_PLURAL_ZERO, DC_PLURAL_ONE or DC_PLURAL_OTHER
+ * @param stock_msg the message to be used for this plural form
+ * @return int (==0 on error, 1 on success)
+ */
+int             dc_set_stock_translation_plural(dc_context_t* context, uint32_t stock_id, int plural_form, const char* stock_msg);
+
+
+/**
+ * Callback formatting a timestamp, see dc_set_time_formatter().
+ *
+ * @param timestamp Unix timestamp to format.
+ * @param userdata The userdata passed to dc_set_time_formatter().
+ * @return The formatted timestamp as a string allocated with malloc(),
+ *     the core frees it using free().
+ *     NULL formats the timestamp in the default format.
+ */
+typedef char* (*dc_time_formatter_t) (int64_t timestamp, void* userdata);
+
+
+/**
+ * Set the function formatting timestamps in user-visible strings
+ * generated by the core, eg. in system messages or in dc_get_msg_info().
+ *
+ * This allows to respect the locale and the 12h/24h preference of the user.
+ * The formatter may be called from any thread at any time
+ * until it is replaced or the context is released.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param formatter The formatter to use,
+ *     NULL restores the default format `YYYY.MM.DD hh:mm:ss` in local time.
+ * @param userdata Passed to each call of the formatter.
+ */
+void            dc_set_time_formatter        (dc_context_t* context, dc_time_formatter_t formatter, void* userdata);
+
+
+/**
+ * Set the minimum severity of log events.
+ *
+ * Log messages below the given level are discarded
+ * before they are formatted and are not emitted as events.
+ * By default, all log messages are emitted.
+ * Errors are always emitted.
+ *
+ * @memberof dc_context_t
+ * @param context The context object
+ * @param level One of the @ref DC_LOG_LEVEL constants.
+ * @return int (==0 on error, 1 on success)
+ */
+int             dc_set_log_level        (dc_context_t* context, int level);
+
+
+/**
+ * Enable or disable structured logging.
+ *
+ * If enabled, log messages are emitted as #DC_EVENT_LOG
+ * with key-value fields passed separately as JSON.
+ * By default, structured logging is disabled
+ * and log messages are emitted as #DC_EVENT_INFO and #DC_EVENT_WARNING
+ * with the fields appended to the message.
+ *
+ * @memberof dc_context_t
+ * @param context The context object
+ * @param enabled 1=emit #DC_EVENT_LOG, 0=emit plain strings
+ * @return None.
+ */
+void            dc_set_structured_logging(dc_context_t* context, int enabled);
+
+
 /**
  * Set configuration values from a QR code.
  * Before this function is called, dc_check_qr() should confirm the type of the
@@ -435,6 +600,42 @@ int             dc_set_config_from_qr   (dc_context_t* context, const char* qr);
 char*           dc_get_info                  (dc_context_t* context);
 
 
+/**
+ * Get anonymized statistics about the context.
+ *
+ * The report contains counts of chats, contacts and messages,
+ * the used provider and some settings,
+ * but no addresses, names or message content.
+ * Nothing is sent automatically,
+ * the UI may offer to send the report to the developers
+ * if the user explicitly agrees.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @return Multi-line string which must be released using dc_str_unref() after usage.  Never returns NULL.
+ */
+char*           dc_get_self_report           (dc_context_t* context);
+
+
+/**
+ * Get the disk space used by the account, eg. for a storage management screen.
+ *
+ * The usage is returned as a JSON object with the following keys:
+ *
+ * - `db_size`: size of the database file in bytes.
+ * - `blobdir_size`: size of all files in the blob directory in bytes.
+ * - `chats`: array of objects with the keys `chat_id`, `msg_count`,
+ *   `db_bytes`, the estimated size of the messages in the database,
+ *   and `blob_bytes`, the size of the attachments; largest chats first.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @return JSON object as described above, an empty string on errors.
+ *     Never returns NULL. Must be released using dc_str_unref() after usage.
+ */
+char*           dc_get_storage_usage_json    (dc_context_t* context);
+
+
 /**
  * Get url that can be used to initiate an OAuth2 authorisation.
  *
@@ -600,6 +801,21 @@ void            dc_stop_io(dc_context_t* context);
 void            dc_maybe_network             (dc_context_t* context);
 
 
+/**
+ * Fetch all folders from scratch, as if no message had been seen before.
+ *
+ * This may be used eg. after a feature that ignored some messages was enabled.
+ * Messages that are already known are not downloaded again.
+ * The folders are fetched in the background,
+ * the progress is reported by #DC_EVENT_RESCAN_PROGRESS.
+ *
+ * @memberof dc_context_t
+ * @param context The context as created by dc_context_new().
+ * @return None.
+ */
+void            dc_resync_all_folders        (dc_context_t* context);
+
+
 
 /**
  * Save a keypair as the default keys for the user.
@@ -912,6 +1128,41 @@ uint32_t        dc_send_text_msg             (dc_context_t* context, uint32_t ch
 uint32_t dc_send_videochat_invitation (dc_context_t* context, uint32_t chat_id);
 
 
+/**
+ * Send a status update of a webxdc app to all members of its chat.
+ *
+ * The update is stored locally as well,
+ * on all devices #DC_EVENT_WEBXDC_STATUS_UPDATE is emitted for stored updates.
+ * Status updates are sent as hidden messages and are not shown in the chat.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param msg_id The ID of the message of type #DC_MSG_WEBXDC.
+ * @param json The update, any JSON value.
+ * @return The ID of the hidden message sent out, 0 on errors,
+ *     eg. if `json` is no valid JSON.
+ */
+uint32_t dc_send_webxdc_status_update (dc_context_t* context, uint32_t msg_id, const char* json);
+
+
+/**
+ * Get the status updates of a webxdc app.
+ *
+ * The updates are returned as a JSON array of objects with the keys
+ * `serial`, increasing with every update stored on this device,
+ * and `payload`, the JSON value given to dc_send_webxdc_status_update().
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param msg_id The ID of the message of type #DC_MSG_WEBXDC.
+ * @param last_known_serial Only updates with a larger serial are returned.
+ *     Pass 0 to get all updates.
+ * @return JSON array as described above, an empty string on errors.
+ *     NULL is never returned. Must be released using dc_str_unref() when done.
+ */
+char* dc_get_webxdc_status_updates (dc_context_t* context, uint32_t msg_id, uint32_t last_known_serial);
+
+
 /**
  * Save a draft for a chat in the database.
  *
@@ -1022,6 +1273,19 @@ void            dc_update_device_chats       (dc_context_t* context);
 int             dc_was_device_msg_ever_added (dc_context_t* context, const char* label);
 
 
+/**
+ * Get the ID of the device-chat.
+ * The device-chat is created when the first device-message is added,
+ * see dc_add_device_msg().
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @return The chat ID of the device-chat,
+ *     0 if the device-chat was not created yet.
+ */
+uint32_t        dc_get_device_chat_id        (dc_context_t* context);
+
+
 /**
  * Get draft for a chat, if any.
  * See dc_set_draft() for more details about drafts.
@@ -1115,13 +1379,32 @@ int             dc_estimate_deletion_cnt    (dc_context_t* context, int from_ser
 dc_array_t*     dc_get_fresh_msgs            (dc_context_t* context);
 
 
+/**
+ * Get the first message of each group of consecutive messages of the same sender in a chat.
+ * UIs may use this to show grouped messages as one bubble.
+ *
+ * A group ends when another contact writes, when the message type changes
+ * or when more than `message_group_window` seconds passed since the previous message,
+ * see dc_set_config().
+ *
+ * @memberof dc_context_t
+ * @param context The context object as returned from dc_context_new().
+ * @param chat_id The chat ID to get the message groups for.
+ * @return Array of message IDs, each starting a group, oldest first.
+ *     Must be dc_array_unref()'d when no longer used.
+ *     On errors, the list is empty. NULL is never returned.
+ */
+dc_array_t*     dc_get_message_groups        (dc_context_t* context, uint32_t chat_id);
+
+
 /**
  * Mark all messages in a chat as _noticed_.
  * _Noticed_ messages are no longer _fresh_ and do not count as being unseen
  * but are still waiting for being marked as "seen" using dc_markseen_msgs()
  * (IMAP/MDNs is not done for noticed messages).
  *
- * Calling this function usually results in the event #DC_EVENT_MSGS_CHANGED.
+ * No read receipts are sent, so this may be called eg. when a chat is opened.
+ * If there were fresh messages, the event #DC_EVENT_MSGS_NOTICED is emitted.
  * See also dc_marknoticed_all_chats(), dc_marknoticed_contact() and dc_markseen_msgs().
  *
  * @memberof dc_context_t
@@ -1231,6 +1514,22 @@ void            dc_set_chat_visibility       (dc_context_t* context, uint32_t ch
 void            dc_delete_chat               (dc_context_t* context, uint32_t chat_id);
 
 
+/**
+ * Delete all messages of a chat, keeping the chat with its members and draft.
+ *
+ * Emits a single #DC_EVENT_MSGS_CHANGED if messages were deleted.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The chat ID to clear.
+ * @param also_on_server 1=delete the messages from the server and inform other devices,
+ *     0=delete the messages on this device only.
+ * @param keep_starred 1=keep starred messages, 0=delete starred messages as well.
+ * @return 1=success, 0=error
+ */
+int             dc_delete_all_messages       (dc_context_t* context, uint32_t chat_id, int also_on_server, int keep_starred);
+
+
 /**
  * Get contact IDs belonging to a chat.
  *
@@ -1376,6 +1675,76 @@ int             dc_add_contact_to_chat       (dc_context_t* context, uint32_t ch
 int             dc_remove_contact_from_chat  (dc_context_t* context, uint32_t chat_id, uint32_t contact_id);
 
 
+/**
+ * Leave a group and delete the chat with all its messages.
+ *
+ * The message informing the other members is queued and sent even if the chat is already deleted.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The chat ID of the group to leave.
+ * @param defer_deletion 1=keep the chat until the leave message is sent,
+ *     0=delete the chat at once.
+ * @return 1=success, 0=error
+ */
+int             dc_leave_and_delete_chat     (dc_context_t* context, uint32_t chat_id, int defer_deletion);
+
+
+/**
+ * Make a group member admin or revoke the admin role.
+ *
+ * Only admins can add and remove members, change the group name and the group image
+ * and set roles; the creator of a group starts as admin.
+ * Groups without any admin can be managed by all members.
+ *
+ * If the group is already _promoted_,
+ * all group members are informed by a special status message that is sent automatically by this function.
+ *
+ * Sends out #DC_EVENT_CHAT_MODIFIED and #DC_EVENT_MSGS_CHANGED if a status message was sent.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The chat ID of the group.
+ * @param contact_id The contact ID of the member.
+ * @param role DC_ROLE_ADMIN or DC_ROLE_MEMBER.
+ * @return 1=role set, 0=error, eg. if you are no admin or when removing the last admin
+ */
+int             dc_set_member_role           (dc_context_t* context, uint32_t chat_id, uint32_t contact_id, int role);
+
+#define DC_ROLE_MEMBER 0
+#define DC_ROLE_ADMIN  1
+
+
+/**
+ * Make a group a broadcast group where only admins can post,
+ * or a normal group again.
+ *
+ * Messages are still sent to all members;
+ * messages of other members to a broadcast group are ignored.
+ * The other members learn about the change with the next message sent to the group.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The chat ID of the group.
+ * @param broadcast 1=only admins can post, 0=everyone can post.
+ * @return 1=success, 0=error, eg. if you are no admin
+ */
+int             dc_set_broadcast             (dc_context_t* context, uint32_t chat_id, int broadcast);
+
+
+/**
+ * Get the role of a group member.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The chat ID of the group.
+ * @param contact_id The contact ID of the member.
+ * @return DC_ROLE_ADMIN or DC_ROLE_MEMBER,
+ *     DC_ROLE_MEMBER is also returned for contacts not being member of the group.
+ */
+int             dc_get_member_role           (dc_context_t* context, uint32_t chat_id, uint32_t contact_id);
+
+
 /**
  * Set group name.
  *
@@ -1408,11 +1777,32 @@ int             dc_set_chat_name             (dc_context_t* context, uint32_t ch
  */
 int dc_set_chat_ephemeral_timer (dc_context_t* context, uint32_t chat_id, uint32_t timer);
 
+
+/**
+ * Unsubscribe from the mailing list shown in a chat,
+ * see dc_chat_is_mailing_list().
+ *
+ * If the list supports the One-Click unsubscription of RFC 8058,
+ * the request is sent as HTTPS POST,
+ * otherwise an unsubscribe message is sent to the address
+ * given in the `List-Unsubscribe` header.
+ * The result is reported by #DC_EVENT_LIST_UNSUBSCRIBED,
+ * for the message only after it was sent or sending it failed.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The ID of the mailing list chat.
+ * @return 1=unsubscribe request sent or queued, 0=error
+ */
+int dc_unsubscribe_from_list (dc_context_t* context, uint32_t chat_id);
+
 /**
  * Set group profile image.
  *
  * If the group is already _promoted_ (any message was sent to the group),
  * all group members are informed by a special status message that is sent automatically by this function.
+ * If several members change the image at about the same time,
+ * the change sent last is used by all members.
  *
  * Sends out #DC_EVENT_CHAT_MODIFIED and #DC_EVENT_MSGS_CHANGED if a status message was sent.
  *
@@ -1431,6 +1821,25 @@ int             dc_set_chat_profile_image    (dc_context_t* context, uint32_t ch
 
 
 
+/**
+ * Enable or disable read receipts for a single chat.
+ *
+ * This overrides the `mdns_enabled` option set by dc_set_config()
+ * for requesting and sending read receipts in the chat,
+ * eg. to send them to close contacts only.
+ *
+ * Sends out #DC_EVENT_CHAT_MODIFIED.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The chat ID to set read receipts for.
+ * @param enabled 1=send read receipts, 0=do not send read receipts,
+ *     -1=use the `mdns_enabled` option again.
+ * @return 1=success, 0=error
+ */
+int             dc_set_chat_mdns_enabled              (dc_context_t* context, uint32_t chat_id, int enabled);
+
+
 /**
  * Set mute duration of a chat.
  *
@@ -1480,6 +1889,21 @@ char*           dc_get_msg_info              (dc_context_t* context, uint32_t ms
 char*           dc_get_mime_headers          (dc_context_t* context, uint32_t msg_id);
 
 
+/**
+ * Get the sanitized HTML part of the given message.
+ * Scripts and event handlers are removed,
+ * remote images are removed unless `auto_show_remote_images` is set.
+ * The plain text returned by dc_msg_get_text() is always available.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param msg_id The message id, must be the id of an incoming message.
+ * @return HTML as a string, must be released using dc_str_unref() after usage.
+ *     Returns NULL if the message has no HTML part.
+ */
+char*           dc_get_msg_html              (dc_context_t* context, uint32_t msg_id);
+
+
 /**
  * Delete messages. The messages are deleted on the current device and
  * on the IMAP server.
@@ -1562,6 +1986,23 @@ void            dc_markseen_msgs             (dc_context_t* context, const uint3
 void            dc_star_msgs                 (dc_context_t* context, const uint32_t* msg_ids, int msg_cnt, int star);
 
 
+/**
+ * Move messages out of the spam chat DC_CHAT_ID_SPAM.
+ * The senders are marked as known, so that their future messages
+ * are not moved to the spam chat anymore.
+ * The messages are moved to the one-to-one chat with the sender,
+ * if there is no such chat yet, it is created as a contact request.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param msg_ids An array of uint32_t message IDs defining the messages that are not spam.
+ *     IDs of messages not in DC_CHAT_ID_SPAM are ignored.
+ * @param msg_cnt The number of IDs in msg_ids
+ * @return None.
+ */
+void            dc_mark_not_spam             (dc_context_t* context, const uint32_t* msg_ids, int msg_cnt);
+
+
 /**
  * Get a single message object of the type dc_msg_t.
  * For a list of messages in a chat, see dc_get_chat_msgs()
@@ -1636,6 +2077,7 @@ uint32_t        dc_create_contact            (dc_context_t* context, const char*
 
 #define         DC_GCL_VERIFIED_ONLY         0x01
 #define         DC_GCL_ADD_SELF              0x02
+#define         DC_GCL_KNOWN_ONLY            0x04
 
 
 /**
@@ -1675,6 +2117,9 @@ int             dc_add_address_book          (dc_context_t* context, const char*
  *     - if the flag DC_GCL_ADD_SELF is set, SELF is added to the list unless filtered by other parameters
  *     - if the flag DC_GCL_VERIFIED_ONLY is set, only verified contacts are returned.
  *       if DC_GCL_VERIFIED_ONLY is not set, verified and unverified contacts are returned.
+ *     - if the flag DC_GCL_KNOWN_ONLY is set, only contacts the user has written to,
+ *       created a chat with or added manually are returned.
+ *       Contacts that have only sent messages or were only mentioned by others are left out.
  * @param query A string to filter the list.  Typically used to implement an
  *     incremental search.  NULL for no filtering.
  * @return An array containing all contact IDs.  Must be dc_array_unref()'d
@@ -1744,6 +2189,24 @@ char*           dc_get_contact_encrinfo      (dc_context_t* context, uint32_t co
 int             dc_delete_contact            (dc_context_t* context, uint32_t contact_id);
 
 
+/**
+ * Move a contact to a new address, typically after #DC_EVENT_CONTACT_ADDRESS_CHANGED.
+ *
+ * The contact at the new address is merged into the contact at the old address,
+ * which keeps its chats and uses the new address from now on.
+ * The contact stays verified only if it uses its verified key at the new address.
+ *
+ * Sends out #DC_EVENT_CONTACTS_CHANGED.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param contact_id ID of the contact at the old address.
+ * @param new_contact_id ID of the contact at the new address.
+ * @return 1=success, 0=error
+ */
+int             dc_accept_contact_address_change (dc_context_t* context, uint32_t contact_id, uint32_t new_contact_id);
+
+
 /**
  * Get a single contact object.  For a list, see eg. dc_get_contacts().
  *
@@ -1764,6 +2227,7 @@ dc_contact_t*   dc_get_contact               (dc_context_t* context, uint32_t co
 
 #define         DC_IMEX_EXPORT_SELF_KEYS      1 // param1 is a directory where the keys are written to
 #define         DC_IMEX_IMPORT_SELF_KEYS      2 // param1 is a directory where the keys are searched in and read from
+#define         DC_IMEX_EXPORT_SELF_PUBLIC_KEY 3 // param1 is a directory where the public key is written to
 #define         DC_IMEX_EXPORT_BACKUP        11 // param1 is a directory where the backup is written to
 #define         DC_IMEX_IMPORT_BACKUP        12 // param1 is the file with the backup to import
 
@@ -1790,6 +2254,10 @@ dc_contact_t*   dc_get_contact               (dc_context_t* context, uint32_t co
  * - **DC_IMEX_IMPORT_SELF_KEYS** (2) - Import private keys found in the directory given as `param1`.
  *   The last imported key is made the default keys unless its name contains the string `legacy`.  Public keys are not imported.
  *
+ * - **DC_IMEX_EXPORT_SELF_PUBLIC_KEY** (3) - Export the default public key of the user to the
+ *   directory given as `param1`.  The key is written to the file `public-key-<addr>.asc`
+ *   and can be imported by other OpenPGP implementations as GnuPG.  The private key is not exported.
+ *
  * While dc_imex() returns immediately, the started job may take a while,
  * you can stop it using dc_stop_ongoing_process(). During execution of the job,
  * some events are sent out:
@@ -2208,6 +2676,45 @@ dc_array_t* dc_get_locations                (dc_context_t* context, uint32_t cha
 void        dc_delete_all_locations         (dc_context_t* context);
 
 
+/**
+ * Add a geofence watching the locations streamed by a contact.
+ *
+ * The geofence is a circle of the given radius around the given center.
+ * Whenever a received location of the contact crosses the boundary
+ * in the direction given by `trigger`,
+ * the event #DC_EVENT_GEOFENCE_TRIGGERED is emitted.
+ * The first location received after adding the geofence
+ * only determines whether the contact is inside.
+ *
+ * Geofences are stored in the database until deleted by dc_delete_geofence().
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param contact_id The ID of the contact to watch.
+ * @param latitude Latitude of the center.
+ * @param longitude Longitude of the center.
+ * @param radius Radius in meters.
+ * @param trigger DC_GEOFENCE_ENTER to get notified when the contact arrives
+ *     or DC_GEOFENCE_EXIT to get notified when the contact leaves.
+ * @return The ID of the new geofence, 0 on errors.
+ */
+uint32_t    dc_add_geofence                 (dc_context_t* context, uint32_t contact_id, double latitude, double longitude, double radius, int trigger);
+
+#define DC_GEOFENCE_ENTER 1
+#define DC_GEOFENCE_EXIT  2
+
+
+/**
+ * Delete a geofence added by dc_add_geofence().
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param geofence_id The ID of the geofence to delete.
+ * @return 1=success, 0=error
+ */
+int         dc_delete_geofence              (dc_context_t* context, uint32_t geofence_id);
+
+
 /**
  * Release a string returned by another deltachat-core function.
  * - Strings returned by any deltachat-core-function
@@ -2766,6 +3273,27 @@ dc_lot_t*        dc_chatlist_get_summary     (const dc_chatlist_t* chatlist, siz
 dc_lot_t*        dc_chatlist_get_summary2    (dc_context_t* context, uint32_t chat_id, uint32_t msg_id);
 
 
+/**
+ * Get a display-ready summary of a chatlist entry as JSON.
+ *
+ * Unlike dc_chatlist_get_summary(), the draft of a chat always takes precedence
+ * over the last message and attachments are previewed with an icon, eg. "📷 Image".
+ * The returned object has the following fields:
+ *
+ * - `prefix`: the sender name in groups, "Me" or "Draft"; null if nothing should be shown
+ * - `text`: one-line preview of the message or draft
+ * - `is_draft`: true if `text` previews the draft
+ * - `timestamp`: timestamp of the previewed message, 0 if there is none
+ *
+ * @memberof dc_chatlist_t
+ * @param chatlist The chatlist to query as returned eg. from dc_get_chatlist().
+ * @param index The index to get the summary for.
+ * @return JSON string, must be released using dc_str_unref().
+ *     Empty string on errors.
+ */
+char*            dc_chatlist_get_summary_json (const dc_chatlist_t* chatlist, size_t index);
+
+
 /**
  * Helper function to get the associated context object.
  *
@@ -2810,6 +3338,7 @@ char*            dc_chat_get_info_json       (dc_context_t* context, size_t chat
 #define         DC_CHAT_ID_STARRED           5 // virtual chat showing all messages flagged with msgs.starred=2
 #define         DC_CHAT_ID_ARCHIVED_LINK     6 // only an indicator in a chatlist
 #define         DC_CHAT_ID_ALLDONE_HINT      7 // only an indicator in a chatlist
+#define         DC_CHAT_ID_SPAM              8 // virtual chat containing messages of unknown senders flagged as spam, see dc_set_config() with "spam_handling"
 #define         DC_CHAT_ID_LAST_SPECIAL      9 // larger chat IDs are "real" chats, their messages are "real" messages.
 
 
@@ -2948,6 +3477,19 @@ int             dc_chat_get_visibility       (const dc_chat_t* chat);
 int             dc_chat_is_unpromoted        (const dc_chat_t* chat);
 
 
+/**
+ * Check if only admins can post to a group.
+ *
+ * Members of broadcast groups can read messages but cannot send,
+ * see dc_set_broadcast().
+ *
+ * @memberof dc_chat_t
+ * @param chat The chat object.
+ * @return 1=only admins can post, 0=everyone can post or the chat is no group.
+ */
+int             dc_chat_is_broadcast         (const dc_chat_t* chat);
+
+
 /**
  * Check if a chat is a self talk.  Self talks are normal chats with
  * the only contact DC_CONTACT_ID_SELF.
@@ -2976,9 +3518,23 @@ int             dc_chat_is_self_talk         (const dc_chat_t* chat);
 int             dc_chat_is_device_talk       (const dc_chat_t* chat);
 
 
+/**
+ * Check if a chat is a mailing list.
+ * Mailing list chats are created for incoming messages with a `List-Id` header,
+ * they are shown as contact request first.
+ * Messages cannot be sent to mailing list chats, cmp. dc_chat_can_send().
+ *
+ * @memberof dc_chat_t
+ * @param chat The chat object.
+ * @return 1=chat is a mailing list, 0=chat is no mailing list
+ */
+int             dc_chat_is_mailing_list      (const dc_chat_t* chat);
+
+
 /**
  * Check if messages can be sent to a give chat.
- * This is not true eg. for the deaddrop or for the device-talk, cmp. dc_chat_is_device_talk().
+ * This is not true eg. for the deaddrop, for the device-talk or for mailing lists,
+ * cmp. dc_chat_is_device_talk() and dc_chat_is_mailing_list().
  *
  * Calling dc_send_msg() for these chats will fail
  * and the ui may decide to hide input controls therefore.
@@ -3015,6 +3571,17 @@ int             dc_chat_is_verified          (const dc_chat_t* chat);
 int             dc_chat_is_sending_locations (const dc_chat_t* chat);
 
 
+/**
+ * Check whether read receipts are enabled for the chat
+ * regardless of the `mdns_enabled` option (can be changed by dc_set_chat_mdns_enabled()).
+ *
+ * @memberof dc_chat_t
+ * @param chat The chat object.
+ * @return 1=enabled for the chat, 0=disabled for the chat, -1=the `mdns_enabled` option is used
+ */
+int             dc_chat_get_mdns_enabled (const dc_chat_t* chat);
+
+
 /**
  * Check whether the chat is currently muted (can be changed by dc_set_chat_mute_duration()).
  *
@@ -3179,6 +3746,21 @@ int             dc_msg_get_viewtype           (const dc_msg_t* msg);
 int             dc_msg_get_state              (const dc_msg_t* msg);
 
 
+/**
+ * Get the time of the next attempt to send a message.
+ * If sending a message fails because of a temporary error,
+ * the message stays in the state DC_STATE_OUT_PENDING
+ * and sending is retried with increasing delays.
+ * This function can be used to show eg. "retry in 5 minutes".
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @return The time of the next attempt as unix timestamp in seconds,
+ *     0 if the message is not waiting for a retry.
+ */
+int64_t          dc_msg_get_next_send_attempt  (const dc_msg_t* msg);
+
+
 /**
  * Get message sending time.
  * The sending time is returned as a unix timestamp in seconds.
@@ -3504,6 +4086,20 @@ int             dc_msg_is_starred             (const dc_msg_t* msg);
 int             dc_msg_is_forwarded           (const dc_msg_t* msg);
 
 
+/**
+ * Check if the HTML part of a message references remote images.
+ *
+ * Remote images are removed from dc_get_msg_html() by default
+ * as they may be used to track whether a message was read.
+ * If this function returns 1, the UI may offer to load them.
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @return 1=message has remote images, 0=message has no remote images.
+ */
+int             dc_msg_has_remote_images      (const dc_msg_t* msg);
+
+
 /**
  * Check if the message is an informational message, created by the
  * device or by another users. Such messages are not "typed" by the user but
@@ -3572,6 +4168,21 @@ int             dc_msg_is_setupmessage        (const dc_msg_t* msg);
 char*           dc_msg_get_setupcodebegin     (const dc_msg_t* msg);
 
 
+/**
+ * Get the language of the message text.
+ *
+ * The language is taken from the `Content-Language` header of the received message
+ * and may be used eg. to offer a translation or to pick a suitable font.
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @return The language tag, eg. `de` or `en-US`,
+ *     an empty string if the language is unknown.
+ *     NULL is never returned. Must be released using dc_str_unref() when done.
+ */
+char* dc_msg_get_language (const dc_msg_t* msg);
+
+
 /**
  * Get url of a videochat invitation.
  *
@@ -3588,6 +4199,26 @@ char*           dc_msg_get_setupcodebegin     (const dc_msg_t* msg);
 char* dc_msg_get_videochat_url (const dc_msg_t* msg);
 
 
+/**
+ * Get information about a webxdc app.
+ *
+ * Calling this function only makes sense for messages of type #DC_MSG_WEBXDC.
+ * The information is returned as a JSON object with the following keys:
+ *
+ * - `name`: name of the app as given in the `manifest.toml` of the archive,
+ *   the file name without suffix if the manifest has no name.
+ * - `icon`: path of the icon inside the archive, `icon.png` or `icon.jpg`,
+ *   null if the archive has no icon.
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @return JSON object as described above,
+ *     an empty string if the message is no webxdc app or on errors.
+ *     NULL is never returned. Must be released using dc_str_unref() when done.
+ */
+char* dc_msg_get_webxdc_info (const dc_msg_t* msg);
+
+
 /**
  * Get type of videochat.
  *
@@ -4169,6 +4800,15 @@ int64_t          dc_lot_get_timestamp     (const dc_lot_t* lot);
  */
 #define DC_MSG_VIDEOCHAT_INVITATION 70
 
+/**
+ * Message containing a webxdc app,
+ * a zip archive with the suffix `.xdc` containing an `index.html`.
+ * Name and icon of the app can be retrieved using dc_msg_get_webxdc_info().
+ *
+ * Malformed `.xdc` archives are shown as #DC_MSG_FILE.
+ */
+#define DC_MSG_WEBXDC 80
+
 /**
  * @}
  */
@@ -4440,6 +5080,23 @@ uint32_t dc_event_get_account_id(dc_event_t* event);
 void dc_event_unref(dc_event_t* event);
 
 
+/**
+ * @defgroup DC_LOG_LEVEL DC_LOG_LEVEL
+ *
+ * Log levels as used by dc_set_log_level().
+ *
+ * @addtogroup DC_LOG_LEVEL
+ * @{
+ */
+#define DC_LOG_LEVEL_DEBUG 0 ///< Emit all log events, including protocol traces, this is the default.
+#define DC_LOG_LEVEL_INFO  1 ///< Emit all log events except for debug messages.
+#define DC_LOG_LEVEL_WARN  2 ///< Emit DC_EVENT_WARNING and errors only.
+#define DC_LOG_LEVEL_ERROR 3 ///< Emit errors only.
+/**
+ * @}
+ */
+
+
 /**
  * @defgroup DC_EVENT DC_EVENT
  *
@@ -4530,6 +5187,18 @@ void dc_event_unref(dc_event_t* event);
  */
 #define DC_EVENT_DELETED_BLOB_FILE 151
 
+
+/**
+ * A log message with key-value fields attached.
+ * Emitted instead of #DC_EVENT_INFO and #DC_EVENT_WARNING
+ * if structured logging is enabled using dc_set_structured_logging().
+ *
+ * @param data1 (int) Log level, one of the @ref DC_LOG_LEVEL constants.
+ * @param data2 (char*) JSON object with the keys `msg` for the message string
+ *     and `fields` for an object mapping field names to string values.
+ */
+#define DC_EVENT_LOG                      110
+
 /**
  * The library-user should write a warning string to the log.
  *
@@ -4619,6 +5288,17 @@ void dc_event_unref(dc_event_t* event);
 #define DC_EVENT_INCOMING_MSG             2005
 
 
+/**
+ * Messages of a chat were marked as noticed by dc_marknoticed_chat().
+ * The UI should update the counter of fresh messages
+ * and remove the notifications of the chat.
+ *
+ * @param data1 (int) chat_id
+ * @param data2 0
+ */
+#define DC_EVENT_MSGS_NOTICED             2008
+
+
 /**
  * A single message is sent successfully. State changed from  DC_STATE_OUT_PENDING to
  * DC_STATE_OUT_DELIVERED, see dc_msg_get_state().
@@ -4650,6 +5330,19 @@ void dc_event_unref(dc_event_t* event);
 #define DC_EVENT_MSG_READ                 2015
 
 
+/**
+ * The states of several messages in a chat changed at once,
+ * eg. while sending queued messages or receiving read receipts.
+ * This event is sent instead of #DC_EVENT_MSG_DELIVERED, #DC_EVENT_MSG_FAILED
+ * and #DC_EVENT_MSG_READ for each of the messages,
+ * use dc_msg_get_state() to get the new states.
+ *
+ * @param data1 (int) chat_id
+ * @param data2 (char*) comma-separated list of msg_id
+ */
+#define DC_EVENT_MSG_STATES_CHANGED       2016
+
+
 /**
  * Chat changed.  The name or the image of a chat group was changed or members were added or removed.
  * Or the verify state of a chat has changed.
@@ -4667,6 +5360,39 @@ void dc_event_unref(dc_event_t* event);
 #define DC_EVENT_CHAT_EPHEMERAL_TIMER_MODIFIED 2021
 
 
+/**
+ * Unsubscribing from a mailing list was attempted,
+ * see dc_unsubscribe_from_list().
+ *
+ * @param data1 (int) chat_id of the mailing list chat.
+ * @param data2 (int) 1 if the unsubscribe request was sent, 0 on errors.
+ */
+#define DC_EVENT_LIST_UNSUBSCRIBED        2022
+
+
+/**
+ * Expired messages were deleted locally,
+ * either because of their ephemeral timer
+ * or because of the `delete_device_after` setting.
+ *
+ * @param data1 (int) Number of deleted messages.
+ * @param data2 0
+ */
+#define DC_EVENT_MSGS_DELETED             2025
+
+
+/**
+ * Messages were deleted from the server,
+ * either because they were deleted locally
+ * or because of their ephemeral timer or the `delete_server_after` setting.
+ * The event is emitted once after all pending deletions are done.
+ *
+ * @param data1 (int) Number of deleted messages.
+ * @param data2 0
+ */
+#define DC_EVENT_SERVER_MSGS_DELETED      2026
+
+
 /**
  * Contact(s) created, renamed, verified, blocked or deleted.
  *
@@ -4688,6 +5414,38 @@ void dc_event_unref(dc_event_t* event);
 #define DC_EVENT_LOCATION_CHANGED         2035
 
 
+/**
+ * A contact crossed the boundary of a geofence added by dc_add_geofence()
+ * in the direction the geofence is watching.
+ *
+ * @param data1 (int) contact_id of the contact.
+ * @param data2 (int) ID of the geofence.
+ */
+#define DC_EVENT_GEOFENCE_TRIGGERED       2036
+
+
+/**
+ * The Autocrypt key of a contact has changed.
+ * An info message is added to the 1:1 chat with the contact as well.
+ *
+ * @param data1 (int) contact_id of the contact.
+ * @param data2 0
+ */
+#define DC_EVENT_CONTACT_KEY_CHANGED      2037
+
+
+/**
+ * A verified contact wrote from a new address,
+ * the message was signed with the verified key of the contact.
+ * The UI may ask the user whether to move the contact to the new address
+ * using dc_accept_contact_address_change().
+ *
+ * @param data1 (int) contact_id of the contact at the old address.
+ * @param data2 (int) contact_id of the new address.
+ */
+#define DC_EVENT_CONTACT_ADDRESS_CHANGED  2038
+
+
 /**
  * Inform about the configuration progress started by dc_configure().
  *
@@ -4697,6 +5455,15 @@ void dc_event_unref(dc_event_t* event);
 #define DC_EVENT_CONFIGURE_PROGRESS       2041
 
 
+/**
+ * Inform about the progress of fetching all folders started by dc_resync_all_folders().
+ *
+ * @param data1 (int) 0=error, 1-999=progress in permille, 1000=success and done
+ * @param data2 0
+ */
+#define DC_EVENT_RESCAN_PROGRESS          2042
+
+
 /**
  * Inform about the import/export progress started by dc_imex().
  *
@@ -4750,6 +5517,26 @@ void dc_event_unref(dc_event_t* event);
  */
 #define DC_EVENT_SECUREJOIN_JOINER_PROGRESS       2061
 
+
+/**
+ * A status update of a webxdc app was stored,
+ * get it using dc_get_webxdc_status_updates().
+ *
+ * @param data1 (int) msg_id of the webxdc app.
+ * @param data2 (int) serial of the status update.
+ */
+#define DC_EVENT_WEBXDC_STATUS_UPDATE             2120
+
+
+/**
+ * Housekeeping finished.
+ * Housekeeping runs automatically every dc_get_config("housekeeping_interval") seconds.
+ *
+ * @param data1 (int) Number of deleted unreferenced files.
+ * @param data2 (int) Reclaimed disk space in bytes.
+ */
+#define DC_EVENT_HOUSEKEEPING_DONE                2130
+
 /**
  * @}
  */
@@ -4762,7 +5549,7 @@ void dc_event_unref(dc_event_t* event);
 #define DC_ERROR_SELF_NOT_IN_GROUP   1    // not used anymore
 #define DC_STR_SELFNOTINGRP          21   // not used anymore
 #define DC_EVENT_DATA1_IS_STRING(e)  0    // not used anymore 
-#define DC_EVENT_DATA2_IS_STRING(e)  ((e)==DC_EVENT_IMEX_FILE_WRITTEN || ((e)>=100 && (e)<=499))
+#define DC_EVENT_DATA2_IS_STRING(e)  ((e)==DC_EVENT_IMEX_FILE_WRITTEN || (e)==DC_EVENT_MSG_STATES_CHANGED || ((e)>=100 && (e)<=499))
 #define DC_EVENT_RETURNS_INT(e)      ((e)==DC_EVENT_IS_OFFLINE) // not used anymore
 #define DC_EVENT_RETURNS_STRING(e)   ((e)==DC_EVENT_GET_STRING) // not used anymore
 #define dc_archive_chat(a,b,c)  dc_set_chat_visibility((a), (b), (c)? 1 : 0) // not used anymore
@@ -4777,6 +5564,13 @@ void dc_event_unref(dc_event_t* event);
 #define DC_SHOW_EMAILS_ALL               2
 
 
+/*
+ * Values for dc_get|set_config("spam_handling")
+ */
+#define DC_SPAM_HANDLING_OFF      0
+#define DC_SPAM_HANDLING_SEPARATE 1
+
+
 /*
  * Values for dc_get|set_config("media_quality")
  */
@@ -4784,6 +5578,13 @@ void dc_event_unref(dc_event_t* event);
 #define DC_MEDIA_QUALITY_WORSE    1
 
 
+/*
+ * Values for dc_get|set_config("display_name_preference")
+ */
+#define DC_DISPLAY_NAME_PREFERENCE_USER_SET  0
+#define DC_DISPLAY_NAME_PREFERENCE_AUTH_NAME 1
+
+
 /*
  * Values for dc_get|set_config("key_gen_type")
  */
@@ -4957,13 +5758,35 @@ void dc_event_unref(dc_event_t* event);
 #define DC_STR_EPHEMERAL_FOUR_WEEKS       81
 #define DC_STR_VIDEOCHAT_INVITATION       82
 #define DC_STR_VIDEOCHAT_INVITE_MSG_BODY  83
+#define DC_STR_NEW_MESSAGES               84
+#define DC_STR_MSG_MEMBER_ADMIN           85
+#define DC_STR_MSG_MEMBER_NO_ADMIN        86
+#define DC_STR_MSG_LOCATION_ENABLED_UNTIL 87
 
-#define DC_STR_COUNT                      83
+#define DC_STR_COUNT                      87
 
 /*
  * @}
  */
 
+
+/**
+ * @defgroup DC_PLURAL DC_PLURAL
+ *
+ * Plural forms of stock strings,
+ * see dc_set_stock_translation_plural().
+ *
+ * @addtogroup DC_PLURAL
+ * @{
+ */
+#define DC_PLURAL_ZERO                    0
+#define DC_PLURAL_ONE                     1
+#define DC_PLURAL_OTHER                   2
+
+/**
+ * @}
+ */
+
 #ifdef PY_CFFI_INC
 /* Helper utility to locate the header file when building python bindings. */
 char* _dc_header_file_location(void) {
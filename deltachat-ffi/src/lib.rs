@@ -31,8 +31,9 @@ use deltachat::contact::{Contact, Origin};
 use deltachat::context::Context;
 use deltachat::ephemeral::Timer as EphemeralTimer;
 use deltachat::key::DcKey;
+use deltachat::log::LogLevel;
 use deltachat::message::MsgId;
-use deltachat::stock::StockMessage;
+use deltachat::stock::{PluralForm, StockMessage};
 use deltachat::*;
 
 mod dc_array;
@@ -194,6 +195,120 @@ pub unsafe extern "C" fn dc_set_stock_translation(
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_stock_translation_plural(
+    context: *mut dc_context_t,
+    stock_id: u32,
+    plural_form: libc::c_int,
+    stock_msg: *mut libc::c_char,
+) -> libc::c_int {
+    if context.is_null() || stock_msg.is_null() {
+        eprintln!("ignoring careless call to dc_set_stock_translation_plural");
+        return 0;
+    }
+    let msg = to_string_lossy(stock_msg);
+    let ctx = &*context;
+
+    block_on(async move {
+        let form = match PluralForm::from_i32(plural_form) {
+            Some(form) => form,
+            None => {
+                warn!(ctx, "invalid plural form {}", plural_form);
+                return 0;
+            }
+        };
+        match StockMessage::from_u32(stock_id) {
+            Some(id) => match ctx.set_stock_translation_plural(id, form, msg).await {
+                Ok(()) => 1,
+                Err(err) => {
+                    warn!(ctx, "set_stock_translation_plural failed: {}", err);
+                    0
+                }
+            },
+            None => {
+                warn!(ctx, "invalid stock message id {}", stock_id);
+                0
+            }
+        }
+    })
+}
+
+/// Formats a timestamp, see dc_set_time_formatter().
+pub type dc_time_formatter_t =
+    unsafe extern "C" fn(timestamp: i64, userdata: *mut libc::c_void) -> *mut libc::c_char;
+
+/// Userdata passed to the time formatter,
+/// the caller of dc_set_time_formatter() guarantees it may be used from any thread.
+struct TimeFormatterUserdata(*mut libc::c_void);
+
+unsafe impl Send for TimeFormatterUserdata {}
+unsafe impl Sync for TimeFormatterUserdata {}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_time_formatter(
+    context: *mut dc_context_t,
+    formatter: Option<dc_time_formatter_t>,
+    userdata: *mut libc::c_void,
+) {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_time_formatter()");
+        return;
+    }
+    let ctx = &*context;
+    match formatter {
+        Some(formatter) => {
+            let userdata = TimeFormatterUserdata(userdata);
+            ctx.set_time_formatter(Some(Box::new(move |timestamp| {
+                let formatted = formatter(timestamp, userdata.0);
+                if formatted.is_null() {
+                    return dc_tools::dc_timestamp_to_str(timestamp);
+                }
+                let res = to_string_lossy(formatted);
+                libc::free(formatted as *mut _);
+                res
+            })));
+        }
+        None => ctx.set_time_formatter(None),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_log_level(
+    context: *mut dc_context_t,
+    level: libc::c_int,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_log_level()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    match LogLevel::from_i32(level) {
+        Some(level) => {
+            ctx.set_log_level(level);
+            1
+        }
+        None => {
+            warn!(ctx, "invalid log level {}", level);
+            0
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_structured_logging(
+    context: *mut dc_context_t,
+    enabled: libc::c_int,
+) {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_structured_logging()");
+        return;
+    }
+    let ctx = &*context;
+
+    ctx.set_structured_logging(enabled != 0)
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_set_config_from_qr(
     context: *mut dc_context_t,
@@ -230,6 +345,42 @@ pub unsafe extern "C" fn dc_get_info(context: *mut dc_context_t) -> *mut libc::c
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_self_report(context: *mut dc_context_t) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_self_report()");
+        return "".strdup();
+    }
+    let ctx = &*context;
+    block_on(async move { ctx.get_self_report().await.strdup() })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_storage_usage_json(
+    context: *mut dc_context_t,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_storage_usage_json()");
+        return "".strdup();
+    }
+    let ctx = &*context;
+    block_on(async move {
+        let usage = match ctx.get_storage_usage().await {
+            Ok(usage) => usage,
+            Err(err) => {
+                error!(&ctx, "dc_get_storage_usage_json() failed: {}", err);
+                return "".strdup();
+            }
+        };
+        serde_json::to_string(&usage)
+            .unwrap_or_log_default(
+                &ctx,
+                "dc_get_storage_usage_json() failed to serialise to json",
+            )
+            .strdup()
+    })
+}
+
 fn render_info(
     info: BTreeMap<&'static str, String>,
 ) -> std::result::Result<String, std::fmt::Error> {
@@ -367,18 +518,30 @@ pub unsafe extern "C" fn dc_event_get_data1_int(event: *mut dc_event_t) -> libc:
         | EventType::MsgDelivered { chat_id, .. }
         | EventType::MsgFailed { chat_id, .. }
         | EventType::MsgRead { chat_id, .. }
+        | EventType::MsgStatesChanged { chat_id, .. }
         | EventType::ChatModified(chat_id)
-        | EventType::ChatEphemeralTimerModified { chat_id, .. } => chat_id.to_u32() as libc::c_int,
+        | EventType::MsgsNoticed(chat_id)
+        | EventType::ChatEphemeralTimerModified { chat_id, .. }
+        | EventType::ListUnsubscribed { chat_id, .. } => chat_id.to_u32() as libc::c_int,
         EventType::ContactsChanged(id) | EventType::LocationChanged(id) => {
             let id = id.unwrap_or_default();
             id as libc::c_int
         }
-        EventType::ConfigureProgress(progress) | EventType::ImexProgress(progress) => {
-            *progress as libc::c_int
+        EventType::WebxdcStatusUpdate { msg_id, .. } => msg_id.to_u32() as libc::c_int,
+        EventType::HousekeepingDone(stats) => stats.deleted_files as libc::c_int,
+        EventType::Log { level, .. } => *level as libc::c_int,
+        EventType::ConfigureProgress(progress)
+        | EventType::RescanProgress(progress)
+        | EventType::ImexProgress(progress) => *progress as libc::c_int,
+        EventType::MsgsDeleted(count) | EventType::ServerMsgsDeleted(count) => {
+            *count as libc::c_int
         }
         EventType::ImexFileWritten(_) => 0,
         EventType::SecurejoinInviterProgress { contact_id, .. }
-        | EventType::SecurejoinJoinerProgress { contact_id, .. } => *contact_id as libc::c_int,
+        | EventType::SecurejoinJoinerProgress { contact_id, .. }
+        | EventType::GeofenceTriggered { contact_id, .. }
+        | EventType::ContactKeyChanged { contact_id }
+        | EventType::ContactAddressChanged { contact_id, .. } => *contact_id as libc::c_int,
     }
 }
 
@@ -408,17 +571,34 @@ pub unsafe extern "C" fn dc_event_get_data2_int(event: *mut dc_event_t) -> libc:
         | EventType::ContactsChanged(_)
         | EventType::LocationChanged(_)
         | EventType::ConfigureProgress(_)
+        | EventType::RescanProgress(_)
         | EventType::ImexProgress(_)
         | EventType::ImexFileWritten(_)
-        | EventType::ChatModified(_) => 0,
+        | EventType::ChatModified(_)
+        | EventType::MsgsNoticed(_)
+        | EventType::MsgsDeleted(_)
+        | EventType::ServerMsgsDeleted(_)
+        | EventType::ContactKeyChanged { .. }
+        | EventType::Log { .. } => 0,
         EventType::MsgsChanged { msg_id, .. }
         | EventType::IncomingMsg { msg_id, .. }
         | EventType::MsgDelivered { msg_id, .. }
         | EventType::MsgFailed { msg_id, .. }
         | EventType::MsgRead { msg_id, .. } => msg_id.to_u32() as libc::c_int,
+        EventType::MsgStatesChanged { .. } => 0,
         EventType::SecurejoinInviterProgress { progress, .. }
         | EventType::SecurejoinJoinerProgress { progress, .. } => *progress as libc::c_int,
         EventType::ChatEphemeralTimerModified { timer, .. } => timer.to_u32() as libc::c_int,
+        EventType::ListUnsubscribed { success, .. } => *success as libc::c_int,
+        EventType::GeofenceTriggered { geofence_id, .. } => *geofence_id as libc::c_int,
+        EventType::ContactAddressChanged { new_contact_id, .. } => *new_contact_id as libc::c_int,
+        EventType::WebxdcStatusUpdate {
+            status_update_serial,
+            ..
+        } => *status_update_serial as libc::c_int,
+        EventType::HousekeepingDone(stats) => {
+            stats.deleted_bytes.min(libc::c_int::MAX as u64) as libc::c_int
+        }
     }
 }
 
@@ -454,17 +634,48 @@ pub unsafe extern "C" fn dc_event_get_data2_str(event: *mut dc_event_t) -> *mut
         | EventType::MsgFailed { .. }
         | EventType::MsgRead { .. }
         | EventType::ChatModified(_)
+        | EventType::MsgsNoticed(_)
+        | EventType::MsgsDeleted(_)
+        | EventType::ServerMsgsDeleted(_)
         | EventType::ContactsChanged(_)
         | EventType::LocationChanged(_)
         | EventType::ConfigureProgress(_)
+        | EventType::RescanProgress(_)
         | EventType::ImexProgress(_)
         | EventType::SecurejoinInviterProgress { .. }
         | EventType::SecurejoinJoinerProgress { .. }
-        | EventType::ChatEphemeralTimerModified { .. } => ptr::null_mut(),
+        | EventType::ChatEphemeralTimerModified { .. }
+        | EventType::ListUnsubscribed { .. }
+        | EventType::GeofenceTriggered { .. }
+        | EventType::ContactKeyChanged { .. }
+        | EventType::ContactAddressChanged { .. }
+        | EventType::WebxdcStatusUpdate { .. }
+        | EventType::HousekeepingDone(_) => ptr::null_mut(),
         EventType::ImexFileWritten(file) => {
             let data2 = file.to_c_string().unwrap_or_default();
             data2.into_raw()
         }
+        EventType::MsgStatesChanged { msg_ids, .. } => {
+            let data2 = msg_ids
+                .iter()
+                .map(|msg_id| msg_id.to_u32().to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+                .to_c_string()
+                .unwrap_or_default();
+            data2.into_raw()
+        }
+        EventType::Log { msg, fields, .. } => {
+            let fields: serde_json::Map<String, serde_json::Value> = fields
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone().into()))
+                .collect();
+            let data2 = serde_json::json!({ "msg": msg, "fields": fields })
+                .to_string()
+                .to_c_string()
+                .unwrap_or_default();
+            data2.into_raw()
+        }
     }
 }
 
@@ -540,6 +751,17 @@ pub unsafe extern "C" fn dc_maybe_network(context: *mut dc_context_t) {
     block_on(async move { ctx.maybe_network().await })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_resync_all_folders(context: *mut dc_context_t) {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_resync_all_folders()");
+        return;
+    }
+    let ctx = &*context;
+
+    block_on(async move { ctx.resync_all_folders().await })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_preconfigure_keypair(
     context: *mut dc_context_t,
@@ -755,6 +977,58 @@ pub unsafe extern "C" fn dc_send_videochat_invitation(
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_send_webxdc_status_update(
+    context: *mut dc_context_t,
+    msg_id: u32,
+    json: *const libc::c_char,
+) -> u32 {
+    if context.is_null() || json.is_null() {
+        eprintln!("ignoring careless call to dc_send_webxdc_status_update()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        ctx.send_webxdc_status_update(MsgId::new(msg_id), &to_string_lossy(json))
+            .await
+            .map(|msg_id| msg_id.to_u32())
+            .unwrap_or_log_default(&ctx, "Failed to send webxdc status update")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_webxdc_status_updates(
+    context: *mut dc_context_t,
+    msg_id: u32,
+    last_known_serial: u32,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_webxdc_status_updates()");
+        return "".strdup();
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        let updates = match ctx
+            .get_webxdc_status_updates(MsgId::new(msg_id), last_known_serial)
+            .await
+        {
+            Ok(updates) => updates,
+            Err(err) => {
+                error!(&ctx, "dc_get_webxdc_status_updates() failed: {}", err);
+                return "".strdup();
+            }
+        };
+        serde_json::to_string(&updates)
+            .unwrap_or_log_default(
+                &ctx,
+                "dc_get_webxdc_status_updates() failed to serialise to json",
+            )
+            .strdup()
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_set_draft(
     context: *mut dc_context_t,
@@ -835,6 +1109,25 @@ pub unsafe extern "C" fn dc_was_device_msg_ever_added(
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_device_chat_id(context: *mut dc_context_t) -> u32 {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_device_chat_id()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        match chat::get_device_chat_id(&ctx).await {
+            Ok(chat_id) => chat_id.unwrap_or_default().to_u32(),
+            Err(err) => {
+                error!(ctx, "Failed to get device chat: {}", err);
+                0
+            }
+        }
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_get_draft(context: *mut dc_context_t, chat_id: u32) -> *mut dc_msg_t {
     if context.is_null() {
@@ -953,6 +1246,32 @@ pub unsafe extern "C" fn dc_get_fresh_msgs(
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_message_groups(
+    context: *mut dc_context_t,
+    chat_id: u32,
+) -> *mut dc_array::dc_array_t {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_message_groups()");
+        return ptr::null_mut();
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        let groups = chat::get_message_groups(&ctx, ChatId::new(chat_id))
+            .await
+            .unwrap_or_log_default(&ctx, "Failed to get message groups");
+        let arr = dc_array_t::from(
+            groups
+                .iter()
+                .filter_map(|group| group.msg_ids.first())
+                .map(|msg_id| msg_id.to_u32())
+                .collect::<Vec<u32>>(),
+        );
+        Box::into_raw(Box::new(arr))
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_marknoticed_chat(context: *mut dc_context_t, chat_id: u32) {
     if context.is_null() {
@@ -1118,6 +1437,32 @@ pub unsafe extern "C" fn dc_delete_chat(context: *mut dc_context_t, chat_id: u32
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_delete_all_messages(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    also_on_server: libc::c_int,
+    keep_starred: libc::c_int,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_delete_all_messages()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        chat::delete_all_messages(
+            &ctx,
+            ChatId::new(chat_id),
+            also_on_server != 0,
+            keep_starred != 0,
+        )
+        .await
+        .map(|_| 1)
+        .unwrap_or_log_default(&ctx, "Failed to delete all messages")
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_get_chat_contacts(
     context: *mut dc_context_t,
@@ -1257,6 +1602,94 @@ pub unsafe extern "C" fn dc_remove_contact_from_chat(
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_leave_and_delete_chat(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    defer_deletion: libc::c_int,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_leave_and_delete_chat()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        chat::leave_and_delete(&ctx, ChatId::new(chat_id), defer_deletion != 0)
+            .await
+            .map(|_| 1)
+            .unwrap_or_log_default(&ctx, "Failed to leave and delete chat")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_member_role(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    contact_id: u32,
+    role: libc::c_int,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_member_role()");
+        return 0;
+    }
+    let ctx = &*context;
+    let role = match chat::Role::from_i32(role) {
+        Some(role) => role,
+        None => {
+            eprintln!("ignoring careless call to dc_set_member_role(): invalid role");
+            return 0;
+        }
+    };
+
+    block_on(async move {
+        chat::set_member_role(&ctx, ChatId::new(chat_id), contact_id, role)
+            .await
+            .map(|_| 1)
+            .unwrap_or_log_default(&ctx, "Failed to set member role")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_broadcast(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    broadcast: libc::c_int,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_broadcast()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        chat::set_broadcast(&ctx, ChatId::new(chat_id), broadcast != 0)
+            .await
+            .map(|_| 1)
+            .unwrap_or_log_default(&ctx, "Failed to set broadcast")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_member_role(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    contact_id: u32,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_member_role()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        chat::get_member_role(&ctx, ChatId::new(chat_id), contact_id)
+            .await
+            .to_i32()
+            .unwrap_or_default()
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_set_chat_name(
     context: *mut dc_context_t,
@@ -1297,6 +1730,35 @@ pub unsafe extern "C" fn dc_set_chat_profile_image(
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_chat_mdns_enabled(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    enabled: libc::c_int,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_chat_mdns_enabled()");
+        return 0;
+    }
+    let ctx = &*context;
+    let enabled = match enabled {
+        -1 => None,
+        0 => Some(false),
+        1 => Some(true),
+        _ => {
+            warn!(ctx, "dc_set_chat_mdns_enabled(): invalid value {}", enabled);
+            return 0;
+        }
+    };
+
+    block_on(async move {
+        chat::set_mdns_enabled(&ctx, ChatId::new(chat_id), enabled)
+            .await
+            .map(|_| 1)
+            .unwrap_or_log_default(&ctx, "Failed to set read receipts")
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_set_chat_mute_duration(
     context: *mut dc_context_t,
@@ -1324,7 +1786,7 @@ pub unsafe extern "C" fn dc_set_chat_mute_duration(
     };
 
     block_on(async move {
-        chat::set_muted(&ctx, ChatId::new(chat_id), muteDuration)
+        chat::set_mute_duration(&ctx, ChatId::new(chat_id), muteDuration)
             .await
             .map(|_| 1)
             .unwrap_or_log_default(&ctx, "Failed to set mute duration")
@@ -1374,6 +1836,25 @@ pub unsafe extern "C" fn dc_set_chat_ephemeral_timer(
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_unsubscribe_from_list(
+    context: *mut dc_context_t,
+    chat_id: u32,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_unsubscribe_from_list()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        chat::unsubscribe_from_list(ctx, ChatId::new(chat_id))
+            .await
+            .log_err(ctx, "Failed to unsubscribe from list")
+            .is_ok() as libc::c_int
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_get_msg_info(
     context: *mut dc_context_t,
@@ -1407,6 +1888,29 @@ pub unsafe extern "C" fn dc_get_mime_headers(
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_msg_html(
+    context: *mut dc_context_t,
+    msg_id: u32,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_msg_html()");
+        return ptr::null_mut(); // NULL explicitly defined as "no html"
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        match message::Message::load_from_db(&ctx, MsgId::new(msg_id)).await {
+            Ok(msg) => msg
+                .get_html(&ctx)
+                .await
+                .map(|s| s.strdup())
+                .unwrap_or_else(ptr::null_mut),
+            Err(_) => ptr::null_mut(),
+        }
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_delete_msgs(
     context: *mut dc_context_t,
@@ -1453,7 +1957,7 @@ pub unsafe extern "C" fn dc_forward_msgs(
     let ctx = &*context;
 
     block_on(async move {
-        chat::forward_msgs(&ctx, &msg_ids[..], ChatId::new(chat_id))
+        chat::forward_msgs(&ctx, &msg_ids[..], &[ChatId::new(chat_id)])
             .await
             .unwrap_or_log_default(&ctx, "Failed to forward message")
     })
@@ -1503,6 +2007,26 @@ pub unsafe extern "C" fn dc_star_msgs(
     block_on(message::star_msgs(&ctx, msg_ids, star == 1));
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_mark_not_spam(
+    context: *mut dc_context_t,
+    msg_ids: *const u32,
+    msg_cnt: libc::c_int,
+) {
+    if context.is_null() || msg_ids.is_null() || msg_cnt <= 0 {
+        eprintln!("ignoring careless call to dc_mark_not_spam()");
+        return;
+    }
+    let msg_ids = convert_and_prune_message_ids(msg_ids, msg_cnt);
+    let ctx = &*context;
+
+    block_on(async move {
+        message::mark_not_spam(&ctx, &msg_ids)
+            .await
+            .unwrap_or_log_default(&ctx, "Failed to mark message as not spam")
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_get_msg(context: *mut dc_context_t, msg_id: u32) -> *mut dc_msg_t {
     if context.is_null() {
@@ -1714,6 +2238,26 @@ pub unsafe extern "C" fn dc_delete_contact(
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_accept_contact_address_change(
+    context: *mut dc_context_t,
+    contact_id: u32,
+    new_contact_id: u32,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_accept_contact_address_change()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        contact::accept_address_change(&ctx, contact_id, new_contact_id)
+            .await
+            .map(|_| 1)
+            .unwrap_or_log_default(&ctx, "Failed to accept address change")
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_get_contact(
     context: *mut dc_context_t,
@@ -1974,6 +2518,55 @@ pub unsafe extern "C" fn dc_get_locations(
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_add_geofence(
+    context: *mut dc_context_t,
+    contact_id: u32,
+    latitude: libc::c_double,
+    longitude: libc::c_double,
+    radius: libc::c_double,
+    trigger: libc::c_int,
+) -> u32 {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_add_geofence()");
+        return 0;
+    }
+    let ctx = &*context;
+    let trigger = match location::GeofenceTrigger::from_i32(trigger) {
+        Some(trigger) => trigger,
+        None => {
+            eprintln!("ignoring careless call to dc_add_geofence(): invalid trigger");
+            return 0;
+        }
+    };
+
+    block_on(async move {
+        location::add_geofence(&ctx, contact_id, latitude, longitude, radius, trigger)
+            .await
+            .log_err(ctx, "Failed to add geofence")
+            .unwrap_or_default()
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_delete_geofence(
+    context: *mut dc_context_t,
+    geofence_id: u32,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_delete_geofence()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        location::delete_geofence(&ctx, geofence_id)
+            .await
+            .log_err(ctx, "Failed to delete geofence")
+            .is_ok() as libc::c_int
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_delete_all_locations(context: *mut dc_context_t) {
     if context.is_null() {
@@ -2255,6 +2848,42 @@ pub unsafe extern "C" fn dc_chatlist_get_summary(
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_chatlist_get_summary_json(
+    chatlist: *mut dc_chatlist_t,
+    index: libc::size_t,
+) -> *mut libc::c_char {
+    if chatlist.is_null() {
+        eprintln!("ignoring careless call to dc_chatlist_get_summary_json()");
+        return "".strdup();
+    }
+    let ffi_list = &*chatlist;
+    let ctx = &*ffi_list.context;
+
+    block_on(async move {
+        let item = match ffi_list.list.get_item(index as usize) {
+            Some(item) => item,
+            None => {
+                error!(&ctx, "dc_chatlist_get_summary_json(): bad index {}", index);
+                return "".strdup();
+            }
+        };
+        let summary = match item.summary(&ctx).await {
+            Ok(summary) => summary,
+            Err(err) => {
+                error!(&ctx, "dc_chatlist_get_summary_json() failed: {}", err);
+                return "".strdup();
+            }
+        };
+        serde_json::to_string(&summary)
+            .unwrap_or_log_default(
+                &ctx,
+                "dc_chatlist_get_summary_json() failed to serialise to json",
+            )
+            .strdup()
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_chatlist_get_summary2(
     context: *mut dc_context_t,
@@ -2395,6 +3024,16 @@ pub unsafe extern "C" fn dc_chat_is_unpromoted(chat: *mut dc_chat_t) -> libc::c_
     ffi_chat.chat.is_unpromoted() as libc::c_int
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_chat_is_broadcast(chat: *mut dc_chat_t) -> libc::c_int {
+    if chat.is_null() {
+        eprintln!("ignoring careless call to dc_chat_is_broadcast()");
+        return 0;
+    }
+    let ffi_chat = &*chat;
+    ffi_chat.chat.is_broadcast() as libc::c_int
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_chat_is_self_talk(chat: *mut dc_chat_t) -> libc::c_int {
     if chat.is_null() {
@@ -2415,6 +3054,16 @@ pub unsafe extern "C" fn dc_chat_is_device_talk(chat: *mut dc_chat_t) -> libc::c
     ffi_chat.chat.is_device_talk() as libc::c_int
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_chat_is_mailing_list(chat: *mut dc_chat_t) -> libc::c_int {
+    if chat.is_null() {
+        eprintln!("ignoring careless call to dc_chat_is_mailing_list()");
+        return 0;
+    }
+    let ffi_chat = &*chat;
+    ffi_chat.chat.is_mailing_list() as libc::c_int
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_chat_can_send(chat: *mut dc_chat_t) -> libc::c_int {
     if chat.is_null() {
@@ -2455,6 +3104,19 @@ pub unsafe extern "C" fn dc_chat_is_muted(chat: *mut dc_chat_t) -> libc::c_int {
     ffi_chat.chat.is_muted() as libc::c_int
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_chat_get_mdns_enabled(chat: *mut dc_chat_t) -> libc::c_int {
+    if chat.is_null() {
+        eprintln!("ignoring careless call to dc_chat_get_mdns_enabled()");
+        return -1;
+    }
+    let ffi_chat = &*chat;
+    match ffi_chat.chat.get_mdns_enabled() {
+        Some(enabled) => enabled as libc::c_int,
+        None => -1,
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_chat_get_remaining_mute_duration(chat: *mut dc_chat_t) -> i64 {
     if chat.is_null() {
@@ -2610,6 +3272,17 @@ pub unsafe extern "C" fn dc_msg_get_state(msg: *mut dc_msg_t) -> libc::c_int {
     ffi_msg.message.get_state() as libc::c_int
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_get_next_send_attempt(msg: *mut dc_msg_t) -> i64 {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_get_next_send_attempt()");
+        return 0;
+    }
+    let ffi_msg = &*msg;
+    let ctx = &*ffi_msg.context;
+    block_on(job::get_next_send_attempt(ctx, ffi_msg.message.get_id())).unwrap_or_default()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_msg_get_timestamp(msg: *mut dc_msg_t) -> i64 {
     if msg.is_null() {
@@ -2855,6 +3528,16 @@ pub unsafe extern "C" fn dc_msg_is_forwarded(msg: *mut dc_msg_t) -> libc::c_int
     ffi_msg.message.is_forwarded().into()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_has_remote_images(msg: *mut dc_msg_t) -> libc::c_int {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_has_remote_images()");
+        return 0;
+    }
+    let ffi_msg = &*msg;
+    ffi_msg.message.has_remote_images().into()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_msg_is_info(msg: *mut dc_msg_t) -> libc::c_int {
     if msg.is_null() {
@@ -2885,6 +3568,17 @@ pub unsafe extern "C" fn dc_msg_is_setupmessage(msg: *mut dc_msg_t) -> libc::c_i
     ffi_msg.message.is_setupmessage().into()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_get_language(msg: *mut dc_msg_t) -> *mut libc::c_char {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_get_language()");
+        return "".strdup();
+    }
+    let ffi_msg = &*msg;
+
+    ffi_msg.message.get_language().unwrap_or_default().strdup()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_msg_get_videochat_url(msg: *mut dc_msg_t) -> *mut libc::c_char {
     if msg.is_null() {
@@ -2900,6 +3594,29 @@ pub unsafe extern "C" fn dc_msg_get_videochat_url(msg: *mut dc_msg_t) -> *mut li
         .strdup()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_get_webxdc_info(msg: *mut dc_msg_t) -> *mut libc::c_char {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_get_webxdc_info()");
+        return "".strdup();
+    }
+    let ffi_msg = &*msg;
+    let ctx = &*ffi_msg.context;
+
+    block_on(async move {
+        let info = match ffi_msg.message.get_webxdc_info(&ctx).await {
+            Ok(info) => info,
+            Err(err) => {
+                error!(&ctx, "dc_msg_get_webxdc_info() failed: {}", err);
+                return "".strdup();
+            }
+        };
+        serde_json::to_string(&info)
+            .unwrap_or_log_default(&ctx, "dc_msg_get_webxdc_info() failed to serialise to json")
+            .strdup()
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_msg_get_videochat_type(msg: *mut dc_msg_t) -> libc::c_int {
     if msg.is_null() {
@@ -121,6 +121,9 @@ impl Accounts {
     }
 
     /// Migrate an existing account into this structure.
+    ///
+    /// The database and the blobdir are moved into the accounts directory,
+    /// so the account must not be opened by any context.
     pub async fn migrate_account(&self, dbfile: PathBuf) -> Result<u32> {
         let blobdir = Context::derive_blobdir(&dbfile);
 
@@ -135,6 +138,21 @@ impl Accounts {
             blobdir.display()
         );
 
+        ensure!(
+            !crate::sql::is_dbfile_open(dbfile.as_ref()),
+            "database is still open: {}",
+            dbfile.display()
+        );
+        // databases opened by other processes keep their write-ahead log,
+        // it is removed when the last connection is closed
+        let mut walfile = dbfile.clone().into_os_string();
+        walfile.push("-wal");
+        ensure!(
+            !PathBuf::from(walfile).exists().await,
+            "database is still open: {}",
+            dbfile.display()
+        );
+
         let old_id = self.config.get_selected_account().await;
 
         // create new account
@@ -530,4 +548,88 @@ mod tests {
             ctx.get_config(crate::config::Config::Addr).await.unwrap()
         );
     }
+
+    #[async_std::test]
+    async fn test_migrate_account_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let p: PathBuf = dir.path().join("accounts").into();
+        let accounts = Accounts::new("my_os".into(), p.clone()).await.unwrap();
+
+        let extern_dbfile: PathBuf = dir.path().join("other").into();
+        let ctx = Context::new("my_os".into(), extern_dbfile.clone(), 0)
+            .await
+            .unwrap();
+        let mut msg = crate::message::Message::new(crate::constants::Viewtype::Text);
+        msg.set_text(Some("hello".to_string()));
+        crate::chat::add_device_msg(&ctx, None, Some(&mut msg))
+            .await
+            .unwrap();
+
+        // the account is still in use
+        assert!(accounts
+            .migrate_account(extern_dbfile.clone())
+            .await
+            .is_err());
+        assert_eq!(accounts.accounts.read().await.len(), 1);
+
+        drop(ctx);
+        let id = accounts
+            .migrate_account(extern_dbfile.clone())
+            .await
+            .unwrap();
+        assert!(!extern_dbfile.exists().await);
+
+        let ctx = accounts.get_account(id).await.unwrap();
+        let chat_id = crate::chat::get_device_chat_id(&ctx)
+            .await
+            .unwrap()
+            .unwrap();
+        let msgs = crate::chat::get_chat_msgs(&ctx, chat_id, 0, None).await;
+        assert_eq!(msgs.len(), 1);
+    }
+
+    #[async_std::test]
+    async fn test_merged_events_of_in_memory_accounts() {
+        use crate::events::EventType;
+        use crate::test_utils::{
+            alice_keypair, bob_keypair, configured_in_memory_context, prepare_text_msg,
+            send_and_receive,
+        };
+        use std::time::Duration;
+
+        let dir = tempfile::tempdir().unwrap();
+        let p: PathBuf = dir.path().join("accounts").into();
+        let accounts = Accounts::new("my_os".into(), p).await.unwrap();
+
+        let alice = configured_in_memory_context(alice_keypair()).await;
+        let bob = configured_in_memory_context(bob_keypair()).await;
+        let (alice_id, bob_id) = (alice.get_id(), bob.get_id());
+        {
+            let mut contexts = accounts.accounts.write().await;
+            contexts.insert(alice_id, alice.clone());
+            contexts.insert(bob_id, bob.clone());
+        }
+        let emitter = accounts.get_event_emitter().await;
+
+        let msg = prepare_text_msg(&alice, "bob@example.net", "hello").await;
+        let received = send_and_receive(&alice, &msg, &bob).await;
+
+        let mut alice_changed = false;
+        let mut bob_changed = false;
+        while !(alice_changed && bob_changed) {
+            let event = async_std::future::timeout(Duration::from_secs(10), emitter.recv())
+                .await
+                .expect("missing events")
+                .unwrap();
+            if let EventType::MsgsChanged { msg_id, .. } = event.typ {
+                // both databases may use the same message ids,
+                // only the account id tells the events apart
+                if event.id == alice_id && msg_id == msg.id {
+                    alice_changed = true;
+                } else if event.id == bob_id && msg_id == received.id {
+                    bob_changed = true;
+                }
+            }
+        }
+    }
 }
@@ -9,14 +9,17 @@ use async_std::{fs, io};
 
 use image::GenericImageView;
 use num_traits::FromPrimitive;
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 
 use crate::config::Config;
 use crate::constants::*;
 use crate::context::Context;
+use crate::dc_tools::dc_delete_file;
 use crate::error::Error;
 use crate::events::EventType;
 use crate::message;
+use crate::param::{Param, Params};
 
 /// Represents a file in the blob directory.
 ///
@@ -434,6 +437,118 @@ impl<'a> BlobObject<'a> {
         Ok(())
     }
 
+    /// Replaces the blob by an existing blob with identical content.
+    ///
+    /// The SHA-256 hash of every blob passed to this function is recorded,
+    /// so that attaching the same file several times, eg. when sending an image
+    /// to several chats, does not store the file several times.
+    /// Only blobs not referenced anywhere else, see [BlobObject::count_references],
+    /// are replaced and deleted,
+    /// the returned blob may have a different name but always has the same suffix;
+    /// callers should keep the original name in [Param::Filename].
+    pub async fn deduplicate(self, context: &'a Context) -> Result<BlobObject<'a>, Error> {
+        let hash = sha256_file(&self.to_abs_path()).await?;
+
+        let names = context
+            .sql
+            .query_map(
+                "SELECT name FROM blobs WHERE sha256=? AND name!=?;",
+                paramsv![hash, self.as_name()],
+                |row| row.get::<_, String>(0),
+                |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+            )
+            .await?;
+        for name in names {
+            let existing = match BlobObject::from_name(context, name) {
+                Ok(existing) => existing,
+                Err(_) => continue,
+            };
+            if existing.suffix() == self.suffix() && existing.to_abs_path().exists().await {
+                if self.count_references(context).await? > 0 {
+                    // the blob is in use, eg. as an avatar, it must not be deleted.
+                    break;
+                }
+                info!(
+                    context,
+                    "Using existing blob {} for {}",
+                    existing.as_name(),
+                    self.as_name()
+                );
+                dc_delete_file(context, self.to_abs_path()).await;
+                return Ok(existing);
+            }
+        }
+
+        context
+            .sql
+            .execute(
+                "INSERT OR REPLACE INTO blobs (name, sha256) VALUES (?, ?);",
+                paramsv![self.as_name(), hash],
+            )
+            .await?;
+        Ok(self)
+    }
+
+    /// Returns the number of references to the blob.
+    ///
+    /// References are attachments of messages and jobs, chat and contact avatars
+    /// and config values as the self-avatar.
+    /// Blobs without references are deleted by [housekeeping].
+    ///
+    /// There is no stored reference counter, the references are counted
+    /// by searching the params of all messages, jobs, chats and contacts
+    /// for the blob name with `LIKE`, so this scans these tables
+    /// and gets slower with a larger database.
+    ///
+    /// [housekeeping]: crate::sql::housekeeping
+    pub async fn count_references(&self, context: &Context) -> Result<usize, Error> {
+        let pattern = format!("%{}%", self.as_name());
+        let mut refcount = 0;
+        for (query, param) in &[
+            (
+                "SELECT param FROM msgs WHERE chat_id!=3 AND type!=10 AND param LIKE ?;",
+                Param::File,
+            ),
+            ("SELECT param FROM jobs WHERE param LIKE ?;", Param::File),
+            (
+                "SELECT param FROM chats WHERE param LIKE ?;",
+                Param::ProfileImage,
+            ),
+            (
+                "SELECT param FROM contacts WHERE param LIKE ?;",
+                Param::ProfileImage,
+            ),
+        ] {
+            refcount += context
+                .sql
+                .query_map(
+                    query,
+                    paramsv![pattern],
+                    |row| row.get::<_, String>(0),
+                    |rows| {
+                        let mut refcount = 0;
+                        for row in rows {
+                            let params: Params = row?.parse().unwrap_or_default();
+                            if params.get(*param) == Some(self.as_name()) {
+                                refcount += 1;
+                            }
+                        }
+                        Ok(refcount)
+                    },
+                )
+                .await?;
+        }
+        refcount += context
+            .sql
+            .query_get_value_result::<_, isize>(
+                "SELECT COUNT(*) FROM config WHERE value=?;",
+                paramsv![self.as_name()],
+            )
+            .await?
+            .unwrap_or_default() as usize;
+        Ok(refcount)
+    }
+
     pub fn get_exif_orientation(&self, context: &Context) -> Result<i32, Error> {
         let file = std::fs::File::open(self.to_abs_path())?;
         let mut bufreader = std::io::BufReader::new(&file);
@@ -453,6 +568,23 @@ impl<'a> BlobObject<'a> {
     }
 }
 
+/// Returns the hex-encoded SHA-256 hash of a file.
+///
+/// The file is read in chunks, so large attachments are not loaded into memory.
+async fn sha256_file(path: &Path) -> Result<String, Error> {
+    let mut file = fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(buf.get(..n).unwrap_or_default());
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
 impl<'a> fmt::Display for BlobObject<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "$BLOBDIR/{}", self.name)
@@ -704,4 +836,122 @@ mod tests {
         assert!(!stem.contains("*"));
         assert!(!stem.contains("?"));
     }
+
+    #[async_std::test]
+    async fn test_sha256_file() {
+        let t = TestContext::new().await;
+        // larger than one chunk
+        let data: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+        let path = t.dir.path().join("large.bin");
+        std::fs::write(&path, &data).unwrap();
+        let path = PathBuf::from(path);
+        assert_eq!(
+            sha256_file(&path).await.unwrap(),
+            hex::encode(Sha256::digest(&data))
+        );
+    }
+
+    #[async_std::test]
+    async fn test_deduplicate() {
+        use crate::chat;
+        use crate::contact::Contact;
+        use crate::message::Message;
+
+        let t = TestContext::new_alice().await;
+        let image = t.dir.path().join("dedup.png");
+        std::fs::write(
+            &image,
+            include_bytes!("../test-data/image/avatar64x64.png").as_ref(),
+        )
+        .unwrap();
+
+        let mut chat_ids = Vec::new();
+        for addr in &["bob@example.net", "claire@example.org", "dave@example.com"] {
+            let contact_id = Contact::create(&t.ctx, "", addr).await.unwrap();
+            chat_ids.push(
+                chat::create_by_contact_id(&t.ctx, contact_id)
+                    .await
+                    .unwrap(),
+            );
+        }
+
+        // sending the same image twice reuses the first blob
+        let mut msg_ids = Vec::new();
+        for chat_id in &chat_ids[..2] {
+            let mut msg = Message::new(Viewtype::Image);
+            msg.set_file(image.to_str().unwrap(), None);
+            msg_ids.push(chat::prepare_msg(&t.ctx, *chat_id, &mut msg).await.unwrap());
+        }
+        chat::forward_msgs(&t.ctx, &msg_ids[..1], &chat_ids[2..])
+            .await
+            .unwrap();
+
+        let msg = Message::load_from_db(&t.ctx, msg_ids[1]).await.unwrap();
+        let blob =
+            BlobObject::from_name(&t.ctx, msg.param.get(Param::File).unwrap().to_string()).unwrap();
+        assert_eq!(blob.count_references(&t.ctx).await.unwrap(), 3);
+
+        let blob_files: Vec<_> = std::fs::read_dir(t.ctx.get_blobdir())
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with("dedup"))
+            .collect();
+        assert_eq!(blob_files.len(), 1);
+        assert_eq!(
+            blob_files[0].file_name().to_str(),
+            Some(blob.as_file_name())
+        );
+
+        // the original filename is kept if another blob is used
+        let other = t.dir.path().join("other.png");
+        std::fs::copy(&image, &other).unwrap();
+        let mut msg = Message::new(Viewtype::File);
+        msg.set_file(other.to_str().unwrap(), None);
+        let msg_id = chat::prepare_msg(&t.ctx, chat_ids[0], &mut msg)
+            .await
+            .unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.param.get(Param::File), Some(blob.as_name()));
+        assert_eq!(msg.get_filename(), Some("other.png".to_string()));
+    }
+
+    #[async_std::test]
+    async fn test_deduplicate_avatar() {
+        use crate::chat;
+        use crate::contact::Contact;
+        use crate::message::Message;
+
+        let t = TestContext::new_alice().await;
+        let image = t.dir.path().join("avatar.png");
+        std::fs::write(
+            &image,
+            include_bytes!("../test-data/image/avatar64x64.png").as_ref(),
+        )
+        .unwrap();
+        t.ctx
+            .set_config(Config::Selfavatar, Some(image.to_str().unwrap()))
+            .await
+            .unwrap();
+        let avatar = t.ctx.get_config(Config::Selfavatar).await.unwrap();
+
+        let contact_id = Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, contact_id)
+            .await
+            .unwrap();
+
+        // a copy of the avatar is recorded first, then the avatar itself is sent,
+        // the avatar must not be replaced by the copy.
+        let copy = t.dir.path().join("copy.png");
+        std::fs::copy(&avatar, &copy).unwrap();
+        for file in &[copy.to_str().unwrap(), avatar.as_str()] {
+            let mut msg = Message::new(Viewtype::File);
+            msg.set_file(file, None);
+            chat::prepare_msg(&t.ctx, chat_id, &mut msg).await.unwrap();
+        }
+        assert!(Path::new(&avatar).exists().await);
+        let blob = BlobObject::new_from_path(&t.ctx, &avatar).await.unwrap();
+        assert_eq!(blob.count_references(&t.ctx).await.unwrap(), 2);
+    }
 }
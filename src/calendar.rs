@@ -0,0 +1,254 @@
+//! # Calendar invitations
+//!
+//! Minimal parser for the `VEVENT` component of iCalendar data
+//! (RFC 5545) as sent in `text/calendar` parts of invitation emails.
+
+use chrono::{NaiveDate, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Basic information about an event found in a `text/calendar` part.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CalendarEvent {
+    /// Title of the event.
+    pub summary: String,
+
+    /// Start of the event as unix timestamp, see [CalendarEvent::floating].
+    pub start: i64,
+
+    /// End of the event as unix timestamp, if given.
+    pub end: Option<i64>,
+
+    /// True if `start` or `end` is a local time and not an absolute time.
+    ///
+    /// This is the case for dates, for times without timezone and for
+    /// times given in a named timezone, which is not resolved. The timestamp
+    /// then holds the local date and time as if it was UTC.
+    pub floating: bool,
+
+    /// Timezone of the local times as given by the `TZID` parameter, if any.
+    pub timezone: Option<String>,
+
+    /// Location of the event, if given.
+    pub location: Option<String>,
+
+    /// Email address of the organizer, if given.
+    pub organizer: Option<String>,
+}
+
+impl CalendarEvent {
+    /// Parses the first `VEVENT` of the given iCalendar data.
+    ///
+    /// Returns `None` if there is no event or if it has no valid start time.
+    pub(crate) fn parse(ics: &str) -> Option<Self> {
+        let mut in_event = false;
+        // Nesting depth of components inside the event, as VALARM.
+        let mut nested = 0;
+        let mut event = CalendarEvent::default();
+        let mut has_start = false;
+
+        for line in unfold_lines(ics) {
+            let (name, value) = match split_property(&line) {
+                Some(property) => property,
+                None => continue,
+            };
+            let mut name_params = name.splitn(2, ';');
+            let name = name_params.next().unwrap_or_default();
+            let params = name_params.next().unwrap_or_default();
+            let name = name.to_ascii_uppercase();
+
+            if !in_event {
+                if name == "BEGIN" && value.eq_ignore_ascii_case("VEVENT") {
+                    in_event = true;
+                }
+                continue;
+            }
+
+            match name.as_str() {
+                "BEGIN" => nested += 1,
+                "END" if nested > 0 => nested -= 1,
+                "END" => break,
+                // Properties of nested components do not describe the event.
+                _ if nested > 0 => {}
+                "SUMMARY" => event.summary = unescape_text(value),
+                "LOCATION" => {
+                    let location = unescape_text(value);
+                    if !location.is_empty() {
+                        event.location = Some(location);
+                    }
+                }
+                "ORGANIZER" => {
+                    let addr = match value.get(..7) {
+                        Some(scheme) if scheme.eq_ignore_ascii_case("mailto:") => {
+                            value.get(7..).unwrap_or_default()
+                        }
+                        _ => value,
+                    };
+                    event.organizer = Some(addr.to_string()).filter(|a| !a.is_empty());
+                }
+                "DTSTART" => {
+                    event.start = event.parse_date_time(value, params)?;
+                    has_start = true;
+                }
+                "DTEND" => event.end = event.parse_date_time(value, params),
+                _ => {}
+            }
+        }
+
+        if in_event && has_start {
+            Some(event)
+        } else {
+            None
+        }
+    }
+
+    /// Parses a `DATE` or `DATE-TIME` value to a unix timestamp,
+    /// marking the event as floating if the value is a local time.
+    fn parse_date_time(&mut self, value: &str, params: &str) -> Option<i64> {
+        let value = value.trim();
+        let mut is_date = value.len() == 8;
+        let mut tzid = None;
+        for param in params.split(';') {
+            let mut param = param.splitn(2, '=');
+            let name = param.next().unwrap_or_default();
+            let param_value = param.next().unwrap_or_default();
+            if name.eq_ignore_ascii_case("VALUE") && param_value.eq_ignore_ascii_case("DATE") {
+                is_date = true;
+            } else if name.eq_ignore_ascii_case("TZID") {
+                tzid = Some(param_value.trim_matches('"').to_string());
+            }
+        }
+
+        let timestamp = if is_date {
+            let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+            self.floating = true;
+            date.and_hms(0, 0, 0).timestamp()
+        } else {
+            let (value, is_utc) = match value.strip_suffix('Z') {
+                Some(value) => (value, true),
+                None => (value, false),
+            };
+            let time = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+            if !is_utc {
+                self.floating = true;
+                if tzid.is_some() {
+                    self.timezone = tzid;
+                }
+            }
+            Utc.from_utc_datetime(&time).timestamp()
+        };
+        Some(timestamp)
+    }
+}
+
+/// Joins lines folded as described in RFC 5545, section 3.1.
+fn unfold_lines(ics: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in ics.lines() {
+        let line = line.trim_end_matches('\r');
+        if let Some(folded) = line.strip_prefix(' ').or_else(|| line.strip_prefix('\t')) {
+            if let Some(last) = lines.last_mut() {
+                last.push_str(folded);
+                continue;
+            }
+        }
+        lines.push(line.to_string());
+    }
+    lines
+}
+
+/// Splits a content line into name with parameters and value.
+///
+/// Colons inside quoted parameter values do not end the name.
+fn split_property(line: &str) -> Option<(&str, &str)> {
+    let mut quoted = false;
+    for (ix, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ':' if !quoted => return Some((line.get(..ix)?, line.get(ix + 1..)?)),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn unescape_text(value: &str) -> String {
+    let mut ret = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') | Some('N') => ret.push('\n'),
+                Some(c) => ret.push(c),
+                None => {}
+            }
+        } else {
+            ret.push(c);
+        }
+    }
+    ret.trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_calendar_event() {
+        let ics = "BEGIN:VCALENDAR\r\n\
+                   VERSION:2.0\r\n\
+                   BEGIN:VEVENT\r\n\
+                   DTSTART;VALUE=DATE:20200401\r\n\
+                   DTEND;TZID=Europe/Berlin:20200402T100000\r\n\
+                   SUMMARY:Meeting\\, with a very long title that is folded over \r\n \
+                   two lines\r\n\
+                   ORGANIZER;CN=\"Bob: the organizer\":MAILTO:bob@example.org\r\n\
+                   BEGIN:VALARM\r\n\
+                   SUMMARY:Reminder\r\n\
+                   END:VALARM\r\n\
+                   END:VEVENT\r\n\
+                   END:VCALENDAR\r\n";
+        let event = CalendarEvent::parse(ics).unwrap();
+        assert_eq!(
+            event.summary,
+            "Meeting, with a very long title that is folded over two lines"
+        );
+        // the local times are kept, the timezone is not resolved
+        assert_eq!(event.start, 1585699200);
+        assert_eq!(event.end, Some(1585821600));
+        assert!(event.floating);
+        assert_eq!(event.timezone, Some("Europe/Berlin".to_string()));
+        assert_eq!(event.location, None);
+        assert_eq!(event.organizer, Some("bob@example.org".to_string()));
+    }
+
+    #[test]
+    fn test_parse_calendar_event_utc() {
+        let ics = "BEGIN:VEVENT\n\
+                   DTSTART:20200402T080000Z\n\
+                   DTEND:20200402T090000Z\n\
+                   SUMMARY:Call\n\
+                   END:VEVENT\n";
+        let event = CalendarEvent::parse(ics).unwrap();
+        assert_eq!(event.start, 1585814400);
+        assert_eq!(event.end, Some(1585818000));
+        assert!(!event.floating);
+        assert_eq!(event.timezone, None);
+    }
+
+    #[test]
+    fn test_parse_calendar_event_malformed() {
+        assert_eq!(CalendarEvent::parse(""), None);
+        assert_eq!(CalendarEvent::parse("not a calendar"), None);
+        assert_eq!(
+            CalendarEvent::parse(
+                "BEGIN:VCALENDAR\nBEGIN:VTODO\nDTSTART:20200401T100000Z\nEND:VTODO\nEND:VCALENDAR"
+            ),
+            None
+        );
+        assert_eq!(
+            CalendarEvent::parse("BEGIN:VEVENT\nSUMMARY:no start\nDTSTART:tomorrow\nEND:VEVENT"),
+            None
+        );
+    }
+}
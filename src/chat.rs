@@ -1,9 +1,11 @@
 //! # Chat module
 
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::time::{Duration, SystemTime};
 
 use async_std::path::{Path, PathBuf};
+use deltachat_derive::{FromSql, ToSql};
 use itertools::Itertools;
 use num_traits::FromPrimitive;
 use serde::{Deserialize, Serialize};
@@ -15,15 +17,20 @@ use crate::constants::*;
 use crate::contact::*;
 use crate::context::Context;
 use crate::dc_tools::*;
+use crate::e2ee::EncryptHelper;
 use crate::ephemeral::{delete_expired_messages, schedule_ephemeral_task, Timer as EphemeralTimer};
 use crate::error::{bail, ensure, format_err, Error};
 use crate::events::EventType;
 use crate::job::{self, Action};
+use crate::mailinglist::{self, ListHeaders, Mailto};
 use crate::message::{self, InvalidMsgId, Message, MessageState, MsgId};
 use crate::mimeparser::SystemMessage;
 use crate::param::*;
+use crate::peerstate::{Peerstate, PeerstateVerifiedStatus};
 use crate::sql;
 use crate::stock::StockMessage;
+use crate::sync::{self, SyncData};
+use crate::webxdc;
 
 /// An chat item, such as a message or a marker.
 #[derive(Debug, Copy, Clone)]
@@ -119,6 +126,16 @@ impl ChatId {
         self.0 == DC_CHAT_ID_ALLDONE_HINT
     }
 
+    /// Chat ID holding messages of unknown senders flagged as spam.
+    ///
+    /// Messages only get this chat ID if [Config::SpamHandling] is
+    /// set to [SpamHandling::Separate], they can be listed using
+    /// [get_chat_msgs] and moved to a normal chat using
+    /// [crate::message::mark_not_spam].
+    pub fn is_spam(self) -> bool {
+        self.0 == DC_CHAT_ID_SPAM
+    }
+
     pub async fn set_selfavatar_timestamp(
         self,
         context: &Context,
@@ -167,6 +184,49 @@ impl ChatId {
             self
         );
 
+        self.set_visibility_ex(context, visibility, time()).await?;
+
+        if let Some(chat) = sync::ChatIdentifier::from_chat_id(context, self).await {
+            let data = SyncData::ChatVisibility { chat, visibility };
+            if let Err(err) = sync::send_sync_msg(context, data).await {
+                warn!(context, "Cannot sync chat visibility: {}", err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets the visibility without informing other devices.
+    ///
+    /// The change is ignored if a change newer than `timestamp` was
+    /// already applied, so that the most recent change wins if several
+    /// devices changed the visibility.
+    pub(crate) async fn set_visibility_ex(
+        self,
+        context: &Context,
+        visibility: ChatVisibility,
+        timestamp: i64,
+    ) -> Result<(), Error> {
+        ensure!(
+            !self.is_special(),
+            "bad chat_id, can not be special chat: {}",
+            self
+        );
+
+        let mut chat = Chat::load_from_db(context, self).await?;
+        let last_change = chat
+            .param
+            .get(Param::VisibilityTimestamp)
+            .and_then(|ts| ts.parse::<i64>().ok())
+            .unwrap_or_default();
+        if timestamp < last_change {
+            info!(
+                context,
+                "Ignoring visibility change of chat {}, a newer one exists.", self
+            );
+            return Ok(());
+        }
+
         if visibility == ChatVisibility::Archived {
             context
                 .sql
@@ -184,6 +244,9 @@ impl ChatId {
                 paramsv![visibility, self],
             )
             .await?;
+        chat.param
+            .set(Param::VisibilityTimestamp, timestamp.to_string());
+        chat.update_param(context).await?;
 
         context.emit_event(EventType::MsgsChanged {
             msg_id: MsgId::new(0),
@@ -224,6 +287,14 @@ impl ChatId {
             )
             .await?;
 
+        context
+            .sql
+            .execute(
+                "DELETE FROM msgs_seen WHERE msg_id IN (SELECT id FROM msgs WHERE chat_id=?);",
+                paramsv![self],
+            )
+            .await?;
+
         context
             .sql
             .execute("DELETE FROM msgs WHERE chat_id=?;", paramsv![self])
@@ -636,9 +707,23 @@ impl Chat {
         self.param.exists(Param::Devicetalk)
     }
 
+    /// Returns true if chat is a mailing list.
+    ///
+    /// Mailing list chats are created for messages with a `List-Id`
+    /// header, see [Chat::get_mailinglist].
+    pub fn is_mailing_list(&self) -> bool {
+        self.param.exists(Param::ListId)
+    }
+
+    /// Returns the information from the `List-*` headers of the last
+    /// message received in a mailing list chat.
+    pub fn get_mailinglist(&self) -> Option<ListHeaders> {
+        ListHeaders::from_params(&self.param)
+    }
+
     /// Returns true if user can send messages to this chat.
     pub fn can_send(&self) -> bool {
-        !self.id.is_special() && !self.is_device_talk()
+        !self.id.is_special() && !self.is_device_talk() && !self.is_mailing_list()
     }
 
     pub async fn update_param(&mut self, context: &Context) -> Result<(), Error> {
@@ -684,6 +769,15 @@ impl Chat {
         None
     }
 
+    /// Returns the time of the last change of the group image,
+    /// changes sent before are ignored when received.
+    pub fn get_profile_image_timestamp(&self) -> i64 {
+        self.param
+            .get(Param::ProfileImageTimestamp)
+            .and_then(|ts| ts.parse::<i64>().ok())
+            .unwrap_or_default()
+    }
+
     pub async fn get_gossiped_timestamp(&self, context: &Context) -> i64 {
         get_gossiped_timestamp(context, self.id).await
     }
@@ -738,6 +832,11 @@ impl Chat {
         self.visibility
     }
 
+    /// Returns true if the chat is pinned to the top of the chatlist.
+    pub fn is_pinned(&self) -> bool {
+        self.visibility == ChatVisibility::Pinned
+    }
+
     pub fn is_unpromoted(&self) -> bool {
         self.param.get_int(Param::Unpromoted).unwrap_or_default() == 1
     }
@@ -746,6 +845,11 @@ impl Chat {
         !self.is_unpromoted()
     }
 
+    /// Returns true if only admins can post to the group, see [set_broadcast].
+    pub fn is_broadcast(&self) -> bool {
+        self.param.get_bool(Param::Broadcast).unwrap_or_default()
+    }
+
     /// Returns true if chat is a verified group chat.
     pub fn is_verified(&self) -> bool {
         self.typ == Chattype::VerifiedGroup
@@ -764,6 +868,12 @@ impl Chat {
         }
     }
 
+    /// Returns the override of [Config::MdnsEnabled] set by [set_mdns_enabled],
+    /// `None` if the global setting is used.
+    pub fn get_mdns_enabled(&self) -> Option<bool> {
+        self.param.get_bool(Param::MdnsEnabled)
+    }
+
     async fn prepare_msg_raw(
         &mut self,
         context: &Context,
@@ -800,7 +910,7 @@ impl Chat {
                     Chattype::Group | Chattype::VerifiedGroup => Some(self.grpid.as_str()),
                     _ => None,
                 };
-                dc_create_outgoing_rfc724_mid(grpid, &from)
+                dc_create_outgoing_rfc724_mid(grpid, &context.get_message_id_addr(&from).await)
             };
 
             if self.typ == Chattype::Single {
@@ -1375,6 +1485,7 @@ pub(crate) fn msgtype_has_file(msgtype: Viewtype) -> bool {
         Viewtype::Video => true,
         Viewtype::File => true,
         Viewtype::VideochatInvitation => false,
+        Viewtype::Webxdc => true,
     }
 }
 
@@ -1395,8 +1506,27 @@ async fn prepare_msg_blob(context: &Context, msg: &mut Message) -> Result<(), Er
                 warn!(context, "Cannot recode image, using original data: {:?}", e);
             }
         }
+        let blob = if msg.is_increation() {
+            blob
+        } else {
+            let filename = blob.as_file_name().to_string();
+            let blob = blob.deduplicate(context).await?;
+            if blob.as_file_name() != filename && msg.param.get(Param::Filename).is_none() {
+                msg.param.set(Param::Filename, filename);
+            }
+            blob
+        };
         msg.param.set(Param::File, blob.as_name());
 
+        if msg.viewtype == Viewtype::Webxdc {
+            let data = async_std::fs::read(blob.to_abs_path()).await?;
+            ensure!(
+                webxdc::is_webxdc(blob.as_name(), &data),
+                "Invalid webxdc app {}",
+                blob.as_name()
+            );
+        }
+
         if msg.viewtype == Viewtype::File || msg.viewtype == Viewtype::Image {
             // Correct the type, take care not to correct already very special
             // formats as GIF or VOICE.
@@ -1440,6 +1570,12 @@ async fn prepare_msg_common(
 
     let mut chat = Chat::load_from_db(context, chat_id).await?;
     ensure!(chat.can_send(), "cannot send to {}", chat_id);
+    ensure!(
+        msg.param.get_cmd() != SystemMessage::Unknown
+            || may_post(context, &chat, DC_CONTACT_ID_SELF).await,
+        "Only admins can post to broadcast {}",
+        chat_id
+    );
 
     // The OutPreparing state is set by dc_prepare_msg() before it
     // calls this function and the message is left in the OutPreparing
@@ -1614,6 +1750,78 @@ pub async fn send_text_msg(
     send_msg(context, chat_id, &mut msg).await
 }
 
+/// Unsubscribes from the mailing list shown in the given chat.
+///
+/// If the list supports the One-Click unsubscription of RFC 8058, the
+/// request is sent as HTTPS POST, otherwise an unsubscribe message is
+/// sent to the `mailto:` address of the `List-Unsubscribe` header.
+/// In both cases the result is reported by [EventType::ListUnsubscribed],
+/// for the message only after the SMTP job has finished or given up.
+pub async fn unsubscribe_from_list(context: &Context, chat_id: ChatId) -> Result<(), Error> {
+    let chat = Chat::load_from_db(context, chat_id).await?;
+    let list = chat
+        .get_mailinglist()
+        .ok_or_else(|| format_err!("chat {} is not a mailing list", chat_id))?;
+
+    let res = send_unsubscribe_request(context, chat_id, &list).await;
+    match res {
+        Ok(UnsubscribeRequest::Posted) => context.emit_event(EventType::ListUnsubscribed {
+            chat_id,
+            success: true,
+        }),
+        Ok(UnsubscribeRequest::Queued) => {}
+        Err(ref err) => {
+            warn!(context, "Cannot unsubscribe from {}: {}", list.id, err);
+            context.emit_event(EventType::ListUnsubscribed {
+                chat_id,
+                success: false,
+            });
+        }
+    }
+    res.map(|_| ())
+}
+
+/// How an unsubscribe request was sent.
+enum UnsubscribeRequest {
+    /// The One-Click POST succeeded.
+    Posted,
+
+    /// The unsubscribe mail is waiting for the SMTP job.
+    Queued,
+}
+
+async fn send_unsubscribe_request(
+    context: &Context,
+    chat_id: ChatId,
+    list: &ListHeaders,
+) -> Result<UnsubscribeRequest, Error> {
+    if list.unsubscribe_one_click {
+        if let Some(ref url) = list.unsubscribe_url {
+            mailinglist::post_one_click(url).await?;
+            return Ok(UnsubscribeRequest::Posted);
+        }
+    }
+
+    let mailto = list
+        .unsubscribe_mailto
+        .as_ref()
+        .and_then(|uri| Mailto::parse(uri))
+        .ok_or_else(|| format_err!("no supported unsubscribe method for {}", list.id))?;
+
+    // the request is not shown in any chat and does not create a contact
+    let rendered_msg = crate::mimefactory::render_plain_mail(
+        context,
+        &mailto.addr,
+        mailto.subject.as_deref().unwrap_or("unsubscribe"),
+        mailto.body.as_deref().unwrap_or("unsubscribe"),
+    )
+    .await?;
+    let mut param = Params::new();
+    param.set_int(Param::ListUnsubscribeChatId, chat_id.to_u32() as i32);
+    job::add_smtp_mail(context, &[mailto.addr], &rendered_msg, param).await?;
+    Ok(UnsubscribeRequest::Queued)
+}
+
 pub async fn send_videochat_invitation(context: &Context, chat_id: ChatId) -> Result<MsgId, Error> {
     ensure!(
         !chat_id.is_special(),
@@ -1761,33 +1969,233 @@ pub async fn get_chat_msgs(
     }
 }
 
-pub async fn marknoticed_chat(context: &Context, chat_id: ChatId) -> Result<(), Error> {
-    if !context
+/// Consecutive messages of a sender that are shown together, see [get_message_groups].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageGroup {
+    /// Contact ID of the sender.
+    pub from_id: u32,
+
+    /// Messages of the group, oldest first.
+    pub msg_ids: Vec<MsgId>,
+}
+
+/// Groups the messages of a chat into bubbles of consecutive messages of the same sender.
+///
+/// A group ends when another contact writes, when the view type changes
+/// or when more than [Config::MessageGroupWindow] seconds passed since the previous message.
+pub async fn get_message_groups(
+    context: &Context,
+    chat_id: ChatId,
+) -> Result<Vec<MessageGroup>, Error> {
+    let window = i64::from(context.get_config_int(Config::MessageGroupWindow).await);
+    let msgs = context
         .sql
-        .exists(
-            "SELECT id FROM msgs  WHERE chat_id=? AND state=?;",
-            paramsv![chat_id, MessageState::InFresh],
+        .query_map(
+            "SELECT id, from_id, timestamp, type
+               FROM msgs
+              WHERE chat_id=?
+                AND hidden=0
+              ORDER BY timestamp, id;",
+            paramsv![chat_id],
+            |row| {
+                Ok((
+                    row.get::<_, MsgId>(0)?,
+                    row.get::<_, u32>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, Viewtype>(3)?,
+                ))
+            },
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
         )
-        .await?
-    {
-        return Ok(());
+        .await?;
+
+    let mut groups: Vec<MessageGroup> = Vec::new();
+    let mut prev: Option<(u32, i64, Viewtype)> = None;
+    for (msg_id, from_id, timestamp, viewtype) in msgs {
+        let continues_group = match prev {
+            Some((prev_from_id, prev_timestamp, prev_viewtype)) => {
+                from_id == prev_from_id
+                    && viewtype == prev_viewtype
+                    && timestamp - prev_timestamp <= window
+            }
+            None => false,
+        };
+        match groups.last_mut() {
+            Some(group) if continues_group => group.msg_ids.push(msg_id),
+            _ => groups.push(MessageGroup {
+                from_id,
+                msg_ids: vec![msg_id],
+            }),
+        }
+        prev = Some((from_id, timestamp, viewtype));
     }
+    Ok(groups)
+}
 
-    context
+/// Disk space used by a chat, see [get_storage_usage].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatStorageUsage {
+    pub chat_id: ChatId,
+
+    /// Number of messages in the chat, including hidden ones.
+    pub msg_count: usize,
+
+    /// Estimated size of the messages in the database, in bytes.
+    pub db_bytes: u64,
+
+    /// Size of the attachments in the blob directory, in bytes.
+    pub blob_bytes: u64,
+}
+
+impl ChatStorageUsage {
+    /// Returns the total size used by the chat in bytes.
+    pub fn total_bytes(&self) -> u64 {
+        self.db_bytes + self.blob_bytes
+    }
+}
+
+/// Returns the disk space used by the messages and attachments of a chat.
+///
+/// The database size is estimated from the length of the stored texts and headers,
+/// attachments shared by several messages are counted once.
+pub async fn get_storage_usage(
+    context: &Context,
+    chat_id: ChatId,
+) -> Result<ChatStorageUsage, Error> {
+    let (msg_count, db_bytes) = context
+        .sql
+        .query_row(
+            "SELECT COUNT(*),
+                    IFNULL(SUM(IFNULL(LENGTH(txt), 0)
+                             + IFNULL(LENGTH(txt_raw), 0)
+                             + IFNULL(LENGTH(param), 0)
+                             + IFNULL(LENGTH(mime_headers), 0)), 0)
+               FROM msgs
+              WHERE chat_id=?;",
+            paramsv![chat_id],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+        )
+        .await?;
+
+    let params = context
+        .sql
+        .query_map(
+            "SELECT param FROM msgs WHERE chat_id=? AND param LIKE '%f=%';",
+            paramsv![chat_id],
+            |row| row.get::<_, String>(0),
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+    let mut files = HashSet::new();
+    for param in params {
+        let param: Params = param.parse().unwrap_or_default();
+        if let Ok(Some(path)) = param.get_path(Param::File, context) {
+            files.insert(path);
+        }
+    }
+    let mut blob_bytes = 0;
+    for path in &files {
+        blob_bytes += context.get_blob_size(path).await;
+    }
+
+    Ok(ChatStorageUsage {
+        chat_id,
+        msg_count: msg_count as usize,
+        db_bytes: db_bytes as u64,
+        blob_bytes,
+    })
+}
+
+/// Returns the conversation thread the message `msg_id` belongs to.
+///
+/// The thread is reconstructed from the `In-Reply-To` and `References` headers
+/// of the messages in the same chat, as for [Message::quoted_message].
+/// Every message follows its parent, replies to the same message are sorted by time.
+/// If the parent of a message was not downloaded, it follows the closest known ancestor.
+pub async fn get_message_thread(context: &Context, msg_id: MsgId) -> Result<Vec<MsgId>, Error> {
+    let msg = Message::load_from_db(context, msg_id).await?;
+    let msgs = context
+        .sql
+        .query_map(
+            "SELECT id, rfc724_mid, mime_in_reply_to, mime_references
+               FROM msgs
+              WHERE chat_id=?
+              ORDER BY timestamp, id;",
+            paramsv![msg.chat_id],
+            |row| {
+                Ok((
+                    row.get::<_, MsgId>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                    row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+                ))
+            },
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+
+    let mut ids_by_mid = HashMap::new();
+    for (id, rfc724_mid, _, _) in &msgs {
+        ids_by_mid.entry(rfc724_mid.as_str()).or_insert(*id);
+    }
+    let mut parents = HashMap::new();
+    let mut replies: HashMap<MsgId, Vec<MsgId>> = HashMap::new();
+    for (id, rfc724_mid, in_reply_to, references) in &msgs {
+        let parent = message::parent_candidates(rfc724_mid, in_reply_to, references)
+            .iter()
+            .filter_map(|candidate| ids_by_mid.get(candidate.as_str()))
+            .find(|parent| *parent != id)
+            .copied();
+        if let Some(parent) = parent {
+            parents.insert(*id, parent);
+            replies.entry(parent).or_default().push(*id);
+        }
+    }
+
+    // forged headers may form a cycle, so every message is visited once only
+    let mut visited = HashSet::new();
+    let mut root = msg_id;
+    while let Some(parent) = parents.get(&root) {
+        if !visited.insert(root) {
+            break;
+        }
+        root = *parent;
+    }
+
+    let mut visited = HashSet::new();
+    let mut thread = Vec::new();
+    let mut stack = vec![root];
+    while let Some(id) = stack.pop() {
+        if !visited.insert(id) {
+            continue;
+        }
+        thread.push(id);
+        if let Some(replies) = replies.get(&id) {
+            stack.extend(replies.iter().rev());
+        }
+    }
+    Ok(thread)
+}
+
+/// Marks all fresh messages of a chat as noticed, eg. when the chat is opened.
+///
+/// Unlike [message::markseen_msgs], no read receipts are sent
+/// and the messages are not marked as seen on the server.
+/// Emits a single [EventType::MsgsNoticed] if there were fresh messages.
+pub async fn marknoticed_chat(context: &Context, chat_id: ChatId) -> Result<(), Error> {
+    let noticed = context
         .sql
         .execute(
             "UPDATE msgs
-            SET state=13
+            SET state=?
           WHERE chat_id=?
-            AND state=10;",
-            paramsv![chat_id],
+            AND state=?;",
+            paramsv![MessageState::InNoticed, chat_id, MessageState::InFresh],
         )
         .await?;
-
-    context.emit_event(EventType::MsgsChanged {
-        chat_id: ChatId::new(0),
-        msg_id: MsgId::new(0),
-    });
+    if noticed > 0 {
+        context.emit_event(EventType::MsgsNoticed(chat_id));
+    }
 
     Ok(())
 }
@@ -1824,6 +2232,39 @@ pub async fn marknoticed_all_chats(context: &Context) -> Result<(), Error> {
     Ok(())
 }
 
+/// Deletes all messages of a chat, keeping the chat with its members and draft.
+///
+/// If `also_on_server` is set, the messages are deleted from the server as well
+/// and other devices are informed, otherwise they are only deleted locally.
+/// Starred messages are kept if `keep_starred` is set.
+/// Emits a single [EventType::MsgsChanged] if messages were deleted.
+pub async fn delete_all_messages(
+    context: &Context,
+    chat_id: ChatId,
+    also_on_server: bool,
+    keep_starred: bool,
+) -> Result<(), Error> {
+    ensure!(!chat_id.is_special(), "Invalid chat ID");
+    Chat::load_from_db(context, chat_id).await?;
+
+    let msg_ids = context
+        .sql
+        .query_map(
+            "SELECT id FROM msgs WHERE chat_id=? AND state!=? AND (?=0 OR starred=0);",
+            paramsv![chat_id, MessageState::OutDraft, keep_starred],
+            |row| row.get::<_, MsgId>(0),
+            |ids| ids.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+    if also_on_server {
+        message::delete_msgs(context, &msg_ids).await;
+    } else {
+        message::delete_msgs_ex(context, &msg_ids, false).await;
+    }
+
+    Ok(())
+}
+
 pub async fn get_chat_media(
     context: &Context,
     chat_id: ChatId,
@@ -1949,6 +2390,82 @@ pub async fn get_chat_contacts(context: &Context, chat_id: ChatId) -> Vec<u32> {
         .unwrap_or_default()
 }
 
+/// Whether a message sent to a chat would be end-to-end encrypted, as returned by [would_encrypt].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncryptionDecision {
+    /// True if a message sent now would be encrypted.
+    pub encrypt: bool,
+
+    /// Addresses of the recipients there is no usable key for.
+    pub missing_keys: Vec<String>,
+}
+
+/// Determines whether a message sent to the chat would be encrypted,
+/// without rendering a message.
+///
+/// The same rules as for sending apply: all recipients need a key,
+/// a verified one in verified groups,
+/// and everyone must prefer encryption unless the chat is verified.
+/// A draft forced to be sent unencrypted is not encrypted outside of verified groups.
+pub async fn would_encrypt(
+    context: &Context,
+    chat_id: ChatId,
+) -> Result<EncryptionDecision, Error> {
+    let chat = Chat::load_from_db(context, chat_id).await?;
+    let e2ee_guaranteed = chat.typ == Chattype::VerifiedGroup;
+
+    let mut addrs = Vec::new();
+    for contact_id in get_chat_contacts(context, chat_id).await {
+        if contact_id > DC_CONTACT_ID_LAST_SPECIAL {
+            let contact = Contact::load_from_db(context, contact_id).await?;
+            addrs.push(contact.get_addr().to_string());
+        }
+    }
+
+    let mut peerstates = Vec::new();
+    for addr in &addrs {
+        peerstates.push((Peerstate::from_addr(context, addr).await?, addr.as_str()));
+    }
+
+    let min_verified = if e2ee_guaranteed {
+        PeerstateVerifiedStatus::BidirectVerified
+    } else {
+        PeerstateVerifiedStatus::Unverified
+    };
+    let missing_keys: Vec<String> = peerstates
+        .iter()
+        .filter(|(peerstate, _)| {
+            peerstate
+                .as_ref()
+                .and_then(|peerstate| peerstate.peek_key(min_verified))
+                .is_none()
+        })
+        .map(|(_, addr)| addr.to_string())
+        .collect();
+
+    let force_plaintext = !e2ee_guaranteed
+        && chat_id.get_draft(context).await?.map_or(false, |draft| {
+            draft
+                .param
+                .get_int(Param::ForcePlaintext)
+                .unwrap_or_default()
+                != 0
+        });
+
+    let encrypt = missing_keys.is_empty()
+        && !force_plaintext
+        && EncryptHelper::new(context).await?.should_encrypt(
+            context,
+            e2ee_guaranteed,
+            &peerstates,
+        )?;
+
+    Ok(EncryptionDecision {
+        encrypt,
+        missing_keys,
+    })
+}
+
 pub async fn create_group_chat(
     context: &Context,
     verified: VerifiedStatus,
@@ -1983,6 +2500,7 @@ pub async fn create_group_chat(
 
     let chat_id = ChatId::new(row_id);
     if add_to_chat_contacts_table(context, chat_id, DC_CONTACT_ID_SELF).await {
+        set_member_role_raw(context, chat_id, DC_CONTACT_ID_SELF, Role::Admin).await?;
         let mut draft_msg = Message::new(Viewtype::Text);
         draft_msg.set_text(Some(draft_txt));
         chat_id.set_draft_raw(context, &mut draft_msg).await;
@@ -2094,6 +2612,13 @@ pub(crate) async fn add_contact_to_chat_ex(
         );
         bail!("can not add contact because our account is not part of it");
     }
+    // contacts scanning a QR code were invited by an admin before,
+    // see dc_get_securejoin_qr()
+    ensure!(
+        from_handshake || may_manage_group(context, chat_id, DC_CONTACT_ID_SELF).await,
+        "Only admins can add members to {}",
+        chat_id
+    );
     if from_handshake && chat.param.get_int(Param::Unpromoted).unwrap_or_default() == 1 {
         chat.param.remove(Param::Unpromoted);
         chat.update_param(context).await?;
@@ -2295,7 +2820,7 @@ impl rusqlite::types::FromSql for MuteDuration {
     }
 }
 
-pub async fn set_muted(
+pub async fn set_mute_duration(
     context: &Context,
     chat_id: ChatId,
     duration: MuteDuration,
@@ -2317,39 +2842,128 @@ pub async fn set_muted(
     Ok(())
 }
 
-pub async fn remove_contact_from_chat(
+/// Enables or disables read receipts for a chat regardless of [Config::MdnsEnabled].
+///
+/// With `None`, the global setting is used again.
+pub async fn set_mdns_enabled(
     context: &Context,
     chat_id: ChatId,
-    contact_id: u32,
+    enabled: Option<bool>,
 ) -> Result<(), Error> {
-    ensure!(
-        !chat_id.is_special(),
-        "bad chat_id, can not be special chat: {}",
-        chat_id
-    );
-    ensure!(
-        contact_id > DC_CONTACT_ID_LAST_SPECIAL || contact_id == DC_CONTACT_ID_SELF,
-        "Cannot remove special contact"
-    );
-
-    let mut msg = Message::default();
-    let mut success = false;
+    ensure!(!chat_id.is_special(), "Invalid chat ID");
+    let mut chat = Chat::load_from_db(context, chat_id).await?;
+    match enabled {
+        Some(enabled) => chat.param.set_int(Param::MdnsEnabled, enabled as i32),
+        None => chat.param.remove(Param::MdnsEnabled),
+    };
+    chat.update_param(context).await?;
+    context.emit_event(EventType::ChatModified(chat_id));
+    Ok(())
+}
 
-    /* we do not check if "contact_id" exists but just delete all records with the id from chats_contacts */
-    /* this allows to delete pending references to deleted contacts.  Of course, this should _not_ happen. */
+/// Returns whether read receipts are requested and sent in the chat,
+/// see [set_mdns_enabled].
+pub(crate) async fn mdns_enabled(context: &Context, chat_id: ChatId) -> bool {
     if let Ok(chat) = Chat::load_from_db(context, chat_id).await {
-        if real_group_exists(context, chat_id).await {
-            if !is_contact_in_chat(context, chat_id, DC_CONTACT_ID_SELF).await {
-                emit_event!(
-                    context,
-                    EventType::ErrorSelfNotInGroup(
-                        "Cannot remove contact from chat; self not in group.".into()
-                    )
-                );
-            } else {
-                if let Ok(contact) = Contact::get_by_id(context, contact_id).await {
-                    if chat.is_promoted() {
-                        msg.viewtype = Viewtype::Text;
+        if let Some(enabled) = chat.get_mdns_enabled() {
+            return enabled;
+        }
+    }
+    context.get_config_bool(Config::MdnsEnabled).await
+}
+
+/// Archives a chat.
+///
+/// Archived chats are hidden from the normal chatlist, which shows a
+/// single `DC_CHAT_ID_ARCHIVED_LINK` entry instead as long as there is
+/// any archived chat.  A chat is unarchived automatically when a new
+/// message arrives; use [ChatId::set_visibility] to unarchive it manually.
+pub async fn archive(context: &Context, chat_id: ChatId) -> Result<(), Error> {
+    chat_id
+        .set_visibility(context, ChatVisibility::Archived)
+        .await
+}
+
+/// Pins a chat to the top of the chatlist or unpins it.
+///
+/// Pinned chats are sorted by recency among themselves.  Unpinning a
+/// chat that is not pinned, e.g. an archived chat, does nothing.
+pub async fn set_pinned(context: &Context, chat_id: ChatId, pinned: bool) -> Result<(), Error> {
+    if pinned {
+        chat_id
+            .set_visibility(context, ChatVisibility::Pinned)
+            .await
+    } else {
+        let chat = Chat::load_from_db(context, chat_id).await?;
+        if chat.is_pinned() {
+            chat_id
+                .set_visibility(context, ChatVisibility::Normal)
+                .await
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Returns true if the chat is currently muted.
+///
+/// Timed mutes expire on their own, so a chat muted until a time in
+/// the past is reported as not muted.
+pub async fn is_muted(context: &Context, chat_id: ChatId) -> Result<bool, Error> {
+    ensure!(!chat_id.is_special(), "Invalid chat ID");
+    let chat = Chat::load_from_db(context, chat_id).await?;
+    Ok(chat.is_muted())
+}
+
+pub async fn remove_contact_from_chat(
+    context: &Context,
+    chat_id: ChatId,
+    contact_id: u32,
+) -> Result<(), Error> {
+    remove_contact_from_chat_ex(context, chat_id, contact_id)
+        .await
+        .map(|_| ())
+}
+
+/// Removes a contact from the chat and returns the ID of the message
+/// informing the group, which is unset if the group is not promoted.
+async fn remove_contact_from_chat_ex(
+    context: &Context,
+    chat_id: ChatId,
+    contact_id: u32,
+) -> Result<MsgId, Error> {
+    ensure!(
+        !chat_id.is_special(),
+        "bad chat_id, can not be special chat: {}",
+        chat_id
+    );
+    ensure!(
+        contact_id > DC_CONTACT_ID_LAST_SPECIAL || contact_id == DC_CONTACT_ID_SELF,
+        "Cannot remove special contact"
+    );
+
+    let mut msg = Message::default();
+    let mut success = false;
+
+    /* we do not check if "contact_id" exists but just delete all records with the id from chats_contacts */
+    /* this allows to delete pending references to deleted contacts.  Of course, this should _not_ happen. */
+    if let Ok(chat) = Chat::load_from_db(context, chat_id).await {
+        if real_group_exists(context, chat_id).await {
+            if !is_contact_in_chat(context, chat_id, DC_CONTACT_ID_SELF).await {
+                emit_event!(
+                    context,
+                    EventType::ErrorSelfNotInGroup(
+                        "Cannot remove contact from chat; self not in group.".into()
+                    )
+                );
+            } else if contact_id != DC_CONTACT_ID_SELF
+                && !may_manage_group(context, chat_id, DC_CONTACT_ID_SELF).await
+            {
+                bail!("Only admins can remove members from {}", chat_id);
+            } else {
+                if let Ok(contact) = Contact::get_by_id(context, contact_id).await {
+                    if chat.is_promoted() {
+                        msg.viewtype = Viewtype::Text;
                         if contact.id == DC_CONTACT_ID_SELF {
                             set_group_explicitly_left(context, chat.grpid).await?;
                             msg.text = Some(
@@ -2399,6 +3013,64 @@ pub async fn remove_contact_from_chat(
         bail!("Failed to remove contact");
     }
 
+    Ok(msg.id)
+}
+
+/// Leaves a group and deletes the chat with all its messages.
+///
+/// The leave message is queued first and sent even if the chat is gone.
+/// If `defer_deletion` is set, the chat is kept until the leave message is sent,
+/// so that the user can still see it pending; otherwise the chat is deleted at once.
+pub async fn leave_and_delete(
+    context: &Context,
+    chat_id: ChatId,
+    defer_deletion: bool,
+) -> Result<(), Error> {
+    ensure!(!chat_id.is_special(), "Invalid chat ID");
+    let mut chat = Chat::load_from_db(context, chat_id).await?;
+    ensure!(
+        real_group_exists(context, chat_id).await,
+        "{} is not a group that can be left",
+        chat_id
+    );
+
+    let msg_id = if is_contact_in_chat(context, chat_id, DC_CONTACT_ID_SELF).await {
+        remove_contact_from_chat_ex(context, chat_id, DC_CONTACT_ID_SELF).await?
+    } else {
+        MsgId::new(0)
+    };
+
+    if !msg_id.is_unset() {
+        if defer_deletion {
+            chat.param
+                .set_int(Param::DeleteAfterLeave, msg_id.to_u32() as i32);
+            chat.update_param(context).await?;
+            return Ok(());
+        }
+        // the message is already rendered, detach the job from the message
+        // so that deleting the message does not cancel sending
+        context
+            .sql
+            .execute(
+                "UPDATE jobs SET foreign_id=0 WHERE action=? AND foreign_id=?;",
+                paramsv![Action::SendMsgToSmtp, msg_id],
+            )
+            .await?;
+    }
+    chat_id.delete(context).await
+}
+
+/// Deletes the chat of a sent leave message if requested by [leave_and_delete].
+pub(crate) async fn delete_chat_after_leave(context: &Context, msg_id: MsgId) -> Result<(), Error> {
+    let msg = Message::load_from_db(context, msg_id).await?;
+    let chat = Chat::load_from_db(context, msg.chat_id).await?;
+    if chat.param.get_int(Param::DeleteAfterLeave) == Some(msg_id.to_u32() as i32) {
+        info!(
+            context,
+            "Leave message {} sent, deleting {}", msg_id, chat.id
+        );
+        chat.id.delete(context).await?;
+    }
     Ok(())
 }
 
@@ -2430,6 +3102,260 @@ pub(crate) async fn is_group_explicitly_left(
         .map_err(Into::into)
 }
 
+/// Role of a group member, see [set_member_role].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive, FromSql, ToSql)]
+#[repr(u32)]
+pub enum Role {
+    /// Can send messages and leave the group.
+    Member = 0,
+
+    /// Can also add and remove members, change the name and the avatar
+    /// of the group and set the roles of other members.
+    Admin = 1,
+}
+
+impl Default for Role {
+    fn default() -> Self {
+        Role::Member
+    }
+}
+
+/// Returns the role of a group member.
+///
+/// Contacts not being member of the group are reported as [Role::Member].
+pub async fn get_member_role(context: &Context, chat_id: ChatId, contact_id: u32) -> Role {
+    context
+        .sql
+        .query_get_value(
+            context,
+            "SELECT role FROM chats_contacts WHERE chat_id=? AND contact_id=?;",
+            paramsv![chat_id, contact_id],
+        )
+        .await
+        .unwrap_or_default()
+}
+
+/// Returns the contact IDs of the admins of a group.
+pub async fn get_group_admins(context: &Context, chat_id: ChatId) -> Vec<u32> {
+    context
+        .sql
+        .query_map(
+            "SELECT contact_id FROM chats_contacts WHERE chat_id=? AND role=? ORDER BY contact_id;",
+            paramsv![chat_id, Role::Admin],
+            |row| row.get::<_, u32>(0),
+            |ids| ids.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await
+        .unwrap_or_default()
+}
+
+/// Returns the addresses of the admins of a group as sent in the `Chat-Group-Admins` header.
+pub(crate) async fn get_group_admin_addrs(context: &Context, chat_id: ChatId) -> Vec<String> {
+    let mut addrs = Vec::new();
+    for contact_id in get_group_admins(context, chat_id).await {
+        if let Ok(contact) = Contact::get_by_id(context, contact_id).await {
+            if !contact.get_addr().is_empty() {
+                addrs.push(contact.get_addr().to_string());
+            }
+        }
+    }
+    addrs
+}
+
+/// Returns true if the contact may add and remove members,
+/// change the name and the avatar of the group or set roles.
+///
+/// Groups without any admin, eg. created by older versions,
+/// can be managed by all members.
+/// However, admins of such groups are never taken from received messages,
+/// they are only set by the message creating the group.
+pub(crate) async fn may_manage_group(context: &Context, chat_id: ChatId, contact_id: u32) -> bool {
+    let admins = get_group_admins(context, chat_id).await;
+    admins.is_empty() || admins.contains(&contact_id)
+}
+
+/// Returns true if the contact may post to the chat;
+/// in broadcast groups, only admins may post.
+pub(crate) async fn may_post(context: &Context, chat: &Chat, contact_id: u32) -> bool {
+    !chat.is_broadcast() || may_manage_group(context, chat.id, contact_id).await
+}
+
+/// Makes a group a broadcast group where only admins can post,
+/// or a normal group again.
+///
+/// Messages are still sent to all members. The other members learn about
+/// the change with the next message sent to the group.
+pub async fn set_broadcast(
+    context: &Context,
+    chat_id: ChatId,
+    broadcast: bool,
+) -> Result<(), Error> {
+    ensure!(!chat_id.is_special(), "Invalid chat ID");
+    let mut chat = Chat::load_from_db(context, chat_id).await?;
+    ensure!(
+        real_group_exists(context, chat_id).await,
+        "{} is not a group",
+        chat_id
+    );
+    ensure!(
+        may_manage_group(context, chat_id, DC_CONTACT_ID_SELF).await,
+        "Only admins can change the broadcast setting of {}",
+        chat_id
+    );
+    ensure!(
+        !broadcast || !get_group_admins(context, chat_id).await.is_empty(),
+        "Broadcast groups need an admin"
+    );
+    if chat.is_broadcast() == broadcast {
+        return Ok(());
+    }
+    if broadcast {
+        chat.param.set_int(Param::Broadcast, 1);
+    } else {
+        chat.param.remove(Param::Broadcast);
+    }
+    chat.update_param(context).await?;
+    emit_event!(context, EventType::ChatModified(chat_id));
+    Ok(())
+}
+
+async fn set_member_role_raw(
+    context: &Context,
+    chat_id: ChatId,
+    contact_id: u32,
+    role: Role,
+) -> Result<(), Error> {
+    context
+        .sql
+        .execute(
+            "UPDATE chats_contacts SET role=? WHERE chat_id=? AND contact_id=?;",
+            paramsv![role, chat_id, contact_id],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Applies the admins listed in a received `Chat-Group-Admins` header.
+///
+/// Addresses not being members of the group are ignored.
+/// Returns true if the roles were changed.
+pub(crate) async fn set_group_admins_from_addrs(
+    context: &Context,
+    chat_id: ChatId,
+    addrs: &str,
+) -> Result<bool, Error> {
+    let self_addr = context
+        .get_config(Config::ConfiguredAddr)
+        .await
+        .unwrap_or_default();
+    let mut admins = Vec::new();
+    for addr in addrs.split_whitespace() {
+        let contact_id = if addr_cmp(addr, &self_addr) {
+            DC_CONTACT_ID_SELF
+        } else {
+            Contact::lookup_id_by_addr(context, addr, Origin::Unknown).await
+        };
+        if contact_id != 0
+            && is_contact_in_chat(context, chat_id, contact_id).await
+            && !admins.contains(&contact_id)
+        {
+            admins.push(contact_id);
+        }
+    }
+    admins.sort_unstable();
+
+    if admins.is_empty() || admins == get_group_admins(context, chat_id).await {
+        return Ok(false);
+    }
+    context
+        .sql
+        .execute(
+            "UPDATE chats_contacts SET role=? WHERE chat_id=?;",
+            paramsv![Role::Member, chat_id],
+        )
+        .await?;
+    for contact_id in admins {
+        set_member_role_raw(context, chat_id, contact_id, Role::Admin).await?;
+    }
+    Ok(true)
+}
+
+/// Sets the role of a group member and announces the change to the group.
+///
+/// Only admins can set roles, the creator of a group starts as admin.
+/// A group always keeps at least one admin.
+pub async fn set_member_role(
+    context: &Context,
+    chat_id: ChatId,
+    contact_id: u32,
+    role: Role,
+) -> Result<(), Error> {
+    ensure!(!chat_id.is_special(), "Invalid chat ID");
+    let chat = Chat::load_from_db(context, chat_id).await?;
+    ensure!(
+        real_group_exists(context, chat_id).await,
+        "{} is not a group where one can set roles",
+        chat_id
+    );
+    if !is_contact_in_chat(context, chat_id, DC_CONTACT_ID_SELF).await {
+        emit_event!(
+            context,
+            EventType::ErrorSelfNotInGroup("Cannot set member role; self not in group.".into())
+        );
+        bail!("Failed to set member role");
+    }
+    ensure!(
+        is_contact_in_chat(context, chat_id, contact_id).await,
+        "Contact {} is not a member of {}",
+        contact_id,
+        chat_id
+    );
+    ensure!(
+        may_manage_group(context, chat_id, DC_CONTACT_ID_SELF).await,
+        "Only admins can set roles in {}",
+        chat_id
+    );
+    if get_member_role(context, chat_id, contact_id).await == role {
+        return Ok(());
+    }
+    ensure!(
+        role == Role::Admin
+            || get_group_admins(context, chat_id)
+                .await
+                .iter()
+                .any(|admin_id| *admin_id != contact_id),
+        "Cannot remove the last admin of {}",
+        chat_id
+    );
+
+    set_member_role_raw(context, chat_id, contact_id, role).await?;
+    if chat.is_promoted() {
+        let contact = Contact::get_by_id(context, contact_id).await?;
+        let stock_id = match role {
+            Role::Admin => StockMessage::MsgMemberAdmin,
+            Role::Member => StockMessage::MsgMemberNoAdmin,
+        };
+        let mut msg = Message::new(Viewtype::Text);
+        msg.text = Some(
+            context
+                .stock_system_msg(stock_id, contact.get_addr(), "", DC_CONTACT_ID_SELF)
+                .await,
+        );
+        msg.param.set_cmd(SystemMessage::MemberRoleChanged);
+        msg.param.set(Param::Arg, contact.get_addr());
+        msg.id = send_msg(context, chat_id, &mut msg).await?;
+        emit_event!(
+            context,
+            EventType::MsgsChanged {
+                chat_id,
+                msg_id: msg.id
+            }
+        );
+    }
+    emit_event!(context, EventType::ChatModified(chat_id));
+    Ok(())
+}
+
 pub async fn set_chat_name(
     context: &Context,
     chat_id: ChatId,
@@ -2453,6 +3379,8 @@ pub async fn set_chat_name(
                 context,
                 EventType::ErrorSelfNotInGroup("Cannot set chat name; self not in group".into())
             );
+        } else if !may_manage_group(context, chat_id, DC_CONTACT_ID_SELF).await {
+            bail!("Only admins can rename {}", chat_id);
         } else {
             /* we should respect this - whatever we send to the group, it gets discarded anyway! */
             if context
@@ -2525,6 +3453,11 @@ pub async fn set_chat_profile_image(
         );
         bail!("Failed to set profile image");
     }
+    ensure!(
+        may_manage_group(context, chat_id, DC_CONTACT_ID_SELF).await,
+        "Only admins can set the profile image of {}",
+        chat_id
+    );
     let mut msg = Message::new(Viewtype::Text);
     msg.param
         .set_int(Param::Cmd, SystemMessage::GroupImageChanged as i32);
@@ -2555,6 +3488,8 @@ pub async fn set_chat_profile_image(
                 .await,
         );
     }
+    chat.param
+        .set(Param::ProfileImageTimestamp, time().to_string());
     chat.update_param(context).await?;
     if chat.is_promoted() {
         msg.id = send_msg(context, chat_id, &mut msg).await?;
@@ -2570,37 +3505,57 @@ pub async fn set_chat_profile_image(
     Ok(())
 }
 
+/// Forwards messages to one or more chats.
+///
+/// The messages are copied into each of the chats and sent there,
+/// attachments are referenced from the blobdir and not copied.
+/// A single `MsgsChanged` event is emitted for every chat.
 pub async fn forward_msgs(
     context: &Context,
     msg_ids: &[MsgId],
-    chat_id: ChatId,
+    chat_ids: &[ChatId],
 ) -> Result<(), Error> {
     ensure!(!msg_ids.is_empty(), "empty msgs_ids: nothing to forward");
-    ensure!(!chat_id.is_special(), "can not forward to special chat");
-
-    let mut created_chats: Vec<ChatId> = Vec::new();
-    let mut created_msgs: Vec<MsgId> = Vec::new();
-    let mut curr_timestamp: i64;
+    ensure!(
+        !chat_ids.is_empty(),
+        "empty chat_ids: nowhere to forward to"
+    );
 
-    chat_id.unarchive(context).await?;
-    if let Ok(mut chat) = Chat::load_from_db(context, chat_id).await {
+    let mut chats = Vec::with_capacity(chat_ids.len());
+    for chat_id in chat_ids {
+        ensure!(!chat_id.is_special(), "can not forward to special chat");
+        let chat = Chat::load_from_db(context, *chat_id).await?;
         ensure!(chat.can_send(), "cannot send to {}", chat_id);
-        curr_timestamp = dc_create_smeared_timestamps(context, msg_ids.len()).await;
-        let ids = context
-            .sql
-            .query_map(
-                format!(
-                    "SELECT id FROM msgs WHERE id IN({}) ORDER BY timestamp,id",
-                    msg_ids.iter().map(|_| "?").join(",")
-                ),
-                msg_ids.iter().map(|v| v as &dyn crate::ToSql).collect(),
-                |row| row.get::<_, MsgId>(0),
-                |ids| ids.collect::<Result<Vec<_>, _>>().map_err(Into::into),
-            )
-            .await?;
+        ensure!(
+            may_post(context, &chat, DC_CONTACT_ID_SELF).await,
+            "Only admins can post to broadcast {}",
+            chat_id
+        );
+        chats.push(chat);
+    }
 
-        for id in ids {
-            let src_msg_id: MsgId = id;
+    let ids = context
+        .sql
+        .query_map(
+            format!(
+                "SELECT id FROM msgs WHERE id IN({}) ORDER BY timestamp,id",
+                msg_ids.iter().map(|_| "?").join(",")
+            ),
+            msg_ids.iter().map(|v| v as &dyn crate::ToSql).collect(),
+            |row| row.get::<_, MsgId>(0),
+            |ids| ids.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+
+    let mut last_msg_ids: Vec<(ChatId, MsgId)> = Vec::new();
+    for mut chat in chats {
+        let chat_id = chat.id;
+        chat_id.unarchive(context).await?;
+        let mut curr_timestamp = dc_create_smeared_timestamps(context, ids.len()).await;
+        let mut last_msg_id = None;
+
+        for src_msg_id in &ids {
+            let src_msg_id = *src_msg_id;
             let msg = Message::load_from_db(context, src_msg_id).await;
             if msg.is_err() {
                 break;
@@ -2645,15 +3600,14 @@ pub async fn forward_msgs(
                     job::add(context, send_job).await;
                 }
             }
-            created_chats.push(chat_id);
-            created_msgs.push(new_msg_id);
+            last_msg_id = Some(new_msg_id);
+        }
+        if let Some(msg_id) = last_msg_id {
+            last_msg_ids.push((chat_id, msg_id));
         }
     }
-    for (chat_id, msg_id) in created_chats.iter().zip(created_msgs.iter()) {
-        context.emit_event(EventType::MsgsChanged {
-            chat_id: *chat_id,
-            msg_id: *msg_id,
-        });
+    for (chat_id, msg_id) in last_msg_ids {
+        context.emit_event(EventType::MsgsChanged { chat_id, msg_id });
     }
     Ok(())
 }
@@ -2768,8 +3722,8 @@ pub async fn add_device_msg_with_importance(
         context
             .sql
             .execute(
-                "INSERT INTO devmsglabels (label) VALUES (?);",
-                paramsv![label.to_string()],
+                "INSERT INTO devmsglabels (label, msg_id) VALUES (?, ?);",
+                paramsv![label.to_string(), msg_id.to_u32()],
             )
             .await?;
     }
@@ -2793,6 +3747,52 @@ pub async fn add_device_msg(
     add_device_msg_with_importance(context, label, msg, false).await
 }
 
+/// Adds a message with the given `label` to the device chat
+/// unless a message with this label was added before.
+///
+/// Returns the id of the first message added with this label,
+/// so calling this function again, eg. on reconfigure, does not add duplicates.
+/// The returned message may be deleted by the user meanwhile.
+pub async fn add_device_msg_with_id(
+    context: &Context,
+    label: &str,
+    msg: &mut Message,
+) -> Result<MsgId, Error> {
+    ensure!(!label.is_empty(), "empty label");
+    let msg_id = add_device_msg(context, Some(label), Some(msg)).await?;
+    if !msg_id.is_unset() {
+        return Ok(msg_id);
+    }
+
+    let msg_id = context
+        .sql
+        .query_get_value_result(
+            "SELECT msg_id FROM devmsglabels WHERE label=? ORDER BY id LIMIT 1;",
+            paramsv![label],
+        )
+        .await?
+        .unwrap_or_else(MsgId::new_unset);
+    Ok(msg_id)
+}
+
+/// Returns the id of the device chat, or `None` if it was not created yet.
+pub async fn get_device_chat_id(context: &Context) -> Result<Option<ChatId>, Error> {
+    let chat_id = context
+        .sql
+        .query_get_value_result(
+            "SELECT c.id
+               FROM chats c
+              INNER JOIN chats_contacts j
+                      ON c.id=j.chat_id
+              WHERE c.type=?
+                AND c.id>9
+                AND j.contact_id=?;",
+            paramsv![Chattype::Single, DC_CONTACT_ID_DEVICE],
+        )
+        .await?;
+    Ok(chat_id)
+}
+
 pub async fn was_device_msg_ever_added(context: &Context, label: &str) -> Result<bool, Error> {
     ensure!(!label.is_empty(), "empty label");
     if let Ok(()) = context
@@ -2876,6 +3876,7 @@ pub(crate) async fn add_info_msg(context: &Context, chat_id: ChatId, text: impl
 mod tests {
     use super::*;
 
+    use crate::aheader::{Aheader, EncryptPreference};
     use crate::contact::Contact;
     use crate::test_utils::*;
 
@@ -3096,6 +4097,30 @@ mod tests {
         assert!(msg2_id.as_ref().unwrap().is_unset());
     }
 
+    #[async_std::test]
+    async fn test_add_device_msg_with_id() {
+        let t = TestContext::new().await;
+        assert_eq!(get_device_chat_id(&t.ctx).await.unwrap(), None);
+
+        let mut msg = Message::new(Viewtype::Text);
+        msg.text = Some("provider hint".to_string());
+        let msg1_id = add_device_msg_with_id(&t.ctx, "core-provider-info", &mut msg)
+            .await
+            .unwrap();
+        assert!(!msg1_id.is_unset());
+        let msg2_id = add_device_msg_with_id(&t.ctx, "core-provider-info", &mut msg)
+            .await
+            .unwrap();
+        assert_eq!(msg1_id, msg2_id);
+
+        let device_chat_id = get_device_chat_id(&t.ctx).await.unwrap().unwrap();
+        assert_eq!(device_chat_id.get_msg_cnt(&t.ctx).await, 1);
+        let chat = Chat::load_from_db(&t.ctx, device_chat_id).await.unwrap();
+        assert!(chat.is_device_talk());
+
+        assert!(add_device_msg_with_id(&t.ctx, "", &mut msg).await.is_err());
+    }
+
     #[async_std::test]
     async fn test_add_device_msg_label_only() {
         let t = TestContext::new().await;
@@ -3175,11 +4200,54 @@ mod tests {
         assert!(prepare_msg(&t.ctx, device_chat_id, &mut msg).await.is_err());
 
         let msg_id = add_device_msg(&t.ctx, None, Some(&mut msg)).await.unwrap();
-        assert!(forward_msgs(&t.ctx, &[msg_id], device_chat_id)
+        assert!(forward_msgs(&t.ctx, &[msg_id], &[device_chat_id])
             .await
             .is_err());
     }
 
+    #[async_std::test]
+    async fn test_forward_msgs_to_multiple_chats() {
+        let t = TestContext::new_alice().await;
+        let mut msg = Message::new(Viewtype::Text);
+        msg.text = Some("first".to_string());
+        let msg_id1 = add_device_msg(&t.ctx, None, Some(&mut msg)).await.unwrap();
+        let mut msg = Message::new(Viewtype::Text);
+        msg.text = Some("second".to_string());
+        let msg_id2 = add_device_msg(&t.ctx, None, Some(&mut msg)).await.unwrap();
+
+        let mut chat_ids = Vec::new();
+        for addr in &["bob@example.net", "claire@example.net", "dave@example.net"] {
+            let contact_id = Contact::create(&t.ctx, "", addr).await.unwrap();
+            chat_ids.push(create_by_contact_id(&t.ctx, contact_id).await.unwrap());
+        }
+
+        let emitter = t.ctx.get_event_emitter();
+        while emitter.try_recv().is_ok() {}
+        forward_msgs(&t.ctx, &[msg_id1, msg_id2], &chat_ids)
+            .await
+            .unwrap();
+
+        let mut forwarded = 0;
+        for chat_id in &chat_ids {
+            for item in get_chat_msgs(&t.ctx, *chat_id, 0, None).await {
+                if let ChatItem::Message { msg_id } = item {
+                    let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+                    assert!(msg.is_forwarded());
+                    forwarded += 1;
+                }
+            }
+        }
+        assert_eq!(forwarded, 6);
+
+        let mut changed_chats = Vec::new();
+        while let Ok(event) = emitter.try_recv() {
+            if let EventType::MsgsChanged { chat_id, .. } = event.typ {
+                changed_chats.push(chat_id);
+            }
+        }
+        assert_eq!(changed_chats, chat_ids);
+    }
+
     #[async_std::test]
     async fn test_delete_and_reset_all_device_msgs() {
         let t = TestContext::new().await;
@@ -3390,6 +4458,62 @@ mod tests {
         assert_eq!(chatlist, vec![chat_id3, chat_id2, chat_id1]);
     }
 
+    #[async_std::test]
+    async fn test_set_pinned() {
+        let t = TestContext::new().await;
+
+        let mut chat_ids = Vec::new();
+        for name in &["foo", "bar", "baz"] {
+            chat_ids.push(
+                create_group_chat(&t.ctx, VerifiedStatus::Unverified, name)
+                    .await
+                    .unwrap(),
+            );
+            async_std::task::sleep(std::time::Duration::from_millis(1000)).await;
+        }
+        let (chat_id1, chat_id2, chat_id3) = (chat_ids[0], chat_ids[1], chat_ids[2]);
+        let chatlist = get_chats_from_chat_list(&t.ctx, DC_GCL_NO_SPECIALS).await;
+        assert_eq!(chatlist, vec![chat_id3, chat_id2, chat_id1]);
+
+        // pin the chat in the middle
+        set_pinned(&t.ctx, chat_id2, true).await.unwrap();
+        assert!(Chat::load_from_db(&t.ctx, chat_id2)
+            .await
+            .unwrap()
+            .is_pinned());
+        let chatlist = get_chats_from_chat_list(&t.ctx, DC_GCL_NO_SPECIALS).await;
+        assert_eq!(chatlist, vec![chat_id2, chat_id3, chat_id1]);
+
+        // pinned chats are sorted by recency among themselves
+        set_pinned(&t.ctx, chat_id1, true).await.unwrap();
+        let chatlist = get_chats_from_chat_list(&t.ctx, DC_GCL_NO_SPECIALS).await;
+        assert_eq!(chatlist, vec![chat_id2, chat_id1, chat_id3]);
+
+        // unpin
+        set_pinned(&t.ctx, chat_id2, false).await.unwrap();
+        set_pinned(&t.ctx, chat_id1, false).await.unwrap();
+        assert!(!Chat::load_from_db(&t.ctx, chat_id2)
+            .await
+            .unwrap()
+            .is_pinned());
+        let chatlist = get_chats_from_chat_list(&t.ctx, DC_GCL_NO_SPECIALS).await;
+        assert_eq!(chatlist, vec![chat_id3, chat_id2, chat_id1]);
+
+        // unpinning does not unarchive
+        chat_id3
+            .set_visibility(&t.ctx, ChatVisibility::Archived)
+            .await
+            .unwrap();
+        set_pinned(&t.ctx, chat_id3, false).await.unwrap();
+        assert_eq!(
+            Chat::load_from_db(&t.ctx, chat_id3)
+                .await
+                .unwrap()
+                .get_visibility(),
+            ChatVisibility::Archived
+        );
+    }
+
     #[async_std::test]
     async fn test_set_chat_name() {
         let t = TestContext::new().await;
@@ -3472,7 +4596,7 @@ mod tests {
             false
         );
         // Forever
-        set_muted(&t.ctx, chat_id, MuteDuration::Forever)
+        set_mute_duration(&t.ctx, chat_id, MuteDuration::Forever)
             .await
             .unwrap();
         assert_eq!(
@@ -3483,7 +4607,7 @@ mod tests {
             true
         );
         // unMute
-        set_muted(&t.ctx, chat_id, MuteDuration::NotMuted)
+        set_mute_duration(&t.ctx, chat_id, MuteDuration::NotMuted)
             .await
             .unwrap();
         assert_eq!(
@@ -3494,7 +4618,7 @@ mod tests {
             false
         );
         // Timed in the future
-        set_muted(
+        set_mute_duration(
             &t.ctx,
             chat_id,
             MuteDuration::Until(SystemTime::now() + Duration::from_secs(3600)),
@@ -3509,7 +4633,7 @@ mod tests {
             true
         );
         // Time in the past
-        set_muted(
+        set_mute_duration(
             &t.ctx,
             chat_id,
             MuteDuration::Until(SystemTime::now() - Duration::from_secs(3600)),
@@ -3538,4 +4662,943 @@ mod tests {
         chat_id.set_draft(&t.ctx, Some(&mut msg)).await;
         assert!(!chat_id.parent_is_encrypted(&t.ctx).await.unwrap());
     }
+
+    async fn create_list_chat(context: &Context, list: &ListHeaders) -> ChatId {
+        let chat_id = create_group_chat(context, VerifiedStatus::Unverified, &list.name)
+            .await
+            .unwrap();
+        let mut chat = Chat::load_from_db(context, chat_id).await.unwrap();
+        list.to_params(&mut chat.param);
+        chat.update_param(context).await.unwrap();
+        chat_id
+    }
+
+    fn list_unsubscribed_event(emitter: &crate::events::EventEmitter) -> Option<(ChatId, bool)> {
+        while let Ok(event) = emitter.try_recv() {
+            if let EventType::ListUnsubscribed { chat_id, success } = event.typ {
+                return Some((chat_id, success));
+            }
+        }
+        None
+    }
+
+    #[async_std::test]
+    async fn test_unsubscribe_from_list_one_click() {
+        use async_std::net::TcpListener;
+        use async_std::prelude::*;
+
+        let t = TestContext::new_alice().await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!(
+            "http://{}/unsubscribe?id=123",
+            listener.local_addr().unwrap()
+        );
+        let server = async_std::task::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !String::from_utf8_lossy(&request).contains("One-Click") {
+                let n = stream.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8_lossy(&request).to_string()
+        });
+
+        let chat_id = create_list_chat(
+            &t.ctx,
+            &ListHeaders {
+                id: "news.example.org".to_string(),
+                name: "News".to_string(),
+                unsubscribe_mailto: Some("mailto:news-request@example.org".to_string()),
+                unsubscribe_url: Some(url),
+                unsubscribe_one_click: true,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let emitter = t.ctx.get_event_emitter();
+        while emitter.try_recv().is_ok() {}
+        unsubscribe_from_list(&t.ctx, chat_id).await.unwrap();
+
+        let request = server.await;
+        assert!(request.starts_with("POST /unsubscribe?id=123 "));
+        assert!(request.ends_with("List-Unsubscribe=One-Click"));
+        assert_eq!(list_unsubscribed_event(&emitter), Some((chat_id, true)));
+
+        // no message is sent to the mailto: address
+        assert_eq!(
+            Contact::lookup_id_by_addr(&t.ctx, "news-request@example.org", Origin::Unknown).await,
+            0
+        );
+    }
+
+    #[async_std::test]
+    async fn test_unsubscribe_from_list_mailto() {
+        use mailparse::MailHeaderMap;
+
+        let t = TestContext::new_alice().await;
+        let chat_id = create_list_chat(
+            &t.ctx,
+            &ListHeaders {
+                id: "news.example.org".to_string(),
+                name: "News".to_string(),
+                unsubscribe_mailto: Some(
+                    "mailto:news-request@example.org?subject=unsubscribe%20me".to_string(),
+                ),
+                unsubscribe_url: Some("https://example.org/unsubscribe".to_string()),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let emitter = t.ctx.get_event_emitter();
+        while emitter.try_recv().is_ok() {}
+        unsubscribe_from_list(&t.ctx, chat_id).await.unwrap();
+        // the result is not known before the mail is sent
+        assert_eq!(list_unsubscribed_event(&emitter), None);
+
+        // the request is sent without creating a contact or a chat
+        let contact_id =
+            Contact::lookup_id_by_addr(&t.ctx, "news-request@example.org", Origin::Unknown).await;
+        assert_eq!(contact_id, 0);
+        let mails = pending_smtp_mails(&t).await;
+        assert_eq!(mails.len(), 1);
+        let (foreign_id, mail) = &mails[0];
+        assert_eq!(*foreign_id, 0);
+        let mail = mailparse::parse_mail(mail.as_bytes()).unwrap();
+        assert!(mail
+            .headers
+            .get_header_value("To")
+            .unwrap()
+            .contains("news-request@example.org"));
+        assert_eq!(
+            mail.headers.get_header_value("Subject"),
+            Some("unsubscribe me".to_string())
+        );
+        assert_eq!(mail.get_body().unwrap().trim(), "unsubscribe");
+
+        // the SMTP job reports the result when it gives up
+        let mut job = job::get_pending(&t.ctx)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|job| job.action == Action::SendMsgToSmtp)
+            .unwrap();
+        assert_eq!(
+            job.param.get_int(Param::ListUnsubscribeChatId),
+            Some(chat_id.to_u32() as i32)
+        );
+        job.tries = 1000;
+        let mut smtp = crate::smtp::Smtp::new();
+        job::perform_job(&t.ctx, job::Connection::Smtp(&mut smtp), job).await;
+        assert_eq!(list_unsubscribed_event(&emitter), Some((chat_id, false)));
+        assert!(pending_smtp_mails(&t).await.is_empty());
+
+        // without a supported method, the failure is reported at once
+        let chat_id = create_list_chat(
+            &t.ctx,
+            &ListHeaders {
+                id: "other.example.org".to_string(),
+                name: "Other".to_string(),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert!(unsubscribe_from_list(&t.ctx, chat_id).await.is_err());
+        assert_eq!(list_unsubscribed_event(&emitter), Some((chat_id, false)));
+    }
+
+    /// Renders the last message of `chat_id` in `from` and receives it in `to`.
+    async fn receive_last_msg(from: &TestContext, chat_id: ChatId, to: &TestContext) {
+        let msg_id = match get_chat_msgs(&from.ctx, chat_id, 0, None).await.pop() {
+            Some(ChatItem::Message { msg_id }) => msg_id,
+            _ => panic!("no message in chat"),
+        };
+        let msg = Message::load_from_db(&from.ctx, msg_id).await.unwrap();
+        send_and_receive(&from.ctx, &msg, &to.ctx).await;
+    }
+
+    /// Returns the rendered `SendMsgToSmtp` jobs as (foreign_id, mail).
+    async fn pending_smtp_mails(t: &TestContext) -> Vec<(u32, String)> {
+        let mut mails = Vec::new();
+        for job in job::get_pending(&t.ctx).await.unwrap() {
+            if job.action == Action::SendMsgToSmtp {
+                let path = job.param.get_path(Param::File, &t.ctx).unwrap().unwrap();
+                let mail = dc_read_file(&t.ctx, &path).await.unwrap();
+                mails.push((job.foreign_id, String::from_utf8_lossy(&mail).to_string()));
+            }
+        }
+        mails
+    }
+
+    #[async_std::test]
+    async fn test_leave_and_delete() {
+        let t = TestContext::new_alice().await;
+        let bob_id = Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = create_group_chat(&t.ctx, VerifiedStatus::Unverified, "foo")
+            .await
+            .unwrap();
+        assert!(add_contact_to_chat(&t.ctx, chat_id, bob_id).await);
+        send_text_msg(&t.ctx, chat_id, "hi".to_string())
+            .await
+            .unwrap();
+
+        leave_and_delete(&t.ctx, chat_id, false).await.unwrap();
+        assert!(Chat::load_from_db(&t.ctx, chat_id).await.is_err());
+        assert!(get_chat_msgs(&t.ctx, chat_id, 0, None).await.is_empty());
+
+        // the leave message is still sent, detached from the deleted message
+        let mails = pending_smtp_mails(&t).await;
+        let (foreign_id, _) = mails
+            .iter()
+            .find(|(_, mail)| mail.contains("Chat-Group-Member-Removed: alice@example.com"))
+            .unwrap();
+        assert_eq!(*foreign_id, 0);
+    }
+
+    #[async_std::test]
+    async fn test_leave_and_delete_deferred() {
+        let t = TestContext::new_alice().await;
+        let bob_id = Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = create_group_chat(&t.ctx, VerifiedStatus::Unverified, "foo")
+            .await
+            .unwrap();
+        assert!(add_contact_to_chat(&t.ctx, chat_id, bob_id).await);
+        let hi_msg_id = send_text_msg(&t.ctx, chat_id, "hi".to_string())
+            .await
+            .unwrap();
+
+        leave_and_delete(&t.ctx, chat_id, true).await.unwrap();
+        assert!(!is_contact_in_chat(&t.ctx, chat_id, DC_CONTACT_ID_SELF).await);
+        let mails = pending_smtp_mails(&t).await;
+        let (foreign_id, _) = mails
+            .iter()
+            .find(|(_, mail)| mail.contains("Chat-Group-Member-Removed: alice@example.com"))
+            .unwrap();
+        let leave_msg_id = MsgId::new(*foreign_id);
+        let leave_msg = Message::load_from_db(&t.ctx, leave_msg_id).await.unwrap();
+        assert_eq!(leave_msg.chat_id, chat_id);
+
+        // messages queued before the leave message do not delete the chat
+        delete_chat_after_leave(&t.ctx, hi_msg_id).await.unwrap();
+        assert!(Chat::load_from_db(&t.ctx, chat_id).await.is_ok());
+
+        // the chat is deleted once the leave message is sent
+        delete_chat_after_leave(&t.ctx, leave_msg_id).await.unwrap();
+        assert!(Chat::load_from_db(&t.ctx, chat_id).await.is_err());
+    }
+
+    /// Creates a group by Alice with Bob and Claire and receives it on Bob's side.
+    async fn setup_group_with_admin() -> (TestContext, TestContext, ChatId, ChatId) {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new().await;
+        bob.configure_addr("bob@example.net").await;
+
+        let alice_chat_id = create_group_chat(&alice.ctx, VerifiedStatus::Unverified, "foo")
+            .await
+            .unwrap();
+        for addr in &["bob@example.net", "claire@example.org"] {
+            let contact_id = Contact::create(&alice.ctx, "", addr).await.unwrap();
+            assert!(add_contact_to_chat(&alice.ctx, alice_chat_id, contact_id).await);
+        }
+        send_text_msg(&alice.ctx, alice_chat_id, "hi".to_string())
+            .await
+            .unwrap();
+        receive_last_msg(&alice, alice_chat_id, &bob).await;
+        let grpid = Chat::load_from_db(&alice.ctx, alice_chat_id)
+            .await
+            .unwrap()
+            .grpid;
+        let (bob_chat_id, _, _) = get_chat_id_by_grpid(&bob.ctx, &grpid).await.unwrap();
+        (alice, bob, alice_chat_id, bob_chat_id)
+    }
+
+    #[async_std::test]
+    async fn test_member_role_admin_removes_member() {
+        let (alice, bob, alice_chat_id, bob_chat_id) = setup_group_with_admin().await;
+        assert_eq!(
+            get_group_admins(&alice.ctx, alice_chat_id).await,
+            vec![DC_CONTACT_ID_SELF]
+        );
+        let alice_on_bob =
+            Contact::lookup_id_by_addr(&bob.ctx, "alice@example.com", Origin::Unknown).await;
+        assert_eq!(
+            get_group_admins(&bob.ctx, bob_chat_id).await,
+            vec![alice_on_bob]
+        );
+        assert_eq!(get_chat_contacts(&bob.ctx, bob_chat_id).await.len(), 3);
+
+        let claire_id =
+            Contact::lookup_id_by_addr(&alice.ctx, "claire@example.org", Origin::Unknown).await;
+        remove_contact_from_chat(&alice.ctx, alice_chat_id, claire_id)
+            .await
+            .unwrap();
+        assert_eq!(get_chat_contacts(&alice.ctx, alice_chat_id).await.len(), 2);
+        receive_last_msg(&alice, alice_chat_id, &bob).await;
+        assert_eq!(get_chat_contacts(&bob.ctx, bob_chat_id).await.len(), 2);
+
+        // admins can make others admin, the last admin cannot be removed
+        let bob_id =
+            Contact::lookup_id_by_addr(&alice.ctx, "bob@example.net", Origin::Unknown).await;
+        assert!(
+            set_member_role(&alice.ctx, alice_chat_id, DC_CONTACT_ID_SELF, Role::Member)
+                .await
+                .is_err()
+        );
+        set_member_role(&alice.ctx, alice_chat_id, bob_id, Role::Admin)
+            .await
+            .unwrap();
+        assert_eq!(
+            get_member_role(&alice.ctx, alice_chat_id, bob_id).await,
+            Role::Admin
+        );
+        receive_last_msg(&alice, alice_chat_id, &bob).await;
+        assert_eq!(
+            get_member_role(&bob.ctx, bob_chat_id, DC_CONTACT_ID_SELF).await,
+            Role::Admin
+        );
+    }
+
+    #[async_std::test]
+    async fn test_member_role_non_admin_invite() {
+        let (_alice, bob, _alice_chat_id, bob_chat_id) = setup_group_with_admin().await;
+        let dave_id = Contact::create(&bob.ctx, "", "dave@example.org")
+            .await
+            .unwrap();
+
+        // non-admins can neither add members nor invite them by QR code
+        assert!(
+            add_contact_to_chat_ex(&bob.ctx, bob_chat_id, dave_id, false)
+                .await
+                .is_err()
+        );
+        assert!(
+            crate::securejoin::dc_get_securejoin_qr(&bob.ctx, bob_chat_id)
+                .await
+                .is_none()
+        );
+
+        // contacts joining with a QR code generated by an admin are still added
+        assert!(add_contact_to_chat_ex(&bob.ctx, bob_chat_id, dave_id, true)
+            .await
+            .unwrap());
+        assert!(is_contact_in_chat(&bob.ctx, bob_chat_id, dave_id).await);
+    }
+
+    #[async_std::test]
+    async fn test_member_role_admin_not_claimed() {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new().await;
+        bob.configure_addr("bob@example.net").await;
+
+        // a group without admins, as created by older versions
+        let alice_chat_id = create_group_chat(&alice.ctx, VerifiedStatus::Unverified, "foo")
+            .await
+            .unwrap();
+        let bob_id = Contact::create(&alice.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        assert!(add_contact_to_chat(&alice.ctx, alice_chat_id, bob_id).await);
+        alice
+            .ctx
+            .sql
+            .execute(
+                "UPDATE chats_contacts SET role=0 WHERE chat_id=?;",
+                paramsv![alice_chat_id],
+            )
+            .await
+            .unwrap();
+        send_text_msg(&alice.ctx, alice_chat_id, "hi".to_string())
+            .await
+            .unwrap();
+        receive_last_msg(&alice, alice_chat_id, &bob).await;
+        let grpid = Chat::load_from_db(&alice.ctx, alice_chat_id)
+            .await
+            .unwrap()
+            .grpid;
+        let (bob_chat_id, _, _) = get_chat_id_by_grpid(&bob.ctx, &grpid).await.unwrap();
+        assert!(get_group_admins(&bob.ctx, bob_chat_id).await.is_empty());
+
+        // bob cannot become admin of the existing group by claiming it
+        bob.ctx
+            .sql
+            .execute(
+                "UPDATE chats_contacts SET role=? WHERE chat_id=? AND contact_id=?;",
+                paramsv![Role::Admin, bob_chat_id, DC_CONTACT_ID_SELF],
+            )
+            .await
+            .unwrap();
+        send_text_msg(&bob.ctx, bob_chat_id, "i am admin".to_string())
+            .await
+            .unwrap();
+        receive_last_msg(&bob, bob_chat_id, &alice).await;
+        assert!(get_group_admins(&alice.ctx, alice_chat_id).await.is_empty());
+    }
+
+    #[async_std::test]
+    async fn test_member_role_non_admin_removal_rejected() {
+        let (alice, bob, alice_chat_id, bob_chat_id) = setup_group_with_admin().await;
+
+        // bob is no admin and cannot remove claire, but may leave
+        let claire_on_bob =
+            Contact::lookup_id_by_addr(&bob.ctx, "claire@example.org", Origin::Unknown).await;
+        assert!(
+            remove_contact_from_chat(&bob.ctx, bob_chat_id, claire_on_bob)
+                .await
+                .is_err()
+        );
+        assert!(set_chat_name(&bob.ctx, bob_chat_id, "bar").await.is_err());
+        assert!(is_contact_in_chat(&bob.ctx, bob_chat_id, claire_on_bob).await);
+
+        // a removal sent by a non-admin is ignored
+        let grpid = Chat::load_from_db(&alice.ctx, alice_chat_id)
+            .await
+            .unwrap()
+            .grpid;
+        let mail = format!(
+            "From: claire@example.org\n\
+             To: alice@example.com, bob@example.net\n\
+             Subject: Chat: foo\n\
+             Chat-Version: 1.0\n\
+             Chat-Group-ID: {}\n\
+             Chat-Group-Name: foo\n\
+             Chat-Group-Member-Removed: bob@example.net\n\
+             Message-ID: <removal@example.org>\n\
+             Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+             \n\
+             Member bob@example.net removed.\n",
+            grpid
+        );
+        crate::dc_receive_imf::dc_receive_imf(&alice.ctx, mail.as_bytes(), "INBOX", 2, false)
+            .await
+            .unwrap();
+        let bob_id =
+            Contact::lookup_id_by_addr(&alice.ctx, "bob@example.net", Origin::Unknown).await;
+        assert!(is_contact_in_chat(&alice.ctx, alice_chat_id, bob_id).await);
+        assert_eq!(get_chat_contacts(&alice.ctx, alice_chat_id).await.len(), 3);
+
+        remove_contact_from_chat(&bob.ctx, bob_chat_id, DC_CONTACT_ID_SELF)
+            .await
+            .unwrap();
+    }
+
+    #[async_std::test]
+    async fn test_broadcast_member_send_rejected() {
+        let (alice, bob, alice_chat_id, bob_chat_id) = setup_group_with_admin().await;
+        set_broadcast(&alice.ctx, alice_chat_id, true)
+            .await
+            .unwrap();
+        send_text_msg(&alice.ctx, alice_chat_id, "news".to_string())
+            .await
+            .unwrap();
+        receive_last_msg(&alice, alice_chat_id, &bob).await;
+
+        let bob_chat = Chat::load_from_db(&bob.ctx, bob_chat_id).await.unwrap();
+        assert!(bob_chat.is_broadcast());
+        assert!(send_text_msg(&bob.ctx, bob_chat_id, "reply".to_string())
+            .await
+            .is_err());
+        assert!(set_broadcast(&bob.ctx, bob_chat_id, false).await.is_err());
+
+        // messages by non-admins are ignored
+        let msg_cnt = get_chat_msgs(&alice.ctx, alice_chat_id, 0, None)
+            .await
+            .len();
+        let grpid = Chat::load_from_db(&alice.ctx, alice_chat_id)
+            .await
+            .unwrap()
+            .grpid;
+        let mail = format!(
+            "From: claire@example.org\n\
+             To: alice@example.com, bob@example.net\n\
+             Subject: Chat: foo\n\
+             Chat-Version: 1.0\n\
+             Chat-Group-ID: {}\n\
+             Chat-Group-Name: foo\n\
+             Message-ID: <reply@example.org>\n\
+             Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+             \n\
+             reply\n",
+            grpid
+        );
+        crate::dc_receive_imf::dc_receive_imf(&alice.ctx, mail.as_bytes(), "INBOX", 2, false)
+            .await
+            .unwrap();
+        assert_eq!(
+            get_chat_msgs(&alice.ctx, alice_chat_id, 0, None)
+                .await
+                .len(),
+            msg_cnt
+        );
+    }
+
+    #[async_std::test]
+    async fn test_broadcast_reaches_all_members() {
+        let (alice, bob, alice_chat_id, bob_chat_id) = setup_group_with_admin().await;
+        set_broadcast(&alice.ctx, alice_chat_id, true)
+            .await
+            .unwrap();
+        let msg_id = send_text_msg(&alice.ctx, alice_chat_id, "news".to_string())
+            .await
+            .unwrap();
+
+        let job = job::get_pending(&alice.ctx)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|job| job.action == Action::SendMsgToSmtp && job.foreign_id == msg_id.to_u32())
+            .unwrap();
+        let recipients = job.param.get(Param::Recipients).unwrap();
+        assert!(recipients.contains("bob@example.net"));
+        assert!(recipients.contains("claire@example.org"));
+
+        receive_last_msg(&alice, alice_chat_id, &bob).await;
+        let msg_id = match get_chat_msgs(&bob.ctx, bob_chat_id, 0, None).await.pop() {
+            Some(ChatItem::Message { msg_id }) => msg_id,
+            _ => panic!("no message in chat"),
+        };
+        let msg = Message::load_from_db(&bob.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.get_text().unwrap(), "news");
+    }
+
+    #[async_std::test]
+    async fn test_group_avatar_propagation() {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new().await;
+        bob.configure_addr("bob@example.net").await;
+
+        let alice_chat_id = create_group_chat(&alice.ctx, VerifiedStatus::Unverified, "foo")
+            .await
+            .unwrap();
+        let bob_id = Contact::create(&alice.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        assert!(add_contact_to_chat(&alice.ctx, alice_chat_id, bob_id).await);
+        send_text_msg(&alice.ctx, alice_chat_id, "hi".to_string())
+            .await
+            .unwrap();
+        receive_last_msg(&alice, alice_chat_id, &bob).await;
+        let grpid = Chat::load_from_db(&alice.ctx, alice_chat_id)
+            .await
+            .unwrap()
+            .grpid;
+        let (bob_chat_id, _, _) = get_chat_id_by_grpid(&bob.ctx, &grpid).await.unwrap();
+        let bob_chat = Chat::load_from_db(&bob.ctx, bob_chat_id).await.unwrap();
+        assert!(bob_chat.get_profile_image(&bob.ctx).await.is_none());
+
+        let avatar = alice.dir.path().join("avatar.png");
+        std::fs::write(
+            &avatar,
+            include_bytes!("../test-data/image/avatar64x64.png").as_ref(),
+        )
+        .unwrap();
+        set_chat_profile_image(&alice.ctx, alice_chat_id, avatar.to_str().unwrap())
+            .await
+            .unwrap();
+        let alice_chat = Chat::load_from_db(&alice.ctx, alice_chat_id).await.unwrap();
+        assert!(alice_chat.get_profile_image(&alice.ctx).await.is_some());
+        receive_last_msg(&alice, alice_chat_id, &bob).await;
+        let bob_chat = Chat::load_from_db(&bob.ctx, bob_chat_id).await.unwrap();
+        let image = bob_chat.get_profile_image(&bob.ctx).await.unwrap();
+        assert!(image.exists());
+        let changed_at = bob_chat.get_profile_image_timestamp();
+        assert!(changed_at > 0);
+
+        // a change sent before the current image is ignored
+        let mut bob_chat = bob_chat;
+        bob_chat
+            .param
+            .set(Param::ProfileImageTimestamp, (changed_at + 10).to_string());
+        bob_chat.update_param(&bob.ctx).await.unwrap();
+        set_chat_profile_image(&alice.ctx, alice_chat_id, "")
+            .await
+            .unwrap();
+        receive_last_msg(&alice, alice_chat_id, &bob).await;
+        let bob_chat = Chat::load_from_db(&bob.ctx, bob_chat_id).await.unwrap();
+        assert_eq!(bob_chat.get_profile_image(&bob.ctx).await, Some(image));
+
+        // a change dated in the future does not block later changes
+        let mut bob_chat = Chat::load_from_db(&bob.ctx, bob_chat_id).await.unwrap();
+        bob_chat.param.remove(Param::ProfileImageTimestamp);
+        bob_chat.update_param(&bob.ctx).await.unwrap();
+        set_chat_profile_image(&alice.ctx, alice_chat_id, avatar.to_str().unwrap())
+            .await
+            .unwrap();
+        let msg_id = match get_chat_msgs(&alice.ctx, alice_chat_id, 0, None)
+            .await
+            .pop()
+        {
+            Some(ChatItem::Message { msg_id }) => msg_id,
+            _ => panic!("no message in chat"),
+        };
+        let msg = Message::load_from_db(&alice.ctx, msg_id).await.unwrap();
+        let rendered = crate::mimefactory::MimeFactory::from_msg(&alice.ctx, &msg, false)
+            .await
+            .unwrap()
+            .render()
+            .await
+            .unwrap();
+        let mail = String::from_utf8(rendered.message).unwrap();
+        let date_re = regex::Regex::new(r"(?m)^Date: .*$").unwrap();
+        let mail = date_re.replace_all(&mail, "Date: Thu, 22 Mar 2091 22:37:55 +0000");
+        crate::dc_receive_imf::dc_receive_imf(&bob.ctx, mail.as_bytes(), "INBOX", 1, false)
+            .await
+            .unwrap();
+        let bob_chat = Chat::load_from_db(&bob.ctx, bob_chat_id).await.unwrap();
+        assert!(bob_chat.get_profile_image(&bob.ctx).await.is_some());
+        assert!(bob_chat.get_profile_image_timestamp() <= time());
+    }
+
+    #[async_std::test]
+    async fn test_would_encrypt() {
+        let alice = configured_in_memory_context(alice_keypair()).await;
+        let bob_id = Contact::create(&alice, "", "bob@example.net")
+            .await
+            .unwrap();
+        let claire_id = Contact::create(&alice, "", "claire@example.org")
+            .await
+            .unwrap();
+        let header = Aheader::new(
+            "bob@example.net".to_string(),
+            bob_keypair().public,
+            EncryptPreference::Mutual,
+        );
+        Peerstate::from_header(&alice, &header, time())
+            .save_to_db(&alice.sql, true)
+            .await
+            .unwrap();
+
+        let bob_chat_id = create_by_contact_id(&alice, bob_id).await.unwrap();
+        assert_eq!(
+            would_encrypt(&alice, bob_chat_id).await.unwrap(),
+            EncryptionDecision {
+                encrypt: true,
+                missing_keys: vec![],
+            }
+        );
+
+        let group_id = create_group_chat(&alice, VerifiedStatus::Unverified, "grp")
+            .await
+            .unwrap();
+        add_contact_to_chat(&alice, group_id, bob_id).await;
+        assert!(would_encrypt(&alice, group_id).await.unwrap().encrypt);
+
+        add_contact_to_chat(&alice, group_id, claire_id).await;
+        assert_eq!(
+            would_encrypt(&alice, group_id).await.unwrap(),
+            EncryptionDecision {
+                encrypt: false,
+                missing_keys: vec!["claire@example.org".to_string()],
+            }
+        );
+
+        // a peerstate without keys does not help
+        Peerstate::new(&alice, "claire@example.org".to_string())
+            .save_to_db(&alice.sql, true)
+            .await
+            .unwrap();
+        assert_eq!(
+            would_encrypt(&alice, group_id).await.unwrap().missing_keys,
+            vec!["claire@example.org".to_string()]
+        );
+
+        // everyone has to prefer encryption
+        let dave_id = Contact::create(&alice, "", "dave@example.org")
+            .await
+            .unwrap();
+        let header = Aheader::new(
+            "dave@example.org".to_string(),
+            bob_keypair().public,
+            EncryptPreference::NoPreference,
+        );
+        Peerstate::from_header(&alice, &header, time())
+            .save_to_db(&alice.sql, true)
+            .await
+            .unwrap();
+        let dave_chat_id = create_by_contact_id(&alice, dave_id).await.unwrap();
+        assert_eq!(
+            would_encrypt(&alice, dave_chat_id).await.unwrap(),
+            EncryptionDecision {
+                encrypt: false,
+                missing_keys: vec![],
+            }
+        );
+
+        // drafts forced to be sent unencrypted are not encrypted
+        let mut draft = Message::new(Viewtype::Text);
+        draft.set_text(Some("plain".to_string()));
+        draft.param.set_int(Param::ForcePlaintext, 1);
+        bob_chat_id.set_draft(&alice, Some(&mut draft)).await;
+        assert!(!would_encrypt(&alice, bob_chat_id).await.unwrap().encrypt);
+    }
+
+    #[async_std::test]
+    async fn test_get_message_thread() {
+        let t = TestContext::new_alice().await;
+        // (Message-ID, In-Reply-To, References)
+        let mails = [
+            ("root@example.net", "", ""),
+            (
+                "reply@example.net",
+                "<root@example.net>",
+                "<root@example.net>",
+            ),
+            (
+                "reply2@example.net",
+                "<reply@example.net>",
+                "<root@example.net> <reply@example.net>",
+            ),
+            (
+                "other@example.net",
+                "<root@example.net>",
+                "<root@example.net>",
+            ),
+            // the parent of this message was not downloaded
+            (
+                "orphan@example.net",
+                "<missing@example.net>",
+                "<root@example.net> <missing@example.net>",
+            ),
+        ];
+        for (i, (message_id, in_reply_to, references)) in mails.iter().enumerate() {
+            let mail = format!(
+                "From: Bob <bob@example.net>\n\
+                 To: alice@example.com\n\
+                 Subject: Chat: thread\n\
+                 Chat-Version: 1.0\n\
+                 Message-ID: <{}>\n\
+                 In-Reply-To: {}\n\
+                 References: {}\n\
+                 Date: Sun, 22 Mar 2020 22:37:{:02} +0000\n\
+                 \n\
+                 message {}\n",
+                message_id, in_reply_to, references, i, i
+            );
+            crate::dc_receive_imf::dc_receive_imf(
+                &t.ctx,
+                mail.as_bytes(),
+                "INBOX",
+                i as u32 + 1,
+                false,
+            )
+            .await
+            .unwrap();
+        }
+        let mut msg_ids = Vec::new();
+        for (message_id, _, _) in &mails {
+            let (_, _, msg_id) = message::rfc724_mid_exists(&t.ctx, message_id)
+                .await
+                .unwrap()
+                .unwrap();
+            msg_ids.push(msg_id);
+        }
+
+        // the same thread is returned for every message of it
+        for msg_id in &msg_ids {
+            assert_eq!(get_message_thread(&t.ctx, *msg_id).await.unwrap(), msg_ids);
+        }
+    }
+
+    #[async_std::test]
+    async fn test_get_message_groups() {
+        let t = TestContext::new_alice().await;
+        let bob_id = Contact::create(&t.ctx, "Bob", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = create_by_contact_id(&t.ctx, bob_id).await.unwrap();
+        // (From, To, Date)
+        let mails = [
+            ("bob@example.net", "alice@example.com", "22:00:00"),
+            ("bob@example.net", "alice@example.com", "22:01:00"),
+            ("alice@example.com", "bob@example.net", "22:02:00"),
+            ("alice@example.com", "bob@example.net", "22:03:00"),
+            ("bob@example.net", "alice@example.com", "22:04:00"),
+            // more than 10 minutes later
+            ("bob@example.net", "alice@example.com", "22:14:01"),
+            // sent as image below
+            ("bob@example.net", "alice@example.com", "22:15:00"),
+        ];
+        let mut msg_ids = Vec::new();
+        for (i, (from, to, time)) in mails.iter().enumerate() {
+            let mail = format!(
+                "From: {}\n\
+                 To: {}\n\
+                 Subject: Chat: hello\n\
+                 Chat-Version: 1.0\n\
+                 Message-ID: <{}@example.net>\n\
+                 Date: Sun, 22 Mar 2020 {} +0000\n\
+                 \n\
+                 message {}\n",
+                from, to, i, time, i
+            );
+            crate::dc_receive_imf::dc_receive_imf(
+                &t.ctx,
+                mail.as_bytes(),
+                "INBOX",
+                i as u32 + 1,
+                true,
+            )
+            .await
+            .unwrap();
+            let (_, _, msg_id) = message::rfc724_mid_exists(&t.ctx, &format!("{}@example.net", i))
+                .await
+                .unwrap()
+                .unwrap();
+            msg_ids.push(msg_id);
+        }
+        t.ctx
+            .sql
+            .execute(
+                "UPDATE msgs SET type=? WHERE id=?;",
+                paramsv![Viewtype::Image, msg_ids[6]],
+            )
+            .await
+            .unwrap();
+
+        let groups = get_message_groups(&t.ctx, chat_id).await.unwrap();
+        let group_ids: Vec<Vec<MsgId>> = groups.iter().map(|group| group.msg_ids.clone()).collect();
+        assert_eq!(
+            group_ids,
+            vec![
+                vec![msg_ids[0], msg_ids[1]],
+                vec![msg_ids[2], msg_ids[3]],
+                vec![msg_ids[4]],
+                vec![msg_ids[5]],
+                vec![msg_ids[6]],
+            ]
+        );
+        assert_eq!(groups[0].from_id, bob_id);
+        assert_eq!(groups[1].from_id, DC_CONTACT_ID_SELF);
+
+        // a larger window joins messages across the gap
+        t.ctx
+            .set_config(Config::MessageGroupWindow, Some("3600"))
+            .await
+            .unwrap();
+        let groups = get_message_groups(&t.ctx, chat_id).await.unwrap();
+        assert_eq!(groups.len(), 4);
+        assert_eq!(groups[2].msg_ids, vec![msg_ids[4], msg_ids[5]]);
+    }
+
+    #[async_std::test]
+    async fn test_marknoticed_chat() {
+        let t = TestContext::new_alice().await;
+        let bob_id = Contact::create(&t.ctx, "Bob", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = create_by_contact_id(&t.ctx, bob_id).await.unwrap();
+        for i in 1..=3 {
+            let mail = format!(
+                "From: Bob <bob@example.net>\n\
+                 To: alice@example.com\n\
+                 Subject: Chat: hi\n\
+                 Chat-Version: 1.0\n\
+                 Chat-Disposition-Notification-To: bob@example.net\n\
+                 Message-ID: <noticed.{}@example.net>\n\
+                 Date: Sun, 22 Mar 2020 22:37:5{} +0000\n\
+                 \n\
+                 message {}\n",
+                i, i, i
+            );
+            crate::dc_receive_imf::dc_receive_imf(&t.ctx, mail.as_bytes(), "INBOX", i, false)
+                .await
+                .unwrap();
+        }
+        assert_eq!(chat_id.get_fresh_msg_cnt(&t.ctx).await, 3);
+
+        marknoticed_chat(&t.ctx, chat_id).await.unwrap();
+        assert_eq!(chat_id.get_fresh_msg_cnt(&t.ctx).await, 0);
+        for item in get_chat_msgs(&t.ctx, chat_id, 0, None).await {
+            if let ChatItem::Message { msg_id } = item {
+                let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+                assert_eq!(msg.get_state(), MessageState::InNoticed);
+            }
+        }
+
+        // unlike marking messages as seen, no read receipts are sent
+        let pending = job::get_pending(&t.ctx).await.unwrap();
+        assert!(pending.iter().all(|job| job.action != Action::SendMdn));
+
+        // nothing to do for chats without fresh messages
+        marknoticed_chat(&t.ctx, chat_id).await.unwrap();
+        assert_eq!(chat_id.get_fresh_msg_cnt(&t.ctx).await, 0);
+    }
+
+    #[async_std::test]
+    async fn test_delete_all_messages() {
+        let t = TestContext::new_alice().await;
+        let bob_id = Contact::create(&t.ctx, "Bob", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = create_by_contact_id(&t.ctx, bob_id).await.unwrap();
+        let mut msg_ids = Vec::new();
+        for i in 0..3 {
+            msg_ids.push(
+                send_text_msg(&t.ctx, chat_id, format!("msg {}", i))
+                    .await
+                    .unwrap(),
+            );
+        }
+        let mut starred = Message::load_from_db(&t.ctx, msg_ids[1]).await.unwrap();
+        starred.set_starred(&t.ctx, true).await.unwrap();
+        let mut draft = Message::new(Viewtype::Text);
+        draft.set_text(Some("draft".to_string()));
+        chat_id.set_draft(&t.ctx, Some(&mut draft)).await;
+
+        // starred messages can be kept
+        delete_all_messages(&t.ctx, chat_id, false, true)
+            .await
+            .unwrap();
+        let msgs = get_chat_msgs(&t.ctx, chat_id, 0, None).await;
+        assert_eq!(msgs.len(), 1);
+        assert!(
+            matches!(msgs.first(), Some(ChatItem::Message { msg_id }) if *msg_id == msg_ids[1])
+        );
+
+        delete_all_messages(&t.ctx, chat_id, false, false)
+            .await
+            .unwrap();
+        assert!(get_chat_msgs(&t.ctx, chat_id, 0, None).await.is_empty());
+        assert!(Chat::load_from_db(&t.ctx, chat_id).await.is_ok());
+        assert_eq!(get_chat_contacts(&t.ctx, chat_id).await, vec![bob_id]);
+        assert_eq!(
+            chat_id
+                .get_draft(&t.ctx)
+                .await
+                .unwrap()
+                .unwrap()
+                .get_text()
+                .unwrap(),
+            "draft"
+        );
+
+        // messages are only deleted on the server if requested
+        let pending = job::get_pending(&t.ctx).await.unwrap();
+        assert!(pending
+            .iter()
+            .all(|job| job.action != Action::DeleteMsgOnImap));
+        let msg_id = send_text_msg(&t.ctx, chat_id, "another".to_string())
+            .await
+            .unwrap();
+        delete_all_messages(&t.ctx, chat_id, true, false)
+            .await
+            .unwrap();
+        assert!(get_chat_msgs(&t.ctx, chat_id, 0, None).await.is_empty());
+        let pending = job::get_pending(&t.ctx).await.unwrap();
+        assert!(pending
+            .iter()
+            .any(|job| job.action == Action::DeleteMsgOnImap && job.foreign_id == msg_id.to_u32()));
+    }
 }
@@ -1,5 +1,7 @@
 //! # Chat list module
 
+use serde::Serialize;
+
 use crate::chat;
 use crate::chat::*;
 use crate::constants::*;
@@ -7,8 +9,8 @@ use crate::contact::*;
 use crate::context::*;
 use crate::ephemeral::delete_expired_messages;
 use crate::error::{bail, ensure, Result};
-use crate::lot::Lot;
-use crate::message::{Message, MessageState, MsgId};
+use crate::lot::{Lot, Meaning};
+use crate::message::{self, Message, MessageState, MsgId};
 use crate::stock::StockMessage;
 
 /// An object representing a single chatlist in memory.
@@ -280,6 +282,96 @@ impl Chatlist {
         Ok(Chatlist { ids })
     }
 
+    /// Get a window of the chatlist.
+    ///
+    /// This works like [Chatlist::try_load] with the `DC_GCL_NO_SPECIALS` flag,
+    /// but only loads up to `limit` chats starting at `offset`.  Together with
+    /// the chats, the total number of matching chats is returned, so the UI
+    /// can size its view without loading the whole list.
+    ///
+    /// If `query` is given, only chats with a matching name are returned,
+    /// including archived ones.  Otherwise archived chats are skipped and
+    /// pinned chats are sorted to the top.
+    ///
+    /// Chats with the same timestamp are ordered by their ID, so the order
+    /// is stable and consecutive pages neither skip nor repeat chats.
+    pub async fn load_paged(
+        context: &Context,
+        query: Option<&str>,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Self, usize)> {
+        if let Err(err) = delete_expired_messages(context).await {
+            warn!(context, "Failed to hide expired messages: {}", err);
+        }
+
+        let str_like_cmd = match query.map(|query| query.trim()) {
+            Some(query) => {
+                ensure!(!query.is_empty(), "missing query");
+                if let Err(err) = update_special_chat_names(context).await {
+                    warn!(context, "cannot update special chat names: {:?}", err)
+                }
+                Some(format!("%{}%", query))
+            }
+            None => None,
+        };
+
+        let total: u32 = context
+            .sql
+            .query_row(
+                "SELECT COUNT(*)
+                 FROM chats c
+                 WHERE c.id>9
+                   AND c.blocked=0
+                   AND ((?1 IS NULL AND NOT c.archived=?2) OR c.name LIKE ?1);",
+                paramsv![str_like_cmd, ChatVisibility::Archived],
+                |row| row.get(0),
+            )
+            .await?;
+
+        let ids = context
+            .sql
+            .query_map(
+                "SELECT c.id, m.id
+                 FROM chats c
+                 LEFT JOIN msgs m
+                        ON c.id=m.chat_id
+                       AND m.id=(
+                               SELECT id
+                                 FROM msgs
+                                WHERE chat_id=c.id
+                                  AND (hidden=0 OR state=?1)
+                                  ORDER BY timestamp DESC, id DESC LIMIT 1)
+                 WHERE c.id>9
+                   AND c.blocked=0
+                   AND ((?2 IS NULL AND NOT c.archived=?3) OR c.name LIKE ?2)
+                 GROUP BY c.id
+                 ORDER BY ?2 IS NULL AND c.archived=?4 DESC,
+                          IFNULL(m.timestamp,c.created_timestamp) DESC, m.id DESC, c.id DESC
+                 LIMIT ?5 OFFSET ?6;",
+                paramsv![
+                    MessageState::OutDraft,
+                    str_like_cmd,
+                    ChatVisibility::Archived,
+                    ChatVisibility::Pinned,
+                    limit as i64,
+                    offset as i64
+                ],
+                |row: &rusqlite::Row| {
+                    let chat_id: ChatId = row.get(0)?;
+                    let msg_id: MsgId = row.get(1).unwrap_or_default();
+                    Ok((chat_id, msg_id))
+                },
+                |rows| {
+                    rows.collect::<std::result::Result<Vec<_>, _>>()
+                        .map_err(Into::into)
+                },
+            )
+            .await?;
+
+        Ok((Chatlist { ids }, total as usize))
+    }
+
     /// Find out the number of chats.
     pub fn len(&self) -> usize {
         self.ids.len()
@@ -394,6 +486,89 @@ impl Chatlist {
     pub fn get_index_for_id(&self, id: ChatId) -> Option<usize> {
         self.ids.iter().position(|(chat_id, _)| chat_id == &id)
     }
+
+    /// Get a single entry of a chatlist, see [ChatlistItem::summary].
+    pub fn get_item(&self, index: usize) -> Option<ChatlistItem> {
+        self.ids.get(index).map(|(chat_id, msg_id)| ChatlistItem {
+            chat_id: *chat_id,
+            msg_id: *msg_id,
+        })
+    }
+}
+
+/// A single entry of a [Chatlist].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChatlistItem {
+    pub chat_id: ChatId,
+
+    /// The last message of the chat, unset if there is none.
+    pub msg_id: MsgId,
+}
+
+/// Summary of a chatlist entry, ready for display.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChatlistSummary {
+    /// Sender name in groups, "Me" for outgoing messages or "Draft";
+    /// `None` if no prefix should be shown.
+    pub prefix: Option<String>,
+
+    /// Meaning of the prefix, eg. to show drafts in a different color.
+    #[serde(skip)]
+    pub prefix_meaning: Meaning,
+
+    /// One-line preview of the message, attachments are prefixed by an icon,
+    /// eg. "📷 Image" or "📎 file.pdf".
+    pub text: String,
+
+    /// True if the preview shows the draft of the chat.
+    pub is_draft: bool,
+
+    /// Timestamp of the previewed message, 0 if there is none.
+    pub timestamp: i64,
+}
+
+impl ChatlistItem {
+    /// Returns the summary of the chat for display in the chatlist.
+    ///
+    /// A draft takes precedence over the last message.
+    pub async fn summary(&self, context: &Context) -> Result<ChatlistSummary> {
+        let chat = Chat::load_from_db(context, self.chat_id).await?;
+        let draft = self.chat_id.get_draft(context).await?;
+        let mut msg = match draft {
+            Some(draft) => draft,
+            None if !self.msg_id.is_unset() => Message::load_from_db(context, self.msg_id).await?,
+            None => {
+                return Ok(ChatlistSummary {
+                    prefix: None,
+                    prefix_meaning: Meaning::None,
+                    text: context
+                        .stock_str(StockMessage::NoMessages)
+                        .await
+                        .to_string(),
+                    is_draft: false,
+                    timestamp: 0,
+                })
+            }
+        };
+
+        let contact = if msg.from_id != DC_CONTACT_ID_SELF
+            && (chat.typ == Chattype::Group || chat.typ == Chattype::VerifiedGroup)
+        {
+            Contact::load_from_db(context, msg.from_id).await.ok()
+        } else {
+            None
+        };
+        let mut lot = Lot::new();
+        lot.fill(&mut msg, &chat, contact.as_ref(), context).await;
+
+        Ok(ChatlistSummary {
+            prefix: lot.text1,
+            prefix_meaning: lot.text1_meaning,
+            text: message::get_preview_text(context, &msg).await,
+            is_draft: msg.state == MessageState::OutDraft,
+            timestamp: msg.get_timestamp(),
+        })
+    }
 }
 
 /// Returns the number of archived chats
@@ -485,6 +660,112 @@ mod tests {
         assert_eq!(chats.len(), 1);
     }
 
+    #[async_std::test]
+    async fn test_archived_link() {
+        let t = TestContext::new().await;
+        let chat_id1 = create_group_chat(&t.ctx, VerifiedStatus::Unverified, "a chat")
+            .await
+            .unwrap();
+        let chat_id2 = create_group_chat(&t.ctx, VerifiedStatus::Unverified, "b chat")
+            .await
+            .unwrap();
+
+        // no archived chats, no link
+        let chats = Chatlist::try_load(&t.ctx, 0, None, None).await.unwrap();
+        assert_eq!(chats.len(), 2);
+        assert!((0..chats.len()).all(|i| !chats.get_chat_id(i).is_archived_link()));
+
+        // the archived chat is replaced by the link
+        chat::archive(&t.ctx, chat_id1).await.unwrap();
+        let chats = Chatlist::try_load(&t.ctx, 0, None, None).await.unwrap();
+        assert_eq!(chats.len(), 2);
+        assert_eq!(chats.get_chat_id(0), chat_id2);
+        assert!(chats.get_chat_id(1).is_archived_link());
+
+        // the link disappears when the archive is empty again
+        chat_id1
+            .set_visibility(&t.ctx, ChatVisibility::Normal)
+            .await
+            .unwrap();
+        let chats = Chatlist::try_load(&t.ctx, 0, None, None).await.unwrap();
+        assert_eq!(chats.len(), 2);
+        assert!((0..chats.len()).all(|i| !chats.get_chat_id(i).is_archived_link()));
+    }
+
+    #[async_std::test]
+    async fn test_archived_link_unarchive_on_incoming() {
+        let t = TestContext::new_alice().await;
+        let bob_id = Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, bob_id).await.unwrap();
+        chat::archive(&t.ctx, chat_id).await.unwrap();
+        let chats = Chatlist::try_load(&t.ctx, 0, None, None).await.unwrap();
+        assert_eq!(chats.len(), 1);
+        assert!(chats.get_chat_id(0).is_archived_link());
+
+        // a new message moves the chat back to the chatlist, the empty archive is not shown
+        let msg = receive_chat_msg(&t.ctx, "bob@example.net", "1@example.net", "hi", "").await;
+        assert_eq!(msg.chat_id, chat_id);
+        let chat = Chat::load_from_db(&t.ctx, chat_id).await.unwrap();
+        assert_eq!(chat.get_visibility(), ChatVisibility::Normal);
+        let chats = Chatlist::try_load(&t.ctx, 0, None, None).await.unwrap();
+        assert_eq!(chats.len(), 1);
+        assert_eq!(chats.get_chat_id(0), chat_id);
+    }
+
+    #[async_std::test]
+    async fn test_load_paged() {
+        let t = TestContext::new().await;
+        let mut chat_ids = Vec::new();
+        for i in 0..100 {
+            let name = format!("chat {}", i);
+            chat_ids.push(
+                create_group_chat(&t.ctx, VerifiedStatus::Unverified, &name)
+                    .await
+                    .unwrap(),
+            );
+        }
+        // most recent chats first, chats created in the same second are ordered by ID
+        chat_ids.reverse();
+
+        let (chats, total) = Chatlist::load_paged(&t.ctx, None, 20, 20).await.unwrap();
+        assert_eq!(total, 100);
+        assert_eq!(chats.len(), 20);
+        for i in 0..20 {
+            assert_eq!(chats.get_chat_id(i), chat_ids[20 + i]);
+        }
+
+        // pages cover the whole list exactly once
+        let mut all = Vec::new();
+        for page in 0..4 {
+            let (chats, _) = Chatlist::load_paged(&t.ctx, None, page * 30, 30)
+                .await
+                .unwrap();
+            all.extend((0..chats.len()).map(|i| chats.get_chat_id(i)));
+        }
+        assert_eq!(all, chat_ids);
+
+        // the text filter is applied before paging
+        let (chats, total) = Chatlist::load_paged(&t.ctx, Some("chat 1"), 5, 5)
+            .await
+            .unwrap();
+        assert_eq!(total, 11); // "chat 1" and "chat 10" to "chat 19"
+        assert_eq!(chats.len(), 5);
+
+        // archived chats are only returned when searching
+        chat_ids[0]
+            .set_visibility(&t.ctx, ChatVisibility::Archived)
+            .await
+            .unwrap();
+        let (_, total) = Chatlist::load_paged(&t.ctx, None, 0, 10).await.unwrap();
+        assert_eq!(total, 99);
+        let (_, total) = Chatlist::load_paged(&t.ctx, Some("chat 99"), 0, 10)
+            .await
+            .unwrap();
+        assert_eq!(total, 1);
+    }
+
     #[async_std::test]
     async fn test_sort_self_talk_up_on_forward() {
         let t = TestContext::new().await;
@@ -558,4 +839,72 @@ mod tests {
         let summary = chats.get_summary(&t.ctx, 0, None).await;
         assert_eq!(summary.get_text2().unwrap(), "foo: bar test"); // the linebreak should be removed from summary
     }
+
+    #[async_std::test]
+    async fn test_item_summary_image() {
+        let t = TestContext::new_alice().await;
+        let bob_id = Contact::create(&t.ctx, "Bob", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = create_by_contact_id(&t.ctx, bob_id).await.unwrap();
+
+        let file = t.dir.path().join("image.png");
+        std::fs::write(
+            &file,
+            include_bytes!("../test-data/image/avatar64x64.png").as_ref(),
+        )
+        .unwrap();
+        let mut msg = Message::new(Viewtype::Image);
+        msg.set_file(file.to_str().unwrap(), None);
+        send_msg(&t.ctx, chat_id, &mut msg).await.unwrap();
+
+        let chats = Chatlist::try_load(&t.ctx, DC_GCL_NO_SPECIALS, None, None)
+            .await
+            .unwrap();
+        let summary = chats.get_item(0).unwrap().summary(&t.ctx).await.unwrap();
+        assert_eq!(summary.prefix, Some("Me".to_string()));
+        assert_eq!(summary.prefix_meaning, Meaning::Text1Self);
+        assert_eq!(summary.text, "📷 Image");
+        assert!(!summary.is_draft);
+        assert!(summary.timestamp > 0);
+    }
+
+    #[async_std::test]
+    async fn test_item_summary_draft() {
+        let t = TestContext::new_alice().await;
+        let chat_id = create_group_chat(&t.ctx, VerifiedStatus::Unverified, "a chat")
+            .await
+            .unwrap();
+        chat_id.set_draft(&t.ctx, None).await;
+        let item = ChatlistItem {
+            chat_id,
+            msg_id: MsgId::new_unset(),
+        };
+        assert_eq!(item.summary(&t.ctx).await.unwrap().text, "No messages.");
+
+        send_text_msg(&t.ctx, chat_id, "sent".to_string())
+            .await
+            .unwrap();
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("unfinished".to_string()));
+        chat_id.set_draft(&t.ctx, Some(&mut msg)).await;
+
+        // the draft takes precedence over the last message
+        let chats = Chatlist::try_load(&t.ctx, DC_GCL_NO_SPECIALS, None, None)
+            .await
+            .unwrap();
+        let summary = chats.get_item(0).unwrap().summary(&t.ctx).await.unwrap();
+        assert_eq!(summary.prefix, Some("Draft".to_string()));
+        assert_eq!(summary.prefix_meaning, Meaning::Text1Draft);
+        assert_eq!(summary.text, "unfinished");
+        assert!(summary.is_draft);
+
+        chat_id.set_draft(&t.ctx, None).await;
+        let chats = Chatlist::try_load(&t.ctx, DC_GCL_NO_SPECIALS, None, None)
+            .await
+            .unwrap();
+        let summary = chats.get_item(0).unwrap().summary(&t.ctx).await.unwrap();
+        assert_eq!(summary.text, "sent");
+        assert!(!summary.is_draft);
+    }
 }
@@ -42,6 +42,17 @@ pub enum Config {
     Selfstatus,
     Selfavatar,
 
+    /// Appended to messages written by the user after a `-- ` line.
+    /// If unset, the status is used, an empty string disables the footer.
+    MessageSignature,
+
+    /// Sent as encrypted `X-Mailer` header, defaults to the core version.
+    /// An empty string omits the header.
+    MailerUserAgent,
+
+    /// Domain used in generated Message-IDs instead of the domain of the configured address.
+    MessageIdDomain,
+
     #[strum(props(default = "0"))]
     BccSelf,
 
@@ -51,6 +62,11 @@ pub enum Config {
     #[strum(props(default = "1"))]
     MdnsEnabled,
 
+    /// If set, seen markers are sent to groups, see [crate::message::Message::get_seen_by].
+    /// Nothing is sent if `MdnsEnabled` is unset.
+    #[strum(props(default = "0"))]
+    SeenMarkersEnabled,
+
     #[strum(props(default = "1"))]
     InboxWatch,
 
@@ -63,12 +79,37 @@ pub enum Config {
     #[strum(props(default = "1"))]
     MvboxMove,
 
+    /// If set, only the inbox is watched and the DeltaChat folder
+    /// is neither created nor used, regardless of the options above.
+    #[strum(props(default = "0"))]
+    OnlyFetchInbox,
+
+    /// Folder chosen by the user as DeltaChat folder, see [crate::context::Context::list_imap_folders].
+    /// If unset, the folder is detected, the folder in use is [Config::ConfiguredMvboxFolder].
+    MvboxFolder,
+
+    /// Folder chosen by the user as Sent folder.
+    /// If unset, the folder is detected, the folder in use is [Config::ConfiguredSentboxFolder].
+    SentboxFolder,
+
     #[strum(props(default = "0"))] // also change ShowEmails.default() on changes
     ShowEmails,
 
+    #[strum(props(default = "0"))] // also change SpamHandling.default() on changes
+    SpamHandling,
+
+    /// Number of topmost `Received` headers added by the own mail server,
+    /// `X-Spam-*` headers above the last of them are trusted.
+    /// Set this to 2 or more if a filter as amavis reinjects messages.
+    #[strum(props(default = "1"))]
+    SpamTrustedHops,
+
     #[strum(props(default = "0"))] // also change MediaQuality.default() on changes
     MediaQuality,
 
+    #[strum(props(default = "0"))] // also change DisplayNamePreference.default() on changes
+    DisplayNamePreference,
+
     #[strum(props(default = "0"))]
     KeyGenType,
 
@@ -91,6 +132,72 @@ pub enum Config {
     #[strum(props(default = "0"))]
     DeleteDeviceAfter,
 
+    /// Whether remote images in HTML messages are shown.
+    ///
+    /// Off by default, so that opening a message does not tell the
+    /// sender that it was read.
+    #[strum(props(default = "0"))]
+    AutoShowRemoteImages,
+
+    /// Number of recent messages fetched from the inbox after configuring.
+    ///
+    /// Messages older than the configuration are not fetched otherwise,
+    /// 0 disables fetching them.
+    #[strum(props(default = "100"))]
+    FetchRecentMsgs,
+
+    /// Seconds after which an unused SMTP connection is closed.
+    ///
+    /// Servers drop idle connections after some minutes, closing them
+    /// before avoids a failing send on a dead connection. 0 keeps idle
+    /// connections open.
+    #[strum(props(default = "240"))]
+    SmtpMaxIdle,
+
+    /// Maximum number of messages downloaded with a single IMAP `UID FETCH` command.
+    ///
+    /// Larger batches need less round trips but more memory.
+    /// Values outside 1..1000 are clamped.
+    #[strum(props(default = "100"))]
+    ImapFetchBatchSize,
+
+    /// Maximum number of seconds between two messages of a sender
+    /// to be shown in the same group, see chat::get_message_groups().
+    #[strum(props(default = "300"))]
+    MessageGroupWindow,
+
+    /// If set, IMAP and SMTP protocol lines are logged at debug level,
+    /// with passwords and OAuth2 tokens masked.
+    #[strum(props(default = "0"))]
+    DebugTraceProtocol,
+
+    /// If set, the Mozilla `.well-known` autoconfig URL is retried over plain HTTP
+    /// after all HTTPS sources failed. The response may be forged by anyone on the network.
+    #[strum(props(default = "0"))]
+    AllowInsecureAutoconfig,
+
+    /// SASL mechanism used for OAuth2, `XOAUTH2` or `OAUTHBEARER`.
+    ///
+    /// If unset, the mechanism is selected from those advertised by the IMAP server.
+    /// SMTP only supports `XOAUTH2` and fails to connect if `OAUTHBEARER` is set.
+    #[strum(serialize = "oauth2_sasl_mechanism")]
+    Oauth2SaslMechanism,
+
+    /// Seconds between two automatic housekeeping runs, deleting unreferenced blobs,
+    /// expired messages and unused keys. 0 disables automatic housekeeping.
+    #[strum(props(default = "86400"))]
+    HousekeepingInterval,
+
+    /// Number of superseded keys kept for every peer by [keyring::prune],
+    /// the most recent ones are kept. Verified keys are always kept.
+    ///
+    /// [keyring::prune]: crate::keyring::prune
+    #[strum(props(default = "0"))]
+    KeepSupersededKeys,
+
+    /// Timestamp of the last housekeeping run.
+    LastHousekeeping,
+
     SaveMimeHeaders,
     ConfiguredAddr,
     ConfiguredMailServer,
@@ -156,6 +263,7 @@ impl Context {
         match key {
             Config::Selfstatus => Some(self.stock_str(StockMessage::StatusLine).await.into_owned()),
             Config::ConfiguredInboxFolder => Some("INBOX".to_owned()),
+            Config::MailerUserAgent => Some(format!("Delta Chat Core {}", &*DC_VERSION_STR)),
             _ => key.get_str("default").map(|s| s.to_string()),
         }
     }
@@ -183,6 +291,27 @@ impl Context {
         }
     }
 
+    /// Returns the address whose domain is used for Message-IDs generated for `from_addr`.
+    ///
+    /// This is `from_addr` unless a valid [Config::MessageIdDomain] is set.
+    pub(crate) async fn get_message_id_addr(&self, from_addr: &str) -> String {
+        match self.get_config(Config::MessageIdDomain).await {
+            Some(domain) => {
+                let domain = domain.trim().trim_start_matches('@');
+                if domain.is_empty()
+                    || domain
+                        .chars()
+                        .any(|c| c.is_whitespace() || c == '<' || c == '>' || c == '@')
+                {
+                    from_addr.to_string()
+                } else {
+                    format!("@{}", domain)
+                }
+            }
+            None => from_addr.to_string(),
+        }
+    }
+
     /// Gets configured "delete_device_after" value.
     ///
     /// `None` means never delete the message, `Some(x)` means delete
@@ -244,6 +373,13 @@ impl Context {
                 job::schedule_resync(self).await;
                 ret
             }
+            Config::MvboxFolder | Config::SentboxFolder => {
+                self.sql.set_raw_config(self, key, value).await?;
+                // configure the folders again on the next connect
+                self.sql
+                    .set_raw_config_int(self, "folders_configured", 0)
+                    .await
+            }
             _ => self.sql.set_raw_config(self, key, value).await,
         }
     }
@@ -390,4 +526,39 @@ mod tests {
         let media_quality = constants::MediaQuality::from_i32(media_quality).unwrap_or_default();
         assert_eq!(media_quality, constants::MediaQuality::Worse);
     }
+
+    #[async_std::test]
+    async fn test_mvbox_folder_config_option() {
+        let t = TestContext::new().await;
+        t.ctx
+            .set_config(Config::ConfiguredMvboxFolder, Some("DeltaChat"))
+            .await
+            .unwrap();
+        t.ctx
+            .sql
+            .set_raw_config_int(&t.ctx, "folders_configured", 3)
+            .await
+            .unwrap();
+
+        // the detected folder is kept until the folders are configured again
+        t.ctx
+            .set_config(Config::MvboxFolder, Some("Chats"))
+            .await
+            .unwrap();
+        assert_eq!(
+            t.ctx.get_config(Config::MvboxFolder).await,
+            Some("Chats".to_string())
+        );
+        assert_eq!(
+            t.ctx.get_config(Config::ConfiguredMvboxFolder).await,
+            Some("DeltaChat".to_string())
+        );
+        assert_eq!(
+            t.ctx
+                .sql
+                .get_raw_config_int(&t.ctx, "folders_configured")
+                .await,
+            Some(0)
+        );
+    }
 }
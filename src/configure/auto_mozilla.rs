@@ -7,6 +7,7 @@ use std::io::BufRead;
 use std::str::FromStr;
 
 use crate::context::Context;
+use crate::dc_tools::normalize_address;
 use crate::login_param::LoginParam;
 use crate::provider::{Protocol, Socket};
 
@@ -179,10 +180,9 @@ fn parse_xml_reader<B: BufRead>(
 /// Parses XML and fills in address and domain placeholders.
 fn parse_xml_with_address(in_emailaddr: &str, xml_raw: &str) -> Result<MozAutoconfigure, Error> {
     // Split address into local part and domain part.
-    let parts: Vec<&str> = in_emailaddr.rsplitn(2, '@').collect();
-    let (in_emaillocalpart, in_emaildomain) = match &parts[..] {
-        [domain, local] => (local, domain),
-        _ => return Err(Error::InvalidEmailAddress(in_emailaddr.to_string())),
+    let (in_emaillocalpart, in_emaildomain) = match normalize_address(in_emailaddr) {
+        Ok(addr) => (addr.local, addr.domain),
+        Err(_) => return Err(Error::InvalidEmailAddress(in_emailaddr.to_string())),
     };
 
     let mut reader = quick_xml::Reader::from_str(xml_raw);
@@ -195,8 +195,8 @@ fn parse_xml_with_address(in_emailaddr: &str, xml_raw: &str) -> Result<MozAutoco
 
     let fill_placeholders = |val: &str| -> String {
         val.replace("%EMAILADDRESS%", in_emailaddr)
-            .replace("%EMAILLOCALPART%", in_emaillocalpart)
-            .replace("%EMAILDOMAIN%", in_emaildomain)
+            .replace("%EMAILLOCALPART%", &in_emaillocalpart)
+            .replace("%EMAILDOMAIN%", &in_emaildomain)
     };
 
     let fill_server_placeholders = |server: Server| -> Server {
@@ -7,6 +7,7 @@ mod server_params;
 
 use anyhow::{bail, ensure, Context as _, Result};
 use async_std::prelude::*;
+use async_std_resolver::{config, resolver};
 use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 
 use crate::config::Config;
@@ -19,7 +20,7 @@ use crate::message::Message;
 use crate::oauth2::*;
 use crate::provider::{Protocol, Socket, UsernamePattern};
 use crate::smtp::Smtp;
-use crate::{chat, e2ee, provider};
+use crate::{chat, e2ee, job, provider};
 
 use auto_mozilla::moz_autoconfigure;
 use auto_outlook::outlk_autodiscover;
@@ -93,7 +94,7 @@ impl Context {
             if !provider.after_login_hint.is_empty() {
                 let mut msg = Message::new(Viewtype::Text);
                 msg.text = Some(provider.after_login_hint.to_string());
-                if chat::add_device_msg(self, Some("core-provider-info"), Some(&mut msg))
+                if chat::add_device_msg_with_id(self, "core-provider-info", &mut msg)
                     .await
                     .is_err()
                 {
@@ -106,6 +107,7 @@ impl Context {
             Ok(_) => {
                 self.set_config(Config::NotifyAboutWrongPw, Some("1"))
                     .await?;
+                job::schedule_fetch_recent_msgs(self).await;
                 progress!(self, 1000);
                 Ok(())
             }
@@ -162,9 +164,7 @@ async fn configure(ctx: &Context, param: &mut LoginParam) -> Result<()> {
     }
     // no oauth? - just continue it's no error
 
-    let parsed: EmailAddress = param.addr.parse().context("Bad email-address")?;
-    let param_domain = parsed.domain;
-    let param_addr_urlencoded = utf8_percent_encode(&param.addr, NON_ALPHANUMERIC).to_string();
+    let parsed = normalize_address(&param.addr).context("Bad email-address")?;
 
     // Step 2: Autoconfig
     progress!(ctx, 200);
@@ -184,8 +184,7 @@ async fn configure(ctx: &Context, param: &mut LoginParam) -> Result<()> {
         if let Some(servers) = get_offline_autoconfig(ctx, &param.addr) {
             param_autoconfig = Some(servers);
         } else {
-            param_autoconfig =
-                get_autoconfig(ctx, param, &param_domain, &param_addr_urlencoded).await;
+            param_autoconfig = get_autoconfig(ctx, param, &parsed).await;
         }
     } else {
         param_autoconfig = None;
@@ -218,7 +217,7 @@ async fn configure(ctx: &Context, param: &mut LoginParam) -> Result<()> {
         // changed if default value (address with domain) didn't work
         // for all available hosts and ports.
         .flat_map(|params| params.expand_usernames(&param.addr).into_iter())
-        .flat_map(|params| params.expand_hostnames(&param_domain).into_iter())
+        .flat_map(|params| params.expand_hostnames(&parsed.domain).into_iter())
         .flat_map(|params| params.expand_ports().into_iter())
         .collect();
 
@@ -271,9 +270,7 @@ async fn configure(ctx: &Context, param: &mut LoginParam) -> Result<()> {
 
     progress!(ctx, 900);
 
-    let create_mvbox = ctx.get_config_bool(Config::MvboxWatch).await
-        || ctx.get_config_bool(Config::MvboxMove).await;
-
+    let create_mvbox = ctx.should_create_mvbox().await;
     imap.configure_folders(ctx, create_mvbox).await?;
 
     imap.select_with_uidvalidity(ctx, "INBOX")
@@ -312,7 +309,12 @@ struct AutoconfigSource {
 }
 
 impl AutoconfigSource {
-    fn all(domain: &str, addr: &str) -> [Self; 5] {
+    /// Returns the sources to query for the given address.
+    ///
+    /// The URLs use the punycode form of internationalized domains.
+    fn all(addr: &NormalizedAddr) -> [Self; 5] {
+        let domain = &addr.domain;
+        let addr = utf8_percent_encode(&addr.to_string(), NON_ALPHANUMERIC).to_string();
         [
             AutoconfigSource {
                 provider: AutoconfigProvider::Mozilla,
@@ -349,6 +351,21 @@ impl AutoconfigSource {
         ]
     }
 
+    /// Returns the Mozilla `.well-known` source queried over plain HTTP.
+    ///
+    /// Only used if [Config::AllowInsecureAutoconfig] is set.
+    fn insecure(addr: &NormalizedAddr) -> Self {
+        let domain = &addr.domain;
+        let addr = utf8_percent_encode(&addr.to_string(), NON_ALPHANUMERIC).to_string();
+        AutoconfigSource {
+            provider: AutoconfigProvider::Mozilla,
+            url: format!(
+                "http://{}/.well-known/autoconfig/mail/config-v1.1.xml?emailaddress={}",
+                domain, addr
+            ),
+        }
+    }
+
     async fn fetch(&self, ctx: &Context, param: &LoginParam) -> Result<Vec<ServerParams>> {
         let params = match self.provider {
             AutoconfigProvider::Mozilla => moz_autoconfigure(ctx, &self.url, &param).await?,
@@ -363,13 +380,13 @@ impl AutoconfigSource {
 ///
 /// A Search configurations from the domain used in the email-address, prefer encrypted
 /// B. If we have no configuration yet, search configuration in Thunderbird's centeral database
+/// C. If still nothing is found, use the provider database entry of the domain's MX hosts
 async fn get_autoconfig(
     ctx: &Context,
     param: &LoginParam,
-    param_domain: &str,
-    param_addr_urlencoded: &str,
+    addr: &NormalizedAddr,
 ) -> Option<Vec<ServerParams>> {
-    let sources = AutoconfigSource::all(param_domain, param_addr_urlencoded);
+    let sources = AutoconfigSource::all(addr);
 
     let mut progress = 300;
     for source in &sources {
@@ -381,6 +398,97 @@ async fn get_autoconfig(
         }
     }
 
+    if let Some(res) = get_mx_autoconfig(ctx, addr).await {
+        return Some(res);
+    }
+
+    if ctx.get_config_bool(Config::AllowInsecureAutoconfig).await {
+        let source = AutoconfigSource::insecure(addr);
+        warn!(
+            ctx,
+            "All HTTPS autoconfig sources failed, trying INSECURE plain HTTP: {}", source.url
+        );
+        if let Ok(res) = source.fetch(ctx, param).await {
+            warn!(ctx, "Using autoconfig received over INSECURE plain HTTP");
+            return Some(res);
+        }
+    }
+
+    None
+}
+
+/// Returns the servers of `provider` with the usernames derived from `addr`.
+fn provider_servers(provider: &provider::Provider, addr: &str) -> Vec<ServerParams> {
+    provider
+        .server
+        .iter()
+        .map(|s| ServerParams {
+            protocol: s.protocol,
+            socket: s.socket,
+            hostname: s.hostname.to_string(),
+            port: s.port,
+            username: match s.username_pattern {
+                UsernamePattern::EMAIL => addr.to_string(),
+                UsernamePattern::EMAILLOCALPART => normalize_address(addr)
+                    .map(|addr| addr.local)
+                    .unwrap_or_else(|_| addr.to_string()),
+            },
+        })
+        .collect()
+}
+
+/// Looks up the MX records of the domain and returns the servers
+/// of the provider operating them, if it is in the provider database.
+///
+/// This finds the servers of custom domains hosted by a known provider.
+async fn get_mx_autoconfig(context: &Context, addr: &NormalizedAddr) -> Option<Vec<ServerParams>> {
+    let resolver = resolver(
+        config::ResolverConfig::default(),
+        config::ResolverOpts::default(),
+    )
+    .await
+    .ok()?;
+
+    let mut fqdn = addr.domain.clone();
+    if !fqdn.ends_with('.') {
+        fqdn.push('.');
+    }
+    let lookup = match resolver.mx_lookup(fqdn).await {
+        Ok(lookup) => lookup,
+        Err(err) => {
+            info!(context, "MX lookup for {} failed: {}", addr.domain, err);
+            return None;
+        }
+    };
+    let mut records: Vec<_> = lookup.iter().collect();
+    records.sort_by_key(|mx| mx.preference());
+    let mx_hosts: Vec<String> = records
+        .iter()
+        .map(|mx| mx.exchange().to_lowercase().to_utf8())
+        .collect();
+
+    autoconfig_from_mx(context, &addr.to_string(), &mx_hosts)
+}
+
+/// Returns the servers of the first provider operating one of `mx_hosts`.
+fn autoconfig_from_mx(
+    context: &Context,
+    addr: &str,
+    mx_hosts: &[String],
+) -> Option<Vec<ServerParams>> {
+    for mx_host in mx_hosts {
+        if let Some(provider) = provider::get_provider_by_mx(mx_host) {
+            if provider.status == provider::Status::BROKEN || provider.server.is_empty() {
+                continue;
+            }
+            info!(
+                context,
+                "MX {} is operated by a known provider, using its servers", mx_host
+            );
+            return Some(provider_servers(provider, addr));
+        }
+    }
+
     None
 }
 
@@ -398,27 +506,7 @@ fn get_offline_autoconfig(context: &Context, addr: &str) -> Option<Vec<ServerPar
                     None
                 } else {
                     info!(context, "offline autoconfig found");
-                    let servers = provider
-                        .server
-                        .iter()
-                        .map(|s| ServerParams {
-                            protocol: s.protocol,
-                            socket: s.socket,
-                            hostname: s.hostname.to_string(),
-                            port: s.port,
-                            username: match s.username_pattern {
-                                UsernamePattern::EMAIL => addr.to_string(),
-                                UsernamePattern::EMAILLOCALPART => {
-                                    if let Some(at) = addr.find('@') {
-                                        addr.split_at(at).0.to_string()
-                                    } else {
-                                        addr.to_string()
-                                    }
-                                }
-                            },
-                        })
-                        .collect();
-                    Some(servers)
+                    Some(provider_servers(provider, addr))
                 }
             }
             provider::Status::BROKEN => {
@@ -517,6 +605,102 @@ mod tests {
         assert!(t.ctx.configure().await.is_err());
     }
 
+    #[test]
+    fn test_autoconfig_sources_idn() {
+        let addr = normalize_address("Jürgen@Müller.de").unwrap();
+        let sources = AutoconfigSource::all(&addr);
+        assert_eq!(
+            sources[0].url,
+            "https://autoconfig.xn--mller-kva.de/mail/config-v1.1.xml\
+             ?emailaddress=J%C3%BCrgen%40xn%2D%2Dmller%2Dkva%2Ede"
+        );
+        for source in sources.iter() {
+            assert!(source.url.contains("xn--mller-kva.de"));
+            assert!(!source.url.contains("ü"));
+        }
+    }
+
+    #[async_std::test]
+    async fn test_insecure_autoconfig() {
+        use async_std::net::TcpListener;
+
+        let t = TestContext::new().await;
+        assert!(!t.ctx.get_config_bool(Config::AllowInsecureAutoconfig).await);
+
+        // mock server answering a single request over plain HTTP
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let domain = listener.local_addr().unwrap().to_string();
+        let server = async_std::task::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !String::from_utf8_lossy(&request).contains("\r\n\r\n") {
+                let n = stream.read(&mut buf).await.unwrap();
+                assert!(n > 0);
+                request.extend_from_slice(buf.get(..n).unwrap());
+            }
+            let body = include_str!("../../test-data/autoconfig/lakenet.ch.xml");
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&request).to_string()
+        });
+
+        let addr = NormalizedAddr {
+            local: "example".to_string(),
+            domain: domain.clone(),
+            display: format!("example@{}", domain),
+        };
+        let source = AutoconfigSource::insecure(&addr);
+        assert!(source
+            .url
+            .starts_with(&format!("http://{}/.well-known/", domain)));
+
+        let param = LoginParam {
+            addr: "example@lakenet.ch".to_string(),
+            ..Default::default()
+        };
+        let servers = source.fetch(&t.ctx, &param).await.unwrap();
+        let request = server.await;
+        assert!(request.starts_with("GET /.well-known/autoconfig/mail/config-v1.1.xml?"));
+
+        // servers found over HTTP are not downgraded further, the socket types are kept
+        assert_eq!(servers[0].protocol, Protocol::IMAP);
+        assert_eq!(servers[0].hostname, "mail.lakenet.ch");
+        assert_eq!(servers[0].socket, Socket::SSL);
+        let smtp = servers
+            .iter()
+            .find(|params| params.protocol == Protocol::SMTP)
+            .unwrap();
+        assert_eq!(smtp.hostname, "mail.lakenet.ch");
+        assert_eq!(smtp.socket, Socket::STARTTLS);
+    }
+
+    #[async_std::test]
+    async fn test_autoconfig_from_mx() {
+        let context = TestContext::new().await.ctx;
+        let addr = "alice@customdomain.example";
+
+        let mx_hosts = vec![
+            "mx.unexistant.org".to_string(),
+            "aspmx.l.google.com".to_string(),
+        ];
+        let servers = autoconfig_from_mx(&context, addr, &mx_hosts).unwrap();
+        assert_eq!(servers.len(), 2);
+        assert_eq!(servers[0].protocol, Protocol::IMAP);
+        assert_eq!(servers[0].hostname, "imap.gmail.com");
+        assert_eq!(servers[0].username, addr);
+        assert_eq!(servers[1].protocol, Protocol::SMTP);
+        assert_eq!(servers[1].hostname, "smtp.gmail.com");
+        assert_eq!(servers[1].username, addr);
+
+        let mx_hosts = vec!["mx.unexistant.org".to_string()];
+        assert!(autoconfig_from_mx(&context, addr, &mx_hosts).is_none());
+    }
+
     #[async_std::test]
     async fn test_get_offline_autoconfig() {
         let context = TestContext::new().await.ctx;
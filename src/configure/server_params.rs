@@ -1,5 +1,6 @@
 //! Variable server parameters lists
 
+use crate::dc_tools::normalize_address;
 use crate::provider::{Protocol, Socket};
 
 /// Set of variable parameters to try during configuration.
@@ -32,8 +33,8 @@ impl ServerParams {
             self.username = addr.to_string();
             res.push(self.clone());
 
-            if let Some(at) = addr.find('@') {
-                self.username = addr.split_at(at).0.to_string();
+            if let Ok(addr) = normalize_address(addr) {
+                self.username = addr.local;
                 res.push(self);
             }
         } else {
@@ -57,6 +57,38 @@ impl Default for ShowEmails {
     }
 }
 
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive, FromSql, ToSql)]
+#[repr(u8)]
+pub enum DisplayNamePreference {
+    /// The name set by the user, then the name set by the contact.
+    UserSet = 0,
+
+    /// The name set by the contact, then the name set by the user.
+    AuthName = 1,
+}
+
+impl Default for DisplayNamePreference {
+    fn default() -> Self {
+        DisplayNamePreference::UserSet // also change Config.DisplayNamePreference props(default) on changes
+    }
+}
+
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive, FromSql, ToSql)]
+#[repr(u8)]
+pub enum SpamHandling {
+    /// Messages flagged as spam are handled as any other message.
+    Off = 0,
+
+    /// Messages of unknown senders flagged as spam go to [DC_CHAT_ID_SPAM].
+    Separate = 1,
+}
+
+impl Default for SpamHandling {
+    fn default() -> Self {
+        SpamHandling::Off // also change Config.SpamHandling props(default) on changes
+    }
+}
+
 #[derive(Debug, Display, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive, FromSql, ToSql)]
 #[repr(u8)]
 pub enum MediaQuality {
@@ -113,6 +145,7 @@ pub const DC_GCM_ADDDAYMARKER: u32 = 0x01;
 
 pub const DC_GCL_VERIFIED_ONLY: usize = 0x01;
 pub const DC_GCL_ADD_SELF: usize = 0x02;
+pub const DC_GCL_KNOWN_ONLY: usize = 0x04;
 
 // unchanged user avatars are resent to the recipients every some days
 pub const DC_RESEND_USER_AVATAR_DAYS: i64 = 14;
@@ -129,6 +162,8 @@ pub const DC_CHAT_ID_STARRED: u32 = 5;
 pub const DC_CHAT_ID_ARCHIVED_LINK: u32 = 6;
 /// only an indicator in a chatlist
 pub const DC_CHAT_ID_ALLDONE_HINT: u32 = 7;
+/// messages of unknown senders flagged as spam get this chat_id if `spam_handling` is set to `Separate`
+pub const DC_CHAT_ID_SPAM: u32 = 8;
 /// larger chat IDs are "real" chats, their messages are "real" messages.
 pub const DC_CHAT_ID_LAST_SPECIAL: u32 = 9;
 
@@ -281,6 +316,11 @@ pub enum Viewtype {
 
     /// Message is an invitation to a videochat.
     VideochatInvitation = 70,
+
+    /// Webxdc app, a zip archive with an `index.html`.
+    /// The file is set via dc_msg_set_file(),
+    /// name and icon are retrieved via dc_msg_get_webxdc_info().
+    Webxdc = 80,
 }
 
 impl Default for Viewtype {
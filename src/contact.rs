@@ -4,10 +4,11 @@ use async_std::path::PathBuf;
 use deltachat_derive::*;
 use itertools::Itertools;
 use lazy_static::lazy_static;
+use num_traits::FromPrimitive;
 use regex::Regex;
 
 use crate::aheader::EncryptPreference;
-use crate::chat::ChatId;
+use crate::chat::{self, ChatId};
 use crate::config::Config;
 use crate::constants::*;
 use crate::context::Context;
@@ -67,6 +68,14 @@ pub struct Contact {
 
     /// Parameters as Param::ProfileImage
     pub param: Params,
+
+    /// Timestamp of the last message received from the contact,
+    /// 0 if nothing was received yet. Use `Contact::last_seen` to access this field.
+    last_seen: i64,
+
+    /// Which name `Contact::get_display_name` prefers,
+    /// taken from `Config::DisplayNamePreference` when loading.
+    display_name_preference: DisplayNamePreference,
 }
 
 /// Possible origins of a contact.
@@ -100,6 +109,7 @@ pub enum Origin {
     IncomingTo = 0x400,
 
     /// a chat was manually created for this user, but no message yet sent
+    /// Contacts with at least this origin value are returned with `DC_GCL_KNOWN_ONLY`.
     CreateChat = 0x800,
 
     /// message sent by us
@@ -165,7 +175,7 @@ impl Contact {
         let mut res = context
             .sql
             .query_row(
-                "SELECT c.name, c.addr, c.origin, c.blocked, c.authname, c.param
+                "SELECT c.name, c.addr, c.origin, c.blocked, c.authname, c.param, c.last_seen
                FROM contacts c
               WHERE c.id=?;",
                 paramsv![contact_id as i32],
@@ -178,11 +188,17 @@ impl Contact {
                         blocked: row.get::<_, Option<i32>>(3)?.unwrap_or_default() != 0,
                         origin: row.get(2)?,
                         param: row.get::<_, String>(5)?.parse().unwrap_or_default(),
+                        last_seen: row.get(6)?,
+                        display_name_preference: DisplayNamePreference::default(),
                     };
                     Ok(contact)
                 },
             )
             .await?;
+        res.display_name_preference = DisplayNamePreference::from_i32(
+            context.get_config_int(Config::DisplayNamePreference).await,
+        )
+        .unwrap_or_default();
         if contact_id == DC_CONTACT_ID_SELF {
             res.name = context.stock_str(StockMessage::SelfMsg).await.to_string();
             res.addr = context
@@ -305,15 +321,22 @@ impl Contact {
         if addr_cmp(addr_normalized, addr_self) {
             return DC_CONTACT_ID_SELF;
         }
-        context.sql.query_get_value(
-            context,
-            "SELECT id FROM contacts WHERE addr=?1 COLLATE NOCASE AND id>?2 AND origin>=?3 AND blocked=0;",
-            paramsv![
-                addr_normalized,
-                DC_CONTACT_ID_LAST_SPECIAL as i32,
-                min_origin as u32,
-            ],
-        ).await.unwrap_or_default()
+        context
+            .sql
+            .query_get_value(
+                context,
+                "SELECT id FROM contacts
+              WHERE (addr=?1 COLLATE NOCASE
+                     OR id IN (SELECT contact_id FROM contacts_addrs WHERE addr=?1))
+                AND id>?2 AND origin>=?3 AND blocked=0;",
+                paramsv![
+                    addr_normalized,
+                    DC_CONTACT_ID_LAST_SPECIAL as i32,
+                    min_origin as u32,
+                ],
+            )
+            .await
+            .unwrap_or_default()
     }
 
     /// Lookup a contact and create it if it does not exist yet.
@@ -379,15 +402,35 @@ impl Contact {
             bail!("Bad address supplied: {:?}", addr);
         }
 
+        // addresses of contacts merged into others, see [merge];
+        // the name and origin of the contact are updated as for its primary address
+        let merged_id: Option<u32> = context
+            .sql
+            .query_get_value(
+                context,
+                "SELECT contact_id FROM contacts_addrs WHERE addr=?;",
+                paramsv![addr],
+            )
+            .await;
+        let (query, params) = match merged_id {
+            Some(ref merged_id) => (
+                "SELECT id, name, addr, origin, authname FROM contacts WHERE id=?;",
+                paramsv![*merged_id],
+            ),
+            None => (
+                "SELECT id, name, addr, origin, authname FROM contacts WHERE addr=? COLLATE NOCASE;",
+                paramsv![addr],
+            ),
+        };
+
         let mut update_addr = false;
         let mut update_name = false;
         let mut update_authname = false;
         let mut row_id = 0;
 
-        if let Ok((id, row_name, row_addr, row_origin, row_authname)) = context.sql.query_row(
-            "SELECT id, name, addr, origin, authname FROM contacts WHERE addr=? COLLATE NOCASE;",
-            paramsv![addr.to_string()],
-            |row| {
+        if let Ok((id, row_name, row_addr, row_origin, row_authname)) = context
+            .sql
+            .query_row(query, params, |row| {
                 let row_id = row.get(0)?;
                 let row_name: String = row.get(1)?;
                 let row_addr: String = row.get(2)?;
@@ -413,11 +456,11 @@ impl Contact {
                 }
 
                 Ok((row_id, row_name, row_addr, row_origin, row_authname))
-            },
-        )
-        .await {
+            })
+            .await
+        {
             row_id = id;
-            if origin as i32 >= row_origin as i32 && addr != row_addr {
+            if merged_id.is_none() && origin as i32 >= row_origin as i32 && addr != row_addr {
                 update_addr = true;
             }
             if update_name || update_authname || update_addr || origin > row_origin {
@@ -437,7 +480,11 @@ impl Contact {
                         "UPDATE contacts SET name=?, addr=?, origin=?, authname=? WHERE id=?;",
                         paramsv![
                             new_name,
-                            if update_addr { addr.to_string() } else { row_addr },
+                            if update_addr {
+                                addr.to_string()
+                            } else {
+                                row_addr
+                            },
                             if origin > row_origin {
                                 origin
                             } else {
@@ -477,7 +524,11 @@ impl Contact {
                         name.as_ref().to_string(),
                         addr,
                         origin,
-                        if update_authname { name.as_ref().to_string() } else { "".to_string() }
+                        if update_authname {
+                            name.as_ref().to_string()
+                        } else {
+                            "".to_string()
+                        }
                     ],
                 )
                 .await
@@ -549,6 +600,8 @@ impl Contact {
     /// - if the flag DC_GCL_ADD_SELF is set, SELF is added to the list unless filtered by other parameters
     /// - if the flag DC_GCL_VERIFIED_ONLY is set, only verified contacts are returned.
     ///   if DC_GCL_VERIFIED_ONLY is not set, verified and unverified contacts are returned.
+    /// - if the flag DC_GCL_KNOWN_ONLY is set, only contacts the user has written to,
+    ///   created a chat with or added manually are returned.
     /// `query` is a string to filter the list.
     pub async fn get_all(
         context: &Context,
@@ -564,6 +617,11 @@ impl Contact {
         let mut ret = Vec::new();
         let flag_verified_only = listflags_has(listflags, DC_GCL_VERIFIED_ONLY);
         let flag_add_self = listflags_has(listflags, DC_GCL_ADD_SELF);
+        let min_origin = if listflags_has(listflags, DC_GCL_KNOWN_ONLY) {
+            Origin::CreateChat
+        } else {
+            Origin::IncomingReplyTo
+        };
 
         if flag_verified_only || query.is_some() {
             let s3str_like_cmd = format!(
@@ -588,7 +646,7 @@ impl Contact {
                     paramsv![
                         self_addr,
                         DC_CONTACT_ID_LAST_SPECIAL as i32,
-                        Origin::IncomingReplyTo,
+                        min_origin,
                         s3str_like_cmd,
                         s3str_like_cmd,
                         if flag_verified_only { 0i32 } else { 1i32 },
@@ -624,7 +682,7 @@ impl Contact {
 
             context.sql.query_map(
                 "SELECT id FROM contacts WHERE addr!=?1 AND id>?2 AND origin>=?3 AND blocked=0 ORDER BY LOWER(name||addr),id;",
-                paramsv![self_addr, DC_CONTACT_ID_LAST_SPECIAL as i32, 0x100],
+                paramsv![self_addr, DC_CONTACT_ID_LAST_SPECIAL as i32, min_origin],
                 |row| row.get::<_, i32>(0),
                 |ids| {
                     for id in ids {
@@ -840,6 +898,37 @@ impl Contact {
         &self.authname
     }
 
+    /// Get the time of the last message received from the contact.
+    ///
+    /// This is derived from the `Date:` of received messages,
+    /// `None` if nothing was received from the contact yet.
+    pub fn last_seen(&self) -> Option<i64> {
+        if self.last_seen > 0 {
+            Some(self.last_seen)
+        } else {
+            None
+        }
+    }
+
+    /// Updates the time of the last message received from the contact.
+    ///
+    /// The timestamp never goes backwards, so messages received out of order
+    /// do not overwrite a more recent value.
+    pub(crate) async fn update_last_seen(
+        context: &Context,
+        contact_id: u32,
+        timestamp: i64,
+    ) -> crate::sql::Result<()> {
+        context
+            .sql
+            .execute(
+                "UPDATE contacts SET last_seen=? WHERE id=? AND last_seen<?;",
+                paramsv![timestamp, contact_id as i32, timestamp],
+            )
+            .await?;
+        Ok(())
+    }
+
     /// Get the contact name. This is the name as defined by the contact himself or
     /// modified by the user.  May be an empty string.
     ///
@@ -852,14 +941,20 @@ impl Contact {
     /// Get display name. This is the name as defined by the contact himself,
     /// modified by the user or, if both are unset, the email address.
     ///
+    /// If `Config::DisplayNamePreference` is set to `AuthName`, the name
+    /// defined by the contact himself is preferred over the one set by the user.
+    ///
     /// This name is typically used in lists.
     /// To get the name editable in a formular, use `Contact::get_name`.
     pub fn get_display_name(&self) -> &str {
-        if !self.name.is_empty() {
-            return &self.name;
-        }
-        if !self.authname.is_empty() {
-            return &self.authname;
+        let names = match self.display_name_preference {
+            DisplayNamePreference::UserSet => [&self.name, &self.authname],
+            DisplayNamePreference::AuthName => [&self.authname, &self.name],
+        };
+        for name in names.iter() {
+            if !name.is_empty() {
+                return name;
+            }
         }
         &self.addr
     }
@@ -904,6 +999,27 @@ impl Contact {
         None
     }
 
+    /// Returns the e-mail address of the contact followed by
+    /// the addresses of the contacts merged into it, see [merge].
+    pub async fn get_addrs(&self, context: &Context) -> Vec<String> {
+        let mut addrs = vec![self.addr.clone()];
+        let merged = context
+            .sql
+            .query_map(
+                "SELECT addr FROM contacts_addrs WHERE contact_id=? ORDER BY rowid;",
+                paramsv![self.id as i32],
+                |row| row.get::<_, String>(0),
+                |rows| {
+                    rows.collect::<std::result::Result<Vec<_>, _>>()
+                        .map_err(Into::into)
+                },
+            )
+            .await
+            .unwrap_or_default();
+        addrs.extend(merged);
+        addrs
+    }
+
     /// Get a color for the contact.
     /// The color is calculated from the contact's email address
     /// and can be used for an fallback avatar with white initials
@@ -1036,15 +1152,11 @@ pub fn may_be_valid_addr(addr: &str) -> bool {
     res.is_ok()
 }
 
-/// Returns address with whitespace trimmed and `mailto:` prefix removed.
+/// Returns address with whitespace, display name and `mailto:` prefix removed.
+///
+/// Unlike [normalize_address] the case of the domain is kept.
 pub fn addr_normalize(addr: &str) -> &str {
-    let norm = addr.trim();
-
-    if norm.starts_with("mailto:") {
-        norm.get(7..).unwrap_or(norm)
-    } else {
-        norm
-    }
+    strip_address(addr)
 }
 
 fn sanitize_name_and_addr(name: impl AsRef<str>, addr: impl AsRef<str>) -> (String, String) {
@@ -1101,6 +1213,329 @@ async fn set_block_contact(context: &Context, contact_id: u32, new_blocking: boo
     }
 }
 
+/// Blocks all current and future senders from `domain` and its subdomains.
+///
+/// The domain may be given with a leading `*.` or `@`.
+/// Incoming messages from blocked domains are moved to the trash
+/// without creating a chat, contacts themselves are not changed.
+pub async fn block_domain(context: &Context, domain: &str) -> Result<()> {
+    let domain = normalize_domain(domain)?;
+    context
+        .sql
+        .execute(
+            "INSERT OR IGNORE INTO blocked_domains (domain) VALUES (?);",
+            paramsv![domain],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Removes a rule added by [block_domain].
+///
+/// Messages received while the domain was blocked are not restored.
+pub async fn unblock_domain(context: &Context, domain: &str) -> Result<()> {
+    let domain = normalize_domain(domain)?;
+    context
+        .sql
+        .execute(
+            "DELETE FROM blocked_domains WHERE domain=?;",
+            paramsv![domain],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Returns the domains blocked by [block_domain].
+pub async fn get_blocked_domains(context: &Context) -> Result<Vec<String>> {
+    context
+        .sql
+        .query_map(
+            "SELECT domain FROM blocked_domains ORDER BY domain;",
+            paramsv![],
+            |row| row.get::<_, String>(0),
+            |domains| {
+                domains
+                    .collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await
+}
+
+/// Returns true if the domain of `addr` or one of its parent domains is blocked.
+pub(crate) async fn is_domain_blocked(context: &Context, addr: &str) -> bool {
+    let domain = match addr.rsplit('@').next().map(normalize_domain) {
+        Some(Ok(domain)) => domain,
+        _ => return false,
+    };
+    let mut domain = domain.as_str();
+    loop {
+        if context
+            .sql
+            .exists(
+                "SELECT domain FROM blocked_domains WHERE domain=?;",
+                paramsv![domain],
+            )
+            .await
+            .unwrap_or_default()
+        {
+            return true;
+        }
+        match domain.splitn(2, '.').nth(1) {
+            Some(parent) => domain = parent,
+            None => return false,
+        }
+    }
+}
+
+fn normalize_domain(domain: &str) -> Result<String> {
+    let domain = domain
+        .trim()
+        .trim_start_matches("*.")
+        .trim_start_matches('@')
+        .trim_end_matches('.');
+    ensure!(
+        !domain.is_empty() && !domain.contains(|c: char| c == '@' || c.is_whitespace()),
+        "Invalid domain {:?}",
+        domain
+    );
+    idna::domain_to_ascii(domain).map_err(|_| format_err!("Invalid domain {:?}", domain))
+}
+
+/// Merges the contact `secondary_id` into `primary_id`.
+///
+/// Messages, chats and read receipts of the secondary contact are moved to the primary one
+/// and the secondary contact is deleted. Its address is kept as an additional address
+/// of the primary contact, so later messages from it are assigned to the primary contact.
+/// The primary contact keeps its name unless it is empty.
+pub async fn merge(context: &Context, primary_id: u32, secondary_id: u32) -> Result<()> {
+    let merge = Merge::load(context, primary_id, secondary_id).await?;
+    context
+        .sql
+        .with_conn(move |mut conn| {
+            let tx = conn.transaction()?;
+            merge.execute(&tx)?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await?;
+
+    info!(
+        context,
+        "Merged contact {} into {}.", secondary_id, primary_id
+    );
+    context.emit_event(EventType::ContactsChanged(None));
+    context.emit_event(EventType::MsgsChanged {
+        chat_id: ChatId::new(0),
+        msg_id: MsgId::new(0),
+    });
+    Ok(())
+}
+
+/// The changes of merging two contacts, see [merge].
+struct Merge {
+    primary_id: u32,
+    secondary_id: u32,
+    primary_chat: Option<ChatId>,
+    secondary_chat: Option<ChatId>,
+    name: String,
+    authname: String,
+    origin: Origin,
+    secondary_addr: String,
+}
+
+impl Merge {
+    async fn load(context: &Context, primary_id: u32, secondary_id: u32) -> Result<Self> {
+        ensure!(
+            primary_id > DC_CONTACT_ID_LAST_SPECIAL && secondary_id > DC_CONTACT_ID_LAST_SPECIAL,
+            "Can not merge special contacts"
+        );
+        ensure!(
+            primary_id != secondary_id,
+            "Can not merge contact into itself"
+        );
+        let primary = Contact::load_from_db(context, primary_id).await?;
+        let secondary = Contact::load_from_db(context, secondary_id).await?;
+        let primary_chat = chat::lookup_by_contact_id(context, primary_id)
+            .await
+            .ok()
+            .map(|(chat_id, _)| chat_id);
+        let secondary_chat = chat::lookup_by_contact_id(context, secondary_id)
+            .await
+            .ok()
+            .map(|(chat_id, _)| chat_id);
+
+        Ok(Merge {
+            primary_id,
+            secondary_id,
+            primary_chat,
+            secondary_chat,
+            name: if primary.name.is_empty() {
+                secondary.name
+            } else {
+                primary.name
+            },
+            authname: if primary.authname.is_empty() {
+                secondary.authname
+            } else {
+                primary.authname
+            },
+            origin: std::cmp::max(primary.origin, secondary.origin),
+            secondary_addr: secondary.addr,
+        })
+    }
+
+    /// Runs the statements of the merge in the transaction `tx`.
+    fn execute(&self, tx: &rusqlite::Transaction) -> rusqlite::Result<()> {
+        if let (Some(primary_chat), Some(secondary_chat)) = (self.primary_chat, self.secondary_chat)
+        {
+            // both contacts have a one-to-one chat, keep the one of the primary contact
+            tx.execute(
+                "UPDATE msgs SET chat_id=? WHERE chat_id=?;",
+                params![primary_chat, secondary_chat],
+            )?;
+            tx.execute(
+                "DELETE FROM chats_contacts WHERE chat_id=?;",
+                params![secondary_chat],
+            )?;
+            tx.execute("DELETE FROM chats WHERE id=?;", params![secondary_chat])?;
+        }
+        // in groups with both contacts, the secondary contact is just removed
+        tx.execute(
+            "UPDATE chats_contacts SET contact_id=?1
+              WHERE contact_id=?2
+                AND chat_id NOT IN (SELECT chat_id FROM chats_contacts WHERE contact_id=?1);",
+            params![self.primary_id, self.secondary_id],
+        )?;
+        tx.execute(
+            "DELETE FROM chats_contacts WHERE contact_id=?;",
+            params![self.secondary_id],
+        )?;
+        tx.execute(
+            "UPDATE msgs SET from_id=? WHERE from_id=?;",
+            params![self.primary_id, self.secondary_id],
+        )?;
+        tx.execute(
+            "UPDATE msgs SET to_id=? WHERE to_id=?;",
+            params![self.primary_id, self.secondary_id],
+        )?;
+        tx.execute(
+            "UPDATE msgs_mdns SET contact_id=? WHERE contact_id=?;",
+            params![self.primary_id, self.secondary_id],
+        )?;
+        tx.execute(
+            "UPDATE OR IGNORE msgs_seen SET contact_id=? WHERE contact_id=?;",
+            params![self.primary_id, self.secondary_id],
+        )?;
+        tx.execute(
+            "DELETE FROM msgs_seen WHERE contact_id=?;",
+            params![self.secondary_id],
+        )?;
+        tx.execute(
+            "UPDATE locations SET from_id=? WHERE from_id=?;",
+            params![self.primary_id, self.secondary_id],
+        )?;
+        tx.execute(
+            "UPDATE geofences SET contact_id=? WHERE contact_id=?;",
+            params![self.primary_id, self.secondary_id],
+        )?;
+        tx.execute(
+            "UPDATE contacts_addrs SET contact_id=? WHERE contact_id=?;",
+            params![self.primary_id, self.secondary_id],
+        )?;
+        tx.execute(
+            "INSERT OR REPLACE INTO contacts_addrs (contact_id, addr) VALUES (?, ?);",
+            params![self.primary_id, self.secondary_addr],
+        )?;
+        tx.execute(
+            "UPDATE contacts SET name=?, authname=?, origin=? WHERE id=?;",
+            params![self.name, self.authname, self.origin, self.primary_id],
+        )?;
+        tx.execute(
+            "DELETE FROM contacts WHERE id=?;",
+            params![self.secondary_id],
+        )?;
+        Ok(())
+    }
+}
+
+/// Moves the contact `contact_id` to the address of `new_contact_id`,
+/// eg. after [EventType::ContactAddressChanged].
+///
+/// The new contact is merged into the old one as by [merge],
+/// so the contact keeps its id and chats, and the old address becomes an additional address.
+/// The contact stays verified only if it uses its verified key at the new address.
+/// All changes are done in a single transaction.
+pub async fn accept_address_change(
+    context: &Context,
+    contact_id: u32,
+    new_contact_id: u32,
+) -> Result<()> {
+    let old_addr = Contact::load_from_db(context, contact_id).await?.addr;
+    let merge = Merge::load(context, contact_id, new_contact_id).await?;
+    let new_addr = merge.secondary_addr.clone();
+
+    let verified_fingerprint = Peerstate::from_addr(context, &old_addr)
+        .await?
+        .and_then(|peerstate| peerstate.verified_key_fingerprint);
+    let mut verified = None;
+    if let Some(fingerprint) = verified_fingerprint {
+        if let Some(mut peerstate) = Peerstate::from_addr(context, &new_addr).await? {
+            if peerstate.set_verified(
+                PeerstateKeyType::PublicKey,
+                &fingerprint,
+                PeerstateVerifiedStatus::BidirectVerified,
+            ) {
+                verified = Some((
+                    peerstate.verified_key.as_ref().map(|key| key.to_bytes()),
+                    fingerprint.hex(),
+                    peerstate.addr,
+                ));
+            }
+        }
+    }
+
+    {
+        let old_addr = old_addr.clone();
+        let new_addr = new_addr.clone();
+        context
+            .sql
+            .with_conn(move |mut conn| {
+                let tx = conn.transaction()?;
+                merge.execute(&tx)?;
+                tx.execute(
+                    "UPDATE contacts_addrs SET addr=? WHERE addr=?;",
+                    params![old_addr, new_addr],
+                )?;
+                tx.execute(
+                    "UPDATE contacts SET addr=? WHERE id=?;",
+                    params![new_addr, contact_id as i32],
+                )?;
+                if let Some((verified_key, verified_key_fingerprint, addr)) = verified {
+                    tx.execute(
+                        "UPDATE acpeerstates SET verified_key=?, verified_key_fingerprint=? \
+                         WHERE addr=?;",
+                        params![verified_key, verified_key_fingerprint, addr],
+                    )?;
+                }
+                tx.commit()?;
+                Ok(())
+            })
+            .await?;
+    }
+
+    info!(
+        context,
+        "Contact {} moved from {} to {}.", contact_id, old_addr, new_addr
+    );
+    context.emit_event(EventType::ContactsChanged(Some(contact_id)));
+    context.emit_event(EventType::MsgsChanged {
+        chat_id: ChatId::new(0),
+        msg_id: MsgId::new(0),
+    });
+    Ok(())
+}
+
 /// Set profile image for a contact.
 ///
 /// The given profile image is expected to be already in the blob directory
@@ -1231,10 +1666,148 @@ fn split_address_book(book: &str) -> Vec<(&str, &str)> {
         .collect()
 }
 
+/// Columns to read contacts from in [import_csv].
+///
+/// Columns are given by their title in the header row,
+/// titles are compared case-insensitively.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvMapping {
+    /// Column containing the name of the contact, if any.
+    pub name: Option<String>,
+
+    /// Column containing the email address of the contact.
+    pub addr: String,
+}
+
+/// Result of [import_csv].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CsvImportReport {
+    /// Number of contacts created or modified.
+    pub modified: usize,
+
+    /// Rows skipped because of an invalid address,
+    /// as line number of the row and the address found.
+    pub invalid: Vec<(usize, String)>,
+}
+
+/// Imports contacts from CSV data as exported by address books.
+///
+/// The first row must be a header naming the columns,
+/// the columns to use are selected by `mapping`.
+/// Rows with invalid or empty addresses are skipped and listed in the report,
+/// all other rows are added like in [Contact::add_address_book].
+pub async fn import_csv(
+    context: &Context,
+    csv: &str,
+    mapping: CsvMapping,
+) -> Result<CsvImportReport> {
+    let mut rows = parse_csv(csv.trim_start_matches('\u{feff}')).into_iter();
+    let header = match rows.next() {
+        Some((_, header)) => header,
+        None => bail!("CSV data has no header row"),
+    };
+    let find_column = |title: &str| {
+        header
+            .iter()
+            .position(|column| column.trim().eq_ignore_ascii_case(title.trim()))
+            .ok_or_else(|| format_err!("CSV data has no column {:?}", title))
+    };
+    let addr_column = find_column(&mapping.addr)?;
+    let name_column = match mapping.name {
+        Some(ref name) => Some(find_column(name)?),
+        None => None,
+    };
+
+    let mut report = CsvImportReport::default();
+    for (line, row) in rows {
+        if row.iter().all(|field| field.trim().is_empty()) {
+            continue;
+        }
+        // Gmail separates multiple addresses of one contact by " ::: "
+        let addr = row
+            .get(addr_column)
+            .and_then(|field| field.split(":::").next())
+            .unwrap_or_default();
+        let name = name_column
+            .and_then(|column| row.get(column))
+            .map_or("", |name| name.as_str());
+
+        let (name, addr) = sanitize_name_and_addr(name.trim(), addr.trim());
+        if !may_be_valid_addr(&addr) {
+            report.invalid.push((line, addr));
+            continue;
+        }
+        let name = normalize_name(name);
+        match Contact::add_or_lookup(context, name, &addr, Origin::AddressBook).await {
+            Err(err) => {
+                warn!(context, "Failed to add address {} from CSV: {}", addr, err);
+                report.invalid.push((line, addr));
+            }
+            Ok((_, modified)) => {
+                if modified != Modifier::None {
+                    report.modified += 1;
+                }
+            }
+        }
+    }
+    if report.modified > 0 {
+        context.emit_event(EventType::ContactsChanged(None));
+    }
+
+    Ok(report)
+}
+
+/// Splits CSV data as described in RFC 4180 into rows of fields.
+///
+/// Each row is returned with the line number it starts at.
+/// Quoted fields may contain separators, line breaks and doubled quotes.
+fn parse_csv(csv: &str) -> Vec<(usize, Vec<String>)> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut row_line = 1;
+    let mut chars = csv.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.is_empty() => quoted = true,
+            '\n' if quoted => {
+                line += 1;
+                field.push(c);
+            }
+            ',' if !quoted => row.push(std::mem::replace(&mut field, String::new())),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' if !quoted => {
+                row.push(std::mem::replace(&mut field, String::new()));
+                rows.push((row_line, std::mem::replace(&mut row, Vec::new())));
+                line += 1;
+                row_line = line;
+            }
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push((row_line, row));
+    }
+    rows
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use crate::dc_receive_imf::dc_receive_imf;
     use crate::test_utils::*;
 
     #[test]
@@ -1269,6 +1842,7 @@ mod tests {
     fn test_normalize_addr() {
         assert_eq!(addr_normalize("mailto:john@doe.com"), "john@doe.com");
         assert_eq!(addr_normalize("  hello@world.com   "), "hello@world.com");
+        assert_eq!(addr_normalize("John <mailto:john@doe.com>"), "john@doe.com");
 
         // normalisation preserves case to allow user-defined spelling.
         // however, case is ignored on addr_cmp()
@@ -1314,6 +1888,69 @@ mod tests {
         assert_eq!(contacts.len(), 0);
     }
 
+    #[async_std::test]
+    async fn test_get_contacts_known_only() {
+        let t = TestContext::new_alice().await;
+        let bob_id = Contact::create(&t.ctx, "bob", "bob@example.net")
+            .await
+            .unwrap();
+
+        // claire only appears in a message from bob
+        dc_receive_imf(
+            &t.ctx,
+            b"From: Bob <bob@example.net>\n\
+              To: alice@example.com, Claire <claire@example.net>\n\
+              Chat-Version: 1.0\n\
+              Subject: Chat: hello\n\
+              Message-ID: <1@example.net>\n\
+              Date: Sun, 22 Mar 2020 22:37:55 +0000\n\
+              \n\
+              hello\n",
+            "INBOX",
+            1,
+            false,
+        )
+        .await
+        .unwrap();
+
+        // alice writes to dave
+        dc_receive_imf(
+            &t.ctx,
+            b"From: alice@example.com\n\
+              To: Dave <dave@example.net>\n\
+              Chat-Version: 1.0\n\
+              Subject: Chat: hello\n\
+              Message-ID: <2@example.com>\n\
+              Date: Sun, 22 Mar 2020 22:38:55 +0000\n\
+              \n\
+              hi\n",
+            "Sent",
+            2,
+            false,
+        )
+        .await
+        .unwrap();
+
+        let claire_id =
+            Contact::lookup_id_by_addr(&t.ctx, "claire@example.net", Origin::Unknown).await;
+        let dave_id = Contact::lookup_id_by_addr(&t.ctx, "dave@example.net", Origin::Unknown).await;
+        assert_ne!(claire_id, 0);
+        assert_ne!(dave_id, 0);
+
+        let all = Contact::get_all(&t.ctx, 0, None::<&str>).await.unwrap();
+        assert_eq!(all, vec![bob_id, claire_id, dave_id]);
+
+        let known = Contact::get_all(&t.ctx, DC_GCL_KNOWN_ONLY as u32, None::<&str>)
+            .await
+            .unwrap();
+        assert_eq!(known, vec![bob_id, dave_id]);
+
+        let known = Contact::get_all(&t.ctx, DC_GCL_KNOWN_ONLY as u32, Some("example"))
+            .await
+            .unwrap();
+        assert_eq!(known, vec![bob_id, dave_id]);
+    }
+
     #[async_std::test]
     async fn test_is_self_addr() -> Result<()> {
         let t = TestContext::new().await;
@@ -1503,6 +2140,66 @@ mod tests {
         assert_eq!(contact.get_display_name(), "bob3");
     }
 
+    #[async_std::test]
+    async fn test_display_name_preference() {
+        let t = TestContext::new().await;
+
+        let (contact_id, _) = Contact::add_or_lookup(
+            &t.ctx,
+            "Robert",
+            "bob@example.org",
+            Origin::IncomingUnknownFrom,
+        )
+        .await
+        .unwrap();
+        Contact::create(&t.ctx, "Bobby", "bob@example.org")
+            .await
+            .unwrap();
+        let contact = Contact::load_from_db(&t.ctx, contact_id).await.unwrap();
+        assert_eq!(contact.get_name(), "Bobby");
+        assert_eq!(contact.get_authname(), "Robert");
+        assert_eq!(contact.get_addr(), "bob@example.org");
+        assert_eq!(contact.get_display_name(), "Bobby");
+
+        t.ctx
+            .set_config(Config::DisplayNamePreference, Some("1"))
+            .await
+            .unwrap();
+        let contact = Contact::load_from_db(&t.ctx, contact_id).await.unwrap();
+        assert_eq!(contact.get_display_name(), "Robert");
+
+        t.ctx
+            .set_config(Config::DisplayNamePreference, Some("0"))
+            .await
+            .unwrap();
+        let contact = Contact::load_from_db(&t.ctx, contact_id).await.unwrap();
+        assert_eq!(contact.get_display_name(), "Bobby");
+    }
+
+    #[async_std::test]
+    async fn test_display_name_preference_fallback() {
+        let t = TestContext::new().await;
+        t.ctx
+            .set_config(Config::DisplayNamePreference, Some("1"))
+            .await
+            .unwrap();
+
+        // without authname, the name set by the user is used
+        let contact_id = Contact::create(&t.ctx, "Claire", "claire@example.org")
+            .await
+            .unwrap();
+        let contact = Contact::load_from_db(&t.ctx, contact_id).await.unwrap();
+        assert_eq!(contact.get_authname(), "");
+        assert_eq!(contact.get_display_name(), "Claire");
+
+        // without any name, the address is used
+        let contact_id = Contact::create(&t.ctx, "", "dave@example.org")
+            .await
+            .unwrap();
+        let contact = Contact::load_from_db(&t.ctx, contact_id).await.unwrap();
+        assert_eq!(contact.get_display_name(), "dave@example.org");
+    }
+
     #[async_std::test]
     async fn test_remote_authnames_create_empty() {
         let t = TestContext::new().await;
@@ -1639,4 +2336,302 @@ mod tests {
             .await
             .is_err());
     }
+
+    async fn receive(
+        t: &TestContext,
+        contact_id: u32,
+        msg_id: &str,
+        date: &str,
+        uid: u32,
+    ) -> Option<i64> {
+        let imf = format!(
+            "From: Bob <bob@example.net>\n\
+             To: alice@example.com\n\
+             Chat-Version: 1.0\n\
+             Subject: Chat: hello\n\
+             Message-ID: <{}>\n\
+             Date: {}\n\
+             \n\
+             hello\n",
+            msg_id, date
+        );
+        dc_receive_imf(&t.ctx, imf.as_bytes(), "INBOX", uid, false)
+            .await
+            .unwrap();
+        Contact::load_from_db(&t.ctx, contact_id)
+            .await
+            .unwrap()
+            .last_seen()
+    }
+
+    #[async_std::test]
+    async fn test_last_seen() {
+        let t = TestContext::new_alice().await;
+        let bob_id = Contact::create(&t.ctx, "bob", "bob@example.net")
+            .await
+            .unwrap();
+        let contact = Contact::load_from_db(&t.ctx, bob_id).await.unwrap();
+        assert_eq!(contact.last_seen(), None);
+
+        assert_eq!(
+            receive(
+                &t,
+                bob_id,
+                "1@example.net",
+                "Sun, 22 Mar 2020 22:37:55 +0000",
+                1
+            )
+            .await,
+            Some(1584916675)
+        );
+
+        // an older message does not move the timestamp backwards
+        assert_eq!(
+            receive(
+                &t,
+                bob_id,
+                "2@example.net",
+                "Sat, 21 Mar 2020 22:37:55 +0000",
+                2
+            )
+            .await,
+            Some(1584916675)
+        );
+
+        assert_eq!(
+            receive(
+                &t,
+                bob_id,
+                "3@example.net",
+                "Mon, 23 Mar 2020 22:37:55 +0000",
+                3
+            )
+            .await,
+            Some(1584916675 + 24 * 60 * 60)
+        );
+
+        // a date in the future is not trusted
+        let last_seen = receive(
+            &t,
+            bob_id,
+            "4@example.net",
+            "Thu, 22 Mar 2091 22:37:55 +0000",
+            4,
+        )
+        .await
+        .unwrap();
+        assert!(last_seen <= time());
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        assert_eq!(
+            parse_csv("a,b\r\n\"x, \"\"y\"\"\",\"multi\nline\"\nlast,"),
+            vec![
+                (1, vec!["a".to_string(), "b".to_string()]),
+                (2, vec!["x, \"y\"".to_string(), "multi\nline".to_string()]),
+                (4, vec!["last".to_string(), "".to_string()]),
+            ]
+        );
+        assert!(parse_csv("").is_empty());
+    }
+
+    #[async_std::test]
+    async fn test_import_csv_gmail() {
+        let t = TestContext::new().await;
+        let csv = "\u{feff}Name,Given Name,Family Name,E-mail 1 - Type,E-mail 1 - Value\r\n\
+                   \"Doe, Jane\",Jane,Doe,* Home,jane@example.org ::: jane@work.example.org\r\n\
+                   Björn,Björn,,* Other,bjoern@example.net\r\n\
+                   No Mail,No,Mail,,\r\n\
+                   Broken,,,* Other,not an address\r\n";
+        let mapping = CsvMapping {
+            name: Some("name".to_string()),
+            addr: "E-mail 1 - Value".to_string(),
+        };
+
+        let report = import_csv(&t.ctx, csv, mapping.clone()).await.unwrap();
+        assert_eq!(report.modified, 2);
+        assert_eq!(
+            report.invalid,
+            vec![(4, "".to_string()), (5, "not an address".to_string())]
+        );
+
+        let id = Contact::lookup_id_by_addr(&t.ctx, "jane@example.org", Origin::Unknown).await;
+        let contact = Contact::load_from_db(&t.ctx, id).await.unwrap();
+        assert_eq!(contact.get_name(), "Doe, Jane");
+        assert_eq!(contact.origin, Origin::AddressBook);
+        let id = Contact::lookup_id_by_addr(&t.ctx, "bjoern@example.net", Origin::Unknown).await;
+        let contact = Contact::load_from_db(&t.ctx, id).await.unwrap();
+        assert_eq!(contact.get_name(), "Björn");
+        assert_eq!(
+            Contact::lookup_id_by_addr(&t.ctx, "jane@work.example.org", Origin::Unknown).await,
+            0
+        );
+
+        // importing again does not modify anything
+        let report = import_csv(&t.ctx, csv, mapping).await.unwrap();
+        assert_eq!(report.modified, 0);
+
+        // unknown columns are an error
+        let mapping = CsvMapping {
+            name: None,
+            addr: "Email".to_string(),
+        };
+        assert!(import_csv(&t.ctx, csv, mapping).await.is_err());
+    }
+
+    /// Receives a chat message from `from` and returns its id.
+    async fn receive_from(t: &TestContext, from: &str, text: &str) -> MsgId {
+        let message_id = format!("{}.{}", text, from);
+        receive_chat_msg(&t.ctx, from, &message_id, text, "")
+            .await
+            .id
+    }
+
+    #[async_std::test]
+    async fn test_merge() {
+        let t = TestContext::new_alice().await;
+        let msg1 = receive_from(&t, "bob@example.net", "one").await;
+        let msg2 = receive_from(&t, "bob@example.org", "two").await;
+        let primary_id =
+            Contact::lookup_id_by_addr(&t.ctx, "bob@example.net", Origin::Unknown).await;
+        let secondary_id =
+            Contact::lookup_id_by_addr(&t.ctx, "bob@example.org", Origin::Unknown).await;
+        Contact::create(&t.ctx, "Bob", "bob@example.org")
+            .await
+            .unwrap();
+        let primary_chat = chat::get_by_contact_id(&t.ctx, primary_id).await.unwrap();
+
+        assert!(merge(&t.ctx, primary_id, primary_id).await.is_err());
+        assert!(merge(&t.ctx, primary_id, DC_CONTACT_ID_SELF).await.is_err());
+        merge(&t.ctx, primary_id, secondary_id).await.unwrap();
+
+        assert!(Contact::load_from_db(&t.ctx, secondary_id).await.is_err());
+        let primary = Contact::load_from_db(&t.ctx, primary_id).await.unwrap();
+        // the primary contact had no name
+        assert_eq!(primary.get_name(), "Bob");
+        assert_eq!(
+            primary.get_addrs(&t.ctx).await,
+            vec!["bob@example.net", "bob@example.org"]
+        );
+        for msg_id in &[msg1, msg2] {
+            let msg = crate::message::Message::load_from_db(&t.ctx, *msg_id)
+                .await
+                .unwrap();
+            assert_eq!(msg.get_from_id(), primary_id);
+            assert_eq!(msg.get_chat_id(), primary_chat);
+        }
+        assert_eq!(
+            chat::get_chat_msgs(&t.ctx, primary_chat, 0, None)
+                .await
+                .len(),
+            2
+        );
+
+        // later messages from the secondary address go to the primary contact
+        assert_eq!(
+            Contact::lookup_id_by_addr(&t.ctx, "BOB@example.org", Origin::Unknown).await,
+            primary_id
+        );
+        let msg3 = receive_from(&t, "bob@example.org", "three").await;
+        let msg = crate::message::Message::load_from_db(&t.ctx, msg3)
+            .await
+            .unwrap();
+        assert_eq!(msg.get_from_id(), primary_id);
+        assert_eq!(msg.get_chat_id(), primary_chat);
+
+        // names set for the secondary address update the primary contact
+        let (contact_id, modified) =
+            Contact::add_or_lookup(&t.ctx, "Robert", "bob@example.org", Origin::ManuallyCreated)
+                .await
+                .unwrap();
+        assert_eq!(contact_id, primary_id);
+        assert_eq!(modified, Modifier::Modified);
+        let primary = Contact::load_from_db(&t.ctx, primary_id).await.unwrap();
+        assert_eq!(primary.get_name(), "Robert");
+        assert_eq!(primary.get_addr(), "bob@example.net");
+        assert_eq!(primary.origin, Origin::ManuallyCreated);
+    }
+
+    /// Sends a text message from `from` to `to_addr` and receives it in `to`.
+    async fn send_text(
+        from: &Context,
+        to: &Context,
+        to_addr: &str,
+        text: &str,
+    ) -> crate::message::Message {
+        let msg = prepare_text_msg(from, to_addr, text).await;
+        send_and_receive(from, &msg, to).await
+    }
+
+    #[async_std::test]
+    async fn test_address_change() {
+        let alice = configured_in_memory_context(alice_keypair()).await;
+        let bob = configured_in_memory_context(bob_keypair()).await;
+        send_text(&alice, &bob, "bob@example.net", "hi bob").await;
+        let msg = send_text(&bob, &alice, "alice@example.com", "hi alice").await;
+        assert!(msg.get_showpadlock());
+        let bob_id = msg.get_from_id();
+
+        let mut peerstate = Peerstate::from_addr(&alice, "bob@example.net")
+            .await
+            .unwrap()
+            .unwrap();
+        let fingerprint = peerstate.public_key_fingerprint.clone().unwrap();
+        assert!(peerstate.set_verified(
+            PeerstateKeyType::PublicKey,
+            &fingerprint,
+            PeerstateVerifiedStatus::BidirectVerified
+        ));
+        peerstate.save_to_db(&alice.sql, false).await.unwrap();
+
+        // bob moves to a new address, keeping his key
+        let mut keypair = bob_keypair();
+        keypair.addr = EmailAddress::new("bob@example.org").unwrap();
+        crate::key::store_self_keypair(&bob, &keypair, crate::key::KeyPairUse::Default)
+            .await
+            .unwrap();
+        bob.set_config(Config::ConfiguredAddr, Some("bob@example.org"))
+            .await
+            .unwrap();
+        let emitter = alice.get_event_emitter();
+        let msg = send_text(&bob, &alice, "alice@example.com", "moved").await;
+        assert!(msg.get_showpadlock());
+        let new_id = msg.get_from_id();
+        assert_ne!(new_id, bob_id);
+        let mut address_changed = None;
+        while let Ok(event) = emitter.try_recv() {
+            if let EventType::ContactAddressChanged {
+                contact_id,
+                new_contact_id,
+            } = event.typ
+            {
+                address_changed = Some((contact_id, new_contact_id));
+            }
+        }
+        assert_eq!(address_changed, Some((bob_id, new_id)));
+
+        accept_address_change(&alice, bob_id, new_id).await.unwrap();
+        assert!(Contact::load_from_db(&alice, new_id).await.is_err());
+        let contact = Contact::load_from_db(&alice, bob_id).await.unwrap();
+        assert_eq!(contact.get_addr(), "bob@example.org");
+        assert_eq!(
+            contact.is_verified(&alice).await,
+            VerifiedStatus::BidirectVerified
+        );
+        assert_eq!(
+            Contact::lookup_id_by_addr(&alice, "bob@example.net", Origin::Unknown).await,
+            bob_id
+        );
+
+        // the change is not offered again
+        let emitter = alice.get_event_emitter();
+        send_text(&bob, &alice, "alice@example.com", "still here").await;
+        while let Ok(event) = emitter.try_recv() {
+            assert!(!matches!(
+                event.typ,
+                EventType::ContactAddressChanged { .. }
+            ));
+        }
+    }
 }
@@ -3,26 +3,49 @@
 use std::collections::{BTreeMap, HashMap};
 use std::ffi::OsString;
 use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 
 use async_std::path::{Path, PathBuf};
+use async_std::prelude::*;
 use async_std::sync::{channel, Arc, Mutex, Receiver, RwLock, Sender};
 use async_std::task;
+use num_traits::FromPrimitive;
+use serde::{Deserialize, Serialize};
 
 use crate::chat::*;
 use crate::config::Config;
 use crate::constants::*;
 use crate::contact::*;
-use crate::dc_tools::duration_to_str;
+use crate::dc_tools::{dc_timestamp_to_str, duration_to_str, time};
 use crate::error::*;
-use crate::events::{Event, EventEmitter, EventType, Events};
+use crate::events::{Event, EventEmitter, EventType, Events, MsgStateBatches};
 use crate::key::{DcKey, SignedPublicKey};
+use crate::log::LogLevel;
 use crate::login_param::LoginParam;
-use crate::message::{self, MsgId};
+use crate::message::{self, MessageState, MsgId};
 use crate::scheduler::Scheduler;
 use crate::securejoin::Bob;
-use crate::sql::Sql;
+use crate::sql::{self, HousekeepingStats, Sql, IN_MEMORY_DBFILE};
+use crate::stock::PluralForm;
 use std::time::SystemTime;
 
+/// Function formatting a unix timestamp for display, see [Context::set_time_formatter].
+pub type TimeFormatter = Box<dyn Fn(i64) -> String + Send + Sync>;
+
+/// Holds the [TimeFormatter] set for a context.
+///
+/// The formatter is called without holding the lock,
+/// so it may use the context itself.
+#[derive(Default)]
+pub(crate) struct TimeFormatterCell(std::sync::RwLock<Option<Arc<TimeFormatter>>>);
+
+impl std::fmt::Debug for TimeFormatterCell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let is_set = self.0.read().map(|f| f.is_some()).unwrap_or_default();
+        f.debug_tuple("TimeFormatterCell").field(&is_set).finish()
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Context {
     pub(crate) inner: Arc<InnerContext>,
@@ -53,8 +76,27 @@ pub struct InnerContext {
     pub(crate) oauth2_mutex: Mutex<()>,
     /// Mutex to prevent a race condition when a "your pw is wrong" warning is sent, resulting in multiple messeges being sent.
     pub(crate) wrong_pw_warning_mutex: Mutex<()>,
+    /// Message currently handed to the SMTP server, it cannot be cancelled anymore.
+    pub(crate) smtp_sending_msg: Mutex<Option<MsgId>>,
     pub(crate) translated_stockstrings: RwLock<HashMap<usize, String>>,
+    /// Plural forms of stock strings by [StockMessage] id and [PluralForm].
+    ///
+    /// [StockMessage]: crate::stock::StockMessage
+    pub(crate) translated_plurals: RwLock<HashMap<(usize, PluralForm), String>>,
     pub(crate) events: Events,
+    /// Message state batches opened by [Context::batch_msg_states].
+    pub(crate) msg_state_batches: std::sync::Mutex<MsgStateBatches>,
+    /// Minimum [LogLevel] of log messages that are emitted, stored as `u8`.
+    pub(crate) log_level: AtomicU8,
+    /// If set, log messages are emitted as [EventType::Log].
+    pub(crate) structured_logging: AtomicBool,
+    /// Passwords and tokens masked in log messages, by config key.
+    pub(crate) secrets: std::sync::RwLock<HashMap<String, String>>,
+    /// Sizes of files in the blob directory by absolute path,
+    /// blobs are not modified once written.
+    pub(crate) blob_sizes: RwLock<HashMap<String, u64>>,
+    /// Formats user-visible timestamps, see [Context::set_time_formatter].
+    pub(crate) time_formatter: TimeFormatterCell,
 
     pub(crate) scheduler: RwLock<Scheduler>,
     pub(crate) ephemeral_task: RwLock<Option<task::JoinHandle<()>>>,
@@ -65,6 +107,20 @@ pub struct InnerContext {
     creation_time: SystemTime,
 }
 
+/// Disk space used by an account, see [Context::get_storage_usage].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageUsage {
+    /// Size of the database file in bytes.
+    pub db_size: u64,
+
+    /// Size of all files in the blob directory in bytes,
+    /// including files not referenced by any message.
+    pub blobdir_size: u64,
+
+    /// Usage of the single chats, largest chats first.
+    pub chats: Vec<ChatStorageUsage>,
+}
+
 #[derive(Debug)]
 pub struct RunningState {
     pub ongoing_running: bool,
@@ -113,7 +169,26 @@ impl Context {
             "Blobdir does not exist: {}",
             blobdir.display()
         );
+        Context::open(os_name, dbfile, blobdir, id).await
+    }
+
+    /// Creates new context backed by an in-memory database.
+    ///
+    /// Nothing is written to disk and all data is lost when the context is dropped.
+    /// As there is no blob directory, features that need files,
+    /// like attachments, sending messages via SMTP and import/export, are not available.
+    ///
+    /// The database is served by a single connection, so database operations
+    /// are processed one after another.
+    pub async fn new_in_memory(os_name: String, id: u32) -> Result<Context> {
+        // the blobdir is never created, so any attempt to write a file fails
+        let blobdir = std::env::temp_dir()
+            .join(format!("deltachat-in-memory-{}-blobs", id))
+            .into();
+        Context::open(os_name, IN_MEMORY_DBFILE.into(), blobdir, id).await
+    }
 
+    async fn open(os_name: String, dbfile: PathBuf, blobdir: PathBuf, id: u32) -> Result<Context> {
         let inner = InnerContext {
             id,
             blobdir,
@@ -126,8 +201,16 @@ impl Context {
             generating_key_mutex: Mutex::new(()),
             oauth2_mutex: Mutex::new(()),
             wrong_pw_warning_mutex: Mutex::new(()),
+            smtp_sending_msg: Mutex::new(None),
             translated_stockstrings: RwLock::new(HashMap::new()),
+            translated_plurals: RwLock::new(HashMap::new()),
             events: Events::default(),
+            msg_state_batches: std::sync::Mutex::new(MsgStateBatches::default()),
+            log_level: AtomicU8::new(LogLevel::default() as u8),
+            structured_logging: AtomicBool::new(false),
+            secrets: std::sync::RwLock::new(HashMap::new()),
+            blob_sizes: RwLock::new(HashMap::new()),
+            time_formatter: Default::default(),
             scheduler: RwLock::new(Scheduler::Stopped),
             ephemeral_task: RwLock::new(None),
             creation_time: std::time::SystemTime::now(),
@@ -140,6 +223,7 @@ impl Context {
             ctx.sql.open(&ctx, &ctx.dbfile, false).await,
             "Failed opening sqlite database"
         );
+        crate::log::load_secrets(&ctx).await;
 
         Ok(ctx)
     }
@@ -192,6 +276,11 @@ impl Context {
         self.blobdir.as_path()
     }
 
+    /// Returns true if the context was created by [Context::new_in_memory].
+    pub fn is_in_memory(&self) -> bool {
+        self.dbfile.as_os_str() == IN_MEMORY_DBFILE
+    }
+
     /// Emits a single event.
     pub fn emit_event(&self, event: EventType) {
         self.events.emit(Event {
@@ -200,6 +289,58 @@ impl Context {
         });
     }
 
+    /// Sets the minimum level of log messages emitted as events.
+    ///
+    /// Log messages below this level are discarded before they are formatted.
+    pub fn set_log_level(&self, level: LogLevel) {
+        self.log_level.store(level as u8, Ordering::Relaxed);
+    }
+
+    /// Returns the minimum level of log messages emitted as events.
+    pub fn get_log_level(&self) -> LogLevel {
+        LogLevel::from_u8(self.log_level.load(Ordering::Relaxed)).unwrap_or_default()
+    }
+
+    /// Returns true if log messages of the given level are emitted.
+    pub fn is_log_enabled(&self, level: LogLevel) -> bool {
+        level >= self.get_log_level()
+    }
+
+    /// Enables or disables structured logging.
+    ///
+    /// If enabled, `info!` and `warn!` emit [EventType::Log] events
+    /// carrying the key-value fields separately.
+    /// Otherwise the fields are appended to the message
+    /// of plain [EventType::Info] and [EventType::Warning] events.
+    pub fn set_structured_logging(&self, enabled: bool) {
+        self.structured_logging.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Returns true if structured logging is enabled.
+    pub fn is_structured_logging(&self) -> bool {
+        self.structured_logging.load(Ordering::Relaxed)
+    }
+
+    /// Sets the function used to format timestamps in user-visible strings
+    /// generated by the core, eg. in system messages or in the message info.
+    ///
+    /// This allows embedders to respect the locale and the 12h/24h preference
+    /// of the user.  Passing `None` restores the default format
+    /// `YYYY.MM.DD hh:mm:ss` in local time.
+    pub fn set_time_formatter(&self, formatter: Option<TimeFormatter>) {
+        *self.time_formatter.0.write().unwrap() = formatter.map(Arc::new);
+    }
+
+    /// Formats a unix timestamp for display using the formatter set by
+    /// [Context::set_time_formatter].
+    pub fn format_timestamp(&self, timestamp: i64) -> String {
+        let formatter = self.time_formatter.0.read().unwrap().clone();
+        match formatter {
+            Some(formatter) => formatter(timestamp),
+            None => dc_timestamp_to_str(timestamp),
+        }
+    }
+
     /// Get the next queued event.
     pub fn get_event_emitter(&self) -> EventEmitter {
         self.events.get_emitter()
@@ -310,6 +451,7 @@ impl Context {
         let sentbox_watch = self.get_config_int(Config::SentboxWatch).await;
         let mvbox_watch = self.get_config_int(Config::MvboxWatch).await;
         let mvbox_move = self.get_config_int(Config::MvboxMove).await;
+        let only_fetch_inbox = self.get_config_int(Config::OnlyFetchInbox).await;
         let folders_configured = self
             .sql
             .get_raw_config_int(self, "folders_configured")
@@ -348,6 +490,7 @@ impl Context {
         res.insert("sentbox_watch", sentbox_watch.to_string());
         res.insert("mvbox_watch", mvbox_watch.to_string());
         res.insert("mvbox_move", mvbox_move.to_string());
+        res.insert("only_fetch_inbox", only_fetch_inbox.to_string());
         res.insert("folders_configured", folders_configured.to_string());
         res.insert("configured_sentbox_folder", configured_sentbox_folder);
         res.insert("configured_mvbox_folder", configured_mvbox_folder);
@@ -370,6 +513,132 @@ impl Context {
         res
     }
 
+    /// Returns anonymized statistics the user may send to the developers.
+    ///
+    /// The report contains only counts and settings,
+    /// no addresses, names or message content.
+    /// Nothing is sent automatically.
+    pub async fn get_self_report(&self) -> String {
+        let chats = get_chat_cnt(self).await;
+        let groups: isize = self
+            .sql
+            .query_get_value(
+                self,
+                "SELECT COUNT(*) FROM chats WHERE id>9 AND blocked=0 AND type IN (?, ?);",
+                paramsv![Chattype::Group, Chattype::VerifiedGroup],
+            )
+            .await
+            .unwrap_or_default();
+        let real_msgs = message::get_real_msg_cnt(self).await;
+        let contacts = Contact::get_real_cnt(self).await;
+        let pub_key_cnt: isize = self
+            .sql
+            .query_get_value(self, "SELECT COUNT(*) FROM acpeerstates;", paramsv![])
+            .await
+            .unwrap_or_default();
+        let has_own_key = SignedPublicKey::load_self(self).await.is_ok();
+        let provider = match self.get_config(Config::ConfiguredAddr).await {
+            Some(addr) => match crate::provider::get_provider_info(&addr) {
+                Some(provider) => provider.overview_page.to_string(),
+                None => "unknown".to_string(),
+            },
+            None => "<unset>".to_string(),
+        };
+
+        let mut res = get_info();
+        res.insert("os_name", self.os_name.clone().unwrap_or_default());
+        res.insert("provider", provider);
+        res.insert("number_of_chats", chats.to_string());
+        res.insert("number_of_groups", groups.to_string());
+        res.insert("number_of_chat_messages", real_msgs.to_string());
+        res.insert("number_of_contacts", contacts.to_string());
+        res.insert("public_key_count", pub_key_cnt.to_string());
+        res.insert("has_own_key", has_own_key.to_string());
+        let mut report = String::from("Self report\n\n");
+        for (key, value) in res {
+            report += &format!("{}={}\n", key, value);
+        }
+        for key in &[
+            Config::Configured,
+            Config::E2eeEnabled,
+            Config::MdnsEnabled,
+            Config::BccSelf,
+            Config::MvboxMove,
+            Config::ShowEmails,
+            Config::DeleteDeviceAfter,
+            Config::DeleteServerAfter,
+        ] {
+            let value = self.get_config_int(*key).await;
+            report += &format!("{}={}\n", key.as_ref(), value);
+        }
+        report
+    }
+
+    /// Returns the disk space used by the database, the blob directory and the single chats.
+    pub async fn get_storage_usage(&self) -> Result<StorageUsage> {
+        let db_size = if self.is_in_memory() {
+            0
+        } else {
+            async_std::fs::metadata(self.get_dbfile()).await?.len()
+        };
+
+        let mut blobdir_size = 0;
+        let mut dir = async_std::fs::read_dir(self.get_blobdir()).await?;
+        while let Some(entry) = dir.next().await {
+            blobdir_size += self.get_blob_size(&entry?.path()).await;
+        }
+
+        let chat_ids = self
+            .sql
+            .query_map(
+                "SELECT id FROM chats WHERE id>?;",
+                paramsv![DC_CHAT_ID_LAST_SPECIAL],
+                |row| row.get::<_, ChatId>(0),
+                |ids| {
+                    ids.collect::<std::result::Result<Vec<_>, _>>()
+                        .map_err(Into::into)
+                },
+            )
+            .await?;
+        let mut chats = Vec::with_capacity(chat_ids.len());
+        for chat_id in chat_ids {
+            chats.push(get_storage_usage(self, chat_id).await?);
+        }
+        chats.sort_by(|a, b| b.total_bytes().cmp(&a.total_bytes()));
+
+        Ok(StorageUsage {
+            db_size,
+            blobdir_size,
+            chats,
+        })
+    }
+
+    /// Deletes files in the blob directory not referenced by any message, chat,
+    /// contact or config value and prunes outdated database entries.
+    ///
+    /// Unreferenced files created within the last hour are kept,
+    /// they may belong to a message that is just created.
+    /// Housekeeping is also done once a day by the IO scheduler.
+    pub async fn housekeeping(&self) -> HousekeepingStats {
+        sql::housekeeping(self).await
+    }
+
+    /// Returns the size of a file in the blob directory, 0 if it does not exist.
+    pub(crate) async fn get_blob_size(&self, path: &Path) -> u64 {
+        let key = path.to_string_lossy().to_string();
+        if let Some(size) = self.blob_sizes.read().await.get(&key) {
+            return *size;
+        }
+        match async_std::fs::metadata(path).await {
+            Ok(metadata) if metadata.is_file() => {
+                let size = metadata.len();
+                self.blob_sizes.write().await.insert(key, size);
+                size
+            }
+            _ => 0,
+        }
+    }
+
     pub async fn get_fresh_msgs(&self) -> Vec<MsgId> {
         let show_deaddrop: i32 = 0;
         self.sql
@@ -402,6 +671,88 @@ impl Context {
             .unwrap_or_default()
     }
 
+    /// Returns the starred messages of all chats, newest first.
+    ///
+    /// Messages of blocked contacts and deleted messages are skipped.
+    pub async fn get_starred_messages(&self) -> Vec<MsgId> {
+        self.sql
+            .query_map(
+                "SELECT m.id
+                   FROM msgs m
+                   LEFT JOIN contacts ct
+                          ON m.from_id=ct.id
+                  WHERE m.starred=1
+                    AND m.hidden=0
+                    AND m.chat_id>?
+                    AND ct.blocked=0
+                  ORDER BY m.timestamp DESC,m.id DESC;",
+                paramsv![DC_CHAT_ID_LAST_SPECIAL],
+                |row| row.get::<_, MsgId>(0),
+                |rows| {
+                    let mut ret = Vec::new();
+                    for row in rows {
+                        ret.push(row?);
+                    }
+                    Ok(ret)
+                },
+            )
+            .await
+            .unwrap_or_default()
+    }
+
+    /// Returns the number of fresh messages in each chat.
+    ///
+    /// All chats are counted in a single query, chats without fresh
+    /// messages are not contained in the map.  If `exclude_muted` is set,
+    /// fresh messages in muted chats are not counted.
+    pub async fn get_fresh_msg_count_per_chat(
+        &self,
+        exclude_muted: bool,
+    ) -> HashMap<ChatId, usize> {
+        self.sql
+            .query_map(
+                concat!(
+                    "SELECT m.chat_id, COUNT(*)",
+                    " FROM msgs m",
+                    " LEFT JOIN contacts ct",
+                    "        ON m.from_id=ct.id",
+                    " LEFT JOIN chats c",
+                    "        ON m.chat_id=c.id",
+                    " WHERE m.state=?",
+                    "   AND m.hidden=0",
+                    "   AND m.chat_id>?",
+                    "   AND ct.blocked=0",
+                    "   AND c.blocked=0",
+                    "   AND (?=0 OR c.muted_until=0 OR (c.muted_until>0 AND c.muted_until<=?))",
+                    " GROUP BY m.chat_id;"
+                ),
+                paramsv![
+                    MessageState::InFresh,
+                    DC_CHAT_ID_LAST_SPECIAL,
+                    exclude_muted,
+                    time()
+                ],
+                |row| Ok((row.get::<_, ChatId>(0)?, row.get::<_, i64>(1)? as usize)),
+                |rows| {
+                    rows.collect::<std::result::Result<HashMap<_, _>, _>>()
+                        .map_err(Into::into)
+                },
+            )
+            .await
+            .unwrap_or_default()
+    }
+
+    /// Returns the number of fresh messages in all chats.
+    ///
+    /// This is the number typically shown as a badge on the app icon.
+    /// If `exclude_muted` is set, fresh messages in muted chats are not counted.
+    pub async fn get_fresh_msg_count_total(&self, exclude_muted: bool) -> usize {
+        self.get_fresh_msg_count_per_chat(exclude_muted)
+            .await
+            .values()
+            .sum()
+    }
+
     #[allow(non_snake_case)]
     pub async fn search_msgs(&self, chat_id: ChatId, query: impl AsRef<str>) -> Vec<MsgId> {
         let real_query = query.as_ref().trim();
@@ -472,6 +823,24 @@ impl Context {
             == Some(folder_name.as_ref().to_string())
     }
 
+    /// Returns true if the folder enabled by the `watch` option,
+    /// eg. `Config::MvboxWatch`, should be watched.
+    ///
+    /// If `Config::OnlyFetchInbox` is set, only the inbox is watched.
+    pub(crate) async fn should_watch_folder(&self, watch: Config) -> bool {
+        if watch != Config::InboxWatch && self.get_config_bool(Config::OnlyFetchInbox).await {
+            return false;
+        }
+        self.get_config_bool(watch).await
+    }
+
+    /// Returns true if the DeltaChat folder should be created if it does not exist.
+    pub(crate) async fn should_create_mvbox(&self) -> bool {
+        !self.get_config_bool(Config::OnlyFetchInbox).await
+            && (self.get_config_bool(Config::MvboxWatch).await
+                || self.get_config_bool(Config::MvboxMove).await)
+    }
+
     pub fn derive_blobdir(dbfile: &PathBuf) -> PathBuf {
         let mut blob_fname = OsString::new();
         blob_fname.push(dbfile.file_name().unwrap_or_default());
@@ -516,6 +885,8 @@ pub fn get_version_str() -> &'static str {
 mod tests {
     use super::*;
 
+    use crate::dc_tools::dc_delete_file;
+    use crate::message::Message;
     use crate::test_utils::*;
 
     #[async_std::test]
@@ -534,6 +905,112 @@ mod tests {
         assert!(fresh.is_empty())
     }
 
+    async fn add_fresh_msgs(t: &TestContext, from: &str, cnt: usize) {
+        for i in 0..cnt {
+            let rfc724_mid = format!("{}.{}", i, from);
+            receive_chat_msg(&t.ctx, from, &rfc724_mid, "hi", "").await;
+        }
+    }
+
+    #[async_std::test]
+    async fn test_get_fresh_msg_count() {
+        let t = TestContext::new_alice().await;
+        let mut chat_ids = Vec::new();
+        for (addr, cnt) in &[
+            ("bob@example.net", 1),
+            ("claire@example.net", 3),
+            ("dave@example.net", 5),
+            ("eve@example.net", 0),
+        ] {
+            let contact_id = Contact::create(&t.ctx, "", addr).await.unwrap();
+            let chat_id = create_by_contact_id(&t.ctx, contact_id).await.unwrap();
+            add_fresh_msgs(&t, addr, *cnt).await;
+            chat_ids.push(chat_id);
+        }
+        assert_eq!(t.ctx.get_fresh_msg_count_total(false).await, 9);
+        assert_eq!(t.ctx.get_fresh_msg_count_total(true).await, 9);
+
+        let per_chat = t.ctx.get_fresh_msg_count_per_chat(false).await;
+        assert_eq!(per_chat.len(), 3);
+        assert_eq!(per_chat.get(&chat_ids[0]), Some(&1));
+        assert_eq!(per_chat.get(&chat_ids[1]), Some(&3));
+        assert_eq!(per_chat.get(&chat_ids[2]), Some(&5));
+        assert_eq!(per_chat.get(&chat_ids[3]), None);
+        for chat_id in &chat_ids {
+            assert_eq!(
+                per_chat.get(chat_id).cloned().unwrap_or_default(),
+                chat_id.get_fresh_msg_cnt(&t.ctx).await
+            );
+        }
+
+        // muted chats are only counted if requested
+        set_mute_duration(&t.ctx, chat_ids[2], MuteDuration::Forever)
+            .await
+            .unwrap();
+        assert_eq!(t.ctx.get_fresh_msg_count_total(false).await, 9);
+        assert_eq!(t.ctx.get_fresh_msg_count_total(true).await, 4);
+        let per_chat = t.ctx.get_fresh_msg_count_per_chat(true).await;
+        assert_eq!(per_chat.get(&chat_ids[2]), None);
+
+        // expired mutes do not exclude the chat
+        set_mute_duration(
+            &t.ctx,
+            chat_ids[1],
+            MuteDuration::Until(SystemTime::now() - std::time::Duration::from_secs(10)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(t.ctx.get_fresh_msg_count_total(true).await, 4);
+    }
+
+    #[async_std::test]
+    async fn test_get_storage_usage() {
+        let t = TestContext::new_alice().await;
+        let contact_id = Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = create_by_contact_id(&t.ctx, contact_id).await.unwrap();
+        let empty_chat_id = create_group_chat(&t.ctx, VerifiedStatus::Unverified, "empty")
+            .await
+            .unwrap();
+
+        for (name, size) in &[("a.bin", 1000), ("b.bin", 2500)] {
+            let file = t.ctx.get_blobdir().join(name);
+            async_std::fs::write(&file, vec![0u8; *size]).await.unwrap();
+            let mut msg = Message::new(Viewtype::File);
+            msg.set_file(file.to_str().unwrap(), None);
+            prepare_msg(&t.ctx, chat_id, &mut msg).await.unwrap();
+        }
+        // a forwarded attachment is counted once
+        let mut msg = Message::new(Viewtype::File);
+        let file = t.ctx.get_blobdir().join("a.bin");
+        msg.set_file(file.to_str().unwrap(), None);
+        prepare_msg(&t.ctx, chat_id, &mut msg).await.unwrap();
+
+        let usage = get_storage_usage(&t.ctx, chat_id).await.unwrap();
+        assert_eq!(usage.msg_count, 3);
+        assert_eq!(usage.blob_bytes, 3500);
+        assert!(usage.db_bytes > 0);
+        assert_eq!(usage.total_bytes(), usage.db_bytes + 3500);
+
+        let usage = t.ctx.get_storage_usage().await.unwrap();
+        assert!(usage.db_size > 0);
+        assert!(usage.blobdir_size >= 3500);
+        assert_eq!(usage.chats[0].chat_id, chat_id);
+        assert_eq!(usage.chats[0].blob_bytes, 3500);
+        let empty = usage
+            .chats
+            .iter()
+            .find(|chat| chat.chat_id == empty_chat_id)
+            .unwrap();
+        assert_eq!(empty.total_bytes(), 0);
+
+        // deleted blobs are not taken from the cache
+        assert!(dc_delete_file(&t.ctx, "$BLOBDIR/b.bin").await);
+        let usage = get_storage_usage(&t.ctx, chat_id).await.unwrap();
+        assert_eq!(usage.blob_bytes, 1000);
+    }
+
     #[async_std::test]
     async fn test_blobdir_exists() {
         let tmp = tempfile::tempdir().unwrap();
@@ -586,12 +1063,193 @@ mod tests {
         assert!(res.is_err());
     }
 
+    #[async_std::test]
+    async fn test_new_in_memory() {
+        use crate::imex::{imex, ImexMode};
+
+        let alice = configured_in_memory_context(alice_keypair()).await;
+        let bob = configured_in_memory_context(bob_keypair()).await;
+        assert!(alice.is_in_memory());
+
+        let msg = prepare_text_msg(&bob, "alice@example.com", "hi alice").await;
+        let received = send_and_receive(&bob, &msg, &alice).await;
+        assert_eq!(received.get_text(), Some("hi alice".to_string()));
+
+        // nothing was written to disk
+        assert!(!alice.get_blobdir().exists().await);
+        assert!(!alice.get_dbfile().exists().await);
+        let tmp = tempfile::tempdir().unwrap();
+        assert!(imex(&alice, ImexMode::ExportBackup, tmp.path().to_str())
+            .await
+            .is_err());
+    }
+
+    #[async_std::test]
+    async fn test_in_memory_concurrent_access() {
+        let ctx = Context::new_in_memory("FakeOS".into(), 1).await.unwrap();
+
+        // the operations share the single connection and see the same database
+        let tasks: Vec<_> = (0..20)
+            .map(|i| {
+                let ctx = ctx.clone();
+                async_std::task::spawn(async move {
+                    let key = format!("test.{}", i);
+                    ctx.sql
+                        .set_raw_config(&ctx, &key, Some(&i.to_string()))
+                        .await
+                        .unwrap();
+                    ctx.sql.get_raw_config(&ctx, &key).await
+                })
+            })
+            .collect();
+        for (i, task) in tasks.into_iter().enumerate() {
+            assert_eq!(task.await, Some(i.to_string()));
+        }
+        for i in 0..20 {
+            let key = format!("test.{}", i);
+            assert_eq!(
+                ctx.sql.get_raw_config(&ctx, &key).await,
+                Some(i.to_string())
+            );
+        }
+    }
+
     #[async_std::test]
     async fn no_crashes_on_context_deref() {
         let t = TestContext::new().await;
         std::mem::drop(t.ctx);
     }
 
+    #[async_std::test]
+    async fn test_log_level() {
+        /// Records whether it has been formatted.
+        struct Probe<'a>(&'a AtomicBool);
+
+        impl std::fmt::Display for Probe<'_> {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                self.0.store(true, Ordering::Relaxed);
+                write!(f, "log level probe")
+            }
+        }
+
+        let t = TestContext::new().await;
+        let emitter = t.ctx.get_event_emitter();
+        assert_eq!(t.ctx.get_log_level(), LogLevel::Debug);
+
+        t.ctx.set_log_level(LogLevel::Warn);
+        assert_eq!(t.ctx.get_log_level(), LogLevel::Warn);
+        let formatted = AtomicBool::new(false);
+        info!(t.ctx, "{}", Probe(&formatted));
+        assert!(!formatted.load(Ordering::Relaxed));
+        warn!(t.ctx, "{}", Probe(&formatted));
+        assert!(formatted.load(Ordering::Relaxed));
+
+        let mut infos = 0;
+        let mut warnings = 0;
+        while let Ok(event) = emitter.try_recv() {
+            match event.typ {
+                EventType::Info(msg) if msg.contains("log level probe") => infos += 1,
+                EventType::Warning(msg) if msg.contains("log level probe") => warnings += 1,
+                _ => {}
+            }
+        }
+        assert_eq!(infos, 0);
+        assert_eq!(warnings, 1);
+    }
+
+    #[async_std::test]
+    async fn test_structured_logging() {
+        let t = TestContext::new().await;
+        let emitter = t.ctx.get_event_emitter();
+        let host = "imap.example.org";
+        let port = 993;
+
+        info!(t.ctx, "connecting {}", "now"; server = host, port = port);
+        t.ctx.set_structured_logging(true);
+        warn!(t.ctx, "connect failed"; server = host, port = port);
+        info!(t.ctx, "plain message");
+
+        let mut events = Vec::new();
+        while let Ok(event) = emitter.try_recv() {
+            events.push(event.typ);
+        }
+        assert!(events.iter().any(|event| match event {
+            EventType::Info(msg) =>
+                msg.ends_with("connecting now server=imap.example.org port=993"),
+            _ => false,
+        }));
+        assert!(events.iter().any(|event| match event {
+            EventType::Log { level, msg, fields } => {
+                *level == LogLevel::Warn
+                    && msg.ends_with("connect failed")
+                    && fields
+                        == &vec![
+                            ("server".to_string(), "imap.example.org".to_string()),
+                            ("port".to_string(), "993".to_string()),
+                        ]
+            }
+            _ => false,
+        }));
+        assert!(events.iter().any(|event| match event {
+            EventType::Log { level, msg, fields } => {
+                *level == LogLevel::Info && msg.ends_with("plain message") && fields.is_empty()
+            }
+            _ => false,
+        }));
+    }
+
+    #[async_std::test]
+    async fn test_log_redaction() {
+        let t = TestContext::new().await;
+        t.ctx
+            .set_config(Config::MailPw, Some("hunter2-secret"))
+            .await
+            .unwrap();
+        let emitter = t.ctx.get_event_emitter();
+
+        info!(t.ctx, "login with {}", "hunter2-secret");
+        warn!(t.ctx, "retrying"; pw = "hunter2-secret");
+        error!(t.ctx, "failed: password=\"other secret\" token: abc123");
+
+        let mut events = Vec::new();
+        while let Ok(event) = emitter.try_recv() {
+            events.push(event.typ);
+        }
+        assert!(events.iter().any(|event| match event {
+            EventType::Info(msg) => msg.ends_with("login with ***"),
+            _ => false,
+        }));
+        assert!(events.iter().any(|event| match event {
+            EventType::Warning(msg) => msg.ends_with("retrying pw=***"),
+            _ => false,
+        }));
+        assert!(events.iter().any(|event| match event {
+            EventType::Error(msg) => msg == "failed: password=*** token: ***",
+            _ => false,
+        }));
+
+        // the password is remembered when the database is opened again
+        t.ctx.secrets.write().unwrap().clear();
+        crate::log::load_secrets(&t.ctx).await;
+        info!(t.ctx, "login with {}", "hunter2-secret");
+        assert!(events_contain_info(&emitter, "login with ***"));
+
+        // secrets are forgotten when unset
+        t.ctx.set_config(Config::MailPw, None).await.unwrap();
+        info!(t.ctx, "login with {}", "hunter2-secret");
+        assert!(events_contain_info(&emitter, "login with hunter2-secret"));
+    }
+
+    fn events_contain_info(emitter: &EventEmitter, suffix: &str) -> bool {
+        let mut found = false;
+        while let Ok(event) = emitter.try_recv() {
+            if let EventType::Info(msg) = event.typ {
+                found |= msg.ends_with(suffix);
+            }
+        }
+        found
+    }
+
     #[async_std::test]
     async fn test_get_info() {
         let t = TestContext::new().await;
@@ -600,6 +1258,27 @@ mod tests {
         assert!(info.get("database_dir").is_some());
     }
 
+    #[async_std::test]
+    async fn test_get_self_report() {
+        let t = TestContext::new_alice().await;
+        let contact_id = Contact::create(&t.ctx, "Bob", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = create_by_contact_id(&t.ctx, contact_id).await.unwrap();
+        send_text_msg(&t.ctx, chat_id, "very secret text".to_string())
+            .await
+            .unwrap();
+
+        let report = t.ctx.get_self_report().await;
+        assert!(report.contains("number_of_groups=0\n"));
+        assert!(report.contains("number_of_contacts=1\n"));
+        assert!(report.contains("number_of_chat_messages=1\n"));
+        assert!(report.contains("has_own_key=true\n"));
+        assert!(!report.contains("secret"));
+        assert!(!report.contains("example"));
+        assert!(!report.contains("Bob"));
+    }
+
     #[test]
     fn test_get_info_no_context() {
         let info = get_info();
@@ -607,4 +1286,40 @@ mod tests {
         assert!(info.get("database_dir").is_none());
         assert_eq!(info.get("level").unwrap(), "awesome");
     }
+
+    #[async_std::test]
+    async fn test_only_fetch_inbox() {
+        let t = TestContext::new().await;
+        assert!(t.ctx.should_watch_folder(Config::InboxWatch).await);
+        assert!(t.ctx.should_watch_folder(Config::MvboxWatch).await);
+        assert!(t.ctx.should_watch_folder(Config::SentboxWatch).await);
+        assert!(t.ctx.should_create_mvbox().await);
+
+        t.ctx
+            .set_config(Config::OnlyFetchInbox, Some("1"))
+            .await
+            .unwrap();
+        assert!(t.ctx.should_watch_folder(Config::InboxWatch).await);
+        assert!(!t.ctx.should_watch_folder(Config::MvboxWatch).await);
+        assert!(!t.ctx.should_watch_folder(Config::SentboxWatch).await);
+        assert!(!t.ctx.should_create_mvbox().await);
+
+        // messages are never moved away from the inbox
+        t.ctx
+            .set_config(Config::ConfiguredMvboxFolder, Some("DeltaChat"))
+            .await
+            .unwrap();
+        assert!(!MsgId::new(DC_MSG_ID_LAST_SPECIAL + 1)
+            .needs_move(&t.ctx, "INBOX")
+            .await
+            .unwrap());
+    }
+
+    #[async_std::test]
+    async fn test_fetch_folder_not_started() {
+        let t = TestContext::new_alice().await;
+        assert!(t.ctx.fetch_folder("").await.is_err());
+        // without started IO there is no connection to fetch with
+        assert!(t.ctx.fetch_folder("INBOX").await.is_err());
+    }
 }
@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use itertools::join;
 use num_traits::FromPrimitive;
 use sha2::{Digest, Sha256};
@@ -14,7 +16,10 @@ use crate::ephemeral::{stock_ephemeral_timer_changed, Timer as EphemeralTimer};
 use crate::error::{bail, ensure, format_err, Result};
 use crate::events::EventType;
 use crate::headerdef::HeaderDef;
+use crate::html;
 use crate::job::{self, Action};
+use crate::key::Fingerprint;
+use crate::mailinglist::ListHeaders;
 use crate::message::{self, MessageState, MessengerMessage, MsgId};
 use crate::mimeparser::*;
 use crate::param::*;
@@ -23,7 +28,7 @@ use crate::securejoin::{
     self, handle_securejoin_handshake, observe_securejoin_on_other_device, BobStatus,
 };
 use crate::stock::StockMessage;
-use crate::{contact, location};
+use crate::{contact, location, sync};
 
 // IndexSet is like HashSet but maintains order of insertion
 type ContactIds = indexmap::IndexSet<u32>;
@@ -46,11 +51,79 @@ pub async fn dc_receive_imf(
     server_uid: u32,
     seen: bool,
 ) -> Result<()> {
+    dc_receive_imf_inner(context, imf_raw, server_folder.as_ref(), server_uid, seen).await?;
+    Ok(())
+}
+
+/// Imports a standalone message, eg. read from an `.eml` file,
+/// using the same pipeline as messages received via IMAP.
+///
+/// If `assign_to_chat` is set, the created messages are moved to this chat
+/// instead of the chat the message would be assigned to normally;
+/// messages that are not shown in any chat, eg. read receipts, are not moved.
+/// The chat must exist.
+/// Returns the ids of the created messages, the list is empty
+/// if the message could not be parsed or was already imported before.
+pub async fn receive_eml(
+    context: &Context,
+    eml: &[u8],
+    assign_to_chat: Option<ChatId>,
+) -> Result<Vec<MsgId>> {
+    if let Some(assign_to_chat) = assign_to_chat {
+        ensure!(
+            !assign_to_chat.is_special(),
+            "Cannot import to special chat"
+        );
+        // fails if the chat does not exist
+        Chat::load_from_db(context, assign_to_chat).await?;
+    }
+    let created_db_entries = dc_receive_imf_inner(context, eml, "", 0, true).await?;
+
+    if let Some(assign_to_chat) = assign_to_chat {
+        for (chat_id, msg_id) in created_db_entries
+            .iter()
+            .filter(|(chat_id, _)| !chat_id.is_special())
+        {
+            context
+                .sql
+                .execute(
+                    "UPDATE msgs SET chat_id=? WHERE id=?;",
+                    paramsv![assign_to_chat, *msg_id],
+                )
+                .await?;
+            context.emit_event(EventType::MsgsChanged {
+                chat_id: *chat_id,
+                msg_id: MsgId::new(0),
+            });
+            context.emit_event(EventType::MsgsChanged {
+                chat_id: assign_to_chat,
+                msg_id: *msg_id,
+            });
+        }
+    }
+
+    Ok(created_db_entries
+        .into_iter()
+        .map(|(_, msg_id)| msg_id)
+        .collect())
+}
+
+/// Receives a message and returns the created database entries.
+///
+/// An empty `server_folder` is used for messages not loaded from the server,
+/// no IMAP jobs are scheduled for them.
+async fn dc_receive_imf_inner(
+    context: &Context,
+    imf_raw: &[u8],
+    server_folder: &str,
+    server_uid: u32,
+    seen: bool,
+) -> Result<Vec<(ChatId, MsgId)>> {
     info!(
         context,
         "Receiving message {}/{}, seen={}...",
-        if !server_folder.as_ref().is_empty() {
-            server_folder.as_ref()
+        if !server_folder.is_empty() {
+            server_folder
         } else {
             "?"
         },
@@ -66,7 +139,7 @@ pub async fn dc_receive_imf(
     let mut mime_parser = match MimeMessage::from_bytes(context, imf_raw).await {
         Err(err) => {
             warn!(context, "dc_receive_imf: can't parse MIME: {}", err);
-            return Ok(());
+            return Ok(Vec::new());
         }
         Ok(mime_parser) => mime_parser,
     };
@@ -74,7 +147,7 @@ pub async fn dc_receive_imf(
     // we can not add even an empty record if we have no info whatsoever
     if !mime_parser.has_headers() {
         warn!(context, "dc_receive_imf: no headers found");
-        return Ok(());
+        return Ok(Vec::new());
     }
 
     // the function returns the number of created messages in the database
@@ -151,6 +224,25 @@ pub async fn dc_receive_imf(
             }
         }
     };
+
+    // check, if the mail is already in our database - if so, just update the folder/uid
+    // (if the mail was moved around) and finish. (we may get a mail twice eg. if it is
+    // moved between folders or delivered to the inbox and the mvbox.
+    // make sure, this check is done before any processing as securejoin,
+    // sync items or status updates, which must not be applied twice)
+    if let Some((old_server_folder, old_server_uid, _)) =
+        message::rfc724_mid_exists(context, &rfc724_mid).await?
+    {
+        if !server_folder.is_empty()
+            && (old_server_folder != server_folder || old_server_uid != server_uid)
+        {
+            message::update_server_uid(context, &rfc724_mid, server_folder, server_uid).await;
+        }
+
+        warn!(context, "Message already in DB");
+        return Ok(Vec::new());
+    }
+
     if mime_parser.parts.last().is_some() {
         if let Err(err) = add_parts(
             context,
@@ -158,7 +250,7 @@ pub async fn dc_receive_imf(
             imf_raw,
             incoming,
             incoming_origin,
-            server_folder.as_ref(),
+            server_folder,
             server_uid,
             &to_ids,
             &rfc724_mid,
@@ -185,6 +277,17 @@ pub async fn dc_receive_imf(
         }
     }
 
+    if incoming && from_id > DC_CONTACT_ID_LAST_SPECIAL {
+        // the sender's clock may be wrong, do not let contacts appear online in the future
+        let last_seen = std::cmp::min(sent_timestamp, time());
+        if let Err(err) = Contact::update_last_seen(context, from_id, last_seen).await {
+            warn!(
+                context,
+                "Cannot update last seen of contact {}: {}", from_id, err
+            );
+        }
+    }
+
     if mime_parser.location_kml.is_some() || mime_parser.message_kml.is_some() {
         save_locations(
             context,
@@ -197,6 +300,33 @@ pub async fn dc_receive_imf(
         .await;
     }
 
+    if let Some(ref sync_items) = mime_parser.sync_items {
+        if from_id == DC_CONTACT_ID_SELF && mime_parser.was_encrypted() {
+            sync::apply_sync_items(context, sync_items).await;
+        } else {
+            warn!(
+                context,
+                "Ignoring sync items not sent encrypted by ourself."
+            );
+        }
+    }
+
+    if let Some(ref status_updates) = mime_parser.webxdc_status_updates {
+        if let Err(err) = context
+            .receive_webxdc_status_updates(chat_id, status_updates)
+            .await
+        {
+            warn!(context, "Cannot apply webxdc status updates: {}", err);
+        }
+    }
+
+    if let Some(value) = mime_parser.get(HeaderDef::ChatSeen) {
+        if incoming {
+            let rfc724_mids = parse_message_ids(value).unwrap_or_default();
+            message::handle_seen_marker(context, from_id, &rfc724_mids, sent_timestamp).await;
+        }
+    }
+
     if let Some(avatar_action) = &mime_parser.user_avatar {
         match contact::set_profile_image(
             &context,
@@ -215,10 +345,14 @@ pub async fn dc_receive_imf(
         };
     }
 
+    if incoming && !mime_parser.signatures.is_empty() {
+        check_address_change(context, from_id, &mime_parser.signatures).await;
+    }
+
     // Get user-configured server deletion
     let delete_server_after = context.get_config_delete_server_after().await;
 
-    if !created_db_entries.is_empty() {
+    if !created_db_entries.is_empty() && !server_folder.is_empty() {
         if needs_delete_job || delete_server_after == Some(0) {
             for db_entry in &created_db_entries {
                 job::add(
@@ -233,7 +367,7 @@ pub async fn dc_receive_imf(
                 .await;
             }
         } else if insert_msg_id
-            .needs_move(context, server_folder.as_ref())
+            .needs_move(context, server_folder)
             .await
             .unwrap_or_default()
         {
@@ -263,13 +397,13 @@ pub async fn dc_receive_imf(
         "received message {} has Message-Id: {}", server_uid, rfc724_mid
     );
 
-    cleanup(context, &create_event_to_send, created_db_entries);
+    cleanup(context, &create_event_to_send, created_db_entries.clone());
 
     mime_parser
         .handle_reports(context, from_id, sent_timestamp, &mime_parser.parts)
         .await;
 
-    Ok(())
+    Ok(created_db_entries)
 }
 
 /// Converts "From" field to contact id.
@@ -344,21 +478,6 @@ async fn add_parts(
     let mut mime_references = String::new();
     let mut incoming_origin = incoming_origin;
 
-    // check, if the mail is already in our database - if so, just update the folder/uid
-    // (if the mail was moved around) and finish. (we may get a mail twice eg. if it is
-    // moved between folders. make sure, this check is done eg. before securejoin-processing) */
-    if let Some((old_server_folder, old_server_uid, _)) =
-        message::rfc724_mid_exists(context, &rfc724_mid).await?
-    {
-        if old_server_folder != server_folder.as_ref() || old_server_uid != server_uid {
-            message::update_server_uid(context, &rfc724_mid, server_folder.as_ref(), server_uid)
-                .await;
-        }
-
-        warn!(context, "Message already in DB");
-        return Ok(());
-    }
-
     let mut is_dc_message = if mime_parser.has_chat_version() {
         MessengerMessage::Yes
     } else if is_reply_to_messenger_message(context, mime_parser).await {
@@ -384,6 +503,16 @@ async fn add_parts(
         }
     }
 
+    if mime_parser.get(HeaderDef::ChatSeen).is_some() {
+        // seen markers are only recorded, see message::handle_seen_marker()
+        *hidden = true;
+    }
+
+    if mime_parser.webxdc_status_updates.is_some() {
+        // status updates are shown by the app, not as messages
+        *hidden = true;
+    }
+
     // check if the message introduces a new chat:
     // - outgoing messages introduce a chat with the first to: address if they are sent by a messenger
     // - incoming messages introduce a chat only for known contacts if they are sent by a messenger
@@ -398,8 +527,17 @@ async fn add_parts(
         };
         to_id = DC_CONTACT_ID_SELF;
 
-        // handshake may mark contacts as verified and must be processed before chats are created
-        if mime_parser.get(HeaderDef::SecureJoin).is_some() {
+        let from_domain_blocked = match mime_parser.from.first() {
+            Some(from) => contact::is_domain_blocked(context, &from.addr).await,
+            None => false,
+        };
+
+        if from_domain_blocked {
+            info!(context, "Message is from a blocked domain, moving to trash");
+            *chat_id = ChatId::new(DC_CHAT_ID_TRASH);
+            allow_creation = false;
+        } else if mime_parser.get(HeaderDef::SecureJoin).is_some() {
+            // handshake may mark contacts as verified and must be processed before chats are created
             is_dc_message = MessengerMessage::Yes; // avoid discarding by show_emails setting
             *chat_id = ChatId::new(0);
             allow_creation = true;
@@ -427,6 +565,20 @@ async fn add_parts(
             }
         }
 
+        // known senders are never routed to the spam chat,
+        // so marking a message as not spam whitelists the sender
+        if chat_id.is_unset()
+            && !incoming_origin.is_known()
+            && mime_parser.is_spam()
+            && SpamHandling::from_i32(context.get_config_int(Config::SpamHandling).await)
+                .unwrap_or_default()
+                == SpamHandling::Separate
+        {
+            info!(context, "Message is flagged as spam, moving to spam chat");
+            *chat_id = ChatId::new(DC_CHAT_ID_SPAM);
+            allow_creation = false;
+        }
+
         let (test_normal_chat_id, test_normal_chat_id_blocked) =
             chat::lookup_by_contact_id(context, from_id)
                 .await
@@ -463,6 +615,7 @@ async fn add_parts(
                 create_blocked,
                 from_id,
                 to_ids,
+                *sent_timestamp,
             )
             .await?;
             *chat_id = new_chat_id;
@@ -479,8 +632,16 @@ async fn add_parts(
         if chat_id.is_unset() {
             // check if the message belongs to a mailing list
             if mime_parser.is_mailinglist_message() {
-                *chat_id = ChatId::new(DC_CHAT_ID_TRASH);
-                info!(context, "Message belongs to a mailing list and is ignored.",);
+                if let Some(list) = mime_parser.get_list_headers() {
+                    let (new_chat_id, new_chat_id_blocked) =
+                        create_or_lookup_mailinglist(context, allow_creation, &list).await;
+                    *chat_id = new_chat_id;
+                    chat_id_blocked = new_chat_id_blocked;
+                }
+                if chat_id.is_unset() {
+                    *chat_id = ChatId::new(DC_CHAT_ID_TRASH);
+                    info!(context, "Message belongs to a mailing list and is ignored.",);
+                }
             }
         }
 
@@ -566,6 +727,11 @@ async fn add_parts(
             }
         }
 
+        if mime_parser.sync_items.is_some() {
+            // state changes for other devices are not shown
+            *hidden = true;
+        }
+
         if !to_ids.is_empty() {
             if chat_id.is_unset() {
                 let (new_chat_id, new_chat_id_blocked) = create_or_lookup_group(
@@ -575,6 +741,7 @@ async fn add_parts(
                     Blocked::Not,
                     from_id,
                     to_ids,
+                    *sent_timestamp,
                 )
                 .await?;
                 *chat_id = new_chat_id;
@@ -746,6 +913,7 @@ async fn add_parts(
     } else {
         None
     };
+    let mut mime_html = mime_parser.html.take();
     let sent_timestamp = *sent_timestamp;
     let is_hidden = *hidden;
     let chat_id = *chat_id;
@@ -765,8 +933,8 @@ async fn add_parts(
                     "INSERT INTO msgs \
          (rfc724_mid, server_folder, server_uid, chat_id, from_id, to_id, timestamp, \
          timestamp_sent, timestamp_rcvd, type, state, msgrmsg,  txt, txt_raw, param, \
-         bytes, hidden, mime_headers,  mime_in_reply_to, mime_references, error, ephemeral_timer, ephemeral_timestamp) \
-         VALUES (?,?,?,?,?,?, ?,?,?,?,?,?, ?,?,?,?,?,?, ?,?, ?,?,?);",
+         bytes, hidden, mime_headers,  mime_in_reply_to, mime_references, error, ephemeral_timer, ephemeral_timestamp, html, quoted_text, signature, footer) \
+         VALUES (?,?,?,?,?,?, ?,?,?,?,?,?, ?,?,?,?,?,?, ?,?, ?,?,?, ?,?,?,?);",
                 )?;
 
                 let is_location_kml = location_kml_is
@@ -780,9 +948,18 @@ async fn add_parts(
                     }
                 }
 
+                let mut part_html = None;
                 if part.typ == Viewtype::Text {
                     let msg_raw = part.msg_raw.as_ref().cloned().unwrap_or_default();
                     txt_raw = format!("{}\n\n{}", subject, msg_raw);
+                    // the html belongs to the first text part only
+                    part_html = mime_html.take();
+                    if part_html
+                        .as_deref()
+                        .map_or(false, |html| html::has_remote_images(context, html))
+                    {
+                        part.param.set_int(Param::RemoteImages, 1);
+                    }
                 }
                 if is_system_message != SystemMessage::Unknown {
                     part.param.set_int(Param::Cmd, is_system_message as i32);
@@ -821,7 +998,11 @@ async fn add_parts(
                     mime_references,
                     part.error,
                     ephemeral_timer,
-                    ephemeral_timestamp
+                    ephemeral_timestamp,
+                    part_html.unwrap_or_default(),
+                    part.quoted_text,
+                    part.signature,
+                    part.footer
                 ])?;
 
                 drop(stmt);
@@ -853,7 +1034,12 @@ async fn add_parts(
     if chat_id.is_trash() || *hidden {
         *create_event_to_send = None;
     } else if incoming && state == MessageState::InFresh {
-        if Blocked::Not != chat_id_blocked {
+        if Blocked::Not != chat_id_blocked
+            || chat_id.is_spam()
+            || mime_parser.is_auto_reply()
+            || chat::is_muted(context, chat_id).await.unwrap_or_default()
+        {
+            // no notification for contact requests, spam, automatic replies and muted chats
             *create_event_to_send = Some(CreateEvent::MsgsChanged);
         } else {
             *create_event_to_send = Some(CreateEvent::IncomingMsg);
@@ -1003,9 +1189,11 @@ async fn create_or_lookup_group(
     create_blocked: Blocked,
     from_id: u32,
     to_ids: &ContactIds,
+    sent_timestamp: i64,
 ) -> Result<(ChatId, Blocked)> {
     let mut chat_id_blocked = Blocked::Not;
     let mut recreate_member_list = false;
+    let mut group_created = false;
     let mut send_EVENT_CHAT_MODIFIED = false;
     let mut X_MrAddToGrp = None;
     let mut X_MrGrpNameChanged = false;
@@ -1105,6 +1293,30 @@ async fn create_or_lookup_group(
                     .await;
 
                 mime_parser.is_system_message = SystemMessage::GroupNameChanged;
+            } else if let Some(role_addr) =
+                mime_parser.get(HeaderDef::ChatGroupRoleChanged).cloned()
+            {
+                let is_admin = mime_parser
+                    .get(HeaderDef::ChatGroupAdmins)
+                    .map(|admins| {
+                        admins
+                            .split_whitespace()
+                            .any(|addr| addr_cmp(addr, &role_addr))
+                    })
+                    .unwrap_or_default();
+                mime_parser.is_system_message = SystemMessage::MemberRoleChanged;
+                better_msg = context
+                    .stock_system_msg(
+                        if is_admin {
+                            StockMessage::MsgMemberAdmin
+                        } else {
+                            StockMessage::MsgMemberNoAdmin
+                        },
+                        &role_addr,
+                        "",
+                        from_id as u32,
+                    )
+                    .await;
             } else if let Some(value) = mime_parser.get(HeaderDef::ChatContent) {
                 if value == "group-avatar-changed" {
                     if let Some(avatar_action) = &mime_parser.group_avatar {
@@ -1203,6 +1415,7 @@ async fn create_or_lookup_group(
         .await;
         chat_id_blocked = create_blocked;
         recreate_member_list = true;
+        group_created = true;
     }
 
     // again, check chat_id
@@ -1234,6 +1447,43 @@ async fn create_or_lookup_group(
 
     // XXX insert code in a different PR :)
 
+    // groups having admins can only be changed by admins, however, everyone may leave
+    let from_admin = chat::may_manage_group(context, chat_id, from_id).await;
+    let had_admins = !chat::get_group_admins(context, chat_id).await.is_empty();
+    if !from_admin {
+        let removes_other = removed_id > 0 && removed_id != from_id;
+        if X_MrAddToGrp.is_some()
+            || X_MrGrpNameChanged
+            || removes_other
+            || mime_parser.is_system_message == SystemMessage::GroupImageChanged
+            || mime_parser.is_system_message == SystemMessage::MemberRoleChanged
+        {
+            warn!(
+                context,
+                "Ignoring group change by non-admin {} in {}", from_id, chat_id
+            );
+            mime_parser.repl_msg_by_error("Group change ignored, sender is not an admin.");
+        }
+        X_MrAddToGrp = None;
+        X_MrGrpNameChanged = false;
+        if removes_other {
+            removed_id = 0;
+        }
+        mime_parser.group_avatar = None;
+
+        if mime_parser.is_system_message == SystemMessage::Unknown {
+            if let Ok(chat) = Chat::load_from_db(context, chat_id).await {
+                if chat.is_broadcast() {
+                    info!(
+                        context,
+                        "Ignoring message by non-admin {} in broadcast {}", from_id, chat_id
+                    );
+                    return Ok((ChatId::new(DC_CHAT_ID_TRASH), chat_id_blocked));
+                }
+            }
+        }
+    }
+
     // execute group commands
     if X_MrAddToGrp.is_some() {
         recreate_member_list = true;
@@ -1257,17 +1507,29 @@ async fn create_or_lookup_group(
     }
     if let Some(avatar_action) = &mime_parser.group_avatar {
         info!(context, "group-avatar change for {}", chat_id);
+        // a wrong clock of the sender must not block later changes
+        let sent_timestamp = std::cmp::min(sent_timestamp, time());
         if let Ok(mut chat) = Chat::load_from_db(context, chat_id).await {
-            match avatar_action {
-                AvatarAction::Change(profile_image) => {
-                    chat.param.set(Param::ProfileImage, profile_image);
-                }
-                AvatarAction::Delete => {
-                    chat.param.remove(Param::ProfileImage);
-                }
-            };
-            chat.update_param(context).await?;
-            send_EVENT_CHAT_MODIFIED = true;
+            if sent_timestamp < chat.get_profile_image_timestamp() {
+                // another member changed the image later, keep that one
+                info!(
+                    context,
+                    "Ignoring group-avatar change for {}, a newer one exists.", chat_id
+                );
+            } else {
+                match avatar_action {
+                    AvatarAction::Change(profile_image) => {
+                        chat.param.set(Param::ProfileImage, profile_image);
+                    }
+                    AvatarAction::Delete => {
+                        chat.param.remove(Param::ProfileImage);
+                    }
+                };
+                chat.param
+                    .set(Param::ProfileImageTimestamp, sent_timestamp.to_string());
+                chat.update_param(context).await?;
+                send_EVENT_CHAT_MODIFIED = true;
+            }
         }
     }
 
@@ -1308,6 +1570,30 @@ async fn create_or_lookup_group(
         send_EVENT_CHAT_MODIFIED = true;
     }
 
+    // in groups without admins, everyone is allowed to manage the group,
+    // but only the message creating the group may set the admins,
+    // otherwise any member could claim to be admin.
+    if (from_admin && had_admins) || group_created {
+        if let Some(admins) = mime_parser.get(HeaderDef::ChatGroupAdmins).cloned() {
+            if chat::set_group_admins_from_addrs(context, chat_id, &admins).await? {
+                send_EVENT_CHAT_MODIFIED = true;
+            }
+        }
+    }
+    if from_admin && mime_parser.has_chat_version() {
+        let broadcast = mime_parser.get(HeaderDef::ChatGroupBroadcast).is_some();
+        let mut chat = Chat::load_from_db(context, chat_id).await?;
+        if chat.is_broadcast() != broadcast {
+            if broadcast {
+                chat.param.set_int(Param::Broadcast, 1);
+            } else {
+                chat.param.remove(Param::Broadcast);
+            }
+            chat.update_param(context).await?;
+            send_EVENT_CHAT_MODIFIED = true;
+        }
+    }
+
     if send_EVENT_CHAT_MODIFIED {
         context.emit_event(EventType::ChatModified(chat_id));
     }
@@ -1324,6 +1610,71 @@ fn extract_grpid(mime_parser: &MimeMessage, headerdef: HeaderDef) -> Option<&str
     parts.filter_map(dc_extract_grpid_from_rfc724_mid).next()
 }
 
+/// Returns the chat of a mailing list, identified by its `List-Id`.
+///
+/// New mailing list chats are created as contact requests, the list
+/// information stored in the chat is updated with every message.
+async fn create_or_lookup_mailinglist(
+    context: &Context,
+    allow_creation: bool,
+    list: &ListHeaders,
+) -> (ChatId, Blocked) {
+    let (mut chat_id, _, mut blocked) = chat::get_chat_id_by_grpid(context, &list.id)
+        .await
+        .unwrap_or((ChatId::new(0), false, Blocked::Not));
+
+    if !chat_id.is_unset() {
+        // a List-Id must not be used to write to groups with the same id
+        let is_mailing_list = Chat::load_from_db(context, chat_id)
+            .await
+            .map(|chat| chat.is_mailing_list())
+            .unwrap_or_default();
+        if !is_mailing_list {
+            warn!(
+                context,
+                "List-Id {} belongs to chat {} which is no mailing list", list.id, chat_id
+            );
+            return (ChatId::new(0), Blocked::Not);
+        }
+    }
+
+    if chat_id.is_unset() {
+        if !allow_creation {
+            info!(
+                context,
+                "Creating mailing list chat for {} not allowed", list.id
+            );
+            return (chat_id, blocked);
+        }
+        chat_id = create_group_record(
+            context,
+            &list.id,
+            &list.name,
+            Blocked::Deaddrop,
+            VerifiedStatus::Unverified,
+        )
+        .await;
+        blocked = Blocked::Deaddrop;
+        if chat_id.is_unset() {
+            return (chat_id, blocked);
+        }
+    }
+
+    if let Ok(mut chat) = Chat::load_from_db(context, chat_id).await {
+        let old_param = chat.param.clone();
+        list.to_params(&mut chat.param);
+        if chat.param != old_param {
+            if let Err(err) = chat.update_param(context).await {
+                warn!(
+                    context,
+                    "Failed to update mailing list {}: {}", list.id, err
+                );
+            }
+        }
+    }
+    (chat_id, blocked)
+}
+
 /// Handle groups for received messages, return chat_id/Blocked status on success
 async fn create_or_lookup_adhoc_group(
     context: &Context,
@@ -1611,6 +1962,44 @@ async fn search_chat_ids_by_contact_ids(
     Ok(chat_ids)
 }
 
+/// Emits [EventType::ContactAddressChanged] if a message from a new address
+/// is signed with the verified key of a contact at another address.
+async fn check_address_change(context: &Context, from_id: u32, signatures: &HashSet<Fingerprint>) {
+    if from_id <= DC_CONTACT_ID_LAST_SPECIAL {
+        return;
+    }
+    let from_addr = match Contact::load_from_db(context, from_id).await {
+        Ok(contact) => contact.get_addr().to_string(),
+        Err(_) => return,
+    };
+    for fingerprint in signatures {
+        let old_addr: Option<String> = context
+            .sql
+            .query_get_value(
+                context,
+                "SELECT addr FROM acpeerstates
+                  WHERE verified_key_fingerprint=? AND addr!=? COLLATE NOCASE;",
+                paramsv![fingerprint.hex(), from_addr],
+            )
+            .await;
+        if let Some(old_addr) = old_addr {
+            let contact_id = Contact::lookup_id_by_addr(context, &old_addr, Origin::Unknown).await;
+            // after the change was accepted, both addresses belong to the same contact
+            if contact_id > DC_CONTACT_ID_LAST_SPECIAL && contact_id != from_id {
+                info!(
+                    context,
+                    "Verified contact {} writes from new address {}.", old_addr, from_addr
+                );
+                context.emit_event(EventType::ContactAddressChanged {
+                    contact_id,
+                    new_contact_id: from_id,
+                });
+                return;
+            }
+        }
+    }
+}
+
 async fn check_verified_properties(
     context: &Context,
     mimeparser: &MimeMessage,
@@ -1886,6 +2275,7 @@ mod tests {
     use crate::chat::{ChatItem, ChatVisibility};
     use crate::chatlist::Chatlist;
     use crate::message::Message;
+    use crate::mimefactory::MimeFactory;
     use crate::test_utils::*;
 
     #[test]
@@ -2140,6 +2530,109 @@ mod tests {
         assert_eq!(chat::get_chat_contacts(&t.ctx, chat_id).await.len(), 3);
     }
 
+    #[async_std::test]
+    async fn test_muted_chat_no_notification() {
+        let t = TestContext::new_alice().await;
+        let bob_id = Contact::create(&t.ctx, "bob", "bob@example.com")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, bob_id).await.unwrap();
+        chat::set_mute_duration(&t.ctx, chat_id, chat::MuteDuration::Forever)
+            .await
+            .unwrap();
+        assert!(chat::is_muted(&t.ctx, chat_id).await.unwrap());
+
+        let emitter = t.ctx.get_event_emitter();
+        while emitter.try_recv().is_ok() {}
+
+        dc_receive_imf(&t.ctx, MSGRMSG, "INBOX", 1, false)
+            .await
+            .unwrap();
+
+        // the message is stored, but no notification is sent
+        assert_eq!(chat::get_chat_msgs(&t.ctx, chat_id, 0, None).await.len(), 1);
+        let mut msgs_changed = false;
+        while let Ok(event) = emitter.try_recv() {
+            match event.typ {
+                EventType::IncomingMsg { .. } => panic!("IncomingMsg emitted for muted chat"),
+                EventType::MsgsChanged { chat_id: id, .. } if id == chat_id => msgs_changed = true,
+                _ => {}
+            }
+        }
+        assert!(msgs_changed);
+
+        // an expired mute does not suppress notifications
+        chat::set_mute_duration(
+            &t.ctx,
+            chat_id,
+            chat::MuteDuration::Until(
+                std::time::SystemTime::now() - std::time::Duration::from_secs(60),
+            ),
+        )
+        .await
+        .unwrap();
+        assert!(!chat::is_muted(&t.ctx, chat_id).await.unwrap());
+        while emitter.try_recv().is_ok() {}
+
+        dc_receive_imf(
+            &t.ctx,
+            b"From: Bob <bob@example.com>\n\
+                 To: alice@example.com\n\
+                 Chat-Version: 1.0\n\
+                 Subject: Chat: hello again\n\
+                 Message-ID: <Mr.1112@example.com>\n\
+                 Date: Sun, 22 Mar 2020 22:38:55 +0000\n\
+                 \n\
+                 hello again\n",
+            "INBOX",
+            2,
+            false,
+        )
+        .await
+        .unwrap();
+        let mut incoming_msg = false;
+        while let Ok(event) = emitter.try_recv() {
+            if let EventType::IncomingMsg { .. } = event.typ {
+                incoming_msg = true;
+            }
+        }
+        assert!(incoming_msg);
+    }
+
+    #[async_std::test]
+    async fn test_receive_calendar_single_part() {
+        let t = TestContext::new_alice().await;
+        dc_receive_imf(
+            &t.ctx,
+            b"From: bob@example.org\n\
+              To: alice@example.com\n\
+              Subject: Invitation: Team lunch\n\
+              Message-ID: <invite@example.org>\n\
+              Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+              Content-Type: text/calendar; method=REQUEST\n\
+              \n\
+              BEGIN:VCALENDAR\n\
+              BEGIN:VEVENT\n\
+              DTSTART:20200401T120000Z\n\
+              SUMMARY:Team lunch\n\
+              END:VEVENT\n\
+              END:VCALENDAR\n",
+            "INBOX",
+            1,
+            false,
+        )
+        .await
+        .unwrap();
+        let (_, _, msg_id) = message::rfc724_mid_exists(&t.ctx, "invite@example.org")
+            .await
+            .unwrap()
+            .unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        let event = msg.get_calendar_event().unwrap();
+        assert_eq!(event.summary, "Team lunch");
+        assert_eq!(event.start, 1585742400);
+    }
+
     #[async_std::test]
     async fn test_read_receipt_and_unarchive() {
         // create alice's account
@@ -2361,9 +2854,340 @@ mod tests {
     }
 
     #[async_std::test]
-    async fn test_escaped_recipients() {
+    async fn test_auto_reply() {
         let t = TestContext::new_alice().await;
-        Contact::create(&t.ctx, "foobar", "foobar@example.com")
+        let raw = b"From: Bob <bob@example.org>\n\
+                    To: alice@example.com\n\
+                    Subject: Out of office\n\
+                    Message-ID: <vacation.1@example.org>\n\
+                    In-Reply-To: <alice.1@example.com>\n\
+                    Auto-Submitted: auto-replied\n\
+                    Chat-Disposition-Notification-To: bob@example.org\n\
+                    Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                    \n\
+                    I am on vacation until Monday.\n";
+
+        let mime_parser = MimeMessage::from_bytes(&t.ctx, &raw[..]).await.unwrap();
+        assert!(mime_parser.is_auto_reply());
+
+        t.ctx
+            .set_config(Config::ShowEmails, Some("2"))
+            .await
+            .unwrap();
+        dc_receive_imf(&t.ctx, raw, "INBOX", 1, false)
+            .await
+            .unwrap();
+
+        // the message is stored, but no read receipt is requested
+        let (_, _, msg_id) = message::rfc724_mid_exists(&t.ctx, "vacation.1@example.org")
+            .await
+            .unwrap()
+            .unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert!(msg.text.unwrap().contains("I am on vacation until Monday."));
+        assert_eq!(msg.param.get_bool(Param::WantsMdn), None);
+    }
+
+    #[async_std::test]
+    async fn test_block_domain() {
+        let t = TestContext::new_alice().await;
+        contact::block_domain(&t.ctx, "*.Spam.example")
+            .await
+            .unwrap();
+        assert_eq!(
+            contact::get_blocked_domains(&t.ctx).await.unwrap(),
+            vec!["spam.example".to_string()]
+        );
+        assert!(contact::block_domain(&t.ctx, "  ").await.is_err());
+
+        let receive = |from: &'static str, msg_id: &'static str, uid: u32| {
+            let t = &t;
+            async move {
+                let raw = format!(
+                    "From: {}\n\
+                     To: alice@example.com\n\
+                     Chat-Version: 1.0\n\
+                     Subject: Chat: buy now\n\
+                     Message-ID: <{}>\n\
+                     Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                     \n\
+                     cheap offers\n",
+                    from, msg_id
+                );
+                dc_receive_imf(&t.ctx, raw.as_bytes(), "INBOX", uid, false)
+                    .await
+                    .unwrap();
+                let (_, chat_id, _) = message::rfc724_mid_exists(&t.ctx, msg_id)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                ChatId::new(chat_id)
+            }
+        };
+
+        // subdomains are blocked as well
+        let chat_id = receive("seller@spam.example", "1@spam", 1).await;
+        assert_eq!(chat_id, ChatId::new(DC_CHAT_ID_TRASH));
+        let chat_id = receive("x@mail.spam.example", "2@spam", 2).await;
+        assert_eq!(chat_id, ChatId::new(DC_CHAT_ID_TRASH));
+        assert_eq!(chat::get_chat_cnt(&t.ctx).await, 0);
+
+        let chat_id = receive("bob@notspam.example", "3@notspam", 3).await;
+        assert_eq!(chat_id, ChatId::new(DC_CHAT_ID_DEADDROP));
+
+        // unblocking restores normal handling of new mail
+        contact::unblock_domain(&t.ctx, "spam.example")
+            .await
+            .unwrap();
+        assert!(contact::get_blocked_domains(&t.ctx)
+            .await
+            .unwrap()
+            .is_empty());
+        let chat_id = receive("seller@spam.example", "4@spam", 4).await;
+        assert_eq!(chat_id, ChatId::new(DC_CHAT_ID_DEADDROP));
+    }
+
+    #[async_std::test]
+    async fn test_spam_handling() {
+        let t = TestContext::new_alice().await;
+        let receive = |from: &'static str, spam_header: &'static str, msg_id: &'static str| {
+            let t = &t;
+            async move {
+                let raw = format!(
+                    "From: {}\n\
+                     To: alice@example.com\n\
+                     Chat-Version: 1.0\n\
+                     Subject: Chat: hello\n\
+                     Message-ID: <{}>\n\
+                     {}\n\
+                     Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                     \n\
+                     hello\n",
+                    from, msg_id, spam_header
+                );
+                dc_receive_imf(&t.ctx, raw.as_bytes(), "INBOX", 1, false)
+                    .await
+                    .unwrap();
+                let (_, chat_id, _) = message::rfc724_mid_exists(&t.ctx, msg_id)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                ChatId::new(chat_id)
+            }
+        };
+
+        // spam is handled as any other message by default
+        let chat_id = receive("bob@example.net", "X-Spam-Flag: YES", "1@example.net").await;
+        assert_eq!(chat_id, ChatId::new(DC_CHAT_ID_DEADDROP));
+
+        t.ctx
+            .set_config(Config::SpamHandling, Some("1"))
+            .await
+            .unwrap();
+        let spam_chat_id = receive(
+            "claire@example.net",
+            "X-Spam-Status: Yes, score=7.5",
+            "2@example.net",
+        )
+        .await;
+        assert!(spam_chat_id.is_spam());
+        let chat_id = receive("dave@example.net", "X-Spam-Flag: NO", "3@example.net").await;
+        assert_eq!(chat_id, ChatId::new(DC_CHAT_ID_DEADDROP));
+
+        let msgs = chat::get_chat_msgs(&t.ctx, spam_chat_id, 0, None).await;
+        assert_eq!(msgs.len(), 1);
+        let msg_id = match msgs[0] {
+            ChatItem::Message { msg_id } => msg_id,
+            _ => panic!("unexpected chat item"),
+        };
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.text, Some("hello".to_string()));
+
+        // marking as not spam moves the message to a contact request and whitelists the sender
+        message::mark_not_spam(&t.ctx, &[msg_id]).await.unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert!(!msg.chat_id.is_special());
+        assert!(chat::get_chat_msgs(&t.ctx, spam_chat_id, 0, None)
+            .await
+            .is_empty());
+        let contact = Contact::load_from_db(&t.ctx, msg.from_id).await.unwrap();
+        assert!(contact.origin.is_known());
+
+        let chat_id = receive("claire@example.net", "X-Spam-Flag: YES", "4@example.net").await;
+        assert_eq!(chat_id, msg.chat_id);
+    }
+
+    #[async_std::test]
+    async fn test_seen_markers() {
+        async fn seen_marker_cnt(t: &TestContext, chat_id: ChatId) -> isize {
+            t.ctx
+                .sql
+                .query_get_value(
+                    &t.ctx,
+                    "SELECT COUNT(*) FROM msgs WHERE chat_id=? AND hidden=1 AND param LIKE ?;",
+                    paramsv![chat_id, format!("%S={}%", SystemMessage::SeenMarker as i32)],
+                )
+                .await
+                .unwrap_or_default()
+        }
+
+        let t = TestContext::new_alice().await;
+        let bob = Contact::create(&t.ctx, "bob", "bob@example.net")
+            .await
+            .unwrap();
+        chat::create_by_contact_id(&t.ctx, bob).await.unwrap();
+        let receive = |from: &'static str, headers: String, msg_id: &'static str| {
+            let t = &t;
+            async move {
+                let raw = format!(
+                    "From: {}\n\
+                     To: alice@example.com, bob@example.net, claire@example.net\n\
+                     Chat-Version: 1.0\n\
+                     Subject: Chat: hello\n\
+                     Message-ID: <{}>\n\
+                     {}\
+                     Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                     \n\
+                     hello\n",
+                    from, msg_id, headers
+                );
+                dc_receive_imf(&t.ctx, raw.as_bytes(), "INBOX", 1, false)
+                    .await
+                    .unwrap();
+            }
+        };
+        let group_headers = "Chat-Group-ID: seen12345ab\nChat-Group-Name: foo\n";
+        for msg_id in &[
+            "Gr.seen12345ab.1@example.net",
+            "Gr.seen12345ab.2@example.net",
+        ] {
+            receive("bob@example.net", group_headers.to_string(), msg_id).await;
+        }
+        let (_, chat_id, msg_id) =
+            message::rfc724_mid_exists(&t.ctx, "Gr.seen12345ab.1@example.net")
+                .await
+                .unwrap()
+                .unwrap();
+        let chat_id = ChatId::new(chat_id);
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert!(msg.get_seen_by(&t.ctx).await.unwrap().is_empty());
+
+        // seen markers of group members are recorded, others are ignored
+        let seen_header = "Chat-Seen: <Gr.seen12345ab.1@example.net>\n";
+        receive(
+            "dave@example.net",
+            seen_header.to_string(),
+            "seen1@example.net",
+        )
+        .await;
+        assert!(msg.get_seen_by(&t.ctx).await.unwrap().is_empty());
+        receive(
+            "claire@example.net",
+            format!("{}{}", group_headers, seen_header),
+            "seen2@example.net",
+        )
+        .await;
+        let claire =
+            Contact::lookup_id_by_addr(&t.ctx, "claire@example.net", Origin::Unknown).await;
+        let seen_by = msg.get_seen_by(&t.ctx).await.unwrap();
+        assert_eq!(seen_by.len(), 1);
+        assert_eq!(seen_by[0].0, claire);
+        assert!(msg.get_read_receipts(&t.ctx).await.unwrap().is_empty());
+        // the seen marker itself is not shown
+        assert_eq!(chat::get_chat_msgs(&t.ctx, chat_id, 0, None).await.len(), 2);
+
+        // seen markers are not sent by default and never when read receipts are disabled
+        message::markseen_msgs(&t.ctx, vec![msg_id]).await;
+        assert_eq!(seen_marker_cnt(&t, chat_id).await, 0);
+        t.ctx
+            .set_config(Config::SeenMarkersEnabled, Some("1"))
+            .await
+            .unwrap();
+        t.ctx
+            .set_config(Config::MdnsEnabled, Some("0"))
+            .await
+            .unwrap();
+        let (_, _, msg_id2) = message::rfc724_mid_exists(&t.ctx, "Gr.seen12345ab.2@example.net")
+            .await
+            .unwrap()
+            .unwrap();
+        message::markseen_msgs(&t.ctx, vec![msg_id2]).await;
+        assert_eq!(seen_marker_cnt(&t, chat_id).await, 0);
+
+        t.ctx
+            .set_config(Config::MdnsEnabled, Some("1"))
+            .await
+            .unwrap();
+        receive(
+            "bob@example.net",
+            group_headers.to_string(),
+            "Gr.seen12345ab.3@example.net",
+        )
+        .await;
+        let (_, _, msg_id3) = message::rfc724_mid_exists(&t.ctx, "Gr.seen12345ab.3@example.net")
+            .await
+            .unwrap()
+            .unwrap();
+        message::markseen_msgs(&t.ctx, vec![msg_id3]).await;
+        assert_eq!(seen_marker_cnt(&t, chat_id).await, 1);
+
+        let marker_id: MsgId = t
+            .ctx
+            .sql
+            .query_get_value(
+                &t.ctx,
+                "SELECT id FROM msgs WHERE chat_id=? AND hidden=1 ORDER BY id DESC LIMIT 1;",
+                paramsv![chat_id],
+            )
+            .await
+            .unwrap();
+        let marker = Message::load_from_db(&t.ctx, marker_id).await.unwrap();
+        let rendered = MimeFactory::from_msg(&t.ctx, &marker, false)
+            .await
+            .unwrap()
+            .render()
+            .await
+            .unwrap();
+        let payload = String::from_utf8(rendered.message).unwrap();
+        assert!(payload.contains("Chat-Seen: <Gr.seen12345ab.3@example.net>"));
+        assert!(!payload.contains("Disposition-Notification-To"));
+    }
+
+    #[async_std::test]
+    async fn test_auto_reply_headers() {
+        let t = TestContext::new().await;
+        for (header, is_auto_reply) in &[
+            (
+                "Auto-Submitted: auto-replied; owner-email=bob@example.org",
+                true,
+            ),
+            ("Auto-Submitted: auto-generated", false),
+            ("Auto-Submitted: no", false),
+            ("X-Autoreply: yes", true),
+            ("X-Autorespond: Out of office", true),
+            ("Precedence: auto_reply", true),
+            ("Precedence: bulk", false),
+        ] {
+            let raw = format!(
+                "From: bob@example.org\n\
+                 To: alice@example.com\n\
+                 Subject: Re: hi\n\
+                 {}\n\
+                 \n\
+                 hello\n",
+                header
+            );
+            let mime_parser = MimeMessage::from_bytes(&t.ctx, raw.as_bytes())
+                .await
+                .unwrap();
+            assert_eq!(mime_parser.is_auto_reply(), *is_auto_reply, "{}", header);
+        }
+    }
+
+    #[async_std::test]
+    async fn test_escaped_recipients() {
+        let t = TestContext::new_alice().await;
+        Contact::create(&t.ctx, "foobar", "foobar@example.com")
             .await
             .unwrap();
 
@@ -2452,6 +3276,95 @@ mod tests {
         );
     }
 
+    #[async_std::test]
+    async fn test_receive_eml() {
+        let t = TestContext::new_alice().await;
+        t.ctx
+            .set_config(Config::ShowEmails, Some("2"))
+            .await
+            .unwrap();
+
+        let eml = include_bytes!("../test-data/message/mail_with_cc.txt");
+        let msg_ids = receive_eml(&t.ctx, eml, None).await.unwrap();
+        assert_eq!(msg_ids.len(), 1);
+        let msg = message::Message::load_from_db(&t.ctx, msg_ids[0])
+            .await
+            .unwrap();
+        assert_eq!(msg.get_text().unwrap(), "hi");
+        let sender = Contact::get_by_id(&t.ctx, msg.get_from_id()).await.unwrap();
+        assert_eq!(sender.get_addr(), "x@tux.org");
+
+        // importing the same message again does not add a duplicate
+        assert!(receive_eml(&t.ctx, eml, None).await.unwrap().is_empty());
+
+        let bob_id = Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, bob_id).await.unwrap();
+        let msg_ids = receive_eml(
+            &t.ctx,
+            b"From: Foobar <foobar@example.com>\n\
+                 To: alice@example.com\n\
+                 Subject: foo\n\
+                 Message-ID: <imported@example.com>\n\
+                 Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                 \n\
+                 imported\n",
+            Some(chat_id),
+        )
+        .await
+        .unwrap();
+        assert_eq!(msg_ids.len(), 1);
+        let msg = message::Message::load_from_db(&t.ctx, msg_ids[0])
+            .await
+            .unwrap();
+        assert_eq!(msg.chat_id, chat_id);
+        assert_eq!(msg.get_text().unwrap(), "foo – imported");
+
+        // read receipts stay in the trash
+        let msg_ids = receive_eml(
+            &t.ctx,
+            b"From: bob@example.net\n\
+                 To: alice@example.com\n\
+                 Subject: message opened\n\
+                 Date: Sun, 22 Mar 2020 23:37:57 +0000\n\
+                 Chat-Version: 1.0\n\
+                 Message-ID: <mdn.1@example.net>\n\
+                 Content-Type: multipart/report; report-type=disposition-notification; boundary=\"SNIPP\"\n\
+                 \n\
+                 --SNIPP\n\
+                 Content-Type: text/plain; charset=utf-8\n\
+                 \n\
+                 Read receipts do not guarantee sth. was read.\n\
+                 \n\
+                 --SNIPP\n\
+                 Content-Type: message/disposition-notification\n\
+                 \n\
+                 Original-Recipient: rfc822;bob@example.net\n\
+                 Final-Recipient: rfc822;bob@example.net\n\
+                 Original-Message-ID: <imported@example.com>\n\
+                 Disposition: manual-action/MDN-sent-automatically; displayed\n\
+                 \n\
+                 --SNIPP--",
+            Some(chat_id),
+        )
+        .await
+        .unwrap();
+        assert!(!msg_ids.is_empty());
+        for msg_id in msg_ids {
+            let msg = message::Message::load_from_db(&t.ctx, msg_id)
+                .await
+                .unwrap();
+            assert!(msg.chat_id.is_trash());
+        }
+        assert_eq!(chat::get_chat_msgs(&t.ctx, chat_id, 0, None).await.len(), 1);
+
+        // the chat must exist
+        assert!(receive_eml(&t.ctx, eml, Some(ChatId::new(1000)))
+            .await
+            .is_err());
+    }
+
     #[async_std::test]
     async fn test_parse_ndn_tiscali() {
         test_parse_ndn(
@@ -2524,6 +3437,18 @@ mod tests {
         .await;
     }
 
+    #[async_std::test]
+    async fn test_parse_ndn_rfc3464() {
+        test_parse_ndn(
+            "alice@example.org",
+            "bob@example.net",
+            "Mr.rfc3464.test@example.org",
+            include_bytes!("../test-data/message/rfc3464_ndn.eml"),
+            "Delivery Status Notification (Failure) – Your message could not be delivered to bob@example.net.",
+        )
+        .await;
+    }
+
     // ndn = Non Delivery Notification
     async fn test_parse_ndn(
         self_addr: &str,
@@ -2656,4 +3581,465 @@ mod tests {
         let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
         assert_eq!(msg.text.unwrap(), "   Guten Abend,   \n\n   Lots of text   \n\n   text with Umlaut ä...   \n\n   MfG    [...]");
     }
+
+    #[async_std::test]
+    async fn test_html_sanitized() {
+        let t = TestContext::new_alice().await;
+        t.ctx
+            .set_config(Config::ShowEmails, Some("2"))
+            .await
+            .unwrap();
+        dc_receive_imf(
+            &t.ctx,
+            b"From: bob@example.com\n\
+                 To: alice@example.com\n\
+                 Subject: html\n\
+                 Message-ID: <html.1@example.com>\n\
+                 Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                 Content-Type: multipart/alternative; boundary=\"==break==\"\n\
+                 \n\
+                 --==break==\n\
+                 Content-Type: text/plain; charset=utf-8\n\
+                 \n\
+                 Hello plain\n\
+                 --==break==\n\
+                 Content-Type: text/html; charset=utf-8\n\
+                 \n\
+                 <html><body><p>Hello <b>html</b></p>\
+                 <script>alert(1)</script>\
+                 <img src=\"https://example.org/t.png\" onerror=\"alert(2)\"></body></html>\n\
+                 --==break==--\n",
+            "INBOX",
+            1,
+            false,
+        )
+        .await
+        .unwrap();
+        let chats = Chatlist::try_load(&t.ctx, 0, None, None).await.unwrap();
+        let msg_id = chats.get_msg_id(0).unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+
+        // the plaintext alternative is still used for the chat
+        assert_eq!(msg.get_text().unwrap(), "Hello plain");
+
+        assert!(msg.has_remote_images());
+        let html = msg.get_html(&t.ctx).await.unwrap();
+        assert!(html.contains("<p>Hello <b>html</b></p>"));
+        assert!(!html.contains("script"));
+        assert!(!html.contains("onerror"));
+        assert!(!html.contains("example.org"));
+
+        // remote images are only loaded if explicitly requested
+        let html = msg.get_html_with_remote(&t.ctx).await.unwrap();
+        assert!(html.contains("src=\"https://example.org/t.png\""));
+
+        t.ctx
+            .set_config(Config::AutoShowRemoteImages, Some("1"))
+            .await
+            .unwrap();
+        let html = msg.get_html(&t.ctx).await.unwrap();
+        assert!(html.contains("src=\"https://example.org/t.png\""));
+        assert!(!html.contains("onerror"));
+    }
+
+    #[async_std::test]
+    async fn test_no_remote_images() {
+        let t = TestContext::new_alice().await;
+        t.ctx
+            .set_config(Config::ShowEmails, Some("2"))
+            .await
+            .unwrap();
+        dc_receive_imf(
+            &t.ctx,
+            b"From: bob@example.com\n\
+                 To: alice@example.com\n\
+                 Subject: html\n\
+                 Message-ID: <html.2@example.com>\n\
+                 Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                 Content-Type: text/html; charset=utf-8\n\
+                 \n\
+                 <p>Hello <a href=\"https://delta.chat\">html</a></p>\n",
+            "INBOX",
+            1,
+            false,
+        )
+        .await
+        .unwrap();
+        let chats = Chatlist::try_load(&t.ctx, 0, None, None).await.unwrap();
+        let msg_id = chats.get_msg_id(0).unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert!(!msg.has_remote_images());
+        assert_eq!(
+            msg.get_html(&t.ctx).await,
+            msg.get_html_with_remote(&t.ctx).await
+        );
+    }
+
+    #[async_std::test]
+    async fn test_quoted_reply() {
+        let t = TestContext::new_alice().await;
+        let bob_id = Contact::create(&t.ctx, "bob", "bob@example.com")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, bob_id).await.unwrap();
+
+        dc_receive_imf(&t.ctx, MSGRMSG, "INBOX", 1, false)
+            .await
+            .unwrap();
+        dc_receive_imf(
+            &t.ctx,
+            b"From: Bob <bob@example.com>\n\
+                 To: alice@example.com\n\
+                 Subject: Re: Chat: hello\n\
+                 Message-ID: <Mr.1112@example.com>\n\
+                 References: <unknown@example.com> <Mr.1111@example.com>\n\
+                 Date: Sun, 22 Mar 2020 22:38:55 +0000\n\
+                 \n\
+                 > hello\n\
+                 \n\
+                 hello back\n",
+            "INBOX",
+            2,
+            false,
+        )
+        .await
+        .unwrap();
+
+        let msgs = chat::get_chat_msgs(&t.ctx, chat_id, 0, None).await;
+        let msg_ids: Vec<MsgId> = msgs
+            .iter()
+            .filter_map(|item| match item {
+                ChatItem::Message { msg_id } => Some(*msg_id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(msg_ids.len(), 2);
+
+        let parent = Message::load_from_db(&t.ctx, msg_ids[0]).await.unwrap();
+        assert_eq!(parent.quoted_text(), None);
+        assert_eq!(parent.quoted_message(&t.ctx).await, None);
+
+        let reply = Message::load_from_db(&t.ctx, msg_ids[1]).await.unwrap();
+        assert_eq!(reply.get_text().unwrap(), "[...]hello back");
+        assert_eq!(reply.quoted_text().unwrap(), "hello");
+        assert_eq!(reply.quoted_message(&t.ctx).await, Some(msg_ids[0]));
+    }
+
+    #[async_std::test]
+    async fn test_text_parts() {
+        let t = TestContext::new_alice().await;
+        t.ctx
+            .set_config(Config::ShowEmails, Some("2"))
+            .await
+            .unwrap();
+        dc_receive_imf(
+            &t.ctx,
+            b"From: bob@example.com\n\
+                 To: alice@example.com\n\
+                 Subject: lunch\n\
+                 Message-ID: <parts.1@example.com>\n\
+                 Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                 \n\
+                 Sure!\n\
+                 \n\
+                 Alice wrote:\n\
+                 > Lunch?\n\
+                 \n\
+                 -- \n\
+                 Bob\n",
+            "INBOX",
+            1,
+            false,
+        )
+        .await
+        .unwrap();
+        let chats = Chatlist::try_load(&t.ctx, 0, None, None).await.unwrap();
+        let msg_id = chats.get_msg_id(0).unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.get_text().unwrap(), "Sure! [...]");
+        assert_eq!(msg.quoted_text().unwrap(), "Lunch?");
+        assert_eq!(msg.get_signature().unwrap(), "Bob");
+        assert_eq!(msg.get_footer(), None);
+    }
+
+    #[async_std::test]
+    async fn test_content_language() {
+        let t = TestContext::new_alice().await;
+        t.ctx
+            .set_config(Config::ShowEmails, Some("2"))
+            .await
+            .unwrap();
+        dc_receive_imf(
+            &t.ctx,
+            b"From: bob@example.com\n\
+                 To: alice@example.com\n\
+                 Subject: Mittag\n\
+                 Message-ID: <language.1@example.com>\n\
+                 Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                 Content-Language: de\n\
+                 \n\
+                 Gerne!\n",
+            "INBOX",
+            1,
+            false,
+        )
+        .await
+        .unwrap();
+        let chats = Chatlist::try_load(&t.ctx, 0, None, None).await.unwrap();
+        let msg = Message::load_from_db(&t.ctx, chats.get_msg_id(0).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(msg.get_text().unwrap(), "Gerne!");
+        assert_eq!(msg.get_language(), Some("de".to_string()));
+
+        // the language of the shown alternative part is used
+        dc_receive_imf(
+            &t.ctx,
+            b"From: bob@example.com\n\
+                 To: alice@example.com\n\
+                 Subject: Lunch\n\
+                 Message-ID: <language.2@example.com>\n\
+                 Date: Sun, 22 Mar 2020 22:38:57 +0000\n\
+                 Content-Language: de, en\n\
+                 Content-Type: multipart/alternative; boundary=\"X\"\n\
+                 \n\
+                 --X\n\
+                 Content-Type: text/plain; charset=utf-8\n\
+                 Content-Language: en-US\n\
+                 \n\
+                 Sure!\n\
+                 --X\n\
+                 Content-Type: text/html; charset=utf-8\n\
+                 Content-Language: de\n\
+                 \n\
+                 <p>Gerne!</p>\n\
+                 --X--\n",
+            "INBOX",
+            2,
+            false,
+        )
+        .await
+        .unwrap();
+        let chats = Chatlist::try_load(&t.ctx, 0, None, None).await.unwrap();
+        let msg = Message::load_from_db(&t.ctx, chats.get_msg_id(0).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(msg.get_text().unwrap(), "Sure!");
+        assert_eq!(msg.get_language(), Some("en-US".to_string()));
+    }
+
+    static MAILMAN_MSG: &[u8] = b"From: Bob <bob@example.org>\n\
+                    To: delta@lists.example.org\n\
+                    Subject: [delta] New release\n\
+                    Message-ID: <list.1@example.org>\n\
+                    Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                    Precedence: list\n\
+                    List-Id: Delta Chat development <delta.lists.example.org>\n\
+                    List-Post: <mailto:delta@lists.example.org>\n\
+                    List-Unsubscribe: <https://lists.example.org/mailman/options/delta>,\n \
+                     <mailto:delta-request@lists.example.org?subject=unsubscribe>\n\
+                    \n\
+                    A new release is out.\n";
+
+    #[async_std::test]
+    async fn test_mailing_list() {
+        let t = TestContext::new_alice().await;
+        t.ctx
+            .set_config(Config::ShowEmails, Some("2"))
+            .await
+            .unwrap();
+        dc_receive_imf(&t.ctx, MAILMAN_MSG, "INBOX", 1, false)
+            .await
+            .unwrap();
+
+        let (chat_id, _, blocked) = chat::get_chat_id_by_grpid(&t.ctx, "delta.lists.example.org")
+            .await
+            .unwrap();
+        assert_eq!(blocked, Blocked::Deaddrop);
+        assert_eq!(chat::get_chat_msgs(&t.ctx, chat_id, 0, None).await.len(), 1);
+        let chat = Chat::load_from_db(&t.ctx, chat_id).await.unwrap();
+        assert!(chat.is_mailing_list());
+        assert!(!chat.can_send());
+        assert_eq!(chat.get_name(), "Delta Chat development");
+
+        let list = chat.get_mailinglist().unwrap();
+        assert_eq!(list.id, "delta.lists.example.org");
+        assert_eq!(list.post, Some("delta@lists.example.org".to_string()));
+        assert_eq!(
+            list.unsubscribe_mailto,
+            Some("mailto:delta-request@lists.example.org?subject=unsubscribe".to_string())
+        );
+        assert_eq!(
+            list.unsubscribe_url,
+            Some("https://lists.example.org/mailman/options/delta".to_string())
+        );
+
+        // further messages are assigned to the same chat
+        dc_receive_imf(
+            &t.ctx,
+            b"From: Claire <claire@example.org>\n\
+                 To: delta@lists.example.org\n\
+                 Subject: Re: [delta] New release\n\
+                 Message-ID: <list.2@example.org>\n\
+                 Date: Sun, 22 Mar 2020 22:40:57 +0000\n\
+                 List-Id: <delta.lists.example.org>\n\
+                 \n\
+                 Great!\n",
+            "INBOX",
+            2,
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(chat::get_chat_msgs(&t.ctx, chat.id, 0, None).await.len(), 2);
+    }
+
+    #[async_std::test]
+    async fn test_mailing_list_not_created() {
+        let t = TestContext::new_alice().await;
+        t.ctx
+            .set_config(Config::ShowEmails, Some("1"))
+            .await
+            .unwrap();
+        dc_receive_imf(&t.ctx, MAILMAN_MSG, "INBOX", 1, false)
+            .await
+            .unwrap();
+        assert!(
+            chat::get_chat_id_by_grpid(&t.ctx, "delta.lists.example.org")
+                .await
+                .is_err()
+        );
+    }
+
+    #[async_std::test]
+    async fn test_mailing_list_id_of_group() {
+        let t = TestContext::new_alice().await;
+        t.ctx
+            .set_config(Config::ShowEmails, Some("2"))
+            .await
+            .unwrap();
+        let group_id = chat::create_group_chat(&t.ctx, VerifiedStatus::Unverified, "foo")
+            .await
+            .unwrap();
+        let grpid = Chat::load_from_db(&t.ctx, group_id).await.unwrap().grpid;
+
+        dc_receive_imf(
+            &t.ctx,
+            format!(
+                "From: Mallory <mallory@example.org>\n\
+                 To: alice@example.com\n\
+                 Subject: [foo] injected\n\
+                 Message-ID: <list.3@example.org>\n\
+                 Date: Sun, 22 Mar 2020 22:40:57 +0000\n\
+                 Precedence: list\n\
+                 List-Id: <{}>\n\
+                 \n\
+                 Injected!\n",
+                grpid
+            )
+            .as_bytes(),
+            "INBOX",
+            1,
+            false,
+        )
+        .await
+        .unwrap();
+        assert!(chat::get_chat_msgs(&t.ctx, group_id, 0, None)
+            .await
+            .is_empty());
+    }
+
+    #[async_std::test]
+    async fn test_bcc_self_copy() {
+        let t = TestContext::new_alice().await;
+        t.ctx.set_config(Config::BccSelf, Some("1")).await.unwrap();
+        let bob_id = Contact::create(&t.ctx, "Bob", "bob@example.org")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, bob_id).await.unwrap();
+
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("hello".to_string()));
+        let msg_id = chat::send_msg(&t.ctx, chat_id, &mut msg).await.unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        let rendered = MimeFactory::from_msg(&t.ctx, &msg, false)
+            .await
+            .unwrap()
+            .render()
+            .await
+            .unwrap();
+
+        // the BCC'd copy of the sent message is recognized
+        dc_receive_imf(&t.ctx, &rendered.message, "INBOX", 7, false)
+            .await
+            .unwrap();
+        assert_eq!(chat::get_chat_msgs(&t.ctx, chat_id, 0, None).await.len(), 1);
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.server_uid, 7);
+        assert_eq!(msg.from_id, DC_CONTACT_ID_SELF);
+
+        // a copy sent by another device is added as outgoing message
+        dc_receive_imf(
+            &t.ctx,
+            b"From: alice@example.com\n\
+                 To: bob@example.org\n\
+                 Subject: Chat: hi\n\
+                 Message-ID: <other.device@example.com>\n\
+                 Chat-Version: 1.0\n\
+                 Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                 \n\
+                 hi from my laptop\n",
+            "INBOX",
+            8,
+            false,
+        )
+        .await
+        .unwrap();
+        let msgs = chat::get_chat_msgs(&t.ctx, chat_id, 0, None).await;
+        assert_eq!(msgs.len(), 2);
+        let msg_id = if let ChatItem::Message { msg_id } = msgs.last().unwrap() {
+            msg_id
+        } else {
+            panic!("Wrong item type");
+        };
+        let msg = Message::load_from_db(&t.ctx, *msg_id).await.unwrap();
+        assert_eq!(msg.from_id, DC_CONTACT_ID_SELF);
+        assert_eq!(msg.state, MessageState::OutDelivered);
+        assert_eq!(msg.text, Some("hi from my laptop".to_string()));
+    }
+
+    #[async_std::test]
+    async fn test_receive_user_avatar() {
+        let t = TestContext::new().await;
+        t.configure_addr("tunis3@example.org").await;
+        dc_receive_imf(
+            &t.ctx,
+            include_bytes!("../test-data/message/mail_with_user_avatar.eml"),
+            "INBOX",
+            1,
+            false,
+        )
+        .await
+        .unwrap();
+        let contact_id =
+            Contact::lookup_id_by_addr(&t.ctx, "tunis4@example.org", Origin::Unknown).await;
+        assert!(contact_id > DC_CONTACT_ID_LAST_SPECIAL);
+        let contact = Contact::get_by_id(&t.ctx, contact_id).await.unwrap();
+        let avatar = contact.get_profile_image(&t.ctx).await.unwrap();
+        assert!(avatar.starts_with(t.ctx.get_blobdir()));
+        assert!(avatar.to_str().unwrap().ends_with(".png"));
+        assert!(async_std::fs::metadata(&avatar).await.unwrap().len() > 0);
+
+        dc_receive_imf(
+            &t.ctx,
+            include_bytes!("../test-data/message/mail_with_user_avatar_deleted.eml"),
+            "INBOX",
+            2,
+            false,
+        )
+        .await
+        .unwrap();
+        let contact = Contact::get_by_id(&t.ctx, contact_id).await.unwrap();
+        assert_eq!(contact.get_profile_image(&t.ctx).await, None);
+    }
 }
@@ -282,8 +282,14 @@ pub(crate) async fn dc_delete_file(context: &Context, path: impl AsRef<Path>) ->
     }
 
     let dpath = format!("{}", path.as_ref().to_string_lossy());
-    match fs::remove_file(path_abs).await {
+    match fs::remove_file(&path_abs).await {
         Ok(_) => {
+            // blob names may be reused for other files
+            context
+                .blob_sizes
+                .write()
+                .await
+                .remove(&*path_abs.to_string_lossy());
             context.emit_event(EventType::DeletedBlobFile(dpath));
             true
         }
@@ -516,6 +522,32 @@ pub(crate) fn time() -> i64 {
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap_or_default()
         .as_secs() as i64
+        + time_shift()
+}
+
+#[cfg(not(test))]
+fn time_shift() -> i64 {
+    0
+}
+
+#[cfg(test)]
+async_std::task_local! {
+    /// Seconds added to [time] in the current task, see [shift_time].
+    static TIME_SHIFT: std::cell::Cell<i64> = std::cell::Cell::new(0);
+}
+
+#[cfg(test)]
+fn time_shift() -> i64 {
+    TIME_SHIFT.try_with(|shift| shift.get()).unwrap_or_default()
+}
+
+/// Advances the clock returned by [time] by `secs` seconds.
+///
+/// Only the current task is affected, so tests running in parallel
+/// and tasks spawned by the test keep the real time.
+#[cfg(test)]
+pub(crate) fn shift_time(secs: i64) {
+    TIME_SHIFT.with(|shift| shift.set(shift.get() + secs));
 }
 
 /// An invalid email address was encountered
@@ -612,6 +644,70 @@ impl FromStr for EmailAddress {
     }
 }
 
+/// An email address normalized for comparison and display.
+///
+/// Created by [normalize_address].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct NormalizedAddr {
+    /// The local part, unchanged.
+    pub local: String,
+
+    /// The domain, lowercased and, for internationalized domains,
+    /// converted to punycode.
+    pub domain: String,
+
+    /// The address with the domain in Unicode, for showing to the user.
+    pub display: String,
+}
+
+impl fmt::Display for NormalizedAddr {
+    /// Formats the address as used on the wire, `local@domain`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}@{}", self.local, self.domain)
+    }
+}
+
+/// Removes surrounding whitespace, a display name as in
+/// `Alice <alice@example.org>` and a `mailto:` prefix from an address.
+///
+/// The address is not validated, use [normalize_address] for that.
+pub(crate) fn strip_address(input: &str) -> &str {
+    let mut addr = input.trim();
+    if let (Some(start), Some(end)) = (addr.rfind('<'), addr.rfind('>')) {
+        if start < end {
+            addr = addr.get(start + 1..end).unwrap_or(addr).trim();
+        }
+    }
+    if addr
+        .get(..7)
+        .map_or(false, |scheme| scheme.eq_ignore_ascii_case("mailto:"))
+    {
+        addr = addr.get(7..).unwrap_or(addr);
+    }
+    addr
+}
+
+/// Normalizes an email address entered by the user or found in a header.
+///
+/// The address is stripped by [strip_address].  The domain is lowercased
+/// and internationalized domains as `müller.de` are converted to
+/// punycode.
+pub fn normalize_address(input: &str) -> Result<NormalizedAddr, InvalidEmailError> {
+    let err = |msg: &str| InvalidEmailError {
+        message: msg.to_string(),
+        addr: input.to_string(),
+    };
+
+    let parsed = EmailAddress::new(strip_address(input))?;
+    let domain = idna::domain_to_ascii(&parsed.domain).map_err(|_| err("invalid domain"))?;
+    let (unicode_domain, _) = idna::domain_to_unicode(&domain);
+    Ok(NormalizedAddr {
+        display: format!("{}@{}", parsed.local, unicode_domain),
+        local: parsed.local,
+        domain,
+    })
+}
+
 impl rusqlite::types::ToSql for EmailAddress {
     fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput> {
         let val = rusqlite::types::Value::Text(self.to_string());
@@ -798,6 +894,39 @@ mod tests {
         assert_eq!("@d.tt".parse::<EmailAddress>().is_ok(), false);
     }
 
+    #[test]
+    fn test_normalize_address() {
+        let addr = normalize_address("Alice@Example.ORG").unwrap();
+        assert_eq!(addr.local, "Alice");
+        assert_eq!(addr.domain, "example.org");
+        assert_eq!(addr.display, "Alice@example.org");
+        assert_eq!(addr.to_string(), "Alice@example.org");
+
+        let addr = normalize_address(" \"Alice\" <alice@example.org> ").unwrap();
+        assert_eq!(addr.to_string(), "alice@example.org");
+        let addr = normalize_address("Alice Smith <mailto:alice@example.org>").unwrap();
+        assert_eq!(addr.to_string(), "alice@example.org");
+        let addr = normalize_address("MAILTO:alice@example.org").unwrap();
+        assert_eq!(addr.to_string(), "alice@example.org");
+
+        assert!(normalize_address("").is_err());
+        assert!(normalize_address("Alice <>").is_err());
+        assert!(normalize_address("alice").is_err());
+    }
+
+    #[test]
+    fn test_normalize_address_idn() {
+        let addr = normalize_address("info@Müller.de").unwrap();
+        assert_eq!(addr.local, "info");
+        assert_eq!(addr.domain, "xn--mller-kva.de");
+        assert_eq!(addr.display, "info@müller.de");
+
+        // already encoded domains are displayed in Unicode
+        let addr = normalize_address("info@XN--MLLER-KVA.DE").unwrap();
+        assert_eq!(addr.domain, "xn--mller-kva.de");
+        assert_eq!(addr.display, "info@müller.de");
+    }
+
     use proptest::prelude::*;
 
     proptest! {
@@ -157,7 +157,7 @@ pub async fn try_decrypt(
     }
 
     /* possibly perform decryption */
-    let private_keyring: Keyring<SignedSecretKey> = Keyring::new_self(context).await?;
+    let private_keyring: Keyring<SignedSecretKey> = Keyring::new_self_all(context).await?;
     let mut public_keyring_for_validate: Keyring<SignedPublicKey> = Keyring::new();
     let mut signatures = HashSet::default();
 
@@ -315,6 +315,31 @@ pub async fn ensure_secret_key_exists(context: &Context) -> Result<String> {
     Ok(self_addr)
 }
 
+/// Replaces the user's key by a newly generated one.
+///
+/// This is meant to be used if the secret key was compromised.  The old
+/// secret key is kept to decrypt messages received before the rotation,
+/// while all new messages are encrypted and signed with the new key.
+/// As the Autocrypt header of outgoing messages always carries the
+/// current key, the next sent message announces the new key to the
+/// peers.  Peerstates are not touched.
+///
+/// Returns the fingerprint of the new key.
+pub async fn rotate_self_key(context: &Context) -> Result<Fingerprint> {
+    let keypair = crate::key::rotate_self_keypair(context).await?;
+    let fingerprint = keypair.public.fingerprint();
+    info!(context, "Self key rotated, new fingerprint {}", fingerprint);
+    Ok(fingerprint)
+}
+
+/// Returns the fingerprint of the user's current key.
+///
+/// A key is generated if there is none yet.
+pub async fn self_fingerprint(context: &Context) -> Result<Fingerprint> {
+    let public_key = SignedPublicKey::load_self(context).await?;
+    Ok(public_key.fingerprint())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -338,6 +363,36 @@ mod tests {
         }
     }
 
+    #[async_std::test]
+    async fn test_rotate_self_key() {
+        let t = TestContext::new_alice().await;
+        let alice = alice_keypair();
+        assert_eq!(
+            self_fingerprint(&t.ctx).await.unwrap(),
+            alice.public.fingerprint()
+        );
+
+        // encrypt something to the old key
+        let mut old_keyring = Keyring::new();
+        old_keyring.add(alice.public.clone());
+        let ctext = pgp::pk_encrypt(b"hello", old_keyring, None).await.unwrap();
+
+        let new_fingerprint = rotate_self_key(&t.ctx).await.unwrap();
+        assert_ne!(new_fingerprint, alice.public.fingerprint());
+        assert_eq!(self_fingerprint(&t.ctx).await.unwrap(), new_fingerprint);
+        let helper = EncryptHelper::new(&t.ctx).await.unwrap();
+        assert_eq!(helper.public_key.fingerprint(), new_fingerprint);
+
+        // the old key is still used for decryption
+        let private_keyring = Keyring::new_self_all(&t.ctx).await.unwrap();
+        assert_eq!(private_keyring.len(), 2);
+        assert_eq!(private_keyring.keys()[1], alice.secret);
+        let plain = pgp::pk_decrypt(ctext.into_bytes(), private_keyring, Keyring::new(), None)
+            .await
+            .unwrap();
+        assert_eq!(plain, b"hello");
+    }
+
     #[test]
     fn test_mailmime_parse() {
         let plain = b"Chat-Disposition-Notification-To: hello@world.de
@@ -27,7 +27,8 @@
 //! settings: `delete_device_after` and `delete_server_after`. These
 //! settings are not synchronized among devices and apply to all
 //! messages known to the device, including messages sent or received
-//! before configuring the setting.
+//! before configuring the setting. Starred messages and messages in
+//! pinned chats are exempt from both settings.
 //!
 //! `delete_device_after` configures the maximum time device is
 //! storing the messages locally. `delete_server_after` configures the
@@ -51,12 +52,16 @@
 //! Local deletion happens when the chatlist or chat is loaded. A
 //! `MsgsChanged` event is emitted when a message deletion is due, to
 //! make UI reload displayed messages and cause actual deletion.
+//! After messages have been deleted locally, a `MsgsDeleted` event
+//! with the number of deleted messages is emitted.
 //!
 //! Server deletion happens by generating IMAP deletion jobs based on
 //! the database entries which are expired either according to their
 //! ephemeral message timers or global `delete_server_after` setting.
+//! When no deletion job is left, a `ServerMsgsDeleted` event with the
+//! number of messages deleted from the server is emitted.
 
-use crate::chat::{lookup_by_contact_id, send_msg, ChatId};
+use crate::chat::{lookup_by_contact_id, send_msg, ChatId, ChatVisibility};
 use crate::constants::{
     Viewtype, DC_CHAT_ID_LAST_SPECIAL, DC_CHAT_ID_TRASH, DC_CONTACT_ID_DEVICE, DC_CONTACT_ID_SELF,
 };
@@ -274,7 +279,7 @@ impl MsgId {
 /// because it is also called when chatlist is reloaded, and emitting
 /// MsgsChanged there will cause infinite reload loop.
 pub(crate) async fn delete_expired_messages(context: &Context) -> Result<bool, Error> {
-    let mut updated = context
+    let mut deleted_cnt = context
         .sql
         .execute(
             "UPDATE msgs \
@@ -285,8 +290,7 @@ pub(crate) async fn delete_expired_messages(context: &Context) -> Result<bool, E
              AND chat_id != ?",
             paramsv![DC_CHAT_ID_TRASH, time(), DC_CHAT_ID_TRASH],
         )
-        .await?
-        > 0;
+        .await?;
 
     if let Some(delete_device_after) = context.get_config_delete_device_after().await {
         let self_chat_id = lookup_by_contact_id(context, DC_CONTACT_ID_SELF)
@@ -300,7 +304,8 @@ pub(crate) async fn delete_expired_messages(context: &Context) -> Result<bool, E
 
         let threshold_timestamp = time() - delete_device_after;
 
-        // Delete expired messages
+        // Delete expired messages, keeping starred messages and
+        // messages in pinned chats.
         //
         // Only update the rows that have to be updated, to avoid emitting
         // unnecessary "chat modified" events.
@@ -312,22 +317,30 @@ pub(crate) async fn delete_expired_messages(context: &Context) -> Result<bool, E
              WHERE timestamp < ? \
              AND chat_id > ? \
              AND chat_id != ? \
-             AND chat_id != ?",
+             AND chat_id != ? \
+             AND starred = 0 \
+             AND chat_id NOT IN (SELECT id FROM chats WHERE archived = ?)",
                 paramsv![
                     DC_CHAT_ID_TRASH,
                     threshold_timestamp,
                     DC_CHAT_ID_LAST_SPECIAL,
                     self_chat_id,
-                    device_chat_id
+                    device_chat_id,
+                    ChatVisibility::Pinned
                 ],
             )
             .await?;
 
-        updated |= rows_modified > 0;
+        deleted_cnt += rows_modified;
+    }
+
+    if deleted_cnt > 0 {
+        info!(context, "Deleted {} expired messages.", deleted_cnt);
+        context.emit_event(EventType::MsgsDeleted(deleted_cnt));
     }
 
     schedule_ephemeral_task(context).await;
-    Ok(updated)
+    Ok(deleted_cnt > 0)
 }
 
 /// Schedule a task to emit MsgsChanged event when the next local
@@ -416,12 +429,14 @@ pub(crate) async fn load_imap_deletion_msgid(context: &Context) -> sql::Result<O
         .query_row_optional(
             "SELECT id FROM msgs \
          WHERE ( \
-         timestamp < ? \
+         (timestamp < ? \
+          AND starred = 0 \
+          AND chat_id NOT IN (SELECT id FROM chats WHERE archived = ?)) \
          OR (ephemeral_timestamp != 0 AND ephemeral_timestamp < ?) \
          ) \
          AND server_uid != 0 \
          LIMIT 1",
-            paramsv![threshold_timestamp, now],
+            paramsv![threshold_timestamp, ChatVisibility::Pinned, now],
             |row| row.get::<_, MsgId>(0),
         )
         .await
@@ -460,8 +475,109 @@ pub(crate) async fn start_ephemeral_timers(context: &Context) -> sql::Result<()>
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::{TimeZone, Utc};
+
+    use crate::chat::{create_by_contact_id, get_chat_msgs};
+    use crate::config::Config;
+    use crate::contact::Contact;
+    use crate::dc_receive_imf::dc_receive_imf;
+    use crate::dc_tools::shift_time;
+    use crate::message::star_msgs;
     use crate::test_utils::*;
 
+    /// Receives a chat message from `from` sent at `timestamp`.
+    async fn receive_msg(context: &Context, from: &str, timestamp: i64, mid: &str) -> MsgId {
+        let imf = format!(
+            "From: {}\n\
+             To: alice@example.com\n\
+             Subject: Chat: hi\n\
+             Chat-Version: 1.0\n\
+             Message-ID: <{}>\n\
+             Date: {}\n\
+             \n\
+             hi\n",
+            from,
+            mid,
+            Utc.timestamp(timestamp, 0).to_rfc2822()
+        );
+        dc_receive_imf(context, imf.as_bytes(), "INBOX", 1, false)
+            .await
+            .unwrap();
+        get_received_msg(context, mid).await.id
+    }
+
+    #[async_std::test]
+    async fn test_delete_device_after() {
+        let t = TestContext::new_alice().await;
+        let bob = Contact::create(&t.ctx, "bob", "bob@example.net")
+            .await
+            .unwrap();
+        let claire = Contact::create(&t.ctx, "claire", "claire@example.net")
+            .await
+            .unwrap();
+        let chat_id = create_by_contact_id(&t.ctx, bob).await.unwrap();
+        let pinned_chat_id = create_by_contact_id(&t.ctx, claire).await.unwrap();
+        pinned_chat_id
+            .set_visibility(&t.ctx, ChatVisibility::Pinned)
+            .await
+            .unwrap();
+
+        let old_msg = receive_msg(&t.ctx, "bob@example.net", time(), "old@example.net").await;
+        let starred_msg =
+            receive_msg(&t.ctx, "bob@example.net", time(), "starred@example.net").await;
+        star_msgs(&t.ctx, vec![starred_msg], true).await;
+        let pinned_msg =
+            receive_msg(&t.ctx, "claire@example.net", time(), "pinned@example.net").await;
+
+        // nothing is deleted while the setting is disabled
+        shift_time(2 * 3600);
+        let new_msg = receive_msg(&t.ctx, "bob@example.net", time(), "new@example.net").await;
+        assert!(!delete_expired_messages(&t.ctx).await.unwrap());
+
+        t.ctx
+            .set_config(Config::DeleteDeviceAfter, Some("3600"))
+            .await
+            .unwrap();
+        t.ctx
+            .set_config(Config::DeleteServerAfter, Some("10800"))
+            .await
+            .unwrap();
+        let emitter = t.ctx.get_event_emitter();
+        while emitter.try_recv().is_ok() {}
+
+        assert!(delete_expired_messages(&t.ctx).await.unwrap());
+        let msg = Message::load_from_db(&t.ctx, old_msg).await.unwrap();
+        assert!(msg.chat_id.is_trash());
+        for msg_id in &[starred_msg, pinned_msg, new_msg] {
+            let msg = Message::load_from_db(&t.ctx, *msg_id).await.unwrap();
+            assert!(!msg.chat_id.is_trash());
+        }
+        assert_eq!(get_chat_msgs(&t.ctx, chat_id, 0, None).await.len(), 2);
+        assert_eq!(
+            get_chat_msgs(&t.ctx, pinned_chat_id, 0, None).await.len(),
+            1
+        );
+
+        let mut deleted_cnt = None;
+        while let Ok(event) = emitter.try_recv() {
+            if let EventType::MsgsDeleted(cnt) = event.typ {
+                deleted_cnt = Some(cnt);
+            }
+        }
+        assert_eq!(deleted_cnt, Some(1));
+
+        // a second sweep has nothing to do
+        assert!(!delete_expired_messages(&t.ctx).await.unwrap());
+
+        // the thresholds are independent, the message expires on the server later
+        assert_eq!(load_imap_deletion_msgid(&t.ctx).await.unwrap(), None);
+        shift_time(2 * 3600);
+        assert_eq!(
+            load_imap_deletion_msgid(&t.ctx).await.unwrap(),
+            Some(old_msg)
+        );
+    }
+
     #[async_std::test]
     async fn test_stock_ephemeral_messages() {
         let context = TestContext::new().await.ctx;
@@ -1,14 +1,19 @@
 //! # Events specification
 
 use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 use async_std::path::PathBuf;
 use async_std::sync::{channel, Receiver, Sender, TrySendError};
 use strum::EnumProperty;
 
 use crate::chat::ChatId;
+use crate::context::Context;
 use crate::ephemeral::Timer as EphemeralTimer;
+use crate::log::LogLevel;
 use crate::message::MsgId;
+use crate::sql::HousekeepingStats;
 
 #[derive(Debug)]
 pub struct Events {
@@ -47,6 +52,141 @@ impl Events {
     }
 }
 
+/// Events collected by one batch, owned by its [MsgStateBatchGuard].
+type MsgStateCollector = Arc<std::sync::Mutex<Vec<EventType>>>;
+
+/// Open message state batches, see [Context::batch_msg_states].
+///
+/// Each batch only collects the events emitted by the task that opened it,
+/// so that eg. the SMTP loop does not hold back read receipts handled by the IMAP loop.
+#[derive(Debug, Default)]
+pub(crate) struct MsgStateBatches(Vec<(Option<usize>, MsgStateCollector)>);
+
+/// Closes the batch opened by [Context::batch_msg_states] when dropped.
+#[derive(Debug)]
+pub(crate) struct MsgStateBatchGuard<'a> {
+    context: &'a Context,
+    task: Option<usize>,
+    events: MsgStateCollector,
+}
+
+impl Drop for MsgStateBatchGuard<'_> {
+    fn drop(&mut self) {
+        let events = std::mem::take(&mut *self.events.lock().unwrap());
+        let outer = {
+            let mut batches = self.context.msg_state_batches.lock().unwrap();
+            batches
+                .0
+                .retain(|(_, events)| !Arc::ptr_eq(events, &self.events));
+            batches
+                .0
+                .iter()
+                .rev()
+                .find(|(task, _)| *task == self.task)
+                .map(|(_, events)| events.clone())
+        };
+        match outer {
+            // nested batches are emitted with the outermost one
+            Some(outer) => outer.lock().unwrap().extend(events),
+            None => {
+                for event in coalesce_msg_states(events) {
+                    self.context.emit_event(event);
+                }
+            }
+        }
+    }
+}
+
+static NEXT_TASK_ID: AtomicUsize = AtomicUsize::new(1);
+
+async_std::task_local! {
+    static TASK_ID: usize = NEXT_TASK_ID.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns an identifier of the current task, `None` outside of tasks.
+fn current_task_id() -> Option<usize> {
+    TASK_ID.try_with(|id| *id).ok()
+}
+
+impl Context {
+    /// Collects message state events of the current task until the returned guard is dropped.
+    ///
+    /// Several state changes in one chat are then emitted as a single
+    /// [EventType::MsgStatesChanged], single changes keep their event.
+    pub(crate) fn batch_msg_states(&self) -> MsgStateBatchGuard<'_> {
+        let guard = MsgStateBatchGuard {
+            context: self,
+            task: current_task_id(),
+            events: Default::default(),
+        };
+        self.msg_state_batches
+            .lock()
+            .unwrap()
+            .0
+            .push((guard.task, guard.events.clone()));
+        guard
+    }
+
+    /// Emits [EventType::MsgDelivered], [EventType::MsgFailed] or [EventType::MsgRead],
+    /// or queues it if the current task has opened a batch.
+    pub(crate) fn emit_msg_state_event(&self, event: EventType) {
+        let batch = {
+            let task = current_task_id();
+            let batches = self.msg_state_batches.lock().unwrap();
+            batches
+                .0
+                .iter()
+                .rev()
+                .find(|(batch_task, _)| *batch_task == task)
+                .map(|(_, events)| events.clone())
+        };
+        match batch {
+            Some(events) => events.lock().unwrap().push(event),
+            None => self.emit_event(event),
+        }
+    }
+}
+
+/// Merges the message state events of each chat with more than one changed message.
+fn coalesce_msg_states(events: Vec<EventType>) -> Vec<EventType> {
+    let mut chats: Vec<(ChatId, Vec<EventType>)> = Vec::new();
+    for event in events {
+        let chat_id = match event {
+            EventType::MsgDelivered { chat_id, .. }
+            | EventType::MsgFailed { chat_id, .. }
+            | EventType::MsgRead { chat_id, .. } => chat_id,
+            _ => ChatId::new(0),
+        };
+        match chats.iter_mut().find(|(id, _)| *id == chat_id) {
+            Some((_, chat_events)) => chat_events.push(event),
+            None => chats.push((chat_id, vec![event])),
+        }
+    }
+
+    let mut coalesced = Vec::new();
+    for (chat_id, mut chat_events) in chats {
+        if chat_events.len() == 1 {
+            coalesced.append(&mut chat_events);
+            continue;
+        }
+        let mut msg_ids = Vec::new();
+        for event in chat_events {
+            match event {
+                EventType::MsgDelivered { msg_id, .. }
+                | EventType::MsgFailed { msg_id, .. }
+                | EventType::MsgRead { msg_id, .. } => {
+                    if !msg_ids.contains(&msg_id) {
+                        msg_ids.push(msg_id);
+                    }
+                }
+                other => coalesced.push(other),
+            }
+        }
+        coalesced.push(EventType::MsgStatesChanged { chat_id, msg_ids });
+    }
+    coalesced
+}
+
 #[derive(Debug, Clone)]
 pub struct EventEmitter(Receiver<Event>);
 
@@ -99,6 +239,18 @@ pub enum EventType {
     #[strum(props(id = "100"))]
     Info(String),
 
+    /// A log message with key-value fields attached.
+    ///
+    /// Emitted instead of `Info` and `Warning`
+    /// if structured logging is enabled with set_structured_logging().
+    /// This event should not be reported to the end-user using a popup or something like that.
+    #[strum(props(id = "110"))]
+    Log {
+        level: LogLevel,
+        msg: String,
+        fields: Vec<(String, String)>,
+    },
+
     /// Emitted when SMTP connection is established and login was successful.
     #[strum(props(id = "101"))]
     SmtpConnected(String),
@@ -190,6 +342,11 @@ pub enum EventType {
     #[strum(props(id = "2005"))]
     IncomingMsg { chat_id: ChatId, msg_id: MsgId },
 
+    /// Messages of a chat were marked as noticed by dc_marknoticed_chat(),
+    /// the UI should update the fresh message counter and remove notifications of the chat.
+    #[strum(props(id = "2008"))]
+    MsgsNoticed(ChatId),
+
     /// A single message is sent successfully. State changed from  DC_STATE_OUT_PENDING to
     /// DC_STATE_OUT_DELIVERED, see dc_msg_get_state().
     #[strum(props(id = "2010"))]
@@ -205,6 +362,16 @@ pub enum EventType {
     #[strum(props(id = "2015"))]
     MsgRead { chat_id: ChatId, msg_id: MsgId },
 
+    /// The states of several messages in a chat changed at once,
+    /// eg. while sending queued messages or receiving read receipts.
+    /// Replaces the #DC_EVENT_MSG_DELIVERED, #DC_EVENT_MSG_FAILED and #DC_EVENT_MSG_READ
+    /// events of the single messages, see dc_msg_get_state() for the new states.
+    #[strum(props(id = "2016"))]
+    MsgStatesChanged {
+        chat_id: ChatId,
+        msg_ids: Vec<MsgId>,
+    },
+
     /// Chat changed.  The name or the image of a chat group was changed or members were added or removed.
     /// Or the verify state of a chat has changed.
     /// See dc_set_chat_name(), dc_set_chat_profile_image(), dc_add_contact_to_chat()
@@ -222,6 +389,31 @@ pub enum EventType {
         timer: EphemeralTimer,
     },
 
+    /// Unsubscribing from a mailing list was attempted,
+    /// see dc_unsubscribe_from_list().
+    ///
+    /// @param data1 (int) chat_id of the mailing list chat.
+    /// @param data2 (int) 1 if the unsubscribe request was sent, 0 on errors.
+    #[strum(props(id = "2022"))]
+    ListUnsubscribed { chat_id: ChatId, success: bool },
+
+    /// Expired messages were deleted locally according to their ephemeral
+    /// timers or the `delete_device_after` setting.
+    ///
+    /// @param data1 (usize) Number of deleted messages.
+    #[strum(props(id = "2025"))]
+    MsgsDeleted(usize),
+
+    /// Messages were deleted from the server, either because they were
+    /// deleted locally or because they expired according to their ephemeral
+    /// timers or the `delete_server_after` setting.
+    ///
+    /// Emitted once after all pending deletions are done.
+    ///
+    /// @param data1 (usize) Number of deleted messages.
+    #[strum(props(id = "2026"))]
+    ServerMsgsDeleted(usize),
+
     /// Contact(s) created, renamed, blocked or deleted.
     ///
     /// @param data1 (int) If set, this is the contact_id of an added contact that should be selected.
@@ -236,12 +428,47 @@ pub enum EventType {
     #[strum(props(id = "2035"))]
     LocationChanged(Option<u32>),
 
+    /// A contact crossed the boundary of a geofence added by add_geofence()
+    /// in the direction the geofence is watching.
+    ///
+    /// @param data1 (u32) contact_id of the contact.
+    /// @param data2 (u32) ID of the geofence.
+    #[strum(props(id = "2036"))]
+    GeofenceTriggered { contact_id: u32, geofence_id: u32 },
+
+    /// The Autocrypt key of a contact has changed.
+    ///
+    /// The previous keys can be listed with peerstate::get_history().
+    ///
+    /// @param data1 (u32) contact_id of the contact.
+    #[strum(props(id = "2037"))]
+    ContactKeyChanged { contact_id: u32 },
+
+    /// A verified contact wrote from a new address,
+    /// the message was signed with the verified key of the contact.
+    ///
+    /// The contact can be moved to the new address with contact::accept_address_change().
+    ///
+    /// @param data1 (u32) contact_id of the contact at the old address.
+    /// @param data2 (u32) contact_id of the new address.
+    #[strum(props(id = "2038"))]
+    ContactAddressChanged {
+        contact_id: u32,
+        new_contact_id: u32,
+    },
+
     /// Inform about the configuration progress started by configure().
     ///
     /// @param data1 (usize) 0=error, 1-999=progress in permille, 1000=success and done
     #[strum(props(id = "2041"))]
     ConfigureProgress(usize),
 
+    /// Inform about the progress of fetching all folders started by resync_all_folders().
+    ///
+    /// @param data1 (usize) 0=error, 1-999=progress in permille, 1000=success and done
+    #[strum(props(id = "2042"))]
+    RescanProgress(usize),
+
     /// Inform about the import/export progress started by imex().
     ///
     /// @param data1 (usize) 0=error, 1-999=progress in permille, 1000=success and done
@@ -284,4 +511,22 @@ pub enum EventType {
     ///     (Bob has verified alice and waits until Alice does the same for him)
     #[strum(props(id = "2061"))]
     SecurejoinJoinerProgress { contact_id: u32, progress: usize },
+
+    /// A status update of a webxdc app was stored,
+    /// get it using dc_get_webxdc_status_updates().
+    ///
+    /// @param data1 (int) msg_id of the webxdc app.
+    /// @param data2 (int) serial of the status update.
+    #[strum(props(id = "2120"))]
+    WebxdcStatusUpdate {
+        msg_id: MsgId,
+        status_update_serial: u32,
+    },
+
+    /// Housekeeping finished.
+    ///
+    /// @param data1 (int) Number of deleted unreferenced files.
+    /// @param data2 (int) Reclaimed disk space in bytes, capped at the maximum int value.
+    #[strum(props(id = "2130"))]
+    HousekeepingDone(HousekeepingStats),
 }
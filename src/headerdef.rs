@@ -17,11 +17,25 @@ pub enum HeaderDef {
     /// Delta Chat extension for message IDs in combined MDNs
     AdditionalMessageIds,
 
+    /// Per-recipient fields of delivery status notifications (RFC 3464)
+    FinalRecipient,
+    Action,
+
     ListId,
+    ListPost,
+    ListUnsubscribe,
+    ListUnsubscribePost,
     References,
     InReplyTo,
     Precedence,
+    AutoSubmitted,
+    XAutoreply,
+    XAutorespond,
+    XSpamFlag,
+    XSpamStatus,
+    Received,
     ContentType,
+    ContentLanguage,
     ChatVersion,
     ChatGroupId,
     ChatGroupName,
@@ -32,10 +46,16 @@ pub enum HeaderDef {
     ChatVoiceMessage,
     ChatGroupMemberRemoved,
     ChatGroupMemberAdded,
+    ChatGroupAdmins,
+    ChatGroupRoleChanged,
+    ChatGroupBroadcast,
     ChatContent,
     ChatDuration,
     ChatDispositionNotificationTo,
     ChatWebrtcRoom,
+
+    /// Message IDs of group messages seen by the sender
+    ChatSeen,
     Autocrypt,
     AutocryptSetupMessage,
     SecureJoin,
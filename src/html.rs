@@ -0,0 +1,298 @@
+//! # HTML sanitizer
+//!
+//! Reduces the HTML part of a received message to a form that can be
+//! rendered by the UIs without running scripts or contacting remote
+//! servers. The plaintext shown in the chat is still produced by
+//! [crate::dehtml], this module only cares about the HTML view.
+
+use quick_xml::events::{BytesStart, BytesText, Event};
+
+use crate::context::Context;
+
+/// Elements that are dropped together with all their content.
+const DROP_WITH_CONTENT: &[&str] = &[
+    "script", "style", "iframe", "object", "applet", "frameset", "noscript", "title", "svg", "math",
+];
+
+/// Elements that are dropped, their content is kept.
+const DROP_TAG_ONLY: &[&str] = &[
+    "html", "head", "body", "form", "input", "button", "select", "option", "textarea", "link",
+    "meta", "base", "embed", "frame", "param",
+];
+
+/// Maximum length of an inline `style` attribute, longer values are dropped.
+const MAX_STYLE_LEN: usize = 1000;
+
+/// Returns a sanitized version of `html`.
+///
+/// Scripts, event handlers and active content are always removed.
+/// If `show_remote_images` is false, image sources pointing to remote
+/// servers are removed as well, so that rendering the result does not
+/// leak the read status to the sender.
+///
+/// If the HTML cannot be parsed, the rest of it is added as escaped text.
+pub(crate) fn sanitize_html(context: &Context, html: &str, show_remote_images: bool) -> String {
+    let mut reader = quick_xml::Reader::from_str(html);
+    reader.check_end_names(false);
+
+    let mut out = String::with_capacity(html.len());
+    let mut buf = Vec::new();
+    // Name and nesting depth of the element whose content is currently skipped.
+    let mut skip: Option<(String, usize)> = None;
+
+    loop {
+        let pos = reader.buffer_position();
+        match reader.read_event(&mut buf) {
+            Ok(Event::Start(ref e)) => {
+                let tag = tag_name(e.name());
+                if let Some((ref skip_tag, ref mut depth)) = skip {
+                    if *skip_tag == tag {
+                        *depth += 1;
+                    }
+                } else if DROP_WITH_CONTENT.contains(&tag.as_str()) {
+                    skip = Some((tag, 1));
+                } else if !DROP_TAG_ONLY.contains(&tag.as_str()) {
+                    write_start_tag(&mut out, &tag, e, show_remote_images, false);
+                }
+            }
+            Ok(Event::Empty(ref e)) => {
+                let tag = tag_name(e.name());
+                if skip.is_none()
+                    && !DROP_WITH_CONTENT.contains(&tag.as_str())
+                    && !DROP_TAG_ONLY.contains(&tag.as_str())
+                {
+                    write_start_tag(&mut out, &tag, e, show_remote_images, true);
+                }
+            }
+            Ok(Event::End(ref e)) => {
+                let tag = tag_name(e.name());
+                if let Some((ref skip_tag, ref mut depth)) = skip {
+                    if *skip_tag == tag {
+                        *depth -= 1;
+                        if *depth == 0 {
+                            skip = None;
+                        }
+                    }
+                } else if !DROP_WITH_CONTENT.contains(&tag.as_str())
+                    && !DROP_TAG_ONLY.contains(&tag.as_str())
+                {
+                    out += "</";
+                    out += &tag;
+                    out += ">";
+                }
+            }
+            Ok(Event::Text(ref e)) | Ok(Event::CData(ref e)) => {
+                if skip.is_none() {
+                    out += &escaper::encode_minimal(&decode_text(e));
+                }
+            }
+            Ok(Event::Eof) => break,
+            // Comments, doctype and processing instructions are dropped,
+            // conditional comments are a common way to hide content.
+            Ok(_) => {}
+            Err(err) => {
+                warn!(
+                    context,
+                    "Cannot parse HTML at position {}: {}, showing the rest as text.", pos, err
+                );
+                if skip.is_none() {
+                    out += &escaper::encode_minimal(html.get(pos..).unwrap_or_default());
+                }
+                break;
+            }
+        }
+        buf.clear();
+    }
+
+    out
+}
+
+/// Returns true if `html` references images or backgrounds on remote servers.
+///
+/// These are exactly the references removed by [sanitize_html] if
+/// `show_remote_images` is false.
+pub(crate) fn has_remote_images(context: &Context, html: &str) -> bool {
+    sanitize_html(context, html, true) != sanitize_html(context, html, false)
+}
+
+fn tag_name(name: &[u8]) -> String {
+    String::from_utf8_lossy(name).trim().to_lowercase()
+}
+
+fn decode_text(event: &BytesText) -> String {
+    escaper::decode_html_buf_sloppy(event.escaped()).unwrap_or_default()
+}
+
+fn write_start_tag(
+    out: &mut String,
+    tag: &str,
+    event: &BytesStart,
+    show_remote_images: bool,
+    empty: bool,
+) {
+    *out += "<";
+    *out += tag;
+    for attr in event.html_attributes().filter_map(|attr| attr.ok()) {
+        let key = tag_name(attr.key);
+        let value = escaper::decode_html_buf_sloppy(&attr.value).unwrap_or_default();
+        if is_allowed_attribute(tag, &key, &value, show_remote_images) {
+            *out += " ";
+            *out += &key;
+            *out += "=\"";
+            *out += &escaper::encode_minimal(&value);
+            *out += "\"";
+        }
+    }
+    *out += if empty { "/>" } else { ">" };
+}
+
+fn is_allowed_attribute(tag: &str, key: &str, value: &str, show_remote_images: bool) -> bool {
+    if key.is_empty() || key.starts_with("on") {
+        // Event handlers as `onerror` or `onload` run scripts.
+        return false;
+    }
+
+    let value_lower: String = value
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_lowercase();
+
+    match key {
+        "href" | "src" | "action" | "formaction" | "background" | "poster" | "srcset"
+        | "xlink:href" => {
+            if value_lower.starts_with("javascript:") || value_lower.starts_with("vbscript:") {
+                return false;
+            }
+            if value_lower.starts_with("data:") && !(tag == "img" && key == "src") {
+                return false;
+            }
+            if !show_remote_images && key != "href" && is_remote_url(&value_lower) {
+                return false;
+            }
+            if !show_remote_images && key == "srcset" {
+                return false;
+            }
+            true
+        }
+        "style" => {
+            value.len() <= MAX_STYLE_LEN
+                && !value_lower.contains("expression")
+                && !value_lower.contains("javascript:")
+                && !value_lower.contains("@import")
+                && !value_lower.contains("behavior")
+                && !value_lower.contains("-moz-binding")
+                && (show_remote_images || !value_lower.contains("url("))
+        }
+        _ => true,
+    }
+}
+
+fn is_remote_url(value_lower: &str) -> bool {
+    value_lower.starts_with("http:")
+        || value_lower.starts_with("https:")
+        || value_lower.starts_with("//")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::TestContext;
+
+    #[async_std::test]
+    async fn test_sanitize_html_script() {
+        let t = TestContext::new().await;
+        let html = "<p>Hello</p><script>alert('pwned');</script><p>World</p>";
+        assert_eq!(
+            sanitize_html(&t.ctx, html, true),
+            "<p>Hello</p><p>World</p>"
+        );
+
+        let html = "<div><SCRIPT type=\"text/javascript\">var a = '<b>';</SCRIPT>text</div>";
+        let sanitized = sanitize_html(&t.ctx, html, true);
+        assert!(!sanitized.to_lowercase().contains("script"));
+        assert!(sanitized.contains("text"));
+    }
+
+    #[async_std::test]
+    async fn test_sanitize_html_event_handlers() {
+        let t = TestContext::new().await;
+        let html = "<img src=\"x\" onerror=\"alert(1)\" alt=\"pic\"><b OnClick='x()'>bold</b>";
+        let sanitized = sanitize_html(&t.ctx, html, true);
+        assert!(!sanitized.to_lowercase().contains("onerror"));
+        assert!(!sanitized.to_lowercase().contains("onclick"));
+        assert!(!sanitized.contains("alert"));
+        assert!(sanitized.contains("alt=\"pic\""));
+        assert!(sanitized.contains("<b>bold</b>"));
+    }
+
+    #[async_std::test]
+    async fn test_sanitize_html_links() {
+        let t = TestContext::new().await;
+        let html = "<a href=\"javascript:alert(1)\">a</a><a href=\"https://delta.chat\">b</a>";
+        assert_eq!(
+            sanitize_html(&t.ctx, html, false),
+            "<a>a</a><a href=\"https://delta.chat\">b</a>"
+        );
+    }
+
+    #[async_std::test]
+    async fn test_sanitize_html_remote_images() {
+        let t = TestContext::new().await;
+        let html = "<img src=\"https://example.org/track.png\" alt=\"x\">";
+        assert_eq!(sanitize_html(&t.ctx, html, false), "<img alt=\"x\">");
+        assert_eq!(
+            sanitize_html(&t.ctx, html, true),
+            "<img src=\"https://example.org/track.png\" alt=\"x\">"
+        );
+
+        let html = "<td style=\"background: url(https://example.org/bg.png)\">x</td>";
+        assert_eq!(sanitize_html(&t.ctx, html, false), "<td>x</td>");
+    }
+
+    #[async_std::test]
+    async fn test_has_remote_images() {
+        let t = TestContext::new().await;
+        assert!(has_remote_images(
+            &t.ctx,
+            "<p>Hi</p><img src=\"https://example.org/pixel.gif\" width=\"1\">"
+        ));
+        assert!(has_remote_images(
+            &t.ctx,
+            "<table background=\"http://example.org/bg.png\"></table>"
+        ));
+        assert!(has_remote_images(
+            &t.ctx,
+            "<div style=\"background-image: url('//example.org/a.png')\"></div>"
+        ));
+        assert!(!has_remote_images(
+            &t.ctx,
+            "<p>Hi <a href=\"https://delta.chat\">link</a></p>"
+        ));
+        assert!(!has_remote_images(
+            &t.ctx,
+            "<img src=\"data:image/png;base64,AAAA\">"
+        ));
+    }
+
+    #[async_std::test]
+    async fn test_sanitize_html_style() {
+        let t = TestContext::new().await;
+        let html = "<p style=\"width: expression(alert(1))\">x</p><p style=\"color: red\">y</p>";
+        assert_eq!(
+            sanitize_html(&t.ctx, html, false),
+            "<p>x</p><p style=\"color: red\">y</p>"
+        );
+    }
+
+    #[async_std::test]
+    async fn test_sanitize_html_broken() {
+        let t = TestContext::new().await;
+        let html = "<p>Hi</p><!-- unterminated <b onclick=\"x()\">bold</b>";
+        let sanitized = sanitize_html(&t.ctx, html, true);
+        assert!(sanitized.starts_with("<p>Hi</p>"));
+        assert!(sanitized.contains("bold"));
+        assert!(!sanitized.contains("<b"));
+    }
+}
@@ -2,14 +2,18 @@ use std::ops::{Deref, DerefMut};
 
 use async_imap::{
     error::{Error as ImapError, Result as ImapResult},
+    imap_proto::{Capability, Response},
     Client as ImapClient,
 };
 use async_std::net::{self, TcpStream};
 
 use super::session::Session;
+use crate::context::Context;
 use crate::login_param::dc_build_tls;
+use crate::trace::TraceStream;
 
 use super::session::SessionStream;
+use super::trace::ImapTrace;
 
 #[derive(Debug)]
 pub(crate) struct Client {
@@ -75,16 +79,40 @@ impl Client {
         Ok(Session { inner: session })
     }
 
+    /// Returns the SASL mechanisms advertised as `AUTH=` capabilities before login.
+    pub async fn auth_mechanisms(&mut self) -> ImapResult<Vec<String>> {
+        let id = self.inner.run_command("CAPABILITY").await?;
+        let mut mechanisms = Vec::new();
+        while let Some(response) = self.inner.read_response().await {
+            match response?.parsed() {
+                Response::Capabilities(capabilities) => {
+                    for capability in capabilities {
+                        if let Capability::Auth(mechanism) = capability {
+                            mechanisms.push(mechanism.to_string());
+                        }
+                    }
+                }
+                Response::Done { tag, .. } if *tag == id => break,
+                _ => {}
+            }
+        }
+        Ok(mechanisms)
+    }
+
+    /// Connects using TLS.
+    ///
+    /// If `trace` is set, the protocol lines are logged to this context.
     pub async fn connect_secure<A: net::ToSocketAddrs, S: AsRef<str>>(
         addr: A,
         domain: S,
         strict_tls: bool,
+        trace: Option<Context>,
     ) -> ImapResult<Self> {
         let stream = TcpStream::connect(addr).await?;
         let tls = dc_build_tls(strict_tls);
         let tls_stream: Box<dyn SessionStream> =
             Box::new(tls.connect(domain.as_ref(), stream).await?);
-        let mut client = ImapClient::new(tls_stream);
+        let mut client = ImapClient::new(traced(tls_stream, trace));
 
         let _greeting = client
             .read_response()
@@ -97,10 +125,16 @@ impl Client {
         })
     }
 
-    pub async fn connect_insecure<A: net::ToSocketAddrs>(addr: A) -> ImapResult<Self> {
+    /// Connects without TLS.
+    ///
+    /// If `trace` is set, the protocol lines are logged to this context.
+    pub async fn connect_insecure<A: net::ToSocketAddrs>(
+        addr: A,
+        trace: Option<Context>,
+    ) -> ImapResult<Self> {
         let stream: Box<dyn SessionStream> = Box::new(TcpStream::connect(addr).await?);
 
-        let mut client = ImapClient::new(stream);
+        let mut client = ImapClient::new(traced(stream, trace));
         let _greeting = client
             .read_response()
             .await
@@ -112,7 +146,15 @@ impl Client {
         })
     }
 
-    pub async fn secure<S: AsRef<str>>(self, domain: S, strict_tls: bool) -> ImapResult<Client> {
+    /// Upgrades the connection using STARTTLS.
+    ///
+    /// If `trace` is set, the protocol lines after the upgrade are logged to this context.
+    pub async fn secure<S: AsRef<str>>(
+        self,
+        domain: S,
+        strict_tls: bool,
+        trace: Option<Context>,
+    ) -> ImapResult<Client> {
         if self.is_secure {
             Ok(self)
         } else {
@@ -126,8 +168,15 @@ impl Client {
 
             Ok(Client {
                 is_secure: true,
-                inner: ImapClient::new(boxed),
+                inner: ImapClient::new(traced(boxed, trace)),
             })
         }
     }
 }
+
+fn traced(stream: Box<dyn SessionStream>, trace: Option<Context>) -> Box<dyn SessionStream> {
+    match trace {
+        Some(context) => Box::new(TraceStream::new(context, stream, ImapTrace::default())),
+        None => stream,
+    }
+}
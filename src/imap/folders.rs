@@ -0,0 +1,202 @@
+//! # IMAP folder listing
+//!
+//! Lists the folders of the IMAP server so that the user can choose
+//! the folders set as [Config::MvboxFolder] and [Config::SentboxFolder].
+//!
+//! [Config::MvboxFolder]: crate::config::Config::MvboxFolder
+//! [Config::SentboxFolder]: crate::config::Config::SentboxFolder
+
+use super::Imap;
+
+use async_imap::types::{Name, NameAttribute};
+use async_std::prelude::*;
+
+use crate::context::Context;
+use crate::error::{ensure, format_err, Result};
+
+/// Special-use attribute of a folder as defined in RFC 6154.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecialUse {
+    All,
+    Archive,
+    Drafts,
+    Flagged,
+    Junk,
+    Sent,
+    Trash,
+}
+
+impl SpecialUse {
+    /// Parses a folder attribute as `\Sent`, ignoring case.
+    fn from_attribute(attribute: &str) -> Option<Self> {
+        let special_use = match attribute.to_lowercase().as_str() {
+            "\\all" => SpecialUse::All,
+            "\\archive" => SpecialUse::Archive,
+            "\\drafts" => SpecialUse::Drafts,
+            "\\flagged" => SpecialUse::Flagged,
+            "\\junk" | "\\spam" => SpecialUse::Junk,
+            "\\sent" => SpecialUse::Sent,
+            "\\trash" => SpecialUse::Trash,
+            _ => return None,
+        };
+        Some(special_use)
+    }
+}
+
+/// A folder on the IMAP server as returned by [Context::list_imap_folders].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FolderInfo {
+    /// Full name of the folder, as used for [Config::MvboxFolder].
+    ///
+    /// [Config::MvboxFolder]: crate::config::Config::MvboxFolder
+    pub name: String,
+
+    /// Hierarchy delimiter, eg. `.` or `/`.
+    pub delimiter: Option<String>,
+
+    /// All attributes returned by the server, eg. `\HasNoChildren`.
+    pub flags: Vec<String>,
+
+    /// False if the folder is only a node in the hierarchy and cannot be selected.
+    pub selectable: bool,
+
+    /// Special use of the folder announced by the server,
+    /// always `None` if the server does not have the `SPECIAL-USE` capability.
+    pub special_use: Option<SpecialUse>,
+}
+
+impl FolderInfo {
+    pub(super) fn new(name: &str, delimiter: Option<&str>, attributes: &[NameAttribute]) -> Self {
+        let flags: Vec<String> = attributes
+            .iter()
+            .map(|attribute| match attribute {
+                NameAttribute::NoInferiors => "\\Noinferiors".to_string(),
+                NameAttribute::NoSelect => "\\Noselect".to_string(),
+                NameAttribute::Marked => "\\Marked".to_string(),
+                NameAttribute::Unmarked => "\\Unmarked".to_string(),
+                NameAttribute::Custom(label) => label.to_string(),
+            })
+            .collect();
+        let selectable = !attributes
+            .iter()
+            .any(|attribute| matches!(attribute, NameAttribute::NoSelect))
+            && !flags
+                .iter()
+                .any(|flag| flag.eq_ignore_ascii_case("\\NonExistent"));
+        let special_use = flags
+            .iter()
+            .find_map(|flag| SpecialUse::from_attribute(flag));
+
+        FolderInfo {
+            name: name.to_string(),
+            delimiter: delimiter.map(|delimiter| delimiter.to_string()),
+            flags,
+            selectable,
+            special_use,
+        }
+    }
+
+    pub(super) fn from_name(name: &Name) -> Self {
+        FolderInfo::new(name.name(), name.delimiter(), name.attributes())
+    }
+}
+
+impl Imap {
+    /// Lists all folders of the IMAP server.
+    pub async fn list_folders(&mut self, context: &Context) -> Result<Vec<FolderInfo>> {
+        self.setup_handle_if_needed(context).await?;
+
+        let session = self
+            .session
+            .as_mut()
+            .ok_or_else(|| format_err!("IMAP No Connection established"))?;
+        let mut list = session.list(Some(""), Some("*")).await?;
+        let mut folders = Vec::new();
+        while let Some(name) = list.next().await {
+            let mut folder = FolderInfo::from_name(&name?);
+            if !self.config.can_special_use {
+                folder.special_use = None;
+            }
+            folders.push(folder);
+        }
+        Ok(folders)
+    }
+}
+
+impl Context {
+    /// Lists the folders of the configured IMAP server.
+    ///
+    /// Selectable folders can be set as [Config::MvboxFolder]
+    /// and [Config::SentboxFolder], they are used from the next connect on.
+    ///
+    /// [Config::MvboxFolder]: crate::config::Config::MvboxFolder
+    /// [Config::SentboxFolder]: crate::config::Config::SentboxFolder
+    pub async fn list_imap_folders(&self) -> Result<Vec<FolderInfo>> {
+        ensure!(self.is_configured().await, "Not configured");
+
+        let (_s, r) = async_std::sync::channel(1);
+        let mut imap = Imap::new(r);
+        imap.connect_configured(self).await?;
+        let folders = imap.list_folders(self).await;
+        imap.disconnect(self).await;
+        folders
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::borrow::Cow;
+
+    #[test]
+    fn test_folder_info() {
+        let folders = vec![
+            FolderInfo::new("INBOX", Some("/"), &[NameAttribute::Unmarked]),
+            FolderInfo::new(
+                "Sent Items",
+                Some("/"),
+                &[
+                    NameAttribute::Custom(Cow::Borrowed("\\HasNoChildren")),
+                    NameAttribute::Custom(Cow::Borrowed("\\Sent")),
+                ],
+            ),
+            FolderInfo::new(
+                "Archive",
+                Some("/"),
+                &[NameAttribute::Custom(Cow::Borrowed("\\ARCHIVE"))],
+            ),
+            FolderInfo::new(
+                "[Gmail]",
+                Some("/"),
+                &[
+                    NameAttribute::NoSelect,
+                    NameAttribute::Custom(Cow::Borrowed("\\HasChildren")),
+                ],
+            ),
+            FolderInfo::new(
+                "[Gmail]/Spam",
+                Some("/"),
+                &[NameAttribute::Custom(Cow::Borrowed("\\Junk"))],
+            ),
+        ];
+
+        assert_eq!(
+            folders[0],
+            FolderInfo {
+                name: "INBOX".to_string(),
+                delimiter: Some("/".to_string()),
+                flags: vec!["\\Unmarked".to_string()],
+                selectable: true,
+                special_use: None,
+            }
+        );
+        assert_eq!(folders[1].special_use, Some(SpecialUse::Sent));
+        assert_eq!(folders[1].flags, vec!["\\HasNoChildren", "\\Sent"]);
+        assert!(folders[1].selectable);
+        assert_eq!(folders[2].special_use, Some(SpecialUse::Archive));
+        assert!(!folders[3].selectable);
+        assert_eq!(folders[3].special_use, None);
+        assert_eq!(folders[4].special_use, Some(SpecialUse::Junk));
+    }
+}
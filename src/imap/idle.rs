@@ -163,7 +163,7 @@ impl Imap {
                         match self.fetch_new_messages(context, watch_folder).await {
                             Ok(res) => {
                                 info!(context, "fetch_new_messages returned {:?}", res);
-                                if res {
+                                if res > 0 {
                                     break InterruptInfo::new(false, None);
                                 }
                             }
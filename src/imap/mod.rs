@@ -3,11 +3,11 @@
 //! uses [async-email/async-imap](https://github.com/async-email/async-imap)
 //! to implement connect, fetch, delete functionality with standard IMAP servers.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use async_imap::{
     error::Result as ImapResult,
-    types::{Capability, Fetch, Flag, Mailbox, Name, NameAttribute},
+    types::{Capability, Fetch, Flag, Mailbox},
 };
 use async_std::prelude::*;
 use async_std::sync::Receiver;
@@ -26,7 +26,7 @@ use crate::job::{self, Action};
 use crate::login_param::{CertificateChecks, LoginParam, ServerLoginParam};
 use crate::message::{self, update_server_uid, MessageState};
 use crate::mimeparser;
-use crate::oauth2::dc_get_oauth2_access_token;
+use crate::oauth2::{dc_get_oauth2_access_token, SaslMechanism};
 use crate::param::Params;
 use crate::provider::{get_provider_info, Socket};
 use crate::{
@@ -34,12 +34,15 @@ use crate::{
 };
 
 mod client;
+mod folders;
 mod idle;
 pub mod select_folder;
 mod session;
+mod trace;
 
 use chat::get_chat_id_by_grpid;
 use client::Client;
+pub use folders::{FolderInfo, SpecialUse};
 use message::Message;
 use session::Session;
 
@@ -70,6 +73,10 @@ const JUST_UID: &str = "(UID)";
 const BODY_FLAGS: &str = "(FLAGS BODY.PEEK[])";
 const SELECT_ALL: &str = "1:*";
 
+/// Limits for [Config::ImapFetchBatchSize].
+const MIN_FETCH_BATCH_SIZE: usize = 1;
+const MAX_FETCH_BATCH_SIZE: usize = 1000;
+
 #[derive(Debug)]
 pub struct Imap {
     idle_interrupt: Receiver<InterruptInfo>,
@@ -83,6 +90,7 @@ pub struct Imap {
 
 #[derive(Debug)]
 struct OAuth2 {
+    mechanism: SaslMechanism,
     user: String,
     access_token: String,
 }
@@ -91,20 +99,11 @@ impl async_imap::Authenticator for OAuth2 {
     type Response = String;
 
     fn process(&mut self, _data: &[u8]) -> Self::Response {
-        format!(
-            "user={}\x01auth=Bearer {}\x01\x01",
-            self.user, self.access_token
-        )
+        self.mechanism
+            .initial_response(&self.user, &self.access_token)
     }
 }
 
-#[derive(Debug, PartialEq)]
-enum FolderMeaning {
-    Unknown,
-    SentObjects,
-    Other,
-}
-
 #[derive(Debug)]
 struct ImapConfig {
     pub addr: String,
@@ -114,11 +113,18 @@ struct ImapConfig {
     pub selected_folder: Option<String>,
     pub selected_mailbox: Option<Mailbox>,
     pub selected_folder_needs_expunge: bool,
+
+    /// Number of messages marked as deleted since the last [Imap::report_deleted_msgs].
+    pub deleted_msgs_cnt: usize,
     pub can_idle: bool,
 
     /// True if the server has MOVE capability as defined in
     /// https://tools.ietf.org/html/rfc6851
     pub can_move: bool,
+
+    /// True if the server has SPECIAL-USE capability as defined in
+    /// https://tools.ietf.org/html/rfc6154
+    pub can_special_use: bool,
 }
 
 impl Default for ImapConfig {
@@ -131,8 +137,10 @@ impl Default for ImapConfig {
             selected_folder: None,
             selected_mailbox: None,
             selected_folder_needs_expunge: false,
+            deleted_msgs_cnt: 0,
             can_idle: false,
             can_move: false,
+            can_special_use: false,
         }
     }
 }
@@ -175,6 +183,11 @@ impl Imap {
         }
 
         let oauth2 = self.config.oauth2;
+        let trace = if context.get_config_bool(Config::DebugTraceProtocol).await {
+            Some(context.clone())
+        } else {
+            None
+        };
 
         let connection_res: ImapResult<Client> = if self.config.lp.security == Socket::STARTTLS
             || self.config.lp.security == Socket::Plain
@@ -183,26 +196,31 @@ impl Imap {
             let imap_server: &str = config.lp.server.as_ref();
             let imap_port = config.lp.port;
 
-            match Client::connect_insecure((imap_server, imap_port)).await {
-                Ok(client) => {
-                    if config.lp.security == Socket::STARTTLS {
-                        client.secure(imap_server, config.strict_tls).await
-                    } else {
-                        Ok(client)
-                    }
+            if config.lp.security == Socket::STARTTLS {
+                // only the encrypted part of the connection is traced
+                match Client::connect_insecure((imap_server, imap_port), None).await {
+                    Ok(client) => client.secure(imap_server, config.strict_tls, trace).await,
+                    Err(err) => Err(err),
                 }
-                Err(err) => Err(err),
+            } else {
+                Client::connect_insecure((imap_server, imap_port), trace).await
             }
         } else {
             let config = &self.config;
             let imap_server: &str = config.lp.server.as_ref();
             let imap_port = config.lp.port;
 
-            Client::connect_secure((imap_server, imap_port), imap_server, config.strict_tls).await
+            Client::connect_secure(
+                (imap_server, imap_port),
+                imap_server,
+                config.strict_tls,
+                trace,
+            )
+            .await
         };
 
         let login_res = match connection_res {
-            Ok(client) => {
+            Ok(mut client) => {
                 let config = &self.config;
                 let imap_user: &str = config.lp.user.as_ref();
                 let imap_pw: &str = config.lp.password.as_ref();
@@ -213,11 +231,22 @@ impl Imap {
                     if let Some(token) =
                         dc_get_oauth2_access_token(context, addr, imap_pw, true).await
                     {
+                        let advertised = match client.auth_mechanisms().await {
+                            Ok(advertised) => advertised,
+                            Err(err) => {
+                                warn!(context, "IMAP could not get capabilities: {}", err);
+                                Vec::new()
+                            }
+                        };
+                        let forced = SaslMechanism::from_config(context).await;
+                        let mechanism = SaslMechanism::select(&advertised, forced);
+                        info!(context, "IMAP authenticating using {}", mechanism.as_str());
                         let auth = OAuth2 {
+                            mechanism,
                             user: imap_user.into(),
                             access_token: token,
                         };
-                        client.authenticate("XOAUTH2", auth).await
+                        client.authenticate(mechanism.as_str(), auth).await
                     } else {
                         bail!("IMAP Could not get OAUTH token");
                     }
@@ -316,6 +345,7 @@ impl Imap {
 
         cfg.can_idle = false;
         cfg.can_move = false;
+        cfg.can_special_use = false;
     }
 
     /// Connects to imap account using already-configured parameters.
@@ -341,7 +371,8 @@ impl Imap {
         {
             bail!("IMAP Connection Failed with params {}: {}", param, err);
         } else {
-            self.ensure_configured_folders(context, true).await
+            let create_mvbox = !context.get_config_bool(Config::OnlyFetchInbox).await;
+            self.ensure_configured_folders(context, create_mvbox).await
         }
     }
 
@@ -390,6 +421,7 @@ impl Imap {
                     } else {
                         let can_idle = caps.has_str("IDLE");
                         let can_move = caps.has_str("MOVE");
+                        let can_special_use = caps.has_str("SPECIAL-USE");
                         let caps_list = caps.iter().fold(String::new(), |s, c| {
                             if let Capability::Atom(x) = c {
                                 s + &format!(" {}", x)
@@ -400,6 +432,7 @@ impl Imap {
 
                         self.config.can_idle = can_idle;
                         self.config.can_move = can_move;
+                        self.config.can_special_use = can_special_use;
                         self.connected = true;
                         emit_event!(
                             context,
@@ -439,12 +472,66 @@ impl Imap {
         }
         self.setup_handle_if_needed(context).await?;
 
-        while self.fetch_new_messages(context, &watch_folder).await? {
+        // read receipts fetched together are emitted together
+        let _batch = context.batch_msg_states();
+        while self.fetch_new_messages(context, &watch_folder).await? > 0 {
             // We fetch until no more new messages are there.
         }
         Ok(())
     }
 
+    /// Fetches new messages from `folder` like [Imap::fetch]
+    /// and returns the number of messages read.
+    ///
+    /// Unlike [Imap::fetch], a folder without stored state also gets
+    /// the most recent messages already in it, as many as the inbox
+    /// gets after configuring, see [Config::FetchRecentMsgs].
+    pub(crate) async fn fetch_folder(&mut self, context: &Context, folder: &str) -> Result<usize> {
+        if !context.sql.is_open().await {
+            // probably shutdown
+            bail!("IMAP operation attempted while it is torn down");
+        }
+        self.setup_handle_if_needed(context).await?;
+
+        let (uid_validity, _) = self.get_config_last_seen_uid(context, folder).await;
+        if uid_validity == 0 {
+            let count = context.get_config_int(Config::FetchRecentMsgs).await;
+            if count > 0 {
+                self.fetch_recent_msgs(context, folder, count as u32)
+                    .await?;
+            }
+        }
+
+        let mut read_cnt = 0;
+        loop {
+            match self.fetch_new_messages(context, folder).await? {
+                0 => break,
+                cnt => read_cnt += cnt,
+            }
+        }
+        Ok(read_cnt)
+    }
+
+    /// Fetches all folders known from previous fetches and the configured folders
+    /// from scratch, as if no message had been seen before.
+    ///
+    /// Messages already in the database are not downloaded again.
+    /// Progress is reported by [EventType::RescanProgress].
+    pub(crate) async fn rescan_folders(&mut self, context: &Context) -> Result<()> {
+        let folders = known_folders(context).await?;
+        for (done, folder) in folders.iter().enumerate() {
+            reset_last_seen_uid(context, folder).await?;
+            match self.fetch_folder(context, folder).await {
+                Ok(cnt) => info!(context, "Rescan: {} mails read from {}", cnt, folder),
+                Err(err) => warn!(context, "Rescan: could not fetch {}: {}", folder, err),
+            }
+            let permille = (done + 1) * 1000 / folders.len();
+            context.emit_event(EventType::RescanProgress(permille.min(999)));
+        }
+        context.emit_event(EventType::RescanProgress(1000));
+        Ok(())
+    }
+
     async fn get_config_last_seen_uid<S: AsRef<str>>(
         &self,
         context: &Context,
@@ -646,7 +733,7 @@ impl Imap {
         &mut self,
         context: &Context,
         folder: S,
-    ) -> Result<bool> {
+    ) -> Result<usize> {
         let show_emails = ShowEmails::from_i32(context.get_config_int(Config::ShowEmails).await)
             .unwrap_or_default();
 
@@ -717,7 +804,82 @@ impl Imap {
             );
         }
 
-        Ok(read_cnt > 0)
+        Ok(read_cnt)
+    }
+
+    /// Fetches up to `count` of the most recent messages in `folder`
+    /// that arrived before the last seen message.
+    ///
+    /// This is done once after configuring, so that the chatlist is not
+    /// empty; messages arriving later are fetched by [Imap::fetch].
+    pub(crate) async fn fetch_recent_msgs(
+        &mut self,
+        context: &Context,
+        folder: &str,
+        count: u32,
+    ) -> Result<()> {
+        let show_emails = ShowEmails::from_i32(context.get_config_int(Config::ShowEmails).await)
+            .unwrap_or_default();
+
+        let (_, last_seen_uid) = self.select_with_uidvalidity(context, folder).await?;
+        let exists = self
+            .config
+            .selected_mailbox
+            .as_ref()
+            .map(|mailbox| mailbox.exists)
+            .unwrap_or_default();
+        let set = match recent_seq_set(exists, count) {
+            Some(set) => set,
+            None => return Ok(()),
+        };
+
+        let session = match self.session {
+            Some(ref mut session) => session,
+            None => bail!("IMAP No Connection established"),
+        };
+        // fetch by sequence number, the last `count` messages are wanted
+        let mut list = session
+            .fetch(set, PREFETCH_FLAGS)
+            .await
+            .map_err(|err| format_err!("IMAP Could not fetch: {}", err))?;
+        let mut msgs = BTreeMap::new();
+        while let Some(fetch) = list.next().await {
+            let msg = fetch?;
+            match msg.uid {
+                // newer messages are fetched by fetch_new_messages()
+                Some(uid) if uid <= last_seen_uid => {
+                    msgs.insert(uid, msg);
+                }
+                _ => {}
+            }
+        }
+        drop(list);
+
+        let mut uids = Vec::with_capacity(msgs.len());
+        for (uid, msg) in msgs.into_iter() {
+            let headers = match get_fetch_headers(&msg) {
+                Ok(headers) => headers,
+                Err(err) => {
+                    warn!(context, "{}", err);
+                    continue;
+                }
+            };
+            let msg_id = prefetch_get_message_id(&headers).unwrap_or_default();
+            if message_needs_processing(context, uid, &headers, &msg_id, folder, show_emails).await
+            {
+                uids.push(uid);
+            }
+        }
+
+        let (_, read_errors) = self.fetch_many_msgs(context, folder, &uids).await;
+        info!(
+            context,
+            "{} recent mails fetched from \"{}\" with {} errors.",
+            uids.len(),
+            folder,
+            read_errors
+        );
+        Ok(())
     }
 
     /// Fetch all uids larger than the passed in. Returns a sorted list of fetch results.
@@ -788,6 +950,8 @@ impl Imap {
     /// Fetches a list of messages by server UID.
     /// The passed in list of uids must be sorted.
     ///
+    /// At most [Config::ImapFetchBatchSize] messages are fetched per `UID FETCH` command.
+    ///
     /// Returns the last uid fetch successfully and an error count.
     async fn fetch_many_msgs<S: AsRef<str>>(
         &mut self,
@@ -795,17 +959,9 @@ impl Imap {
         folder: S,
         server_uids: &[u32],
     ) -> (Option<u32>, usize) {
-        let set = match server_uids {
-            [] => return (None, 0),
-            [server_uid] => server_uid.to_string(),
-            [first_uid, .., last_uid] => {
-                // XXX: it is assumed that UIDs are sorted and
-                // contiguous. If UIDs are not contiguous, more
-                // messages than needed will be downloaded.
-                debug_assert!(first_uid < last_uid, "uids must be sorted");
-                format!("{}:{}", first_uid, last_uid)
-            }
-        };
+        if server_uids.is_empty() {
+            return (None, 0);
+        }
 
         if !self.is_connected() {
             warn!(context, "Not connected");
@@ -819,61 +975,70 @@ impl Imap {
             return (None, server_uids.len());
         }
 
-        let session = self.session.as_mut().unwrap();
-
-        let mut msgs = match session.uid_fetch(&set, BODY_FLAGS).await {
-            Ok(msgs) => msgs,
-            Err(err) => {
-                // TODO: maybe differentiate between IO and input/parsing problems
-                // so we don't reconnect if we have a (rare) input/output parsing problem?
-                self.should_reconnect = true;
-                warn!(
-                    context,
-                    "Error on fetching messages #{} from folder \"{}\"; error={}.",
-                    &set,
-                    folder.as_ref(),
-                    err
-                );
-                return (None, server_uids.len());
-            }
-        };
-
+        let batch_size = fetch_batch_size(context).await;
         let folder = folder.as_ref().to_string();
 
         let mut read_errors = 0;
         let mut last_uid = None;
         let mut count = 0;
 
-        while let Some(Ok(msg)) = msgs.next().await {
-            let server_uid = msg.uid.unwrap_or_default();
-
-            if !server_uids.contains(&server_uid) {
-                // skip if there are some in between we are not interested in
-                continue;
-            }
-            count += 1;
+        for (batch, set) in server_uids
+            .chunks(batch_size)
+            .zip(build_fetch_sets(server_uids, batch_size))
+        {
+            let session = self.session.as_mut().unwrap();
 
-            let is_deleted = msg.flags().any(|flag| flag == Flag::Deleted);
-            if is_deleted || msg.body().is_none() {
-                // No need to process these.
-                continue;
-            }
+            let mut msgs = match session.uid_fetch(&set, BODY_FLAGS).await {
+                Ok(msgs) => msgs,
+                Err(err) => {
+                    // TODO: maybe differentiate between IO and input/parsing problems
+                    // so we don't reconnect if we have a (rare) input/output parsing problem?
+                    self.should_reconnect = true;
+                    warn!(
+                        context,
+                        "Error on fetching messages #{} from folder \"{}\"; error={}.",
+                        &set,
+                        folder,
+                        err
+                    );
+                    // this and all following batches are not fetched
+                    return (last_uid, read_errors + server_uids.len() - count);
+                }
+            };
 
-            // XXX put flags into a set and pass them to dc_receive_imf
-            let context = context.clone();
-            let folder = folder.clone();
+            let mut batch_count = 0;
+            while let Some(Ok(msg)) = msgs.next().await {
+                let server_uid = msg.uid.unwrap_or_default();
 
-            // safe, as we checked above that there is a body.
-            let body = msg.body().unwrap();
-            let is_seen = msg.flags().any(|flag| flag == Flag::Seen);
+                if !batch.contains(&server_uid) {
+                    // skip if there are some in between we are not interested in
+                    continue;
+                }
+                batch_count += 1;
 
-            match dc_receive_imf(&context, &body, &folder, server_uid, is_seen).await {
-                Ok(_) => last_uid = Some(server_uid),
-                Err(err) => {
-                    warn!(context, "dc_receive_imf error: {}", err);
-                    read_errors += 1;
+                let is_deleted = msg.flags().any(|flag| flag == Flag::Deleted);
+                if is_deleted || msg.body().is_none() {
+                    // No need to process these.
+                    continue;
                 }
-            };
+
+                // XXX put flags into a set and pass them to dc_receive_imf
+                let context = context.clone();
+                let folder = folder.clone();
+
+                // safe, as we checked above that there is a body.
+                let body = msg.body().unwrap();
+                let is_seen = msg.flags().any(|flag| flag == Flag::Seen);
+
+                match dc_receive_imf(&context, &body, &folder, server_uid, is_seen).await {
+                    Ok(_) => last_uid = Some(server_uid),
+                    Err(err) => {
+                        warn!(context, "dc_receive_imf error: {}", err);
+                        read_errors += 1;
+                    }
+                };
+            }
+            count += batch_count;
         }
 
         if count != server_uids.len() {
@@ -1182,10 +1347,21 @@ impl Imap {
                 ))
             );
             self.config.selected_folder_needs_expunge = true;
+            self.config.deleted_msgs_cnt += 1;
             ImapActionResult::Success
         }
     }
 
+    /// Emits a summary event for the messages deleted by [Imap::delete_msg]
+    /// since the last call.
+    pub(crate) fn report_deleted_msgs(&mut self, context: &Context) {
+        let cnt = std::mem::replace(&mut self.config.deleted_msgs_cnt, 0);
+        if cnt > 0 {
+            info!(context, "Deleted {} messages on the server.", cnt);
+            context.emit_event(EventType::ServerMsgsDeleted(cnt));
+        }
+    }
+
     pub async fn ensure_configured_folders(
         &mut self,
         context: &Context,
@@ -1217,9 +1393,7 @@ impl Imap {
 
             let mut delimiter = ".".to_string();
             let mut delimiter_is_default = true;
-            let mut sentbox_folder = None;
-            let mut mvbox_folder = None;
-            let mut fallback_folder = get_fallback_folder(&delimiter);
+            let mut folder_infos = Vec::new();
 
             while let Some(folder) = folders.next().await {
                 let folder = folder?;
@@ -1229,30 +1403,33 @@ impl Imap {
                 if let Some(d) = folder.delimiter() {
                     if delimiter_is_default && !d.is_empty() && delimiter != d {
                         delimiter = d.to_string();
-                        fallback_folder = get_fallback_folder(&delimiter);
                         delimiter_is_default = false;
                     }
                 }
+                folder_infos.push(FolderInfo::from_name(&folder));
+            }
+            drop(folders);
 
-                if folder.name() == "DeltaChat" {
-                    // Always takes precendent
-                    mvbox_folder = Some(folder.name().to_string());
-                } else if folder.name() == fallback_folder {
-                    // only set iff none has been already set
-                    if mvbox_folder.is_none() {
-                        mvbox_folder = Some(folder.name().to_string());
-                    }
-                } else if let FolderMeaning::SentObjects = get_folder_meaning(&folder) {
-                    // Always takes precedent
-                    sentbox_folder = Some(folder.name().to_string());
-                } else if let FolderMeaning::SentObjects = get_folder_meaning_by_name(&folder) {
-                    // only set iff none has been already set
-                    if sentbox_folder.is_none() {
-                        sentbox_folder = Some(folder.name().to_string());
+            let fallback_folder = get_fallback_folder(&delimiter);
+            let (mut mvbox_folder, mut sentbox_folder) =
+                detect_folders(&folder_infos, &fallback_folder, self.config.can_special_use);
+
+            // folders chosen by the user take precedence as long as they exist
+            for (key, folder) in vec![
+                (Config::MvboxFolder, &mut mvbox_folder),
+                (Config::SentboxFolder, &mut sentbox_folder),
+            ] {
+                if let Some(configured) = context.get_config(key).await {
+                    if folder_infos
+                        .iter()
+                        .any(|info| info.selectable && info.name == configured)
+                    {
+                        *folder = Some(configured);
+                    } else {
+                        warn!(context, "{} {:?} does not exist", key, configured);
                     }
                 }
             }
-            drop(folders);
 
             info!(context, "Using \"{}\" as folder-delimiter.", delimiter);
             info!(context, "sentbox folder is {:?}", sentbox_folder);
@@ -1372,36 +1549,51 @@ impl Imap {
     }
 }
 
-/// Try to get the folder meaning by the name of the folder only used if the server does not support XLIST.
+/// Finds the mvbox and the sentbox among the `folders` of the server.
+///
+/// If the server has the `SPECIAL-USE` capability, the sentbox is the folder
+/// with the RFC 6154 `\Sent` special-use attribute. The folder names are only
+/// checked if no folder has this attribute or the server does not announce
+/// the capability, in which case the attributes are ignored.
+fn detect_folders(
+    folders: &[FolderInfo],
+    fallback_folder: &str,
+    special_use: bool,
+) -> (Option<String>, Option<String>) {
+    let mvbox_folder = folders
+        .iter()
+        .find(|folder| folder.name == "DeltaChat")
+        .or_else(|| folders.iter().find(|folder| folder.name == fallback_folder))
+        .map(|folder| folder.name.clone());
+
+    let sentbox_folder = folders
+        .iter()
+        .filter(|folder| folder.selectable && special_use)
+        .find(|folder| folder.special_use == Some(SpecialUse::Sent))
+        .or_else(|| {
+            folders.iter().find(|folder| {
+                folder.selectable
+                    && (!special_use || folder.special_use.is_none())
+                    && is_sent_folder_name(&folder.name)
+            })
+        })
+        .map(|folder| folder.name.clone());
+
+    (mvbox_folder, sentbox_folder)
+}
+
+/// Checks if the folder name is a common name of the sent folder,
+/// only used if the server does not announce special-use attributes.
 // TODO: lots languages missing - maybe there is a list somewhere on other MUAs?
 // however, if we fail to find out the sent-folder,
 // only watching this folder is not working. at least, this is no show stopper.
 // CAVE: if possible, take care not to add a name here that is "sent" in one language
 // but sth. different in others - a hard job.
-fn get_folder_meaning_by_name(folder_name: &Name) -> FolderMeaning {
+fn is_sent_folder_name(folder_name: &str) -> bool {
     let sent_names = vec!["sent", "sentmail", "sent objects", "gesendet"];
-    let lower = folder_name.name().to_lowercase();
+    let lower = folder_name.to_lowercase();
 
-    if sent_names.into_iter().any(|s| s == lower) {
-        FolderMeaning::SentObjects
-    } else {
-        FolderMeaning::Unknown
-    }
-}
-
-fn get_folder_meaning(folder_name: &Name) -> FolderMeaning {
-    let special_names = vec!["\\Spam", "\\Trash", "\\Drafts", "\\Junk"];
-
-    for attr in folder_name.attributes() {
-        if let NameAttribute::Custom(ref label) = attr {
-            if special_names.iter().any(|s| *s == label) {
-                return FolderMeaning::Other;
-            } else if label == "\\Sent" {
-                return FolderMeaning::SentObjects;
-            }
-        }
-    }
-    FolderMeaning::Unknown
+    sent_names.into_iter().any(|s| s == lower)
 }
 
 async fn precheck_imf(
@@ -1476,7 +1668,22 @@ async fn precheck_imf(
 
         if old_server_folder != server_folder || old_server_uid != server_uid {
             update_server_uid(context, rfc724_mid, server_folder, server_uid).await;
-            if let Ok(MessageState::InSeen) = msg_id.get_state(context).await {
+            if !old_server_folder.is_empty()
+                && old_server_folder != server_folder
+                && msg_id
+                    .needs_move(context, server_folder)
+                    .await
+                    .unwrap_or_default()
+            {
+                // the message was already imported from another folder, eg. from the mvbox;
+                // move this copy as well instead of leaving it in the inbox.
+                // The MarkSeen job is added after the Move Job completed.
+                job::add(
+                    context,
+                    job::Job::new(Action::MoveMsg, msg_id.to_u32(), Params::new(), 0),
+                )
+                .await;
+            } else if let Ok(MessageState::InSeen) = msg_id.get_state(context).await {
                 job::add(
                     context,
                     job::Job::new(Action::MarkseenMsgOnImap, msg_id.to_u32(), Params::new(), 0),
@@ -1578,6 +1785,41 @@ pub(crate) async fn prefetch_should_download(
     Ok(should_download)
 }
 
+/// Returns the number of messages fetched per `UID FETCH` command,
+/// [Config::ImapFetchBatchSize] limited to a sane range.
+async fn fetch_batch_size(context: &Context) -> usize {
+    let batch_size = context.get_config_int(Config::ImapFetchBatchSize).await;
+    (batch_size.max(0) as usize)
+        .max(MIN_FETCH_BATCH_SIZE)
+        .min(MAX_FETCH_BATCH_SIZE)
+}
+
+/// Splits sorted `uids` into UID sets of at most `batch_size` messages each.
+///
+/// It is assumed that UIDs are contiguous,
+/// otherwise more messages than needed are downloaded.
+fn build_fetch_sets(uids: &[u32], batch_size: usize) -> Vec<String> {
+    uids.chunks(batch_size.max(1))
+        .map(|batch| match batch {
+            [] => String::new(),
+            [uid] => uid.to_string(),
+            [first_uid, .., last_uid] => {
+                debug_assert!(first_uid < last_uid, "uids must be sorted");
+                format!("{}:{}", first_uid, last_uid)
+            }
+        })
+        .collect()
+}
+
+/// Returns the IMAP sequence set of the last `count` of `exists` messages.
+fn recent_seq_set(exists: u32, count: u32) -> Option<String> {
+    if exists == 0 || count == 0 {
+        None
+    } else {
+        Some(format!("{}:*", exists.saturating_sub(count) + 1))
+    }
+}
+
 async fn message_needs_processing(
     context: &Context,
     current_uid: u32,
@@ -1625,6 +1867,465 @@ async fn message_needs_processing(
     true
 }
 
+/// Returns the configured folders and all folders a last seen UID is stored for.
+async fn known_folders(context: &Context) -> Result<Vec<String>> {
+    let mut folders: BTreeSet<String> = context
+        .sql
+        .query_map(
+            "SELECT keyname FROM config WHERE keyname LIKE 'imap.mailbox.%';",
+            paramsv![],
+            |row| row.get::<_, String>(0),
+            |rows| {
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?
+        .into_iter()
+        .filter_map(|key: String| {
+            key.get("imap.mailbox.".len()..)
+                .map(|folder| folder.to_string())
+        })
+        .collect();
+    for config in &[
+        Config::ConfiguredInboxFolder,
+        Config::ConfiguredMvboxFolder,
+        Config::ConfiguredSentboxFolder,
+    ] {
+        if let Some(folder) = context.get_config(*config).await {
+            folders.insert(folder);
+        }
+    }
+    Ok(folders.into_iter().collect())
+}
+
+/// Sets the last seen UID of `folder` to 0, keeping the UIDVALIDITY,
+/// so that the next fetch gets all messages of the folder.
+async fn reset_last_seen_uid(context: &Context, folder: &str) -> Result<()> {
+    let key = format!("imap.mailbox.{}", folder);
+    if let Some(entry) = context.sql.get_raw_config(context, &key).await {
+        let uid_validity = entry.split(':').next().unwrap_or_default();
+        context
+            .sql
+            .set_raw_config(context, &key, Some(&format!("{}:0", uid_validity)))
+            .await?;
+    }
+    Ok(())
+}
+
+impl Context {
+    /// Schedules fetching all known folders from scratch,
+    /// eg. to get messages skipped while a feature was disabled.
+    ///
+    /// The folders are fetched one after another on the inbox connection,
+    /// between the other IMAP jobs.  Progress is reported by [EventType::RescanProgress].
+    pub async fn resync_all_folders(&self) {
+        job::add(
+            self,
+            job::Job::new(Action::RescanFolders, 0, Params::new(), 0),
+        )
+        .await;
+    }
+}
+
 fn get_fallback_folder(delimiter: &str) -> String {
     format!("INBOX{}DeltaChat", delimiter)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use async_imap::types::NameAttribute;
+
+    #[test]
+    fn test_recent_seq_set() {
+        assert_eq!(recent_seq_set(0, 100), None);
+        assert_eq!(recent_seq_set(10, 0), None);
+        assert_eq!(recent_seq_set(10, 100), Some("1:*".to_string()));
+        assert_eq!(recent_seq_set(250, 100), Some("151:*".to_string()));
+        assert_eq!(recent_seq_set(1, 1), Some("1:*".to_string()));
+    }
+
+    /// Serves one IMAP connection with a single folder of `exists` messages,
+    /// FETCH returns no messages.  Returns the received commands without tags.
+    async fn serve_folder(listener: async_std::net::TcpListener, exists: u32) -> Vec<String> {
+        use async_std::io::BufReader;
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut reader = BufReader::new(&stream);
+        let mut writer = &stream;
+        writer.write_all(b"* OK IMAP4rev1 ready\r\n").await.unwrap();
+
+        let mut commands = Vec::new();
+        let mut line = String::new();
+        while reader.read_line(&mut line).await.unwrap_or_default() > 0 {
+            let mut parts = line.trim_end().splitn(2, ' ');
+            let tag = parts.next().unwrap_or_default().to_string();
+            let command = parts.next().unwrap_or_default().to_string();
+            let untagged = if command.starts_with("CAPABILITY") {
+                "* CAPABILITY IMAP4rev1\r\n".to_string()
+            } else if command.starts_with("SELECT") {
+                format!(
+                    "* {} EXISTS\r\n\
+                     * OK [UIDVALIDITY 7] UIDs valid\r\n\
+                     * OK [UIDNEXT {}] Predicted next UID\r\n",
+                    exists,
+                    exists + 50
+                )
+            } else if command.starts_with("LOGOUT") {
+                "* BYE\r\n".to_string()
+            } else {
+                String::new()
+            };
+            writer
+                .write_all(format!("{}{} OK done\r\n", untagged, tag).as_bytes())
+                .await
+                .unwrap();
+            commands.push(command);
+            line.clear();
+        }
+        commands
+    }
+
+    #[async_std::test]
+    async fn test_fetch_folder_first_time() {
+        let t = crate::test_utils::TestContext::new().await;
+        let listener = async_std::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = async_std::task::spawn(serve_folder(listener, 250));
+
+        let mut imap = Imap::new(async_std::sync::channel(1).1);
+        let lp = ServerLoginParam {
+            server: "127.0.0.1".to_string(),
+            user: "alice".to_string(),
+            password: "secret".to_string(),
+            port,
+            security: Socket::Plain,
+            certificate_checks: CertificateChecks::Automatic,
+        };
+        imap.connect(&t.ctx, &lp, "alice@example.org", false)
+            .await
+            .unwrap();
+        assert_eq!(imap.fetch_folder(&t.ctx, "Archive").await.unwrap(), 0);
+        // the next fetch starts after the messages that were already there
+        assert_eq!(
+            imap.get_config_last_seen_uid(&t.ctx, "Archive").await,
+            (7, 299)
+        );
+        imap.disconnect(&t.ctx).await;
+
+        // only the 100 most recent messages are fetched, not all from UID 1
+        let commands = server.await;
+        assert!(
+            commands.iter().any(|c| c.starts_with("FETCH 151:* ")),
+            "{:?}",
+            commands
+        );
+        assert!(
+            commands.iter().any(|c| c.starts_with("UID FETCH 300:* ")),
+            "{:?}",
+            commands
+        );
+        assert!(
+            !commands.iter().any(|c| c.starts_with("UID FETCH 1:")),
+            "{:?}",
+            commands
+        );
+    }
+
+    #[async_std::test]
+    async fn test_fetch_batch_size() {
+        let t = crate::test_utils::TestContext::new().await;
+        assert_eq!(fetch_batch_size(&t.ctx).await, 100);
+
+        let uids: Vec<u32> = (1..=250).collect();
+        assert_eq!(
+            build_fetch_sets(&uids, fetch_batch_size(&t.ctx).await),
+            vec!["1:100", "101:200", "201:250"]
+        );
+
+        t.ctx
+            .set_config(Config::ImapFetchBatchSize, Some("30"))
+            .await
+            .unwrap();
+        let sets = build_fetch_sets(&uids, fetch_batch_size(&t.ctx).await);
+        assert_eq!(sets.len(), 9);
+        assert_eq!(sets[0], "1:30");
+        assert_eq!(sets[8], "241:250");
+
+        t.ctx
+            .set_config(Config::ImapFetchBatchSize, Some("0"))
+            .await
+            .unwrap();
+        assert_eq!(fetch_batch_size(&t.ctx).await, MIN_FETCH_BATCH_SIZE);
+        assert_eq!(build_fetch_sets(&[3, 5], 1), vec!["3", "5"]);
+
+        t.ctx
+            .set_config(Config::ImapFetchBatchSize, Some("1000000"))
+            .await
+            .unwrap();
+        assert_eq!(fetch_batch_size(&t.ctx).await, MAX_FETCH_BATCH_SIZE);
+        assert_eq!(build_fetch_sets(&uids, MAX_FETCH_BATCH_SIZE), vec!["1:250"]);
+    }
+
+    #[async_std::test]
+    async fn test_report_deleted_msgs() {
+        let t = crate::test_utils::TestContext::new().await;
+        let mut imap = Imap::new(async_std::sync::channel(1).1);
+        let emitter = t.ctx.get_event_emitter();
+        let deleted_events = || {
+            let mut events = Vec::new();
+            while let Ok(event) = emitter.try_recv() {
+                if let EventType::ServerMsgsDeleted(cnt) = event.typ {
+                    events.push(cnt);
+                }
+            }
+            events
+        };
+
+        imap.report_deleted_msgs(&t.ctx);
+        assert!(deleted_events().is_empty());
+
+        imap.config.deleted_msgs_cnt = 3;
+        imap.report_deleted_msgs(&t.ctx);
+        assert_eq!(deleted_events(), vec![3]);
+        imap.report_deleted_msgs(&t.ctx);
+        assert!(deleted_events().is_empty());
+    }
+
+    #[async_std::test]
+    async fn test_rescan_resets_last_seen_uid() {
+        let t = crate::test_utils::TestContext::new().await;
+        let imap = Imap::new(async_std::sync::channel(1).1);
+        for (folder, last_seen) in &[("INBOX", "100:42"), ("Sent", "200:7"), ("Spam", "300:5")] {
+            t.ctx
+                .sql
+                .set_raw_config(&t.ctx, &format!("imap.mailbox.{}", folder), Some(last_seen))
+                .await
+                .unwrap();
+        }
+        t.ctx
+            .set_config(Config::ConfiguredMvboxFolder, Some("DeltaChat"))
+            .await
+            .unwrap();
+
+        let folders = known_folders(&t.ctx).await.unwrap();
+        assert_eq!(folders, vec!["DeltaChat", "INBOX", "Sent", "Spam"]);
+        for folder in &folders {
+            reset_last_seen_uid(&t.ctx, folder).await.unwrap();
+        }
+
+        // all messages are fetched again, the UIDVALIDITY is still known
+        assert_eq!(
+            imap.get_config_last_seen_uid(&t.ctx, "INBOX").await,
+            (100, 0)
+        );
+        assert_eq!(
+            imap.get_config_last_seen_uid(&t.ctx, "Sent").await,
+            (200, 0)
+        );
+        assert_eq!(
+            imap.get_config_last_seen_uid(&t.ctx, "Spam").await,
+            (300, 0)
+        );
+        assert_eq!(
+            imap.get_config_last_seen_uid(&t.ctx, "DeltaChat").await,
+            (0, 0)
+        );
+
+        t.ctx.resync_all_folders().await;
+        t.ctx.resync_all_folders().await;
+        let pending = job::get_pending(&t.ctx).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].action, Action::RescanFolders);
+    }
+
+    async fn count_msgs(context: &Context, rfc724_mid: &str) -> isize {
+        context
+            .sql
+            .query_get_value(
+                context,
+                "SELECT COUNT(*) FROM msgs WHERE rfc724_mid=?;",
+                paramsv![rfc724_mid],
+            )
+            .await
+            .unwrap_or_default()
+    }
+
+    #[async_std::test]
+    async fn test_rescan_refetches_seen_messages() {
+        let t = crate::test_utils::TestContext::new_alice().await;
+        let imap = Imap::new(async_std::sync::channel(1).1);
+        let seen = b"From: Bob <bob@example.net>\n\
+                     To: alice@example.com\n\
+                     Subject: Chat: seen\n\
+                     Message-ID: <seen.1@example.net>\n\
+                     Chat-Version: 1.0\n\
+                     Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                     \n\
+                     seen\n";
+        let missed = b"From: Bob <bob@example.net>\n\
+                       To: alice@example.com\n\
+                       Subject: Chat: missed\n\
+                       Message-ID: <missed.1@example.net>\n\
+                       Chat-Version: 1.0\n\
+                       Date: Sun, 22 Mar 2020 22:38:57 +0000\n\
+                       \n\
+                       missed\n";
+
+        // UID 41 was skipped, eg. because the device was offline for too long
+        dc_receive_imf(&t.ctx, seen, "INBOX", 42, false)
+            .await
+            .unwrap();
+        imap.set_config_last_seen_uid(&t.ctx, "INBOX", 100, 42)
+            .await;
+
+        reset_last_seen_uid(&t.ctx, "INBOX").await.unwrap();
+        assert_eq!(
+            imap.get_config_last_seen_uid(&t.ctx, "INBOX").await,
+            (100, 0)
+        );
+
+        // the refetched messages are received again,
+        // the missed one is added, the seen one is not duplicated
+        assert!(message::rfc724_mid_exists(&t.ctx, "missed.1@example.net")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(!precheck_imf(&t.ctx, "missed.1@example.net", "INBOX", 41)
+            .await
+            .unwrap());
+        dc_receive_imf(&t.ctx, missed, "INBOX", 41, false)
+            .await
+            .unwrap();
+        assert!(precheck_imf(&t.ctx, "seen.1@example.net", "INBOX", 42)
+            .await
+            .unwrap());
+        dc_receive_imf(&t.ctx, seen, "INBOX", 42, false)
+            .await
+            .unwrap();
+
+        for (rfc724_mid, server_uid) in &[("missed.1@example.net", 41), ("seen.1@example.net", 42)]
+        {
+            assert_eq!(count_msgs(&t.ctx, rfc724_mid).await, 1);
+            let (folder, uid, _) = message::rfc724_mid_exists(&t.ctx, rfc724_mid)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!((folder.as_str(), uid), ("INBOX", *server_uid));
+        }
+    }
+
+    #[async_std::test]
+    async fn test_duplicate_in_inbox_and_mvbox() {
+        let t = crate::test_utils::TestContext::new_alice().await;
+        t.ctx
+            .set_config(Config::MvboxMove, Some("1"))
+            .await
+            .unwrap();
+        t.ctx
+            .set_config(Config::ConfiguredMvboxFolder, Some("DeltaChat"))
+            .await
+            .unwrap();
+        let raw = b"From: Bob <bob@example.net>\n\
+                    To: alice@example.com\n\
+                    Subject: Chat: hi\n\
+                    Message-ID: <dup.1@example.net>\n\
+                    Chat-Version: 1.0\n\
+                    Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                    \n\
+                    hi\n";
+
+        dc_receive_imf(&t.ctx, raw, "DeltaChat", 1, false)
+            .await
+            .unwrap();
+        assert_eq!(count_msgs(&t.ctx, "dup.1@example.net").await, 1);
+
+        // the copy in the inbox is moved, not imported again
+        assert!(precheck_imf(&t.ctx, "dup.1@example.net", "INBOX", 7)
+            .await
+            .unwrap());
+        let (folder, uid, msg_id) = message::rfc724_mid_exists(&t.ctx, "dup.1@example.net")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((folder.as_str(), uid), ("INBOX", 7));
+        let pending = job::get_pending(&t.ctx).await.unwrap();
+        assert!(pending
+            .iter()
+            .any(|job| job.action == Action::MoveMsg && job.foreign_id == msg_id.to_u32()));
+
+        // messages delivered again are not stored twice
+        dc_receive_imf(&t.ctx, raw, "INBOX", 7, false)
+            .await
+            .unwrap();
+        dc_receive_imf(&t.ctx, raw, "DeltaChat", 2, false)
+            .await
+            .unwrap();
+        assert_eq!(count_msgs(&t.ctx, "dup.1@example.net").await, 1);
+        let (folder, uid, _) = message::rfc724_mid_exists(&t.ctx, "dup.1@example.net")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((folder.as_str(), uid), ("DeltaChat", 2));
+    }
+
+    #[test]
+    fn test_detect_folders_special_use() {
+        let folders = vec![
+            FolderInfo::new("INBOX", Some("."), &[]),
+            FolderInfo::new("Sent", Some("."), &[]),
+            FolderInfo::new(
+                "Gesendete Elemente",
+                Some("."),
+                &[NameAttribute::Custom("\\Sent".into())],
+            ),
+            FolderInfo::new("INBOX.DeltaChat", Some("."), &[]),
+        ];
+        assert_eq!(
+            detect_folders(&folders, "INBOX.DeltaChat", true),
+            (
+                Some("INBOX.DeltaChat".to_string()),
+                Some("Gesendete Elemente".to_string())
+            )
+        );
+
+        // attributes are ignored if the server does not announce SPECIAL-USE
+        assert_eq!(
+            detect_folders(&folders, "INBOX.DeltaChat", false),
+            (
+                Some("INBOX.DeltaChat".to_string()),
+                Some("Sent".to_string())
+            )
+        );
+    }
+
+    #[test]
+    fn test_detect_folders_by_name() {
+        let folders = vec![
+            FolderInfo::new("INBOX", Some("/"), &[]),
+            FolderInfo::new("Junk", Some("/"), &[NameAttribute::Custom("\\Junk".into())]),
+            FolderInfo::new("Sent", Some("/"), &[]),
+            FolderInfo::new("DeltaChat", Some("/"), &[]),
+            FolderInfo::new("INBOX/DeltaChat", Some("/"), &[]),
+        ];
+        assert_eq!(
+            detect_folders(&folders, "INBOX/DeltaChat", true),
+            (Some("DeltaChat".to_string()), Some("Sent".to_string()))
+        );
+
+        // a folder named "Sent" used for something else is not the sentbox
+        let folders = vec![FolderInfo::new(
+            "Sent",
+            Some("/"),
+            &[NameAttribute::Custom("\\Trash".into())],
+        )];
+        assert_eq!(
+            detect_folders(&folders, "INBOX/DeltaChat", true),
+            (None, None)
+        );
+    }
+}
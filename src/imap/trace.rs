@@ -0,0 +1,195 @@
+//! # IMAP protocol tracing
+//!
+//! If [Config::DebugTraceProtocol] is set, all lines sent to and received from
+//! the IMAP server are logged at debug level. Credentials are masked.
+//!
+//! [Config::DebugTraceProtocol]: crate::config::Config::DebugTraceProtocol
+
+use super::session::SessionStream;
+use crate::trace::{TraceProtocol, TraceStream, MASK};
+
+/// Masks the IMAP credentials, see [redact_command].
+#[derive(Debug, Default)]
+pub(crate) struct ImapTrace {
+    /// Set while the client sends authentication data
+    /// until the server answers with a tagged response.
+    authenticating: bool,
+}
+
+impl TraceProtocol for ImapTrace {
+    const NAME: &'static str = "IMAP";
+
+    fn sent(&mut self, line: &str) -> Option<String> {
+        Some(redact_command(line, &mut self.authenticating))
+    }
+
+    fn received(&mut self, line: &str) {
+        if !line.starts_with('+') {
+            self.authenticating = false;
+        }
+    }
+}
+
+impl<S: SessionStream> SessionStream for TraceStream<S, ImapTrace> {}
+
+/// Masks passwords and tokens in a line sent by the client.
+///
+/// `authenticating` is set if the following client lines
+/// carry authentication data and must be masked completely.
+fn redact_command(line: &str, authenticating: &mut bool) -> String {
+    if *authenticating {
+        return MASK.to_string();
+    }
+
+    let mut parts = line.splitn(3, ' ');
+    let tag = parts.next().unwrap_or_default();
+    let command = parts.next().unwrap_or_default();
+    let args = parts.next().unwrap_or_default();
+    let first_arg = args.split(' ').next().unwrap_or_default();
+    if command.eq_ignore_ascii_case("LOGIN") {
+        // a literal, eg. `{8}`, is sent in the following line
+        *authenticating = line.ends_with('}');
+        format!("{} {} {} {}", tag, command, first_arg, MASK)
+    } else if command.eq_ignore_ascii_case("AUTHENTICATE") {
+        *authenticating = true;
+        if args.contains(' ') {
+            // initial response given inline
+            format!("{} {} {} {}", tag, command, first_arg, MASK)
+        } else {
+            line.to_string()
+        }
+    } else {
+        line.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::pin::Pin;
+    use std::task::{Context as TaskContext, Poll};
+
+    use async_std::io::{self, Cursor, Read, ReadExt, Write, WriteExt};
+
+    use crate::events::EventType;
+    use crate::test_utils::TestContext;
+
+    #[derive(Debug)]
+    struct MockStream {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for MockStream {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut TaskContext<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            Pin::new(&mut self.input).poll_read(cx, buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut TaskContext<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Pin::new(&mut self.output).poll_write(cx, buf)
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.output).poll_flush(cx)
+        }
+
+        fn poll_close(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.output).poll_close(cx)
+        }
+    }
+
+    #[test]
+    fn test_redact_command() {
+        let mut authenticating = false;
+        assert_eq!(
+            redact_command("A1 LOGIN \"alice\" \"secret\"", &mut authenticating),
+            "A1 LOGIN \"alice\" ***"
+        );
+        assert!(!authenticating);
+        assert_eq!(
+            redact_command("A2 SELECT INBOX", &mut authenticating),
+            "A2 SELECT INBOX"
+        );
+
+        assert_eq!(
+            redact_command("A3 LOGIN alice {6}", &mut authenticating),
+            "A3 LOGIN alice ***"
+        );
+        assert!(authenticating);
+        assert_eq!(redact_command("secret", &mut authenticating), "***");
+
+        let mut authenticating = false;
+        assert_eq!(
+            redact_command("A4 AUTHENTICATE XOAUTH2", &mut authenticating),
+            "A4 AUTHENTICATE XOAUTH2"
+        );
+        assert!(authenticating);
+        assert_eq!(
+            redact_command("dXNlcj1hbGljZQ==", &mut authenticating),
+            "***"
+        );
+
+        let mut authenticating = false;
+        assert_eq!(
+            redact_command(
+                "A5 AUTHENTICATE PLAIN AGFsaWNlAHNlY3JldA==",
+                &mut authenticating
+            ),
+            "A5 AUTHENTICATE PLAIN ***"
+        );
+    }
+
+    #[async_std::test]
+    async fn test_trace_login() {
+        let t = TestContext::new().await;
+        let emitter = t.ctx.get_event_emitter();
+        let mock = MockStream {
+            input: Cursor::new(b"* OK IMAP ready\r\nA1 OK LOGIN completed\r\n".to_vec()),
+            output: Vec::new(),
+        };
+        let mut stream = TraceStream::new(t.ctx.clone(), mock, ImapTrace::default());
+
+        let mut buf = [0u8; 17];
+        stream.read_exact(&mut buf).await.unwrap();
+        stream
+            .write_all(b"A1 LOGIN alice@example.org \"secret\"\r\n")
+            .await
+            .unwrap();
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+
+        // the password is sent, but not logged
+        assert_eq!(
+            stream.get_ref().output,
+            b"A1 LOGIN alice@example.org \"secret\"\r\n".to_vec()
+        );
+        let mut traced = Vec::new();
+        while let Ok(event) = emitter.try_recv() {
+            if let EventType::Info(msg) = event.typ {
+                if msg.contains("IMAP ") {
+                    assert!(!msg.contains("secret"));
+                    traced.push(msg.splitn(2, "IMAP ").nth(1).unwrap().to_string());
+                }
+            }
+        }
+        assert_eq!(
+            traced,
+            vec![
+                "<< * OK IMAP ready",
+                ">> A1 LOGIN alice@example.org ***",
+                "<< A1 OK LOGIN completed",
+            ]
+        );
+    }
+}
@@ -1,11 +1,13 @@
 //! # Import/export module
 
 use std::any::Any;
+use std::collections::HashSet;
 use std::{
     cmp::{max, min},
     ffi::OsStr,
 };
 
+use async_std::io::Write;
 use async_std::path::{Path, PathBuf};
 use async_std::{
     fs::{self, File},
@@ -14,17 +16,19 @@ use async_std::{
 use rand::{thread_rng, Rng};
 
 use crate::blob::BlobObject;
-use crate::chat;
 use crate::chat::delete_and_reset_all_device_msgs;
+use crate::chat::{self, ChatId, ChatItem};
 use crate::config::Config;
 use crate::constants::*;
+use crate::contact::Contact;
 use crate::context::Context;
 use crate::dc_tools::*;
 use crate::e2ee;
 use crate::error::*;
 use crate::events::EventType;
 use crate::key::{self, DcKey, DcSecretKey, SignedPublicKey, SignedSecretKey};
-use crate::message::{Message, MsgId};
+use crate::message::{self, Message, MsgId};
+use crate::mimefactory::MimeFactory;
 use crate::mimeparser::SystemMessage;
 use crate::param::*;
 use crate::pgp;
@@ -50,6 +54,11 @@ pub enum ImexMode {
     /// Public keys are not imported.
     ImportSelfKeys = 2,
 
+    /// Export the default public key of the user to the directory given as `param1`.
+    /// The key is written to the file `public-key-<addr>.asc` and can be imported by other
+    /// OpenPGP implementations.  The private key is not exported.
+    ExportSelfPublicKey = 3,
+
     /// Export a backup to the directory given as `param1`.
     /// The backup contains all contacts, chats, images and other data and device independent settings.
     /// The backup does not contain device dependent settings as ringtones or LED notification settings.
@@ -82,6 +91,18 @@ pub async fn imex(
     what: ImexMode,
     param1: Option<impl AsRef<Path>>,
 ) -> Result<()> {
+    ensure!(
+        !context.is_in_memory(),
+        "Import/export is not supported for in-memory contexts."
+    );
+    // check this before starting, a rejected import must not be cleaned up
+    // as this would wipe the account in use.
+    if what == ImexMode::ImportBackup {
+        ensure!(
+            !context.is_configured().await,
+            "Cannot import backups to accounts in use."
+        );
+    }
     let cancel = context.alloc_ongoing().await?;
 
     let res = async {
@@ -114,6 +135,8 @@ pub async fn imex(
 
 async fn cleanup_aborted_imex(context: &Context, what: ImexMode) {
     if what == ImexMode::ImportBackup {
+        // leave an empty database rather than a partially imported one
+        context.sql.close().await;
         dc_delete_file(context, context.get_dbfile()).await;
         dc_delete_files_in_dir(context, context.get_blobdir()).await;
     }
@@ -444,7 +467,10 @@ async fn imex_inner(
     ensure!(context.sql.is_open().await, "Database not opened.");
 
     let path = param.ok_or_else(|| format_err!("Imex: Param was None"))?;
-    if what == ImexMode::ExportBackup || what == ImexMode::ExportSelfKeys {
+    if what == ImexMode::ExportBackup
+        || what == ImexMode::ExportSelfKeys
+        || what == ImexMode::ExportSelfPublicKey
+    {
         // before we export anything, make sure the private key exists
         if e2ee::ensure_secret_key_exists(context).await.is_err() {
             bail!("Cannot create private key or private key not available.");
@@ -456,6 +482,7 @@ async fn imex_inner(
     match what {
         ImexMode::ExportSelfKeys => export_self_keys(context, path).await,
         ImexMode::ImportSelfKeys => import_self_keys(context, path).await,
+        ImexMode::ExportSelfPublicKey => export_self_public_key(context, path).await,
 
         // TODO In some months we can change the export_backup_old() call to export_backup() and delete export_backup_old().
         // (now is 07/2020)
@@ -495,10 +522,17 @@ async fn import_backup(context: &Context, backup_to_import: impl AsRef<Path>) ->
     );
 
     let backup_file = File::open(backup_to_import).await?;
+    let total_bytes = backup_file.metadata().await?.len();
     let archive = Archive::new(backup_file);
     let mut entries = archive.entries()?;
+    let mut processed_bytes = 0;
+    // imex() already reported 10 when starting
+    let mut last_permille = 10;
     while let Some(file) = entries.next().await {
         let f = &mut file?;
+        if context.shall_stop_ongoing().await {
+            bail!("received stop signal");
+        }
         if f.path()?.file_name() == Some(OsStr::new(DBFILE_BACKUP_NAME)) {
             // async_tar can't unpack to a specified file name, so we just unpack to the blobdir and then move the unpacked file.
             f.unpack_in(context.get_blobdir()).await?;
@@ -507,7 +541,6 @@ async fn import_backup(context: &Context, backup_to_import: impl AsRef<Path>) ->
                 context.get_dbfile(),
             )
             .await?;
-            context.emit_event(EventType::ImexProgress(400)); // Just guess the progress, we at least have the dbfile by now
         } else {
             // async_tar will unpack to blobdir/BLOBS_BACKUP_NAME, so we move the file afterwards.
             f.unpack_in(context.get_blobdir()).await?;
@@ -520,6 +553,14 @@ async fn import_backup(context: &Context, backup_to_import: impl AsRef<Path>) ->
                 }
             }
         }
+
+        // the database and the blobs are weighted by their size in the archive
+        processed_bytes += f.header().size()?;
+        let permille = progress_permille(processed_bytes, total_bytes);
+        if permille > last_permille {
+            context.emit_event(EventType::ImexProgress(permille));
+            last_permille = permille;
+        }
     }
 
     ensure!(
@@ -611,13 +652,7 @@ async fn import_backup_old(context: &Context, backup_to_import: impl AsRef<Path>
             all_files_extracted = false;
             break;
         }
-        let mut permille = processed_files_cnt * 1000 / total_files_cnt;
-        if permille < 10 {
-            permille = 10
-        }
-        if permille > 990 {
-            permille = 990
-        }
+        let permille = progress_permille(processed_files_cnt as u64, total_files_cnt as u64);
         context.emit_event(EventType::ImexProgress(permille));
         if file_blob.is_empty() {
             continue;
@@ -640,6 +675,17 @@ async fn import_backup_old(context: &Context, backup_to_import: impl AsRef<Path>
     }
 }
 
+/// Maps `done` of `total` units to a value for [EventType::ImexProgress].
+///
+/// The result is kept between 10 and 990,
+/// the start and the end of an import or export are reported by [imex].
+fn progress_permille(done: u64, total: u64) -> usize {
+    if total == 0 {
+        return 990;
+    }
+    max(min(done * 1000 / total, 990), 10) as usize
+}
+
 /*******************************************************************************
  * Export backup
  ******************************************************************************/
@@ -947,6 +993,23 @@ async fn export_self_keys(context: &Context, dir: impl AsRef<Path>) -> Result<()
 /*******************************************************************************
  * Classic key export
  ******************************************************************************/
+async fn export_self_public_key(context: &Context, dir: impl AsRef<Path>) -> Result<()> {
+    let addr = context
+        .get_config(Config::ConfiguredAddr)
+        .await
+        .unwrap_or_default();
+    let armored = key::export_self_public_key(context).await?;
+    let file_name = dir.as_ref().join(format!(
+        "public-key-{}.asc",
+        sanitize_filename::sanitize(&addr)
+    ));
+    info!(context, "Exporting public key {}", file_name.display());
+    dc_delete_file(context, &file_name).await;
+    dc_write_file(context, &file_name, armored.as_bytes()).await?;
+    context.emit_event(EventType::ImexFileWritten(file_name));
+    Ok(())
+}
+
 async fn export_key_to_asc_file<T>(
     context: &Context,
     dir: impl AsRef<Path>,
@@ -981,6 +1044,98 @@ where
     res
 }
 
+/// Writes all messages of a chat to `out` in the mbox format.
+///
+/// Messages are written as received if `save_mime_headers` was enabled
+/// when they arrived, otherwise they are reconstructed including their
+/// attachments.  Lines starting with `From ` are quoted as in the mboxrd
+/// variant, so that the file can be imported by other mail clients.
+pub async fn export_chat_mbox<W: Write + Unpin>(
+    context: &Context,
+    chat_id: ChatId,
+    out: &mut W,
+) -> Result<()> {
+    ensure!(
+        !chat_id.is_special(),
+        "Cannot export special chat {}",
+        chat_id
+    );
+    let self_addr = context
+        .get_config(Config::ConfiguredAddr)
+        .await
+        .unwrap_or_default();
+
+    // all parts of a received message share the same raw MIME
+    let mut exported_raw_mids = HashSet::new();
+    for item in chat::get_chat_msgs(context, chat_id, 0, None).await {
+        let msg_id = match item {
+            ChatItem::Message { msg_id } => msg_id,
+            _ => continue,
+        };
+        let msg = Message::load_from_db(context, msg_id).await?;
+        if msg.is_info() {
+            continue;
+        }
+        let raw = match message::get_mime_headers(context, msg_id).await {
+            Some(raw) if !raw.is_empty() => {
+                if !exported_raw_mids.insert(msg.rfc724_mid.clone()) {
+                    continue;
+                }
+                raw.into_bytes()
+            }
+            _ => {
+                MimeFactory::from_msg_for_export(context, &msg)
+                    .await?
+                    .render()
+                    .await?
+                    .message
+            }
+        };
+        let sender = if msg.from_id == DC_CONTACT_ID_SELF {
+            self_addr.clone()
+        } else {
+            Contact::load_from_db(context, msg.from_id)
+                .await?
+                .get_addr()
+                .to_string()
+        };
+        out.write_all(&render_mbox_entry(&sender, msg.get_timestamp(), &raw))
+            .await?;
+    }
+    out.flush().await?;
+    Ok(())
+}
+
+/// Renders a message as mbox entry with a `From ` separator line.
+fn render_mbox_entry(sender: &str, timestamp: i64, raw: &[u8]) -> Vec<u8> {
+    let date = chrono::NaiveDateTime::from_timestamp(timestamp, 0).format("%a %b %e %H:%M:%S %Y");
+    let sender = if sender.is_empty() {
+        "MAILER-DAEMON"
+    } else {
+        sender
+    };
+    let mut entry = format!("From {} {}\n", sender, date).into_bytes();
+    let raw = match raw.split_last() {
+        Some((&b'\n', rest)) => rest,
+        _ => raw,
+    };
+    for line in raw.split(|c| *c == b'\n') {
+        let line = match line.split_last() {
+            Some((&b'\r', rest)) => rest,
+            _ => line,
+        };
+        let quote_len = line.iter().take_while(|c| **c == b'>').count();
+        let unquoted = line.get(quote_len..).unwrap_or_default();
+        if unquoted.starts_with(b"From ") {
+            entry.push(b'>');
+        }
+        entry.extend_from_slice(line);
+        entry.push(b'\n');
+    }
+    entry.push(b'\n');
+    entry
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -988,6 +1143,98 @@ mod tests {
     use crate::test_utils::*;
     use ::pgp::armor::BlockType;
 
+    #[async_std::test]
+    async fn test_export_chat_mbox() {
+        use mailparse::MailHeaderMap;
+
+        let t = TestContext::new_alice().await;
+        let bob = Contact::create(&t.ctx, "bob", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, bob).await.unwrap();
+
+        // received message, stored as raw MIME
+        t.ctx
+            .set_config(Config::SaveMimeHeaders, Some("1"))
+            .await
+            .unwrap();
+        crate::dc_receive_imf::dc_receive_imf(
+            &t.ctx,
+            b"From: bob@example.net\n\
+              To: alice@example.com\n\
+              Chat-Version: 1.0\n\
+              Subject: Chat: hello\n\
+              Message-ID: <hello@example.net>\n\
+              Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+              \n\
+              hello\n\
+              From here on, quoting is needed\n",
+            "INBOX",
+            1,
+            false,
+        )
+        .await
+        .unwrap();
+
+        // outgoing messages are reconstructed
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("hi bob".to_string()));
+        chat::prepare_msg(&t.ctx, chat_id, &mut msg).await.unwrap();
+        let file = t.ctx.get_blobdir().join("notes.txt");
+        dc_write_file(&t.ctx, &file, b"attached notes")
+            .await
+            .unwrap();
+        let mut msg = Message::new(Viewtype::File);
+        msg.set_file(file.to_str().unwrap(), Some("text/plain"));
+        chat::prepare_msg(&t.ctx, chat_id, &mut msg).await.unwrap();
+
+        let mut mbox = Vec::new();
+        export_chat_mbox(&t.ctx, chat_id, &mut mbox).await.unwrap();
+        let mbox = String::from_utf8(mbox).unwrap();
+
+        assert!(mbox.starts_with("From "));
+        let entries: Vec<String> = mbox
+            .split("\n\nFrom ")
+            .map(|entry| {
+                let entry = entry.trim_start_matches("From ");
+                let (_, raw) = entry.split_at(entry.find('\n').unwrap() + 1);
+                raw.replace("\n>From ", "\nFrom ")
+            })
+            .collect();
+        assert_eq!(entries.len(), 3);
+
+        let mut found = (false, false, false);
+        for raw in &entries {
+            let mail = mailparse::parse_mail(raw.as_bytes()).unwrap();
+            let from = mail.headers.get_first_value("From").unwrap();
+            if from.contains("bob@example.net") {
+                assert!(mail
+                    .get_body()
+                    .unwrap()
+                    .contains("From here on, quoting is needed"));
+                found.0 = true;
+            } else if mail.subparts.is_empty() {
+                assert!(from.contains("alice@example.com"));
+                assert!(mail.get_body().unwrap().contains("hi bob"));
+                found.1 = true;
+            } else {
+                let attachment = mail
+                    .subparts
+                    .iter()
+                    .find(|part| {
+                        part.get_content_disposition()
+                            .params
+                            .contains_key("filename")
+                    })
+                    .unwrap();
+                assert_eq!(attachment.get_body_raw().unwrap(), b"attached notes");
+                found.2 = true;
+            }
+        }
+        assert_eq!(found, (true, true, true));
+        assert!(mbox.contains("\n>From here on"));
+    }
+
     #[async_std::test]
     async fn test_render_setup_file() {
         let t = TestContext::new().await;
@@ -1050,6 +1297,60 @@ mod tests {
         assert_eq!(bytes, key.to_asc(None).into_bytes());
     }
 
+    #[async_std::test]
+    async fn test_import_backup_progress() {
+        let alice = TestContext::new_alice().await;
+        let blob = alice.ctx.get_blobdir().join("file.bin");
+        async_std::fs::write(&blob, vec![0u8; 100_000])
+            .await
+            .unwrap();
+        let backup_dir = tempfile::tempdir().unwrap();
+        export_backup(&alice.ctx, backup_dir.path()).await.unwrap();
+        let backup = has_backup(&alice.ctx, backup_dir.path()).await.unwrap();
+        assert!(backup.ends_with(".tar"));
+
+        let t = TestContext::new().await;
+        let emitter = t.ctx.get_event_emitter();
+        imex(&t.ctx, ImexMode::ImportBackup, Some(&backup))
+            .await
+            .unwrap();
+        assert!(t.ctx.is_configured().await);
+        assert!(t.ctx.get_blobdir().join("file.bin").exists().await);
+
+        let mut progress = Vec::new();
+        while let Ok(event) = emitter.try_recv() {
+            if let EventType::ImexProgress(permille) = event.typ {
+                progress.push(permille);
+            }
+        }
+        assert!(progress.len() > 2);
+        assert!(progress.windows(2).all(|w| w[0] < w[1]), "{:?}", progress);
+        assert_eq!(progress.last(), Some(&1000));
+    }
+
+    #[async_std::test]
+    async fn test_import_backup_rejected() {
+        let alice = TestContext::new_alice().await;
+        let backup_dir = tempfile::tempdir().unwrap();
+        export_backup(&alice.ctx, backup_dir.path()).await.unwrap();
+        let backup = has_backup(&alice.ctx, backup_dir.path()).await.unwrap();
+
+        let t = TestContext::new_alice().await;
+        let blob = t.ctx.get_blobdir().join("file.bin");
+        async_std::fs::write(&blob, b"data").await.unwrap();
+        assert!(imex(&t.ctx, ImexMode::ImportBackup, Some(&backup))
+            .await
+            .is_err());
+
+        assert!(t.ctx.is_configured().await);
+        assert!(t.ctx.sql.is_open().await);
+        assert!(blob.exists().await);
+        assert_eq!(
+            t.ctx.get_config(Config::Addr).await,
+            Some("alice@example.com".to_string())
+        );
+    }
+
     #[test]
     fn test_normalize_setup_code() {
         let norm = normalize_setup_code("123422343234423452346234723482349234");
@@ -28,14 +28,21 @@ use crate::imap::*;
 use crate::location;
 use crate::message::MsgId;
 use crate::message::{self, Message, MessageState};
-use crate::mimefactory::MimeFactory;
+use crate::mimefactory::{MimeFactory, RenderedEmail};
 use crate::param::*;
 use crate::smtp::Smtp;
 use crate::{scheduler::InterruptInfo, sql};
 
-// results in ~3 weeks for the last backoff timespan
+// with the backoff below, a job is given up after 3 to 6.5 days,
+// depending on the jitter
 const JOB_RETRIES: u32 = 17;
 
+/// Delay before the first retry of a failed job, in seconds.
+const JOB_BACKOFF_BASE: i64 = 60;
+
+/// Upper limit of the delay between retries, in seconds.
+const JOB_BACKOFF_MAX: i64 = 24 * 60 * 60;
+
 /// Thread IDs
 #[derive(Debug, Display, Copy, Clone, PartialEq, Eq, FromPrimitive, ToPrimitive, FromSql, ToSql)]
 #[repr(i32)]
@@ -94,7 +101,9 @@ pub enum Action {
     // Jobs in the INBOX-thread, range from DC_IMAP_THREAD..DC_IMAP_THREAD+999
     Housekeeping = 105, // low priority ...
     EmptyServer = 107,
+    FetchRecentMsgs = 120,
     MarkseenMsgOnImap = 130,
+    RescanFolders = 140,
 
     // Moving message is prioritized lower than deletion so we don't
     // bother moving message if it is already scheduled for deletion.
@@ -112,6 +121,53 @@ pub enum Action {
     SendMsgToSmtp = 5901, // ... high priority
 }
 
+/// Priority of a job.
+///
+/// Jobs of a thread are processed by priority first, jobs of the same
+/// priority are ordered by their action.
+#[derive(
+    Debug,
+    Display,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    FromPrimitive,
+    ToPrimitive,
+    FromSql,
+    ToSql,
+)]
+#[repr(i32)]
+pub enum Priority {
+    /// Background work the user does not wait for.
+    Low = 0,
+    Normal = 1,
+    /// Actions the user is waiting for, as sending messages.
+    High = 2,
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Priority::Normal
+    }
+}
+
+impl Action {
+    /// Returns the priority jobs of this action get by default.
+    pub fn priority(self) -> Priority {
+        use Action::*;
+
+        match self {
+            Housekeeping | MaybeSendLocations | MaybeSendLocationsEnded => Priority::Low,
+            SendMsgToSmtp => Priority::High,
+            Unknown | EmptyServer | FetchRecentMsgs | MarkseenMsgOnImap | RescanFolders
+            | MoveMsg | DeleteMsgOnImap | ResyncFolders | SendMdn => Priority::Normal,
+        }
+    }
+}
+
 impl Default for Action {
     fn default() -> Self {
         Action::Unknown
@@ -129,7 +185,9 @@ impl From<Action> for Thread {
             DeleteMsgOnImap => Thread::Imap,
             ResyncFolders => Thread::Imap,
             EmptyServer => Thread::Imap,
+            FetchRecentMsgs => Thread::Imap,
             MarkseenMsgOnImap => Thread::Imap,
+            RescanFolders => Thread::Imap,
             MoveMsg => Thread::Imap,
 
             MaybeSendLocations => Thread::Smtp,
@@ -149,6 +207,7 @@ pub struct Job {
     pub added_timestamp: i64,
     pub tries: u32,
     pub param: Params,
+    pub priority: Priority,
     pub pending_error: Option<String>,
 }
 
@@ -170,10 +229,17 @@ impl Job {
             added_timestamp: timestamp,
             tries: 0,
             param,
+            priority: action.priority(),
             pending_error: None,
         }
     }
 
+    /// Sets a priority other than the default of the action.
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
     pub fn delay_seconds(&self) -> i64 {
         self.desired_timestamp - self.added_timestamp
     }
@@ -213,14 +279,15 @@ impl Job {
                 .await?;
         } else {
             context.sql.execute(
-                "INSERT INTO jobs (added_timestamp, thread, action, foreign_id, param, desired_timestamp) VALUES (?,?,?,?,?,?);",
+                "INSERT INTO jobs (added_timestamp, thread, action, foreign_id, param, desired_timestamp, priority) VALUES (?,?,?,?,?,?,?);",
                 paramsv![
                     self.added_timestamp,
                     thread,
                     self.action,
                     self.foreign_id,
                     self.param.to_string(),
-                    self.desired_timestamp
+                    self.desired_timestamp,
+                    self.priority
                 ]
             ).await?;
         }
@@ -382,18 +449,51 @@ impl Job {
         };
 
         let foreign_id = self.foreign_id;
-        self.smtp_send(context, recipients_list, body, self.job_id, smtp, || {
-            async move {
-                // smtp success, update db ASAP, then delete smtp file
-                if 0 != foreign_id {
-                    set_delivered(context, MsgId::new(foreign_id)).await;
-                }
-                // now also delete the generated file
-                dc_delete_file(context, filename).await;
-                Ok(())
+        {
+            // the job may have been cancelled by `cancel_send_msg` meanwhile,
+            // from now on, cancelling is not possible anymore
+            let mut sending = context.smtp_sending_msg.lock().await;
+            if !job_try!(job_exists(context, self.job_id).await) {
+                return Status::Finished(Err(format_err!(
+                    "Not sending Message {} as it was cancelled",
+                    foreign_id
+                )));
             }
-        })
-        .await
+            *sending = Some(MsgId::new(foreign_id));
+        }
+        let status = self
+            .smtp_send(context, recipients_list, body, self.job_id, smtp, || {
+                async move {
+                    // smtp success, update db ASAP, then delete smtp file
+                    if 0 != foreign_id {
+                        set_delivered(context, MsgId::new(foreign_id)).await;
+                        if let Err(err) =
+                            chat::delete_chat_after_leave(context, MsgId::new(foreign_id)).await
+                        {
+                            warn!(context, "Cannot delete chat after leaving: {}", err);
+                        }
+                    }
+                    // now also delete the generated file
+                    dc_delete_file(context, filename).await;
+                    Ok(())
+                }
+            })
+            .await;
+        *context.smtp_sending_msg.lock().await = None;
+        status
+    }
+
+    /// Reports the final result of jobs the UI is waiting for.
+    fn emit_result(&self, context: &Context, success: bool) {
+        if self.action != Action::SendMsgToSmtp {
+            return;
+        }
+        if let Some(chat_id) = self.param.get_int(Param::ListUnsubscribeChatId) {
+            context.emit_event(EventType::ListUnsubscribed {
+                chat_id: ChatId::new(chat_id as u32),
+                success,
+            });
+        }
     }
 
     /// Get `SendMdn` jobs with foreign_id equal to `contact_id` excluding the `job_id` job.
@@ -440,12 +540,6 @@ impl Job {
     }
 
     async fn send_mdn(&mut self, context: &Context, smtp: &mut Smtp) -> Status {
-        if !context.get_config_bool(Config::MdnsEnabled).await {
-            // User has disabled MDNs after job scheduling but before
-            // execution.
-            return Status::Finished(Err(format_err!("MDNs are disabled")));
-        }
-
         let contact_id = self.foreign_id;
         let contact = job_try!(Contact::load_from_db(context, contact_id).await);
         if contact.is_blocked() {
@@ -461,6 +555,13 @@ impl Job {
             )));
         };
 
+        let msg = job_try!(Message::load_from_db(context, msg_id).await);
+        if !chat::mdns_enabled(context, msg.chat_id).await {
+            // User has disabled MDNs after job scheduling but before
+            // execution.
+            return Status::Finished(Err(format_err!("MDNs are disabled")));
+        }
+
         // Try to aggregate other SendMdn jobs and send a combined MDN.
         let (additional_job_ids, additional_rfc724_mids) = self
             .get_additional_mdn_jobs(context, contact_id)
@@ -475,7 +576,6 @@ impl Job {
             )
         }
 
-        let msg = job_try!(Message::load_from_db(context, msg_id).await);
         let mimefactory =
             job_try!(MimeFactory::from_mdn(context, &msg, additional_rfc724_mids).await);
         let rendered_msg = job_try!(mimefactory.render().await);
@@ -679,6 +779,37 @@ impl Job {
         Status::Finished(Ok(()))
     }
 
+    async fn fetch_recent_msgs(&mut self, context: &Context, imap: &mut Imap) -> Status {
+        if let Err(err) = imap.connect_configured(context).await {
+            warn!(context, "could not connect: {:?}", err);
+            return Status::RetryLater;
+        }
+
+        let count = context.get_config_int(Config::FetchRecentMsgs).await;
+        if count > 0 {
+            if let Some(inbox_folder) = &context.get_config(Config::ConfiguredInboxFolder).await {
+                job_try!(
+                    imap.fetch_recent_msgs(context, inbox_folder, count as u32)
+                        .await
+                );
+            }
+        }
+        Status::Finished(Ok(()))
+    }
+
+    async fn rescan_folders(&mut self, context: &Context, imap: &mut Imap) -> Status {
+        if let Err(err) = imap.connect_configured(context).await {
+            warn!(context, "could not connect: {:?}", err);
+            return Status::RetryLater;
+        }
+
+        if let Err(err) = imap.rescan_folders(context).await {
+            context.emit_event(EventType::RescanProgress(0));
+            return Status::Finished(Err(err));
+        }
+        Status::Finished(Ok(()))
+    }
+
     async fn markseen_msg_on_imap(&mut self, context: &Context, imap: &mut Imap) -> Status {
         if let Err(err) = imap.connect_configured(context).await {
             warn!(context, "could not connect: {:?}", err);
@@ -720,10 +851,7 @@ impl Job {
                 // quote "Group left by <name>", and the name can be a
                 // display name stored in address book rather than
                 // the name sent in the From field by the user.
-                if msg.param.get_bool(Param::WantsMdn).unwrap_or_default()
-                    && !msg.is_system_message()
-                    && context.get_config_bool(Config::MdnsEnabled).await
-                {
+                if shall_send_mdn(context, &msg).await {
                     if let Err(err) = send_mdn(context, &msg).await {
                         warn!(context, "could not send out mdn for {}: {}", msg.id, err);
                         return Status::Finished(Err(err));
@@ -759,6 +887,60 @@ async fn kill_ids(context: &Context, job_ids: &[u32]) -> sql::Result<()> {
     Ok(())
 }
 
+async fn job_exists(context: &Context, job_id: u32) -> sql::Result<bool> {
+    context
+        .sql
+        .exists("SELECT id FROM jobs WHERE id=?;", paramsv![job_id as i32])
+        .await
+}
+
+/// Removes the pending SMTP job of a message.
+///
+/// Returns false if there is no such job or if the message is already
+/// being transmitted to the SMTP server.  On success, the message is
+/// marked as failed.
+pub(crate) async fn cancel_send_msg(context: &Context, msg_id: MsgId) -> bool {
+    let sending = context.smtp_sending_msg.lock().await;
+    if *sending == Some(msg_id) {
+        info!(context, "Message {} is being sent, cannot cancel.", msg_id);
+        return false;
+    }
+
+    let jobs: Vec<(u32, Params)> = context
+        .sql
+        .query_map(
+            "SELECT id, param FROM jobs WHERE action=? AND foreign_id=?;",
+            paramsv![Action::SendMsgToSmtp, msg_id],
+            |row| {
+                let params: String = row.get(1)?;
+                Ok((row.get(0)?, params.parse().unwrap_or_default()))
+            },
+            |rows| {
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await
+        .unwrap_or_default();
+    if jobs.is_empty() {
+        return false;
+    }
+    let job_ids: Vec<u32> = jobs.iter().map(|(job_id, _)| *job_id).collect();
+    if let Err(err) = kill_ids(context, &job_ids).await {
+        warn!(context, "Cannot cancel sending message {}: {}", msg_id, err);
+        return false;
+    }
+    drop(sending);
+
+    for (_, param) in jobs {
+        if let Ok(Some(filename)) = param.get_path(Param::File, context) {
+            dc_delete_file(context, filename).await;
+        }
+    }
+    message::set_msg_failed(context, msg_id, Some("Sending was cancelled.")).await;
+    true
+}
+
 pub async fn action_exists(context: &Context, action: Action) -> bool {
     context
         .sql
@@ -778,7 +960,7 @@ async fn set_delivered(context: &Context, msg_id: MsgId) {
         )
         .await
         .unwrap_or_default();
-    context.emit_event(EventType::MsgDelivered { chat_id, msg_id });
+    context.emit_msg_state_event(EventType::MsgDelivered { chat_id, msg_id });
 }
 
 /// Constructs a job for sending a message.
@@ -981,12 +1163,14 @@ pub(crate) async fn perform_job(context: &Context, mut connection: Connection<'_
                     job,
                     JOB_RETRIES
                 );
+                job.emit_result(context, false);
                 job.delete(context).await.unwrap_or_else(|err| {
                     error!(context, "failed to delete job: {}", err);
                 });
             }
         }
         Status::Finished(res) => {
+            job.emit_result(context, res.is_ok());
             if let Err(err) = res {
                 warn!(
                     context,
@@ -1004,6 +1188,17 @@ pub(crate) async fn perform_job(context: &Context, mut connection: Connection<'_
             });
         }
     }
+
+    if job.action == Action::DeleteMsgOnImap
+        && !action_exists(context, Action::DeleteMsgOnImap).await
+    {
+        // all pending deletions are done, expunge and report them
+        let imap = connection.inbox();
+        if let Err(err) = imap.maybe_close_folder(context).await {
+            warn!(context, "failed to close folder: {:?}", err);
+        }
+        imap.report_deleted_msgs(context);
+    }
 }
 
 async fn perform_job_action(
@@ -1026,9 +1221,11 @@ async fn perform_job_action(
             location::job_maybe_send_locations_ended(context, job).await
         }
         Action::EmptyServer => job.empty_server(context, connection.inbox()).await,
+        Action::FetchRecentMsgs => job.fetch_recent_msgs(context, connection.inbox()).await,
         Action::DeleteMsgOnImap => job.delete_msg_on_imap(context, connection.inbox()).await,
         Action::ResyncFolders => job.resync_folders(context, connection.inbox()).await,
         Action::MarkseenMsgOnImap => job.markseen_msg_on_imap(context, connection.inbox()).await,
+        Action::RescanFolders => job.rescan_folders(context, connection.inbox()).await,
         Action::MoveMsg => job.move_msg(context, connection.inbox()).await,
         Action::Housekeeping => {
             sql::housekeeping(context).await;
@@ -1041,15 +1238,39 @@ async fn perform_job_action(
     try_res
 }
 
+/// Returns the delay before retrying a job that failed `tries` times.
+///
+/// The delay doubles with every try up to [JOB_BACKOFF_MAX]. A random part
+/// of up to half of the delay is subtracted, so that jobs failing at the
+/// same time, eg. because the server is down, are not all retried at once.
 fn get_backoff_time_offset(tries: u32) -> i64 {
-    let n = 2_i32.pow(tries - 1) * 60;
-    let mut rng = thread_rng();
-    let r: i32 = rng.gen();
-    let mut seconds = r % (n + 1);
-    if seconds < 1 {
-        seconds = 1;
-    }
-    seconds as i64
+    let n = 2_i64
+        .checked_pow(tries.saturating_sub(1))
+        .and_then(|factor| factor.checked_mul(JOB_BACKOFF_BASE))
+        .map_or(JOB_BACKOFF_MAX, |n| n.min(JOB_BACKOFF_MAX));
+    n - thread_rng().gen_range(0, n / 2 + 1)
+}
+
+/// Returns the time of the next attempt to send a message whose sending
+/// failed before.
+///
+/// Returns `None` if the message is not waiting for a retry.
+pub async fn get_next_send_attempt(context: &Context, msg_id: MsgId) -> Option<i64> {
+    context
+        .sql
+        .query_get_value(
+            context,
+            "SELECT desired_timestamp FROM jobs WHERE action=? AND foreign_id=? AND tries>0;",
+            paramsv![Action::SendMsgToSmtp, msg_id],
+        )
+        .await
+}
+
+/// Returns whether a read receipt is sent for a seen message.
+async fn shall_send_mdn(context: &Context, msg: &Message) -> bool {
+    msg.param.get_bool(Param::WantsMdn).unwrap_or_default()
+        && !msg.is_system_message()
+        && chat::mdns_enabled(context, msg.chat_id).await
 }
 
 async fn send_mdn(context: &Context, msg: &Message) -> Result<()> {
@@ -1070,6 +1291,18 @@ pub(crate) async fn schedule_resync(context: &Context) {
     .await;
 }
 
+/// Schedules fetching recent messages after configuring,
+/// see [Config::FetchRecentMsgs].
+pub(crate) async fn schedule_fetch_recent_msgs(context: &Context) {
+    if context.get_config_int(Config::FetchRecentMsgs).await > 0 {
+        add(
+            context,
+            Job::new(Action::FetchRecentMsgs, 0, Params::new(), 0),
+        )
+        .await;
+    }
+}
+
 /// Creates a job.
 pub fn create(action: Action, foreign_id: i32, param: Params, delay_seconds: i64) -> Result<Job> {
     ensure!(
@@ -1080,22 +1313,70 @@ pub fn create(action: Action, foreign_id: i32, param: Params, delay_seconds: i64
     Ok(Job::new(action, foreign_id as u32, param, delay_seconds))
 }
 
+/// Returns the ID of a pending job doing the same as `job`.
+///
+/// Only jobs to be run immediately are checked, the time of delayed jobs
+/// matters. Sending a message is never queued twice, even if the rendered
+/// message differs; mails not belonging to a message are compared by their params.
+async fn find_duplicate(context: &Context, job: &Job) -> Option<u32> {
+    if job.job_id != 0 || job.delay_seconds() != 0 {
+        return None;
+    }
+    let any_param = job.action == Action::SendMsgToSmtp && job.foreign_id != 0;
+    context
+        .sql
+        .query_get_value(
+            context,
+            "SELECT id FROM jobs WHERE action=? AND foreign_id=? AND (? OR param=?);",
+            paramsv![job.action, job.foreign_id, any_param, job.param.to_string()],
+        )
+        .await
+}
+
+/// Queues a rendered mail not belonging to any message for sending.
+///
+/// `param` may carry additional job params, eg. [Param::ListUnsubscribeChatId].
+pub(crate) async fn add_smtp_mail(
+    context: &Context,
+    recipients: &[String],
+    rendered_msg: &RenderedEmail,
+    mut param: Params,
+) -> Result<()> {
+    ensure!(!recipients.is_empty(), "no recipients for smtp job set");
+    let blob = BlobObject::create(context, &rendered_msg.rfc724_mid, &rendered_msg.message).await?;
+    param.set(Param::File, blob.as_name());
+    param.set(Param::Recipients, recipients.join("\x1e"));
+    add(context, Job::new(Action::SendMsgToSmtp, 0, param, 0)).await;
+    Ok(())
+}
+
 /// Adds a job to the database, scheduling it.
+///
+/// If the same job is already pending, no new job is added.
 pub async fn add(context: &Context, job: Job) {
     let action = job.action;
     let delay_seconds = job.delay_seconds();
-    job.save(context).await.unwrap_or_else(|err| {
-        error!(context, "failed to save job: {}", err);
-    });
+    if let Some(job_id) = find_duplicate(context, &job).await {
+        info!(
+            context,
+            "not adding job {}, it is a duplicate of job #{}", job, job_id
+        );
+    } else {
+        job.save(context).await.unwrap_or_else(|err| {
+            error!(context, "failed to save job: {}", err);
+        });
+    }
 
     if delay_seconds == 0 {
         match action {
             Action::Unknown => unreachable!(),
             Action::Housekeeping
             | Action::EmptyServer
+            | Action::FetchRecentMsgs
             | Action::DeleteMsgOnImap
             | Action::ResyncFolders
             | Action::MarkseenMsgOnImap
+            | Action::RescanFolders
             | Action::MoveMsg => {
                 info!(context, "interrupt: imap");
                 context
@@ -1115,6 +1396,39 @@ pub async fn add(context: &Context, job: Job) {
     }
 }
 
+fn job_from_row(row: &rusqlite::Row) -> rusqlite::Result<Job> {
+    Ok(Job {
+        job_id: row.get("id")?,
+        action: row.get("action")?,
+        foreign_id: row.get("foreign_id")?,
+        desired_timestamp: row.get("desired_timestamp")?,
+        added_timestamp: row.get("added_timestamp")?,
+        tries: row.get("tries")?,
+        param: row.get::<_, String>("param")?.parse().unwrap_or_default(),
+        priority: row.get("priority")?,
+        pending_error: None,
+    })
+}
+
+/// Returns all pending jobs for debugging.
+///
+/// The jobs of each thread are returned in the order they are
+/// processed once they are due.
+pub async fn get_pending(context: &Context) -> Result<Vec<Job>> {
+    let jobs = context
+        .sql
+        .query_map(
+            "SELECT id, action, foreign_id, param, added_timestamp, desired_timestamp, tries, priority \
+             FROM jobs \
+             ORDER BY thread, priority DESC, action DESC, added_timestamp;",
+            paramsv![],
+            job_from_row,
+            |jobs| jobs.collect::<std::result::Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+    Ok(jobs)
+}
+
 /// Load jobs from the database.
 ///
 /// Load jobs for this "[Thread]", i.e. either load SMTP jobs or load
@@ -1136,10 +1450,10 @@ pub(crate) async fn load_next(
 
     if let Some(msg_id) = info.msg_id {
         query = r#"
-SELECT id, action, foreign_id, param, added_timestamp, desired_timestamp, tries
+SELECT id, action, foreign_id, param, added_timestamp, desired_timestamp, tries, priority
 FROM jobs
 WHERE thread=? AND foreign_id=?
-ORDER BY action DESC, added_timestamp
+ORDER BY priority DESC, action DESC, added_timestamp
 LIMIT 1;
 "#;
         m = msg_id;
@@ -1148,10 +1462,10 @@ LIMIT 1;
         // processing for first-try and after backoff-timeouts:
         // process jobs in the order they were added.
         query = r#"
-SELECT id, action, foreign_id, param, added_timestamp, desired_timestamp, tries
+SELECT id, action, foreign_id, param, added_timestamp, desired_timestamp, tries, priority
 FROM jobs
 WHERE thread=? AND desired_timestamp<=?
-ORDER BY action DESC, added_timestamp
+ORDER BY priority DESC, action DESC, added_timestamp
 LIMIT 1;
 "#;
         params = paramsv![thread_i, t];
@@ -1160,10 +1474,10 @@ LIMIT 1;
         // process _all_ pending jobs that failed before
         // in the order of their backoff-times.
         query = r#"
-SELECT id, action, foreign_id, param, added_timestamp, desired_timestamp, tries
+SELECT id, action, foreign_id, param, added_timestamp, desired_timestamp, tries, priority
 FROM jobs
 WHERE thread=? AND tries>0
-ORDER BY desired_timestamp, action DESC
+ORDER BY priority DESC, desired_timestamp, action DESC
 LIMIT 1;
 "#;
         params = paramsv![thread_i];
@@ -1172,20 +1486,7 @@ LIMIT 1;
     let job = loop {
         let job_res = context
             .sql
-            .query_row_optional(query, params.clone(), |row| {
-                let job = Job {
-                    job_id: row.get("id")?,
-                    action: row.get("action")?,
-                    foreign_id: row.get("foreign_id")?,
-                    desired_timestamp: row.get("desired_timestamp")?,
-                    added_timestamp: row.get("added_timestamp")?,
-                    tries: row.get("tries")?,
-                    param: row.get::<_, String>("param")?.parse().unwrap_or_default(),
-                    pending_error: None,
-                };
-
-                Ok(job)
-            })
+            .query_row_optional(query, params.clone(), job_from_row)
             .await;
 
         match job_res {
@@ -1305,4 +1606,320 @@ mod tests {
         .await;
         assert!(jobs.is_some());
     }
+
+    #[async_std::test]
+    async fn test_job_priority() {
+        let t = TestContext::new().await;
+        add(
+            &t.ctx,
+            Job::new(Action::MaybeSendLocations, 0, Params::new(), 0),
+        )
+        .await;
+        add(&t.ctx, Job::new(Action::SendMdn, 1, Params::new(), 0)).await;
+        add(&t.ctx, Job::new(Action::SendMsgToSmtp, 2, Params::new(), 0)).await;
+
+        let info = InterruptInfo::new(false, None);
+        let job = load_next(&t.ctx, Thread::Smtp, &info).await.unwrap();
+        assert_eq!(job.action, Action::SendMsgToSmtp);
+        assert_eq!(job.priority, Priority::High);
+
+        // the priority can be raised above the one of the action
+        add(&t.ctx, Job::new(Action::MoveMsg, 1, Params::new(), 0)).await;
+        add(
+            &t.ctx,
+            Job::new(Action::MarkseenMsgOnImap, 2, Params::new(), 0).with_priority(Priority::High),
+        )
+        .await;
+        let job = load_next(&t.ctx, Thread::Imap, &info).await.unwrap();
+        assert_eq!(job.action, Action::MarkseenMsgOnImap);
+
+        let pending: Vec<Action> = get_pending(&t.ctx)
+            .await
+            .unwrap()
+            .iter()
+            .map(|job| job.action)
+            .collect();
+        assert_eq!(
+            pending,
+            vec![
+                Action::MarkseenMsgOnImap,
+                Action::MoveMsg,
+                Action::SendMsgToSmtp,
+                Action::SendMdn,
+                Action::MaybeSendLocations,
+            ]
+        );
+    }
+
+    #[async_std::test]
+    async fn test_job_dedup() {
+        let t = TestContext::new().await;
+        add(&t.ctx, Job::new(Action::ResyncFolders, 0, Params::new(), 0)).await;
+        add(&t.ctx, Job::new(Action::ResyncFolders, 0, Params::new(), 0)).await;
+        add(&t.ctx, Job::new(Action::MoveMsg, 1, Params::new(), 0)).await;
+        add(&t.ctx, Job::new(Action::MoveMsg, 2, Params::new(), 0)).await;
+
+        let mut param = Params::new();
+        param.set(Param::File, "$BLOBDIR/a.eml");
+        add(&t.ctx, Job::new(Action::SendMsgToSmtp, 5, param, 0)).await;
+        let mut param = Params::new();
+        param.set(Param::File, "$BLOBDIR/b.eml");
+        add(&t.ctx, Job::new(Action::SendMsgToSmtp, 5, param, 0)).await;
+
+        // delayed jobs are kept, their time matters
+        add(
+            &t.ctx,
+            Job::new(Action::MaybeSendLocationsEnded, 3, Params::new(), 60),
+        )
+        .await;
+        add(
+            &t.ctx,
+            Job::new(Action::MaybeSendLocationsEnded, 3, Params::new(), 120),
+        )
+        .await;
+
+        let pending = get_pending(&t.ctx).await.unwrap();
+        let count = |action| pending.iter().filter(|job| job.action == action).count();
+        assert_eq!(count(Action::ResyncFolders), 1);
+        assert_eq!(count(Action::MoveMsg), 2);
+        assert_eq!(count(Action::SendMsgToSmtp), 1);
+        assert_eq!(count(Action::MaybeSendLocationsEnded), 2);
+        let send_job = pending
+            .iter()
+            .find(|job| job.action == Action::SendMsgToSmtp)
+            .unwrap();
+        assert_eq!(send_job.param.get(Param::File), Some("$BLOBDIR/a.eml"));
+    }
+
+    #[test]
+    fn test_get_backoff_time_offset() {
+        for _ in 0..100 {
+            let first = get_backoff_time_offset(1);
+            assert!(first >= 30 && first <= 60);
+            let second = get_backoff_time_offset(2);
+            assert!(second >= 60 && second <= 120);
+            let tenth = get_backoff_time_offset(10);
+            assert!(tenth >= 15360 && tenth <= 30720);
+
+            // capped
+            for tries in &[12, JOB_RETRIES, 63, 64, 1000] {
+                let offset = get_backoff_time_offset(*tries);
+                assert!(offset >= JOB_BACKOFF_MAX / 2 && offset <= JOB_BACKOFF_MAX);
+            }
+
+            let total: i64 = (1..JOB_RETRIES).map(get_backoff_time_offset).sum();
+            assert!(total >= 3 * 24 * 60 * 60 && total <= 13 * 12 * 60 * 60);
+        }
+    }
+
+    #[async_std::test]
+    async fn test_get_next_send_attempt() {
+        let t = TestContext::new().await;
+        let msg_id = MsgId::new(10);
+        let job = Job::new(Action::SendMsgToSmtp, 10, Params::new(), 0);
+        add(&t.ctx, job).await;
+        assert_eq!(get_next_send_attempt(&t.ctx, msg_id).await, None);
+
+        let mut job = get_pending(&t.ctx).await.unwrap().pop().unwrap();
+        job.tries = 1;
+        job.desired_timestamp = time() + 42;
+        let desired_timestamp = job.desired_timestamp;
+        job.save(&t.ctx).await.unwrap();
+        assert_eq!(
+            get_next_send_attempt(&t.ctx, msg_id).await,
+            Some(desired_timestamp)
+        );
+        assert_eq!(get_next_send_attempt(&t.ctx, MsgId::new(11)).await, None);
+    }
+
+    #[async_std::test]
+    async fn test_schedule_fetch_recent_msgs() {
+        let t = TestContext::new().await;
+        assert_eq!(t.ctx.get_config_int(Config::FetchRecentMsgs).await, 100);
+        schedule_fetch_recent_msgs(&t.ctx).await;
+        schedule_fetch_recent_msgs(&t.ctx).await;
+        let pending = get_pending(&t.ctx).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].action, Action::FetchRecentMsgs);
+        assert_eq!(Thread::from(pending[0].action), Thread::Imap);
+
+        let t = TestContext::new().await;
+        t.ctx
+            .set_config(Config::FetchRecentMsgs, Some("0"))
+            .await
+            .unwrap();
+        schedule_fetch_recent_msgs(&t.ctx).await;
+        assert!(get_pending(&t.ctx).await.unwrap().is_empty());
+    }
+
+    async fn send_text_to_bob(t: &TestContext) -> Message {
+        let bob_id = Contact::create(&t.ctx, "Bob", "bob@example.org")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, bob_id).await.unwrap();
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("oops".to_string()));
+        let msg_id = chat::send_msg(&t.ctx, chat_id, &mut msg).await.unwrap();
+        Message::load_from_db(&t.ctx, msg_id).await.unwrap()
+    }
+
+    async fn count_smtp_jobs(t: &TestContext) -> usize {
+        get_pending(&t.ctx)
+            .await
+            .unwrap()
+            .iter()
+            .filter(|job| job.action == Action::SendMsgToSmtp)
+            .count()
+    }
+
+    #[async_std::test]
+    async fn test_cancel_send_msg() {
+        let t = TestContext::new_alice().await;
+        let msg = send_text_to_bob(&t).await;
+        assert_eq!(msg.state, MessageState::OutPending);
+        assert_eq!(count_smtp_jobs(&t).await, 1);
+
+        assert!(msg.try_cancel_send(&t.ctx).await);
+        assert_eq!(count_smtp_jobs(&t).await, 0);
+        let msg = Message::load_from_db(&t.ctx, msg.id).await.unwrap();
+        assert_eq!(msg.state, MessageState::OutFailed);
+
+        // nothing left to cancel
+        assert!(!msg.try_cancel_send(&t.ctx).await);
+    }
+
+    #[async_std::test]
+    async fn test_cancel_send_msg_in_flight() {
+        let t = TestContext::new_alice().await;
+        let msg = send_text_to_bob(&t).await;
+
+        *t.ctx.smtp_sending_msg.lock().await = Some(msg.id);
+        assert!(!msg.try_cancel_send(&t.ctx).await);
+        assert_eq!(count_smtp_jobs(&t).await, 1);
+        let msg = Message::load_from_db(&t.ctx, msg.id).await.unwrap();
+        assert_eq!(msg.state, MessageState::OutPending);
+
+        // once the transmission is over, a retry can be cancelled
+        *t.ctx.smtp_sending_msg.lock().await = None;
+        assert!(msg.try_cancel_send(&t.ctx).await);
+    }
+
+    #[async_std::test]
+    async fn test_set_delivered_batched() {
+        let t = TestContext::new_alice().await;
+        let mut msgs = Vec::new();
+        for _ in 0..10 {
+            msgs.push(send_text_to_bob(&t).await);
+        }
+        let chat_id = msgs[0].chat_id;
+        let emitter = t.ctx.get_event_emitter();
+        while emitter.try_recv().is_ok() {}
+
+        {
+            let _batch = t.ctx.batch_msg_states();
+            for msg in &msgs {
+                set_delivered(&t.ctx, msg.id).await;
+            }
+            assert!(emitter.try_recv().is_err());
+        }
+        let mut events = Vec::new();
+        while let Ok(event) = emitter.try_recv() {
+            events.push(event.typ);
+        }
+        assert_eq!(
+            events,
+            vec![EventType::MsgStatesChanged {
+                chat_id,
+                msg_ids: msgs.iter().map(|msg| msg.id).collect(),
+            }]
+        );
+        for msg in &msgs {
+            let msg = Message::load_from_db(&t.ctx, msg.id).await.unwrap();
+            assert_eq!(msg.state, MessageState::OutDelivered);
+        }
+
+        // single updates are emitted at once
+        let msg = send_text_to_bob(&t).await;
+        while emitter.try_recv().is_ok() {}
+        set_delivered(&t.ctx, msg.id).await;
+        assert_eq!(
+            emitter.try_recv().unwrap().typ,
+            EventType::MsgDelivered {
+                chat_id,
+                msg_id: msg.id
+            }
+        );
+
+        // batches do not hold back the events of other tasks
+        let msg = send_text_to_bob(&t).await;
+        while emitter.try_recv().is_ok() {}
+        {
+            let _batch = t.ctx.batch_msg_states();
+            let ctx = t.ctx.clone();
+            let msg_id = msg.id;
+            async_std::task::spawn(async move { set_delivered(&ctx, msg_id).await }).await;
+            assert_eq!(
+                emitter.try_recv().unwrap().typ,
+                EventType::MsgDelivered {
+                    chat_id,
+                    msg_id: msg.id
+                }
+            );
+        }
+        assert!(emitter.try_recv().is_err());
+    }
+
+    async fn receive_msg_wanting_mdn(t: &TestContext, from: &str, message_id: &str) -> Message {
+        let headers = format!("Chat-Disposition-Notification-To: {}\n", from);
+        receive_chat_msg(&t.ctx, from, message_id, "hello", &headers).await
+    }
+
+    #[async_std::test]
+    async fn test_send_mdn_chat_override() {
+        let t = TestContext::new_alice().await;
+        t.ctx
+            .set_config(Config::MdnsEnabled, Some("0"))
+            .await
+            .unwrap();
+        let bob_msg = receive_msg_wanting_mdn(&t, "bob@example.net", "bob@example.net.1").await;
+        let claire_msg =
+            receive_msg_wanting_mdn(&t, "claire@example.org", "claire@example.org.1").await;
+        assert_ne!(bob_msg.chat_id, claire_msg.chat_id);
+
+        chat::set_mdns_enabled(&t.ctx, bob_msg.chat_id, Some(true))
+            .await
+            .unwrap();
+        let bob_chat = chat::Chat::load_from_db(&t.ctx, bob_msg.chat_id)
+            .await
+            .unwrap();
+        assert_eq!(bob_chat.get_mdns_enabled(), Some(true));
+        for msg in &[&bob_msg, &claire_msg] {
+            if shall_send_mdn(&t.ctx, msg).await {
+                send_mdn(&t.ctx, msg).await.unwrap();
+            }
+        }
+        let mdn_jobs: Vec<Job> = get_pending(&t.ctx)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|job| job.action == Action::SendMdn)
+            .collect();
+        assert_eq!(mdn_jobs.len(), 1);
+        assert_eq!(mdn_jobs[0].param.get_msg_id(), Some(bob_msg.id));
+
+        // without override, the global setting is used
+        chat::set_mdns_enabled(&t.ctx, bob_msg.chat_id, None)
+            .await
+            .unwrap();
+        assert!(!shall_send_mdn(&t.ctx, &bob_msg).await);
+        t.ctx
+            .set_config(Config::MdnsEnabled, Some("1"))
+            .await
+            .unwrap();
+        assert!(shall_send_mdn(&t.ctx, &bob_msg).await);
+        chat::set_mdns_enabled(&t.ctx, claire_msg.chat_id, Some(false))
+            .await
+            .unwrap();
+        assert!(!shall_send_mdn(&t.ctx, &claire_msg).await);
+    }
 }
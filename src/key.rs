@@ -7,8 +7,9 @@ use std::io::Cursor;
 use async_trait::async_trait;
 use num_traits::FromPrimitive;
 use pgp::composed::Deserializable;
+use pgp::crypto::PublicKeyAlgorithm;
 use pgp::ser::Serialize;
-use pgp::types::{KeyTrait, SecretKeyTrait};
+use pgp::types::{KeyTrait, PublicParams, SecretKeyTrait, SecretParams};
 use thiserror::Error;
 
 use crate::config::Config;
@@ -41,6 +42,8 @@ pub enum Error {
     InvalidConfiguredAddr(#[from] InvalidEmailError),
     #[error("no data provided")]
     Empty,
+    #[error("Wrong or missing passphrase for secret key")]
+    WrongPassphrase,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -105,6 +108,14 @@ pub trait DcKey: Serialize + Deserializable + KeyTrait + Clone {
     // SignedSecretKey we can not generically implement this.
     fn to_asc(&self, header: Option<(&str, &str)>) -> String;
 
+    /// Checks the self-signatures of the key.
+    ///
+    /// Keys from outside, eg. imported by the user, must be validated
+    /// before they are used.
+    // Since .verify() are actual methods on SignedPublicKey and
+    // SignedSecretKey we can not generically implement this.
+    fn validate(&self) -> Result<()>;
+
     /// The fingerprint for the key.
     fn fingerprint(&self) -> Fingerprint {
         Fingerprint::new(KeyTrait::fingerprint(self)).expect("Invalid fingerprint from rpgp")
@@ -154,6 +165,11 @@ impl DcKey for SignedPublicKey {
             .unwrap_or_default();
         std::string::String::from_utf8(buf).unwrap_or_default()
     }
+
+    fn validate(&self) -> Result<()> {
+        self.verify()?;
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -199,6 +215,11 @@ impl DcKey for SignedSecretKey {
             .unwrap_or_default();
         std::string::String::from_utf8(buf).unwrap_or_default()
     }
+
+    fn validate(&self) -> Result<()> {
+        self.verify()?;
+        Ok(())
+    }
 }
 
 /// Deltachat extension trait for secret keys.
@@ -247,25 +268,76 @@ async fn generate_keypair(context: &Context) -> Result<KeyPair> {
             secret: SignedSecretKey::from_slice(&sec_bytes)?,
         }),
         Err(sql::Error::Sql(rusqlite::Error::QueryReturnedNoRows)) => {
-            let start = std::time::SystemTime::now();
-            let keytype = KeyGenType::from_i32(context.get_config_int(Config::KeyGenType).await)
-                .unwrap_or_default();
-            info!(context, "Generating keypair with type {}", keytype);
-            let keypair =
-                async_std::task::spawn_blocking(move || crate::pgp::create_keypair(addr, keytype))
-                    .await?;
-            store_self_keypair(context, &keypair, KeyPairUse::Default).await?;
-            info!(
-                context,
-                "Keypair generated in {:.3}s.",
-                start.elapsed().unwrap_or_default().as_secs()
-            );
-            Ok(keypair)
+            create_default_keypair(context, addr).await
         }
         Err(err) => Err(err.into()),
     }
 }
 
+/// Generates a new keypair and stores it as the default key.
+///
+/// Previous keys are kept as non-default keys.  The caller must hold
+/// `generating_key_mutex`.
+async fn create_default_keypair(context: &Context, addr: EmailAddress) -> Result<KeyPair> {
+    let start = std::time::SystemTime::now();
+    let keytype =
+        KeyGenType::from_i32(context.get_config_int(Config::KeyGenType).await).unwrap_or_default();
+    info!(context, "Generating keypair with type {}", keytype);
+    let keypair =
+        async_std::task::spawn_blocking(move || crate::pgp::create_keypair(addr, keytype)).await?;
+    store_self_keypair(context, &keypair, KeyPairUse::Default).await?;
+    info!(
+        context,
+        "Keypair generated in {:.3}s.",
+        start.elapsed().unwrap_or_default().as_secs()
+    );
+    Ok(keypair)
+}
+
+/// Replaces the default keypair by a newly generated one.
+///
+/// The old keypair is kept as a non-default key, so messages encrypted
+/// to it can still be decrypted.
+pub(crate) async fn rotate_self_keypair(context: &Context) -> Result<KeyPair> {
+    let addr = context
+        .get_config(Config::ConfiguredAddr)
+        .await
+        .ok_or_else(|| Error::NoConfiguredAddr)?;
+    let addr = EmailAddress::new(&addr)?;
+    let _guard = context.generating_key_mutex.lock().await;
+    create_default_keypair(context, addr).await
+}
+
+/// Loads all secret keys of the configured address.
+///
+/// The default key comes first, followed by the keys that were replaced
+/// by key rotation, newest first.
+pub(crate) async fn load_self_secret_keys(context: &Context) -> Result<Vec<SignedSecretKey>> {
+    let mut keys = vec![SignedSecretKey::load_self(context).await?];
+    let old_keys = context
+        .sql
+        .query_map(
+            r#"
+            SELECT private_key
+              FROM keypairs
+             WHERE addr=(SELECT value FROM config WHERE keyname="configured_addr")
+               AND is_default=0
+             ORDER BY created DESC, id DESC;
+            "#,
+            paramsv![],
+            |row| row.get::<_, Vec<u8>>(0),
+            |rows| {
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+    for bytes in old_keys {
+        keys.push(SignedSecretKey::from_slice(&bytes)?);
+    }
+    Ok(keys)
+}
+
 /// Use of a [KeyPair] for encryption or decryption.
 ///
 /// This is used by [store_self_keypair] to know what kind of key is
@@ -354,6 +426,100 @@ pub async fn store_self_keypair(
     Ok(())
 }
 
+/// Returns the default public key ASCII-armored, to be given to other
+/// people out-of-band.
+///
+/// The address is added as `Comment` header, the secret key is never
+/// part of the result.
+pub async fn export_self_public_key(context: &Context) -> Result<String> {
+    let addr = context
+        .get_config(Config::ConfiguredAddr)
+        .await
+        .ok_or(Error::NoConfiguredAddr)?;
+    let public_key = SignedPublicKey::load_self(context).await?;
+    Ok(public_key.to_asc(Some(("Comment", &addr))))
+}
+
+/// Imports an ASCII-armored secret key and makes it the default key.
+///
+/// The public key is derived from the secret key, so the Autocrypt
+/// header of outgoing messages carries the imported key from now on.
+/// Secret keys protected by a passphrase are unlocked using `passphrase`
+/// and stored without protection, as all other keys.
+pub async fn import_self_key(
+    context: &Context,
+    armored: &str,
+    passphrase: Option<&str>,
+) -> Result<Fingerprint> {
+    let (secret, _) = SignedSecretKey::from_asc(armored)?;
+    secret.validate()?;
+
+    // rPGP only asks for the passphrase if the key is protected.
+    let secret = if secret.unlock(|| "".into(), |_| Ok(())).is_ok() {
+        secret
+    } else {
+        match passphrase {
+            Some(passphrase) if secret.unlock(|| passphrase.to_string(), |_| Ok(())).is_ok() => {
+                remove_passphrase(&secret, passphrase)?
+            }
+            _ => return Err(Error::WrongPassphrase),
+        }
+    };
+
+    let addr = context
+        .get_config(Config::ConfiguredAddr)
+        .await
+        .ok_or(Error::NoConfiguredAddr)?;
+    let keypair = KeyPair {
+        addr: EmailAddress::new(&addr)?,
+        public: secret.split_public_key()?,
+        secret,
+    };
+    store_self_keypair(context, &keypair, KeyPairUse::Default).await?;
+    Ok(keypair.public.fingerprint())
+}
+
+/// Returns a copy of the key with the passphrase protection
+/// removed from the primary key and all subkeys.
+fn remove_passphrase(key: &SignedSecretKey, passphrase: &str) -> Result<SignedSecretKey> {
+    let mut key = key.clone();
+    let public = key.primary_key.public_key();
+    let params = decrypt_secret_params(
+        key.primary_key.secret_params(),
+        passphrase,
+        public.algorithm(),
+        public.public_params(),
+    )?;
+    key.primary_key = pgp::packet::SecretKey::new(public, params);
+    for subkey in key.secret_subkeys.iter_mut() {
+        let public = subkey.key.public_key();
+        let params = decrypt_secret_params(
+            subkey.key.secret_params(),
+            passphrase,
+            public.algorithm(),
+            public.public_params(),
+        )?;
+        subkey.key = pgp::packet::SecretSubkey::new(public, params);
+    }
+    Ok(key)
+}
+
+fn decrypt_secret_params(
+    params: &SecretParams,
+    passphrase: &str,
+    algorithm: PublicKeyAlgorithm,
+    public_params: &PublicParams,
+) -> Result<SecretParams> {
+    match params {
+        SecretParams::Plain(_) => Ok(params.clone()),
+        SecretParams::Encrypted(encrypted) => Ok(SecretParams::Plain(encrypted.unlock(
+            || passphrase.to_string(),
+            algorithm,
+            public_params,
+        )?)),
+    }
+}
+
 /// A key fingerprint
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct Fingerprint(Vec<u8>);
@@ -697,4 +863,95 @@ i8pcjGO+IZffvyZJVRWfVooBJmWWbPB1pueo3tx8w3+fcuzpxz+RLFKaPyqXO+dD
             "0102 0408 1020 4080 FF01\n0204 0810 2040 80FF 1314"
         );
     }
+
+    #[async_std::test]
+    async fn test_import_self_key() {
+        let t = TestContext::new().await;
+        t.configure_addr("alice@example.com").await;
+        let alice = alice_keypair();
+
+        let fingerprint = import_self_key(&t.ctx, &alice.secret.to_asc(None), None)
+            .await
+            .unwrap();
+        assert_eq!(fingerprint, alice.public.fingerprint());
+        let seckey = SignedSecretKey::load_self(&t.ctx).await.unwrap();
+        assert_eq!(seckey, alice.secret);
+        let aheader = crate::e2ee::EncryptHelper::new(&t.ctx)
+            .await
+            .unwrap()
+            .get_aheader();
+        assert_eq!(aheader.public_key.fingerprint(), fingerprint);
+
+        // importing another key replaces the default key
+        let bob = bob_keypair();
+        import_self_key(&t.ctx, &bob.secret.to_asc(None), Some("unused"))
+            .await
+            .unwrap();
+        let pubkey = SignedPublicKey::load_self(&t.ctx).await.unwrap();
+        assert_eq!(pubkey.fingerprint(), bob.public.fingerprint());
+    }
+
+    #[async_std::test]
+    async fn test_import_self_key_invalid() {
+        let t = TestContext::new().await;
+        let alice = alice_keypair();
+        let armored = alice.secret.to_asc(None);
+
+        // not configured
+        assert!(matches!(
+            import_self_key(&t.ctx, &armored, None).await,
+            Err(super::Error::NoConfiguredAddr)
+        ));
+
+        t.configure_addr("alice@example.com").await;
+        assert!(import_self_key(&t.ctx, "not a key", None).await.is_err());
+        assert!(import_self_key(&t.ctx, &alice.public.to_asc(None), None)
+            .await
+            .is_err());
+        assert!(import_self_key(&t.ctx, &armored, None).await.is_ok());
+    }
+
+    #[async_std::test]
+    async fn test_import_self_key_protected() {
+        let t = TestContext::new().await;
+        t.configure_addr("alice@example.com").await;
+        let armored = include_str!("../test-data/key/alice-secret-protected.asc");
+
+        assert!(matches!(
+            import_self_key(&t.ctx, armored, None).await,
+            Err(super::Error::WrongPassphrase)
+        ));
+        assert!(matches!(
+            import_self_key(&t.ctx, armored, Some("wrong")).await,
+            Err(super::Error::WrongPassphrase)
+        ));
+
+        let fingerprint = import_self_key(&t.ctx, armored, Some("secret"))
+            .await
+            .unwrap();
+        assert_eq!(fingerprint, alice_keypair().public.fingerprint());
+        let seckey = SignedSecretKey::load_self(&t.ctx).await.unwrap();
+        assert!(seckey.unlock(|| "".into(), |_| Ok(())).is_ok());
+        for subkey in &seckey.secret_subkeys {
+            assert!(matches!(subkey.key.secret_params(), SecretParams::Plain(_)));
+        }
+    }
+
+    #[async_std::test]
+    async fn test_export_self_public_key() {
+        let t = TestContext::new().await;
+        assert!(export_self_public_key(&t.ctx).await.is_err());
+
+        t.configure_alice().await;
+        let armored = export_self_public_key(&t.ctx).await.unwrap();
+        assert!(armored.starts_with("-----BEGIN PGP PUBLIC KEY BLOCK-----"));
+        assert!(!armored.contains("PRIVATE KEY"));
+
+        let (key, headers) = SignedPublicKey::from_asc(&armored).unwrap();
+        assert_eq!(
+            headers.get("Comment"),
+            Some(&"alice@example.com".to_string())
+        );
+        assert_eq!(key.fingerprint(), alice_keypair().public.fingerprint());
+    }
 }
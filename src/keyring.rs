@@ -2,8 +2,13 @@
 
 use anyhow::Result;
 
+use crate::aheader::EncryptPreference;
+use crate::config::Config;
+use crate::contact::addr_normalize;
 use crate::context::Context;
-use crate::key::{self, DcKey};
+use crate::dc_tools::time;
+use crate::key::{self, DcKey, Fingerprint, SignedPublicKey, SignedSecretKey};
+use crate::peerstate::Peerstate;
 
 /// An in-memory keyring.
 ///
@@ -58,10 +63,242 @@ where
     }
 }
 
+impl Keyring<SignedSecretKey> {
+    /// Create a new keyring with all of the user's secret keys loaded.
+    ///
+    /// Besides the default key this contains keys replaced by key
+    /// rotation, so it should be used for decryption.
+    pub async fn new_self_all(context: &Context) -> Result<Self, key::Error> {
+        Ok(Keyring {
+            keys: key::load_self_secret_keys(context).await?,
+        })
+    }
+}
+
+/// Imports an ASCII-armored public key as the key of `addr`.
+///
+/// The key replaces any key received in Autocrypt headers before and
+/// messages to `addr` are encrypted with it from now on.
+pub async fn import_public_key(
+    context: &Context,
+    addr: &str,
+    armored: &str,
+) -> Result<Fingerprint> {
+    let (public_key, _) = SignedPublicKey::from_asc(armored)?;
+    public_key.validate()?;
+    let fingerprint = public_key.fingerprint();
+
+    let addr = addr_normalize(addr);
+    let (mut peerstate, create) = match Peerstate::from_addr(context, addr).await? {
+        Some(peerstate) => (peerstate, false),
+        None => (Peerstate::new(context, addr.to_string()), true),
+    };
+    let now = time();
+    peerstate.last_seen = now;
+    peerstate.last_seen_autocrypt = now;
+    peerstate.prefer_encrypt = EncryptPreference::Mutual;
+    peerstate.public_key = Some(public_key);
+    peerstate.recalc_fingerprint();
+    peerstate.save_to_db(&context.sql, create).await?;
+
+    if peerstate.fingerprint_changed {
+        peerstate.handle_fingerprint_change(context).await?;
+    }
+
+    Ok(fingerprint)
+}
+
+/// A key of a peer removed by [prune].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrunedKey {
+    /// Address of the peer.
+    pub addr: String,
+
+    /// Fingerprint of the removed key.
+    pub fingerprint: Fingerprint,
+}
+
+/// Removes superseded keys of peers.
+///
+/// If there are several peerstates for one address, only the most
+/// recently updated one is kept. Gossiped keys are removed if a newer
+/// key was received in an Autocrypt header. Verified keys and the keys
+/// of the user are never removed.
+///
+/// [Config::KeepSupersededKeys] sets the number of superseded keys kept for every
+/// address, the most recent ones are kept.
+///
+/// If `dry_run` is true, nothing is removed, the keys that would be
+/// removed are returned only.
+pub async fn prune(context: &Context, dry_run: bool) -> Result<Vec<PrunedKey>> {
+    let self_addr = context
+        .get_config(Config::ConfiguredAddr)
+        .await
+        .unwrap_or_default();
+    let keep = context
+        .get_config_int(Config::KeepSupersededKeys)
+        .await
+        .max(0) as usize;
+    let rows = context
+        .sql
+        .query_map(
+            "SELECT id, addr, MAX(last_seen, last_seen_autocrypt, gossip_timestamp), \
+                    last_seen_autocrypt, gossip_timestamp, public_key_fingerprint, \
+                    gossip_key_fingerprint, verified_key_fingerprint \
+             FROM acpeerstates ORDER BY addr, 3 DESC, id DESC;",
+            paramsv![],
+            |row| {
+                Ok(PeerKeys {
+                    id: row.get(0)?,
+                    addr: row.get(1)?,
+                    last_seen_autocrypt: row.get(3)?,
+                    gossip_timestamp: row.get(4)?,
+                    public: parse_fingerprint(row.get(5)?),
+                    gossip: parse_fingerprint(row.get(6)?),
+                    verified: parse_fingerprint(row.get(7)?),
+                })
+            },
+            |rows| {
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+
+    let mut pruned = Vec::new();
+    let mut kept: Option<PeerKeys> = None;
+    // superseded keys of the address of `kept` that are kept
+    let mut kept_superseded: Vec<Fingerprint> = Vec::new();
+    for row in rows {
+        if row.addr.eq_ignore_ascii_case(&self_addr) {
+            continue;
+        }
+
+        if let Some(newest) = kept
+            .as_ref()
+            .filter(|newest| newest.addr.eq_ignore_ascii_case(&row.addr))
+        {
+            // An older duplicate of the peerstate `newest`.
+            if row.verified.is_some() && row.verified != newest.verified {
+                continue;
+            }
+            let newest_fingerprints = newest.fingerprints();
+            let superseded: Vec<Fingerprint> = row
+                .fingerprints()
+                .into_iter()
+                .filter(|fingerprint| {
+                    !newest_fingerprints.contains(fingerprint)
+                        && !kept_superseded.contains(fingerprint)
+                })
+                .collect();
+            if !superseded.is_empty() && kept_superseded.len() < keep {
+                // keep the whole row, it is one of the most recent ones
+                kept_superseded.extend(superseded);
+                continue;
+            }
+            for fingerprint in superseded {
+                if !pruned.iter().any(|key: &PrunedKey| {
+                    key.fingerprint == fingerprint && key.addr.eq_ignore_ascii_case(&row.addr)
+                }) {
+                    pruned.push(PrunedKey {
+                        addr: row.addr.clone(),
+                        fingerprint,
+                    });
+                }
+            }
+            if !dry_run {
+                context
+                    .sql
+                    .execute("DELETE FROM acpeerstates WHERE id=?;", paramsv![row.id])
+                    .await?;
+            }
+            continue;
+        }
+
+        kept_superseded.clear();
+        if let Some(gossip) = row.superseded_gossip_key() {
+            if keep > 0 {
+                kept_superseded.push(gossip);
+            } else {
+                pruned.push(PrunedKey {
+                    addr: row.addr.clone(),
+                    fingerprint: gossip,
+                });
+                if !dry_run {
+                    context
+                        .sql
+                        .execute(
+                            "UPDATE acpeerstates \
+                             SET gossip_key=NULL, gossip_key_fingerprint='' WHERE id=?;",
+                            paramsv![row.id],
+                        )
+                        .await?;
+                }
+            }
+        }
+        kept = Some(row);
+    }
+
+    if !pruned.is_empty() {
+        info!(
+            context,
+            "{} {} superseded peer keys.",
+            if dry_run { "Would prune" } else { "Pruned" },
+            pruned.len()
+        );
+    }
+    Ok(pruned)
+}
+
+/// Fingerprints of the keys of one peerstate row.
+struct PeerKeys {
+    id: i64,
+    addr: String,
+    last_seen_autocrypt: i64,
+    gossip_timestamp: i64,
+    public: Option<Fingerprint>,
+    gossip: Option<Fingerprint>,
+    verified: Option<Fingerprint>,
+}
+
+impl PeerKeys {
+    fn fingerprints(&self) -> Vec<Fingerprint> {
+        let mut fingerprints: Vec<Fingerprint> = Vec::new();
+        for fingerprint in [&self.public, &self.gossip, &self.verified]
+            .iter()
+            .filter_map(|fingerprint| fingerprint.as_ref())
+        {
+            if !fingerprints.contains(fingerprint) {
+                fingerprints.push(fingerprint.clone());
+            }
+        }
+        fingerprints
+    }
+
+    /// Returns the gossiped key if a newer key was received in an
+    /// Autocrypt header and the gossiped key is not verified.
+    fn superseded_gossip_key(&self) -> Option<Fingerprint> {
+        let gossip = self.gossip.as_ref()?;
+        if self.public.is_some()
+            && self.public.as_ref() != Some(gossip)
+            && self.verified.as_ref() != Some(gossip)
+            && self.gossip_timestamp < self.last_seen_autocrypt
+        {
+            Some(gossip.clone())
+        } else {
+            None
+        }
+    }
+}
+
+fn parse_fingerprint(hex: Option<String>) -> Option<Fingerprint> {
+    hex.filter(|hex| !hex.is_empty())
+        .and_then(|hex| hex.parse().ok())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::key::{SignedPublicKey, SignedSecretKey};
     use crate::test_utils::*;
 
     #[test]
@@ -89,4 +326,168 @@ mod tests {
         let sec_ring: Keyring<SignedSecretKey> = Keyring::new_self(&t.ctx).await.unwrap();
         assert_eq!(sec_ring.keys(), [alice.secret]);
     }
+
+    #[async_std::test]
+    async fn test_import_public_key() {
+        let t = TestContext::new_alice().await;
+        let bob = bob_keypair();
+
+        let fingerprint = import_public_key(&t.ctx, "Bob@Example.net", &bob.public.to_asc(None))
+            .await
+            .unwrap();
+        assert_eq!(fingerprint, bob.public.fingerprint());
+        let peerstate = Peerstate::from_addr(&t.ctx, "bob@example.net")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(peerstate.public_key, Some(bob.public));
+        assert_eq!(peerstate.prefer_encrypt, EncryptPreference::Mutual);
+
+        // the key of an existing peer is replaced
+        let fiona = SignedPublicKey::from_base64(include_str!("../test-data/key/fiona-public.asc"))
+            .unwrap();
+        import_public_key(&t.ctx, "bob@example.net", &fiona.to_asc(None))
+            .await
+            .unwrap();
+        let peerstate = Peerstate::from_addr(&t.ctx, "bob@example.net")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(peerstate.public_key_fingerprint, Some(fiona.fingerprint()));
+
+        assert!(import_public_key(&t.ctx, "bob@example.net", "garbage")
+            .await
+            .is_err());
+    }
+
+    async fn insert_peerstate(
+        t: &TestContext,
+        addr: &str,
+        last_seen: i64,
+        gossip_timestamp: i64,
+        fingerprints: (&str, &str, &str),
+    ) {
+        let (public, gossip, verified) = fingerprints;
+        let mut peerstate = Peerstate::new(&t.ctx, addr.to_string());
+        peerstate.last_seen = last_seen;
+        peerstate.last_seen_autocrypt = last_seen;
+        peerstate.gossip_timestamp = gossip_timestamp;
+        peerstate.public_key_fingerprint = public.parse().ok();
+        peerstate.gossip_key_fingerprint = gossip.parse().ok();
+        peerstate.verified_key_fingerprint = verified.parse().ok();
+        // creating the peerstate again adds a duplicate
+        peerstate.save_to_db(&t.ctx.sql, true).await.unwrap();
+    }
+
+    fn fp(c: char) -> String {
+        std::iter::repeat(c).take(40).collect()
+    }
+
+    async fn peerstate_count(t: &TestContext) -> i32 {
+        t.ctx
+            .sql
+            .query_get_value(&t.ctx, "SELECT COUNT(*) FROM acpeerstates;", paramsv![])
+            .await
+            .unwrap()
+    }
+
+    #[async_std::test]
+    async fn test_prune() {
+        let t = TestContext::new_alice().await;
+        let (a, b, c, d, e, f) = (fp('A'), fp('B'), fp('C'), fp('D'), fp('E'), fp('F'));
+
+        // duplicate peerstates, the older one is removed
+        insert_peerstate(&t, "bob@example.net", 100, 0, (&a, &a, "")).await;
+        insert_peerstate(&t, "Bob@Example.net", 50, 0, (&b, &c, "")).await;
+        insert_peerstate(&t, "bob@example.net", 40, 0, (&a, "", "")).await;
+        // gossiped key superseded by a newer Autocrypt header
+        insert_peerstate(&t, "carol@example.net", 200, 100, (&d, &e, "")).await;
+        // gossiped key newer than the Autocrypt header
+        insert_peerstate(&t, "dave@example.net", 100, 200, (&d, &e, "")).await;
+        // verified keys are kept
+        insert_peerstate(&t, "erin@example.net", 100, 0, (&d, "", &d)).await;
+        insert_peerstate(&t, "erin@example.net", 50, 20, (&f, &e, &f)).await;
+        // keys of the user are kept
+        insert_peerstate(&t, "alice@example.com", 100, 50, (&d, &e, "")).await;
+
+        let expected = vec![
+            PrunedKey {
+                addr: "Bob@Example.net".to_string(),
+                fingerprint: b.parse().unwrap(),
+            },
+            PrunedKey {
+                addr: "Bob@Example.net".to_string(),
+                fingerprint: c.parse().unwrap(),
+            },
+            PrunedKey {
+                addr: "carol@example.net".to_string(),
+                fingerprint: e.parse().unwrap(),
+            },
+        ];
+        assert_eq!(prune(&t.ctx, true).await.unwrap(), expected);
+        assert_eq!(peerstate_count(&t).await, 8);
+
+        assert_eq!(prune(&t.ctx, false).await.unwrap(), expected);
+        assert_eq!(peerstate_count(&t).await, 6);
+        let carol = Peerstate::from_addr(&t.ctx, "carol@example.net")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(carol.gossip_key_fingerprint, None);
+
+        assert!(prune(&t.ctx, false).await.unwrap().is_empty());
+    }
+
+    #[async_std::test]
+    async fn test_prune_keep_superseded() {
+        let t = TestContext::new_alice().await;
+        t.ctx
+            .set_config(Config::KeepSupersededKeys, Some("2"))
+            .await
+            .unwrap();
+        let (a, b, c, d, e, f) = (fp('A'), fp('B'), fp('C'), fp('D'), fp('E'), fp('F'));
+
+        // bob rotated his key several times, the two most recent old keys are kept
+        insert_peerstate(&t, "bob@example.net", 100, 0, (&a, "", "")).await;
+        insert_peerstate(&t, "bob@example.net", 90, 0, (&b, "", "")).await;
+        insert_peerstate(&t, "bob@example.net", 80, 0, (&a, "", "")).await;
+        insert_peerstate(&t, "bob@example.net", 70, 0, (&c, "", "")).await;
+        insert_peerstate(&t, "bob@example.net", 60, 0, (&d, "", "")).await;
+        insert_peerstate(&t, "bob@example.net", 50, 0, (&e, "", "")).await;
+        // the superseded gossiped key is one of the kept keys
+        insert_peerstate(&t, "carol@example.net", 200, 100, (&a, &b, "")).await;
+        insert_peerstate(&t, "carol@example.net", 90, 0, (&c, "", "")).await;
+        insert_peerstate(&t, "carol@example.net", 80, 0, (&f, "", "")).await;
+
+        let expected = vec![
+            PrunedKey {
+                addr: "bob@example.net".to_string(),
+                fingerprint: d.parse().unwrap(),
+            },
+            PrunedKey {
+                addr: "bob@example.net".to_string(),
+                fingerprint: e.parse().unwrap(),
+            },
+            PrunedKey {
+                addr: "carol@example.net".to_string(),
+                fingerprint: f.parse().unwrap(),
+            },
+        ];
+        assert_eq!(prune(&t.ctx, false).await.unwrap(), expected);
+        // bob: a, b and c; carol: a with gossiped b and c
+        assert_eq!(peerstate_count(&t).await, 5);
+        let gossiped: i32 = t
+            .ctx
+            .sql
+            .query_get_value(
+                &t.ctx,
+                "SELECT COUNT(*) FROM acpeerstates WHERE gossip_key_fingerprint=?;",
+                paramsv![b],
+            )
+            .await
+            .unwrap();
+        assert_eq!(gossiped, 1);
+
+        assert!(prune(&t.ctx, false).await.unwrap().is_empty());
+    }
 }
@@ -32,6 +32,7 @@ pub mod sql;
 #[cfg(not(feature = "internals"))]
 #[macro_use]
 mod sql;
+pub use sql::HousekeepingStats;
 
 pub mod headerdef;
 
@@ -40,6 +41,7 @@ pub use events::*;
 
 mod aheader;
 mod blob;
+pub mod calendar;
 pub mod chat;
 pub mod chatlist;
 pub mod config;
@@ -50,16 +52,18 @@ pub mod context;
 mod e2ee;
 pub mod ephemeral;
 mod imap;
+pub use imap::{FolderInfo, SpecialUse};
 pub mod imex;
 mod scheduler;
 #[macro_use]
 pub mod job;
 mod format_flowed;
 pub mod key;
-mod keyring;
+pub mod keyring;
 pub mod location;
 mod login_param;
 pub mod lot;
+pub mod mailinglist;
 pub mod message;
 mod mimefactory;
 pub mod mimeparser;
@@ -72,10 +76,16 @@ pub mod qr;
 pub mod securejoin;
 mod simplify;
 mod smtp;
+#[cfg(feature = "loopback")]
+pub use smtp::loopback::Loopback;
 pub mod stock;
+mod sync;
 mod token;
+mod trace;
+pub mod webxdc;
 #[macro_use]
 mod dehtml;
+mod html;
 
 pub mod dc_receive_imf;
 pub mod dc_tools;
@@ -1,11 +1,13 @@
 //! Location handling
 
 use bitflags::bitflags;
+use deltachat_derive::{FromSql, ToSql};
 use quick_xml::events::{BytesEnd, BytesStart, BytesText};
 
 use crate::chat::{self, ChatId};
 use crate::config::Config;
 use crate::constants::*;
+use crate::contact::Contact;
 use crate::context::*;
 use crate::dc_tools::*;
 use crate::error::{ensure, Error};
@@ -214,7 +216,12 @@ pub async fn send_locations_to_chat(context: &Context, chat_id: ChatId, seconds:
                 let mut msg = Message::new(Viewtype::Text);
                 msg.text = Some(
                     context
-                        .stock_system_msg(StockMessage::MsgLocationEnabled, "", "", 0)
+                        .stock_system_msg(
+                            StockMessage::MsgLocationEnabledUntil,
+                            context.format_timestamp(now + seconds),
+                            "",
+                            0,
+                        )
                         .await,
                 );
                 msg.param.set_cmd(SystemMessage::LocationStreamingEnabled);
@@ -371,6 +378,74 @@ pub async fn get_range(
         .unwrap_or_default()
 }
 
+/// Number of clustering cells per map tile and axis.
+///
+/// Map tiles are usually 256 pixels wide, so points closer than about
+/// 32 pixels on the screen are combined into one cluster.
+const CLUSTER_CELLS_PER_TILE: f64 = 8.0;
+
+/// Highest supported zoom level, higher levels are treated as this one.
+const MAX_CLUSTER_ZOOM_LEVEL: u32 = 24;
+
+/// Several nearby locations combined for displaying them on a map.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Cluster {
+    /// Latitude of the center of the clustered locations.
+    pub latitude: f64,
+    /// Longitude of the center of the clustered locations.
+    pub longitude: f64,
+    /// Number of locations in the cluster.
+    pub count: usize,
+    /// Timestamp of the newest location in the cluster.
+    pub timestamp: i64,
+}
+
+/// Returns the locations of a chat combined into clusters.
+///
+/// `zoom_level` is the zoom level of the map as used for web map tiles,
+/// 0 shows the whole world on one tile, each further level doubles the
+/// resolution. If `chat_id` is unset, the locations of all chats are
+/// clustered. Use [get_range] to get the single locations.
+pub async fn get_clustered(context: &Context, chat_id: ChatId, zoom_level: u32) -> Vec<Cluster> {
+    let locations = get_range(context, chat_id, 0, 0, 0).await;
+    cluster_locations(&locations, zoom_level)
+}
+
+fn cluster_locations(locations: &[Location], zoom_level: u32) -> Vec<Cluster> {
+    let cells = f64::from(1u32 << zoom_level.min(MAX_CLUSTER_ZOOM_LEVEL)) * CLUSTER_CELLS_PER_TILE;
+    let mut clusters: Vec<Cluster> = Vec::new();
+    let mut cluster_by_cell = std::collections::HashMap::new();
+
+    for location in locations {
+        let (x, y) = web_mercator(location.latitude, location.longitude);
+        let cell = ((x * cells) as u64, (y * cells) as u64);
+        let index = *cluster_by_cell.entry(cell).or_insert_with(|| {
+            clusters.push(Cluster::default());
+            clusters.len() - 1
+        });
+
+        // The center is the running mean of the locations in the cluster.
+        if let Some(cluster) = clusters.get_mut(index) {
+            cluster.count += 1;
+            let weight = 1.0 / cluster.count as f64;
+            cluster.latitude += (location.latitude - cluster.latitude) * weight;
+            cluster.longitude += (location.longitude - cluster.longitude) * weight;
+            cluster.timestamp = cluster.timestamp.max(location.timestamp);
+        }
+    }
+
+    clusters
+}
+
+/// Projects a coordinate to the unit square as done for web map tiles.
+fn web_mercator(latitude: f64, longitude: f64) -> (f64, f64) {
+    // Beyond this latitude the projection goes to infinity.
+    let latitude = latitude.max(-85.051_128).min(85.051_128).to_radians();
+    let x = (longitude + 180.0) / 360.0;
+    let y = (1.0 - (latitude.tan() + 1.0 / latitude.cos()).ln() / std::f64::consts::PI) / 2.0;
+    (x.max(0.0).min(1.0), y.max(0.0).min(1.0))
+}
+
 fn is_marker(txt: &str) -> bool {
     txt.len() == 1 && !txt.starts_with(' ')
 }
@@ -479,6 +554,109 @@ pub fn get_message_kml(timestamp: i64, latitude: f64, longitude: f64) -> String
     )
 }
 
+/// Exports the locations streamed in a chat as KML document.
+///
+/// The locations of each contact are put into a separate folder, each
+/// location is a placemark with its timestamp. Only locations between
+/// `timestamp_from` and `timestamp_to` are exported, if `timestamp_to` is 0,
+/// all locations up to now are exported. If there are no locations, an
+/// empty document is returned.
+pub async fn export_kml(
+    context: &Context,
+    chat_id: ChatId,
+    timestamp_from: i64,
+    timestamp_to: i64,
+) -> String {
+    let mut ret = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <kml xmlns=\"http://www.opengis.net/kml/2.2\">\n\
+         <Document>\n",
+    );
+    for (name, locations) in get_tracks(context, chat_id, timestamp_from, timestamp_to).await {
+        ret += &format!("<Folder><name>{}</name>\n", escaper::encode_minimal(&name));
+        for location in locations {
+            ret += &format!(
+                "<Placemark><TimeStamp><when>{}</when></TimeStamp>\
+                 <Point><coordinates>{},{}</coordinates></Point></Placemark>\n",
+                get_kml_timestamp(location.timestamp),
+                location.longitude,
+                location.latitude,
+            );
+        }
+        ret += "</Folder>\n";
+    }
+    ret += "</Document>\n</kml>";
+    ret
+}
+
+/// Exports the locations streamed in a chat as GPX document.
+///
+/// Works as [export_kml], the locations of each contact are exported as a
+/// separate track.
+pub async fn export_gpx(
+    context: &Context,
+    chat_id: ChatId,
+    timestamp_from: i64,
+    timestamp_to: i64,
+) -> String {
+    let mut ret = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <gpx version=\"1.1\" creator=\"Delta Chat\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n",
+    );
+    for (name, locations) in get_tracks(context, chat_id, timestamp_from, timestamp_to).await {
+        ret += &format!(
+            "<trk><name>{}</name><trkseg>\n",
+            escaper::encode_minimal(&name)
+        );
+        for location in locations {
+            ret += &format!(
+                "<trkpt lat=\"{}\" lon=\"{}\"><time>{}</time></trkpt>\n",
+                location.latitude,
+                location.longitude,
+                get_kml_timestamp(location.timestamp),
+            );
+        }
+        ret += "</trkseg></trk>\n";
+    }
+    ret += "</gpx>";
+    ret
+}
+
+/// Returns the streamed locations of a chat as list of contact names
+/// and their locations, sorted by time.
+async fn get_tracks(
+    context: &Context,
+    chat_id: ChatId,
+    timestamp_from: i64,
+    timestamp_to: i64,
+) -> Vec<(String, Vec<Location>)> {
+    let mut tracks: Vec<(u32, Vec<Location>)> = Vec::new();
+    let locations = get_range(context, chat_id, 0, timestamp_from, timestamp_to).await;
+    // get_range() returns the newest locations first
+    for location in locations.into_iter().rev() {
+        if location.independent != 0 {
+            continue;
+        }
+        match tracks
+            .iter_mut()
+            .find(|(contact_id, _)| *contact_id == location.contact_id)
+        {
+            Some((_, track)) => track.push(location),
+            None => tracks.push((location.contact_id, vec![location])),
+        }
+    }
+
+    let mut ret = Vec::with_capacity(tracks.len());
+    for (contact_id, locations) in tracks {
+        let name = match Contact::get_by_id(context, contact_id).await {
+            Ok(contact) => contact.get_display_name().to_string(),
+            Err(_) => contact_id.to_string(),
+        };
+        ret.push((name, locations));
+    }
+    ret
+}
+
 pub async fn set_kml_sent_timestamp(
     context: &Context,
     chat_id: ChatId,
@@ -576,9 +754,167 @@ pub async fn save(
         newest_location_id = loc_id;
     }
 
+    // Independent locations are points attached to messages,
+    // they do not tell where the contact is.
+    if !independent {
+        if let Err(err) = check_geofences(context, contact_id, locations).await {
+            warn!(context, "Failed to check geofences: {}", err);
+        }
+    }
+
     Ok(newest_location_id)
 }
 
+/// Mean earth radius in meters.
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+/// Direction of a boundary crossing that triggers a geofence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive, FromSql, ToSql)]
+#[repr(u32)]
+pub enum GeofenceTrigger {
+    /// The contact moves into the geofence.
+    Enter = 1,
+
+    /// The contact leaves the geofence.
+    Exit = 2,
+}
+
+/// Adds a geofence watching the locations streamed by a contact.
+///
+/// The geofence is a circle of `radius` meters around the given center.
+/// [EventType::GeofenceTriggered] is emitted whenever a received location
+/// of the contact crosses the boundary in the direction given by `trigger`.
+/// The first location received after adding the geofence only determines
+/// whether the contact is inside, so a contact already at home does not
+/// trigger an `Enter` geofence around it.
+///
+/// Returns the ID of the new geofence.
+pub async fn add_geofence(
+    context: &Context,
+    contact_id: u32,
+    latitude: f64,
+    longitude: f64,
+    radius: f64,
+    trigger: GeofenceTrigger,
+) -> Result<u32, Error> {
+    ensure!(contact_id != 0, "Invalid contact id");
+    ensure!(radius > 0.0, "Invalid geofence radius {}", radius);
+
+    let geofence_id = context
+        .sql
+        .with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO geofences (contact_id, latitude, longitude, radius, direction) \
+                 VALUES (?,?,?,?,?);",
+                paramsv![contact_id as i32, latitude, longitude, radius, trigger],
+            )?;
+            Ok(conn.last_insert_rowid() as u32)
+        })
+        .await?;
+    Ok(geofence_id)
+}
+
+/// Deletes a geofence added by [add_geofence].
+pub async fn delete_geofence(context: &Context, geofence_id: u32) -> Result<(), Error> {
+    context
+        .sql
+        .execute(
+            "DELETE FROM geofences WHERE id=?;",
+            paramsv![geofence_id as i32],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Checks the geofences of a contact against newly received locations.
+async fn check_geofences(
+    context: &Context,
+    contact_id: u32,
+    locations: &[Location],
+) -> Result<(), Error> {
+    let geofences = context
+        .sql
+        .query_map(
+            "SELECT id, latitude, longitude, radius, direction, inside, timestamp \
+             FROM geofences WHERE contact_id=?;",
+            paramsv![contact_id as i32],
+            |row| {
+                Ok((
+                    row.get::<_, u32>(0)?,
+                    row.get::<_, f64>(1)?,
+                    row.get::<_, f64>(2)?,
+                    row.get::<_, f64>(3)?,
+                    row.get::<_, GeofenceTrigger>(4)?,
+                    row.get::<_, i32>(5)?,
+                    row.get::<_, i64>(6)?,
+                ))
+            },
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+    if geofences.is_empty() {
+        return Ok(());
+    }
+
+    let mut locations: Vec<&Location> = locations.iter().collect();
+    locations.sort_by_key(|location| location.timestamp);
+
+    for (geofence_id, latitude, longitude, radius, trigger, inside, timestamp) in geofences {
+        // -1 means that no location was checked yet.
+        let mut inside = match inside {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        };
+        let mut newest_timestamp = timestamp;
+
+        // Older locations may arrive late, they must not trigger again.
+        for location in locations.iter().filter(|l| l.timestamp > timestamp) {
+            let now_inside =
+                distance(latitude, longitude, location.latitude, location.longitude) <= radius;
+            let triggered = match (inside, trigger) {
+                (Some(false), GeofenceTrigger::Enter) => now_inside,
+                (Some(true), GeofenceTrigger::Exit) => !now_inside,
+                _ => false,
+            };
+            if triggered {
+                info!(
+                    context,
+                    "Geofence {} triggered by contact {}", geofence_id, contact_id
+                );
+                context.emit_event(EventType::GeofenceTriggered {
+                    contact_id,
+                    geofence_id,
+                });
+            }
+            inside = Some(now_inside);
+            newest_timestamp = location.timestamp;
+        }
+
+        if newest_timestamp != timestamp {
+            context
+                .sql
+                .execute(
+                    "UPDATE geofences SET inside=?, timestamp=? WHERE id=?;",
+                    paramsv![inside == Some(true), newest_timestamp, geofence_id as i32],
+                )
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the distance between two coordinates in meters.
+fn distance(latitude1: f64, longitude1: f64, latitude2: f64, longitude2: f64) -> f64 {
+    let delta_latitude = (latitude2 - latitude1).to_radians();
+    let delta_longitude = (longitude2 - longitude1).to_radians();
+    let a = (delta_latitude / 2.0).sin().powi(2)
+        + latitude1.to_radians().cos()
+            * latitude2.to_radians().cos()
+            * (delta_longitude / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * a.sqrt().min(1.0).asin()
+}
+
 pub(crate) async fn job_maybe_send_locations(context: &Context, _job: &Job) -> job::Status {
     let now = time();
     let mut continue_streaming = false;
@@ -775,4 +1111,379 @@ mod tests {
         assert_eq!(locations_ref[0].accuracy, 0.0f64);
         assert_eq!(locations_ref[0].timestamp, timestamp);
     }
+
+    fn test_location(latitude: f64, longitude: f64, timestamp: i64) -> Location {
+        Location {
+            latitude,
+            longitude,
+            timestamp,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_cluster_locations() {
+        // two points in Berlin about 90 meters apart and one in Munich
+        let locations = vec![
+            test_location(52.5200, 13.4050, 1000),
+            test_location(52.5206, 13.4060, 2000),
+            test_location(48.1372, 11.5756, 3000),
+        ];
+
+        let clusters = cluster_locations(&locations, 0);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].count, 3);
+        assert_eq!(clusters[0].timestamp, 3000);
+
+        let clusters = cluster_locations(&locations, 10);
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].count, 2);
+        assert!((clusters[0].latitude - 52.5203).abs() < 0.000_001);
+        assert!((clusters[0].longitude - 13.4055).abs() < 0.000_001);
+        assert_eq!(clusters[0].timestamp, 2000);
+        assert_eq!(clusters[1].count, 1);
+        assert_eq!(clusters[1].latitude, 48.1372);
+
+        let clusters = cluster_locations(&locations, 18);
+        assert_eq!(clusters.len(), 3);
+        assert!(clusters.iter().all(|cluster| cluster.count == 1));
+
+        // levels beyond the maximum do not overflow
+        assert_eq!(cluster_locations(&locations, 100).len(), 3);
+        assert!(cluster_locations(&[], 5).is_empty());
+    }
+
+    #[async_std::test]
+    async fn test_location_enabled_uses_time_formatter() {
+        let t = TestContext::new_alice().await;
+        let contact_id = crate::contact::Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, contact_id)
+            .await
+            .unwrap();
+        let label = "tomorrow at 9 pm".to_string();
+        t.ctx
+            .set_time_formatter(Some(Box::new(move |_| label.clone())));
+        send_locations_to_chat(&t.ctx, chat_id, 1000).await;
+
+        let msgs = chat::get_chat_msgs(&t.ctx, chat_id, 0, None).await;
+        let msg_id = match msgs.last() {
+            Some(chat::ChatItem::Message { msg_id }) => *msg_id,
+            _ => panic!("no message sent"),
+        };
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(
+            msg.get_text().unwrap(),
+            "Location streaming enabled until tomorrow at 9 pm."
+        );
+
+        t.ctx.set_time_formatter(None);
+        assert_eq!(t.ctx.format_timestamp(0), dc_timestamp_to_str(0));
+    }
+
+    #[async_std::test]
+    async fn test_get_clustered() {
+        let t = TestContext::new_alice().await;
+        let contact_id = crate::contact::Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, contact_id)
+            .await
+            .unwrap();
+        send_locations_to_chat(&t.ctx, chat_id, 1000).await;
+        assert!(set(&t.ctx, 52.5200, 13.4050, 10.0).await);
+        assert!(set(&t.ctx, 52.5206, 13.4060, 10.0).await);
+
+        assert_eq!(get_range(&t.ctx, chat_id, 0, 0, 0).await.len(), 2);
+        let clusters = get_clustered(&t.ctx, chat_id, 5).await;
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].count, 2);
+        assert_eq!(get_clustered(&t.ctx, chat_id, 18).await.len(), 2);
+    }
+
+    #[async_std::test]
+    async fn test_send_locations_auto_stop() {
+        let t = TestContext::new_alice().await;
+        let contact_id = crate::contact::Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, contact_id)
+            .await
+            .unwrap();
+        send_locations_to_chat(&t.ctx, chat_id, 1000).await;
+        assert!(is_sending_locations_to_chat(&t.ctx, chat_id).await);
+
+        // end time and stop job survive a restart
+        let TestContext { ctx, dir } = t;
+        drop(ctx);
+        let ctx = Context::new("FakeOS".into(), dir.path().join("db.sqlite").into(), 1)
+            .await
+            .unwrap();
+        assert!(is_sending_locations_to_chat(&ctx, chat_id).await);
+        assert!(job::action_exists(&ctx, job::Action::MaybeSendLocationsEnded).await);
+
+        // once the duration elapsed, the stop job ends streaming with an info message
+        ctx.sql
+            .execute(
+                "UPDATE chats SET locations_send_until=? WHERE id=?",
+                paramsv![time() - 1, chat_id],
+            )
+            .await
+            .unwrap();
+        assert!(!is_sending_locations_to_chat(&ctx, chat_id).await);
+        let mut job = Job::new(
+            job::Action::MaybeSendLocationsEnded,
+            chat_id.to_u32(),
+            Params::new(),
+            0,
+        );
+        job_maybe_send_locations_ended(&ctx, &mut job).await;
+
+        let msgs = chat::get_chat_msgs(&ctx, chat_id, 0, None).await;
+        let msg_id = match msgs.last() {
+            Some(chat::ChatItem::Message { msg_id }) => *msg_id,
+            _ => panic!("no info message added"),
+        };
+        let msg = Message::load_from_db(&ctx, msg_id).await.unwrap();
+        assert!(msg.is_info());
+        assert_eq!(msg.get_text().unwrap(), "Location streaming disabled.");
+        let (send_begin, send_until) = ctx
+            .sql
+            .query_row(
+                "SELECT locations_send_begin, locations_send_until FROM chats WHERE id=?",
+                paramsv![chat_id],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+            )
+            .await
+            .unwrap();
+        assert_eq!((send_begin, send_until), (0, 0));
+
+        // running the job again does not add another message
+        job_maybe_send_locations_ended(&ctx, &mut job).await;
+        assert_eq!(
+            chat::get_chat_msgs(&ctx, chat_id, 0, None).await.len(),
+            msgs.len()
+        );
+    }
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(distance(52.52, 13.405, 52.52, 13.405), 0.0);
+        // Berlin - Munich is about 504 km
+        let d = distance(52.5200, 13.4050, 48.1372, 11.5756);
+        assert!(d > 503_000.0 && d < 506_000.0);
+    }
+
+    fn count_geofence_events(emitter: &crate::events::EventEmitter, geofence_id: u32) -> usize {
+        let mut count = 0;
+        while let Ok(event) = emitter.try_recv() {
+            if let EventType::GeofenceTriggered {
+                geofence_id: id, ..
+            } = event.typ
+            {
+                if id == geofence_id {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    #[async_std::test]
+    async fn test_geofence() {
+        let t = TestContext::new_alice().await;
+        let bob_id = crate::contact::Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, bob_id).await.unwrap();
+
+        // 200 meters around Alexanderplatz
+        let enter_id = add_geofence(
+            &t.ctx,
+            bob_id,
+            52.5219,
+            13.4132,
+            200.0,
+            GeofenceTrigger::Enter,
+        )
+        .await
+        .unwrap();
+        let exit_id = add_geofence(
+            &t.ctx,
+            bob_id,
+            52.5219,
+            13.4132,
+            200.0,
+            GeofenceTrigger::Exit,
+        )
+        .await
+        .unwrap();
+        let emitter = t.ctx.get_event_emitter();
+        while emitter.try_recv().is_ok() {}
+
+        // Bob walks from the Brandenburg Gate to Alexanderplatz and stays there.
+        let track = [
+            (52.5163, 13.3777),
+            (52.5186, 13.3996),
+            (52.5215, 13.4110),
+            (52.5220, 13.4135),
+            (52.5218, 13.4129),
+        ];
+        for (i, (latitude, longitude)) in track.iter().enumerate() {
+            let location = test_location(*latitude, *longitude, 1000 + i as i64);
+            save(&t.ctx, chat_id, bob_id, &[location], false)
+                .await
+                .unwrap();
+        }
+        assert_eq!(count_geofence_events(&emitter, enter_id), 1);
+
+        // a late location from before does not trigger again
+        let location = test_location(52.5163, 13.3777, 900);
+        save(&t.ctx, chat_id, bob_id, &[location], false)
+            .await
+            .unwrap();
+        assert_eq!(count_geofence_events(&emitter, enter_id), 0);
+
+        // leaving the geofence only triggers the exit geofence,
+        // also if several locations are received at once
+        let locations = [
+            test_location(52.5100, 13.4000, 2001),
+            test_location(52.5110, 13.4010, 2000),
+        ];
+        save(&t.ctx, chat_id, bob_id, &locations, false)
+            .await
+            .unwrap();
+        let mut exits = 0;
+        let mut enters = 0;
+        while let Ok(event) = emitter.try_recv() {
+            if let EventType::GeofenceTriggered { geofence_id, .. } = event.typ {
+                if geofence_id == exit_id {
+                    exits += 1;
+                } else if geofence_id == enter_id {
+                    enters += 1;
+                }
+            }
+        }
+        assert_eq!((enters, exits), (0, 1));
+
+        // markers attached to messages are no positions of the contact
+        let location = test_location(52.5219, 13.4132, 3000);
+        save(&t.ctx, chat_id, bob_id, &[location], true)
+            .await
+            .unwrap();
+        assert_eq!(count_geofence_events(&emitter, enter_id), 0);
+
+        // geofences and their state survive a restart
+        let TestContext { ctx, dir } = t;
+        drop(ctx);
+        let ctx = Context::new("FakeOS".into(), dir.path().join("db.sqlite").into(), 1)
+            .await
+            .unwrap();
+        let emitter = ctx.get_event_emitter();
+        let location = test_location(52.5219, 13.4132, 4000);
+        save(&ctx, chat_id, bob_id, &[location], false)
+            .await
+            .unwrap();
+        assert_eq!(count_geofence_events(&emitter, enter_id), 1);
+
+        delete_geofence(&ctx, enter_id).await.unwrap();
+        let location = test_location(52.5100, 13.4000, 5000);
+        save(&ctx, chat_id, bob_id, &[location], false)
+            .await
+            .unwrap();
+        let location = test_location(52.5219, 13.4132, 6000);
+        save(&ctx, chat_id, bob_id, &[location], false)
+            .await
+            .unwrap();
+        assert_eq!(count_geofence_events(&emitter, enter_id), 0);
+    }
+
+    #[async_std::test]
+    async fn test_export_kml_gpx() {
+        let t = TestContext::new_alice().await;
+        let bob_id = Contact::create(&t.ctx, "Bob", "bob@example.net")
+            .await
+            .unwrap();
+        let claire_id = Contact::create(&t.ctx, "Claire & Co", "claire@example.net")
+            .await
+            .unwrap();
+        let chat_id =
+            chat::create_group_chat(&t.ctx, crate::contact::VerifiedStatus::Unverified, "group")
+                .await
+                .unwrap();
+
+        // empty ranges give an empty but valid document
+        let kml = export_kml(&t.ctx, chat_id, 0, 0).await;
+        assert!(kml.contains("<Document>\n</Document>"));
+        assert_eq!(
+            Kml::parse(&t.ctx, kml.as_bytes()).unwrap().locations.len(),
+            0
+        );
+        let gpx = export_gpx(&t.ctx, chat_id, 0, 0).await;
+        assert!(!gpx.contains("<trk>"));
+
+        let bob_track = [
+            test_location(52.51, 13.37, 1000),
+            test_location(52.52, 13.38, 1060),
+            test_location(52.53, 13.39, 1120),
+        ];
+        save(&t.ctx, chat_id, bob_id, &bob_track, false)
+            .await
+            .unwrap();
+        let claire_track = [test_location(48.13, 11.57, 1030)];
+        save(&t.ctx, chat_id, claire_id, &claire_track, false)
+            .await
+            .unwrap();
+        // markers are not part of the tracks
+        save(
+            &t.ctx,
+            chat_id,
+            bob_id,
+            &[test_location(1.0, 2.0, 1090)],
+            true,
+        )
+        .await
+        .unwrap();
+
+        let kml = export_kml(&t.ctx, chat_id, 0, 2000).await;
+        let mut reader = quick_xml::Reader::from_str(&kml);
+        let mut buf = Vec::new();
+        let mut placemarks = 0;
+        let mut folders = 0;
+        loop {
+            match reader.read_event(&mut buf).expect("malformed KML") {
+                quick_xml::events::Event::Start(ref e) if e.name() == b"Placemark" => {
+                    placemarks += 1
+                }
+                quick_xml::events::Event::Start(ref e) if e.name() == b"Folder" => folders += 1,
+                quick_xml::events::Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+        assert_eq!(placemarks, 4);
+        assert_eq!(folders, 2);
+        assert!(kml.contains("<name>Claire &amp; Co</name>"));
+
+        let locations = Kml::parse(&t.ctx, kml.as_bytes()).unwrap().locations;
+        let timestamps: Vec<i64> = locations.iter().map(|l| l.timestamp).collect();
+        assert_eq!(timestamps, vec![1000, 1060, 1120, 1030]);
+        assert_eq!(locations[2].latitude, 52.53);
+        assert_eq!(locations[2].longitude, 13.39);
+
+        // the range is applied
+        let kml = export_kml(&t.ctx, chat_id, 1050, 1100).await;
+        assert_eq!(
+            Kml::parse(&t.ctx, kml.as_bytes()).unwrap().locations.len(),
+            1
+        );
+
+        let gpx = export_gpx(&t.ctx, chat_id, 0, 2000).await;
+        assert_eq!(gpx.matches("<trk>").count(), 2);
+        assert_eq!(gpx.matches("<trkpt ").count(), 4);
+        assert!(gpx.contains(
+            "<trkpt lat=\"52.51\" lon=\"13.37\"><time>1970-01-01T00:16:40Z</time></trkpt>"
+        ));
+    }
 }
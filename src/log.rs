@@ -1,32 +1,207 @@
 //! # Logging macros
 
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::context::Context;
+use crate::events::EventType;
+
+/// Replacement for secrets in log messages.
+const REDACTED: &str = "***";
+
+/// Config keys whose values are masked in all log messages.
+const SECRET_KEYS: &[&str] = &[
+    "mail_pw",
+    "send_pw",
+    "configured_mail_pw",
+    "configured_send_pw",
+    "oauth2_access_token",
+    "oauth2_refresh_token",
+];
+
+/// Secrets shorter than this are not masked,
+/// otherwise a one-letter password would garble every log message.
+const MIN_SECRET_LEN: usize = 3;
+
+/// Severity threshold for logging.
+///
+/// Log messages below the threshold set with [Context::set_log_level]
+/// are neither formatted nor emitted as events.
+///
+/// [Context::set_log_level]: crate::context::Context::set_log_level
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, FromPrimitive, ToPrimitive)]
+#[repr(u8)]
+pub enum LogLevel {
+    /// Emit all log messages, including protocol traces.
+    Debug = 0,
+    /// Emit informational messages, warnings and errors.
+    Info = 1,
+    /// Emit warnings and errors.
+    Warn = 2,
+    /// Emit errors only.
+    Error = 3,
+}
+
+impl Default for LogLevel {
+    fn default() -> Self {
+        LogLevel::Debug
+    }
+}
+
+/// Emits a log message, either as plain [EventType::Info] or [EventType::Warning]
+/// or, if structured logging is enabled, as [EventType::Log].
+///
+/// Use the [info!] and [warn!] macros instead of calling this directly.
+#[doc(hidden)]
+pub fn emit_log(
+    context: &Context,
+    level: LogLevel,
+    file: &str,
+    line: u32,
+    msg: String,
+    fields: Vec<(String, String)>,
+) {
+    let msg = format!("{}:{}: {}", file, line, redact(context, &msg));
+    let fields: Vec<(String, String)> = fields
+        .into_iter()
+        .map(|(key, value)| {
+            let value = redact(context, &value);
+            (key, value)
+        })
+        .collect();
+    if context.is_structured_logging() {
+        context.emit_event(EventType::Log { level, msg, fields });
+        return;
+    }
+
+    let mut full = msg;
+    for (key, value) in fields {
+        full += &format!(" {}={}", key, value);
+    }
+    context.emit_event(match level {
+        LogLevel::Debug | LogLevel::Info => EventType::Info(full),
+        LogLevel::Warn => EventType::Warning(full),
+        LogLevel::Error => EventType::Error(full),
+    });
+}
+
+/// Masks the configured passwords and tokens
+/// and anything looking like a credential, eg. `password=foo`, in `msg`.
+#[doc(hidden)]
+pub fn redact(context: &Context, msg: &str) -> String {
+    lazy_static! {
+        static ref CREDENTIAL: Regex = Regex::new(
+            r#"(?i)\b(\w*(?:pass(?:word|wd)?|pw|secret|token)\s*[=:]\s*)("[^"]*"|[^\s"&,;]+)"#
+        )
+        .unwrap();
+    }
+
+    let mut msg = CREDENTIAL
+        .replace_all(msg, |caps: &regex::Captures| {
+            format!("{}{}", caps.get(1).map_or("", |m| m.as_str()), REDACTED)
+        })
+        .into_owned();
+    if let Ok(secrets) = context.secrets.read() {
+        for secret in secrets.values() {
+            if msg.contains(secret.as_str()) {
+                msg = msg.replace(secret.as_str(), REDACTED);
+            }
+        }
+    }
+    msg
+}
+
+/// Remembers the value of `key` for masking if it is a secret,
+/// to be called whenever a config value changes.
+pub(crate) fn update_secret(context: &Context, key: &str, value: Option<&str>) {
+    if !SECRET_KEYS.contains(&key) {
+        return;
+    }
+    if let Ok(mut secrets) = context.secrets.write() {
+        match value {
+            Some(value) if value.len() >= MIN_SECRET_LEN => {
+                secrets.insert(key.to_string(), value.to_string());
+            }
+            _ => {
+                secrets.remove(key);
+            }
+        }
+    }
+}
+
+/// Loads all secrets from the database, to be called when the database is opened.
+pub(crate) async fn load_secrets(context: &Context) {
+    for key in SECRET_KEYS {
+        let value = context.sql.get_raw_config(context, key).await;
+        update_secret(context, key, value.as_deref());
+    }
+}
+
+/// Logs an informational message.
+///
+/// Key-value fields can be attached after a semicolon,
+/// eg. `info!(context, "connect"; server = host, port = port)`.
 #[macro_export]
 macro_rules! info {
-    ($ctx:expr,  $msg:expr) => {
+    ($ctx:expr, $msg:expr $(, $args:expr)* ; $($key:ident = $value:expr),+ $(,)?) => {{
+        if $ctx.is_log_enabled($crate::log::LogLevel::Info) {
+            let fields = vec![$((stringify!($key).to_string(), $value.to_string())),+];
+            $crate::log::emit_log(
+                &$ctx,
+                $crate::log::LogLevel::Info,
+                file!(),
+                line!(),
+                format!($msg $(, $args)*),
+                fields,
+            );
+        }
+    }};
+    ($ctx:expr, $msg:expr) => {
         info!($ctx, $msg,)
     };
     ($ctx:expr, $msg:expr, $($args:expr),* $(,)?) => {{
-        let formatted = format!($msg, $($args),*);
-        let full = format!("{file}:{line}: {msg}",
-                           file = file!(),
-                           line = line!(),
-                           msg = &formatted);
-        emit_event!($ctx, $crate::EventType::Info(full));
+        if $ctx.is_log_enabled($crate::log::LogLevel::Info) {
+            $crate::log::emit_log(
+                &$ctx,
+                $crate::log::LogLevel::Info,
+                file!(),
+                line!(),
+                format!($msg, $($args),*),
+                Vec::new(),
+            );
+        }
     }};
 }
 
 #[macro_export]
 macro_rules! warn {
+    ($ctx:expr, $msg:expr $(, $args:expr)* ; $($key:ident = $value:expr),+ $(,)?) => {{
+        if $ctx.is_log_enabled($crate::log::LogLevel::Warn) {
+            let fields = vec![$((stringify!($key).to_string(), $value.to_string())),+];
+            $crate::log::emit_log(
+                &$ctx,
+                $crate::log::LogLevel::Warn,
+                file!(),
+                line!(),
+                format!($msg $(, $args)*),
+                fields,
+            );
+        }
+    }};
     ($ctx:expr, $msg:expr) => {
         warn!($ctx, $msg,)
     };
     ($ctx:expr, $msg:expr, $($args:expr),* $(,)?) => {{
-        let formatted = format!($msg, $($args),*);
-        let full = format!("{file}:{line}: {msg}",
-                           file = file!(),
-                           line = line!(),
-                           msg = &formatted);
-        emit_event!($ctx, $crate::EventType::Warning(full));
+        if $ctx.is_log_enabled($crate::log::LogLevel::Warn) {
+            $crate::log::emit_log(
+                &$ctx,
+                $crate::log::LogLevel::Warn,
+                file!(),
+                line!(),
+                format!($msg, $($args),*),
+                Vec::new(),
+            );
+        }
     }};
 }
 
@@ -36,7 +211,7 @@ macro_rules! error {
         error!($ctx, $msg,)
     };
     ($ctx:expr, $msg:expr, $($args:expr),* $(,)?) => {{
-        let formatted = format!($msg, $($args),*);
+        let formatted = $crate::log::redact(&$ctx, &format!($msg, $($args),*));
         emit_event!($ctx, $crate::EventType::Error(formatted));
     }};
 }
@@ -47,7 +222,7 @@ macro_rules! error_network {
         error_network!($ctx, $msg,)
     };
     ($ctx:expr, $msg:expr, $($args:expr),* $(,)?) => {{
-        let formatted = format!($msg, $($args),*);
+        let formatted = $crate::log::redact(&$ctx, &format!($msg, $($args),*));
         emit_event!($ctx, $crate::EventType::ErrorNetwork(formatted));
     }};
 }
@@ -0,0 +1,338 @@
+//! # Mailing list headers
+//!
+//! Parsing of the `List-Id` (RFC 2919), `List-Post` and
+//! `List-Unsubscribe` (RFC 2369) and `List-Unsubscribe-Post` (RFC 8058)
+//! headers.
+
+use percent_encoding::percent_decode_str;
+
+use crate::error::{bail, format_err, Result};
+use crate::param::{Param, Params};
+
+/// Information about a mailing list taken from the `List-*` headers.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ListHeaders {
+    /// Identifier of the list, eg. `mylist.example.org`.
+    pub id: String,
+
+    /// Human readable name of the list, the identifier if none is given.
+    pub name: String,
+
+    /// Address to post to the list, if posting is allowed.
+    pub post: Option<String>,
+
+    /// `mailto:` URI to unsubscribe from the list.
+    pub unsubscribe_mailto: Option<String>,
+
+    /// `https:` or `http:` URL to unsubscribe from the list.
+    pub unsubscribe_url: Option<String>,
+
+    /// True if `unsubscribe_url` supports the One-Click POST of RFC 8058.
+    pub unsubscribe_one_click: bool,
+}
+
+impl ListHeaders {
+    /// Parses the values of the `List-Id`, `List-Post`, `List-Unsubscribe`
+    /// and `List-Unsubscribe-Post` headers.
+    ///
+    /// Returns `None` if `List-Id` does not contain an identifier.
+    pub(crate) fn parse(
+        list_id: &str,
+        list_post: Option<&str>,
+        list_unsubscribe: Option<&str>,
+        list_unsubscribe_post: Option<&str>,
+    ) -> Option<Self> {
+        let (name, id) = match (list_id.rfind('<'), list_id.rfind('>')) {
+            (Some(start), Some(end)) if start < end => (
+                list_id.get(..start).unwrap_or_default(),
+                list_id.get(start + 1..end).unwrap_or_default(),
+            ),
+            _ => ("", list_id),
+        };
+        let name = name.trim().trim_matches('"').trim();
+        let id = id.trim();
+        if id.is_empty() || id.contains(char::is_whitespace) {
+            return None;
+        }
+
+        let mut list = ListHeaders {
+            id: id.to_string(),
+            name: if name.is_empty() { id } else { name }.to_string(),
+            ..Default::default()
+        };
+
+        // "List-Post: NO" means posting is not allowed
+        list.post = list_post
+            .map(parse_uris)
+            .unwrap_or_default()
+            .into_iter()
+            .find_map(|uri| {
+                strip_scheme(&uri, "mailto:")
+                    .map(|addr| addr.split('?').next().unwrap_or_default().to_string())
+            })
+            .filter(|addr| addr.contains('@'));
+
+        for uri in list_unsubscribe.map(parse_uris).unwrap_or_default() {
+            let is_mailto = strip_scheme(&uri, "mailto:").is_some();
+            let is_url =
+                strip_scheme(&uri, "https:").is_some() || strip_scheme(&uri, "http:").is_some();
+            if is_mailto && list.unsubscribe_mailto.is_none() {
+                list.unsubscribe_mailto = Some(uri);
+            } else if is_url && list.unsubscribe_url.is_none() {
+                list.unsubscribe_url = Some(uri);
+            }
+        }
+
+        // RFC 8058 requires the One-Click URL to be HTTPS.
+        list.unsubscribe_one_click = list
+            .unsubscribe_url
+            .as_ref()
+            .map_or(false, |url| strip_scheme(url, "https:").is_some())
+            && list_unsubscribe_post.map_or(false, |value| {
+                value
+                    .split(',')
+                    .any(|v| v.trim().eq_ignore_ascii_case("List-Unsubscribe=One-Click"))
+            });
+
+        Some(list)
+    }
+
+    /// Loads the list information stored in the params of a chat.
+    pub(crate) fn from_params(param: &Params) -> Option<Self> {
+        let id = param.get(Param::ListId)?;
+        Some(ListHeaders {
+            id: id.to_string(),
+            name: param.get(Param::ListName).unwrap_or(id).to_string(),
+            post: param.get(Param::ListPost).map(|s| s.to_string()),
+            unsubscribe_mailto: param
+                .get(Param::ListUnsubscribeMailto)
+                .map(|s| s.to_string()),
+            unsubscribe_url: param.get(Param::ListUnsubscribeUrl).map(|s| s.to_string()),
+            unsubscribe_one_click: param
+                .get_bool(Param::ListUnsubscribeOneClick)
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Stores the list information in the params of a chat.
+    pub(crate) fn to_params(&self, param: &mut Params) {
+        param.set(Param::ListId, &self.id);
+        param.set(Param::ListName, &self.name);
+        set_or_remove(param, Param::ListPost, &self.post);
+        set_or_remove(
+            param,
+            Param::ListUnsubscribeMailto,
+            &self.unsubscribe_mailto,
+        );
+        set_or_remove(param, Param::ListUnsubscribeUrl, &self.unsubscribe_url);
+        if self.unsubscribe_one_click {
+            param.set_int(Param::ListUnsubscribeOneClick, 1);
+        } else {
+            param.remove(Param::ListUnsubscribeOneClick);
+        }
+    }
+}
+
+/// A `mailto:` URI split into address, subject and body.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Mailto {
+    pub addr: String,
+    pub subject: Option<String>,
+    pub body: Option<String>,
+}
+
+impl Mailto {
+    /// Parses a `mailto:` URI as described in RFC 6068.
+    ///
+    /// Only the first recipient is used, other header fields are ignored.
+    pub(crate) fn parse(uri: &str) -> Option<Self> {
+        let rest = strip_scheme(uri, "mailto:")?;
+        let mut parts = rest.splitn(2, '?');
+        let to = parts.next().unwrap_or_default();
+        let query = parts.next().unwrap_or_default();
+        let addr = decode(to.split(',').next().unwrap_or_default());
+        if !addr.contains('@') {
+            return None;
+        }
+
+        let mut mailto = Mailto {
+            addr,
+            ..Default::default()
+        };
+        for field in query.split('&') {
+            let mut parts = field.splitn(2, '=');
+            let (name, value) = match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) => (name, value),
+                _ => continue,
+            };
+            if name.eq_ignore_ascii_case("subject") {
+                mailto.subject = Some(decode(value));
+            } else if name.eq_ignore_ascii_case("body") {
+                mailto.body = Some(decode(value));
+            }
+        }
+        Some(mailto)
+    }
+}
+
+fn decode(value: &str) -> String {
+    percent_decode_str(value).decode_utf8_lossy().to_string()
+}
+
+/// Sends the One-Click unsubscribe POST of RFC 8058 to `url`.
+pub(crate) async fn post_one_click(url: &str) -> Result<()> {
+    let response = surf::post(url)
+        .body_form(&[("List-Unsubscribe", "One-Click")])
+        .map_err(|err| format_err!("Cannot create request to {}: {}", url, err))?
+        .await
+        .map_err(|err| format_err!("POST to {} failed: {}", url, err))?;
+    if !response.status().is_success() {
+        bail!("POST to {} failed with status {}", url, response.status());
+    }
+    Ok(())
+}
+
+fn set_or_remove(param: &mut Params, key: Param, value: &Option<String>) {
+    match value {
+        Some(value) => param.set(key, value),
+        None => param.remove(key),
+    };
+}
+
+/// Returns the URIs enclosed in angle brackets, comments and other
+/// text outside of the brackets is ignored.
+fn parse_uris(value: &str) -> Vec<String> {
+    let mut uris = Vec::new();
+    let mut rest = value;
+    while let Some(start) = rest.find('<') {
+        let after_start = rest.get(start + 1..).unwrap_or_default();
+        let end = match after_start.find('>') {
+            Some(end) => end,
+            None => break,
+        };
+        let uri: String = after_start
+            .get(..end)
+            .unwrap_or_default()
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        if !uri.is_empty() {
+            uris.push(uri);
+        }
+        rest = after_start.get(end + 1..).unwrap_or_default();
+    }
+    uris
+}
+
+fn strip_scheme<'a>(uri: &'a str, scheme: &str) -> Option<&'a str> {
+    let prefix = uri.get(..scheme.len())?;
+    if prefix.eq_ignore_ascii_case(scheme) {
+        uri.get(scheme.len()..)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mailman_headers() {
+        let list = ListHeaders::parse(
+            "\"Delta Chat\" <delta.lists.example.org>",
+            Some("<mailto:delta@lists.example.org>"),
+            Some(
+                "<https://lists.example.org/mailman/options/delta>,\r\n \
+                 <mailto:delta-request@lists.example.org?subject=unsubscribe>",
+            ),
+            None,
+        )
+        .unwrap();
+        assert_eq!(list.id, "delta.lists.example.org");
+        assert_eq!(list.name, "Delta Chat");
+        assert_eq!(list.post, Some("delta@lists.example.org".to_string()));
+        assert_eq!(
+            list.unsubscribe_mailto,
+            Some("mailto:delta-request@lists.example.org?subject=unsubscribe".to_string())
+        );
+        assert_eq!(
+            list.unsubscribe_url,
+            Some("https://lists.example.org/mailman/options/delta".to_string())
+        );
+        assert!(!list.unsubscribe_one_click);
+
+        let mut param = Params::new();
+        list.to_params(&mut param);
+        assert_eq!(ListHeaders::from_params(&param), Some(list));
+    }
+
+    #[test]
+    fn test_parse_list_headers_minimal() {
+        let list = ListHeaders::parse("<announce.example.org>", Some("NO"), None, None).unwrap();
+        assert_eq!(list.id, "announce.example.org");
+        assert_eq!(list.name, "announce.example.org");
+        assert_eq!(list.post, None);
+        assert_eq!(list.unsubscribe_mailto, None);
+        assert_eq!(list.unsubscribe_url, None);
+
+        let list =
+            ListHeaders::parse("Announcements <announce.example.org>", None, None, None).unwrap();
+        assert_eq!(list.name, "Announcements");
+
+        assert_eq!(ListHeaders::parse("", None, None, None), None);
+        assert_eq!(
+            ListHeaders::parse("no brackets here", None, None, None),
+            None
+        );
+        assert_eq!(ListHeaders::parse("Name <>", None, None, None), None);
+    }
+
+    #[test]
+    fn test_parse_one_click() {
+        let list = ListHeaders::parse(
+            "<news.example.org>",
+            None,
+            Some("<mailto:leave@example.org>, <https://example.org/unsub?id=123>"),
+            Some("List-Unsubscribe=One-Click"),
+        )
+        .unwrap();
+        assert!(list.unsubscribe_one_click);
+
+        let mut param = Params::new();
+        list.to_params(&mut param);
+        assert_eq!(ListHeaders::from_params(&param), Some(list));
+
+        // One-Click requires HTTPS
+        let list = ListHeaders::parse(
+            "<news.example.org>",
+            None,
+            Some("<http://example.org/unsub?id=123>"),
+            Some("List-Unsubscribe=One-Click"),
+        )
+        .unwrap();
+        assert!(!list.unsubscribe_one_click);
+    }
+
+    #[test]
+    fn test_parse_mailto() {
+        assert_eq!(
+            Mailto::parse("mailto:delta-request@lists.example.org?subject=unsubscribe"),
+            Some(Mailto {
+                addr: "delta-request@lists.example.org".to_string(),
+                subject: Some("unsubscribe".to_string()),
+                body: None,
+            })
+        );
+        assert_eq!(
+            Mailto::parse("MAILTO:leave%2Bnews@example.org?Body=leave%20me&cc=x@example.org"),
+            Some(Mailto {
+                addr: "leave+news@example.org".to_string(),
+                subject: None,
+                body: Some("leave me".to_string()),
+            })
+        );
+        assert_eq!(Mailto::parse("mailto:?subject=unsubscribe"), None);
+        assert_eq!(Mailto::parse("https://example.org"), None);
+    }
+}
@@ -1,10 +1,13 @@
 //! # Messages and their identifiers
 
+use std::collections::BTreeMap;
+
 use async_std::path::{Path, PathBuf};
 use deltachat_derive::{FromSql, ToSql};
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 
+use crate::calendar::CalendarEvent;
 use crate::chat::{self, Chat, ChatId};
 use crate::config::Config;
 use crate::constants::*;
@@ -15,10 +18,11 @@ use crate::error::{ensure, Error};
 use crate::events::EventType;
 use crate::job::{self, Action};
 use crate::lot::{Lot, LotState, Meaning};
-use crate::mimeparser::{FailureReport, SystemMessage};
+use crate::mimeparser::{parse_message_ids, FailureReport, SystemMessage};
 use crate::param::*;
 use crate::pgp::*;
 use crate::stock::StockMessage;
+use crate::sync::{self, SyncData};
 
 lazy_static! {
     static ref UNWRAP_RE: regex::Regex = regex::Regex::new(r"\s+").unwrap();
@@ -81,7 +85,9 @@ impl MsgId {
 
     /// Returns true if the message needs to be moved from `folder`.
     pub async fn needs_move(self, context: &Context, folder: &str) -> Result<bool, Error> {
-        if !context.get_config_bool(Config::MvboxMove).await {
+        if !context.get_config_bool(Config::MvboxMove).await
+            || context.get_config_bool(Config::OnlyFetchInbox).await
+        {
             return Ok(false);
         }
 
@@ -120,7 +126,7 @@ impl MsgId {
         Ok(())
     }
 
-    /// Deletes a message and corresponding MDNs from the database.
+    /// Deletes a message and corresponding MDNs and seen markers from the database.
     pub async fn delete_from_db(self, context: &Context) -> crate::sql::Result<()> {
         // We don't use transactions yet, so remove MDNs first to make
         // sure they are not left while the message is deleted.
@@ -128,6 +134,10 @@ impl MsgId {
             .sql
             .execute("DELETE FROM msgs_mdns WHERE msg_id=?;", paramsv![self])
             .await?;
+        context
+            .sql
+            .execute("DELETE FROM msgs_seen WHERE msg_id=?;", paramsv![self])
+            .await?;
         context
             .sql
             .execute("DELETE FROM msgs WHERE id=?;", paramsv![self])
@@ -263,6 +273,9 @@ pub struct Message {
     pub(crate) text: Option<String>,
     pub(crate) rfc724_mid: String,
     pub(crate) in_reply_to: Option<String>,
+    pub(crate) quoted_text: Option<String>,
+    pub(crate) signature: Option<String>,
+    pub(crate) footer: Option<String>,
     pub(crate) server_folder: Option<String>,
     pub(crate) server_uid: u32,
     pub(crate) is_dc_message: MessengerMessage,
@@ -294,6 +307,9 @@ impl Message {
                     "    m.id AS id,",
                     "    rfc724_mid AS rfc724mid,",
                     "    m.mime_in_reply_to AS mime_in_reply_to,",
+                    "    m.quoted_text AS quoted_text,",
+                    "    m.signature AS signature,",
+                    "    m.footer AS footer,",
                     "    m.server_folder AS server_folder,",
                     "    m.server_uid AS server_uid,",
                     "    m.chat_id AS chat_id,",
@@ -324,6 +340,9 @@ impl Message {
                     msg.id = row.get("id")?;
                     msg.rfc724_mid = row.get::<_, String>("rfc724mid")?;
                     msg.in_reply_to = row.get::<_, Option<String>>("mime_in_reply_to")?;
+                    msg.quoted_text = row.get::<_, Option<String>>("quoted_text")?;
+                    msg.signature = row.get::<_, Option<String>>("signature")?;
+                    msg.footer = row.get::<_, Option<String>>("footer")?;
                     msg.server_folder = row.get::<_, Option<String>>("server_folder")?;
                     msg.server_uid = row.get("server_uid")?;
                     msg.chat_id = row.get("chat_id")?;
@@ -389,6 +408,17 @@ impl Message {
         None
     }
 
+    /// Returns the mimetype declared by the sender if it differs from
+    /// [Message::get_filemime].
+    ///
+    /// This is the case for attachments sent as
+    /// `application/octet-stream` that were recognized by their content.
+    pub fn get_original_filemime(&self) -> Option<String> {
+        self.param
+            .get(Param::OriginalMimeType)
+            .map(|m| m.to_string())
+    }
+
     pub fn get_file(&self, context: &Context) -> Option<PathBuf> {
         self.param.get_path(Param::File, context).unwrap_or(None)
     }
@@ -497,7 +527,16 @@ impl Message {
             .map(|text| dc_truncate(text, 30000).to_string())
     }
 
+    /// Returns the language of the text as given in the `Content-Language` header,
+    /// eg. `de` or `en-US`.
+    pub fn get_language(&self) -> Option<String> {
+        self.param.get(Param::Language).map(|lang| lang.to_string())
+    }
+
     pub fn get_filename(&self) -> Option<String> {
+        if let Some(filename) = self.param.get(Param::Filename) {
+            return Some(filename.to_string());
+        }
         self.param
             .get(Param::File)
             .and_then(|file| Path::new(file).file_name())
@@ -750,6 +789,342 @@ impl Message {
         self.update_param(context).await;
     }
 
+    /// Stars or unstars the message.
+    ///
+    /// Starred messages are listed by [Context::get_starred_messages]
+    /// and are not deleted by the `delete_device_after` and
+    /// `delete_server_after` settings.
+    pub async fn set_starred(&mut self, context: &Context, starred: bool) -> Result<(), Error> {
+        context
+            .sql
+            .execute(
+                "UPDATE msgs SET starred=? WHERE id=?;",
+                paramsv![starred as i32, self.id],
+            )
+            .await?;
+        self.starred = starred;
+        context.emit_event(EventType::MsgsChanged {
+            chat_id: self.chat_id,
+            msg_id: self.id,
+        });
+        Ok(())
+    }
+
+    /// Returns the read receipts (MDNs) received for this message.
+    ///
+    /// Each entry is the ID of the contact who read the message and the
+    /// time the receipt was sent, ordered by that time.  For one-to-one
+    /// chats there is at most one entry, for groups there is one entry per
+    /// member who has sent a read receipt.
+    pub async fn get_read_receipts(&self, context: &Context) -> Result<Vec<(u32, i64)>, Error> {
+        let receipts = context
+            .sql
+            .query_map(
+                "SELECT contact_id, timestamp_sent FROM msgs_mdns WHERE msg_id=? \
+                 ORDER BY timestamp_sent, contact_id;",
+                paramsv![self.id],
+                |row| Ok((row.get::<_, u32>(0)?, row.get::<_, i64>(1)?)),
+                |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+            )
+            .await?;
+        Ok(receipts)
+    }
+
+    /// Returns the group members who have seen this message.
+    ///
+    /// Unlike [Message::get_read_receipts], this also works for messages of
+    /// other members, but only if they have enabled `SeenMarkersEnabled`.
+    /// Each entry is the ID of the contact and the time the seen marker
+    /// was sent, ordered by that time.
+    pub async fn get_seen_by(&self, context: &Context) -> Result<Vec<(u32, i64)>, Error> {
+        let seen_by = context
+            .sql
+            .query_map(
+                "SELECT contact_id, timestamp_sent FROM msgs_seen WHERE msg_id=? \
+                 ORDER BY timestamp_sent, contact_id;",
+                paramsv![self.id],
+                |row| Ok((row.get::<_, u32>(0)?, row.get::<_, i64>(1)?)),
+                |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+            )
+            .await?;
+        Ok(seen_by)
+    }
+
+    /// Tries to stop sending the message.
+    ///
+    /// This only works while the message waits in the queue, once it is
+    /// handed to the SMTP server, false is returned.  If sending was
+    /// cancelled, the message is marked as failed and true is returned.
+    pub async fn try_cancel_send(&self, context: &Context) -> bool {
+        job::cancel_send_msg(context, self.id).await
+    }
+
+    /// Returns a diagnostic text about the message.
+    ///
+    /// The text contains sender, times, encryption state, the
+    /// fingerprints of the signing keys, the Message-ID and the
+    /// location on the server, one item per line.  It is meant to be
+    /// copied into bug reports.
+    pub async fn get_info(&self, context: &Context) -> String {
+        let mut ret = String::new();
+
+        let rawtxt: Option<String> = context
+            .sql
+            .query_get_value(
+                context,
+                "SELECT txt_raw FROM msgs WHERE id=?;",
+                paramsv![self.id],
+            )
+            .await;
+
+        if rawtxt.is_none() {
+            ret += &format!("Cannot load message {}.", self.id);
+            return ret;
+        }
+        let rawtxt = rawtxt.unwrap_or_default();
+        let rawtxt = dc_truncate(rawtxt.trim(), 100_000);
+
+        let fts = context.format_timestamp(self.get_timestamp());
+        ret += &format!("Sent: {}", fts);
+
+        let name = Contact::load_from_db(context, self.from_id)
+            .await
+            .map(|contact| contact.get_name_n_addr())
+            .unwrap_or_default();
+
+        ret += &format!(" by {}", name);
+        ret += "\n";
+
+        if self.from_id != DC_CONTACT_ID_SELF as u32 {
+            let s = context.format_timestamp(if 0 != self.timestamp_rcvd {
+                self.timestamp_rcvd
+            } else {
+                self.timestamp_sort
+            });
+            ret += &format!("Received: {}", &s);
+            ret += "\n";
+        }
+
+        if self.ephemeral_timer != 0 {
+            ret += &format!("Ephemeral timer: {}\n", self.ephemeral_timer);
+        }
+
+        if self.ephemeral_timestamp != 0 {
+            ret += &format!(
+                "Expires: {}\n",
+                context.format_timestamp(self.ephemeral_timestamp)
+            );
+        }
+
+        if self.from_id == DC_CONTACT_ID_INFO || self.to_id == DC_CONTACT_ID_INFO {
+            // device-internal message, no further details needed
+            return ret;
+        }
+
+        if let Ok(rows) = context
+            .sql
+            .query_map(
+                "SELECT contact_id, timestamp_sent FROM msgs_mdns WHERE msg_id=?;",
+                paramsv![self.id],
+                |row| {
+                    let contact_id: i32 = row.get(0)?;
+                    let ts: i64 = row.get(1)?;
+                    Ok((contact_id, ts))
+                },
+                |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+            )
+            .await
+        {
+            for (contact_id, ts) in rows {
+                let fts = context.format_timestamp(ts);
+                ret += &format!("Read: {}", fts);
+
+                let name = Contact::load_from_db(context, contact_id as u32)
+                    .await
+                    .map(|contact| contact.get_name_n_addr())
+                    .unwrap_or_default();
+
+                ret += &format!(" by {}", name);
+                ret += "\n";
+            }
+        }
+
+        ret += &format!("State: {}", self.state);
+
+        if self.has_location() {
+            ret += ", Location sent";
+        }
+
+        let e2ee_errors = self.param.get_int(Param::ErroneousE2ee).unwrap_or_default();
+
+        if 0 != e2ee_errors {
+            if 0 != e2ee_errors & 0x2 {
+                ret += ", Encrypted, no valid signature";
+            }
+        } else if 0 != self.param.get_int(Param::GuaranteeE2ee).unwrap_or_default() {
+            ret += ", Encrypted";
+        }
+
+        ret += "\n";
+
+        if let Some(fingerprints) = self.param.get(Param::SignatureFingerprints) {
+            if !fingerprints.is_empty() {
+                ret += &format!("Signed by: {}\n", fingerprints);
+            }
+        }
+
+        if !self.error.is_empty() {
+            ret += &format!("Error: {}", self.error);
+        }
+
+        if let Some(path) = self.get_file(context) {
+            let bytes = dc_get_filebytes(context, &path).await;
+            ret += &format!("\nFile: {}, {}, bytes\n", path.display(), bytes);
+        }
+
+        if self.viewtype != Viewtype::Text {
+            ret += "Type: ";
+            ret += &format!("{}", self.viewtype);
+            ret += "\n";
+            ret += &format!("Mimetype: {}\n", &self.get_filemime().unwrap_or_default());
+        }
+        let w = self.param.get_int(Param::Width).unwrap_or_default();
+        let h = self.param.get_int(Param::Height).unwrap_or_default();
+        if w != 0 || h != 0 {
+            ret += &format!("Dimension: {} x {}\n", w, h,);
+        }
+        let duration = self.param.get_int(Param::Duration).unwrap_or_default();
+        if duration != 0 {
+            ret += &format!("Duration: {} ms\n", duration,);
+        }
+        if !rawtxt.is_empty() {
+            ret += &format!("\n{}\n", rawtxt);
+        }
+        if !self.rfc724_mid.is_empty() {
+            ret += &format!("\nMessage-ID: {}", self.rfc724_mid);
+        }
+        if let Some(ref server_folder) = self.server_folder {
+            if server_folder != "" {
+                ret += &format!("\nLast seen as: {}/{}", server_folder, self.server_uid);
+            }
+        }
+
+        ret
+    }
+
+    /// Returns the text quoted in the message, if any.
+    ///
+    /// The quote is removed from [Message::get_text] and returned
+    /// without the leading `>` marks.  Quotes in chat messages
+    /// are left in the text.
+    pub fn quoted_text(&self) -> Option<String> {
+        self.quoted_text.clone()
+    }
+
+    /// Returns the signature removed from [Message::get_text], if any.
+    ///
+    /// This is the text after the standard `-- ` separator line.
+    pub fn get_signature(&self) -> Option<String> {
+        self.signature.clone()
+    }
+
+    /// Returns the nonstandard footer removed from [Message::get_text], if any.
+    ///
+    /// Footers are only removed from classic emails, the separator line
+    /// is part of the returned text.
+    pub fn get_footer(&self) -> Option<String> {
+        self.footer.clone()
+    }
+
+    /// Returns the ID of the message this message replies to.
+    ///
+    /// The parent is looked up using the `In-Reply-To` header first,
+    /// then the `References` header starting with the most recent
+    /// entry.  Returns `None` if the parent is not known locally.
+    pub async fn quoted_message(&self, context: &Context) -> Option<MsgId> {
+        let references: String = context
+            .sql
+            .query_get_value(
+                context,
+                "SELECT mime_references FROM msgs WHERE id=?;",
+                paramsv![self.id],
+            )
+            .await
+            .unwrap_or_default();
+
+        let in_reply_to = self.in_reply_to.as_deref().unwrap_or_default();
+        for rfc724_mid in parent_candidates(&self.rfc724_mid, in_reply_to, &references) {
+            let parent: Option<MsgId> = context
+                .sql
+                .query_get_value(
+                    context,
+                    "SELECT id FROM msgs WHERE rfc724_mid=? AND chat_id!=? ORDER BY id LIMIT 1;",
+                    paramsv![rfc724_mid, DC_CHAT_ID_TRASH],
+                )
+                .await;
+            if parent.is_some() {
+                return parent;
+            }
+        }
+        None
+    }
+
+    /// Returns the event of a calendar invitation sent with the message.
+    pub fn get_calendar_event(&self) -> Option<CalendarEvent> {
+        self.param
+            .get(Param::CalendarEvent)
+            .and_then(|event| serde_json::from_str(event).ok())
+    }
+
+    /// Returns true if the HTML part of the message references remote images.
+    ///
+    /// These images are not loaded by default, the UI may offer to show
+    /// them using [Message::get_html_with_remote].
+    pub fn has_remote_images(&self) -> bool {
+        self.param.get_bool(Param::RemoteImages).unwrap_or_default()
+    }
+
+    /// Returns the sanitized HTML part of a received message.
+    ///
+    /// Scripts and event handlers are already removed when the message
+    /// is received.  Unless [Config::AutoShowRemoteImages] is set, remote
+    /// images are removed as well.  Returns `None` if the message had no
+    /// HTML part, the plaintext from [Message::get_text] is always
+    /// available.
+    pub async fn get_html(&self, context: &Context) -> Option<String> {
+        let html = self.load_html(context).await?;
+        if context.get_config_bool(Config::AutoShowRemoteImages).await {
+            Some(html)
+        } else {
+            Some(crate::html::sanitize_html(context, &html, false))
+        }
+    }
+
+    /// Returns the sanitized HTML part of a received message including
+    /// remote images.
+    ///
+    /// To be used when the user explicitly wants to load the remote
+    /// images of this message.
+    pub async fn get_html_with_remote(&self, context: &Context) -> Option<String> {
+        self.load_html(context).await
+    }
+
+    async fn load_html(&self, context: &Context) -> Option<String> {
+        let html: String = context
+            .sql
+            .query_get_value(
+                context,
+                "SELECT html FROM msgs WHERE id=?;",
+                paramsv![self.id],
+            )
+            .await?;
+        if html.is_empty() {
+            None
+        } else {
+            Some(html)
+        }
+    }
+
     pub async fn update_param(&mut self, context: &Context) -> bool {
         context
             .sql
@@ -945,152 +1320,12 @@ impl Lot {
     }
 }
 
+/// Returns a diagnostic text about the message, see [Message::get_info].
 pub async fn get_msg_info(context: &Context, msg_id: MsgId) -> String {
-    let mut ret = String::new();
-
-    let msg = Message::load_from_db(context, msg_id).await;
-    if msg.is_err() {
-        return ret;
-    }
-
-    let msg = msg.unwrap_or_default();
-
-    let rawtxt: Option<String> = context
-        .sql
-        .query_get_value(
-            context,
-            "SELECT txt_raw FROM msgs WHERE id=?;",
-            paramsv![msg_id],
-        )
-        .await;
-
-    if rawtxt.is_none() {
-        ret += &format!("Cannot load message {}.", msg_id);
-        return ret;
-    }
-    let rawtxt = rawtxt.unwrap_or_default();
-    let rawtxt = dc_truncate(rawtxt.trim(), 100_000);
-
-    let fts = dc_timestamp_to_str(msg.get_timestamp());
-    ret += &format!("Sent: {}", fts);
-
-    let name = Contact::load_from_db(context, msg.from_id)
-        .await
-        .map(|contact| contact.get_name_n_addr())
-        .unwrap_or_default();
-
-    ret += &format!(" by {}", name);
-    ret += "\n";
-
-    if msg.from_id != DC_CONTACT_ID_SELF as u32 {
-        let s = dc_timestamp_to_str(if 0 != msg.timestamp_rcvd {
-            msg.timestamp_rcvd
-        } else {
-            msg.timestamp_sort
-        });
-        ret += &format!("Received: {}", &s);
-        ret += "\n";
-    }
-
-    if msg.ephemeral_timer != 0 {
-        ret += &format!("Ephemeral timer: {}\n", msg.ephemeral_timer);
-    }
-
-    if msg.ephemeral_timestamp != 0 {
-        ret += &format!(
-            "Expires: {}\n",
-            dc_timestamp_to_str(msg.ephemeral_timestamp)
-        );
-    }
-
-    if msg.from_id == DC_CONTACT_ID_INFO || msg.to_id == DC_CONTACT_ID_INFO {
-        // device-internal message, no further details needed
-        return ret;
-    }
-
-    if let Ok(rows) = context
-        .sql
-        .query_map(
-            "SELECT contact_id, timestamp_sent FROM msgs_mdns WHERE msg_id=?;",
-            paramsv![msg_id],
-            |row| {
-                let contact_id: i32 = row.get(0)?;
-                let ts: i64 = row.get(1)?;
-                Ok((contact_id, ts))
-            },
-            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
-        )
-        .await
-    {
-        for (contact_id, ts) in rows {
-            let fts = dc_timestamp_to_str(ts);
-            ret += &format!("Read: {}", fts);
-
-            let name = Contact::load_from_db(context, contact_id as u32)
-                .await
-                .map(|contact| contact.get_name_n_addr())
-                .unwrap_or_default();
-
-            ret += &format!(" by {}", name);
-            ret += "\n";
-        }
-    }
-
-    ret += &format!("State: {}", msg.state);
-
-    if msg.has_location() {
-        ret += ", Location sent";
-    }
-
-    let e2ee_errors = msg.param.get_int(Param::ErroneousE2ee).unwrap_or_default();
-
-    if 0 != e2ee_errors {
-        if 0 != e2ee_errors & 0x2 {
-            ret += ", Encrypted, no valid signature";
-        }
-    } else if 0 != msg.param.get_int(Param::GuaranteeE2ee).unwrap_or_default() {
-        ret += ", Encrypted";
-    }
-
-    ret += "\n";
-
-    if !msg.error.is_empty() {
-        ret += &format!("Error: {}", msg.error);
+    match Message::load_from_db(context, msg_id).await {
+        Ok(msg) => msg.get_info(context).await,
+        Err(_) => String::new(),
     }
-
-    if let Some(path) = msg.get_file(context) {
-        let bytes = dc_get_filebytes(context, &path).await;
-        ret += &format!("\nFile: {}, {}, bytes\n", path.display(), bytes);
-    }
-
-    if msg.viewtype != Viewtype::Text {
-        ret += "Type: ";
-        ret += &format!("{}", msg.viewtype);
-        ret += "\n";
-        ret += &format!("Mimetype: {}\n", &msg.get_filemime().unwrap_or_default());
-    }
-    let w = msg.param.get_int(Param::Width).unwrap_or_default();
-    let h = msg.param.get_int(Param::Height).unwrap_or_default();
-    if w != 0 || h != 0 {
-        ret += &format!("Dimension: {} x {}\n", w, h,);
-    }
-    let duration = msg.param.get_int(Param::Duration).unwrap_or_default();
-    if duration != 0 {
-        ret += &format!("Duration: {} ms\n", duration,);
-    }
-    if !rawtxt.is_empty() {
-        ret += &format!("\n{}\n", rawtxt);
-    }
-    if !msg.rfc724_mid.is_empty() {
-        ret += &format!("\nMessage-ID: {}", msg.rfc724_mid);
-    }
-    if let Some(ref server_folder) = msg.server_folder {
-        if server_folder != "" {
-            ret += &format!("\nLast seen as: {}/{}", server_folder, msg.server_uid);
-        }
-    }
-
-    ret
 }
 
 pub fn guess_msgtype_from_suffix(path: &Path) -> Option<(Viewtype, &str)> {
@@ -1158,6 +1393,7 @@ pub fn guess_msgtype_from_suffix(path: &Path) -> Option<(Viewtype, &str)> {
             Viewtype::File,
             "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
         ),
+        "xdc" => (Viewtype::File, "application/webxdc+zip"),
         "xml" => (Viewtype::File, "application/vnd.ms-excel"),
         "zip" => (Viewtype::File, "application/zip"),
         _ => {
@@ -1179,20 +1415,41 @@ pub async fn get_mime_headers(context: &Context, msg_id: MsgId) -> Option<String
 }
 
 pub async fn delete_msgs(context: &Context, msg_ids: &[MsgId]) {
+    let rfc724_mids = delete_msgs_ex(context, msg_ids, true).await;
+    if let Err(err) = sync::send_sync_msg(context, SyncData::DeleteMsgs { rfc724_mids }).await {
+        warn!(context, "Cannot sync deleted messages: {}", err);
+    }
+}
+
+/// Deletes messages without informing other devices.
+///
+/// If `delete_on_imap` is false, the messages are only deleted locally.
+/// Returns the Message-IDs of the deleted messages.
+pub(crate) async fn delete_msgs_ex(
+    context: &Context,
+    msg_ids: &[MsgId],
+    delete_on_imap: bool,
+) -> Vec<String> {
+    let mut rfc724_mids = Vec::with_capacity(msg_ids.len());
     for msg_id in msg_ids.iter() {
         if let Ok(msg) = Message::load_from_db(context, *msg_id).await {
             if msg.location_id > 0 {
                 delete_poi_location(context, msg.location_id).await;
             }
+            if !msg.rfc724_mid.is_empty() {
+                rfc724_mids.push(msg.rfc724_mid);
+            }
         }
         if let Err(err) = msg_id.trash(context).await {
             error!(context, "Unable to trash message {}: {}", msg_id, err);
         }
-        job::add(
-            context,
-            job::Job::new(Action::DeleteMsgOnImap, msg_id.to_u32(), Params::new(), 0),
-        )
-        .await;
+        if delete_on_imap {
+            job::add(
+                context,
+                job::Job::new(Action::DeleteMsgOnImap, msg_id.to_u32(), Params::new(), 0),
+            )
+            .await;
+        }
     }
 
     if !msg_ids.is_empty() {
@@ -1207,6 +1464,8 @@ pub async fn delete_msgs(context: &Context, msg_ids: &[MsgId]) {
         )
         .await;
     }
+
+    rfc724_mids
 }
 
 async fn delete_poi_location(context: &Context, location_id: u32) -> bool {
@@ -1231,6 +1490,9 @@ pub async fn markseen_msgs(context: &Context, msg_ids: Vec<MsgId>) -> bool {
             let mut stmt = conn.prepare_cached(concat!(
                 "SELECT",
                 "    m.state AS state,",
+                "    m.rfc724_mid AS rfc724_mid,",
+                "    m.chat_id AS chat_id,",
+                "    c.type AS type,",
                 "    c.blocked AS blocked",
                 " FROM msgs m LEFT JOIN chats c ON c.id=m.chat_id",
                 " WHERE m.id=? AND m.chat_id>9"
@@ -1241,6 +1503,9 @@ pub async fn markseen_msgs(context: &Context, msg_ids: Vec<MsgId>) -> bool {
                 let query_res = stmt.query_row(paramsv![id], |row| {
                     Ok((
                         row.get::<_, MessageState>("state")?,
+                        row.get::<_, String>("rfc724_mid")?,
+                        row.get::<_, ChatId>("chat_id")?,
+                        row.get::<_, Option<Chattype>>("type")?.unwrap_or_default(),
                         row.get::<_, Option<Blocked>>("blocked")?
                             .unwrap_or_default(),
                     ))
@@ -1248,8 +1513,9 @@ pub async fn markseen_msgs(context: &Context, msg_ids: Vec<MsgId>) -> bool {
                 if let Err(rusqlite::Error::QueryReturnedNoRows) = query_res {
                     continue;
                 }
-                let (state, blocked) = query_res.map_err(Into::<anyhow::Error>::into)?;
-                msgs.push((id, state, blocked));
+                let (state, rfc724_mid, chat_id, chat_type, blocked) =
+                    query_res.map_err(Into::<anyhow::Error>::into)?;
+                msgs.push((id, state, rfc724_mid, chat_id, chat_type, blocked));
             }
 
             Ok(msgs)
@@ -1258,8 +1524,10 @@ pub async fn markseen_msgs(context: &Context, msg_ids: Vec<MsgId>) -> bool {
         .unwrap_or_default();
 
     let mut send_event = false;
+    let mut seen_rfc724_mids = Vec::new();
+    let mut seen_group_msgs: BTreeMap<ChatId, Vec<String>> = BTreeMap::new();
 
-    for (id, curr_state, curr_blocked) in msgs.into_iter() {
+    for (id, curr_state, rfc724_mid, chat_id, chat_type, curr_blocked) in msgs.into_iter() {
         if let Err(err) = id.start_ephemeral_timer(context).await {
             error!(
                 context,
@@ -1279,6 +1547,13 @@ pub async fn markseen_msgs(context: &Context, msg_ids: Vec<MsgId>) -> bool {
                 )
                 .await;
                 send_event = true;
+                if chat_type == Chattype::Group || chat_type == Chattype::VerifiedGroup {
+                    seen_group_msgs
+                        .entry(chat_id)
+                        .or_default()
+                        .push(rfc724_mid.clone());
+                }
+                seen_rfc724_mids.push(rfc724_mid);
             }
         } else if curr_state == MessageState::InFresh {
             update_msg_state(context, id, MessageState::InNoticed).await;
@@ -1293,9 +1568,84 @@ pub async fn markseen_msgs(context: &Context, msg_ids: Vec<MsgId>) -> bool {
         });
     }
 
+    for (chat_id, rfc724_mids) in seen_group_msgs {
+        if let Err(err) = send_seen_marker(context, chat_id, &rfc724_mids).await {
+            warn!(context, "Cannot send seen marker to {}: {}", chat_id, err);
+        }
+    }
+
+    let data = SyncData::MarkseenMsgs {
+        rfc724_mids: seen_rfc724_mids,
+    };
+    if let Err(err) = sync::send_sync_msg(context, data).await {
+        warn!(context, "Cannot sync seen messages: {}", err);
+    }
+
     true
 }
 
+/// Tells the members of a group which messages were seen.
+///
+/// Seen markers are sent as hidden messages and only if enabled by
+/// `SeenMarkersEnabled` as well as `MdnsEnabled` or the override of the chat.
+async fn send_seen_marker(
+    context: &Context,
+    chat_id: ChatId,
+    rfc724_mids: &[String],
+) -> Result<(), Error> {
+    if !context.get_config_bool(Config::SeenMarkersEnabled).await
+        || !chat::mdns_enabled(context, chat_id).await
+    {
+        return Ok(());
+    }
+    let mut msg = Message::new(Viewtype::Text);
+    msg.hidden = true;
+    msg.param.set_cmd(SystemMessage::SeenMarker);
+    msg.param.set(
+        Param::Arg,
+        rfc724_mids
+            .iter()
+            .map(|rfc724_mid| format!("<{}>", rfc724_mid))
+            .collect::<Vec<_>>()
+            .join(" "),
+    );
+    chat::send_msg(context, chat_id, &mut msg).await?;
+    Ok(())
+}
+
+/// Records that a group member has seen the messages listed in a
+/// `Chat-Seen` header.
+///
+/// Messages of chats the contact is not a member of are ignored.
+pub(crate) async fn handle_seen_marker(
+    context: &Context,
+    from_id: u32,
+    rfc724_mids: &[String],
+    timestamp_sent: i64,
+) {
+    for rfc724_mid in rfc724_mids {
+        let (chat_id, msg_id) = match rfc724_mid_exists(context, rfc724_mid).await {
+            Ok(Some((_, chat_id, msg_id))) => (ChatId::new(chat_id), msg_id),
+            _ => continue,
+        };
+        if chat_id.is_special() || !chat::is_contact_in_chat(context, chat_id, from_id).await {
+            continue;
+        }
+        match context
+            .sql
+            .execute(
+                "INSERT OR IGNORE INTO msgs_seen (msg_id, contact_id, timestamp_sent) VALUES (?, ?, ?);",
+                paramsv![msg_id, from_id, timestamp_sent],
+            )
+            .await
+        {
+            Ok(1) => context.emit_event(EventType::MsgsChanged { chat_id, msg_id }),
+            Ok(_) => {}
+            Err(err) => warn!(context, "Cannot record seen marker: {}", err),
+        }
+    }
+}
+
 pub async fn update_msg_state(context: &Context, msg_id: MsgId, state: MessageState) -> bool {
     context
         .sql
@@ -1311,7 +1661,7 @@ pub async fn star_msgs(context: &Context, msg_ids: Vec<MsgId>, star: bool) -> bo
     if msg_ids.is_empty() {
         return false;
     }
-    context
+    let res = context
         .sql
         .with_conn(move |conn| {
             let mut stmt = conn.prepare("UPDATE msgs SET starred=? WHERE id=?;")?;
@@ -1320,8 +1670,45 @@ pub async fn star_msgs(context: &Context, msg_ids: Vec<MsgId>, star: bool) -> bo
             }
             Ok(())
         })
-        .await
-        .is_ok()
+        .await;
+    if res.is_err() {
+        return false;
+    }
+    context.emit_event(EventType::MsgsChanged {
+        chat_id: ChatId::new(0),
+        msg_id: MsgId::new(0),
+    });
+    true
+}
+
+/// Moves messages out of the spam chat, see [ChatId::is_spam].
+///
+/// The senders are marked as known, so their future messages are not
+/// routed to the spam chat anymore. The messages are moved to the
+/// one-to-one chat with the sender which is created as a contact
+/// request if it does not exist yet.
+pub async fn mark_not_spam(context: &Context, msg_ids: &[MsgId]) -> Result<(), Error> {
+    for msg_id in msg_ids {
+        let msg = Message::load_from_db(context, *msg_id).await?;
+        if !msg.chat_id.is_spam() {
+            continue;
+        }
+        Contact::scaleup_origin_by_id(context, msg.from_id, Origin::IncomingReplyTo).await;
+        let (chat_id, _) =
+            chat::create_or_lookup_by_contact_id(context, msg.from_id, Blocked::Deaddrop).await?;
+        context
+            .sql
+            .execute(
+                "UPDATE msgs SET chat_id=? WHERE id=?;",
+                paramsv![chat_id, msg_id],
+            )
+            .await?;
+        context.emit_event(EventType::MsgsChanged {
+            chat_id,
+            msg_id: *msg_id,
+        });
+    }
+    Ok(())
 }
 
 /// Returns a summary text.
@@ -1342,7 +1729,7 @@ pub async fn get_summarytext_by_raw(
             .stock_str(StockMessage::VoiceMessage)
             .await
             .into_owned(),
-        Viewtype::Audio | Viewtype::File => {
+        Viewtype::Audio | Viewtype::File | Viewtype::Webxdc => {
             if param.get_cmd() == SystemMessage::AutocryptSetupMessage {
                 append_text = false;
                 context
@@ -1405,6 +1792,57 @@ pub async fn get_summarytext_by_raw(
     UNWRAP_RE.replace_all(&summary, " ").to_string()
 }
 
+/// Returns a one-line preview of a message as shown in the chatlist.
+///
+/// Attachments are prefixed by an icon, eg. "📷 Image" or "📎 file.pdf".
+pub(crate) async fn get_preview_text(context: &Context, msg: &Message) -> String {
+    let icon = match msg.viewtype {
+        Viewtype::Image => "📷",
+        Viewtype::Gif => "🎞",
+        Viewtype::Sticker => "🖼",
+        Viewtype::Video => "🎥",
+        Viewtype::Voice => "🎤",
+        Viewtype::Audio => "🎵",
+        Viewtype::File | Viewtype::Webxdc => "📎",
+        _ => "",
+    };
+    if icon.is_empty() || msg.param.get_cmd() == SystemMessage::AutocryptSetupMessage {
+        return get_summarytext_by_raw(
+            msg.viewtype,
+            msg.text.as_ref(),
+            &msg.param,
+            SUMMARY_CHARACTERS,
+            context,
+        )
+        .await;
+    }
+
+    let label = match msg.viewtype {
+        Viewtype::Audio | Viewtype::File | Viewtype::Webxdc => msg.get_filename(),
+        _ => None,
+    };
+    let label = match label {
+        Some(label) => label,
+        None => {
+            get_summarytext_by_raw(
+                msg.viewtype,
+                None::<&str>,
+                &msg.param,
+                SUMMARY_CHARACTERS,
+                context,
+            )
+            .await
+        }
+    };
+    let preview = match msg.text.as_ref().filter(|text| !text.is_empty()) {
+        Some(text) => format!("{} {} – {}", icon, label, text),
+        None => format!("{} {}", icon, label),
+    };
+    UNWRAP_RE
+        .replace_all(&dc_truncate(&preview, SUMMARY_CHARACTERS), " ")
+        .to_string()
+}
+
 // as we do not cut inside words, this results in about 32-42 characters.
 // Do not use too long subjects - we add a tag after the subject which gets truncated by the clients otherwise.
 // It should also be very clear, the subject is _not_ the whole message.
@@ -1454,7 +1892,7 @@ pub async fn set_msg_failed(context: &Context, msg_id: MsgId, error: Option<impl
             )
             .await
         {
-            Ok(_) => context.emit_event(EventType::MsgFailed {
+            Ok(_) => context.emit_msg_state_event(EventType::MsgFailed {
                 chat_id: msg.chat_id,
                 msg_id,
             }),
@@ -1507,10 +1945,13 @@ pub async fn handle_mdn(
     if let Ok((msg_id, chat_id, chat_type, msg_state)) = res {
         let mut read_by_all = false;
 
-        if msg_state == MessageState::OutPreparing
+        let waiting_for_mdn = msg_state == MessageState::OutPreparing
             || msg_state == MessageState::OutPending
-            || msg_state == MessageState::OutDelivered
-        {
+            || msg_state == MessageState::OutDelivered;
+
+        // MDNs are recorded even if the message is already marked as read,
+        // so that every group member's receipt shows up in get_read_receipts().
+        if waiting_for_mdn || msg_state == MessageState::OutMdnRcvd {
             let mdn_already_in_table = context
                 .sql
                 .exists(
@@ -1528,7 +1969,9 @@ pub async fn handle_mdn(
                     .await
                            .unwrap_or_default(); // TODO: better error handling
             }
+        }
 
+        if waiting_for_mdn {
             // Normal chat? that's quite easy.
             if chat_type == Chattype::Single {
                 update_msg_state(context, msg_id, MessageState::OutMdnRcvd).await;
@@ -1697,6 +2140,7 @@ pub async fn estimate_deletion_cnt(
              WHERE m.id > ?
                AND timestamp < ?
                AND chat_id != ?
+               AND starred = 0
                AND server_uid != 0;",
                 paramsv![DC_MSG_ID_LAST_SPECIAL, threshold_timestamp, self_chat_id],
                 |row| row.get(0),
@@ -1711,7 +2155,8 @@ pub async fn estimate_deletion_cnt(
              WHERE m.id > ?
                AND timestamp < ?
                AND chat_id != ?
-               AND chat_id != ? AND hidden = 0;",
+               AND chat_id != ? AND hidden = 0
+               AND starred = 0;",
                 paramsv![
                     DC_MSG_ID_LAST_SPECIAL,
                     threshold_timestamp,
@@ -1748,6 +2193,28 @@ pub async fn rfc724_mid_cnt(context: &Context, rfc724_mid: &str) -> i32 {
     }
 }
 
+/// Returns the Message-IDs a message with the given headers may reply to,
+/// the most likely parent first.
+///
+/// `In-Reply-To` is used first, then `References` starting with the most
+/// recent entry, so that a known ancestor is found if the direct parent
+/// was not downloaded.
+pub(crate) fn parent_candidates(
+    rfc724_mid: &str,
+    in_reply_to: &str,
+    references: &str,
+) -> Vec<String> {
+    let mut candidates = parse_message_ids(in_reply_to).unwrap_or_default();
+    candidates.extend(
+        parse_message_ids(references)
+            .unwrap_or_default()
+            .into_iter()
+            .rev(),
+    );
+    candidates.retain(|candidate| candidate != rfc724_mid);
+    candidates
+}
+
 pub(crate) async fn rfc724_mid_exists(
     context: &Context,
     rfc724_mid: &str,
@@ -1991,6 +2458,115 @@ mod tests {
         assert_eq!(instance, "basicwebrtc:https://basic.stuff/12345ab");
     }
 
+    #[async_std::test]
+    async fn test_get_read_receipts() {
+        let t = test::TestContext::new_alice().await;
+        let ctx = &t.ctx;
+
+        let bob = Contact::create(ctx, "bob", "bob@example.net")
+            .await
+            .unwrap();
+        let claire = Contact::create(ctx, "claire", "claire@example.net")
+            .await
+            .unwrap();
+        let chat_id = chat::create_group_chat(ctx, VerifiedStatus::Unverified, "foo")
+            .await
+            .unwrap();
+        chat::add_contact_to_chat(ctx, chat_id, bob).await;
+        chat::add_contact_to_chat(ctx, chat_id, claire).await;
+
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("hello".to_string()));
+        let msg_id = chat::prepare_msg(ctx, chat_id, &mut msg).await.unwrap();
+        let msg = Message::load_from_db(ctx, msg_id).await.unwrap();
+        assert!(msg.get_read_receipts(ctx).await.unwrap().is_empty());
+
+        // first receipt, the message is not yet read by the majority
+        assert!(handle_mdn(ctx, claire, &msg.rfc724_mid, 2000)
+            .await
+            .is_none());
+        assert_eq!(
+            msg.get_read_receipts(ctx).await.unwrap(),
+            vec![(claire, 2000)]
+        );
+
+        // second receipt, duplicates are ignored
+        assert_eq!(
+            handle_mdn(ctx, bob, &msg.rfc724_mid, 1000).await,
+            Some((chat_id, msg_id))
+        );
+        assert!(handle_mdn(ctx, bob, &msg.rfc724_mid, 3000).await.is_none());
+        assert_eq!(
+            msg.get_read_receipts(ctx).await.unwrap(),
+            vec![(bob, 1000), (claire, 2000)]
+        );
+        assert_eq!(
+            msg_id.get_state(ctx).await.unwrap(),
+            MessageState::OutMdnRcvd
+        );
+    }
+
+    #[async_std::test]
+    async fn test_set_starred() {
+        use crate::config::Config;
+        use crate::ephemeral::delete_expired_messages;
+
+        let t = test::TestContext::new_alice().await;
+        let ctx = &t.ctx;
+        let bob = Contact::create(ctx, "bob", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(ctx, bob).await.unwrap();
+        let mut msg_ids = Vec::new();
+        for text in &["first", "second"] {
+            let mut msg = Message::new(Viewtype::Text);
+            msg.set_text(Some(text.to_string()));
+            msg_ids.push(chat::prepare_msg(ctx, chat_id, &mut msg).await.unwrap());
+        }
+        assert!(ctx.get_starred_messages().await.is_empty());
+
+        let emitter = ctx.get_event_emitter();
+        while emitter.try_recv().is_ok() {}
+        let mut msg = Message::load_from_db(ctx, msg_ids[0]).await.unwrap();
+        msg.set_starred(ctx, true).await.unwrap();
+        assert!(msg.is_starred());
+        assert!(Message::load_from_db(ctx, msg_ids[0])
+            .await
+            .unwrap()
+            .is_starred());
+        assert_eq!(ctx.get_starred_messages().await, vec![msg_ids[0]]);
+        let mut msgs_changed = false;
+        while let Ok(event) = emitter.try_recv() {
+            if let EventType::MsgsChanged {
+                chat_id: id,
+                msg_id,
+            } = event.typ
+            {
+                msgs_changed |= id == chat_id && msg_id == msg_ids[0];
+            }
+        }
+        assert!(msgs_changed);
+
+        // starred messages are skipped by auto-deletion
+        ctx.sql
+            .execute("UPDATE msgs SET timestamp=?;", paramsv![time() - 7200])
+            .await
+            .unwrap();
+        assert_eq!(estimate_deletion_cnt(ctx, false, 3600).await.unwrap(), 1);
+        ctx.set_config(Config::DeleteDeviceAfter, Some("3600"))
+            .await
+            .unwrap();
+        assert!(delete_expired_messages(ctx).await.unwrap());
+        let msg = Message::load_from_db(ctx, msg_ids[0]).await.unwrap();
+        assert_eq!(msg.chat_id, chat_id);
+        let msg = Message::load_from_db(ctx, msg_ids[1]).await.unwrap();
+        assert!(msg.chat_id.is_trash());
+
+        let mut msg = Message::load_from_db(ctx, msg_ids[0]).await.unwrap();
+        msg.set_starred(ctx, false).await.unwrap();
+        assert!(ctx.get_starred_messages().await.is_empty());
+    }
+
     #[async_std::test]
     async fn test_get_width_height() {
         let t = test::TestContext::new().await;
@@ -2021,4 +2597,66 @@ mod tests {
         }
         assert!(has_image);
     }
+
+    #[async_std::test]
+    async fn test_get_info_encrypted() {
+        use crate::dc_receive_imf::dc_receive_imf;
+        use crate::key::{self, DcKey};
+        use crate::keyring;
+        use crate::mimefactory::MimeFactory;
+
+        let alice = test::TestContext::new_alice().await;
+        let bob = test::TestContext::new().await;
+        bob.configure_addr("bob@example.net").await;
+        key::store_self_keypair(&bob.ctx, &test::bob_keypair(), key::KeyPairUse::Default)
+            .await
+            .unwrap();
+        keyring::import_public_key(
+            &bob.ctx,
+            "alice@example.com",
+            &test::alice_keypair().public.to_asc(None),
+        )
+        .await
+        .unwrap();
+
+        let contact_id = Contact::create(&bob.ctx, "", "alice@example.com")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&bob.ctx, contact_id)
+            .await
+            .unwrap();
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("hi alice".to_string()));
+        let msg_id = chat::prepare_msg(&bob.ctx, chat_id, &mut msg)
+            .await
+            .unwrap();
+        let msg = Message::load_from_db(&bob.ctx, msg_id).await.unwrap();
+        let rendered = MimeFactory::from_msg(&bob.ctx, &msg, false)
+            .await
+            .unwrap()
+            .render()
+            .await
+            .unwrap();
+        assert!(rendered.is_encrypted);
+
+        dc_receive_imf(&alice.ctx, &rendered.message, "INBOX", 7, false)
+            .await
+            .unwrap();
+        let (_, _, received_id) = rfc724_mid_exists(&alice.ctx, &msg.rfc724_mid)
+            .await
+            .unwrap()
+            .unwrap();
+        let received = Message::load_from_db(&alice.ctx, received_id)
+            .await
+            .unwrap();
+        let info = received.get_info(&alice.ctx).await;
+        assert!(info.contains(&format!("Message-ID: {}", msg.rfc724_mid)));
+        assert!(info.contains(", Encrypted\n"));
+        assert!(info.contains(&format!(
+            "Signed by: {}\n",
+            test::bob_keypair().public.fingerprint().hex()
+        )));
+        assert!(info.contains("Last seen as: INBOX/7"));
+        assert_eq!(get_msg_info(&alice.ctx, received_id).await, info);
+    }
 }
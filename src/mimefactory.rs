@@ -19,6 +19,8 @@ use crate::param::*;
 use crate::peerstate::{Peerstate, PeerstateVerifiedStatus};
 use crate::simplify::escape_message_footer_marks;
 use crate::stock::StockMessage;
+use crate::sync::SYNC_FILENAME;
+use crate::webxdc::STATUS_UPDATE_FILENAME;
 
 // attachments of 25 mb brutto should work on the majority of providers
 // (brutto examples: web.de=50, 1&1=40, t-online.de=32, gmail=25, posteo=50, yahoo=25, all-inkl=100).
@@ -52,6 +54,9 @@ pub struct MimeFactory<'a, 'b> {
     context: &'a Context,
     last_added_location_id: u32,
     attach_selfavatar: bool,
+
+    /// Render unencrypted and without sending-only headers, see [MimeFactory::from_msg_for_export].
+    for_export: bool,
 }
 
 /// Result of rendering a message, ready to be submitted to a send job.
@@ -114,7 +119,7 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
                 )
                 .await?;
 
-            if !msg.is_system_message() && context.get_config_bool(Config::MdnsEnabled).await {
+            if !msg.is_system_message() && chat::mdns_enabled(context, msg.chat_id).await {
                 req_mdn = true;
             }
         }
@@ -135,17 +140,10 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
             )
             .await?;
 
-        let default_str = context
-            .stock_str(StockMessage::StatusLine)
-            .await
-            .to_string();
         let factory = MimeFactory {
             from_addr,
             from_displayname,
-            selfstatus: context
-                .get_config(Config::Selfstatus)
-                .await
-                .unwrap_or_else(|| default_str),
+            selfstatus: load_footer(context).await,
             recipients,
             timestamp: msg.timestamp_sort,
             loaded: Loaded::Message { chat },
@@ -155,11 +153,42 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
             req_mdn,
             last_added_location_id: 0,
             attach_selfavatar,
+            for_export: false,
             context,
         };
         Ok(factory)
     }
 
+    /// Creates a factory reconstructing a message whose raw MIME is not stored.
+    ///
+    /// The message is rendered unencrypted, without requesting read receipts
+    /// and without our status as footer.  For incoming messages, the sender is
+    /// put into the `From:` header and we are added to the recipients.
+    pub async fn from_msg_for_export(
+        context: &'a Context,
+        msg: &'b Message,
+    ) -> Result<MimeFactory<'a, 'b>, Error> {
+        let mut factory = MimeFactory::from_msg(context, msg, false).await?;
+        factory.req_mdn = false;
+        factory.selfstatus = String::new();
+        factory.timestamp = msg.get_timestamp();
+        factory.for_export = true;
+        if msg.from_id != DC_CONTACT_ID_SELF {
+            let contact = Contact::load_from_db(context, msg.from_id).await?;
+            let self_addr =
+                std::mem::replace(&mut factory.from_addr, contact.get_addr().to_string());
+            let self_displayname = std::mem::replace(
+                &mut factory.from_displayname,
+                contact.get_authname().to_string(),
+            );
+            factory
+                .recipients
+                .retain(|(_, addr)| !addr_cmp(addr, contact.get_addr()));
+            factory.recipients.insert(0, (self_displayname, self_addr));
+        }
+        Ok(factory)
+    }
+
     pub async fn from_mdn(
         context: &'a Context,
         msg: &'b Message,
@@ -176,14 +205,7 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
             .get_config(Config::Displayname)
             .await
             .unwrap_or_default();
-        let default_str = context
-            .stock_str(StockMessage::StatusLine)
-            .await
-            .to_string();
-        let selfstatus = context
-            .get_config(Config::Selfstatus)
-            .await
-            .unwrap_or_else(|| default_str);
+        let selfstatus = load_footer(context).await;
         let timestamp = dc_create_smeared_timestamp(context).await;
 
         let res = MimeFactory::<'a, 'b> {
@@ -201,6 +223,7 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
             in_reply_to: String::default(),
             references: String::default(),
             req_mdn: false,
+            for_export: false,
             last_added_location_id: 0,
             attach_selfavatar: false,
         };
@@ -231,6 +254,9 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
     }
 
     fn is_e2ee_guaranteed(&self) -> bool {
+        if self.for_export {
+            return false;
+        }
         match &self.loaded {
             Loaded::Message { chat } => {
                 if chat.typ == Chattype::VerifiedGroup {
@@ -272,6 +298,9 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
     }
 
     fn should_force_plaintext(&self) -> i32 {
+        if self.for_export {
+            return ForcePlaintext::NoAutocryptHeader as i32;
+        }
         match &self.loaded {
             Loaded::Message { chat } => {
                 if chat.typ == Chattype::VerifiedGroup {
@@ -335,7 +364,9 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
     async fn subject_str(&self) -> String {
         match self.loaded {
             Loaded::Message { ref chat } => {
-                if self.msg.param.get_cmd() == SystemMessage::AutocryptSetupMessage {
+                if let Some(subject) = self.msg.param.get(Param::Subject) {
+                    subject.to_string()
+                } else if self.msg.param.get_cmd() == SystemMessage::AutocryptSetupMessage {
                     self.context
                         .stock_str(StockMessage::AcSetupMsgSubject)
                         .await
@@ -456,6 +487,12 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
 
         unprotected_headers.push(Header::new("Chat-Version".to_string(), "1.0".to_string()));
 
+        if let Some(user_agent) = self.context.get_config(Config::MailerUserAgent).await {
+            if !user_agent.is_empty() {
+                protected_headers.push(Header::new("X-Mailer".into(), user_agent));
+            }
+        }
+
         if let Loaded::MDN { .. } = self.loaded {
             unprotected_headers.push(Header::new(
                 "Auto-Submitted".to_string(),
@@ -514,7 +551,10 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
 
         let rfc724_mid = match self.loaded {
             Loaded::Message { .. } => self.msg.rfc724_mid.clone(),
-            Loaded::MDN { .. } => dc_create_outgoing_rfc724_mid(None, &self.from_addr),
+            Loaded::MDN { .. } => dc_create_outgoing_rfc724_mid(
+                None,
+                &self.context.get_message_id_addr(&self.from_addr).await,
+            ),
         };
 
         let ephemeral_timer = self.msg.chat_id.get_ephemeral_timer(self.context).await?;
@@ -665,6 +705,28 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
         Some(part)
     }
 
+    fn get_sync_part(&self) -> PartBuilder {
+        let json = self.msg.param.get(Param::Arg).unwrap_or_default();
+        PartBuilder::new()
+            .content_type(&"application/json".parse::<mime::Mime>().unwrap())
+            .header((
+                "Content-Disposition",
+                format!("attachment; filename=\"{}\"", SYNC_FILENAME),
+            ))
+            .body(json)
+    }
+
+    fn get_webxdc_status_update_part(&self) -> PartBuilder {
+        let json = self.msg.param.get(Param::Arg).unwrap_or_default();
+        PartBuilder::new()
+            .content_type(&"application/json".parse::<mime::Mime>().unwrap())
+            .header((
+                "Content-Disposition",
+                format!("attachment; filename=\"{}\"", STATUS_UPDATE_FILENAME),
+            ))
+            .body(json)
+    }
+
     async fn get_location_kml_part(&mut self) -> Result<PartBuilder, Error> {
         let (kml_content, last_added_location_id) =
             location::get_kml(self.context, self.msg.chat_id).await?;
@@ -712,6 +774,14 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
             let encoded = encode_words(&chat.name);
             protected_headers.push(Header::new("Chat-Group-Name".into(), encoded));
 
+            let admins = chat::get_group_admin_addrs(context, chat.id).await;
+            if !admins.is_empty() {
+                protected_headers.push(Header::new("Chat-Group-Admins".into(), admins.join(" ")));
+            }
+            if chat.is_broadcast() {
+                protected_headers.push(Header::new("Chat-Group-Broadcast".into(), "1".into()));
+            }
+
             match command {
                 SystemMessage::MemberRemovedFromGroup => {
                     let email_to_remove = self.msg.param.get(Param::Arg).unwrap_or_default();
@@ -744,6 +814,15 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
                         ));
                     }
                 }
+                SystemMessage::MemberRoleChanged => {
+                    let email_changed = self.msg.param.get(Param::Arg).unwrap_or_default();
+                    if !email_changed.is_empty() {
+                        protected_headers.push(Header::new(
+                            "Chat-Group-Role-Changed".into(),
+                            email_changed.into(),
+                        ));
+                    }
+                }
                 SystemMessage::GroupNameChanged => {
                     let value_to_add = self.msg.param.get(Param::Arg).unwrap_or_default();
 
@@ -781,7 +860,23 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
                     "ephemeral-timer-changed".to_string(),
                 ));
             }
-            SystemMessage::LocationOnly => {
+            SystemMessage::SeenMarker => {
+                protected_headers.push(Header::new(
+                    "Chat-Seen".to_string(),
+                    self.msg
+                        .param
+                        .get(Param::Arg)
+                        .unwrap_or_default()
+                        .to_string(),
+                ));
+                unprotected_headers.push(Header::new(
+                    "Auto-Submitted".to_string(),
+                    "auto-generated".to_string(),
+                ));
+            }
+            SystemMessage::LocationOnly
+            | SystemMessage::MultiDeviceSync
+            | SystemMessage::WebxdcStatusUpdate => {
                 // This should prevent automatic replies,
                 // such as non-delivery reports.
                 //
@@ -913,7 +1008,12 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
 
         let flowed_text = format_flowed(final_text);
 
-        let footer = &self.selfstatus;
+        // system messages are not written by the user, so they are not signed
+        let footer = if command == SystemMessage::Unknown {
+            self.selfstatus.as_str()
+        } else {
+            ""
+        };
         let message_text = format!(
             "{}{}{}{}{}",
             fwdhint.unwrap_or_default(),
@@ -957,7 +1057,17 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
             parts.push(msg_kml_part);
         }
 
-        if location::is_sending_locations_to_chat(context, self.msg.chat_id).await {
+        if command == SystemMessage::MultiDeviceSync {
+            parts.push(self.get_sync_part());
+        }
+
+        if command == SystemMessage::WebxdcStatusUpdate {
+            parts.push(self.get_webxdc_status_update_part());
+        }
+
+        if !self.for_export
+            && location::is_sending_locations_to_chat(context, self.msg.chat_id).await
+        {
             match self.get_location_kml_part().await {
                 Ok(part) => parts.push(part),
                 Err(err) => {
@@ -1085,6 +1195,18 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
 ///
 /// This line length limit is an
 /// [RFC5322 requirement](https://tools.ietf.org/html/rfc5322#section-2.1.1).
+/// Returns the footer appended to messages written by the user,
+/// the [Config::MessageSignature] if set, the status otherwise.
+async fn load_footer(context: &Context) -> String {
+    match context.get_config(Config::MessageSignature).await {
+        Some(signature) => signature,
+        None => context
+            .get_config(Config::Selfstatus)
+            .await
+            .unwrap_or_default(),
+    }
+}
+
 fn wrapped_base64_encode(buf: &[u8]) -> String {
     let base64 = base64::encode(&buf);
     let mut chars = base64.chars();
@@ -1125,7 +1247,11 @@ async fn build_body_file(
             &suffix,
         ),
         Viewtype::Video => format!("video.{}", &suffix),
-        _ => blob.as_file_name().to_string(),
+        _ => msg
+            .param
+            .get(Param::Filename)
+            .unwrap_or_else(|| blob.as_file_name())
+            .to_string(),
     };
 
     /* check mimetype */
@@ -1208,6 +1334,72 @@ async fn is_file_size_okay(context: &Context, msg: &Message) -> bool {
     }
 }
 
+/// Renders a plain text mail to `addr`, eg. to unsubscribe from a mailing list.
+///
+/// The mail does not belong to any chat, so no contact is created for `addr`.
+pub(crate) async fn render_plain_mail(
+    context: &Context,
+    addr: &str,
+    subject: &str,
+    body: &str,
+) -> Result<RenderedEmail, Error> {
+    let from_addr = context
+        .get_config(Config::ConfiguredAddr)
+        .await
+        .unwrap_or_default();
+    let from_displayname = context
+        .get_config(Config::Displayname)
+        .await
+        .unwrap_or_default();
+    let rfc724_mid =
+        dc_create_outgoing_rfc724_mid(None, &context.get_message_id_addr(&from_addr).await);
+    let date = chrono::Utc
+        .from_local_datetime(&chrono::NaiveDateTime::from_timestamp(
+            dc_create_smeared_timestamp(context).await,
+            0,
+        ))
+        .unwrap()
+        .to_rfc2822();
+    let subject = if subject
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == ' ')
+    {
+        subject.to_string()
+    } else {
+        encode_words(subject)
+    };
+
+    let message = PartBuilder::new()
+        .content_type(&mime::TEXT_PLAIN_UTF_8)
+        .header(Header::new("MIME-Version".into(), "1.0".into()))
+        .header(Header::new("Date".into(), date))
+        .header(Header::new(
+            "Message-ID".into(),
+            render_rfc724_mid(&rfc724_mid),
+        ))
+        .header(
+            Header::new_with_value(
+                "From".into(),
+                vec![Address::new_mailbox_with_name(from_displayname, from_addr)],
+            )
+            .unwrap(),
+        )
+        .header(
+            Header::new_with_value("To".into(), vec![Address::new_mailbox(addr.to_string())])
+                .unwrap(),
+        )
+        .header(Header::new("Subject".into(), subject))
+        .body(format!("{}\r\n", body));
+
+    Ok(RenderedEmail {
+        message: message.build().as_string().into_bytes(),
+        is_encrypted: false,
+        is_gossiped: false,
+        last_added_location_id: 0,
+        rfc724_mid,
+    })
+}
+
 fn render_rfc724_mid(rfc724_mid: &str) -> String {
     let rfc724_mid = rfc724_mid.trim().to_string();
 
@@ -1247,7 +1439,7 @@ mod tests {
     use crate::chatlist::Chatlist;
     use crate::dc_receive_imf::dc_receive_imf;
     use crate::mimeparser::*;
-    use crate::test_utils::TestContext;
+    use crate::test_utils::*;
 
     #[test]
     fn test_render_email_address() {
@@ -1527,6 +1719,83 @@ mod tests {
         new_msg
     }
 
+    #[async_std::test]
+    async fn test_selfavatar_attach_throttle() {
+        let t = TestContext::new_alice().await;
+        let avatar = t.ctx.get_blobdir().join("avatar.png");
+        async_std::fs::write(
+            &avatar,
+            include_bytes!("../test-data/image/avatar64x64.png").as_ref(),
+        )
+        .await
+        .unwrap();
+        t.ctx
+            .set_config(Config::Selfavatar, avatar.to_str())
+            .await
+            .unwrap();
+
+        let msg = incoming_msg_to_reply_msg(
+            b"From: Charlie <charlie@example.com>\n\
+                To: alice@example.com\n\
+                Subject: Chat: hello\n\
+                Chat-Version: 1.0\n\
+                Message-ID: <2224@example.com>\n\
+                Date: Sun, 22 Mar 2020 22:37:56 +0000\n\
+                \n\
+                hello\n",
+            &t.ctx,
+        )
+        .await;
+
+        // the avatar is attached to the first message to the chat
+        let attach_selfavatar = chat::shall_attach_selfavatar(&t.ctx, msg.chat_id)
+            .await
+            .unwrap();
+        assert!(attach_selfavatar);
+        let rendered_msg = MimeFactory::from_msg(&t.ctx, &msg, attach_selfavatar)
+            .await
+            .unwrap()
+            .render()
+            .await
+            .unwrap();
+        let parsed = MimeMessage::from_bytes(&t.ctx, &rendered_msg.message)
+            .await
+            .unwrap();
+        assert!(parsed.user_avatar.is_some());
+        msg.chat_id
+            .set_selfavatar_timestamp(&t.ctx, time())
+            .await
+            .unwrap();
+
+        // but not to the following ones
+        let attach_selfavatar = chat::shall_attach_selfavatar(&t.ctx, msg.chat_id)
+            .await
+            .unwrap();
+        assert!(!attach_selfavatar);
+        let rendered_msg = MimeFactory::from_msg(&t.ctx, &msg, attach_selfavatar)
+            .await
+            .unwrap()
+            .render()
+            .await
+            .unwrap();
+        let parsed = MimeMessage::from_bytes(&t.ctx, &rendered_msg.message)
+            .await
+            .unwrap();
+        assert!(parsed.user_avatar.is_none());
+
+        // until it was last sent too long ago
+        msg.chat_id
+            .set_selfavatar_timestamp(
+                &t.ctx,
+                time() - DC_RESEND_USER_AVATAR_DAYS * 24 * 60 * 60 - 1,
+            )
+            .await
+            .unwrap();
+        assert!(chat::shall_attach_selfavatar(&t.ctx, msg.chat_id)
+            .await
+            .unwrap());
+    }
+
     #[async_std::test]
     // This test could still be extended
     async fn test_render_reply() {
@@ -1567,4 +1836,200 @@ mod tests {
             .await
             .unwrap();
     }
+
+    #[async_std::test]
+    async fn test_selfstatus_footer() {
+        let alice = configured_in_memory_context(alice_keypair()).await;
+        let bob = configured_in_memory_context(bob_keypair()).await;
+        alice
+            .set_config(Config::Selfstatus, Some("Sent from my phone"))
+            .await
+            .unwrap();
+        let bob_id = Contact::create(&alice, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&alice, bob_id).await.unwrap();
+
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("hi bob".to_string()));
+        let msg_id = chat::prepare_msg(&alice, chat_id, &mut msg).await.unwrap();
+        let msg = Message::load_from_db(&alice, msg_id).await.unwrap();
+        let rendered_msg = MimeFactory::from_msg(&alice, &msg, false)
+            .await
+            .unwrap()
+            .render()
+            .await
+            .unwrap();
+        let payload = String::from_utf8_lossy(&rendered_msg.message);
+        assert!(payload.contains("hi bob\r\n\r\n-- \r\nSent from my phone"));
+
+        dc_receive_imf(&bob, &rendered_msg.message, "INBOX", 1, false)
+            .await
+            .unwrap();
+        let (_, _, received_id) = message::rfc724_mid_exists(&bob, &msg.rfc724_mid)
+            .await
+            .unwrap()
+            .unwrap();
+        let received = Message::load_from_db(&bob, received_id).await.unwrap();
+        assert_eq!(received.get_text(), Some("hi bob".to_string()));
+        assert_eq!(
+            received.get_signature(),
+            Some("Sent from my phone".to_string())
+        );
+
+        // system messages are sent without footer
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("Location streaming enabled.".to_string()));
+        msg.param.set_cmd(SystemMessage::LocationStreamingEnabled);
+        let msg_id = chat::prepare_msg(&alice, chat_id, &mut msg).await.unwrap();
+        let msg = Message::load_from_db(&alice, msg_id).await.unwrap();
+        let rendered_msg = MimeFactory::from_msg(&alice, &msg, false)
+            .await
+            .unwrap()
+            .render()
+            .await
+            .unwrap();
+        let payload = String::from_utf8_lossy(&rendered_msg.message);
+        assert!(!payload.contains("Sent from my phone"));
+
+        // the signature replaces the status
+        alice
+            .set_config(Config::MessageSignature, Some("Alice from ACME"))
+            .await
+            .unwrap();
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("hi again".to_string()));
+        let msg_id = chat::prepare_msg(&alice, chat_id, &mut msg).await.unwrap();
+        let msg = Message::load_from_db(&alice, msg_id).await.unwrap();
+        let rendered_msg = MimeFactory::from_msg(&alice, &msg, false)
+            .await
+            .unwrap()
+            .render()
+            .await
+            .unwrap();
+        let payload = String::from_utf8_lossy(&rendered_msg.message);
+        assert!(payload.contains("hi again\r\n\r\n-- \r\nAlice from ACME"));
+        assert!(!payload.contains("Sent from my phone"));
+
+        dc_receive_imf(&bob, &rendered_msg.message, "INBOX", 2, false)
+            .await
+            .unwrap();
+        let (_, _, received_id) = message::rfc724_mid_exists(&bob, &msg.rfc724_mid)
+            .await
+            .unwrap()
+            .unwrap();
+        let received = Message::load_from_db(&bob, received_id).await.unwrap();
+        assert_eq!(received.get_text(), Some("hi again".to_string()));
+
+        // an empty signature disables the footer
+        alice
+            .set_config(Config::MessageSignature, Some(""))
+            .await
+            .unwrap();
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("bye".to_string()));
+        let msg_id = chat::prepare_msg(&alice, chat_id, &mut msg).await.unwrap();
+        let msg = Message::load_from_db(&alice, msg_id).await.unwrap();
+        let rendered_msg = MimeFactory::from_msg(&alice, &msg, false)
+            .await
+            .unwrap()
+            .render()
+            .await
+            .unwrap();
+        let payload = String::from_utf8_lossy(&rendered_msg.message);
+        assert!(!payload.contains("-- \r\n"));
+    }
+
+    #[async_std::test]
+    async fn test_mailer_user_agent() {
+        let t = TestContext::new_alice().await;
+        let bob_id = Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, bob_id).await.unwrap();
+
+        async fn render_payload(t: &TestContext, chat_id: chat::ChatId) -> String {
+            let mut msg = Message::new(Viewtype::Text);
+            msg.set_text(Some("hi".to_string()));
+            let msg_id = chat::prepare_msg(&t.ctx, chat_id, &mut msg).await.unwrap();
+            let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+            let rendered_msg = MimeFactory::from_msg(&t.ctx, &msg, false)
+                .await
+                .unwrap()
+                .render()
+                .await
+                .unwrap();
+            String::from_utf8_lossy(&rendered_msg.message).to_string()
+        }
+
+        let payload = render_payload(&t, chat_id).await;
+        assert!(payload.contains(&format!(
+            "X-Mailer: Delta Chat Core {}\r\n",
+            get_version_str()
+        )));
+
+        t.ctx
+            .set_config(Config::MailerUserAgent, Some("White Label Messenger 2.0"))
+            .await
+            .unwrap();
+        let payload = render_payload(&t, chat_id).await;
+        assert!(payload.contains("X-Mailer: White Label Messenger 2.0\r\n"));
+
+        t.ctx
+            .set_config(Config::MailerUserAgent, Some(""))
+            .await
+            .unwrap();
+        let payload = render_payload(&t, chat_id).await;
+        assert!(!payload.contains("X-Mailer"));
+    }
+
+    #[async_std::test]
+    async fn test_message_id_domain() {
+        let t = TestContext::new_alice().await;
+        let bob_id = Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, bob_id).await.unwrap();
+
+        let msg_id = chat::send_text_msg(&t.ctx, chat_id, "hi".to_string())
+            .await
+            .unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert!(msg.rfc724_mid.ends_with("@example.com"));
+
+        t.ctx
+            .set_config(Config::MessageIdDomain, Some("gateway.example.org"))
+            .await
+            .unwrap();
+        let msg_id = chat::send_text_msg(&t.ctx, chat_id, "hi".to_string())
+            .await
+            .unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert!(msg.rfc724_mid.ends_with("@gateway.example.org"));
+        let rendered_msg = MimeFactory::from_msg(&t.ctx, &msg, false)
+            .await
+            .unwrap()
+            .render()
+            .await
+            .unwrap();
+        let payload = String::from_utf8_lossy(&rendered_msg.message);
+        assert!(payload.contains(&format!("Message-ID: <{}>", msg.rfc724_mid)));
+
+        // ids stay unique
+        let msg_id2 = chat::send_text_msg(&t.ctx, chat_id, "hi".to_string())
+            .await
+            .unwrap();
+        let msg2 = Message::load_from_db(&t.ctx, msg_id2).await.unwrap();
+        assert_ne!(msg.rfc724_mid, msg2.rfc724_mid);
+
+        // invalid domains are ignored
+        t.ctx
+            .set_config(Config::MessageIdDomain, Some("bad domain"))
+            .await
+            .unwrap();
+        assert_eq!(
+            t.ctx.get_message_id_addr("alice@example.com").await,
+            "alice@example.com"
+        );
+    }
 }
@@ -9,6 +9,8 @@ use mailparse::{addrparse_header, DispositionType, MailHeader, MailHeaderMap, Si
 
 use crate::aheader::Aheader;
 use crate::blob::BlobObject;
+use crate::calendar::CalendarEvent;
+use crate::config::Config;
 use crate::constants::Viewtype;
 use crate::contact::*;
 use crate::context::Context;
@@ -19,13 +21,17 @@ use crate::error::{bail, Result};
 use crate::events::EventType;
 use crate::format_flowed::unformat_flowed;
 use crate::headerdef::{HeaderDef, HeaderDefMap};
+use crate::html;
 use crate::key::Fingerprint;
 use crate::location;
+use crate::mailinglist::ListHeaders;
 use crate::message;
 use crate::param::*;
 use crate::peerstate::Peerstate;
 use crate::simplify::*;
 use crate::stock::StockMessage;
+use crate::sync::{SyncItems, SYNC_FILENAME};
+use crate::webxdc::{self, StatusUpdates};
 
 /// A parsed MIME message.
 ///
@@ -63,6 +69,21 @@ pub struct MimeMessage {
     pub(crate) group_avatar: Option<AvatarAction>,
     pub(crate) mdn_reports: Vec<Report>,
     pub(crate) failure_report: Option<FailureReport>,
+
+    /// Sanitized HTML body, remote images are not removed yet.
+    pub(crate) html: Option<String>,
+
+    /// Event from the first `text/calendar` part.
+    pub(crate) calendar_event: Option<CalendarEvent>,
+
+    /// State changes sent by another device of the same account.
+    pub(crate) sync_items: Option<SyncItems>,
+
+    /// True if the spam filter of our server flagged the message, see [MimeMessage::is_spam].
+    spam_flagged: bool,
+
+    /// Status updates of a webxdc app.
+    pub(crate) webxdc_status_updates: Option<StatusUpdates>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -86,6 +107,18 @@ pub enum SystemMessage {
 
     /// Chat ephemeral message timer is changed.
     EphemeralTimerChanged = 10,
+
+    /// Hidden message informing other devices about state changes.
+    MultiDeviceSync = 11,
+
+    /// Hidden message telling group members which messages were seen.
+    SeenMarker = 12,
+
+    /// Hidden message carrying status updates of a webxdc app.
+    WebxdcStatusUpdate = 13,
+
+    /// A group member was made admin or is no longer admin.
+    MemberRoleChanged = 14,
 }
 
 impl Default for SystemMessage {
@@ -105,6 +138,8 @@ impl MimeMessage {
             .get_header_value(HeaderDef::Date)
             .and_then(|v| mailparse::dateparse(&v).ok())
             .unwrap_or_default();
+        let trusted_hops = context.get_config_int(Config::SpamTrustedHops).await;
+        let spam_flagged = is_spam_flagged(&mail.headers, trusted_hops.max(1) as usize);
 
         let mut headers = Default::default();
         let mut recipients = Default::default();
@@ -216,6 +251,11 @@ impl MimeMessage {
             user_avatar: None,
             group_avatar: None,
             failure_report: None,
+            html: None,
+            calendar_event: None,
+            sync_items: None,
+            webxdc_status_updates: None,
+            spam_flagged,
         };
         parser.parse_mime_recursive(context, &mail).await?;
         parser.heuristically_parse_ndn(context).await;
@@ -394,8 +434,9 @@ impl MimeMessage {
 
         self.parse_attachments();
 
-        // See if an MDN is requested from the other side
-        if !self.decrypting_failed && !self.parts.is_empty() {
+        // See if an MDN is requested from the other side,
+        // never answer automatic replies to avoid loops
+        if !self.decrypting_failed && !self.parts.is_empty() && !self.is_auto_reply() {
             if let Some(ref dn_to) = self.chat_disposition_notification_to {
                 if let Some(ref from) = self.from.get(0) {
                     if from.addr == dn_to.addr {
@@ -420,10 +461,25 @@ impl MimeMessage {
                     part.msg = subject.to_string();
                 }
             }
+            // a message consisting of a single `text/calendar` part
+            // carries the event on this part, show its title at least
+            if part.msg.is_empty() {
+                if let Some(ref event) = self.calendar_event {
+                    part.msg = event.summary.clone();
+                }
+            }
 
             self.parts.push(part);
         }
 
+        if let Some(ref event) = self.calendar_event {
+            if let Some(part) = self.parts.first_mut() {
+                if let Ok(event) = serde_json::to_string(event) {
+                    part.param.set(Param::CalendarEvent, event);
+                }
+            }
+        }
+
         Ok(())
     }
 
@@ -560,6 +616,25 @@ impl MimeMessage {
                     }
                 }
                 if !any_part_added {
+                    /* keep the html alternative for UIs that want to render it */
+                    for cur_data in &mail.subparts {
+                        if get_mime_type(cur_data)?.0 == mime::TEXT_HTML {
+                            if let Ok(html) = cur_data.get_body() {
+                                self.set_html(context, &html);
+                            }
+                            break;
+                        }
+                    }
+
+                    /* invitations often come as calendar alternative to the text */
+                    for cur_data in &mail.subparts {
+                        if is_calendar_mimetype(&cur_data.ctype.mimetype) {
+                            if let Ok(ics) = cur_data.get_body() {
+                                self.set_calendar_event(&ics);
+                            }
+                        }
+                    }
+
                     /* search for text/plain and add this */
                     for cur_data in &mail.subparts {
                         if get_mime_type(cur_data)?.0.type_() == mime::TEXT {
@@ -663,6 +738,18 @@ impl MimeMessage {
         Ok(any_part_added)
     }
 
+    fn set_html(&mut self, context: &Context, html: &str) {
+        if self.html.is_none() && !html.trim().is_empty() {
+            self.html = Some(html::sanitize_html(context, html, true));
+        }
+    }
+
+    fn set_calendar_event(&mut self, ics: &str) {
+        if self.calendar_event.is_none() {
+            self.calendar_event = CalendarEvent::parse(ics);
+        }
+    }
+
     async fn add_single_part_if_known(
         &mut self,
         context: &Context,
@@ -678,6 +765,11 @@ impl MimeMessage {
 
         match filename {
             Some(filename) => {
+                if is_calendar_mimetype(&raw_mime) || filename.to_lowercase().ends_with(".ics") {
+                    if let Ok(ics) = mail.get_body() {
+                        self.set_calendar_event(&ics);
+                    }
+                }
                 self.do_add_single_file_part(
                     context,
                     msg_type,
@@ -689,6 +781,14 @@ impl MimeMessage {
                 .await;
             }
             None => {
+                if is_calendar_mimetype(&raw_mime) {
+                    // the event is attached to the first part of the message,
+                    // the raw calendar data is not shown as text.
+                    if let Ok(ics) = mail.get_body() {
+                        self.set_calendar_event(&ics);
+                    }
+                    return Ok(false);
+                }
                 match mime_type.type_() {
                     mime::IMAGE | mime::AUDIO | mime::VIDEO | mime::APPLICATION => {
                         warn!(context, "Missing attachment");
@@ -704,16 +804,17 @@ impl MimeMessage {
                             }
                         };
 
-                        let (simplified_txt, is_forwarded) = if decoded_data.is_empty() {
-                            ("".into(), false)
+                        let (simplified, is_forwarded) = if decoded_data.is_empty() {
+                            (SimplifiedText::default(), false)
                         } else {
                             let is_html = mime_type == mime::TEXT_HTML;
                             let out = if is_html {
+                                self.set_html(context, &decoded_data);
                                 dehtml(&decoded_data)
                             } else {
                                 decoded_data.clone()
                             };
-                            simplify(out, self.has_chat_version())
+                            simplify_parts(out, self.has_chat_version())
                         };
 
                         let is_format_flowed = if let Some(format) = mail.ctype.params.get("format")
@@ -723,7 +824,7 @@ impl MimeMessage {
                             false
                         };
 
-                        let simplified_txt = if mime_type.type_() == mime::TEXT
+                        let simplified = if mime_type.type_() == mime::TEXT
                             && mime_type.subtype() == mime::PLAIN
                             && is_format_flowed
                         {
@@ -732,17 +833,36 @@ impl MimeMessage {
                             } else {
                                 false
                             };
-                            unformat_flowed(&simplified_txt, delsp)
+                            let unformat = |text: String| unformat_flowed(&text, delsp);
+                            SimplifiedText {
+                                top_text: unformat(simplified.top_text),
+                                quoted_text: simplified.quoted_text.map(unformat),
+                                signature: simplified.signature.map(unformat),
+                                footer: simplified.footer.map(unformat),
+                            }
                         } else {
-                            simplified_txt
+                            simplified
                         };
 
-                        if !simplified_txt.is_empty() {
+                        if !simplified.top_text.is_empty() {
                             let mut part = Part::default();
                             part.typ = Viewtype::Text;
                             part.mimetype = Some(mime_type);
-                            part.msg = simplified_txt;
+                            part.msg = simplified.top_text;
                             part.msg_raw = Some(decoded_data);
+                            part.quoted_text = simplified.quoted_text;
+                            part.signature = simplified.signature;
+                            part.footer = simplified.footer;
+                            // the header of the text part describes the shown text best,
+                            // eg. if alternative parts in different languages are sent
+                            let language = mail
+                                .headers
+                                .get_header_value(HeaderDef::ContentLanguage)
+                                .or_else(|| self.get(HeaderDef::ContentLanguage).cloned())
+                                .and_then(|value| parse_content_language(&value));
+                            if let Some(language) = language {
+                                part.param.set(Param::Language, language);
+                            }
                             self.do_add_single_part(part);
                         }
 
@@ -789,6 +909,47 @@ impl MimeMessage {
                 return;
             }
         }
+        if filename == SYNC_FILENAME {
+            self.sync_items = SyncItems::parse(decoded_data)
+                .map_err(|err| {
+                    warn!(context, "failed to parse sync part: {}", err);
+                })
+                .ok();
+            return;
+        }
+        if filename == webxdc::STATUS_UPDATE_FILENAME {
+            self.webxdc_status_updates = StatusUpdates::parse(decoded_data)
+                .map_err(|err| {
+                    warn!(context, "failed to parse webxdc status update: {}", err);
+                })
+                .ok();
+            return;
+        }
+        /* many senders label all attachments as application/octet-stream,
+        use the content to find out if it can be shown as image, video etc. */
+        let (msg_type, mime_type, raw_mime, org_mime) =
+            match sniff_octet_stream(raw_mime, decoded_data, filename) {
+                Some((sniffed_type, sniffed_mime)) => {
+                    info!(
+                        context,
+                        "attachment {} looks like {}, not {}", filename, sniffed_mime, raw_mime
+                    );
+                    let sniffed_mime_type = sniffed_mime.parse::<Mime>().unwrap_or(mime_type);
+                    (
+                        sniffed_type,
+                        sniffed_mime_type,
+                        sniffed_mime,
+                        Some(raw_mime),
+                    )
+                }
+                None => (msg_type, mime_type, raw_mime, None),
+            };
+        let msg_type = if webxdc::is_webxdc(filename, decoded_data) {
+            Viewtype::Webxdc
+        } else {
+            msg_type
+        };
+
         /* we have a regular file attachment,
         write decoded data to new blob object */
 
@@ -802,6 +963,19 @@ impl MimeMessage {
                 return;
             }
         };
+        let orig_blob_name = blob.as_file_name().to_string();
+        let blob = match blob.clone().deduplicate(context).await {
+            Ok(blob) => blob,
+            Err(err) => {
+                warn!(
+                    context,
+                    "Cannot deduplicate blob {}: {}",
+                    blob.as_name(),
+                    err
+                );
+                blob
+            }
+        };
         info!(context, "added blobfile: {:?}", blob.as_name());
 
         /* create and register Mime part referencing the new Blob object */
@@ -818,7 +992,13 @@ impl MimeMessage {
         part.mimetype = Some(mime_type);
         part.bytes = decoded_data.len();
         part.param.set(Param::File, blob.as_name());
+        if blob.as_file_name() != orig_blob_name {
+            part.param.set(Param::Filename, orig_blob_name);
+        }
         part.param.set(Param::MimeType, raw_mime);
+        if let Some(org_mime) = org_mime {
+            part.param.set(Param::OriginalMimeType, org_mime);
+        }
 
         self.do_add_single_part(part);
     }
@@ -826,10 +1006,44 @@ impl MimeMessage {
     fn do_add_single_part(&mut self, mut part: Part) {
         if self.was_encrypted() {
             part.param.set_int(Param::GuaranteeE2ee, 1);
+            let mut fingerprints: Vec<String> = self.signatures.iter().map(|fp| fp.hex()).collect();
+            fingerprints.sort();
+            part.param
+                .set(Param::SignatureFingerprints, fingerprints.join(" "));
         }
         self.parts.push(part);
     }
 
+    /// Returns true if the message is an automatic reply as a vacation
+    /// notice, see RFC 3834.
+    ///
+    /// Besides `Auto-Submitted: auto-replied`, some vacation responders
+    /// only set `X-Autoreply`, `X-Autorespond` or `Precedence: auto_reply`.
+    pub(crate) fn is_auto_reply(&self) -> bool {
+        if let Some(auto_submitted) = self.get(HeaderDef::AutoSubmitted) {
+            if auto_submitted.split(';').next().map_or(false, |value| {
+                value.trim().eq_ignore_ascii_case("auto-replied")
+            }) {
+                return true;
+            }
+        }
+        self.get(HeaderDef::XAutoreply).is_some()
+            || self.get(HeaderDef::XAutorespond).is_some()
+            || self
+                .get(HeaderDef::Precedence)
+                .map_or(false, |precedence| precedence == "auto_reply")
+    }
+
+    /// Returns true if a spam filter on the server flagged the message,
+    /// either by `X-Spam-Flag: YES` or by an `X-Spam-Status` starting with `Yes`
+    /// as added by SpamAssassin and compatible filters.
+    ///
+    /// Only headers above the topmost `Received` header are considered,
+    /// everything below may come from the sender.
+    pub(crate) fn is_spam(&self) -> bool {
+        self.spam_flagged
+    }
+
     pub fn is_mailinglist_message(&self) -> bool {
         if self.get(HeaderDef::ListId).is_some() {
             return true;
@@ -842,6 +1056,16 @@ impl MimeMessage {
         }
     }
 
+    /// Returns the parsed `List-*` headers if the message has a `List-Id`.
+    pub(crate) fn get_list_headers(&self) -> Option<ListHeaders> {
+        ListHeaders::parse(
+            self.get(HeaderDef::ListId)?,
+            self.get(HeaderDef::ListPost).map(|s| s.as_str()),
+            self.get(HeaderDef::ListUnsubscribe).map(|s| s.as_str()),
+            self.get(HeaderDef::ListUnsubscribePost).map(|s| s.as_str()),
+        )
+    }
+
     pub fn repl_msg_by_error(&mut self, error_msg: impl AsRef<str>) {
         if let Some(part) = self.parts.first_mut() {
             part.typ = Viewtype::Text;
@@ -956,14 +1180,19 @@ impl MimeMessage {
                     header_key == "x-failed-recipients"
                 });
                 let to = if to_list.len() == 1 {
-                    Some(to_list.pop().unwrap())
+                    to_list.pop().map(|s| s.addr)
                 } else {
-                    None // We do not know which recipient failed
+                    let mut failed = get_failed_recipients(report);
+                    if failed.len() == 1 {
+                        failed.pop()
+                    } else {
+                        None // We do not know which recipient failed
+                    }
                 };
 
                 return Ok(Some(FailureReport {
                     rfc724_mid: original_message_id,
-                    failed_recipient: to.map(|s| s.addr),
+                    failed_recipient: to,
                 }));
             }
 
@@ -1024,6 +1253,7 @@ impl MimeMessage {
         sent_timestamp: i64,
         parts: &[Part],
     ) {
+        let _batch = context.batch_msg_states();
         for report in &self.mdn_reports {
             for original_message_id in
                 std::iter::once(&report.original_message_id).chain(&report.additional_message_ids)
@@ -1031,7 +1261,7 @@ impl MimeMessage {
                 if let Some((chat_id, msg_id)) =
                     message::handle_mdn(context, from_id, original_message_id, sent_timestamp).await
                 {
-                    context.emit_event(EventType::MsgRead { chat_id, msg_id });
+                    context.emit_msg_state_event(EventType::MsgRead { chat_id, msg_id });
                 }
             }
         }
@@ -1125,6 +1355,55 @@ pub(crate) struct FailureReport {
     pub failed_recipient: Option<String>,
 }
 
+/// Returns the recipients with `Action: failed` in the
+/// `message/delivery-status` part of a delivery status notification.
+///
+/// The part consists of the per-message fields followed by one group of
+/// fields per recipient, see RFC 3464.
+fn get_failed_recipients(report: &mailparse::ParsedMail<'_>) -> Vec<String> {
+    let mut failed = Vec::new();
+    let body = match report
+        .subparts
+        .iter()
+        .find(|p| {
+            p.ctype.mimetype == "message/delivery-status"
+                || p.ctype.mimetype == "message/global-delivery-status"
+        })
+        .and_then(|p| p.get_body_raw().ok())
+    {
+        Some(body) => body,
+        None => return failed,
+    };
+
+    let mut rest = body.as_slice();
+    while let Ok((fields, len)) = mailparse::parse_headers(rest) {
+        if len == 0 {
+            break;
+        }
+        let is_failed = fields
+            .get_header_value(HeaderDef::Action)
+            .map_or(false, |action| action.trim().eq_ignore_ascii_case("failed"));
+        if let Some(recipient) = fields.get_header_value(HeaderDef::FinalRecipient) {
+            // the value is of the form `rfc822; bob@example.org`
+            let addr = recipient
+                .splitn(2, ';')
+                .last()
+                .unwrap_or_default()
+                .trim()
+                .trim_start_matches('<')
+                .trim_end_matches('>');
+            if is_failed && !addr.is_empty() {
+                failed.push(addr.to_string());
+            }
+        }
+        rest = match rest.get(len..) {
+            Some(rest) => rest,
+            None => break,
+        };
+    }
+    failed
+}
+
 #[allow(clippy::indexing_slicing)]
 pub(crate) fn parse_message_ids(ids: &str) -> Result<Vec<String>> {
     // take care with mailparse::msgidparse() that is pretty untolerant eg. wrt missing `<` or `>`
@@ -1170,6 +1449,84 @@ pub struct Part {
     pub param: Params,
     org_filename: Option<String>,
     pub error: String,
+
+    /// Parts of the text removed by [simplify_parts].
+    pub(crate) quoted_text: Option<String>,
+    pub(crate) signature: Option<String>,
+    pub(crate) footer: Option<String>,
+}
+
+/// Returns the first language tag of a `Content-Language` header value, eg. `de` or `en-US`.
+///
+/// Invalid tags are ignored.
+fn parse_content_language(value: &str) -> Option<String> {
+    let language = value.split(',').next()?.trim();
+    if language.is_empty()
+        || language.len() > 35
+        || !language
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        return None;
+    }
+    Some(language.to_string())
+}
+
+/// Returns viewtype and mimetype of an `application/octet-stream`
+/// attachment as found by looking at the first bytes of the data.
+///
+/// The type is only corrected if the filename has no extension or an
+/// extension matching the content. An executable renamed to `.jpg`
+/// is not recognized as image, a PNG image named `.exe` stays a file.
+fn sniff_octet_stream(
+    raw_mime: &str,
+    data: &[u8],
+    filename: &str,
+) -> Option<(Viewtype, &'static str)> {
+    if !raw_mime.eq_ignore_ascii_case("application/octet-stream") {
+        return None;
+    }
+
+    let (viewtype, mime) = if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        (Viewtype::Image, "image/png")
+    } else if data.starts_with(b"\xff\xd8\xff") {
+        (Viewtype::Image, "image/jpeg")
+    } else if data.starts_with(b"%PDF-") {
+        (Viewtype::File, "application/pdf")
+    } else if data.get(4..8) == Some(&b"ftyp"[..]) {
+        sniff_ftyp_brand(data.get(8..12)?)?
+    } else {
+        return None;
+    };
+
+    let path = async_std::path::Path::new(filename);
+    if path.extension().is_none() {
+        return Some((viewtype, mime));
+    }
+    match message::guess_msgtype_from_suffix(path) {
+        Some((_, suffix_mime)) if suffix_mime == mime => Some((viewtype, mime)),
+        _ => None,
+    }
+}
+
+/// Returns viewtype and mimetype for the major brand of an ISO base media file.
+///
+/// The same container is used for images (HEIC, AVIF) and audio (M4A),
+/// unknown brands are not corrected.
+fn sniff_ftyp_brand(brand: &[u8]) -> Option<(Viewtype, &'static str)> {
+    match brand {
+        b"isom" | b"iso2" | b"mp41" | b"mp42" | b"avc1" | b"dash" => {
+            Some((Viewtype::Video, "video/mp4"))
+        }
+        b"3gp4" | b"3gp5" | b"3gp6" | b"3gg6" => Some((Viewtype::Video, "video/3gpp")),
+        b"qt  " => Some((Viewtype::Video, "video/quicktime")),
+        _ => None,
+    }
+}
+
+fn is_calendar_mimetype(mimetype: &str) -> bool {
+    mimetype.eq_ignore_ascii_case("text/calendar")
+        || mimetype.eq_ignore_ascii_case("application/ics")
 }
 
 /// return mimetype and viewtype for a parsed mail
@@ -1282,6 +1639,43 @@ pub(crate) fn get_from(headers: &[MailHeader]) -> Vec<SingleInfo> {
     get_all_addresses_from_header(headers, |header_key| header_key == "from")
 }
 
+/// Checks the X-Spam headers added above the `trusted_hops`-th `Received`
+/// header, i.e. by the server delivering the message to us.
+///
+/// The sender can add any headers to the message, but they always end up
+/// below the `Received` headers of the servers transporting it.
+/// If the server's filter reinjects the message, as amavis does, the filter
+/// and the reinjection add further `Received` headers above the one of the
+/// first hop, so the X-Spam headers are only found with `trusted_hops` of 2 or more.
+fn is_spam_flagged(headers: &[MailHeader], trusted_hops: usize) -> bool {
+    let mut hops = 0;
+    for header in headers {
+        let key = header.get_key();
+        if key.eq_ignore_ascii_case(HeaderDef::Received.get_headername()) {
+            hops += 1;
+            if hops >= trusted_hops {
+                break;
+            }
+            continue;
+        }
+        let value = header.get_value();
+        if key.eq_ignore_ascii_case(HeaderDef::XSpamFlag.get_headername())
+            && value.trim().eq_ignore_ascii_case("yes")
+        {
+            return true;
+        }
+        if key.eq_ignore_ascii_case(HeaderDef::XSpamStatus.get_headername())
+            && value
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .find(|s| !s.is_empty())
+                .map_or(false, |value| value.eq_ignore_ascii_case("yes"))
+        {
+            return true;
+        }
+    }
+    false
+}
+
 fn get_all_addresses_from_header<F>(headers: &[MailHeader], pred: F) -> Vec<SingleInfo>
 where
     F: Fn(String) -> bool,
@@ -1342,6 +1736,114 @@ mod tests {
         assert_eq!(mimeparser.parts.len(), 1);
     }
 
+    #[async_std::test]
+    async fn test_parse_delivery_status() {
+        let context = TestContext::new().await;
+        let raw = include_bytes!("../test-data/message/rfc3464_ndn.eml");
+        let mimeparser = MimeMessage::from_bytes(&context.ctx, &raw[..])
+            .await
+            .unwrap();
+
+        let report = mimeparser.failure_report.unwrap();
+        assert_eq!(report.rfc724_mid, "Mr.rfc3464.test@example.org");
+        assert_eq!(report.failed_recipient, Some("bob@example.net".to_string()));
+    }
+
+    #[async_std::test]
+    async fn test_is_spam() {
+        let context = TestContext::new().await;
+        for (header, is_spam) in &[
+            ("X-Spam-Flag: YES", true),
+            ("X-Spam-Flag: NO", false),
+            ("X-Spam-Status: Yes, score=9.1 required=5.0", true),
+            ("X-Spam-Status: No, score=0.3 required=5.0", false),
+            ("X-Spam-Level: ***", false),
+        ] {
+            let raw = format!(
+                "From: alice@example.org\n\
+                 To: bob@example.org\n\
+                 Subject: offer\n\
+                 {}\n\
+                 \n\
+                 hello\n",
+                header
+            );
+            let mimeparser = MimeMessage::from_bytes(&context.ctx, raw.as_bytes())
+                .await
+                .unwrap();
+            assert_eq!(mimeparser.is_spam(), *is_spam, "{}", header);
+        }
+
+        // headers below the Received header of our server are added by the sender
+        let raw = b"X-Spam-Status: No, score=0.3 required=5.0\n\
+                    Received: from mail.example.org by mx.example.org\n\
+                    X-Spam-Flag: YES\n\
+                    Received: from localhost by mail.example.org\n\
+                    From: alice@example.org\n\
+                    To: bob@example.org\n\
+                    X-Spam-Flag: YES\n\
+                    \n\
+                    hello\n";
+        let mimeparser = MimeMessage::from_bytes(&context.ctx, &raw[..])
+            .await
+            .unwrap();
+        assert!(!mimeparser.is_spam());
+
+        let raw = b"X-Spam-Flag: YES\n\
+                    Received: from mail.example.org by mx.example.org\n\
+                    From: alice@example.org\n\
+                    To: bob@example.org\n\
+                    \n\
+                    hello\n";
+        let mimeparser = MimeMessage::from_bytes(&context.ctx, &raw[..])
+            .await
+            .unwrap();
+        assert!(mimeparser.is_spam());
+
+        // amavis adds the headers between its own Received header
+        // and the one of the reinjection
+        let raw = b"Received: from localhost (localhost [127.0.0.1]) by mx.example.org
+                    X-Spam-Flag: YES
+                    X-Spam-Status: Yes, score=9.1 required=5.0
+                    Received: from mx.example.org ([127.0.0.1]) by localhost (amavisd-new)
+                    Received: from mail.example.net by mx.example.org
+                    From: alice@example.net
+                    To: bob@example.org
+                    
+                    hello
+";
+        let mimeparser = MimeMessage::from_bytes(&context.ctx, &raw[..])
+            .await
+            .unwrap();
+        assert!(!mimeparser.is_spam());
+
+        context
+            .ctx
+            .set_config(Config::SpamTrustedHops, Some("2"))
+            .await
+            .unwrap();
+        let mimeparser = MimeMessage::from_bytes(&context.ctx, &raw[..])
+            .await
+            .unwrap();
+        assert!(mimeparser.is_spam());
+
+        // headers added by the sender stay untrusted
+        let raw = b"X-Spam-Status: No, score=0.3 required=5.0
+                    Received: from localhost (localhost [127.0.0.1]) by mx.example.org
+                    Received: from mx.example.org ([127.0.0.1]) by localhost (amavisd-new)
+                    X-Spam-Flag: YES
+                    Received: from mail.example.net by mx.example.org
+                    From: alice@example.net
+                    To: bob@example.org
+                    
+                    hello
+";
+        let mimeparser = MimeMessage::from_bytes(&context.ctx, &raw[..])
+            .await
+            .unwrap();
+        assert!(!mimeparser.is_spam());
+    }
+
     #[async_std::test]
     async fn test_get_rfc724_mid_exists() {
         let context = TestContext::new().await;
@@ -1366,6 +1868,17 @@ mod tests {
         assert_eq!(mimeparser.get_rfc724_mid(), None);
     }
 
+    #[test]
+    fn test_parse_content_language() {
+        assert_eq!(parse_content_language("de"), Some("de".to_string()));
+        assert_eq!(
+            parse_content_language(" en-US, de "),
+            Some("en-US".to_string())
+        );
+        assert_eq!(parse_content_language(""), None);
+        assert_eq!(parse_content_language("de; q=1"), None);
+    }
+
     #[test]
     fn test_get_recipients() {
         let raw = include_bytes!("../test-data/message/mail_with_cc.txt");
@@ -1583,6 +2096,180 @@ mod tests {
         assert_eq!(mimeparser.group_avatar, None);
     }
 
+    #[test]
+    fn test_sniff_octet_stream() {
+        let png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR";
+        let pdf = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3";
+        let mp4 = b"\x00\x00\x00\x20ftypisom\x00\x00\x02\x00";
+        let exe = b"MZ\x90\x00\x03\x00\x00\x00";
+        let octet = "application/octet-stream";
+
+        assert_eq!(
+            sniff_octet_stream(octet, png, "image.png"),
+            Some((Viewtype::Image, "image/png"))
+        );
+        assert_eq!(
+            sniff_octet_stream(octet, png, "image"),
+            Some((Viewtype::Image, "image/png"))
+        );
+        assert_eq!(
+            sniff_octet_stream(octet, b"\xff\xd8\xff\xe0", "photo.JPG"),
+            Some((Viewtype::Image, "image/jpeg"))
+        );
+        assert_eq!(
+            sniff_octet_stream(octet, pdf, "doc.pdf"),
+            Some((Viewtype::File, "application/pdf"))
+        );
+        assert_eq!(
+            sniff_octet_stream(octet, mp4, "clip.mp4"),
+            Some((Viewtype::Video, "video/mp4"))
+        );
+        assert_eq!(
+            sniff_octet_stream(octet, b"\x00\x00\x00\x18ftyp3gp4\x00\x00\x02\x00", "clip"),
+            Some((Viewtype::Video, "video/3gpp"))
+        );
+        assert_eq!(
+            sniff_octet_stream(
+                octet,
+                b"\x00\x00\x00\x14ftypqt  \x00\x00\x02\x00",
+                "clip.mov"
+            ),
+            Some((Viewtype::Video, "video/quicktime"))
+        );
+
+        // images and unknown brands in the same container are not treated as video
+        let heic = b"\x00\x00\x00\x18ftypheic\x00\x00\x00\x00mif1heic";
+        let avif = b"\x00\x00\x00\x1cftypavif\x00\x00\x00\x00avifmif1";
+        assert_eq!(sniff_octet_stream(octet, heic, "photo"), None);
+        assert_eq!(sniff_octet_stream(octet, avif, "photo"), None);
+        assert_eq!(
+            sniff_octet_stream(octet, b"\x00\x00\x00\x20ftyp", "clip"),
+            None
+        );
+
+        // declared types are trusted
+        assert_eq!(sniff_octet_stream("application/pdf", pdf, "doc.pdf"), None);
+        assert_eq!(sniff_octet_stream("image/png", png, "image.png"), None);
+
+        // mismatches are not corrected
+        assert_eq!(sniff_octet_stream(octet, exe, "photo.jpg"), None);
+        assert_eq!(sniff_octet_stream(octet, png, "setup.exe"), None);
+        assert_eq!(sniff_octet_stream(octet, png, "photo.jpg"), None);
+    }
+
+    #[async_std::test]
+    async fn test_mimeparser_sniff_attachment() {
+        let t = TestContext::new().await;
+
+        let raw = b"From: bob@example.org\n\
+                    To: alice@example.com\n\
+                    Subject: attachments\n\
+                    Content-Type: multipart/mixed; boundary=\"==break==\"\n\
+                    \n\
+                    --==break==\n\
+                    Content-Type: application/octet-stream\n\
+                    Content-Disposition: attachment; filename=\"image.png\"\n\
+                    Content-Transfer-Encoding: base64\n\
+                    \n\
+                    iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNk+M9QDwADhgGAWjR9awAAAABJRU5ErkJggg==\n\
+                    --==break==\n\
+                    Content-Type: application/pdf\n\
+                    Content-Disposition: attachment; filename=\"doc.pdf\"\n\
+                    \n\
+                    %PDF-1.4\n\
+                    --==break==--\n";
+        let mimeparser = MimeMessage::from_bytes(&t.ctx, &raw[..]).await.unwrap();
+        assert_eq!(mimeparser.parts.len(), 2);
+
+        let image = &mimeparser.parts[0];
+        assert_eq!(image.typ, Viewtype::Image);
+        assert_eq!(image.param.get(Param::MimeType), Some("image/png"));
+        assert_eq!(
+            image.param.get(Param::OriginalMimeType),
+            Some("application/octet-stream")
+        );
+        assert_eq!(image.param.get_int(Param::Width), Some(1));
+
+        let pdf = &mimeparser.parts[1];
+        assert_eq!(pdf.typ, Viewtype::File);
+        assert_eq!(pdf.param.get(Param::MimeType), Some("application/pdf"));
+        assert_eq!(pdf.param.get(Param::OriginalMimeType), None);
+    }
+
+    #[async_std::test]
+    async fn test_mimeparser_calendar_invite() {
+        let t = TestContext::new().await;
+
+        let raw = include_bytes!("../test-data/message/google_calendar_invite.eml");
+        let mimeparser = MimeMessage::from_bytes(&t.ctx, &raw[..]).await.unwrap();
+        let event = mimeparser.calendar_event.unwrap();
+        assert_eq!(event.summary, "Team lunch");
+        assert_eq!(event.start, 1585742400);
+        assert_eq!(event.end, Some(1585746000));
+        assert_eq!(event.location, Some("Cafeteria, 2nd floor".to_string()));
+        assert_eq!(event.organizer, Some("bob@example.org".to_string()));
+
+        // the text is still shown, the event is attached to the first part
+        assert_eq!(mimeparser.parts[0].typ, Viewtype::Text);
+        assert!(mimeparser.parts[0].msg.contains("You have been invited"));
+        assert!(mimeparser.parts[0].param.exists(Param::CalendarEvent));
+    }
+
+    #[async_std::test]
+    async fn test_mimeparser_calendar_single_part() {
+        let t = TestContext::new().await;
+
+        for chat_version in &["", "Chat-Version: 1.0\n"] {
+            let raw = format!(
+                "From: bob@example.org\n\
+                 To: alice@example.com\n\
+                 Subject: Invitation\n\
+                 {}\
+                 Content-Type: text/calendar; method=REQUEST\n\
+                 \n\
+                 BEGIN:VCALENDAR\n\
+                 BEGIN:VEVENT\n\
+                 DTSTART:20200401T120000Z\n\
+                 SUMMARY:Team lunch\n\
+                 END:VEVENT\n\
+                 END:VCALENDAR\n",
+                chat_version
+            );
+            let mimeparser = MimeMessage::from_bytes(&t.ctx, raw.as_bytes())
+                .await
+                .unwrap();
+            assert_eq!(mimeparser.parts.len(), 1);
+            assert_eq!(mimeparser.parts[0].typ, Viewtype::Text);
+            assert!(!mimeparser.parts[0].msg.is_empty());
+            let event: CalendarEvent = mimeparser.parts[0]
+                .param
+                .get(Param::CalendarEvent)
+                .and_then(|event| serde_json::from_str(event).ok())
+                .unwrap();
+            assert_eq!(event.summary, "Team lunch");
+            assert_eq!(event.start, 1585742400);
+        }
+    }
+
+    #[async_std::test]
+    async fn test_mimeparser_calendar_malformed() {
+        let t = TestContext::new().await;
+
+        let raw = b"From: bob@example.org\n\
+                    To: alice@example.com\n\
+                    Subject: broken invite\n\
+                    Content-Type: text/calendar\n\
+                    \n\
+                    BEGIN:VCALENDAR\n\
+                    BEGIN:VEVENT\n\
+                    DTSTART:someday\n\
+                    END:VEVENT\n";
+        let mimeparser = MimeMessage::from_bytes(&t.ctx, &raw[..]).await.unwrap();
+        assert_eq!(mimeparser.calendar_event, None);
+        assert_eq!(mimeparser.parts.len(), 1);
+        assert!(!mimeparser.parts[0].param.exists(Param::CalendarEvent));
+    }
+
     #[async_std::test]
     async fn test_mimeparser_message_kml() {
         let context = TestContext::new().await;
@@ -7,6 +7,7 @@ use async_std_resolver::{config, resolver};
 use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use serde::Deserialize;
 
+use crate::config::Config;
 use crate::context::Context;
 use crate::dc_tools::*;
 use crate::provider;
@@ -357,6 +358,86 @@ impl Oauth2 {
     }
 }
 
+/// SASL mechanism used to pass the access token to the IMAP or SMTP server.
+///
+/// Both carry the same token, only the framing of the initial response differs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaslMechanism {
+    /// Google's `XOAUTH2`, supported by most servers.
+    XOAuth2,
+    /// `OAUTHBEARER` as standardized in RFC 7628.
+    OAuthBearer,
+}
+
+impl SaslMechanism {
+    /// Returns the mechanism name as used in `AUTHENTICATE` and `AUTH`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SaslMechanism::XOAuth2 => "XOAUTH2",
+            SaslMechanism::OAuthBearer => "OAUTHBEARER",
+        }
+    }
+
+    /// Parses a mechanism name, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim();
+        if name.eq_ignore_ascii_case("XOAUTH2") {
+            Some(SaslMechanism::XOAuth2)
+        } else if name.eq_ignore_ascii_case("OAUTHBEARER") {
+            Some(SaslMechanism::OAuthBearer)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the mechanism set in [Config::Oauth2SaslMechanism], if any.
+    ///
+    /// [Config::Oauth2SaslMechanism]: crate::config::Config::Oauth2SaslMechanism
+    pub async fn from_config(context: &Context) -> Option<Self> {
+        let name = context.get_config(Config::Oauth2SaslMechanism).await?;
+        let mechanism = SaslMechanism::from_name(&name);
+        if mechanism.is_none() && !name.trim().is_empty() {
+            warn!(context, "Unknown OAuth2 SASL mechanism {:?} ignored", name);
+        }
+        mechanism
+    }
+
+    /// Selects the mechanism for the SASL mechanisms advertised by the server.
+    ///
+    /// `OAUTHBEARER` is preferred if advertised. If neither mechanism is advertised,
+    /// `XOAUTH2` is tried as before. `forced` overrides the selection
+    /// for servers that advertise mechanisms they do not support.
+    pub fn select<S: AsRef<str>>(advertised: &[S], forced: Option<Self>) -> Self {
+        if let Some(mechanism) = forced {
+            return mechanism;
+        }
+        let advertises = |mechanism: SaslMechanism| {
+            advertised
+                .iter()
+                .any(|name| SaslMechanism::from_name(name.as_ref()) == Some(mechanism))
+        };
+        if advertises(SaslMechanism::OAuthBearer) {
+            SaslMechanism::OAuthBearer
+        } else {
+            SaslMechanism::XOAuth2
+        }
+    }
+
+    /// Returns the initial client response, before base64 encoding.
+    pub fn initial_response(self, user: &str, access_token: &str) -> String {
+        match self {
+            SaslMechanism::XOAuth2 => {
+                format!("user={}\x01auth=Bearer {}\x01\x01", user, access_token)
+            }
+            SaslMechanism::OAuthBearer => format!(
+                "n,a={},\x01auth=Bearer {}\x01\x01",
+                user.replace('=', "=3D").replace(',', "=2C"),
+                access_token
+            ),
+        }
+    }
+}
+
 async fn is_expired(context: &Context) -> bool {
     let expire_timestamp = context
         .sql
@@ -390,6 +471,67 @@ mod tests {
 
     use crate::test_utils::*;
 
+    #[test]
+    fn test_sasl_mechanism_select() {
+        let none: &[&str] = &[];
+        assert_eq!(SaslMechanism::select(none, None), SaslMechanism::XOAuth2);
+        assert_eq!(
+            SaslMechanism::select(&["PLAIN", "XOAUTH2"], None),
+            SaslMechanism::XOAuth2
+        );
+        assert_eq!(
+            SaslMechanism::select(&["PLAIN", "OAUTHBEARER"], None),
+            SaslMechanism::OAuthBearer
+        );
+        assert_eq!(
+            SaslMechanism::select(&["XOAUTH2", "oauthbearer"], None),
+            SaslMechanism::OAuthBearer
+        );
+        assert_eq!(
+            SaslMechanism::select(&["XOAUTH2", "OAUTHBEARER"], Some(SaslMechanism::XOAuth2)),
+            SaslMechanism::XOAuth2
+        );
+        assert_eq!(
+            SaslMechanism::select(&["PLAIN"], Some(SaslMechanism::OAuthBearer)).as_str(),
+            "OAUTHBEARER"
+        );
+    }
+
+    #[test]
+    fn test_sasl_mechanism_initial_response() {
+        assert_eq!(
+            SaslMechanism::XOAuth2.initial_response("alice@example.org", "token"),
+            "user=alice@example.org\x01auth=Bearer token\x01\x01"
+        );
+        assert_eq!(
+            SaslMechanism::OAuthBearer.initial_response("alice@example.org", "token"),
+            "n,a=alice@example.org,\x01auth=Bearer token\x01\x01"
+        );
+        assert_eq!(
+            SaslMechanism::OAuthBearer.initial_response("a,b=c", "token"),
+            "n,a=a=2Cb=3Dc,\x01auth=Bearer token\x01\x01"
+        );
+    }
+
+    #[async_std::test]
+    async fn test_sasl_mechanism_from_config() {
+        let t = TestContext::new().await;
+        assert_eq!(SaslMechanism::from_config(&t.ctx).await, None);
+        t.ctx
+            .set_config(Config::Oauth2SaslMechanism, Some("oauthbearer"))
+            .await
+            .unwrap();
+        assert_eq!(
+            SaslMechanism::from_config(&t.ctx).await,
+            Some(SaslMechanism::OAuthBearer)
+        );
+        t.ctx
+            .set_config(Config::Oauth2SaslMechanism, Some("foo"))
+            .await
+            .unwrap();
+        assert_eq!(SaslMechanism::from_config(&t.ctx).await, None);
+    }
+
     #[test]
     fn test_normalize_addr() {
         assert_eq!(normalize_addr(" hello@mail.de  "), "hello@mail.de");
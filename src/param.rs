@@ -21,6 +21,10 @@ pub enum Param {
     /// For messages and jobs
     File = b'f',
 
+    /// For messages: original filename if the name of [Param::File] differs,
+    /// eg. because the blob was deduplicated.
+    Filename = b'z',
+
     /// For Messages
     Width = b'w',
 
@@ -33,6 +37,10 @@ pub enum Param {
     /// For Messages
     MimeType = b'm',
 
+    /// For Messages: mimetype declared by the sender if it was
+    /// corrected by looking at the content of the attachment.
+    OriginalMimeType = b'O',
+
     /// For Messages: message is encrypted, outgoing: guarantee E2EE or the message is not send
     GuaranteeE2ee = b'c',
 
@@ -40,6 +48,10 @@ pub enum Param {
     /// 'c' nor 'e' are preset, the messages is only transport encrypted.
     ErroneousE2ee = b'e',
 
+    /// For Messages: space-separated fingerprints of the keys
+    /// that made valid signatures of the message.
+    SignatureFingerprints = b'q',
+
     /// For Messages: force unencrypted message, either `ForcePlaintext::AddAutocryptHeader` (1),
     /// `ForcePlaintext::NoAutocryptHeader` (2) or 0.
     ForcePlaintext = b'u',
@@ -71,6 +83,13 @@ pub enum Param {
     /// For Messages
     WebrtcRoom = b'V',
 
+    /// For Messages: set to 1 if the HTML part references remote images.
+    RemoteImages = b'X',
+
+    /// For Messages: JSON-encoded [crate::calendar::CalendarEvent]
+    /// found in a `text/calendar` part.
+    CalendarEvent = b'C',
+
     /// For Messages: space-separated list of messaged IDs of forwarded copies.
     ///
     /// This is used when a [crate::message::Message] is in the
@@ -109,6 +128,46 @@ pub enum Param {
     /// For Chats
     Devicetalk = b'D',
 
+    /// For Chats: `List-Id` of a mailing list chat.
+    ListId = b'L',
+
+    /// For Chats: name of the mailing list.
+    ListName = b'N',
+
+    /// For Chats: address to post to the mailing list.
+    ListPost = b'p',
+
+    /// For Chats: `mailto:` URI to unsubscribe from the mailing list.
+    ListUnsubscribeMailto = b'j',
+
+    /// For Chats: URL to unsubscribe from the mailing list.
+    ListUnsubscribeUrl = b'k',
+
+    /// For Chats: set to 1 if the unsubscribe URL supports One-Click POST.
+    ListUnsubscribeOneClick = b'o',
+
+    /// For Jobs: mailing list chat an unsubscribe mail is sent for.
+    ListUnsubscribeChatId = b'J',
+
+    /// For Chats: timestamp of the last visibility change.
+    VisibilityTimestamp = b'v',
+
+    /// For Groups: timestamp of the last profile image change.
+    ProfileImageTimestamp = b'Z',
+
+    /// For Chats: overrides `Config::MdnsEnabled` if set.
+    MdnsEnabled = b'Y',
+
+    /// For Groups: set to 1 if only admins can post, see [crate::chat::set_broadcast].
+    Broadcast = b'B',
+
+    /// For Groups: ID of the leave message, the chat is deleted as soon as it is sent,
+    /// see [crate::chat::leave_and_delete].
+    DeleteAfterLeave = b'W',
+
+    /// For Messages: subject to use instead of the generated one.
+    Subject = b'T',
+
     /// For QR
     Auth = b's',
 
@@ -120,6 +179,9 @@ pub enum Param {
 
     /// For MDN-sending job
     MsgId = b'I',
+
+    /// For Messages: language of the text as given in `Content-Language`
+    Language = b'y',
 }
 
 /// Possible values for `Param::ForcePlaintext`.
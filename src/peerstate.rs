@@ -2,7 +2,7 @@
 use std::collections::HashSet;
 use std::fmt;
 
-use num_traits::FromPrimitive;
+use num_traits::{FromPrimitive, ToPrimitive};
 
 use crate::aheader::*;
 use crate::chat;
@@ -14,10 +14,11 @@ use crate::key::{DcKey, Fingerprint, SignedPublicKey};
 use crate::sql::Sql;
 use crate::stock::StockMessage;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+#[repr(u8)]
 pub enum PeerstateKeyType {
-    GossipKey,
-    PublicKey,
+    GossipKey = 0,
+    PublicKey = 1,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, FromPrimitive)]
@@ -28,6 +29,48 @@ pub enum PeerstateVerifiedStatus {
     BidirectVerified = 2,
 }
 
+/// A key that has been seen for a peer, as recorded in the peerstate history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerstateChange {
+    pub fingerprint: Fingerprint,
+    /// Timestamp of the message the key was first seen in.
+    pub timestamp: i64,
+    /// Whether the key was received in an Autocrypt or an Autocrypt-Gossip header.
+    pub key_type: PeerstateKeyType,
+    /// Whether the key has been verified, eg. by a QR code scan.
+    pub verified: bool,
+}
+
+/// Returns all keys ever seen for `addr`, oldest first.
+pub async fn get_history(context: &Context, addr: &str) -> Result<Vec<PeerstateChange>> {
+    let history = context
+        .sql
+        .query_map(
+            "SELECT fingerprint, timestamp, key_type, verified \
+             FROM acpeerstates_history \
+             WHERE addr=? COLLATE NOCASE \
+             ORDER BY timestamp, rowid;",
+            paramsv![addr],
+            |row| {
+                let fingerprint: String = row.get(0)?;
+                let key_type: i32 = row.get(2)?;
+                Ok(PeerstateChange {
+                    fingerprint: fingerprint.parse::<Fingerprint>()?,
+                    timestamp: row.get(1)?,
+                    key_type: PeerstateKeyType::from_i32(key_type)
+                        .unwrap_or(PeerstateKeyType::PublicKey),
+                    verified: row.get(3)?,
+                })
+            },
+            |rows| {
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+    Ok(history)
+}
+
 /// Peerstate represents the state of an Autocrypt peer.
 pub struct Peerstate<'a> {
     pub context: &'a Context,
@@ -279,6 +322,7 @@ impl<'a> Peerstate<'a> {
 
                 chat::add_info_msg(context, contact_chat_id, msg).await;
                 emit_event!(context, EventType::ChatModified(contact_chat_id));
+                emit_event!(context, EventType::ContactKeyChanged { contact_id });
             } else {
                 bail!("contact with peerstate.addr {:?} not found", &self.addr);
             }
@@ -453,6 +497,7 @@ impl<'a> Peerstate<'a> {
                     self.addr,
                 ],
             ).await?;
+            self.save_history(sql).await?;
         } else if self.to_save == Some(ToSave::Timestamps) {
             sql.execute(
                 "UPDATE acpeerstates SET last_seen=?, last_seen_autocrypt=?, gossip_timestamp=? \
@@ -470,6 +515,42 @@ impl<'a> Peerstate<'a> {
         Ok(())
     }
 
+    /// Records the current keys in the peerstate history.
+    ///
+    /// Keys already in the history are kept with the timestamp they were first seen.
+    async fn save_history(&self, sql: &Sql) -> crate::sql::Result<()> {
+        let keys = [
+            (
+                &self.public_key_fingerprint,
+                PeerstateKeyType::PublicKey,
+                self.last_seen_autocrypt,
+            ),
+            (
+                &self.gossip_key_fingerprint,
+                PeerstateKeyType::GossipKey,
+                self.gossip_timestamp,
+            ),
+        ];
+        for (fingerprint, key_type, timestamp) in keys.iter() {
+            if let Some(fingerprint) = fingerprint {
+                sql.execute(
+                    "INSERT OR IGNORE INTO acpeerstates_history \
+                     (addr, fingerprint, key_type, timestamp) VALUES(?,?,?,?);",
+                    paramsv![self.addr, fingerprint.hex(), key_type.to_i32(), timestamp],
+                )
+                .await?;
+            }
+        }
+        if let Some(ref fingerprint) = self.verified_key_fingerprint {
+            sql.execute(
+                "UPDATE acpeerstates_history SET verified=1 WHERE addr=? AND fingerprint=?;",
+                paramsv![self.addr, fingerprint.hex()],
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
     pub fn has_verified_key(&self, fingerprints: &HashSet<Fingerprint>) -> bool {
         if let Some(vkc) = &self.verified_key_fingerprint {
             fingerprints.contains(vkc) && self.verified_key.is_some()
@@ -637,6 +718,47 @@ mod tests {
         assert_eq!(peerstate.verified_key_fingerprint, None);
     }
 
+    #[async_std::test]
+    async fn test_peerstate_history() {
+        let ctx = crate::test_utils::TestContext::new().await;
+        let addr = "hello@mail.com";
+        let alice_key = alice_keypair().public;
+        let bob_key = bob_keypair().public;
+
+        let header = Aheader::new(addr.to_string(), alice_key.clone(), Default::default());
+        let peerstate = Peerstate::from_header(&ctx.ctx, &header, 100);
+        peerstate.save_to_db(&ctx.ctx.sql, true).await.unwrap();
+
+        let mut peerstate = Peerstate::from_addr(&ctx.ctx, addr).await.unwrap().unwrap();
+        let header = Aheader::new(addr.to_string(), bob_key.clone(), Default::default());
+        peerstate.apply_header(&header, 200);
+        assert!(peerstate.fingerprint_changed);
+        peerstate.save_to_db(&ctx.ctx.sql, false).await.unwrap();
+
+        // the current key is not touched by a later message
+        peerstate.apply_header(&header, 300);
+        peerstate.save_to_db(&ctx.ctx.sql, false).await.unwrap();
+
+        let history = get_history(&ctx.ctx, "Hello@Mail.com").await.unwrap();
+        assert_eq!(
+            history,
+            vec![
+                PeerstateChange {
+                    fingerprint: alice_key.fingerprint(),
+                    timestamp: 100,
+                    key_type: PeerstateKeyType::PublicKey,
+                    verified: false,
+                },
+                PeerstateChange {
+                    fingerprint: bob_key.fingerprint(),
+                    timestamp: 200,
+                    key_type: PeerstateKeyType::PublicKey,
+                    verified: false,
+                },
+            ]
+        );
+    }
+
     // TODO: don't copy this from stress.rs
     #[allow(dead_code)]
     struct TestContext {
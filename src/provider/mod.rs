@@ -3,7 +3,7 @@
 mod data;
 
 use crate::config::Config;
-use crate::dc_tools::EmailAddress;
+use crate::dc_tools::normalize_address;
 use crate::provider::data::PROVIDER_DATA;
 
 #[derive(Debug, Display, Copy, Clone, PartialEq, FromPrimitive, ToPrimitive)]
@@ -78,11 +78,10 @@ pub struct Provider {
 }
 
 pub fn get_provider_info(addr: &str) -> Option<&Provider> {
-    let domain = match addr.parse::<EmailAddress>() {
+    let domain = match normalize_address(addr) {
         Ok(addr) => addr.domain,
         Err(_err) => return None,
-    }
-    .to_lowercase();
+    };
 
     if let Some(provider) = PROVIDER_DATA.get(domain.as_str()) {
         return Some(*provider);
@@ -91,6 +90,37 @@ pub fn get_provider_info(addr: &str) -> Option<&Provider> {
     None
 }
 
+/// Domains of mail exchangers that differ from the domains
+/// of the provider database, eg. Gmail's MX are in `google.com`.
+const MX_DOMAINS: &[(&str, &str)] = &[
+    ("google.com", "gmail.com"),
+    ("googlemail.com", "gmail.com"),
+    ("yandex.net", "yandex.ru"),
+];
+
+/// Returns the provider operating the mail exchanger `mx_host`.
+///
+/// This allows configuring custom domains hosted by a known provider.
+/// The host and all its parent domains are looked up,
+/// so `aspmx.l.google.com` is found as `google.com`.
+pub fn get_provider_by_mx(mx_host: &str) -> Option<&'static Provider> {
+    let mx_host = mx_host.trim_end_matches('.').to_lowercase();
+    let mut domain = mx_host.as_str();
+    // do not look up top level domains
+    while domain.contains('.') {
+        let domain_for_lookup = MX_DOMAINS
+            .iter()
+            .find(|(mx_domain, _)| *mx_domain == domain)
+            .map_or(domain, |(_, provider_domain)| *provider_domain);
+        if let Some(provider) = PROVIDER_DATA.get(domain_for_lookup) {
+            return Some(*provider);
+        }
+        domain = domain.splitn(2, '.').nth(1).unwrap_or_default();
+    }
+
+    None
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -136,4 +166,18 @@ mod tests {
         let provider = get_provider_info("user@googlemail.com").unwrap();
         assert!(provider.status == Status::PREPARATION);
     }
+
+    #[test]
+    fn test_get_provider_by_mx() {
+        let provider = get_provider_by_mx("aspmx.l.google.com.").unwrap();
+        assert_eq!(provider.server[0].hostname, "imap.gmail.com");
+        let provider = get_provider_by_mx("ALT1.ASPMX.L.GOOGLE.COM").unwrap();
+        assert_eq!(provider.server[0].hostname, "imap.gmail.com");
+        let provider = get_provider_by_mx("mx.nauta.cu").unwrap();
+        assert_eq!(provider.server[0].hostname, "imap.nauta.cu");
+
+        assert!(get_provider_by_mx("mx.unexistant.org").is_none());
+        assert!(get_provider_by_mx("com").is_none());
+        assert!(get_provider_by_mx("").is_none());
+    }
 }
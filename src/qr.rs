@@ -9,7 +9,7 @@ use crate::config::*;
 use crate::constants::Blocked;
 use crate::contact::*;
 use crate::context::Context;
-use crate::error::{bail, ensure, format_err, Error};
+use crate::error::{bail, format_err, Error};
 use crate::key::Fingerprint;
 use crate::lot::{Lot, LotState};
 use crate::message::Message;
@@ -425,11 +425,10 @@ impl Lot {
 fn normalize_address(addr: &str) -> Result<String, Error> {
     // urldecoding is needed at least for OPENPGP4FPR but should not hurt in the other cases
     let new_addr = percent_decode_str(addr).decode_utf8()?;
-    let new_addr = addr_normalize(&new_addr);
+    let new_addr = crate::dc_tools::normalize_address(&new_addr)
+        .map_err(|_| format_err!("Bad e-mail address"))?;
 
-    ensure!(may_be_valid_addr(&new_addr), "Bad e-mail address");
-
-    Ok(new_addr.to_string())
+    Ok(new_addr.display)
 }
 
 #[cfg(test)]
@@ -1,10 +1,16 @@
+use std::time::Duration;
+
+use async_std::future;
 use async_std::prelude::*;
 use async_std::sync::{channel, Receiver, Sender};
 use async_std::task;
 
 use crate::context::Context;
+use crate::dc_tools::time;
+use crate::error::{bail, ensure, format_err, Result};
 use crate::imap::Imap;
 use crate::job::{self, Thread};
+use crate::sql;
 use crate::{config::Config, message::MsgId, smtp::Smtp};
 
 pub(crate) struct StopToken;
@@ -39,6 +45,37 @@ impl Context {
     pub(crate) async fn interrupt_smtp(&self, info: InterruptInfo) {
         self.scheduler.read().await.interrupt_smtp(info).await;
     }
+
+    /// Fetches new messages from `folder` now, without waiting for
+    /// the next scheduled fetch, and returns their number.
+    ///
+    /// The fetch is done on the inbox connection between its jobs, so
+    /// IO must be started.  Unknown folders result in an error.
+    /// A folder fetched the first time also gets its most recent messages,
+    /// see [Config::FetchRecentMsgs].
+    pub async fn fetch_folder(&self, folder: &str) -> Result<usize> {
+        ensure!(!folder.is_empty(), "No folder given");
+        let (result_sender, result_receiver) = channel(1);
+        self.scheduler
+            .read()
+            .await
+            .request_fetch(FetchRequest {
+                folder: folder.to_string(),
+                result_sender,
+            })
+            .await?;
+        result_receiver
+            .recv()
+            .await
+            .map_err(|_| format_err!("Fetching {} was aborted", folder))?
+    }
+}
+
+/// A request to fetch a folder, see [Context::fetch_folder].
+#[derive(Debug)]
+struct FetchRequest {
+    folder: String,
+    result_sender: Sender<Result<usize>>,
 }
 
 async fn inbox_loop(ctx: Context, started: Sender<()>, inbox_handlers: ImapConnectionHandlers) {
@@ -49,6 +86,7 @@ async fn inbox_loop(ctx: Context, started: Sender<()>, inbox_handlers: ImapConne
         mut connection,
         stop_receiver,
         shutdown_sender,
+        fetch_receiver,
     } = inbox_handlers;
 
     let ctx1 = ctx.clone();
@@ -60,6 +98,11 @@ async fn inbox_loop(ctx: Context, started: Sender<()>, inbox_handlers: ImapConne
         let mut jobs_loaded = 0;
         let mut info = InterruptInfo::default();
         loop {
+            while let Ok(request) = fetch_receiver.try_recv() {
+                let res = fetch_requested_folder(&ctx, &mut connection, &request.folder).await;
+                request.result_sender.send(res).await;
+            }
+
             match job::load_next(&ctx, Thread::Imap, &info).await {
                 Some(job) if jobs_loaded <= 20 => {
                     jobs_loaded += 1;
@@ -75,6 +118,12 @@ async fn inbox_loop(ctx: Context, started: Sender<()>, inbox_handlers: ImapConne
                 None => {
                     jobs_loaded = 0;
 
+                    // Run housekeeping between fetches if it is due,
+                    // so it never runs in the middle of a sync.
+                    if sql::maybe_schedule_housekeeping(&ctx, time()).await {
+                        continue;
+                    }
+
                     // Expunge folder if needed, e.g. if some jobs have
                     // deleted messages on the server.
                     if let Err(err) = connection.maybe_close_folder(&ctx).await {
@@ -122,6 +171,19 @@ async fn fetch(ctx: &Context, connection: &mut Imap) {
     }
 }
 
+async fn fetch_requested_folder(
+    ctx: &Context,
+    connection: &mut Imap,
+    folder: &str,
+) -> Result<usize> {
+    connection.connect_configured(&ctx).await?;
+    let res = connection.fetch_folder(&ctx, folder).await;
+    if let Err(ref err) = res {
+        warn!(ctx, "could not fetch {}: {}", folder, err);
+    }
+    res
+}
+
 async fn fetch_idle(ctx: &Context, connection: &mut Imap, folder: Config) -> InterruptInfo {
     match ctx.get_config(folder).await {
         Some(watch_folder) => {
@@ -171,6 +233,7 @@ async fn simple_imap_loop(
         mut connection,
         stop_receiver,
         shutdown_sender,
+        ..
     } = inbox_handlers;
 
     let ctx1 = ctx.clone();
@@ -211,17 +274,40 @@ async fn smtp_loop(ctx: Context, started: Sender<()>, smtp_handlers: SmtpConnect
         let ctx = ctx1;
 
         let mut interrupt_info = Default::default();
+        // state changes of the messages sent in one go are emitted together
+        let mut state_batch = None;
         loop {
             match job::load_next(&ctx, Thread::Smtp, &interrupt_info).await {
                 Some(job) => {
                     info!(ctx, "executing smtp job");
+                    state_batch.get_or_insert_with(|| ctx.batch_msg_states());
                     job::perform_job(&ctx, job::Connection::Smtp(&mut connection), job).await;
                     interrupt_info = Default::default();
                 }
                 None => {
+                    state_batch = None;
                     // Fake Idle
                     info!(ctx, "smtp fake idle - started");
-                    interrupt_info = idle_interrupt_receiver.recv().await.unwrap_or_default();
+                    let max_idle = Duration::from_secs(
+                        ctx.get_config_int(Config::SmtpMaxIdle).await.max(0) as u64,
+                    );
+                    interrupt_info = match connection.idle_time_left(max_idle) {
+                        Some(time_left) => {
+                            match future::timeout(time_left, idle_interrupt_receiver.recv()).await {
+                                Ok(info) => info.unwrap_or_default(),
+                                Err(_) => {
+                                    // Close the connection before the server drops it.
+                                    info!(
+                                        ctx,
+                                        "smtp connection unused for too long, disconnecting"
+                                    );
+                                    connection.disconnect().await;
+                                    Default::default()
+                                }
+                            }
+                        }
+                        None => idle_interrupt_receiver.recv().await.unwrap_or_default(),
+                    };
                     info!(ctx, "smtp fake idle - interrupted")
                 }
             }
@@ -258,7 +344,7 @@ impl Scheduler {
             inbox_loop(ctx1, inbox_start_send, inbox_handlers).await
         }));
 
-        if ctx.get_config_bool(Config::MvboxWatch).await {
+        if ctx.should_watch_folder(Config::MvboxWatch).await {
             let ctx1 = ctx.clone();
             mvbox_handle = Some(task::spawn(async move {
                 simple_imap_loop(
@@ -273,7 +359,7 @@ impl Scheduler {
             mvbox_start_send.send(()).await;
         }
 
-        if ctx.get_config_bool(Config::SentboxWatch).await {
+        if ctx.should_watch_folder(Config::SentboxWatch).await {
             let ctx1 = ctx.clone();
             sentbox_handle = Some(task::spawn(async move {
                 simple_imap_loop(
@@ -354,6 +440,18 @@ impl Scheduler {
         }
     }
 
+    /// Passes a fetch request to the inbox loop.
+    async fn request_fetch(&self, request: FetchRequest) -> Result<()> {
+        match self {
+            Scheduler::Running { ref inbox, .. } => {
+                inbox.fetch_sender.send(request).await;
+                inbox.interrupt(InterruptInfo::default()).await;
+                Ok(())
+            }
+            Scheduler::Stopped => bail!("IO is not started"),
+        }
+    }
+
     /// Halts the scheduler, must be called first, and then `stop`.
     pub(crate) async fn pre_stop(&self) -> StopToken {
         match self {
@@ -495,6 +593,8 @@ struct SmtpConnectionHandlers {
 #[derive(Debug)]
 pub(crate) struct ImapConnectionState {
     state: ConnectionState,
+    /// Channel to request fetching a folder, only used by the inbox loop.
+    fetch_sender: Sender<FetchRequest>,
 }
 
 impl ImapConnectionState {
@@ -503,11 +603,13 @@ impl ImapConnectionState {
         let (stop_sender, stop_receiver) = channel(1);
         let (shutdown_sender, shutdown_receiver) = channel(1);
         let (idle_interrupt_sender, idle_interrupt_receiver) = channel(1);
+        let (fetch_sender, fetch_receiver) = channel(10);
 
         let handlers = ImapConnectionHandlers {
             connection: Imap::new(idle_interrupt_receiver),
             stop_receiver,
             shutdown_sender,
+            fetch_receiver,
         };
 
         let state = ConnectionState {
@@ -516,7 +618,10 @@ impl ImapConnectionState {
             stop_sender,
         };
 
-        let conn = ImapConnectionState { state };
+        let conn = ImapConnectionState {
+            state,
+            fetch_sender,
+        };
 
         (conn, handlers)
     }
@@ -537,6 +642,7 @@ struct ImapConnectionHandlers {
     connection: Imap,
     stop_receiver: Receiver<()>,
     shutdown_sender: Sender<()>,
+    fetch_receiver: Receiver<FetchRequest>,
 }
 
 #[derive(Default, Debug)]
@@ -553,3 +659,200 @@ impl InterruptInfo {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use async_std::io::BufReader;
+    use async_std::net::{TcpListener, TcpStream};
+
+    use crate::chat;
+    use crate::contact::Contact;
+    use crate::message::{Message, MessageState};
+    use crate::provider::Socket;
+    use crate::test_utils::TestContext;
+
+    #[derive(Debug, PartialEq)]
+    enum SmtpEvent {
+        Connected,
+        Rejected,
+        Delivered(String),
+        Closed,
+    }
+
+    /// Accepts SMTP connections, the first `reject` connections are refused
+    /// with a temporary error.  What happens on the connections is reported to `events`.
+    async fn serve_smtp(listener: TcpListener, mut reject: usize, events: Sender<SmtpEvent>) {
+        while let Ok((stream, _)) = listener.accept().await {
+            if reject > 0 {
+                reject -= 1;
+                (&stream)
+                    .write_all(b"421 4.3.2 Try again later\r\n")
+                    .await
+                    .ok();
+                events.send(SmtpEvent::Rejected).await;
+            } else {
+                task::spawn(serve_smtp_connection(stream, events.clone()));
+            }
+        }
+    }
+
+    async fn serve_smtp_connection(stream: TcpStream, events: Sender<SmtpEvent>) {
+        events.send(SmtpEvent::Connected).await;
+        let mut reader = BufReader::new(&stream);
+        let mut writer = &stream;
+        writer.write_all(b"220 mock ESMTP\r\n").await.ok();
+
+        let mut data: Option<String> = None;
+        let mut line = String::new();
+        while reader.read_line(&mut line).await.unwrap_or_default() > 0 {
+            let response: &[u8] = if data.is_none() {
+                let command = line.to_uppercase();
+                if command.starts_with("EHLO") {
+                    b"250 mock\r\n"
+                } else if command.starts_with("DATA") {
+                    data = Some(String::new());
+                    b"354 Go ahead\r\n"
+                } else if command.starts_with("QUIT") {
+                    b"221 Bye\r\n"
+                } else {
+                    b"250 OK\r\n"
+                }
+            } else if line == ".\r\n" {
+                let mail = data.take().unwrap_or_default();
+                events.send(SmtpEvent::Delivered(mail)).await;
+                b"250 2.0.0 Queued\r\n"
+            } else {
+                if let Some(mail) = data.as_mut() {
+                    mail.push_str(&line);
+                }
+                b""
+            };
+            writer.write_all(response).await.ok();
+            line.clear();
+        }
+        events.send(SmtpEvent::Closed).await;
+    }
+
+    /// Starts a mock SMTP server and configures `t` to send through it.
+    async fn start_smtp_server(t: &TestContext, reject: usize) -> Receiver<SmtpEvent> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = channel(16);
+        task::spawn(serve_smtp(listener, reject, sender));
+
+        let sql = &t.ctx.sql;
+        sql.set_raw_config(&t.ctx, "configured_send_server", Some("127.0.0.1"))
+            .await
+            .unwrap();
+        sql.set_raw_config(&t.ctx, "configured_send_port", Some(&port.to_string()))
+            .await
+            .unwrap();
+        let security = (Socket::Plain as i32).to_string();
+        sql.set_raw_config(&t.ctx, "configured_send_security", Some(&security))
+            .await
+            .unwrap();
+        receiver
+    }
+
+    async fn next_event(events: &Receiver<SmtpEvent>) -> Option<SmtpEvent> {
+        future::timeout(Duration::from_secs(10), events.recv())
+            .await
+            .ok()
+            .and_then(|event| event.ok())
+    }
+
+    async fn no_event_within(events: &Receiver<SmtpEvent>, secs: u64) -> bool {
+        future::timeout(Duration::from_secs(secs), events.recv())
+            .await
+            .is_err()
+    }
+
+    async fn expect_delivery(events: &Receiver<SmtpEvent>, text: &str) {
+        match next_event(events).await {
+            Some(SmtpEvent::Delivered(mail)) => assert!(mail.contains(text), "{}", mail),
+            event => panic!("expected delivery of {:?}, got {:?}", text, event),
+        }
+    }
+
+    async fn send_text(t: &TestContext, text: &str) -> MsgId {
+        let contact_id = Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, contact_id)
+            .await
+            .unwrap();
+        chat::send_text_msg(&t.ctx, chat_id, text.to_string())
+            .await
+            .unwrap()
+    }
+
+    async fn smtp_job_tries(t: &TestContext) -> i32 {
+        let thread = Thread::Smtp as i64;
+        t.ctx
+            .sql
+            .query_get_value(
+                &t.ctx,
+                "SELECT tries FROM jobs WHERE thread=?",
+                paramsv![thread],
+            )
+            .await
+            .unwrap_or_default()
+    }
+
+    #[async_std::test]
+    async fn test_stop_io_keeps_queued_messages() {
+        let t = TestContext::new_alice().await;
+        let events = start_smtp_server(&t, 0).await;
+
+        t.ctx.start_io().await;
+        send_text(&t, "first").await;
+        assert_eq!(next_event(&events).await, Some(SmtpEvent::Connected));
+        expect_delivery(&events, "first").await;
+
+        // stopping IO closes the connection
+        t.ctx.stop_io().await;
+        assert!(!t.ctx.is_io_running().await);
+        assert_eq!(next_event(&events).await, Some(SmtpEvent::Closed));
+
+        // messages sent meanwhile are queued ...
+        let msg_id = send_text(&t, "second").await;
+        assert!(no_event_within(&events, 1).await);
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.get_state(), MessageState::OutPending);
+
+        // ... and sent when IO is started again
+        t.ctx.start_io().await;
+        assert_eq!(next_event(&events).await, Some(SmtpEvent::Connected));
+        expect_delivery(&events, "second").await;
+
+        t.ctx.stop_io().await;
+    }
+
+    #[async_std::test]
+    async fn test_maybe_network_skips_backoff() {
+        let t = TestContext::new_alice().await;
+        let events = start_smtp_server(&t, 1).await;
+
+        t.ctx.start_io().await;
+        send_text(&t, "hello").await;
+        assert_eq!(next_event(&events).await, Some(SmtpEvent::Rejected));
+
+        // the failed job is retried after 30 seconds at the earliest
+        for _ in 0..100 {
+            if smtp_job_tries(&t).await > 0 {
+                break;
+            }
+            task::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(smtp_job_tries(&t).await, 1);
+        assert!(no_event_within(&events, 2).await);
+
+        t.ctx.maybe_network().await;
+        assert_eq!(next_event(&events).await, Some(SmtpEvent::Connected));
+        expect_delivery(&events, "hello").await;
+
+        t.ctx.stop_io().await;
+    }
+}
@@ -94,6 +94,16 @@ pub async fn dc_get_securejoin_qr(context: &Context, group_chat_id: ChatId) -> O
 
     ensure_secret_key_exists(context).await.ok();
 
+    if !group_chat_id.is_unset()
+        && !chat::may_manage_group(context, group_chat_id, DC_CONTACT_ID_SELF).await
+    {
+        error!(
+            context,
+            "Only admins can create QR codes for {}", group_chat_id
+        );
+        return None;
+    }
+
     // invitenumber will be used to allow starting the handshake,
     // auth will be used to verify the fingerprint
     let invitenumber =
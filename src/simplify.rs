@@ -15,13 +15,16 @@ pub fn escape_message_footer_marks(text: &str) -> String {
 }
 
 /// Remove standard (RFC 3676, §4.3) footer if it is found.
+///
+/// Returns the lines before the footer and the lines after the
+/// separator, if a footer was found.
 #[allow(clippy::indexing_slicing)]
-fn remove_message_footer<'a>(lines: &'a [&str]) -> &'a [&'a str] {
+fn remove_message_footer<'a>(lines: &'a [&str]) -> (&'a [&'a str], Option<&'a [&'a str]>) {
     let mut nearly_standard_footer = None;
     for (ix, &line) in lines.iter().enumerate() {
         match line {
             // some providers encode `-- ` to `-- =20` which results in `--  `
-            "-- " | "--  " => return &lines[..ix],
+            "-- " | "--  " => return (&lines[..ix], Some(&lines[ix + 1..])),
             // some providers encode `-- ` to `=2D-` which results in only `--`;
             // use that only when no other footer is found
             // and if the line before is empty and the line after is not empty
@@ -35,15 +38,17 @@ fn remove_message_footer<'a>(lines: &'a [&str]) -> &'a [&'a str] {
         }
     }
     if let Some(ix) = nearly_standard_footer {
-        return &lines[..ix];
+        return (&lines[..ix], Some(&lines[ix + 1..]));
     }
-    lines
+    (lines, None)
 }
 
-/// Remove nonstandard footer and a boolean indicating whether such
-/// footer was removed.
+/// Remove nonstandard footer.
+///
+/// Returns the lines before the footer and the footer including its
+/// separator line, if such footer was found.
 #[allow(clippy::indexing_slicing)]
-fn remove_nonstandard_footer<'a>(lines: &'a [&str]) -> (&'a [&'a str], bool) {
+fn remove_nonstandard_footer<'a>(lines: &'a [&str]) -> (&'a [&'a str], Option<&'a [&'a str]>) {
     for (ix, &line) in lines.iter().enumerate() {
         if line == "--"
             || line.starts_with("---")
@@ -52,45 +57,85 @@ fn remove_nonstandard_footer<'a>(lines: &'a [&str]) -> (&'a [&'a str], bool) {
             || line.starts_with("*****")
             || line.starts_with("~~~~~")
         {
-            return (&lines[..ix], true);
+            return (&lines[..ix], Some(&lines[ix..]));
         }
     }
-    (lines, false)
+    (lines, None)
 }
 
 fn split_lines(buf: &str) -> Vec<&str> {
     buf.split('\n').collect()
 }
 
+/// Message text split into the parts recognized by [simplify_parts].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SimplifiedText {
+    /// The text written by the sender, as shown in the chat.
+    pub top_text: String,
+
+    /// Quote found at the top or at the bottom of the message,
+    /// without the leading `>`.
+    pub quoted_text: Option<String>,
+
+    /// Signature found after the standard `-- ` separator.
+    pub signature: Option<String>,
+
+    /// Nonstandard footer, including its separator line.
+    pub footer: Option<String>,
+}
+
 /// Simplify message text for chat display.
 /// Remove quotes, signatures, trailing empty lines etc.
-pub fn simplify(mut input: String, is_chat_message: bool) -> (String, bool) {
+pub fn simplify(input: String, is_chat_message: bool) -> (String, bool) {
+    let (parts, is_forwarded) = simplify_parts(input, is_chat_message);
+    (parts.top_text, is_forwarded)
+}
+
+/// Like [simplify], but returns the removed quote, signature and
+/// footer along with the simplified text.
+pub fn simplify_parts(mut input: String, is_chat_message: bool) -> (SimplifiedText, bool) {
     input.retain(|c| c != '\r');
     let lines = split_lines(&input);
     let (lines, is_forwarded) = skip_forward_header(&lines);
 
     let original_lines = &lines;
 
-    let lines = remove_message_footer(lines);
+    let (lines, signature) = remove_message_footer(lines);
+    let signature = signature
+        .map(|lines| render_message(lines, false, false))
+        .filter(|signature| !signature.is_empty());
 
-    let text = if is_chat_message {
-        render_message(lines, false, false)
+    let parts = if is_chat_message {
+        SimplifiedText {
+            top_text: render_message(lines, false, false),
+            signature,
+            ..Default::default()
+        }
     } else {
-        let (lines, has_nonstandard_footer) = remove_nonstandard_footer(lines);
-        let (lines, has_bottom_quote) = remove_bottom_quote(lines);
+        let (lines, footer) = remove_nonstandard_footer(lines);
+        let (lines, bottom_quote) = remove_bottom_quote(lines);
+        let top_quote = get_top_quote(lines);
         let (lines, has_top_quote) = remove_top_quote(lines);
 
         if lines.iter().all(|it| it.trim().is_empty()) {
-            render_message(original_lines, false, false)
+            SimplifiedText {
+                top_text: render_message(original_lines, false, false),
+                ..Default::default()
+            }
         } else {
-            render_message(
-                lines,
-                has_top_quote,
-                has_nonstandard_footer || has_bottom_quote,
-            )
+            SimplifiedText {
+                top_text: render_message(
+                    lines,
+                    has_top_quote,
+                    footer.is_some() || bottom_quote.is_some(),
+                ),
+                quoted_text: top_quote.or(bottom_quote),
+                signature,
+                footer: footer.map(|lines| render_message(lines, false, false)),
+            }
         }
     };
-    (text, is_forwarded)
+    (parts, is_forwarded)
 }
 
 /// Skips "forwarded message" header.
@@ -107,8 +152,12 @@ fn skip_forward_header<'a>(lines: &'a [&str]) -> (&'a [&'a str], bool) {
     }
 }
 
+/// Removes the quote at the bottom of the message.
+///
+/// Returns the remaining lines and the quoted text without the leading
+/// `>` marks, if a quote was found.
 #[allow(clippy::indexing_slicing)]
-fn remove_bottom_quote<'a>(lines: &'a [&str]) -> (&'a [&'a str], bool) {
+fn remove_bottom_quote<'a>(lines: &'a [&str]) -> (&'a [&'a str], Option<String>) {
     let mut last_quoted_line = None;
     for (l, line) in lines.iter().enumerate().rev() {
         if is_plain_quote(line) {
@@ -118,6 +167,7 @@ fn remove_bottom_quote<'a>(lines: &'a [&str]) -> (&'a [&'a str], bool) {
         }
     }
     if let Some(mut l_last) = last_quoted_line {
+        let quote = render_quote(&lines[l_last..]);
         if l_last > 1 && is_empty_line(lines[l_last - 1]) {
             l_last -= 1
         }
@@ -127,18 +177,39 @@ fn remove_bottom_quote<'a>(lines: &'a [&str]) -> (&'a [&'a str], bool) {
                 l_last -= 1
             }
         }
-        (&lines[..l_last], true)
+        (&lines[..l_last], Some(quote))
     } else {
-        (lines, false)
+        (lines, None)
     }
 }
 
 #[allow(clippy::indexing_slicing)]
 fn remove_top_quote<'a>(lines: &'a [&str]) -> (&'a [&'a str], bool) {
+    if let Some((_, last_quoted_line)) = find_top_quote(lines) {
+        (&lines[last_quoted_line + 1..], true)
+    } else {
+        (lines, false)
+    }
+}
+
+/// Returns the quote at the top of the message without the leading `>` marks.
+#[allow(clippy::indexing_slicing)]
+fn get_top_quote(lines: &[&str]) -> Option<String> {
+    let (first_quoted_line, last_quoted_line) = find_top_quote(lines)?;
+    Some(render_quote(&lines[first_quoted_line..=last_quoted_line]))
+}
+
+/// Returns the indices of the first and the last line of the quote
+/// at the top of the message.
+fn find_top_quote(lines: &[&str]) -> Option<(usize, usize)> {
+    let mut first_quoted_line = None;
     let mut last_quoted_line = None;
     let mut has_quoted_headline = false;
     for (l, line) in lines.iter().enumerate() {
         if is_plain_quote(line) {
+            if first_quoted_line.is_none() {
+                first_quoted_line = Some(l);
+            }
             last_quoted_line = Some(l)
         } else if !is_empty_line(line) {
             if is_quoted_headline(line) && !has_quoted_headline && last_quoted_line.is_none() {
@@ -149,11 +220,21 @@ fn remove_top_quote<'a>(lines: &'a [&str]) -> (&'a [&'a str], bool) {
             }
         }
     }
-    if let Some(last_quoted_line) = last_quoted_line {
-        (&lines[last_quoted_line + 1..], true)
-    } else {
-        (lines, false)
-    }
+    Some((first_quoted_line?, last_quoted_line?))
+}
+
+/// Joins quoted lines, removing one level of `>` marks.
+fn render_quote(lines: &[&str]) -> String {
+    lines
+        .iter()
+        .map(|line| {
+            line.strip_prefix('>')
+                .map_or(*line, |line| line.strip_prefix(' ').unwrap_or(line))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim_end()
+        .to_string()
 }
 
 fn render_message(lines: &[&str], is_cut_at_begin: bool, is_cut_at_end: bool) -> String {
@@ -300,6 +381,100 @@ mod tests {
         assert!(!has_top_quote);
     }
 
+    #[test]
+    fn test_get_top_quote() {
+        assert_eq!(
+            get_top_quote(&["> first", "> second", "not a quote"]).unwrap(),
+            "first\nsecond"
+        );
+        assert!(get_top_quote(&["not a quote", "> first", "> second"]).is_none());
+        assert_eq!(
+            get_top_quote(&["On Monday, Bob wrote:", ">first", ">> nested", "", "answer"]).unwrap(),
+            "first\n> nested"
+        );
+    }
+
+    #[test]
+    fn test_simplify_top_quote() {
+        let input = "> Are you coming?\n\nYes, see you later.".to_string();
+        let (parts, is_forwarded) = simplify_parts(input.clone(), false);
+        assert_eq!(parts.top_text, "[...]Yes, see you later.");
+        assert!(!is_forwarded);
+        assert_eq!(parts.quoted_text.unwrap(), "Are you coming?");
+
+        // quotes are not removed from chat messages
+        let (parts, _) = simplify_parts(input.clone(), true);
+        assert_eq!(parts.top_text, input);
+        assert!(parts.quoted_text.is_none());
+
+        // a message consisting of a quote only is not changed
+        let (parts, _) = simplify_parts("> only a quote".to_string(), false);
+        assert_eq!(parts.top_text, "> only a quote");
+        assert!(parts.quoted_text.is_none());
+    }
+
+    #[test]
+    fn test_simplify_parts_signature() {
+        let input = "Hi Alice,\nsee you tomorrow.\n\n-- \nBob\nExample Inc.".to_string();
+        let (parts, _) = simplify_parts(input.clone(), false);
+        assert_eq!(parts.top_text, "Hi Alice,\nsee you tomorrow.");
+        assert_eq!(parts.signature.unwrap(), "Bob\nExample Inc.");
+        assert_eq!(parts.quoted_text, None);
+        assert_eq!(parts.footer, None);
+
+        // the default text is the same as returned by simplify()
+        assert_eq!(simplify(input, false).0, "Hi Alice,\nsee you tomorrow.");
+
+        // a signature without text after it is ignored
+        let (parts, _) = simplify_parts("text\n-- \n".to_string(), true);
+        assert_eq!(parts.top_text, "text");
+        assert_eq!(parts.signature, None);
+    }
+
+    #[test]
+    fn test_simplify_parts_bottom_quote() {
+        // layout used by most desktop mail clients: answer, headline, quote, signature
+        let input =
+            "Sure!\n\nOn 2020-03-22, Alice wrote:\n> Do you have time?\n>\n> Alice\n\n-- \nBob"
+                .to_string();
+        let (parts, is_forwarded) = simplify_parts(input, false);
+        assert!(!is_forwarded);
+        assert_eq!(parts.top_text, "Sure! [...]");
+        assert_eq!(parts.quoted_text.unwrap(), "Do you have time?\n\nAlice");
+        assert_eq!(parts.signature.unwrap(), "Bob");
+        assert_eq!(parts.footer, None);
+    }
+
+    #[test]
+    fn test_simplify_parts_top_quote() {
+        // layout used when replying below the quote
+        let input = "Alice wrote:\n> Lunch?\n\nYes, at noon.\n-- \nBob".to_string();
+        let (parts, _) = simplify_parts(input, false);
+        assert_eq!(parts.top_text, "[...]Yes, at noon.");
+        assert_eq!(parts.quoted_text.unwrap(), "Lunch?");
+        assert_eq!(parts.signature.unwrap(), "Bob");
+    }
+
+    #[test]
+    fn test_simplify_parts_nonstandard_footer() {
+        // top-posting clients quote the original message below a separator
+        let input = "Thanks!\n\n-----Original Message-----\nFrom: Alice\nHello".to_string();
+        let (parts, _) = simplify_parts(input, false);
+        assert_eq!(parts.top_text, "Thanks! [...]");
+        assert_eq!(
+            parts.footer.unwrap(),
+            "-----Original Message-----\nFrom: Alice\nHello"
+        );
+        assert_eq!(parts.signature, None);
+        assert_eq!(parts.quoted_text, None);
+
+        // chat messages do not have nonstandard footers
+        let input = "Thanks!\n\n-----\nmore text".to_string();
+        let (parts, _) = simplify_parts(input, true);
+        assert_eq!(parts.top_text, "Thanks!\n\n-----\nmore text");
+        assert_eq!(parts.footer, None);
+    }
+
     #[test]
     fn test_escape_message_footer_marks() {
         let esc = escape_message_footer_marks("--\n--text --in line");
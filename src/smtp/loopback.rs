@@ -0,0 +1,171 @@
+//! # Loopback transport
+//!
+//! Delivers messages sent via [Smtp::send] directly into the receiving contexts,
+//! bypassing the network. Only intended for tests, outside of this crate it is
+//! available with the `loopback` feature:
+//!
+//! ```ignore
+//! let loopback = Loopback::new(vec![alice.clone(), bob.clone()]);
+//! let msg_id = chat::send_text_msg(&alice, chat_id, "hi".to_string()).await?;
+//! loopback.send_msg(&alice, msg_id).await?;
+//! ```
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use async_smtp::EmailAddress;
+
+use crate::config::Config;
+use crate::context::Context;
+use crate::dc_receive_imf::dc_receive_imf;
+use crate::error::Result;
+use crate::message::{Message, MsgId};
+use crate::mimefactory::MimeFactory;
+
+/// Folder the loopback transport delivers into.
+const LOOPBACK_FOLDER: &str = "INBOX";
+
+/// In-process transport connecting a set of contexts.
+#[derive(Debug, Default)]
+pub struct Loopback {
+    /// Contexts that receive messages addressed to their configured address.
+    peers: Vec<Context>,
+
+    /// Last server UID handed out, shared by all peers.
+    last_uid: AtomicU32,
+}
+
+impl Loopback {
+    /// Creates a transport delivering to the configured addresses of `peers`.
+    pub fn new(peers: Vec<Context>) -> Self {
+        Loopback {
+            peers,
+            last_uid: AtomicU32::new(0),
+        }
+    }
+
+    /// Passes `message` to `dc_receive_imf()` of every peer that is one of the `recipients`.
+    ///
+    /// Like a real SMTP server, the sender does not learn whether the
+    /// recipients could process the message, errors are only logged.
+    pub(crate) async fn deliver(
+        &self,
+        context: &Context,
+        recipients: &[EmailAddress],
+        message: &[u8],
+    ) {
+        for peer in &self.peers {
+            let addr = match peer.get_config(Config::ConfiguredAddr).await {
+                Some(addr) => addr,
+                None => continue,
+            };
+            if !recipients
+                .iter()
+                .any(|rcpt| rcpt.to_string().eq_ignore_ascii_case(&addr))
+            {
+                continue;
+            }
+
+            self.deliver_to(context, peer, &addr, message).await;
+        }
+    }
+
+    /// Renders the message `msg_id` of `context` and delivers it to all peers
+    /// among the chat members.
+    ///
+    /// This does not go through the job queue, so the message state of the
+    /// sender is not changed.
+    pub async fn send_msg(&self, context: &Context, msg_id: MsgId) -> Result<()> {
+        let msg = Message::load_from_db(context, msg_id).await?;
+        let mimefactory = MimeFactory::from_msg(context, &msg, false).await?;
+        let recipients = mimefactory.recipients();
+        let rendered = mimefactory.render().await?;
+
+        for peer in &self.peers {
+            let addr = match peer.get_config(Config::ConfiguredAddr).await {
+                Some(addr) => addr,
+                None => continue,
+            };
+            if recipients
+                .iter()
+                .any(|rcpt| rcpt.eq_ignore_ascii_case(&addr))
+            {
+                self.deliver_to(context, peer, &addr, &rendered.message)
+                    .await;
+            }
+        }
+        Ok(())
+    }
+
+    async fn deliver_to(&self, context: &Context, peer: &Context, addr: &str, message: &[u8]) {
+        let uid = self.last_uid.fetch_add(1, Ordering::SeqCst) + 1;
+        if let Err(err) = dc_receive_imf(peer, message, LOOPBACK_FOLDER, uid, false).await {
+            warn!(context, "Loopback delivery to {} failed: {}", addr, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::contact::{Contact, Origin};
+    use crate::message::Message;
+    use crate::smtp::Smtp;
+    use crate::test_utils::*;
+
+    /// Renders a text message from `context` to `to` and sends it via `smtp`.
+    async fn send_text(smtp: &mut Smtp, context: &Context, to: &str, text: &str) -> Message {
+        let msg = prepare_text_msg(context, to, text).await;
+        let recipients = vec![EmailAddress::new(to.to_string()).unwrap()];
+        smtp.send(
+            context,
+            recipients,
+            render_msg(context, &msg).await,
+            msg.id.to_u32(),
+        )
+        .await
+        .unwrap();
+        msg
+    }
+
+    async fn received(context: &Context, sent: &Message) -> Message {
+        get_received_msg(context, &sent.rfc724_mid).await
+    }
+
+    #[async_std::test]
+    async fn test_loopback_encrypted() {
+        let alice = configured_in_memory_context(alice_keypair()).await;
+        let bob = configured_in_memory_context(bob_keypair()).await;
+        let mut smtp = Smtp::new_loopback(vec![alice.clone(), bob.clone()]);
+        assert!(smtp.is_connected().await);
+
+        // the first message carries the Autocrypt key, but can not be encrypted yet
+        let sent = send_text(&mut smtp, &alice, "bob@example.net", "hi bob").await;
+        let msg = received(&bob, &sent).await;
+        assert_eq!(msg.get_text(), Some("hi bob".to_string()));
+        assert!(!msg.get_showpadlock());
+        assert!(Contact::lookup_id_by_addr(&bob, "alice@example.com", Origin::Unknown).await > 0);
+
+        let sent = send_text(&mut smtp, &bob, "alice@example.com", "hi alice").await;
+        let msg = received(&alice, &sent).await;
+        assert_eq!(msg.get_text(), Some("hi alice".to_string()));
+        assert!(msg.get_showpadlock());
+
+        let sent = send_text(&mut smtp, &alice, "bob@example.net", "encrypted").await;
+        let msg = received(&bob, &sent).await;
+        assert_eq!(msg.get_text(), Some("encrypted".to_string()));
+        assert!(msg.get_showpadlock());
+    }
+
+    #[async_std::test]
+    async fn test_loopback_send_msg() {
+        let alice = configured_in_memory_context(alice_keypair()).await;
+        let bob = configured_in_memory_context(bob_keypair()).await;
+        let loopback = Loopback::new(vec![alice.clone(), bob.clone()]);
+
+        let sent = prepare_text_msg(&alice, "bob@example.net", "hi bob").await;
+        loopback.send_msg(&alice, sent.id).await.unwrap();
+        let msg = received(&bob, &sent).await;
+        assert_eq!(msg.get_text(), Some("hi bob".to_string()));
+    }
+}
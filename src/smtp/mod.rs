@@ -1,12 +1,16 @@
 //! # SMTP transport module
 
+#[cfg(any(test, feature = "loopback"))]
+pub mod loopback;
 pub mod send;
+mod trace;
 
 use std::time::{Duration, SystemTime};
 
 use async_smtp::smtp::client::net::*;
 use async_smtp::*;
 
+use crate::config::Config;
 use crate::constants::*;
 use crate::context::Context;
 use crate::events::EventType;
@@ -39,8 +43,14 @@ pub enum Error {
     #[error("SMTP: oauth2 error {address}")]
     Oauth2Error { address: String },
 
+    #[error("SMTP: unsupported SASL mechanism {0}")]
+    UnsupportedSaslMechanism(&'static str),
+
     #[error("TLS error")]
     Tls(#[from] async_native_tls::Error),
+
+    #[error("SMTP: failed to start protocol trace: {0}")]
+    TraceRelay(#[source] std::io::Error),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -56,6 +66,10 @@ pub(crate) struct Smtp {
     /// (eg connect or send succeeded). On initialization and disconnect
     /// it is set to None.
     last_success: Option<SystemTime>,
+
+    /// If set, messages are delivered to local contexts instead of the network.
+    #[cfg(any(test, feature = "loopback"))]
+    loopback: Option<loopback::Loopback>,
 }
 
 impl Smtp {
@@ -64,6 +78,17 @@ impl Smtp {
         Default::default()
     }
 
+    /// Create a new Smtp instance that delivers all messages to `peers`.
+    ///
+    /// Each peer receives the messages addressed to its configured address.
+    #[cfg(any(test, feature = "loopback"))]
+    pub fn new_loopback(peers: Vec<Context>) -> Self {
+        Smtp {
+            loopback: Some(loopback::Loopback::new(peers)),
+            ..Default::default()
+        }
+    }
+
     /// Disconnect the SMTP transport and drop it entirely.
     pub async fn disconnect(&mut self) {
         if let Some(mut transport) = self.transport.take() {
@@ -86,8 +111,26 @@ impl Smtp {
         }
     }
 
+    /// Returns how long the connection may stay unused until it has been
+    /// idle for `max_idle`, zero if it already has.
+    ///
+    /// Returns `None` if there is no connection to close or if `max_idle`
+    /// is zero.
+    pub fn idle_time_left(&self, max_idle: Duration) -> Option<Duration> {
+        if self.transport.is_none() {
+            return None;
+        }
+        idle_time_left(self.last_success, SystemTime::now(), max_idle)
+    }
+
     /// Check whether we are connected.
     pub async fn is_connected(&self) -> bool {
+        #[cfg(any(test, feature = "loopback"))]
+        {
+            if self.loopback.is_some() {
+                return true;
+            }
+        }
         self.transport
             .as_ref()
             .map(|t| t.is_connected())
@@ -151,6 +194,17 @@ impl Smtp {
 
         let (creds, mechanism) = if oauth2 {
             // oauth2
+            // async-smtp only implements the XOAUTH2 framing,
+            // falling back silently would bypass a mechanism forced for broken servers
+            if let Some(mechanism @ SaslMechanism::OAuthBearer) =
+                SaslMechanism::from_config(context).await
+            {
+                context.emit_event(EventType::ErrorNetwork(format!(
+                    "SMTP does not support the OAuth2 mechanism {}.",
+                    mechanism.as_str()
+                )));
+                return Err(Error::UnsupportedSaslMechanism(mechanism.as_str()));
+            }
             let send_pw = &lp.password;
             let access_token = dc_get_oauth2_access_token(context, addr, send_pw, false).await;
             if access_token.is_none() {
@@ -184,9 +238,16 @@ impl Smtp {
             _ => smtp::ClientSecurity::Wrapper(tls_parameters),
         };
 
-        let client = smtp::SmtpClient::with_security((domain.as_str(), port), security)
-            .await
-            .map_err(Error::ConnectionSetupFailure)?;
+        let client = if context.get_config_bool(Config::DebugTraceProtocol).await {
+            let relay_port = trace::start_relay(context, lp, strict_tls)
+                .await
+                .map_err(Error::TraceRelay)?;
+            smtp::SmtpClient::with_security(("127.0.0.1", relay_port), smtp::ClientSecurity::None)
+                .await
+        } else {
+            smtp::SmtpClient::with_security((domain.as_str(), port), security).await
+        }
+        .map_err(Error::ConnectionSetupFailure)?;
 
         let client = client
             .smtp_utf8(true)
@@ -220,3 +281,77 @@ impl Smtp {
         Ok(())
     }
 }
+
+fn idle_time_left(
+    last_success: Option<SystemTime>,
+    now: SystemTime,
+    max_idle: Duration,
+) -> Option<Duration> {
+    if max_idle.as_secs() == 0 {
+        return None;
+    }
+    let idle = match last_success {
+        Some(last_success) => now.duration_since(last_success).unwrap_or_default(),
+        None => max_idle,
+    };
+    Some(max_idle.checked_sub(idle).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::TestContext;
+
+    #[test]
+    fn test_idle_time_left() {
+        let now = SystemTime::now();
+        let max_idle = Duration::from_secs(240);
+
+        assert_eq!(
+            idle_time_left(Some(now - Duration::from_secs(100)), now, max_idle),
+            Some(Duration::from_secs(140))
+        );
+        assert_eq!(
+            idle_time_left(Some(now - Duration::from_secs(300)), now, max_idle),
+            Some(Duration::from_secs(0))
+        );
+        // clock went backwards
+        assert_eq!(
+            idle_time_left(Some(now + Duration::from_secs(10)), now, max_idle),
+            Some(max_idle)
+        );
+        assert_eq!(
+            idle_time_left(None, now, max_idle),
+            Some(Duration::from_secs(0))
+        );
+        assert_eq!(idle_time_left(Some(now), now, Duration::from_secs(0)), None);
+    }
+
+    #[async_std::test]
+    async fn test_oauthbearer_rejected() {
+        let t = TestContext::new().await;
+        t.ctx
+            .set_config(Config::Oauth2SaslMechanism, Some("OAUTHBEARER"))
+            .await
+            .unwrap();
+        let lp = ServerLoginParam {
+            server: "smtp.example.org".to_string(),
+            port: 465,
+            ..Default::default()
+        };
+        let mut smtp = Smtp::new();
+        let res = smtp.connect(&t.ctx, &lp, "alice@example.org", true).await;
+        assert!(matches!(
+            res,
+            Err(Error::UnsupportedSaslMechanism("OAUTHBEARER"))
+        ));
+        assert!(!smtp.is_connected().await);
+    }
+
+    #[test]
+    fn test_idle_time_left_disconnected() {
+        let smtp = Smtp::new();
+        assert_eq!(smtp.idle_time_left(Duration::from_secs(240)), None);
+    }
+}
@@ -33,6 +33,19 @@ impl Smtp {
     ) -> Result<()> {
         let message_len_bytes = message.len();
 
+        #[cfg(any(test, feature = "loopback"))]
+        {
+            if let Some(ref loopback) = self.loopback {
+                loopback.deliver(context, &recipients, &message).await;
+                context.emit_event(EventType::SmtpMessageSent(format!(
+                    "Message len={} was delivered via loopback",
+                    message_len_bytes
+                )));
+                self.last_success = Some(std::time::SystemTime::now());
+                return Ok(());
+            }
+        }
+
         let recipients_display = recipients
             .iter()
             .map(|x| format!("{}", x))
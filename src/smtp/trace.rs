@@ -0,0 +1,298 @@
+//! # SMTP protocol tracing
+//!
+//! async-smtp does not accept a custom stream, so if [Config::DebugTraceProtocol]
+//! is set, the client connects to a local relay instead of the server.
+//! The relay opens the real connection, negotiates TLS and logs
+//! all lines passing through it. Credentials and message contents are not logged.
+//!
+//! [Config::DebugTraceProtocol]: crate::config::Config::DebugTraceProtocol
+
+use std::time::Duration;
+
+use async_std::io::{self, Read, ReadExt, Write, WriteExt};
+use async_std::net::{TcpListener, TcpStream};
+use async_std::prelude::*;
+use async_std::task;
+
+use super::SMTP_TIMEOUT;
+use crate::context::Context;
+use crate::login_param::{dc_build_tls, ServerLoginParam};
+use crate::provider::Socket;
+use crate::trace::{TraceProtocol, TraceStream, MASK};
+
+/// Masks the SMTP credentials and skips the message contents.
+#[derive(Debug, Default)]
+pub(crate) struct SmtpTrace {
+    /// Set while the client sends authentication data
+    /// until the server answers with anything but `334`.
+    authenticating: bool,
+
+    /// `DATA` was sent, the server did not answer yet.
+    data_pending: bool,
+
+    /// The client sends the message contents.
+    in_data: bool,
+}
+
+impl TraceProtocol for SmtpTrace {
+    const NAME: &'static str = "SMTP";
+
+    fn sent(&mut self, line: &str) -> Option<String> {
+        if self.in_data {
+            if line == "." {
+                self.in_data = false;
+                return Some(line.to_string());
+            }
+            return None;
+        }
+        if self.authenticating {
+            return Some(MASK.to_string());
+        }
+
+        let mut parts = line.splitn(3, ' ');
+        let command = parts.next().unwrap_or_default();
+        if command.eq_ignore_ascii_case("AUTH") {
+            self.authenticating = true;
+            let mechanism = parts.next().unwrap_or_default();
+            if parts.next().is_some() {
+                // initial response given inline
+                return Some(format!("{} {} {}", command, mechanism, MASK));
+            }
+        } else if command.eq_ignore_ascii_case("DATA") {
+            self.data_pending = true;
+        }
+        Some(line.to_string())
+    }
+
+    fn received(&mut self, line: &str) {
+        if !line.starts_with("334") {
+            self.authenticating = false;
+        }
+        if self.data_pending {
+            self.data_pending = false;
+            self.in_data = line.starts_with("354");
+        }
+    }
+}
+
+/// Starts a relay to the server described by `lp` and returns its local port.
+///
+/// The relay accepts a single connection,
+/// the client is expected to connect without TLS.
+pub(crate) async fn start_relay(
+    context: &Context,
+    lp: &ServerLoginParam,
+    strict_tls: bool,
+) -> io::Result<u16> {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+    let port = listener.local_addr()?.port();
+
+    let context = context.clone();
+    let lp = lp.clone();
+    task::spawn(async move {
+        let timeout = Duration::from_secs(SMTP_TIMEOUT);
+        let res = async {
+            let (client, _) = io::timeout(timeout, listener.accept()).await?;
+            relay(&context, &client, &lp, strict_tls).await
+        }
+        .await;
+        if let Err(err) = res {
+            warn!(context, "SMTP relay to {} failed: {}", lp.server, err);
+        }
+    });
+
+    Ok(port)
+}
+
+async fn relay(
+    context: &Context,
+    mut client: &TcpStream,
+    lp: &ServerLoginParam,
+    strict_tls: bool,
+) -> io::Result<()> {
+    let tcp = TcpStream::connect((lp.server.as_str(), lp.port)).await?;
+    if lp.security == Socket::SSL {
+        let tls = dc_build_tls(strict_tls)
+            .connect(&lp.server, tcp)
+            .await
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        let server = TraceStream::new(context.clone(), tls, SmtpTrace::default());
+        return forward(server, client).await;
+    }
+
+    let mut server = TraceStream::new(context.clone(), tcp, SmtpTrace::default());
+    let greeting = read_response(&mut server).await?;
+    // the client sends its own EHLO after the greeting
+    client.write_all(greeting.as_bytes()).await?;
+
+    server.write_all(b"EHLO localhost\r\n").await?;
+    let ehlo = read_response(&mut server).await?;
+    let starttls = ehlo.lines().any(|line| {
+        line.get(4..)
+            .map_or(false, |ext| ext.eq_ignore_ascii_case("STARTTLS"))
+    });
+    if lp.security != Socket::STARTTLS && !starttls {
+        return forward(server, client).await;
+    }
+
+    server.write_all(b"STARTTLS\r\n").await?;
+    let response = read_response(&mut server).await?;
+    if !response.starts_with("220") {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("STARTTLS failed: {}", response.trim_end()),
+        ));
+    }
+    let tls = dc_build_tls(strict_tls)
+        .connect(&lp.server, server.into_inner())
+        .await
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+    let server = TraceStream::new(context.clone(), tls, SmtpTrace::default());
+    forward(server, client).await
+}
+
+/// Copies data in both directions until one side closes the connection.
+async fn forward<S: Read + Write + Unpin>(server: S, client: &TcpStream) -> io::Result<()> {
+    let (mut server_read, mut server_write) = futures::io::AsyncReadExt::split(server);
+    let upload = io::copy(&mut &*client, &mut server_write);
+    let download = io::copy(&mut server_read, &mut &*client);
+    upload.race(download).await?;
+    Ok(())
+}
+
+/// Reads a possibly multiline response.
+async fn read_response<S: Read + Unpin>(stream: &mut S) -> io::Result<String> {
+    let mut response = Vec::new();
+    let mut line_start = 0;
+    let mut byte = [0u8; 1];
+    loop {
+        if stream.read(&mut byte).await? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        response.push(byte[0]);
+        if byte[0] == b'\n' {
+            // the last line has a space instead of a dash after the code
+            if response.get(line_start + 3) != Some(&b'-') {
+                break;
+            }
+            line_start = response.len();
+        }
+    }
+    Ok(String::from_utf8_lossy(&response).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::events::EventType;
+    use crate::smtp::Smtp;
+    use crate::test_utils::TestContext;
+
+    #[test]
+    fn test_smtp_trace() {
+        let mut trace = SmtpTrace::default();
+        assert_eq!(
+            trace.sent("AUTH PLAIN AGFsaWNlAHNlY3JldA==").unwrap(),
+            "AUTH PLAIN ***"
+        );
+        trace.received("235 2.7.0 Authentication successful");
+        assert_eq!(
+            trace.sent("MAIL FROM:<alice@example.org>").unwrap(),
+            "MAIL FROM:<alice@example.org>"
+        );
+
+        assert_eq!(trace.sent("AUTH LOGIN").unwrap(), "AUTH LOGIN");
+        trace.received("334 VXNlcm5hbWU6");
+        assert_eq!(trace.sent("YWxpY2U=").unwrap(), MASK);
+        trace.received("334 UGFzc3dvcmQ6");
+        assert_eq!(trace.sent("c2VjcmV0").unwrap(), MASK);
+        trace.received("235 2.7.0 Authentication successful");
+
+        assert_eq!(trace.sent("DATA").unwrap(), "DATA");
+        trace.received("354 Go ahead");
+        assert!(trace.sent("Subject: secret").is_none());
+        assert!(trace.sent("").is_none());
+        assert!(trace.sent("..").is_none());
+        assert_eq!(trace.sent(".").unwrap(), ".");
+        trace.received("250 OK");
+        assert_eq!(trace.sent("QUIT").unwrap(), "QUIT");
+
+        // rejected DATA command
+        assert_eq!(trace.sent("DATA").unwrap(), "DATA");
+        trace.received("554 No valid recipients");
+        assert_eq!(trace.sent("RSET").unwrap(), "RSET");
+    }
+
+    /// Answers the commands of a single SMTP session.
+    async fn mock_server(listener: TcpListener) -> io::Result<()> {
+        let (stream, _) = listener.accept().await?;
+        let mut stream = &stream;
+        stream.write_all(b"220 mock ESMTP\r\n").await?;
+        let mut line = Vec::new();
+        let mut byte = [0u8; 1];
+        while stream.read(&mut byte).await? > 0 {
+            line.push(byte[0]);
+            if byte[0] != b'\n' {
+                continue;
+            }
+            let command = String::from_utf8_lossy(&line).to_uppercase();
+            line.clear();
+            let response: &[u8] = if command.starts_with("EHLO") {
+                b"250-mock\r\n250 AUTH PLAIN LOGIN\r\n"
+            } else if command.starts_with("AUTH") {
+                b"235 2.7.0 Authentication successful\r\n"
+            } else if command.starts_with("QUIT") {
+                stream.write_all(b"221 Bye\r\n").await?;
+                break;
+            } else {
+                b"502 Not implemented\r\n"
+            };
+            stream.write_all(response).await?;
+        }
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_trace_login() {
+        let t = TestContext::new().await;
+        t.ctx
+            .set_config(crate::config::Config::DebugTraceProtocol, Some("1"))
+            .await
+            .unwrap();
+        let emitter = t.ctx.get_event_emitter();
+
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = task::spawn(mock_server(listener));
+
+        let lp = ServerLoginParam {
+            server: "127.0.0.1".to_string(),
+            port,
+            security: Socket::Plain,
+            user: "alice".to_string(),
+            password: "secret".to_string(),
+            ..Default::default()
+        };
+        let mut smtp = Smtp::new();
+        smtp.connect(&t.ctx, &lp, "alice@example.org", false)
+            .await
+            .unwrap();
+        smtp.disconnect().await;
+        server.await.unwrap();
+
+        let mut traced = Vec::new();
+        while let Ok(event) = emitter.try_recv() {
+            if let EventType::Info(msg) = event.typ {
+                if msg.contains("SMTP ") {
+                    assert!(!msg.contains("secret"));
+                    assert!(!msg.contains("AGFsaWNlAHNlY3JldA=="));
+                    traced.push(msg.splitn(2, "SMTP ").nth(1).unwrap().to_string());
+                }
+            }
+        }
+        assert!(traced.contains(&"<< 220 mock ESMTP".to_string()));
+        assert!(traced.contains(&">> AUTH PLAIN ***".to_string()));
+        assert!(traced.contains(&"<< 235 2.7.0 Authentication successful".to_string()));
+    }
+}
@@ -3,17 +3,23 @@
 use async_std::prelude::*;
 use async_std::sync::RwLock;
 
-use std::collections::HashSet;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use std::time::Duration;
 
+use lazy_static::lazy_static;
 use rusqlite::{Connection, Error as SqlError, OpenFlags};
 
 use crate::chat::{update_device_icon, update_saved_messages_icon};
+use crate::config::Config;
 use crate::constants::{ShowEmails, DC_CHAT_ID_TRASH};
 use crate::context::Context;
 use crate::dc_tools::*;
-use crate::ephemeral::start_ephemeral_timers;
+use crate::ephemeral::{delete_expired_messages, start_ephemeral_timers};
+use crate::events::EventType;
+use crate::job::{self, Action};
+use crate::keyring;
 use crate::param::*;
 use crate::peerstate::*;
 
@@ -49,16 +55,63 @@ pub enum Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Database file name used for contexts that are not backed by a file.
+pub(crate) const IN_MEMORY_DBFILE: &str = ":memory:";
+
+lazy_static! {
+    /// Database files opened by the contexts of this process
+    /// with the number of contexts using them.
+    static ref OPEN_DBFILES: Mutex<HashMap<PathBuf, usize>> = Mutex::new(HashMap::new());
+}
+
+/// Registration of a database file in [OPEN_DBFILES], removed on drop.
+#[derive(Debug)]
+struct OpenDbfile(PathBuf);
+
+impl OpenDbfile {
+    fn register(dbfile: &Path) -> Self {
+        let dbfile = std::fs::canonicalize(dbfile).unwrap_or_else(|_| dbfile.to_path_buf());
+        let mut open_dbfiles = OPEN_DBFILES.lock().unwrap_or_else(|e| e.into_inner());
+        *open_dbfiles.entry(dbfile.clone()).or_insert(0) += 1;
+        OpenDbfile(dbfile)
+    }
+}
+
+impl Drop for OpenDbfile {
+    fn drop(&mut self) {
+        let mut open_dbfiles = OPEN_DBFILES.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = open_dbfiles.get_mut(&self.0) {
+            *count -= 1;
+            if *count == 0 {
+                open_dbfiles.remove(&self.0);
+            }
+        }
+    }
+}
+
+/// Returns true if `dbfile` is opened by any context of this process.
+pub(crate) fn is_dbfile_open(dbfile: &Path) -> bool {
+    let dbfile = std::fs::canonicalize(dbfile).unwrap_or_else(|_| dbfile.to_path_buf());
+    OPEN_DBFILES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .contains_key(&dbfile)
+}
+
 /// A wrapper around the underlying Sqlite3 object.
 #[derive(Debug)]
 pub struct Sql {
     pool: RwLock<Option<r2d2::Pool<r2d2_sqlite::SqliteConnectionManager>>>,
+
+    /// Keeps the database file registered as open while the pool exists.
+    open_dbfile: RwLock<Option<OpenDbfile>>,
 }
 
 impl Default for Sql {
     fn default() -> Self {
         Self {
             pool: RwLock::new(None),
+            open_dbfile: RwLock::new(None),
         }
     }
 }
@@ -75,6 +128,7 @@ impl Sql {
     pub async fn close(&self) {
         let _ = self.pool.write().await.take();
         // drop closes the connection
+        let _ = self.open_dbfile.write().await.take();
     }
 
     // return true on success, false on failure
@@ -304,7 +358,10 @@ impl Sql {
         };
 
         match res {
-            Ok(_) => Ok(()),
+            Ok(_) => {
+                crate::log::update_secret(context, key, value);
+                Ok(())
+            }
             Err(err) => {
                 error!(context, "set_raw_config(): Cannot change value. {:?}", &err);
                 Err(err)
@@ -452,9 +509,62 @@ pub fn get_rowid2(
     )
 }
 
-pub async fn housekeeping(context: &Context) {
+/// Unreferenced blobs newer than this are kept by [housekeeping],
+/// they may belong to a message that is just created.
+const HOUSEKEEPING_GRACE_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+/// Blobs deleted by [housekeeping].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct HousekeepingStats {
+    /// Number of deleted unreferenced files.
+    pub deleted_files: usize,
+
+    /// Disk space reclaimed by deleting the files, in bytes.
+    pub deleted_bytes: u64,
+}
+
+pub async fn housekeeping(context: &Context) -> HousekeepingStats {
+    housekeeping_ex(context, HOUSEKEEPING_GRACE_PERIOD).await
+}
+
+/// Adds a housekeeping job if the last housekeeping ran at least
+/// [Config::HousekeepingInterval] seconds before `now`.
+///
+/// The job runs on the IMAP thread between fetching messages,
+/// so it does not interfere with a running fetch.
+/// Returns true if a job was added.
+pub(crate) async fn maybe_schedule_housekeeping(context: &Context, now: i64) -> bool {
+    let interval = context.get_config_int(Config::HousekeepingInterval).await as i64;
+    if interval <= 0 {
+        return false;
+    }
+    let last: i64 = context
+        .get_config(Config::LastHousekeeping)
+        .await
+        .and_then(|last| last.parse().ok())
+        .unwrap_or_default();
+    if last <= now && now - last < interval {
+        return false;
+    }
+    if job::action_exists(context, Action::Housekeeping).await {
+        return false;
+    }
+    info!(context, "Scheduling housekeeping, last run at {}", last);
+    job::add(
+        context,
+        job::Job::new(Action::Housekeeping, 0, Params::new(), 0),
+    )
+    .await;
+    true
+}
+
+pub(crate) async fn housekeeping_ex(
+    context: &Context,
+    grace_period: Duration,
+) -> HousekeepingStats {
     let mut files_in_use = HashSet::new();
     let mut unreferenced_count = 0;
+    let mut reclaimed = HousekeepingStats::default();
 
     info!(context, "Start housekeeping...");
     maybe_add_from_param(
@@ -510,8 +620,9 @@ pub async fn housekeeping(context: &Context) {
     match async_std::fs::read_dir(p).await {
         Ok(mut dir_handle) => {
             /* avoid deletion of files that are just created to build a message object */
-            let diff = std::time::Duration::from_secs(60 * 60);
-            let keep_files_newer_than = std::time::SystemTime::now().checked_sub(diff).unwrap();
+            let keep_files_newer_than = std::time::SystemTime::now()
+                .checked_sub(grace_period)
+                .unwrap();
 
             while let Some(entry) = dir_handle.next().await {
                 if entry.is_err() {
@@ -531,7 +642,9 @@ pub async fn housekeeping(context: &Context) {
 
                 unreferenced_count += 1;
 
+                let mut size = 0;
                 if let Ok(stats) = async_std::fs::metadata(entry.path()).await {
+                    size = stats.len();
                     let recently_created =
                         stats.created().is_ok() && stats.created().unwrap() > keep_files_newer_than;
                     let recently_modified = stats.modified().is_ok()
@@ -556,7 +669,18 @@ pub async fn housekeeping(context: &Context) {
                     entry.file_name()
                 );
                 let path = entry.path();
-                dc_delete_file(context, path).await;
+                if dc_delete_file(context, path).await {
+                    reclaimed.deleted_files += 1;
+                    reclaimed.deleted_bytes += size;
+                    context
+                        .sql
+                        .execute(
+                            "DELETE FROM blobs WHERE name=?;",
+                            paramsv![format!("$BLOBDIR/{}", name_s)],
+                        )
+                        .await
+                        .ok();
+                }
             }
         }
         Err(err) => {
@@ -583,7 +707,32 @@ pub async fn housekeeping(context: &Context) {
         );
     }
 
-    info!(context, "Housekeeping done.",);
+    if let Err(err) = delete_expired_messages(context).await {
+        warn!(
+            context,
+            "Housekeeping: Cannot delete expired messages: {}", err
+        );
+    }
+
+    if let Err(err) = keyring::prune(context, false).await {
+        warn!(context, "Housekeeping: Cannot prune peer keys: {}", err);
+    }
+
+    if let Err(err) = context
+        .set_config(Config::LastHousekeeping, Some(&time().to_string()))
+        .await
+    {
+        warn!(context, "Housekeeping: Cannot save time: {}", err);
+    }
+    context.emit_event(EventType::HousekeepingDone(reclaimed));
+
+    info!(
+        context,
+        "Housekeeping done, deleted {} files with {} bytes.",
+        reclaimed.deleted_files,
+        reclaimed.deleted_bytes
+    );
+    reclaimed
 }
 
 #[allow(clippy::indexing_slicing)]
@@ -662,18 +811,36 @@ async fn open(
     // this actually creates min_idle database handles just now.
     // therefore, with_init() must not try to modify the database as otherwise
     // we easily get busy-errors (eg. table-creation, journal_mode etc. should be done on only one handle)
-    let mgr = r2d2_sqlite::SqliteConnectionManager::file(dbfile.as_ref())
-        .with_flags(open_flags)
-        .with_init(|c| {
-            c.execute_batch(&format!(
-                "PRAGMA secure_delete=on; PRAGMA busy_timeout = {};",
-                Duration::from_secs(10).as_millis()
-            ))?;
-            Ok(())
-        });
-    let pool = r2d2::Pool::builder()
-        .min_idle(Some(2))
-        .max_size(10)
+    // every connection to ":memory:" gets its own database,
+    // so in-memory databases are served by exactly one connection that is never recycled.
+    // A shared-cache database would allow more connections, but shared-cache locks
+    // fail with SQLITE_LOCKED at once instead of waiting for the busy_timeout.
+    // With a single connection, code holding a connection, eg. in with_conn(),
+    // must not wait for other database operations, they would wait for the connection.
+    let in_memory = dbfile.as_ref() == Path::new(IN_MEMORY_DBFILE);
+    let mgr = if in_memory {
+        r2d2_sqlite::SqliteConnectionManager::memory()
+    } else {
+        r2d2_sqlite::SqliteConnectionManager::file(dbfile.as_ref())
+    }
+    .with_flags(open_flags)
+    .with_init(|c| {
+        c.execute_batch(&format!(
+            "PRAGMA secure_delete=on; PRAGMA busy_timeout = {};",
+            Duration::from_secs(10).as_millis()
+        ))?;
+        Ok(())
+    });
+    let builder = if in_memory {
+        r2d2::Pool::builder()
+            .min_idle(Some(1))
+            .max_size(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+    } else {
+        r2d2::Pool::builder().min_idle(Some(2)).max_size(10)
+    };
+    let pool = builder
         .connection_timeout(Duration::from_secs(60))
         .build(mgr)
         .map_err(Error::ConnectionPool)?;
@@ -681,6 +848,9 @@ async fn open(
     {
         *sql.pool.write().await = Some(pool);
     }
+    if !in_memory {
+        *sql.open_dbfile.write().await = Some(OpenDbfile::register(dbfile.as_ref()));
+    }
 
     if !readonly {
         // journal_mode is persisted, it is sufficient to change it only for one handle.
@@ -1315,6 +1485,182 @@ async fn open(
             }
             sql.set_raw_config_int(context, "dbversion", 67).await?;
         }
+        if dbversion < 68 {
+            info!(context, "[migration] v68");
+            sql.execute(
+                "ALTER TABLE contacts ADD COLUMN last_seen INTEGER DEFAULT 0;",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 68).await?;
+        }
+
+        if dbversion < 69 {
+            info!(context, "[migration] v69");
+            sql.execute(
+                "ALTER TABLE msgs ADD COLUMN html TEXT DEFAULT '';",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 69).await?;
+        }
+
+        if dbversion < 70 {
+            info!(context, "[migration] v70");
+            sql.execute("ALTER TABLE msgs ADD COLUMN quoted_text TEXT;", paramsv![])
+                .await?;
+            sql.set_raw_config_int(context, "dbversion", 70).await?;
+        }
+
+        if dbversion < 71 {
+            info!(context, "[migration] v71");
+            sql.execute("ALTER TABLE msgs ADD COLUMN signature TEXT;", paramsv![])
+                .await?;
+            sql.execute("ALTER TABLE msgs ADD COLUMN footer TEXT;", paramsv![])
+                .await?;
+            sql.set_raw_config_int(context, "dbversion", 71).await?;
+        }
+        if dbversion < 72 {
+            info!(context, "[migration] v72");
+            sql.execute(
+                "CREATE TABLE geofences (\
+                 id INTEGER PRIMARY KEY AUTOINCREMENT, \
+                 contact_id INTEGER DEFAULT 0, \
+                 latitude REAL DEFAULT 0.0, \
+                 longitude REAL DEFAULT 0.0, \
+                 radius REAL DEFAULT 0.0, \
+                 direction INTEGER DEFAULT 0, \
+                 inside INTEGER DEFAULT -1, \
+                 timestamp INTEGER DEFAULT 0);",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE INDEX geofences_index1 ON geofences (contact_id);",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 72).await?;
+        }
+        if dbversion < 73 {
+            info!(context, "[migration] v73");
+            sql.execute(
+                "ALTER TABLE jobs ADD COLUMN priority INTEGER DEFAULT 1;",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 73).await?;
+        }
+        if dbversion < 74 {
+            info!(context, "[migration] v74");
+            sql.execute(
+                "CREATE TABLE blocked_domains (domain TEXT PRIMARY KEY);",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 74).await?;
+        }
+        if dbversion < 75 {
+            info!(context, "[migration] v75");
+            sql.execute(
+                "CREATE TABLE msgs_seen (
+                   msg_id INTEGER,
+                   contact_id INTEGER,
+                   timestamp_sent INTEGER DEFAULT 0,
+                   UNIQUE(msg_id, contact_id));",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 75).await?;
+        }
+        if dbversion < 76 {
+            info!(context, "[migration] v76");
+            sql.execute(
+                "CREATE TABLE acpeerstates_history (
+                   addr TEXT,
+                   fingerprint TEXT,
+                   key_type INTEGER DEFAULT 0,
+                   verified INTEGER DEFAULT 0,
+                   timestamp INTEGER DEFAULT 0,
+                   UNIQUE(addr, fingerprint));",
+                paramsv![],
+            )
+            .await?;
+            // seed the history with the keys known so far
+            sql.execute(
+                "INSERT OR IGNORE INTO acpeerstates_history (addr, fingerprint, key_type, timestamp) \
+                 SELECT addr, public_key_fingerprint, 1, last_seen_autocrypt FROM acpeerstates \
+                 WHERE public_key_fingerprint IS NOT NULL AND public_key_fingerprint!='';",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "INSERT OR IGNORE INTO acpeerstates_history (addr, fingerprint, key_type, timestamp) \
+                 SELECT addr, gossip_key_fingerprint, 0, gossip_timestamp FROM acpeerstates \
+                 WHERE gossip_key_fingerprint IS NOT NULL AND gossip_key_fingerprint!='';",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "UPDATE acpeerstates_history SET verified=1 \
+                 WHERE EXISTS (SELECT 1 FROM acpeerstates p \
+                 WHERE p.addr=acpeerstates_history.addr \
+                 AND p.verified_key_fingerprint=acpeerstates_history.fingerprint);",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 76).await?;
+        }
+        if dbversion < 77 {
+            info!(context, "[migration] v77");
+            sql.execute(
+                "CREATE TABLE contacts_addrs (
+                   contact_id INTEGER,
+                   addr TEXT COLLATE NOCASE,
+                   UNIQUE(addr));",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 77).await?;
+        }
+        if dbversion < 78 {
+            info!(context, "[migration] v78");
+            sql.execute(
+                "CREATE TABLE msgs_status_updates (
+                   id INTEGER PRIMARY KEY AUTOINCREMENT,
+                   msg_id INTEGER,
+                   update_item TEXT DEFAULT '');",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE INDEX msgs_status_updates_index1 ON msgs_status_updates (msg_id);",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 78).await?;
+        }
+        if dbversion < 79 {
+            info!(context, "[migration] v79");
+            sql.execute(
+                "ALTER TABLE chats_contacts ADD COLUMN role INTEGER DEFAULT 0;",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 79).await?;
+        }
+
+        if dbversion < 80 {
+            info!(context, "[migration] v80");
+            sql.execute(
+                "CREATE TABLE blobs (name TEXT PRIMARY KEY, sha256 TEXT NOT NULL);",
+                paramsv![],
+            )
+            .await?;
+            sql.execute("CREATE INDEX blobs_index1 ON blobs (sha256);", paramsv![])
+                .await?;
+            sql.set_raw_config_int(context, "dbversion", 80).await?;
+        }
 
         // (2) updates that require high-level objects
         // (the structure is complete now and all objects are usable)
@@ -1396,4 +1742,72 @@ mod test {
         assert!(!is_file_in_use(&files, Some(".txt"), "hello"));
         assert!(is_file_in_use(&files, Some("-suffix"), "world.txt-suffix"));
     }
+
+    #[async_std::test]
+    async fn test_housekeeping_deletes_orphaned_blobs() {
+        use crate::chat;
+        use crate::constants::Viewtype;
+        use crate::contact::Contact;
+        use crate::message::Message;
+        use crate::test_utils::TestContext;
+
+        let t = TestContext::new_alice().await;
+        let contact_id = Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, contact_id)
+            .await
+            .unwrap();
+        let referenced = t.ctx.get_blobdir().join("referenced.txt");
+        async_std::fs::write(&referenced, b"referenced")
+            .await
+            .unwrap();
+        let mut msg = Message::new(Viewtype::File);
+        msg.set_file(referenced.to_str().unwrap(), None);
+        chat::prepare_msg(&t.ctx, chat_id, &mut msg).await.unwrap();
+        let orphan = t.ctx.get_blobdir().join("orphan.txt");
+        async_std::fs::write(&orphan, b"orphan").await.unwrap();
+
+        // new files are kept, they may belong to messages being created
+        assert_eq!(t.ctx.housekeeping().await, HousekeepingStats::default());
+        assert!(orphan.exists().await);
+
+        let reclaimed = housekeeping_ex(&t.ctx, Duration::from_secs(0)).await;
+        assert_eq!(reclaimed.deleted_files, 1);
+        assert_eq!(reclaimed.deleted_bytes, 6);
+        assert!(!orphan.exists().await);
+        assert!(referenced.exists().await);
+    }
+
+    #[async_std::test]
+    async fn test_maybe_schedule_housekeeping() {
+        use crate::test_utils::TestContext;
+
+        let t = TestContext::new_alice().await;
+        t.ctx
+            .set_config(Config::HousekeepingInterval, Some("100"))
+            .await
+            .unwrap();
+        let t0 = time();
+
+        assert!(maybe_schedule_housekeeping(&t.ctx, t0).await);
+        assert!(job::action_exists(&t.ctx, Action::Housekeeping).await);
+        // no duplicate job while one is pending
+        assert!(!maybe_schedule_housekeeping(&t.ctx, t0).await);
+
+        housekeeping(&t.ctx).await;
+        job::kill_action(&t.ctx, Action::Housekeeping).await;
+        let last = t.ctx.get_config_int(Config::LastHousekeeping).await as i64;
+        assert!(last >= t0);
+
+        assert!(!maybe_schedule_housekeeping(&t.ctx, last + 99).await);
+        assert!(maybe_schedule_housekeeping(&t.ctx, last + 100).await);
+        job::kill_action(&t.ctx, Action::Housekeeping).await;
+
+        t.ctx
+            .set_config(Config::HousekeepingInterval, Some("0"))
+            .await
+            .unwrap();
+        assert!(!maybe_schedule_housekeeping(&t.ctx, last + 1000).await);
+    }
 }
@@ -216,6 +216,32 @@ pub enum StockMessage {
 
     #[strum(props(fallback = "You are invited to a video chat, click %1$s to join."))]
     VideochatInviteMsgBody = 83,
+
+    #[strum(props(fallback = "%1$d new messages", fallback_one = "%1$d new message"))]
+    NewMessages = 84,
+
+    #[strum(props(fallback = "Member %1$s is now admin."))]
+    MsgMemberAdmin = 85,
+
+    #[strum(props(fallback = "Member %1$s is no longer admin."))]
+    MsgMemberNoAdmin = 86,
+
+    #[strum(props(fallback = "Location streaming enabled until %1$s."))]
+    MsgLocationEnabledUntil = 87,
+}
+
+/// Plural form of a stock string.
+///
+/// Unlike the CLDR plural categories, the form is selected by the count only:
+/// `Zero` for a count of 0, `One` for a count of 1 and `Other` for all other counts,
+/// see [translated_plural]. Translations for each form are registered with
+/// [Context::set_stock_translation_plural].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, FromPrimitive, ToPrimitive)]
+#[repr(u8)]
+pub enum PluralForm {
+    Zero = 0,
+    One = 1,
+    Other = 2,
 }
 
 /*
@@ -229,30 +255,106 @@ impl StockMessage {
     fn fallback(self) -> &'static str {
         self.get_str("fallback").unwrap_or_default()
     }
+
+    /// Default untranslated string for a count of one, if it differs from [StockMessage::fallback].
+    fn fallback_one(self) -> Option<&'static str> {
+        self.get_str("fallback_one")
+    }
 }
 
-impl Context {
-    /// Set the stock string for the [StockMessage].
-    ///
-    pub async fn set_stock_translation(
-        &self,
-        id: StockMessage,
-        stockstring: String,
-    ) -> Result<(), Error> {
-        if stockstring.contains("%1") && !id.fallback().contains("%1") {
+/// Returns true if `text` contains the placeholder with the given number,
+/// in any of the `%1$s`, `%1$d` or `%1$@` forms.
+fn has_placeholder(text: &str, number: usize) -> bool {
+    ["s", "d", "@"]
+        .iter()
+        .any(|suffix| text.contains(&format!("%{}${}", number, suffix)))
+}
+
+/// Checks that the translation contains the same placeholders as the default.
+///
+/// Unknown placeholders are an error. Missing placeholders only lose
+/// the inserted names or numbers, so they are logged as a warning.
+/// If `count_implied` is set, the translation may omit placeholders
+/// as the count is implied by the plural form, eg. "No new messages".
+fn check_placeholders(
+    context: &Context,
+    id: StockMessage,
+    stockstring: &str,
+    count_implied: bool,
+) -> Result<(), Error> {
+    for number in 1..=2 {
+        let in_translation = has_placeholder(stockstring, number);
+        let in_default = has_placeholder(id.fallback(), number);
+        if in_translation && !in_default {
             bail!(
-                "translation {} contains invalid %1 placeholder, default is {}",
+                "translation {} contains invalid %{} placeholder, default is {}",
                 stockstring,
+                number,
                 id.fallback()
             );
         }
-        if stockstring.contains("%2") && !id.fallback().contains("%2") {
-            bail!(
-                "translation {} contains invalid %2 placeholder, default is {}",
+        if !in_translation && in_default && !count_implied {
+            warn!(
+                context,
+                "translation {} misses %{} placeholder, default is {}",
                 stockstring,
+                number,
                 id.fallback()
             );
         }
+    }
+    Ok(())
+}
+
+/// Returns the stock string for `id`, the translation set with
+/// [Context::set_stock_translation] or the English default.
+pub async fn translated(context: &Context, id: StockMessage) -> String {
+    context.stock_str(id).await.into_owned()
+}
+
+/// Returns the stock string for `id` in the plural form matching `count`.
+///
+/// If plural forms were registered with [Context::set_stock_translation_plural],
+/// `zero` is used for a count of 0 and `one` for a count of 1, if registered,
+/// and `other` otherwise. Without registered forms, the English rules apply.
+/// The placeholders are not replaced.
+pub async fn translated_plural(context: &Context, id: StockMessage, count: usize) -> String {
+    {
+        let plurals = context.translated_plurals.read().await;
+        let form = |form: PluralForm| plurals.get(&(id as usize, form));
+        let registered = match count {
+            0 => form(PluralForm::Zero).or_else(|| form(PluralForm::Other)),
+            1 => form(PluralForm::One).or_else(|| form(PluralForm::Other)),
+            _ => form(PluralForm::Other),
+        };
+        if let Some(text) = registered {
+            return text.clone();
+        }
+    }
+
+    let translated_singular = context
+        .translated_stockstrings
+        .read()
+        .await
+        .contains_key(&(id as usize));
+    match id.fallback_one() {
+        Some(one) if count == 1 && !translated_singular => one.to_string(),
+        _ => translated(context, id).await,
+    }
+}
+
+impl Context {
+    /// Set the stock string for the [StockMessage].
+    ///
+    /// Translations must not contain other `%1$s` and `%2$s` placeholders than the default.
+    /// Missing placeholders are accepted with a warning, the inserted names or numbers
+    /// are lost then.
+    pub async fn set_stock_translation(
+        &self,
+        id: StockMessage,
+        stockstring: String,
+    ) -> Result<(), Error> {
+        check_placeholders(self, id, &stockstring, false)?;
         self.translated_stockstrings
             .write()
             .await
@@ -260,6 +362,25 @@ impl Context {
         Ok(())
     }
 
+    /// Set a plural form of the stock string for the [StockMessage].
+    ///
+    /// The placeholders are checked as for [Context::set_stock_translation],
+    /// the `Zero` and `One` forms may omit them without a warning.
+    /// See [translated_plural] for how the forms are selected.
+    pub async fn set_stock_translation_plural(
+        &self,
+        id: StockMessage,
+        form: PluralForm,
+        stockstring: String,
+    ) -> Result<(), Error> {
+        check_placeholders(self, id, &stockstring, form != PluralForm::Other)?;
+        self.translated_plurals
+            .write()
+            .await
+            .insert((id as usize, form), stockstring);
+        Ok(())
+    }
+
     /// Return the stock string for the [StockMessage].
     ///
     /// Return a translation (if it was set with set_stock_translation before)
@@ -322,8 +443,9 @@ impl Context {
 
     /// Return some kind of stock message
     ///
-    /// If the `id` is [StockMessage::MsgAddMember] or
-    /// [StockMessage::MsgDelMember] then `param1` is considered to be the
+    /// If the `id` is [StockMessage::MsgAddMember], [StockMessage::MsgDelMember],
+    /// [StockMessage::MsgMemberAdmin] or [StockMessage::MsgMemberNoAdmin]
+    /// then `param1` is considered to be the
     /// contact address and will be replaced by that contact's display
     /// name.
     ///
@@ -344,7 +466,13 @@ impl Context {
         param2: impl AsRef<str>,
         from_id: u32,
     ) -> String {
-        let insert1 = if id == StockMessage::MsgAddMember || id == StockMessage::MsgDelMember {
+        let insert1 = if matches!(
+            id,
+            StockMessage::MsgAddMember
+                | StockMessage::MsgDelMember
+                | StockMessage::MsgMemberAdmin
+                | StockMessage::MsgMemberNoAdmin
+        ) {
             let contact_id =
                 Contact::lookup_id_by_addr(self, param1.as_ref(), Origin::Unknown).await;
             if contact_id != 0 {
@@ -425,6 +553,7 @@ mod tests {
     use crate::constants::DC_CONTACT_ID_SELF;
 
     use crate::chatlist::Chatlist;
+    use crate::events::EventType;
     use num_traits::ToPrimitive;
 
     #[test]
@@ -463,6 +592,150 @@ mod tests {
             .is_err());
     }
 
+    #[async_std::test]
+    async fn test_set_stock_translation_placeholders() {
+        let t = TestContext::new().await;
+        // a missing placeholder is accepted with a warning
+        let emitter = t.ctx.get_event_emitter();
+        t.ctx
+            .set_stock_translation(
+                StockMessage::MsgAddMember,
+                "Mitglied hinzugefügt.".to_string(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            translated(&t.ctx, StockMessage::MsgAddMember).await,
+            "Mitglied hinzugefügt."
+        );
+        let mut warned = false;
+        while let Ok(event) = emitter.try_recv() {
+            if let EventType::Warning(msg) = event.typ {
+                warned |= msg.contains("misses %1 placeholder");
+            }
+        }
+        assert!(warned);
+
+        t.ctx
+            .set_stock_translation(
+                StockMessage::MsgAddMember,
+                "Mitglied %1$s hinzugefügt.".to_string(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            translated(&t.ctx, StockMessage::MsgAddMember).await,
+            "Mitglied %1$s hinzugefügt."
+        );
+        assert_eq!(
+            t.ctx
+                .stock_string_repl_str(StockMessage::MsgAddMember, "Foo")
+                .await,
+            "Mitglied Foo hinzugefügt."
+        );
+
+        // the order of placeholders may change
+        t.ctx
+            .set_stock_translation(StockMessage::MsgActionByUser, "%2$s: %1$s".to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            t.ctx
+                .stock_string_repl_str2(StockMessage::MsgActionByUser, "Foo", "Bar")
+                .await,
+            "Bar: Foo"
+        );
+    }
+
+    #[async_std::test]
+    async fn test_translated_plural() {
+        let t = TestContext::new().await;
+        assert_eq!(
+            translated_plural(&t.ctx, StockMessage::NewMessages, 1).await,
+            "%1$d new message"
+        );
+        assert_eq!(
+            translated_plural(&t.ctx, StockMessage::NewMessages, 5).await,
+            "%1$d new messages"
+        );
+        assert_eq!(
+            translated_plural(&t.ctx, StockMessage::NewMessages, 0).await,
+            "%1$d new messages"
+        );
+        // strings without plural forms are the same for all counts
+        assert_eq!(
+            translated_plural(&t.ctx, StockMessage::NoMessages, 1).await,
+            "No messages."
+        );
+
+        t.ctx
+            .set_stock_translation_plural(
+                StockMessage::NewMessages,
+                PluralForm::One,
+                "%1$d neue Nachricht".to_string(),
+            )
+            .await
+            .unwrap();
+        t.ctx
+            .set_stock_translation_plural(
+                StockMessage::NewMessages,
+                PluralForm::Other,
+                "%1$d neue Nachrichten".to_string(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            translated_plural(&t.ctx, StockMessage::NewMessages, 1).await,
+            "%1$d neue Nachricht"
+        );
+        assert_eq!(
+            translated_plural(&t.ctx, StockMessage::NewMessages, 5).await,
+            "%1$d neue Nachrichten"
+        );
+        assert_eq!(
+            translated_plural(&t.ctx, StockMessage::NewMessages, 0).await,
+            "%1$d neue Nachrichten"
+        );
+
+        // the zero and one forms may omit the count
+        t.ctx
+            .set_stock_translation_plural(
+                StockMessage::NewMessages,
+                PluralForm::Zero,
+                "Keine neuen Nachrichten".to_string(),
+            )
+            .await
+            .unwrap();
+        t.ctx
+            .set_stock_translation_plural(
+                StockMessage::NewMessages,
+                PluralForm::One,
+                "Eine neue Nachricht".to_string(),
+            )
+            .await
+            .unwrap();
+        t.ctx
+            .set_stock_translation_plural(
+                StockMessage::NewMessages,
+                PluralForm::Other,
+                "%1$d neue Nachrichten".to_string(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            translated_plural(&t.ctx, StockMessage::NewMessages, 0).await,
+            "Keine neuen Nachrichten"
+        );
+        assert_eq!(
+            translated_plural(&t.ctx, StockMessage::NewMessages, 1).await,
+            "Eine neue Nachricht"
+        );
+        assert_eq!(
+            translated_plural(&t.ctx, StockMessage::NewMessages, 5).await,
+            "%1$d neue Nachrichten"
+        );
+    }
+
     #[async_std::test]
     async fn test_stock_str() {
         let t = TestContext::new().await;
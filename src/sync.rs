@@ -0,0 +1,379 @@
+//! # Multi-device synchronization
+//!
+//! State changes done by the user, as reading, deleting or archiving,
+//! are sent as hidden messages to the self-chat. Other devices of the
+//! same account receive them through the BCC-to-self copy and apply the
+//! changes in [crate::dc_receive_imf].
+//!
+//! The changes are sent as `multi-device-sync.json` attachment.
+
+use serde::{Deserialize, Serialize};
+
+use crate::chat::{self, Chat, ChatId, ChatVisibility};
+use crate::config::Config;
+use crate::constants::*;
+use crate::contact::{Contact, Origin};
+use crate::context::Context;
+use crate::dc_tools::time;
+use crate::error::{bail, Result};
+use crate::events::EventType;
+use crate::message::{self, Message, MessageState, MsgId};
+use crate::mimeparser::SystemMessage;
+use crate::param::*;
+
+/// Filename of the attachment carrying the sync items.
+pub(crate) const SYNC_FILENAME: &str = "multi-device-sync.json";
+
+/// Identifies a chat on all devices.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum ChatIdentifier {
+    /// One-to-one chat with the contact of the given address.
+    Contact(String),
+
+    /// Group chat with the given group ID.
+    Group(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum SyncData {
+    /// Messages were read.
+    MarkseenMsgs { rfc724_mids: Vec<String> },
+
+    /// Messages were deleted.
+    DeleteMsgs { rfc724_mids: Vec<String> },
+
+    /// A chat was archived, unarchived or pinned.
+    ChatVisibility {
+        chat: ChatIdentifier,
+        visibility: ChatVisibility,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SyncItem {
+    /// Time of the change, newer changes win on conflicts.
+    pub timestamp: i64,
+
+    pub data: SyncData,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SyncItems {
+    pub items: Vec<SyncItem>,
+}
+
+impl SyncItems {
+    pub(crate) fn parse(data: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(data)?)
+    }
+}
+
+impl ChatIdentifier {
+    /// Returns the identifier of a chat, `None` for chats that cannot be
+    /// identified on other devices.
+    pub(crate) async fn from_chat_id(context: &Context, chat_id: ChatId) -> Option<Self> {
+        let chat = Chat::load_from_db(context, chat_id).await.ok()?;
+        match chat.typ {
+            Chattype::Single => {
+                let contact_id = *chat::get_chat_contacts(context, chat_id).await.first()?;
+                let contact = Contact::get_by_id(context, contact_id).await.ok()?;
+                Some(ChatIdentifier::Contact(contact.get_addr().to_string()))
+            }
+            Chattype::Group | Chattype::VerifiedGroup if !chat.grpid.is_empty() => {
+                Some(ChatIdentifier::Group(chat.grpid))
+            }
+            _ => None,
+        }
+    }
+
+    async fn lookup(&self, context: &Context) -> Option<ChatId> {
+        match self {
+            ChatIdentifier::Contact(addr) => {
+                let contact_id = Contact::lookup_id_by_addr(context, addr, Origin::Unknown).await;
+                if contact_id == 0 {
+                    return None;
+                }
+                let (chat_id, _) = chat::lookup_by_contact_id(context, contact_id).await.ok()?;
+                Some(chat_id)
+            }
+            ChatIdentifier::Group(grpid) => {
+                let (chat_id, _, _) = chat::get_chat_id_by_grpid(context, grpid).await.ok()?;
+                Some(chat_id)
+            }
+        }
+    }
+}
+
+/// Informs the other devices about a change.
+///
+/// Nothing is sent if BCC-to-self is disabled, as the other devices
+/// would not receive the message anyway.
+pub(crate) async fn send_sync_msg(context: &Context, data: SyncData) -> Result<Option<MsgId>> {
+    if !context.get_config_bool(Config::BccSelf).await || !context.is_configured().await {
+        return Ok(None);
+    }
+    match &data {
+        SyncData::MarkseenMsgs { rfc724_mids } | SyncData::DeleteMsgs { rfc724_mids }
+            if rfc724_mids.is_empty() =>
+        {
+            return Ok(None)
+        }
+        _ => {}
+    }
+
+    let items = SyncItems {
+        items: vec![SyncItem {
+            timestamp: time(),
+            data,
+        }],
+    };
+    let chat_id = chat::create_by_contact_id(context, DC_CONTACT_ID_SELF).await?;
+    let mut msg = Message::new(Viewtype::Text);
+    msg.hidden = true;
+    msg.param.set_cmd(SystemMessage::MultiDeviceSync);
+    msg.param.set(Param::Arg, serde_json::to_string(&items)?);
+    let msg_id = chat::send_msg(context, chat_id, &mut msg).await?;
+    Ok(Some(msg_id))
+}
+
+/// Applies the changes received from another device.
+pub(crate) async fn apply_sync_items(context: &Context, sync_items: &SyncItems) {
+    for item in &sync_items.items {
+        if let Err(err) = apply_sync_item(context, item).await {
+            warn!(context, "Cannot apply sync item {:?}: {}", item, err);
+        }
+    }
+}
+
+async fn apply_sync_item(context: &Context, item: &SyncItem) -> Result<()> {
+    match &item.data {
+        SyncData::MarkseenMsgs { rfc724_mids } => {
+            for msg_id in lookup_msgs(context, rfc724_mids).await? {
+                let updated = context
+                    .sql
+                    .execute(
+                        "UPDATE msgs SET state=? WHERE id=? AND state IN (?, ?);",
+                        paramsv![
+                            MessageState::InSeen,
+                            msg_id,
+                            MessageState::InFresh,
+                            MessageState::InNoticed
+                        ],
+                    )
+                    .await?;
+                if updated > 0 {
+                    msg_id.start_ephemeral_timer(context).await?;
+                    context.emit_event(EventType::MsgsChanged {
+                        chat_id: ChatId::new(0),
+                        msg_id,
+                    });
+                }
+            }
+        }
+        SyncData::DeleteMsgs { rfc724_mids } => {
+            let msg_ids = lookup_msgs(context, rfc724_mids).await?;
+            // the other device already deletes the messages on the server
+            message::delete_msgs_ex(context, &msg_ids, false).await;
+        }
+        SyncData::ChatVisibility { chat, visibility } => match chat.lookup(context).await {
+            Some(chat_id) => {
+                chat_id
+                    .set_visibility_ex(context, *visibility, item.timestamp)
+                    .await?;
+            }
+            None => bail!("chat {:?} not found", chat),
+        },
+    }
+    Ok(())
+}
+
+async fn lookup_msgs(context: &Context, rfc724_mids: &[String]) -> Result<Vec<MsgId>> {
+    let mut msg_ids = Vec::new();
+    for rfc724_mid in rfc724_mids {
+        if let Some((_, _, msg_id)) = message::rfc724_mid_exists(context, rfc724_mid).await? {
+            msg_ids.push(msg_id);
+        }
+    }
+    Ok(msg_ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dc_receive_imf::dc_receive_imf;
+    use crate::mimefactory::MimeFactory;
+    use crate::test_utils::*;
+
+    async fn receive_from_bob(t: &TestContext) -> MsgId {
+        dc_receive_imf(
+            &t.ctx,
+            b"From: Bob <bob@example.org>\n\
+                 To: alice@example.com\n\
+                 Subject: Chat: hi\n\
+                 Message-ID: <bob.1@example.org>\n\
+                 Chat-Version: 1.0\n\
+                 Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                 \n\
+                 hi\n",
+            "INBOX",
+            1,
+            false,
+        )
+        .await
+        .unwrap();
+        let (_, _, msg_id) = message::rfc724_mid_exists(&t.ctx, "bob.1@example.org")
+            .await
+            .unwrap()
+            .unwrap();
+        msg_id
+    }
+
+    /// Renders the last sync message sent by `t`.
+    async fn last_sync_msg(t: &TestContext) -> Vec<u8> {
+        let msg_id: MsgId = t
+            .ctx
+            .sql
+            .query_get_value(
+                &t.ctx,
+                "SELECT id FROM msgs WHERE hidden=1 AND param LIKE ? ORDER BY id DESC LIMIT 1;",
+                paramsv![format!("%S={}%", SystemMessage::MultiDeviceSync as i32)],
+            )
+            .await
+            .unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        MimeFactory::from_msg(&t.ctx, &msg, false)
+            .await
+            .unwrap()
+            .render()
+            .await
+            .unwrap()
+            .message
+    }
+
+    #[async_std::test]
+    async fn test_sync_markseen() {
+        let alice1 = TestContext::new_alice().await;
+        let alice2 = TestContext::new_alice().await;
+        alice1
+            .ctx
+            .set_config(Config::BccSelf, Some("1"))
+            .await
+            .unwrap();
+        let msg_id1 = receive_from_bob(&alice1).await;
+        let msg_id2 = receive_from_bob(&alice2).await;
+
+        chat::create_by_msg_id(&alice1.ctx, msg_id1).await.unwrap();
+        message::markseen_msgs(&alice1.ctx, vec![msg_id1]).await;
+        let sync_msg = last_sync_msg(&alice1).await;
+
+        dc_receive_imf(&alice2.ctx, &sync_msg, "INBOX", 2, false)
+            .await
+            .unwrap();
+        let msg = Message::load_from_db(&alice2.ctx, msg_id2).await.unwrap();
+        assert_eq!(msg.state, MessageState::InSeen);
+
+        // the sync message itself is not shown
+        let self_chat = chat::create_by_contact_id(&alice2.ctx, DC_CONTACT_ID_SELF)
+            .await
+            .unwrap();
+        assert!(chat::get_chat_msgs(&alice2.ctx, self_chat, 0, None)
+            .await
+            .is_empty());
+    }
+
+    #[async_std::test]
+    async fn test_sync_not_from_self() {
+        let t = TestContext::new_alice().await;
+        let msg_id = receive_from_bob(&t).await;
+
+        let items = SyncItems {
+            items: vec![SyncItem {
+                timestamp: time(),
+                data: SyncData::DeleteMsgs {
+                    rfc724_mids: vec!["bob.1@example.org".to_string()],
+                },
+            }],
+        };
+        let raw = format!(
+            "From: Bob <bob@example.org>\n\
+             To: alice@example.com\n\
+             Subject: sync\n\
+             Message-ID: <bob.2@example.org>\n\
+             Chat-Version: 1.0\n\
+             Date: Sun, 22 Mar 2020 22:40:57 +0000\n\
+             Content-Type: multipart/mixed; boundary=\"xxx\"\n\
+             \n\
+             --xxx\n\
+             Content-Type: text/plain\n\
+             \n\
+             sync\n\
+             --xxx\n\
+             Content-Type: application/json\n\
+             Content-Disposition: attachment; filename=\"{}\"\n\
+             \n\
+             {}\n\
+             --xxx--\n",
+            SYNC_FILENAME,
+            serde_json::to_string(&items).unwrap()
+        );
+        dc_receive_imf(&t.ctx, raw.as_bytes(), "INBOX", 2, false)
+            .await
+            .unwrap();
+
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert!(!msg.chat_id.is_trash());
+    }
+
+    #[async_std::test]
+    async fn test_sync_visibility_conflict() {
+        let t = TestContext::new_alice().await;
+        let msg_id = receive_from_bob(&t).await;
+        let chat_id = chat::create_by_msg_id(&t.ctx, msg_id).await.unwrap();
+        let chat = ChatIdentifier::from_chat_id(&t.ctx, chat_id).await.unwrap();
+        assert_eq!(chat, ChatIdentifier::Contact("bob@example.org".to_string()));
+
+        let visibility_item = |timestamp, visibility| SyncItem {
+            timestamp,
+            data: SyncData::ChatVisibility {
+                chat: chat.clone(),
+                visibility,
+            },
+        };
+        apply_sync_items(
+            &t.ctx,
+            &SyncItems {
+                items: vec![
+                    visibility_item(1000, ChatVisibility::Archived),
+                    // older change is ignored
+                    visibility_item(900, ChatVisibility::Pinned),
+                ],
+            },
+        )
+        .await;
+        let chat = Chat::load_from_db(&t.ctx, chat_id).await.unwrap();
+        assert_eq!(chat.get_visibility(), ChatVisibility::Archived);
+
+        // local changes are newer than the synced ones
+        chat_id
+            .set_visibility(&t.ctx, ChatVisibility::Normal)
+            .await
+            .unwrap();
+        let chat = Chat::load_from_db(&t.ctx, chat_id).await.unwrap();
+        assert_eq!(chat.get_visibility(), ChatVisibility::Normal);
+    }
+
+    #[test]
+    fn test_parse_sync_items() {
+        let items = SyncItems {
+            items: vec![SyncItem {
+                timestamp: 1234,
+                data: SyncData::MarkseenMsgs {
+                    rfc724_mids: vec!["a@example.org".to_string()],
+                },
+            }],
+        };
+        let json = serde_json::to_string(&items).unwrap();
+        assert_eq!(SyncItems::parse(json.as_bytes()).unwrap(), items);
+        assert!(SyncItems::parse(b"not json").is_err());
+    }
+}
@@ -4,10 +4,16 @@
 
 use tempfile::{tempdir, TempDir};
 
+use crate::chat;
 use crate::config::Config;
+use crate::constants::Viewtype;
+use crate::contact::Contact;
 use crate::context::Context;
+use crate::dc_receive_imf::dc_receive_imf;
 use crate::dc_tools::EmailAddress;
 use crate::key::{self, DcKey};
+use crate::message::{self, Message};
+use crate::mimefactory::MimeFactory;
 
 /// A Context and temporary directory.
 ///
@@ -78,6 +84,98 @@ impl TestContext {
     }
 }
 
+/// Create an in-memory [Context] configured with the address and key of `keypair`.
+///
+/// [Context]: crate::context::Context
+pub(crate) async fn configured_in_memory_context(keypair: key::KeyPair) -> Context {
+    let ctx = Context::new_in_memory("FakeOS".into(), rand::random())
+        .await
+        .unwrap();
+    let addr = keypair.addr.to_string();
+    ctx.set_config(Config::Addr, Some(&addr)).await.unwrap();
+    ctx.set_config(Config::ConfiguredAddr, Some(&addr))
+        .await
+        .unwrap();
+    ctx.set_config(Config::Configured, Some("1")).await.unwrap();
+    key::store_self_keypair(&ctx, &keypair, key::KeyPairUse::Default)
+        .await
+        .expect("Failed to save key");
+    ctx
+}
+
+/// Creates a chat with `to_addr` in `context` and prepares a text message in it.
+///
+/// The message is not sent, use [render_msg] or [send_and_receive] to get it to the recipient.
+pub(crate) async fn prepare_text_msg(context: &Context, to_addr: &str, text: &str) -> Message {
+    let contact_id = Contact::create(context, "", to_addr).await.unwrap();
+    let chat_id = chat::create_by_contact_id(context, contact_id)
+        .await
+        .unwrap();
+    let mut msg = Message::new(Viewtype::Text);
+    msg.set_text(Some(text.to_string()));
+    let msg_id = chat::prepare_msg(context, chat_id, &mut msg).await.unwrap();
+    Message::load_from_db(context, msg_id).await.unwrap()
+}
+
+/// Renders `msg` as it is sent by `context`.
+pub(crate) async fn render_msg(context: &Context, msg: &Message) -> Vec<u8> {
+    MimeFactory::from_msg(context, msg, false)
+        .await
+        .unwrap()
+        .render()
+        .await
+        .unwrap()
+        .message
+}
+
+/// Returns the message with the Message-ID `rfc724_mid` received by `context`.
+pub(crate) async fn get_received_msg(context: &Context, rfc724_mid: &str) -> Message {
+    let (_, _, msg_id) = message::rfc724_mid_exists(context, rfc724_mid)
+        .await
+        .unwrap()
+        .expect("message was not received");
+    Message::load_from_db(context, msg_id).await.unwrap()
+}
+
+/// Renders `msg` sent by `from` and receives it in `to`,
+/// returns the received message.
+pub(crate) async fn send_and_receive(from: &Context, msg: &Message, to: &Context) -> Message {
+    let imf = render_msg(from, msg).await;
+    dc_receive_imf(to, &imf, "INBOX", 1, false).await.unwrap();
+    get_received_msg(to, &msg.rfc724_mid).await
+}
+
+/// Receives a chat message from `from` in `context` and accepts its chat,
+/// returns the received message.
+///
+/// `headers` are added to the message header, each terminated by a newline.
+pub(crate) async fn receive_chat_msg(
+    context: &Context,
+    from: &str,
+    rfc724_mid: &str,
+    text: &str,
+    headers: &str,
+) -> Message {
+    let imf = format!(
+        "From: {}\n\
+         To: alice@example.com\n\
+         Subject: Chat: hello\n\
+         Chat-Version: 1.0\n\
+         {}\
+         Message-ID: <{}>\n\
+         Date: Sun, 22 Mar 2020 22:37:56 +0000\n\
+         \n\
+         {}\n",
+        from, headers, rfc724_mid, text
+    );
+    dc_receive_imf(context, imf.as_bytes(), "INBOX", 1, false)
+        .await
+        .unwrap();
+    let msg = get_received_msg(context, rfc724_mid).await;
+    chat::create_by_msg_id(context, msg.id).await.unwrap();
+    Message::load_from_db(context, msg.id).await.unwrap()
+}
+
 /// Load a pre-generated keypair for alice@example.com from disk.
 ///
 /// This saves CPU cycles by avoiding having to generate a key.
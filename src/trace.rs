@@ -0,0 +1,136 @@
+//! # Protocol tracing
+//!
+//! If [Config::DebugTraceProtocol] is set, all lines sent to and received from
+//! the IMAP and SMTP servers are logged at debug level. Credentials are masked.
+//!
+//! [Config::DebugTraceProtocol]: crate::config::Config::DebugTraceProtocol
+
+use std::pin::Pin;
+use std::task::{Context as TaskContext, Poll};
+
+use async_std::io::{self, Read, Write};
+
+use crate::context::Context;
+use crate::log::{emit_log, LogLevel};
+
+/// Replacement for masked credentials.
+pub(crate) const MASK: &str = "***";
+
+/// Protocol specific handling of the traced lines.
+pub(crate) trait TraceProtocol {
+    /// Prefix of the logged lines, eg. `IMAP`.
+    const NAME: &'static str;
+
+    /// Returns the line sent by the client with credentials masked,
+    /// `None` if the line is not logged at all.
+    fn sent(&mut self, line: &str) -> Option<String>;
+
+    /// Updates the state with a line received from the server.
+    fn received(&mut self, line: &str);
+}
+
+/// Stream wrapper logging every protocol line read from or written to the inner stream.
+#[derive(Debug)]
+pub(crate) struct TraceStream<S, P> {
+    inner: S,
+    context: Context,
+    protocol: P,
+
+    /// Bytes of incomplete lines.
+    read_buf: Vec<u8>,
+    write_buf: Vec<u8>,
+}
+
+impl<S, P: TraceProtocol> TraceStream<S, P> {
+    pub fn new(context: Context, inner: S, protocol: P) -> Self {
+        TraceStream {
+            inner,
+            context,
+            protocol,
+            read_buf: Vec::new(),
+            write_buf: Vec::new(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn trace(&self, direction: &str, text: &str) {
+        if self.context.is_log_enabled(LogLevel::Debug) {
+            emit_log(
+                &self.context,
+                LogLevel::Debug,
+                file!(),
+                line!(),
+                format!("{} {} {}", P::NAME, direction, text),
+                Vec::new(),
+            );
+        }
+    }
+
+    fn received(&mut self, data: &[u8]) {
+        self.read_buf.extend_from_slice(data);
+        while let Some(pos) = self.read_buf.iter().position(|&b| b == b'\n') {
+            let bytes: Vec<u8> = self.read_buf.drain(..=pos).collect();
+            let text = String::from_utf8_lossy(&bytes);
+            let text = text.trim_end_matches(&['\r', '\n'][..]);
+            self.protocol.received(text);
+            self.trace("<<", text);
+        }
+    }
+
+    fn sent(&mut self, data: &[u8]) {
+        self.write_buf.extend_from_slice(data);
+        while let Some(pos) = self.write_buf.iter().position(|&b| b == b'\n') {
+            let bytes: Vec<u8> = self.write_buf.drain(..=pos).collect();
+            let text = String::from_utf8_lossy(&bytes);
+            let text = text.trim_end_matches(&['\r', '\n'][..]);
+            if let Some(text) = self.protocol.sent(text) {
+                self.trace(">>", &text);
+            }
+        }
+    }
+}
+
+impl<S: Read + Unpin, P: TraceProtocol + Unpin> Read for TraceStream<S, P> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        let res = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(n)) = res {
+            this.received(buf.get(..n).unwrap_or_default());
+        }
+        res
+    }
+}
+
+impl<S: Write + Unpin, P: TraceProtocol + Unpin> Write for TraceStream<S, P> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        let res = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = res {
+            this.sent(buf.get(..n).unwrap_or_default());
+        }
+        res
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
@@ -0,0 +1,458 @@
+//! # Webxdc apps
+//!
+//! Webxdc apps are sent as `.xdc` attachments: zip archives containing
+//! an `index.html` and optionally a `manifest.toml` with the name of the app.
+//!
+//! The state of an app instance is shared by status updates:
+//! JSON objects sent as hidden messages to the chat of the instance,
+//! as `webxdc-status-update.json` attachment referencing the instance.
+//! All devices store the updates with an increasing serial,
+//! so apps can fetch only the updates they have not seen yet.
+
+use std::io::{Cursor, Read};
+
+use async_std::path::Path;
+use serde::{Deserialize, Serialize};
+
+use crate::chat::{self, ChatId};
+use crate::constants::Viewtype;
+use crate::context::Context;
+use crate::error::{bail, ensure, format_err, Result};
+use crate::events::EventType;
+use crate::message::{self, Message, MsgId};
+use crate::mimeparser::SystemMessage;
+use crate::param::*;
+
+/// File name suffix of webxdc apps.
+const WEBXDC_SUFFIX: &str = ".xdc";
+
+/// Filename of the attachment carrying status updates.
+pub(crate) const STATUS_UPDATE_FILENAME: &str = "webxdc-status-update.json";
+
+/// Icons looked up in the archive, the first one found is used.
+const WEBXDC_ICONS: [&str; 2] = ["icon.png", "icon.jpg"];
+
+/// Information about a webxdc app, see [Message::get_webxdc_info].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebxdcInfo {
+    /// Name of the app as given in the manifest,
+    /// the file name of the attachment if the manifest has no name.
+    pub name: String,
+
+    /// Path of the icon inside the archive, if there is one.
+    pub icon: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct WebxdcManifest {
+    name: Option<String>,
+}
+
+impl WebxdcInfo {
+    /// Parses a webxdc archive.
+    ///
+    /// Fails if `data` is no zip archive, has no `index.html`
+    /// or has a malformed `manifest.toml`.
+    pub(crate) fn parse(data: &[u8]) -> Result<Self> {
+        let mut archive = zip::ZipArchive::new(Cursor::new(data))?;
+        ensure!(
+            archive.by_name("index.html").is_ok(),
+            "webxdc has no index.html"
+        );
+
+        let manifest = match archive.by_name("manifest.toml") {
+            Ok(mut file) => {
+                let mut manifest = String::new();
+                file.read_to_string(&mut manifest)?;
+                toml::from_str::<WebxdcManifest>(&manifest)?
+            }
+            Err(_) => WebxdcManifest::default(),
+        };
+        let icon = WEBXDC_ICONS
+            .iter()
+            .find(|icon| archive.by_name(icon).is_ok())
+            .map(|icon| icon.to_string());
+
+        Ok(WebxdcInfo {
+            name: manifest.name.unwrap_or_default(),
+            icon,
+        })
+    }
+}
+
+/// Returns true if the attachment `filename` is a valid webxdc app.
+///
+/// Only files ending with `.xdc` are checked, other zip archives stay files.
+pub(crate) fn is_webxdc(filename: &str, data: &[u8]) -> bool {
+    filename.to_lowercase().ends_with(WEBXDC_SUFFIX) && WebxdcInfo::parse(data).is_ok()
+}
+
+/// Status updates as sent in the [STATUS_UPDATE_FILENAME] attachment.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct StatusUpdates {
+    /// Message-ID of the app instance the updates belong to.
+    pub instance: String,
+
+    pub updates: Vec<StatusUpdateItem>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct StatusUpdateItem {
+    pub payload: serde_json::Value,
+}
+
+impl StatusUpdates {
+    pub(crate) fn parse(data: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(data)?)
+    }
+}
+
+/// A stored status update, see [Context::get_webxdc_status_updates].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebxdcStatusUpdate {
+    /// Serial of the update, increasing with every update stored on this device.
+    pub serial: u32,
+
+    /// The update as given to [Context::send_webxdc_status_update].
+    pub payload: serde_json::Value,
+}
+
+impl Context {
+    /// Sends a status update for the webxdc app sent with `instance_msg_id`.
+    ///
+    /// `update` must be a JSON value. It is stored locally,
+    /// emitting [EventType::WebxdcStatusUpdate], and sent as a hidden message
+    /// to all members of the chat.
+    pub async fn send_webxdc_status_update(
+        &self,
+        instance_msg_id: MsgId,
+        update: &str,
+    ) -> Result<MsgId> {
+        let instance = Message::load_from_db(self, instance_msg_id).await?;
+        ensure!(
+            instance.viewtype == Viewtype::Webxdc,
+            "Message {} is no webxdc app",
+            instance_msg_id
+        );
+        let payload: serde_json::Value = serde_json::from_str(update)?;
+        self.add_webxdc_status_update(instance_msg_id, &payload)
+            .await?;
+
+        let status_updates = StatusUpdates {
+            instance: instance.rfc724_mid.clone(),
+            updates: vec![StatusUpdateItem { payload }],
+        };
+        let mut msg = Message::new(Viewtype::Text);
+        msg.hidden = true;
+        msg.param.set_cmd(SystemMessage::WebxdcStatusUpdate);
+        msg.param
+            .set(Param::Arg, serde_json::to_string(&status_updates)?);
+        chat::send_msg(self, instance.chat_id, &mut msg).await
+    }
+
+    /// Returns the status updates of a webxdc app with a serial larger than `last_known_serial`.
+    ///
+    /// Pass 0 to get all updates, pass the largest serial seen so far
+    /// to get only the updates received since then.
+    pub async fn get_webxdc_status_updates(
+        &self,
+        instance_msg_id: MsgId,
+        last_known_serial: u32,
+    ) -> Result<Vec<WebxdcStatusUpdate>> {
+        let updates = self
+            .sql
+            .query_map(
+                "SELECT id, update_item FROM msgs_status_updates WHERE msg_id=? AND id>? ORDER BY id;",
+                paramsv![instance_msg_id, last_known_serial],
+                |row| Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?)),
+                |rows| {
+                    let mut updates = Vec::new();
+                    for row in rows {
+                        let (serial, item) = row?;
+                        match serde_json::from_str(&item) {
+                            Ok(payload) => updates.push(WebxdcStatusUpdate { serial, payload }),
+                            Err(err) => warn!(self, "Cannot parse status update {}: {}", serial, err),
+                        }
+                    }
+                    Ok(updates)
+                },
+            )
+            .await?;
+        Ok(updates)
+    }
+
+    /// Stores a status update and emits [EventType::WebxdcStatusUpdate].
+    async fn add_webxdc_status_update(
+        &self,
+        instance_msg_id: MsgId,
+        payload: &serde_json::Value,
+    ) -> Result<u32> {
+        let item = serde_json::to_string(payload)?;
+        let serial = self
+            .sql
+            .with_conn(move |conn| {
+                conn.execute(
+                    "INSERT INTO msgs_status_updates (msg_id, update_item) VALUES(?, ?);",
+                    paramsv![instance_msg_id, item],
+                )?;
+                Ok(conn.last_insert_rowid() as u32)
+            })
+            .await?;
+        self.emit_event(EventType::WebxdcStatusUpdate {
+            msg_id: instance_msg_id,
+            status_update_serial: serial,
+        });
+        Ok(serial)
+    }
+
+    /// Stores status updates received in `chat_id`.
+    ///
+    /// Updates are only accepted for app instances in the same chat,
+    /// so that chat members cannot change apps of other chats.
+    pub(crate) async fn receive_webxdc_status_updates(
+        &self,
+        chat_id: ChatId,
+        status_updates: &StatusUpdates,
+    ) -> Result<()> {
+        let instance_msg_id =
+            match message::rfc724_mid_exists(self, &status_updates.instance).await? {
+                Some((_, _, msg_id)) => msg_id,
+                None => bail!("webxdc instance {} not found", status_updates.instance),
+            };
+        let instance = Message::load_from_db(self, instance_msg_id).await?;
+        ensure!(
+            instance.viewtype == Viewtype::Webxdc,
+            "Message {} is no webxdc app",
+            instance_msg_id
+        );
+        ensure!(
+            instance.chat_id == chat_id,
+            "Status update for webxdc app {} received in another chat",
+            instance_msg_id
+        );
+        for item in &status_updates.updates {
+            self.add_webxdc_status_update(instance_msg_id, &item.payload)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+impl Message {
+    /// Returns name and icon of the webxdc app sent with the message.
+    pub async fn get_webxdc_info(&self, context: &Context) -> Result<WebxdcInfo> {
+        ensure!(self.viewtype == Viewtype::Webxdc, "No webxdc message");
+        let path = self
+            .get_file(context)
+            .ok_or_else(|| format_err!("No webxdc file"))?;
+        let data = async_std::fs::read(&path).await?;
+        let mut info = WebxdcInfo::parse(&data)?;
+        if info.name.trim().is_empty() {
+            info.name = Path::new(&self.get_filename().unwrap_or_default())
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default();
+        }
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use crate::contact::Contact;
+    use crate::dc_receive_imf::dc_receive_imf;
+    use crate::test_utils::{get_received_msg, send_and_receive, TestContext};
+
+    fn create_archive(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in files {
+            zip.start_file(*name, zip::write::FileOptions::default())
+                .unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    async fn receive_attachment(t: &TestContext, filename: &str, data: &[u8]) -> Message {
+        let mail = format!(
+            "From: Bob <bob@example.net>\n\
+             To: alice@example.com\n\
+             Subject: Chat: app\n\
+             Chat-Version: 1.0\n\
+             Message-ID: <{}@example.net>\n\
+             Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+             Content-Type: multipart/mixed; boundary=\"==break==\"\n\
+             \n\
+             --==break==\n\
+             Content-Type: text/plain; charset=utf-8\n\
+             \n\
+             an app\n\
+             --==break==\n\
+             Content-Type: application/octet-stream\n\
+             Content-Disposition: attachment; filename=\"{}\"\n\
+             Content-Transfer-Encoding: base64\n\
+             \n\
+             {}\n\
+             --==break==--\n",
+            filename,
+            filename,
+            base64::encode(data)
+        );
+        dc_receive_imf(&t.ctx, mail.as_bytes(), "INBOX", 1, false)
+            .await
+            .unwrap();
+        get_received_msg(&t.ctx, &format!("{}@example.net", filename)).await
+    }
+
+    /// Renders a message sent by `from` and receives it by `to`,
+    /// returns the ID of the received message.
+    async fn receive_msg(from: &TestContext, to: &TestContext, msg_id: MsgId) -> MsgId {
+        let msg = Message::load_from_db(&from.ctx, msg_id).await.unwrap();
+        send_and_receive(&from.ctx, &msg, &to.ctx).await.id
+    }
+
+    #[test]
+    fn test_parse_webxdc() {
+        let info = WebxdcInfo::parse(&create_archive(&[
+            ("index.html", "<html></html>"),
+            ("manifest.toml", "name = \"Poll\"\n"),
+            ("icon.jpg", "jpeg"),
+        ]))
+        .unwrap();
+        assert_eq!(info.name, "Poll");
+        assert_eq!(info.icon, Some("icon.jpg".to_string()));
+
+        let info = WebxdcInfo::parse(&create_archive(&[("index.html", "")])).unwrap();
+        assert_eq!(info, WebxdcInfo::default());
+
+        assert!(WebxdcInfo::parse(&create_archive(&[("manifest.toml", "")])).is_err());
+        assert!(WebxdcInfo::parse(&create_archive(&[
+            ("index.html", ""),
+            ("manifest.toml", "name = "),
+        ]))
+        .is_err());
+        assert!(WebxdcInfo::parse(b"no zip archive").is_err());
+    }
+
+    #[async_std::test]
+    async fn test_receive_webxdc() {
+        let t = TestContext::new_alice().await;
+        let archive = create_archive(&[
+            ("index.html", "<html></html>"),
+            ("manifest.toml", "name = \"Chess\"\n"),
+        ]);
+        let msg = receive_attachment(&t, "chess.xdc", &archive).await;
+        assert_eq!(msg.get_viewtype(), Viewtype::Webxdc);
+        let info = msg.get_webxdc_info(&t.ctx).await.unwrap();
+        assert_eq!(info.name, "Chess");
+        assert_eq!(info.icon, None);
+
+        // without a name in the manifest, the file name is used
+        let archive = create_archive(&[("index.html", "<html></html>")]);
+        let msg = receive_attachment(&t, "minimal.xdc", &archive).await;
+        assert_eq!(msg.get_viewtype(), Viewtype::Webxdc);
+        assert_eq!(msg.get_webxdc_info(&t.ctx).await.unwrap().name, "minimal");
+
+        // malformed apps are shown as files
+        let msg = receive_attachment(&t, "broken.xdc", b"no zip archive").await;
+        assert_eq!(msg.get_viewtype(), Viewtype::File);
+        assert!(msg.get_webxdc_info(&t.ctx).await.is_err());
+
+        // other zip archives are no apps
+        let msg = receive_attachment(&t, "website.zip", &archive).await;
+        assert_eq!(msg.get_viewtype(), Viewtype::File);
+    }
+
+    #[async_std::test]
+    async fn test_webxdc_status_update() {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new().await;
+        bob.configure_addr("bob@example.net").await;
+        let contact_id = Contact::create(&alice.ctx, "Bob", "bob@example.net")
+            .await
+            .unwrap();
+        let alice_chat = chat::create_by_contact_id(&alice.ctx, contact_id)
+            .await
+            .unwrap();
+        let contact_id = Contact::create(&bob.ctx, "Alice", "alice@example.com")
+            .await
+            .unwrap();
+        let bob_chat = chat::create_by_contact_id(&bob.ctx, contact_id)
+            .await
+            .unwrap();
+
+        let file = alice.ctx.get_blobdir().join("poll.xdc");
+        async_std::fs::write(&file, create_archive(&[("index.html", "<html></html>")]))
+            .await
+            .unwrap();
+        let mut instance = Message::new(Viewtype::Webxdc);
+        instance.set_file(file.to_str().unwrap(), None);
+        let alice_instance = chat::send_msg(&alice.ctx, alice_chat, &mut instance)
+            .await
+            .unwrap();
+        let bob_instance = receive_msg(&alice, &bob, alice_instance).await;
+        let msg = Message::load_from_db(&bob.ctx, bob_instance).await.unwrap();
+        assert_eq!(msg.get_viewtype(), Viewtype::Webxdc);
+        assert_eq!(msg.chat_id, bob_chat);
+
+        assert!(alice
+            .ctx
+            .send_webxdc_status_update(alice_instance, "no json")
+            .await
+            .is_err());
+        let update = alice
+            .ctx
+            .send_webxdc_status_update(alice_instance, r#"{"vote":"yes"}"#)
+            .await
+            .unwrap();
+        let updates = alice
+            .ctx
+            .get_webxdc_status_updates(alice_instance, 0)
+            .await
+            .unwrap();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].payload, serde_json::json!({"vote": "yes"}));
+
+        receive_msg(&alice, &bob, update).await;
+        let updates = bob
+            .ctx
+            .get_webxdc_status_updates(bob_instance, 0)
+            .await
+            .unwrap();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].payload, serde_json::json!({"vote": "yes"}));
+        let last_serial = updates[0].serial;
+
+        // only updates received since the last known serial are returned
+        let update = alice
+            .ctx
+            .send_webxdc_status_update(alice_instance, r#"{"vote":"no"}"#)
+            .await
+            .unwrap();
+        receive_msg(&alice, &bob, update).await;
+        let updates = bob
+            .ctx
+            .get_webxdc_status_updates(bob_instance, last_serial)
+            .await
+            .unwrap();
+        assert_eq!(updates.len(), 1);
+        assert!(updates[0].serial > last_serial);
+        assert_eq!(updates[0].payload, serde_json::json!({"vote": "no"}));
+        assert!(bob
+            .ctx
+            .get_webxdc_status_updates(bob_instance, updates[0].serial)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // status updates are not shown in the chat
+        assert_eq!(
+            chat::get_chat_msgs(&bob.ctx, bob_chat, 0, None).await.len(),
+            1
+        );
+    }
+}